@@ -0,0 +1,18 @@
+//! Build script: deflates the autocorrect dictionary into `OUT_DIR`, where
+//! `autocorrect` embeds it from
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/// The dictionary as edited, one front-coded entry per line
+const DICTIONARY: &str = "assets/pt-BR.dict";
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", DICTIONARY);
+    let data = fs::read(DICTIONARY).expect("the autocorrect dictionary is missing");
+    let compressed = miniz_oxide::deflate::compress_to_vec(&data, 10);
+    let out_dir = env::var_os("OUT_DIR").expect("cargo sets OUT_DIR");
+    fs::write(Path::new(&out_dir).join("pt-BR.dict.deflate"), compressed)
+        .expect("can't write the compressed dictionary");
+}
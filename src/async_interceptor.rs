@@ -0,0 +1,143 @@
+//! Async facade over the interceptor for tokio applications (feature `tokio`)
+//!
+//! `KeyboardInterceptor` blocks: starting waits for the hook thread (Windows)
+//! or opens input devices (Linux), and pipeline events reach subscribers on
+//! the hook thread. `AsyncInterceptor` starts and stops the interceptor on
+//! tokio's blocking pool and forwards events through a channel, so GUI
+//! frameworks and services embedding GhostKeys await key events instead of
+//! running threads of their own.
+
+use tokio::sync::mpsc;
+use tokio::task::{self, JoinError};
+
+use crate::error::{GhostKeysError, Result};
+use crate::events::Event;
+use crate::interceptor::KeyboardInterceptor;
+use crate::layout::Layout;
+use crate::state::{EventSubscription, OperationMode, SharedState};
+
+/// Events held for a reader that falls behind; newer ones are dropped until
+/// it catches up, since the hook thread must never wait for it
+const EVENT_BUFFER: usize = 1024;
+
+/// A running interceptor whose pipeline events can be awaited
+pub struct AsyncInterceptor {
+    /// Taken by `stop`
+    interceptor: Option<Box<dyn KeyboardInterceptor>>,
+    state: SharedState,
+    events: mpsc::Receiver<Event>,
+    /// Feeds `events`; dropped by `stop`, or with the interceptor
+    subscription: Option<EventSubscription>,
+}
+
+impl AsyncInterceptor {
+    /// Start `interceptor` with `state`, delivering events from the first
+    /// key press on
+    ///
+    /// If this future is dropped before it completes, the interceptor still
+    /// starts, and is stopped again as soon as it has. Either way, or if the
+    /// interceptor doesn't start, the event subscriber is removed.
+    pub async fn start(
+        mut interceptor: Box<dyn KeyboardInterceptor>,
+        state: SharedState,
+    ) -> Result<Self> {
+        let (sender, events) = mpsc::channel(EVENT_BUFFER);
+        let subscription = state.subscribe_events(move |event| {
+            // Full, or the reader is gone
+            let _ = sender.try_send(event.clone());
+        })?;
+
+        let hook_state = state.clone();
+        let interceptor = task::spawn_blocking(move || {
+            interceptor.start(hook_state).map(|()| interceptor)
+        })
+        .await
+        .map_err(|e| GhostKeysError::HookInstallError(panicked(e)))??;
+        Ok(Self { interceptor: Some(interceptor), state, events, subscription: Some(subscription) })
+    }
+
+    /// The next event from the keystroke pipeline, or `None` once stopped
+    /// and every buffered event has been read
+    ///
+    /// Cancellation safe: dropping the future (e.g., in `tokio::select!`)
+    /// loses no event.
+    pub async fn next_event(&mut self) -> Option<Event> {
+        self.events.recv().await
+    }
+
+    /// Stop intercepting; events already buffered can still be read
+    ///
+    /// Cancellation safe: the interceptor is stopped on the blocking pool,
+    /// which finishes the job even if this future is dropped.
+    pub async fn stop(&mut self) -> Result<()> {
+        self.subscription.take();
+        self.events.close();
+        let Some(mut interceptor) = self.interceptor.take() else {
+            return Ok(());
+        };
+        task::spawn_blocking(move || interceptor.stop())
+            .await
+            .map_err(|e| GhostKeysError::HookReleaseError(panicked(e)))?
+    }
+
+    /// Whether the interceptor is running
+    pub fn is_running(&self) -> bool {
+        self.interceptor.as_ref().is_some_and(|interceptor| interceptor.is_running())
+    }
+
+    /// Pause or resume remapping without releasing the hook
+    pub fn set_mode(&mut self, mode: OperationMode) -> Result<()> {
+        self.running()?.set_mode(mode)
+    }
+
+    /// Switch to another layout (`None` for the built-in ABNT2)
+    pub fn update_layout(&mut self, layout: Option<Layout>) -> Result<()> {
+        self.running()?.update_layout(layout)
+    }
+
+    /// State shared with the hook (mode, direction, config)
+    pub fn state(&self) -> &SharedState {
+        &self.state
+    }
+
+    fn running(&mut self) -> Result<&mut dyn KeyboardInterceptor> {
+        match &mut self.interceptor {
+            Some(interceptor) => Ok(interceptor.as_mut()),
+            None => Err(GhostKeysError::HookInstallError("Interceptor not running".to_string())),
+        }
+    }
+}
+
+/// Why a blocking start or stop didn't return
+fn panicked(error: JoinError) -> String {
+    format!("The interceptor thread panicked: {}", error)
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::platform::linux::LinuxInterceptor;
+
+    #[tokio::test]
+    async fn test_events_until_stopped() {
+        let state = SharedState::new();
+        let interceptor = Box::new(LinuxInterceptor::without_preflight());
+        let mut running = AsyncInterceptor::start(interceptor, state).await.unwrap();
+        assert!(running.is_running());
+
+        running.set_mode(OperationMode::Passthrough).unwrap();
+        assert_eq!(
+            running.next_event().await,
+            Some(Event::ModeChanged(OperationMode::Passthrough))
+        );
+
+        running.state().set_mode(OperationMode::Active).unwrap();
+        running.stop().await.unwrap();
+        assert!(!running.is_running());
+        assert!(running.set_mode(OperationMode::Passthrough).is_err());
+        // Published before the stop, so still delivered
+        assert_eq!(running.next_event().await, Some(Event::ModeChanged(OperationMode::Active)));
+        assert_eq!(running.next_event().await, None);
+        running.stop().await.unwrap();
+    }
+}
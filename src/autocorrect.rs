@@ -0,0 +1,416 @@
+//! Offline Portuguese autocorrect for missing accents
+//!
+//! Tracks the word being typed and, at a word boundary, replaces it with its
+//! accented form when the bundled dictionary knows exactly one spelling for it
+//! (nao -> não, voce -> você). Words with several valid spellings (esta/está,
+//! e/é) are never touched, but can be offered as suggestions to pick from
+//! instead. Everything runs locally; the dictionary is compiled into the
+//! binary, deflated by the build script.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::interceptor::KeyAction;
+
+/// Bundled dictionary, front-coded and deflated to keep the binary small
+const DICTIONARY_DATA: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/pt-BR.dict.deflate"));
+
+/// The bundled dictionary inflated back to its text (see assets/pt-BR.dict)
+fn dictionary_text() -> String {
+    miniz_oxide::inflate::decompress_to_vec(DICTIONARY_DATA)
+        .ok()
+        .and_then(|data| String::from_utf8(data).ok())
+        .unwrap_or_default()
+}
+
+/// Decoded dictionary: unaccented key -> valid spellings
+fn dictionary() -> &'static HashMap<String, Vec<String>> {
+    static DICTIONARY: OnceLock<HashMap<String, Vec<String>>> = OnceLock::new();
+    DICTIONARY.get_or_init(|| decode_dictionary(&dictionary_text()))
+}
+
+/// Decode the front-coded dictionary format (see the asset header)
+fn decode_dictionary(data: &str) -> HashMap<String, Vec<String>> {
+    let mut entries = HashMap::new();
+    let mut previous = String::new();
+
+    for line in data.lines() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((coded_key, candidates)) = line.split_once('\t') else {
+            continue;
+        };
+        let mut chars = coded_key.chars();
+        let Some(shared) = chars.next().and_then(|c| c.to_digit(10)) else {
+            continue;
+        };
+
+        let key: String = previous
+            .chars()
+            .take(shared as usize)
+            .chain(chars)
+            .collect();
+        let candidates = candidates.split(',').map(str::to_string).collect();
+        entries.insert(key.clone(), candidates);
+        previous = key;
+    }
+
+    entries
+}
+
+/// Look up the accented form of a word, if it is unambiguous
+///
+/// Preserves the word's casing: "nao" -> "não", "Nao" -> "Não", "NAO" -> "NÃO",
+/// and "nAO" (Shift on the first letter with Caps Lock on) -> "nÃO".
+pub fn correct(word: &str) -> Option<String> {
+    // Words the user already accented are left alone
+    if !word.is_ascii() {
+        return None;
+    }
+
+    let key = word.to_ascii_lowercase();
+    let corrected = match dictionary().get(&key)?.as_slice() {
+        [only] if *only != key => only,
+        _ => return None,
+    };
+    Some(with_case_of(word, corrected))
+}
+
+/// Accented spellings of a word that has several valid ones
+///
+/// "esta" -> ["está"], "Avo" -> ["Avô", "Avó"]; empty for words that are
+/// corrected automatically, unknown or already accented.
+pub fn suggestions(word: &str) -> Vec<String> {
+    if !word.is_ascii() {
+        return Vec::new();
+    }
+    let key = word.to_ascii_lowercase();
+    match dictionary().get(&key).map(Vec::as_slice) {
+        Some(candidates) if candidates.len() > 1 => candidates
+            .iter()
+            .filter(|candidate| **candidate != key)
+            .map(|candidate| with_case_of(word, candidate))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// `spelling` cased like `word`, letter by letter when they line up, as
+/// accents don't change the length: "nAO" -> "nÃO"; otherwise "Nao" -> "Não",
+/// "NAO" -> "NÃO"
+fn with_case_of(word: &str, spelling: &str) -> String {
+    if word.chars().count() == spelling.chars().count() {
+        return word
+            .chars()
+            .zip(spelling.chars())
+            .map(|(typed, c)| match typed.is_ascii_uppercase() {
+                true => c.to_uppercase().collect(),
+                false => c.to_string(),
+            })
+            .collect();
+    }
+
+    let mut chars = word.chars();
+    let first_upper = chars.next().is_some_and(|c| c.is_ascii_uppercase());
+    let all_upper = word.chars().count() > 1 && word.chars().all(|c| c.is_ascii_uppercase());
+
+    if all_upper {
+        spelling.to_uppercase()
+    } else if first_upper {
+        let mut spelling_chars = spelling.chars();
+        spelling_chars
+            .next()
+            .map(|c| c.to_uppercase().chain(spelling_chars).collect())
+            .unwrap_or_default()
+    } else {
+        spelling.to_string()
+    }
+}
+
+/// Accented spellings offered for the word a boundary just ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    /// The word as typed
+    pub word: String,
+    /// Its accented spellings, in dictionary order
+    pub alternatives: Vec<String>,
+    /// What was typed after the word, from the boundary on
+    after: Vec<char>,
+}
+
+impl Suggestion {
+    /// The word and what follows it, as on screen
+    pub fn typed(&self) -> Vec<char> {
+        self.word.chars().chain(self.after.iter().copied()).collect()
+    }
+}
+
+/// Word tracker that turns word boundaries into corrections
+#[derive(Debug)]
+pub struct Autocorrect {
+    /// Letters typed since the last boundary, as they appear on screen
+    word: String,
+    /// Rewrite unambiguous words at the boundary
+    corrects: bool,
+    /// Offer the spellings of ambiguous words at the boundary
+    suggests: bool,
+    /// Offered for the word just ended, until the next key
+    suggestion: Option<Suggestion>,
+    /// The word the last key corrected and what followed it, as typed
+    uncorrected: Option<Vec<char>>,
+}
+
+impl Default for Autocorrect {
+    fn default() -> Self {
+        Self::with_options(true, false)
+    }
+}
+
+impl Autocorrect {
+    /// Create a new autocorrect tracker
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tracker that corrects unambiguous words and/or suggests spellings for
+    /// ambiguous ones
+    pub fn with_options(corrects: bool, suggests: bool) -> Self {
+        Self { word: String::new(), corrects, suggests, suggestion: None, uncorrected: None }
+    }
+
+    /// Forget the current word (cursor moved, shortcut pressed, ...)
+    pub fn reset(&mut self) {
+        self.word.clear();
+        self.suggestion = None;
+    }
+
+    /// Account for a backspace typed by the user
+    pub fn backspace(&mut self) {
+        self.word.pop();
+        self.suggestion = None;
+    }
+
+    /// What the last key's correction replaced: the word as typed and what
+    /// followed it
+    pub fn uncorrected(&self) -> Option<&[char]> {
+        self.uncorrected.as_deref()
+    }
+
+    /// Spellings offered for the word just ended, if any
+    pub fn suggestion(&self) -> Option<&Suggestion> {
+        self.suggestion.as_ref()
+    }
+
+    /// Replace the word just ended with its `index`th suggested spelling,
+    /// keeping what was typed after it
+    pub fn accept(&mut self, index: usize) -> Option<KeyAction> {
+        let suggestion = self.suggestion.as_ref()?;
+        let spelling = suggestion.alternatives.get(index)?;
+        let action = KeyAction::Rewrite {
+            backspaces: suggestion.word.chars().count() + suggestion.after.len(),
+            chars: spelling.chars().chain(suggestion.after.iter().copied()).collect(),
+        };
+        self.suggestion = None;
+        Some(action)
+    }
+
+    /// Observe the action decided for a keystroke and rewrite it if it ends a
+    /// correctable word
+    ///
+    /// `typed` is the character the key produces when the action is `Pass`.
+    pub fn process(&mut self, action: KeyAction, typed: Option<char>) -> KeyAction {
+        self.suggestion = None;
+        self.uncorrected = None;
+        // A rewrite ends the word, and so do moving the cursor and other keys
+        if matches!(action, KeyAction::Rewrite { .. } | KeyAction::InjectKey(..)) {
+            self.reset();
+            return action;
+        }
+        let output = action.output(typed);
+
+        let Some(boundary) = output.iter().position(|c| !c.is_alphabetic()) else {
+            self.word.extend(output);
+            return action;
+        };
+
+        let on_screen = self.word.chars().count();
+        self.word.extend(&output[..boundary]);
+        let correction = if self.corrects { correct(&self.word) } else { None };
+
+        // Start tracking whatever follows the boundary
+        let rest = &output[boundary..];
+        if self.suggests && correction.is_none() {
+            let alternatives = suggestions(&self.word);
+            if !alternatives.is_empty() {
+                let word = std::mem::take(&mut self.word);
+                self.suggestion = Some(Suggestion { word, alternatives, after: rest.to_vec() });
+            }
+        }
+        if correction.is_some() {
+            self.uncorrected = Some(self.word.chars().chain(rest.iter().copied()).collect());
+        }
+        let trailing = rest.iter().rposition(|c| !c.is_alphabetic()).map_or(0, |i| i + 1);
+        self.word = rest[trailing..].iter().collect();
+
+        match correction {
+            Some(corrected) => KeyAction::Rewrite {
+                backspaces: on_screen,
+                chars: corrected.chars().chain(rest.iter().copied()).collect(),
+            },
+            None => action,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed a string through the tracker as if each char were a passed key
+    fn type_text(autocorrect: &mut Autocorrect, text: &str) -> Vec<KeyAction> {
+        text.chars()
+            .map(|c| autocorrect.process(KeyAction::Pass, Some(c)))
+            .collect()
+    }
+
+    #[test]
+    fn test_dictionary_decodes() {
+        let dict = dictionary();
+        assert_eq!(dict.get("nao"), Some(&vec!["não".to_string()]));
+        assert_eq!(dict.get("acao"), Some(&vec!["ação".to_string()]));
+        assert!(dict.len() > 200);
+    }
+
+    #[test]
+    fn test_dictionary_keys_are_unaccented_and_sorted() {
+        let mut previous = String::new();
+        for line in dictionary_text().lines().filter(|l| !l.starts_with('#')) {
+            let shared = line.chars().next().and_then(|c| c.to_digit(10)).unwrap();
+            let key: String = previous
+                .chars()
+                .take(shared as usize)
+                .chain(line[1..].split('\t').next().unwrap().chars())
+                .collect();
+            assert!(key.is_ascii(), "key {key} must be unaccented");
+            assert!(key > previous, "key {key} out of order");
+            previous = key;
+        }
+    }
+
+    #[test]
+    fn test_correct_unambiguous() {
+        assert_eq!(correct("nao").as_deref(), Some("não"));
+        assert_eq!(correct("voce").as_deref(), Some("você"));
+        assert_eq!(correct("Voce").as_deref(), Some("Você"));
+        assert_eq!(correct("NAO").as_deref(), Some("NÃO"));
+    }
+
+    #[test]
+    fn test_correct_keeps_caps_lock_casing() {
+        // Shift with Caps Lock on types the first letter in lowercase
+        assert_eq!(correct("nAO").as_deref(), Some("nÃO"));
+        assert_eq!(correct("vOCE").as_deref(), Some("vOCÊ"));
+        assert_eq!(suggestions("eSTA"), ["eSTÁ"]);
+    }
+
+    #[test]
+    fn test_correct_skips_ambiguous_and_unknown() {
+        assert_eq!(correct("esta"), None);
+        assert_eq!(correct("e"), None);
+        assert_eq!(correct("casa"), None);
+        assert_eq!(correct("não"), None);
+    }
+
+    #[test]
+    fn test_suggestions_for_ambiguous_words() {
+        assert_eq!(suggestions("esta"), ["está"]);
+        assert_eq!(suggestions("E"), ["É"]);
+        assert_eq!(suggestions("avo"), ["avô", "avó"]);
+        assert!(suggestions("nao").is_empty());
+        assert!(suggestions("está").is_empty());
+        assert!(suggestions("casa").is_empty());
+    }
+
+    #[test]
+    fn test_accept_suggestion_after_boundary() {
+        let mut autocorrect = Autocorrect::with_options(true, true);
+        type_text(&mut autocorrect, "esta ");
+        let suggestion = autocorrect.suggestion().unwrap();
+        assert_eq!(suggestion.word, "esta");
+        assert_eq!(suggestion.alternatives, ["está"]);
+        assert_eq!(suggestion.typed(), "esta ".chars().collect::<Vec<_>>());
+
+        assert_eq!(autocorrect.accept(1), None);
+        assert_eq!(
+            autocorrect.accept(0),
+            Some(KeyAction::Rewrite { backspaces: 5, chars: "está ".chars().collect() })
+        );
+        assert_eq!(autocorrect.suggestion(), None);
+
+        // Only offered until the next key
+        type_text(&mut autocorrect, "e x");
+        assert_eq!(autocorrect.suggestion(), None);
+        assert!(Autocorrect::new().suggestion().is_none());
+    }
+
+    #[test]
+    fn test_suggestions_without_corrections() {
+        let mut autocorrect = Autocorrect::with_options(false, true);
+        let actions = type_text(&mut autocorrect, "nao e ");
+        assert!(actions.iter().all(|action| *action == KeyAction::Pass));
+        assert_eq!(autocorrect.suggestion().unwrap().alternatives, ["é"]);
+    }
+
+    #[test]
+    fn test_boundary_rewrites_word() {
+        let mut autocorrect = Autocorrect::new();
+        let actions = type_text(&mut autocorrect, "nao ");
+        assert_eq!(autocorrect.uncorrected(), Some(&['n', 'a', 'o', ' '][..]));
+
+        assert_eq!(
+            actions.last(),
+            Some(&KeyAction::Rewrite {
+                backspaces: 3,
+                chars: vec!['n', 'ã', 'o', ' '],
+            })
+        );
+    }
+
+    #[test]
+    fn test_boundary_from_remapped_key() {
+        let mut autocorrect = Autocorrect::new();
+        type_text(&mut autocorrect, "entao");
+
+        // The ABNT2 ; position arrives as a Replace
+        assert_eq!(
+            autocorrect.process(KeyAction::Replace(';'), None),
+            KeyAction::Rewrite {
+                backspaces: 5,
+                chars: "então;".chars().collect(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_backspace_and_reset() {
+        let mut autocorrect = Autocorrect::new();
+        type_text(&mut autocorrect, "naox");
+        autocorrect.backspace();
+        assert!(matches!(
+            autocorrect.process(KeyAction::Pass, Some(' ')),
+            KeyAction::Rewrite { .. }
+        ));
+
+        type_text(&mut autocorrect, "nao");
+        autocorrect.reset();
+        assert_eq!(autocorrect.process(KeyAction::Pass, Some(' ')), KeyAction::Pass);
+    }
+
+    #[test]
+    fn test_regular_words_pass_through() {
+        let mut autocorrect = Autocorrect::new();
+        let actions = type_text(&mut autocorrect, "casa azul ");
+        assert!(actions.iter().all(|action| *action == KeyAction::Pass));
+    }
+}
@@ -0,0 +1,233 @@
+//! `ghostkeys check-config`: find mistakes before GhostKeys uses them
+//!
+//! A config with a typo falls back to the defaults, and a custom hotkey that
+//! conflicts or a layout that doesn't parse is only reported once the running
+//! instance reloads it, with the keyboard already affected. This parses the
+//! config file (or the one given) and everything it refers to: custom
+//! hotkeys and the layouts they switch to, device rules, the tray icon, the
+//! saved profiles and layouts. Nothing is applied, and every problem is
+//! reported, not just the first.
+
+use std::path::{Path, PathBuf};
+
+use crate::config::{io_error, Config};
+use crate::error::{GhostKeysError, Result};
+use crate::hotkeys::{self, Action, Chord, Hotkeys};
+use crate::icon::{self, Theme};
+use crate::layout::klc;
+use crate::paths;
+use crate::profile;
+
+/// Problems found in the config file and the files it refers to
+pub fn run(path: &Path) -> Vec<GhostKeysError> {
+    let (config, mut problems) = match std::fs::read_to_string(path) {
+        Ok(text) => check_config(&text, path),
+        Err(e) => (None, vec![io_error(path, e)]),
+    };
+    if let Some(config) = config {
+        problems.extend(check_references(&config));
+    }
+    problems.extend(check_profiles());
+    problems.extend(check_layouts());
+    problems
+}
+
+/// Check the config file at the default location, if there is one
+pub fn run_default() -> Result<Vec<GhostKeysError>> {
+    let path = paths::config_file().ok_or_else(|| GhostKeysError::ConfigError {
+        path: None,
+        line: None,
+        message: "could not determine the config directory".to_string(),
+    })?;
+    if path.exists() {
+        return Ok(run(&path));
+    }
+    // No config file yet: the defaults are in use, and they're fine
+    let mut problems = check_profiles();
+    problems.extend(check_layouts());
+    Ok(problems)
+}
+
+/// Parse `text` as the config file at `path` and check what can be checked
+/// without other files; returns the config if it parsed
+pub fn check_config(text: &str, path: &Path) -> (Option<Config>, Vec<GhostKeysError>) {
+    let config = match Config::parse_from(text, Some(path)) {
+        Ok(config) => config,
+        Err(e) => return (None, vec![e]),
+    };
+    let problem =
+        |section: &str, key: &dyn Fn(&str) -> bool, message: String| GhostKeysError::ConfigError {
+            path: Some(path.to_path_buf()),
+            line: find_line(text, section, key),
+            message,
+        };
+    let mut problems = Vec::new();
+
+    let (_, conflicts) = Hotkeys::from_config(&config);
+    for conflict in conflicts {
+        let (hotkeys::Conflict::Reserved(chord) | hotkeys::Conflict::BuiltIn(chord, _)) = conflict;
+        let written = |key: &str| Chord::parse(key).is_ok_and(|parsed| parsed == chord);
+        problems.push(problem("hotkeys.custom", &written, conflict.to_string()));
+    }
+
+    // An empty pattern is part of every device path
+    if config.devices.contains_key("") {
+        let message = "an empty device pattern applies to every keyboard".to_string();
+        problems.push(problem("devices", &|key| key.is_empty(), message));
+    }
+    if config.auto_pause.when_attached.iter().any(String::is_empty) {
+        let message =
+            "an empty pattern in `when_attached` pauses GhostKeys for every keyboard".to_string();
+        problems.push(problem(
+            "auto_pause",
+            &|key| key == "when_attached",
+            message,
+        ));
+    }
+
+    if let Some(active) = &config.profiles.active {
+        if !profile::list().contains(active) {
+            let message = format!(
+                "the active profile \"{}\" isn't saved in the profiles folder",
+                active
+            );
+            problems.push(problem("profiles", &|key| key == "active", message));
+        }
+    }
+
+    (Some(config), problems)
+}
+
+/// Layouts the custom hotkeys switch to, and the tray icon
+fn check_references(config: &Config) -> Vec<GhostKeysError> {
+    let mut problems: Vec<GhostKeysError> = config
+        .hotkeys
+        .custom
+        .values()
+        .filter_map(|action| match action {
+            Action::SwitchLayout(name) => hotkeys::load_layout(name).err(),
+            _ => None,
+        })
+        .collect();
+    if config.tray.icon.is_some() {
+        let (_, error) = icon::base(&config.tray, Theme::Dark);
+        problems.extend(error);
+    }
+    problems
+}
+
+/// Every saved profile and the layout it carries
+fn check_profiles() -> Vec<GhostKeysError> {
+    let mut problems = Vec::new();
+    for name in profile::list() {
+        match profile::load(&name) {
+            Ok(saved) => {
+                if let Some(Err(e)) = saved.layout.as_deref().map(klc::parse) {
+                    let file = format!("{}.{}", name, profile::EXTENSION);
+                    let path = paths::profiles_dir().map(|dir| dir.join(file));
+                    problems.push(in_file(e, path.as_deref()));
+                }
+            }
+            Err(e) => problems.push(e),
+        }
+    }
+    problems
+}
+
+/// Every `.klc` file in the layouts folder
+fn check_layouts() -> Vec<GhostKeysError> {
+    let Some(entries) = paths::layouts_dir().and_then(|dir| std::fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "klc"))
+        .collect();
+    files.sort();
+    files
+        .iter()
+        .filter_map(|path| klc::load(path).err().map(|e| in_file(e, Some(path))))
+        .collect()
+}
+
+/// Name the file a layout error came from
+fn in_file(error: GhostKeysError, path: Option<&Path>) -> GhostKeysError {
+    match (error, path) {
+        (GhostKeysError::LayoutParseError(message), Some(path)) => {
+            GhostKeysError::LayoutParseError(format!("{}: {}", path.display(), message))
+        }
+        (error, _) => error,
+    }
+}
+
+/// 1-based line of the first key in `[section]` for which `key` is true
+///
+/// Keys are compared without their quotes; `None` if there's no such line.
+fn find_line(text: &str, section: &str, key: &dyn Fn(&str) -> bool) -> Option<usize> {
+    let mut current = String::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if let Some(header) = line
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            current = header.trim().to_string();
+        } else if current == section {
+            if let Some((name, _)) = line.split_once('=') {
+                if key(name.trim().trim_matches('"').trim_matches('\'')) {
+                    return Some(index + 1);
+                }
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(text: &str) -> Vec<String> {
+        let (_, problems) = check_config(text, Path::new("config.toml"));
+        problems.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_valid_config() {
+        let text = "[hotkeys.custom]\n\"ctrl+alt+9\" = \"pause_15m\"\n";
+        assert_eq!(problems(text), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_parse_errors_name_the_line() {
+        let found = problems("[startup]\nshow_tray = true\nstart_pasued = true\n");
+        assert_eq!(found.len(), 1);
+        assert!(found[0].contains("config.toml at line 3"), "{}", found[0]);
+    }
+
+    #[test]
+    fn test_every_conflict_is_reported() {
+        let text = "\
+[hotkeys.custom]
+\"ctrl+alt+9\" = \"pause_15m\"
+\"Alt+Tab\" = \"toggle\"
+\"ctrl+alt+space\" = \"open_palette\"
+
+[devices]
+\"\" = \"passthrough\"
+";
+        let found = problems(text);
+        assert_eq!(found.len(), 3, "{:?}", found);
+        assert!(found[0].contains("line 3") && found[0].contains("reserved"));
+        assert!(found[1].contains("line 4") && found[1].contains("toggle_pause"));
+        assert!(found[2].contains("line 7") && found[2].contains("every keyboard"));
+    }
+
+    #[test]
+    fn test_unusable_remaps_are_refused() {
+        let found = problems("[remap]\ncaps_lock = \"escape\"\nmenu = \"right_ctrl\"\n");
+        assert_eq!(found.len(), 1);
+        assert!(found[0].contains("line 3") && found[0].contains("modifier"), "{}", found[0]);
+    }
+}
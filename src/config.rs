@@ -0,0 +1,1528 @@
+//! User configuration
+//!
+//! Configuration lives in a TOML file in the platform config directory
+//! (`%APPDATA%\ghostkeys\config.toml` on Windows,
+//! `$XDG_CONFIG_HOME/ghostkeys/config.toml` on Linux). Every field has a
+//! default, so a missing file or a partial file is always valid.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use regex::Regex;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, Schema, SchemaObject};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use toml_edit::{Document, Item, Table, Value};
+
+use crate::error::{GhostKeysError, Result};
+use crate::hotkeys::{Action, Chord};
+use crate::i18n::Language;
+use crate::layout::{KeyPosition, SourceLayout};
+use crate::mapper::{AccentType, Direction, MappingFeatures, VirtualKey};
+use crate::paths;
+use crate::remap::{self, RemapKey};
+
+/// Top-level configuration
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Physical layout of the keyboard being remapped (us, uk_iso, dvorak, colemak)
+    pub source_layout: SourceLayout,
+    /// Direction to start in: "us_to_abnt2" (a US keyboard typing ABNT2) or
+    /// "abnt2_to_us" (reverse mode: an ABNT2 keyboard typing US); the tray
+    /// switches it while running
+    pub direction: Direction,
+    /// How the Windows hook tells keys apart: "auto", "scan_code" or
+    /// "virtual_key" (Linux always goes by the keyboard's key codes)
+    pub key_codes: KeyCodes,
+    /// Language of the tray, notifications and messages ("en" or "pt-BR");
+    /// follows the OS locale when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<Language>,
+    /// Parts of the layout in use (position remaps, each dead key, AltGr)
+    pub features: MappingFeatures,
+    /// Keys to use as dead keys instead of the layout's (e.g., `"'" = "acute"`)
+    pub dead_keys: BTreeMap<KeyPosition, AccentType>,
+    /// Startup settings
+    pub startup: StartupConfig,
+    /// Tray icon settings
+    pub tray: TrayConfig,
+    /// Global hotkeys
+    pub hotkeys: HotkeysConfig,
+    /// Compose key settings
+    pub compose: ComposeConfig,
+    /// Accent autocorrect settings
+    pub autocorrect: AutocorrectConfig,
+    /// Macro recording settings
+    pub macros: MacrosConfig,
+    /// Virtual numpad settings
+    pub numpad: NumpadConfig,
+    /// Navigation layer settings
+    pub nav: NavConfig,
+    /// Keys pressed in place of others (e.g., `caps_lock = "escape"`)
+    #[serde(deserialize_with = "remap::deserialize_table")]
+    pub remap: BTreeMap<RemapKey, RemapKey>,
+    /// Character injection settings
+    pub injection: InjectionConfig,
+    /// Terminal settings
+    pub terminals: TerminalsConfig,
+    /// Per-keyboard rules: part of a device path (e.g., "VID_04F2&PID_0833") -> mode;
+    /// keystrokes can't be told apart by keyboard, so a `passthrough` keyboard
+    /// pauses GhostKeys while it's plugged in, like `[auto_pause]`
+    pub devices: BTreeMap<String, DeviceMode>,
+    /// Automatic pause settings
+    pub auto_pause: AutoPauseConfig,
+    /// Logging settings
+    pub logging: LoggingConfig,
+    /// Desktop notification settings
+    pub notifications: NotificationsConfig,
+    /// Dead key sound settings
+    pub feedback: FeedbackConfig,
+    /// Learning mode settings
+    pub learning: LearningConfig,
+    /// Crash handling settings
+    pub crash: CrashConfig,
+    /// Local typing statistics settings
+    pub statistics: StatisticsConfig,
+    /// Profile switcher settings
+    pub profiles: ProfilesConfig,
+    /// Linux backend settings
+    pub linux: LinuxConfig,
+}
+
+/// What the Windows hook tells keys apart by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyCodes {
+    /// Punctuation and dead-key keys by scan code, so positions stay put
+    /// whatever the OS layout, letters as the OS layout types them (AZERTY,
+    /// Dvorak), and the others (triggers, `[remap]`) by virtual key
+    #[default]
+    Auto,
+    /// Every key by scan code, letters included, for keyboards that report
+    /// nonstandard virtual keys and for hosts with unusual layouts
+    ScanCode,
+    /// Every key by virtual key, so positions follow the OS layout (keys a
+    /// Dvorak layout moves get the ABNT2 characters of where they went)
+    VirtualKey,
+}
+
+/// Startup configuration
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct StartupConfig {
+    /// Show the tray icon; without it GhostKeys is controlled with the
+    /// hotkeys and `ghostkeys ctl`
+    pub show_tray: bool,
+    /// Start paused, as if "Pause" had been clicked
+    pub start_paused: bool,
+}
+
+impl Default for StartupConfig {
+    fn default() -> Self {
+        Self { show_tray: true, start_paused: false }
+    }
+}
+
+/// Tray icon configuration
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrayConfig {
+    /// Colors of the built-in icon: "light" for a light taskbar or panel,
+    /// "dark" for a dark one, or "auto" to follow the OS
+    pub theme: TrayTheme,
+    /// Your own icon instead of the built-in one, as a .ico or .png file
+    /// (relative to the config folder); the state badges are drawn over it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon: Option<PathBuf>,
+}
+
+/// Which background the built-in tray icon is drawn for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TrayTheme {
+    /// Follow the OS, switching when it does
+    #[default]
+    Auto,
+    /// A light taskbar or panel
+    Light,
+    /// A dark taskbar or panel
+    Dark,
+}
+
+/// Global hotkey configuration
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct HotkeysConfig {
+    /// Ctrl+Alt+Space pauses and resumes GhostKeys
+    pub toggle_pause: bool,
+    /// Ctrl+Alt+. opens the character palette (Windows)
+    pub palette: bool,
+    /// Ctrl+Alt+Z takes back the last replacement, typing the keys pressed
+    /// as they are (Windows)
+    pub undo: bool,
+    /// Ctrl+Alt+P switches to the next saved profile (Windows)
+    pub cycle_profile: bool,
+    /// More hotkeys: chord -> action (e.g., `"ctrl+alt+9" = "pause_15m"`);
+    /// see `hotkeys` (Windows)
+    pub custom: BTreeMap<Chord, Action>,
+}
+
+impl Default for HotkeysConfig {
+    fn default() -> Self {
+        Self {
+            toggle_pause: true,
+            palette: false,
+            undo: false,
+            cycle_profile: false,
+            custom: BTreeMap::new(),
+        }
+    }
+}
+
+/// Logging configuration
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// Also send warnings and errors to the Windows Event Log / journald
+    pub system_log: bool,
+}
+
+/// Desktop notification configuration
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotificationsConfig {
+    /// Show a toast when a rule (not the user) pauses or resumes GhostKeys,
+    /// so a sudden change in typing is explained; on by default
+    pub mode_changes: bool,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self { mode_changes: true }
+    }
+}
+
+/// A system sound played as a cue
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Sound {
+    /// Silence
+    #[default]
+    None,
+    /// The plain speaker beep
+    Beep,
+    /// The "Default Beep" system sound
+    Default,
+    /// The "Asterisk" system sound
+    Asterisk,
+    /// The "Exclamation" system sound
+    Exclamation,
+    /// The "Question" system sound
+    Question,
+}
+
+/// Sounds for one accent, overriding the `[feedback]` ones
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccentFeedback {
+    /// Played when the accent's dead key arms
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub armed: Option<Sound>,
+    /// Played when the accent has waited for its letter past the timeout
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<Sound>,
+}
+
+/// Dead key sound configuration (Windows), for keeping track of a pending
+/// accent without looking; silent by default
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeedbackConfig {
+    /// Played when a dead key arms
+    pub armed: Sound,
+    /// Played when an armed accent has waited half a second for its letter;
+    /// it stays armed
+    pub timeout: Sound,
+    /// Different sounds for some accents (e.g., `tilde = { armed = "question" }`)
+    pub accents: BTreeMap<AccentType, AccentFeedback>,
+}
+
+impl FeedbackConfig {
+    /// Sound for `accent`'s dead key arming
+    pub fn armed_sound(&self, accent: AccentType) -> Sound {
+        self.accents.get(&accent).and_then(|sounds| sounds.armed).unwrap_or(self.armed)
+    }
+
+    /// Sound for `accent` waiting past the timeout
+    pub fn timeout_sound(&self, accent: AccentType) -> Sound {
+        self.accents.get(&accent).and_then(|sounds| sounds.timeout).unwrap_or(self.timeout)
+    }
+
+    /// Whether any sound is configured
+    pub fn is_enabled(&self) -> bool {
+        self.armed != Sound::None
+            || self.timeout != Sound::None
+            || self.accents.values().any(|sounds| {
+                sounds.armed.is_some_and(|sound| sound != Sound::None)
+                    || sounds.timeout.is_some_and(|sound| sound != Sound::None)
+            })
+    }
+}
+
+/// Learning mode configuration (Windows)
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct LearningConfig {
+    /// Flash each remapped character on screen with the US keys that typed it
+    pub enabled: bool,
+    /// How long each one stays on screen
+    pub duration_ms: u64,
+}
+
+impl Default for LearningConfig {
+    fn default() -> Self {
+        Self { enabled: false, duration_ms: 1500 }
+    }
+}
+
+/// Virtual numpad configuration
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct NumpadConfig {
+    /// Turn the right hand's letter block (u i o, j k l, m , .) into a
+    /// numpad while the trigger is active; see `numpad`
+    pub enabled: bool,
+    /// What turns the numpad on
+    pub trigger: NumpadTrigger,
+    /// What the decimal key types: "," as on an ABNT2 numpad
+    pub decimal: char,
+}
+
+impl Default for NumpadConfig {
+    fn default() -> Self {
+        Self { enabled: false, trigger: NumpadTrigger::default(), decimal: ',' }
+    }
+}
+
+/// Key that turns the virtual numpad on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NumpadTrigger {
+    /// While AltGr (the right Alt) is held
+    #[default]
+    Altgr,
+    /// While Caps Lock is on
+    CapsLock,
+}
+
+/// Navigation layer configuration
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct NavConfig {
+    /// Press arrows, Home, End, Page Up and Page Down with letter keys while
+    /// the trigger is held; see `nav`
+    pub enabled: bool,
+    /// Key to hold; tapped on its own, it still does its job
+    pub trigger: NavTrigger,
+    /// Which letters are the arrows
+    pub keys: NavKeys,
+}
+
+/// Key held for the navigation layer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NavTrigger {
+    /// Caps Lock
+    #[default]
+    CapsLock,
+    /// Tab
+    Tab,
+}
+
+/// Letters of the navigation layer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NavKeys {
+    /// h j k l, as in vim: left, down, up, right
+    #[default]
+    Hjkl,
+    /// i j k l, an inverted T: up, left, down, right
+    Ijkl,
+}
+
+/// Profile switcher state
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProfilesConfig {
+    /// Profile last switched to, kept so it's still in use after a restart
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active: Option<String>,
+}
+
+/// Crash handling configuration
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct CrashConfig {
+    /// Start GhostKeys again after it crashes
+    pub relaunch: bool,
+}
+
+/// Local typing statistics configuration
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatisticsConfig {
+    /// Count remapped key positions and palette picks into stats.toml (see
+    /// `ghostkeys stats export`)
+    pub enabled: bool,
+}
+
+/// Linux configuration
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct LinuxConfig {
+    /// How keys are intercepted
+    pub backend: LinuxBackend,
+    /// Keyboards the evdev backend remaps (all of them when empty)
+    pub keyboards: Vec<KeyboardFilter>,
+}
+
+impl LinuxConfig {
+    /// Check if the evdev backend should remap a keyboard
+    pub fn remaps(&self, name: &str, vendor: u16, product: u16) -> bool {
+        self.keyboards.is_empty()
+            || self
+                .keyboards
+                .iter()
+                .any(|filter| filter.matches(name, vendor, product))
+    }
+}
+
+/// Keyboard selected by name and/or USB ids; unset fields match anything
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyboardFilter {
+    /// Part of the device name (case-insensitive)
+    pub name: Option<String>,
+    /// USB vendor id (e.g., 0x04f2)
+    pub vendor: Option<u16>,
+    /// USB product id
+    pub product: Option<u16>,
+}
+
+impl KeyboardFilter {
+    /// Check if a keyboard matches every field the filter sets
+    pub fn matches(&self, name: &str, vendor: u16, product: u16) -> bool {
+        self.name
+            .as_ref()
+            .is_none_or(|part| name.to_lowercase().contains(&part.to_lowercase()))
+            && self.vendor.is_none_or(|id| id == vendor)
+            && self.product.is_none_or(|id| id == product)
+    }
+}
+
+/// Linux keyboard interception backend
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LinuxBackend {
+    /// Global listener through rdev (X11)
+    #[default]
+    Rdev,
+    /// Exclusive grab of /dev/input keyboards, re-emitted through uinput
+    Evdev,
+}
+
+/// Automatic pause configuration
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct AutoPauseConfig {
+    /// Pause while a keyboard whose device path contains one of these is
+    /// attached, and resume when it is unplugged
+    pub when_attached: Vec<String>,
+    /// Pause while the OS itself is set to the ABNT2 layout (e.g., after
+    /// Win+Space), so characters aren't translated twice; on by default
+    pub when_os_layout_is_abnt2: bool,
+    /// Resume if a rule's condition has been gone this many minutes while
+    /// GhostKeys is still paused by it (0 turns the safeguard off)
+    pub resume_after_minutes: u32,
+}
+
+impl Default for AutoPauseConfig {
+    fn default() -> Self {
+        Self {
+            when_attached: Vec::new(),
+            when_os_layout_is_abnt2: true,
+            resume_after_minutes: 0,
+        }
+    }
+}
+
+impl AutoPauseConfig {
+    /// Check if a keyboard's device path matches one of the rules
+    pub fn matches(&self, device_path: &str) -> bool {
+        self.when_attached
+            .iter()
+            .any(|pattern| device_path_matches(device_path, pattern))
+    }
+}
+
+/// What GhostKeys does while a particular keyboard is plugged in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceMode {
+    /// Keep remapping (a US keyboard)
+    #[default]
+    Remap,
+    /// Pause, letting every key through untouched (e.g., a real ABNT2 keyboard)
+    Passthrough,
+}
+
+/// How replacement characters are delivered to applications
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum InjectionBackend {
+    /// Synthetic Unicode key presses (SendInput); works almost everywhere
+    #[default]
+    SendInput,
+    /// WM_CHAR messages posted to the focused window, for applications that
+    /// ignore synthetic Unicode input (some Java/Swing and old Win32 apps)
+    WmChar,
+}
+
+/// Character injection configuration
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct InjectionConfig {
+    /// Backend used unless an application rule matches
+    pub backend: InjectionBackend,
+    /// Per-application rules: executable name (e.g., "javaw.exe") -> backend
+    pub apps: BTreeMap<String, InjectionBackend>,
+    /// Let keys through unmapped in windows running as administrator, where
+    /// Windows blocks injected characters anyway
+    pub passthrough_elevated: bool,
+    /// Pause between injected characters in milliseconds, unless an
+    /// application rule matches (0 sends them back to back)
+    pub char_delay_ms: u64,
+    /// Per-application pauses: executable name (e.g., "mstsc.exe") -> milliseconds
+    pub delays: BTreeMap<String, u64>,
+    /// Most characters injected in one second before GhostKeys pauses itself,
+    /// in case something (e.g., a macro) types in a loop; 0 turns it off
+    pub max_chars_per_second: u32,
+}
+
+impl Default for InjectionConfig {
+    fn default() -> Self {
+        Self {
+            backend: InjectionBackend::default(),
+            apps: BTreeMap::new(),
+            passthrough_elevated: false,
+            char_delay_ms: 0,
+            delays: BTreeMap::new(),
+            max_chars_per_second: 1000,
+        }
+    }
+}
+
+/// Longest pause between injected characters, in milliseconds
+///
+/// Injection happens inside the Windows keyboard hook, which Windows removes
+/// if it takes longer than a few hundred milliseconds to return.
+pub const MAX_CHAR_DELAY_MS: u64 = 50;
+
+impl InjectionConfig {
+    /// Backend for an application, matching executable names case-insensitively
+    pub fn backend_for(&self, process_name: Option<&str>) -> InjectionBackend {
+        self.backend_rule(process_name).map_or(self.backend, |(_, backend)| backend)
+    }
+
+    /// The `apps` rule for an application, as written in the config
+    pub fn backend_rule(&self, process_name: Option<&str>) -> Option<(&str, InjectionBackend)> {
+        app_rule(&self.apps, process_name).map(|(app, backend)| (app, *backend))
+    }
+
+    /// Pause between characters for an application, matching executable names
+    /// case-insensitively, at most `MAX_CHAR_DELAY_MS`
+    pub fn char_delay_for(&self, process_name: Option<&str>) -> Duration {
+        let ms = self.delay_rule(process_name).map_or(self.char_delay_ms, |(_, ms)| ms);
+        Duration::from_millis(ms.min(MAX_CHAR_DELAY_MS))
+    }
+
+    /// The `delays` rule for an application, as written in the config
+    pub fn delay_rule(&self, process_name: Option<&str>) -> Option<(&str, u64)> {
+        app_rule(&self.delays, process_name).map(|(app, ms)| (app, *ms))
+    }
+}
+
+/// Rule for an executable name, compared case-insensitively, with the name
+/// as written in the rule
+fn app_rule<'a, T>(
+    rules: &'a BTreeMap<String, T>,
+    process_name: Option<&str>,
+) -> Option<(&'a str, &'a T)> {
+    let name = process_name?;
+    rules
+        .iter()
+        .find(|(app, _)| app.eq_ignore_ascii_case(name))
+        .map(|(app, rule)| (app.as_str(), rule))
+}
+
+/// Terminal executables recognized without configuration
+pub const TERMINAL_APPS: &[&str] = &[
+    "WindowsTerminal.exe",
+    "cmd.exe",
+    "conhost.exe",
+    "powershell.exe",
+    "pwsh.exe",
+    "mintty.exe",
+    "wezterm-gui.exe",
+    "alacritty.exe",
+    "ConEmu64.exe",
+];
+
+/// Terminal configuration (Windows)
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct TerminalsConfig {
+    /// Dead keys wait for a vowel in terminals too; when off, `'` and `~`
+    /// keys type their accent right away there
+    pub dead_keys: bool,
+    /// More terminal executables, besides the ones GhostKeys knows
+    pub apps: Vec<String>,
+    /// Windows that are terminals although their executable isn't (e.g., an
+    /// SSH client in a browser tab), by window class and title
+    pub windows: Vec<WindowRule>,
+}
+
+impl Default for TerminalsConfig {
+    fn default() -> Self {
+        Self { dead_keys: true, apps: Vec::new(), windows: Vec::new() }
+    }
+}
+
+impl TerminalsConfig {
+    /// Whether an executable is a terminal, ignoring case
+    pub fn is_terminal(&self, process_name: &str) -> bool {
+        TERMINAL_APPS
+            .iter()
+            .copied()
+            .chain(self.apps.iter().map(String::as_str))
+            .any(|app| app.eq_ignore_ascii_case(process_name))
+    }
+
+    /// Whether a window is a terminal, by its executable or a window rule
+    pub fn is_terminal_window(&self, window: &WindowInfo) -> bool {
+        self.terminal_match(window).is_some()
+    }
+
+    /// What makes a window a terminal, if it is one
+    pub fn terminal_match(&self, window: &WindowInfo) -> Option<TerminalMatch> {
+        if let Some(app) = window.app.as_deref() {
+            if TERMINAL_APPS.iter().any(|known| known.eq_ignore_ascii_case(app)) {
+                return Some(TerminalMatch::BuiltIn);
+            }
+            if self.apps.iter().any(|listed| listed.eq_ignore_ascii_case(app)) {
+                return Some(TerminalMatch::App);
+            }
+        }
+        self.windows.iter().position(|rule| rule.matches(window)).map(TerminalMatch::Window)
+    }
+}
+
+/// Why a window counts as a terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminalMatch {
+    /// Its executable is one GhostKeys knows (`TERMINAL_APPS`)
+    BuiltIn,
+    /// Its executable is listed in `apps`
+    App,
+    /// It matches the `windows` rule at this index
+    Window(usize),
+}
+
+/// What rules know about a window
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WindowInfo {
+    /// Executable name of the process owning it (e.g., "chrome.exe")
+    pub app: Option<String>,
+    /// Window class (e.g., "Chrome_WidgetWin_1")
+    pub class: Option<String>,
+    /// Window title
+    pub title: Option<String>,
+}
+
+impl WindowInfo {
+    /// A window known only by its executable name
+    pub fn app(name: impl Into<String>) -> Self {
+        Self { app: Some(name.into()), ..Self::default() }
+    }
+}
+
+/// A rule matching windows; every part that is set must match
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowRule {
+    /// Executable name, ignoring case
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app: Option<String>,
+    /// Regular expression the window class must match
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub class: Option<Pattern>,
+    /// Regular expression the window title must match
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<Pattern>,
+}
+
+impl WindowRule {
+    /// Whether `window` matches; a part of the window that is unknown
+    /// doesn't match a rule that sets it
+    pub fn matches(&self, window: &WindowInfo) -> bool {
+        let app = self.app.as_deref().is_none_or(|app| {
+            window.app.as_deref().is_some_and(|name| app.eq_ignore_ascii_case(name))
+        });
+        app && pattern_matches(&self.class, &window.class)
+            && pattern_matches(&self.title, &window.title)
+    }
+
+    /// Whether the rule looks at the window class or title, which take
+    /// extra calls to look up
+    pub fn needs_class_or_title(&self) -> bool {
+        self.class.is_some() || self.title.is_some()
+    }
+}
+
+/// Check an optional pattern against an optional value
+fn pattern_matches(pattern: &Option<Pattern>, value: &Option<String>) -> bool {
+    match pattern {
+        Some(pattern) => value.as_deref().is_some_and(|value| pattern.is_match(value)),
+        None => true,
+    }
+}
+
+/// A regular expression from the config, compiled when the config is
+/// parsed so a mistake is reported with its line
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Pattern(Regex);
+
+impl Pattern {
+    /// Compile a regular expression (`(?i)` at the start ignores case)
+    pub fn new(pattern: &str) -> std::result::Result<Self, regex::Error> {
+        Regex::new(pattern).map(Self)
+    }
+
+    /// Whether the pattern matches anywhere in `text`
+    pub fn is_match(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+
+    /// The pattern as written
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for Pattern {}
+
+impl TryFrom<String> for Pattern {
+    type Error = regex::Error;
+
+    fn try_from(pattern: String) -> std::result::Result<Self, Self::Error> {
+        Self::new(&pattern)
+    }
+}
+
+impl From<Pattern> for String {
+    fn from(pattern: Pattern) -> Self {
+        pattern.as_str().to_string()
+    }
+}
+
+impl JsonSchema for Pattern {
+    fn schema_name() -> String {
+        "Pattern".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            format: Some("regex".to_string()),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+/// Macro recording and playback configuration
+///
+/// Ctrl+Shift+F<n> starts or stops recording into slot n; Ctrl+Alt+F<n>
+/// replays it. Recorded macros are written back to this section of the config
+/// file, leaving the rest of it as it was.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct MacrosConfig {
+    /// Enable the macro hotkeys; off by default since they shadow app shortcuts
+    pub enabled: bool,
+    /// Slot ("f1" through "f12") -> text to type
+    pub slots: BTreeMap<String, String>,
+}
+
+/// Accent autocorrect configuration
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct AutocorrectConfig {
+    /// Add missing accents to unambiguous words (nao -> não); off by default
+    pub enabled: bool,
+    /// Offer the accented spellings of ambiguous words (esta -> está) in a
+    /// popup after the word, picked with Alt+1..9 (Windows); off by default
+    pub suggestions: bool,
+}
+
+/// Physical keys that can act as the Compose key
+///
+/// Only keys with no meaning of their own in everyday typing are offered,
+/// since the key is swallowed while GhostKeys is active.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ComposeTrigger {
+    /// The context menu (Application) key
+    Menu,
+    /// Right Ctrl
+    RightCtrl,
+    /// Scroll Lock
+    ScrollLock,
+    /// Pause/Break
+    Pause,
+}
+
+/// Compose key configuration
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct ComposeConfig {
+    /// Key that starts a sequence; compose is disabled when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trigger: Option<ComposeTrigger>,
+    /// Sequence typed after the trigger -> text to insert
+    pub sequences: BTreeMap<String, String>,
+}
+
+impl Default for ComposeConfig {
+    fn default() -> Self {
+        let sequences = [
+            ("oc", "©"),
+            ("or", "®"),
+            ("tm", "™"),
+            ("--", "–"),
+            ("..", "…"),
+            ("<<", "«"),
+            (">>", "»"),
+            ("+-", "±"),
+            // Ordinal indicators, also on AltGr + ] and AltGr + \
+            ("a_", "ª"),
+            ("o_", "º"),
+            ("A_", "ª"),
+            ("O_", "º"),
+        ]
+        .into_iter()
+        .map(|(sequence, output)| (sequence.to_string(), output.to_string()))
+        .collect();
+
+        Self {
+            trigger: None,
+            sequences,
+        }
+    }
+}
+
+impl Config {
+    /// Parse configuration from TOML text
+    pub fn parse(text: &str) -> Result<Self> {
+        Self::parse_from(text, None)
+    }
+
+    /// Parse configuration, naming the file and line in errors
+    pub(crate) fn parse_from(text: &str, path: Option<&Path>) -> Result<Self> {
+        toml::from_str(text).map_err(|e| GhostKeysError::ConfigError {
+            path: path.map(Path::to_path_buf),
+            line: e.span().map(|span| line_number(text, span.start)),
+            message: e.message().to_string(),
+        })
+    }
+
+    /// Load configuration from a file, falling back to defaults if it is missing
+    pub fn load_from(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::parse_from(&text, Some(path)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(io_error(path, e)),
+        }
+    }
+
+    /// Dead keys from `[dead_keys]`, in the form the mapper takes
+    pub fn dead_key_triggers(
+        &self,
+    ) -> impl Iterator<Item = ((VirtualKey, bool), AccentType)> + '_ {
+        self.dead_keys.iter().map(|(position, &accent)| ((position.key, position.shift), accent))
+    }
+
+    /// Mode for a keyboard, matching rules against its device path
+    /// case-insensitively; keyboards without a rule are remapped
+    pub fn device_mode(&self, device_path: &str) -> DeviceMode {
+        self.devices
+            .iter()
+            .find(|(pattern, _)| device_path_matches(device_path, pattern))
+            .map(|(_, mode)| *mode)
+            .unwrap_or_default()
+    }
+
+    /// Whether GhostKeys pauses while this keyboard is plugged in, by an
+    /// `[auto_pause]` rule or a `passthrough` rule in `[devices]`
+    pub fn pauses_for_keyboard(&self, device_path: &str) -> bool {
+        self.auto_pause.matches(device_path) || self.device_mode(device_path) == DeviceMode::Passthrough
+    }
+
+    /// Load configuration from the default location
+    pub fn load() -> Result<Self> {
+        match paths::config_file() {
+            Some(path) => Self::load_from(&path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Write configuration to a file, creating its directory if needed
+    ///
+    /// The file is regenerated from the parsed values, so comments are lost.
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        let text = toml::to_string_pretty(self).map_err(|e| GhostKeysError::ConfigError {
+            path: Some(path.to_path_buf()),
+            line: None,
+            message: e.to_string(),
+        })?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| io_error(dir, e))?;
+        }
+        std::fs::write(path, text).map_err(|e| io_error(path, e))
+    }
+
+    /// JSON Schema of the config file, generated from these types so editors
+    /// can complete and check it (`ghostkeys config schema`)
+    pub fn json_schema() -> String {
+        let schema = schemars::schema_for!(Config);
+        serde_json::to_string_pretty(&schema).expect("schemas serialize to JSON")
+    }
+
+    /// Write configuration to the default location
+    pub fn save(&self) -> Result<()> {
+        let path = paths::config_file().ok_or_else(|| GhostKeysError::ConfigError {
+            path: None,
+            line: None,
+            message: "could not determine the config directory".to_string(),
+        })?;
+        self.save_to(&path)
+    }
+
+    /// Write `sections` of this config (top-level keys, such as "macros")
+    /// into the file at `path` in place, creating it if needed
+    ///
+    /// Unlike `save_to`, only the values that differ from what the file says
+    /// now are written; comments, formatting and the other sections stay as
+    /// the user wrote them. A file that isn't a valid config is left alone.
+    pub fn save_sections_to(&self, path: &Path, sections: &[&str]) -> Result<()> {
+        let error = |message: String| GhostKeysError::ConfigError {
+            path: Some(path.to_path_buf()),
+            line: None,
+            message,
+        };
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(io_error(path, e)),
+        };
+        let current = Self::parse_from(&text, Some(path))?;
+        let as_document = |config: &Config| -> Result<Document> {
+            let text = toml::to_string(config).map_err(|e| error(e.to_string()))?;
+            text.parse().map_err(|e| error(format!("{}", e)))
+        };
+        let (current, values) = (as_document(&current)?, as_document(self)?);
+        let mut document: Document = text.parse().map_err(|e| error(format!("{}", e)))?;
+
+        let root = document.as_table_mut();
+        for &section in sections {
+            let Some(new) = values.get(section) else {
+                root.remove(section);
+                continue;
+            };
+            let effective = current.get(section);
+            if effective.is_some_and(|effective| same(effective, new)) {
+                continue;
+            }
+            match root.get_mut(section) {
+                Some(old) => sync_item(old, effective, new),
+                None => {
+                    // New sections go at the end of the file
+                    let mut table = Table::new();
+                    table.set_position(usize::MAX);
+                    let old = root.entry(section).or_insert(Item::Table(table));
+                    sync_item(old, effective, new);
+                }
+            }
+        }
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| io_error(dir, e))?;
+        }
+        std::fs::write(path, document.to_string()).map_err(|e| io_error(path, e))
+    }
+
+    /// Write `sections` of this config into the file at the default location
+    /// in place (see `save_sections_to`)
+    pub fn save_sections(&self, sections: &[&str]) -> Result<()> {
+        let path = paths::config_file().ok_or_else(|| GhostKeysError::ConfigError {
+            path: None,
+            line: None,
+            message: "could not determine the config directory".to_string(),
+        })?;
+        self.save_sections_to(&path, sections)
+    }
+}
+
+/// Make `old`, from the file, hold what `new` does, changing only what
+/// differs from what it holds now (`effective`, defaults included) so the
+/// comments and formatting around the rest are kept
+fn sync_item(old: &mut Item, effective: Option<&Item>, new: &Item) {
+    let inline = old.is_inline_table();
+    let position = old.as_table().and_then(Table::position).unwrap_or(usize::MAX);
+    if let (Some(old_table), Some(new_table)) = (old.as_table_like_mut(), new.as_table_like()) {
+        let gone: Vec<String> = old_table
+            .iter()
+            .map(|(key, _)| key.to_string())
+            .filter(|key| !new_table.contains_key(key))
+            .collect();
+        for key in gone {
+            old_table.remove(&key);
+        }
+        for (key, item) in new_table.iter() {
+            let effective = effective.and_then(Item::as_table_like).and_then(|table| table.get(key));
+            if effective.is_some_and(|effective| same(effective, item)) {
+                continue;
+            }
+            if let Some(existing) = old_table.get_mut(key) {
+                sync_item(existing, effective, item);
+                continue;
+            }
+            let mut item = item.clone();
+            if inline {
+                item = item.into_value().map_or(Item::None, Item::Value);
+            }
+            // A new sub-table follows its parent in the file
+            place(&mut item, position);
+            old_table.insert(key, item);
+        }
+        return;
+    }
+    if let (Some(old_value), Some(new_value)) = (old.as_value_mut(), new.as_value()) {
+        let decor = old_value.decor().clone();
+        *old_value = new_value.clone();
+        *old_value.decor_mut() = decor;
+        return;
+    }
+    *old = if old.is_value() {
+        new.clone().into_value().map_or(Item::None, Item::Value)
+    } else {
+        new.clone()
+    };
+}
+
+/// Whether two items hold the same values, however they're written
+fn same(a: &Item, b: &Item) -> bool {
+    match (a.as_table_like(), b.as_table_like()) {
+        (Some(a), Some(b)) => {
+            a.len() == b.len()
+                && a.iter().all(|(key, item)| b.get(key).is_some_and(|other| same(item, other)))
+        }
+        (None, None) => match (a, b) {
+            (Item::ArrayOfTables(a), Item::ArrayOfTables(b)) => {
+                a.len() == b.len()
+                    && a.iter().zip(b.iter()).all(|(a, b)| {
+                        same(&Item::Table(a.clone()), &Item::Table(b.clone()))
+                    })
+            }
+            _ => match (a.as_value(), b.as_value()) {
+                (Some(a), Some(b)) => bare(a) == bare(b),
+                _ => false,
+            },
+        },
+        _ => false,
+    }
+}
+
+/// A value as written, without the spaces and comments around it
+fn bare(value: &Value) -> String {
+    let mut value = value.clone();
+    value.decor_mut().clear();
+    value.to_string()
+}
+
+/// Position the tables in `item` at `position` in the file
+fn place(item: &mut Item, position: usize) {
+    fn place_table(table: &mut Table, position: usize) {
+        table.set_position(position);
+        for (_, child) in table.iter_mut() {
+            place(child, position);
+        }
+    }
+    match item {
+        Item::Table(table) => place_table(table, position),
+        Item::ArrayOfTables(tables) => {
+            for table in tables.iter_mut() {
+                place_table(table, position);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// 1-based line containing a byte offset
+pub(crate) fn line_number(text: &str, offset: usize) -> usize {
+    let offset = offset.min(text.len());
+    text.as_bytes()[..offset].iter().filter(|&&byte| byte == b'\n').count() + 1
+}
+
+/// Convert a failure to read or write a config file or directory
+pub(crate) fn io_error(path: &Path, error: std::io::Error) -> GhostKeysError {
+    if error.kind() == std::io::ErrorKind::PermissionDenied {
+        GhostKeysError::PermissionDenied {
+            message: path.display().to_string(),
+            code: error.raw_os_error().unwrap_or_default(),
+            platform_hint: "Check that the file isn't read-only or owned by another user",
+        }
+    } else {
+        GhostKeysError::ConfigError {
+            path: Some(path.to_path_buf()),
+            line: None,
+            message: error.to_string(),
+        }
+    }
+}
+
+/// Check if a device path contains a pattern, ignoring case
+fn device_path_matches(device_path: &str, pattern: &str) -> bool {
+    device_path
+        .to_ascii_uppercase()
+        .contains(&pattern.to_ascii_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::{DeadKeySemantics, SlashPlacement};
+
+    #[test]
+    fn test_empty_config_is_default() {
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_parse_compose() {
+        let config = Config::parse(
+            r#"
+            [compose]
+            trigger = "right_ctrl"
+
+            [compose.sequences]
+            "ae" = "æ"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.compose.trigger, Some(ComposeTrigger::RightCtrl));
+        // User sequences replace the defaults
+        assert_eq!(config.compose.sequences.len(), 1);
+        assert_eq!(config.compose.sequences["ae"], "æ");
+    }
+
+    #[test]
+    fn test_autocorrect_is_opt_in() {
+        assert!(!Config::default().autocorrect.enabled);
+        assert!(!Config::default().autocorrect.suggestions);
+        let config = Config::parse("[autocorrect]\nenabled = true\n").unwrap();
+        assert!(config.autocorrect.enabled);
+    }
+
+    #[test]
+    fn test_feedback_sounds_per_accent() {
+        assert!(!Config::default().feedback.is_enabled());
+        let config = Config::parse(
+            "[feedback]\narmed = \"beep\"\n[feedback.accents]\n\
+             tilde = { armed = \"question\", timeout = \"exclamation\" }\n",
+        )
+        .unwrap();
+        let feedback = &config.feedback;
+        assert!(feedback.is_enabled());
+        assert_eq!(feedback.armed_sound(AccentType::Acute), Sound::Beep);
+        assert_eq!(feedback.armed_sound(AccentType::Tilde), Sound::Question);
+        assert_eq!(feedback.timeout_sound(AccentType::Acute), Sound::None);
+        assert_eq!(feedback.timeout_sound(AccentType::Tilde), Sound::Exclamation);
+        assert!(Config::parse("[feedback]\narmed = \"loud\"\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_injection_rules() {
+        let config = Config::parse(
+            r#"
+            [injection.apps]
+            "javaw.exe" = "wm_char"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.injection.backend, InjectionBackend::SendInput);
+        assert_eq!(config.injection.apps["javaw.exe"], InjectionBackend::WmChar);
+    }
+
+    #[test]
+    fn test_injection_backend_for() {
+        let mut injection = InjectionConfig::default();
+        injection.apps.insert("javaw.exe".to_string(), InjectionBackend::WmChar);
+
+        assert_eq!(injection.backend_for(Some("JavaW.EXE")), InjectionBackend::WmChar);
+        assert_eq!(injection.backend_for(Some("notepad.exe")), InjectionBackend::SendInput);
+        assert_eq!(injection.backend_for(None), InjectionBackend::SendInput);
+        assert_eq!(
+            injection.backend_rule(Some("JavaW.EXE")),
+            Some(("javaw.exe", InjectionBackend::WmChar))
+        );
+    }
+
+    #[test]
+    fn test_parse_injection_delays() {
+        let config = Config::parse(
+            r#"
+            [injection]
+            char_delay_ms = 2
+
+            [injection.delays]
+            "mstsc.exe" = 15
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.injection.char_delay_ms, 2);
+        assert_eq!(config.injection.delays["mstsc.exe"], 15);
+    }
+
+    #[test]
+    fn test_injection_flood_limit() {
+        assert_eq!(InjectionConfig::default().max_chars_per_second, 1000);
+        let config = Config::parse("[injection]\nmax_chars_per_second = 0\n").unwrap();
+        assert_eq!(config.injection.max_chars_per_second, 0);
+    }
+
+    #[test]
+    fn test_injection_char_delay_for() {
+        let mut injection = InjectionConfig::default();
+        assert_eq!(injection.char_delay_for(None), Duration::ZERO);
+
+        injection.char_delay_ms = 2;
+        injection.delays.insert("mstsc.exe".to_string(), 15);
+        injection.delays.insert("slow.exe".to_string(), 10_000);
+        assert_eq!(injection.char_delay_for(Some("MSTSC.exe")), Duration::from_millis(15));
+        assert_eq!(injection.char_delay_for(Some("notepad.exe")), Duration::from_millis(2));
+        assert_eq!(injection.char_delay_for(None), Duration::from_millis(2));
+        // Capped so the keyboard hook isn't timed out
+        assert_eq!(
+            injection.char_delay_for(Some("slow.exe")),
+            Duration::from_millis(MAX_CHAR_DELAY_MS)
+        );
+    }
+
+    #[test]
+    fn test_device_mode() {
+        let config = Config::parse(
+            r#"
+            [devices]
+            "vid_04f2&pid_0833" = "passthrough"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.device_mode(r"\\?\HID#VID_04F2&PID_0833&MI_00#7&1a2b"),
+            DeviceMode::Passthrough
+        );
+        assert_eq!(config.device_mode(r"\\?\ACPI#PNP0303#4&5c6d"), DeviceMode::Remap);
+        assert!(config.pauses_for_keyboard(r"\\?\HID#VID_04F2&PID_0833&MI_00#7&1a2b"));
+        assert!(!config.pauses_for_keyboard(r"\\?\ACPI#PNP0303#4&5c6d"));
+    }
+
+    #[test]
+    fn test_auto_pause_matches() {
+        let auto_pause = AutoPauseConfig {
+            when_attached: vec!["VID_04F2&PID_0833".to_string()],
+            ..Default::default()
+        };
+        assert!(auto_pause.matches(r"\\?\HID#vid_04f2&pid_0833&MI_00#7&1a2b"));
+        assert!(!auto_pause.matches(r"\\?\ACPI#PNP0303#4&5c6d"));
+        assert!(!AutoPauseConfig::default().matches(r"\\?\ACPI#PNP0303#4&5c6d"));
+    }
+
+    #[test]
+    fn test_startup_defaults() {
+        let config = Config::parse("[startup]\nstart_paused = true\n").unwrap();
+        assert!(config.startup.show_tray);
+        assert!(config.startup.start_paused);
+        assert!(config.hotkeys.toggle_pause);
+        assert!(!config.hotkeys.palette);
+        assert!(!config.hotkeys.undo);
+    }
+
+    #[test]
+    fn test_terminals() {
+        let mut terminals = TerminalsConfig::default();
+        assert!(terminals.dead_keys);
+        assert!(terminals.is_terminal("windowsterminal.exe"));
+        assert!(!terminals.is_terminal("notepad.exe"));
+
+        terminals.apps.push("Hyper.exe".to_string());
+        assert!(terminals.is_terminal("hyper.exe"));
+    }
+
+    #[test]
+    fn test_terminal_windows() {
+        let mut terminals = TerminalsConfig::default();
+        terminals.windows.push(WindowRule {
+            app: Some("chrome.exe".to_string()),
+            class: Some(Pattern::new("^Chrome_WidgetWin").unwrap()),
+            title: Some(Pattern::new("(?i)ssh|cloud shell").unwrap()),
+        });
+        let window = |title: &str| WindowInfo {
+            app: Some("Chrome.exe".to_string()),
+            class: Some("Chrome_WidgetWin_1".to_string()),
+            title: Some(title.to_string()),
+        };
+        assert!(terminals.is_terminal_window(&window("SSH-in-browser - Google Chrome")));
+        assert!(terminals.is_terminal_window(&window("Cloud Shell - Google Chrome")));
+        assert!(!terminals.is_terminal_window(&window("Inbox - Google Chrome")));
+        // The title wasn't looked up
+        assert!(!terminals.is_terminal_window(&WindowInfo::app("chrome.exe")));
+        assert!(terminals.is_terminal_window(&WindowInfo::app("cmd.exe")));
+
+        assert_eq!(
+            terminals.terminal_match(&window("SSH - Google Chrome")),
+            Some(TerminalMatch::Window(0))
+        );
+        assert_eq!(
+            terminals.terminal_match(&WindowInfo::app("CMD.EXE")),
+            Some(TerminalMatch::BuiltIn)
+        );
+    }
+
+    #[test]
+    fn test_invalid_window_pattern() {
+        assert!(Pattern::new("(?i)ssh").is_ok());
+        assert!(Pattern::new("[ssh").is_err());
+        let config = "[[terminals.windows]]\ntitle = \"[ssh\"\n";
+        assert!(Config::parse(config).is_err());
+    }
+
+    #[test]
+    fn test_parse_language() {
+        assert_eq!(Config::default().language, None);
+        let config = Config::parse("language = \"pt-BR\"\n").unwrap();
+        assert_eq!(config.language, Some(Language::Portuguese));
+        assert!(Config::parse("language = \"fr\"\n").is_err());
+    }
+
+    #[test]
+    fn test_os_layout_pause_is_on_by_default() {
+        assert!(Config::default().auto_pause.when_os_layout_is_abnt2);
+        let config = Config::parse("[auto_pause]\nwhen_attached = []\n").unwrap();
+        assert!(config.auto_pause.when_os_layout_is_abnt2);
+        assert_eq!(config.auto_pause.resume_after_minutes, 0);
+    }
+
+    #[test]
+    fn test_mode_change_toasts_are_on_by_default() {
+        assert!(Config::default().notifications.mode_changes);
+        let config = Config::parse("[notifications]\nmode_changes = false\n").unwrap();
+        assert!(!config.notifications.mode_changes);
+    }
+
+    #[test]
+    fn test_parse_source_layout() {
+        assert_eq!(Config::default().source_layout, SourceLayout::Us);
+        let config = Config::parse("source_layout = \"colemak\"\n").unwrap();
+        assert_eq!(config.source_layout, SourceLayout::Colemak);
+    }
+
+    #[test]
+    fn test_parse_key_codes() {
+        assert_eq!(Config::default().key_codes, KeyCodes::Auto);
+        let config = Config::parse("key_codes = \"scan_code\"\n").unwrap();
+        assert_eq!(config.key_codes, KeyCodes::ScanCode);
+        assert!(Config::parse("key_codes = \"vk\"\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_features() {
+        assert_eq!(Config::default().features, MappingFeatures::default());
+        let config = Config::parse("[features]
+acute = false
+altgr = false
+dead_key_semantics = \"us_international\"
+apostrophe_c_cedilla = true
+slash_question = \"us_key\"
+").unwrap();
+        assert!(!config.features.acute && !config.features.altgr);
+        assert!(config.features.positions && config.features.tilde);
+        assert_eq!(config.features.dead_key_semantics, DeadKeySemantics::UsInternational);
+        assert_eq!(config.features.apostrophe_c_cedilla, Some(true));
+        assert_eq!(config.features.slash_question, SlashPlacement::UsKey);
+    }
+
+    #[test]
+    fn test_parse_dead_keys() {
+        let text = "[dead_keys]\n\"'\" = \"acute\"\n\"shift+6\" = \"circumflex\"\n";
+        let config = Config::parse(text).unwrap();
+        let circumflex = KeyPosition::parse("^").unwrap();
+        assert_eq!(config.dead_keys.get(&circumflex), Some(&AccentType::Circumflex));
+        assert_eq!(config.dead_keys.len(), 2);
+
+        let error = Config::parse("[dead_keys]\n\"ç\" = \"acute\"\n").unwrap_err();
+        assert!(error.to_string().contains("unknown key"));
+    }
+
+    #[test]
+    fn test_crash_relaunch_is_opt_in() {
+        assert!(!Config::default().crash.relaunch);
+        let config = Config::parse("[crash]\nrelaunch = true\n").unwrap();
+        assert!(config.crash.relaunch);
+    }
+
+    #[test]
+    fn test_parse_linux_backend() {
+        assert_eq!(Config::default().linux.backend, LinuxBackend::Rdev);
+        let config = Config::parse("[linux]\nbackend = \"evdev\"\n").unwrap();
+        assert_eq!(config.linux.backend, LinuxBackend::Evdev);
+    }
+
+    #[test]
+    fn test_keyboard_filters() {
+        let mut linux = LinuxConfig::default();
+        assert!(linux.remaps("AT Translated Set 2 keyboard", 0x0001, 0x0001));
+
+        linux.keyboards = vec![
+            KeyboardFilter { name: Some("translated set".to_string()), ..Default::default() },
+            KeyboardFilter { vendor: Some(0x04f2), product: Some(0x0833), ..Default::default() },
+        ];
+        assert!(linux.remaps("AT Translated Set 2 keyboard", 0x0001, 0x0001));
+        assert!(linux.remaps("USB Keyboard", 0x04f2, 0x0833));
+        assert!(!linux.remaps("USB Keyboard", 0x04f2, 0x0001));
+        assert!(!linux.remaps("Living room remote", 0x1d57, 0xad02));
+    }
+
+    #[test]
+    fn test_save_round_trip() {
+        let mut config = Config::default();
+        config.compose.trigger = Some(ComposeTrigger::Menu);
+        config.source_layout = SourceLayout::Dvorak;
+        config.macros.enabled = true;
+        config.macros.slots.insert("f1".to_string(), "Atenciosamente,\nJoão".to_string());
+
+        let path = std::env::temp_dir()
+            .join(format!("ghostkeys-test-{}", std::process::id()))
+            .join("config.toml");
+        config.save_to(&path).unwrap();
+        let loaded = Config::load_from(&path);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+
+        assert_eq!(loaded.unwrap(), config);
+    }
+
+    #[test]
+    fn test_save_sections_keeps_the_rest_of_the_file() {
+        let text = "\
+# My keyboard
+source_layout = \"dvorak\" # at work
+
+[macros]
+enabled = true # F-keys are free
+slots = { f2 = \"Abraços\" }
+
+[compose]
+# The menu key does nothing else
+trigger = \"menu\"
+";
+        let dir = std::env::temp_dir().join(format!("ghostkeys-sections-{}", std::process::id()));
+        let path = dir.join("config.toml");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&path, text).unwrap();
+
+        let mut config = Config::parse(text).unwrap();
+        config.macros.slots.remove("f2");
+        config.macros.slots.insert("f1".to_string(), "Atenciosamente,\nJoão".to_string());
+        // Only the sections named are written
+        config.compose.trigger = None;
+        config.save_sections_to(&path, &["macros", "nav"]).unwrap();
+        let saved = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        for kept in ["# My keyboard", "# at work", "# F-keys are free", "# The menu key"] {
+            assert!(saved.contains(kept), "{}", saved);
+        }
+        // Values left at their defaults aren't spelled out
+        assert!(!saved.contains("[nav]"), "{}", saved);
+        let loaded = Config::parse(&saved).unwrap();
+        assert_eq!(loaded.macros, config.macros);
+        assert_eq!(loaded.compose.trigger, Some(ComposeTrigger::Menu));
+        assert_eq!(loaded.nav, config.nav);
+    }
+
+    #[test]
+    fn test_parse_error_names_line() {
+        let error = Config::parse("[compose]\ntrigger = \"menu\"\ntrigerr = 1\n").unwrap_err();
+        assert!(matches!(error, GhostKeysError::ConfigError { line: Some(3), .. }));
+    }
+
+    #[test]
+    fn test_line_number() {
+        assert_eq!(line_number("a\nb\nc", 0), 1);
+        assert_eq!(line_number("a\nb\nc", 4), 3);
+        assert_eq!(line_number("a", 10), 1);
+    }
+
+    #[test]
+    fn test_unknown_fields_are_rejected() {
+        assert!(Config::parse("[compose]\ntrigerr = \"menu\"\n").is_err());
+    }
+
+    #[test]
+    fn test_missing_file_is_default() {
+        let config = Config::load_from(Path::new("/nonexistent/ghostkeys/config.toml")).unwrap();
+        assert_eq!(config, Config::default());
+    }
+
+    #[test]
+    fn test_schema_covers_every_section() {
+        let schema: serde_json::Value = serde_json::from_str(&Config::json_schema()).unwrap();
+        let properties = schema["properties"].as_object().unwrap();
+        let defaults = toml::Value::try_from(Config::default()).unwrap();
+        for section in defaults.as_table().unwrap().keys() {
+            assert!(properties.contains_key(section), "{} is missing", section);
+        }
+        assert_eq!(schema["additionalProperties"], false);
+
+        let definitions = &schema["definitions"];
+        assert_eq!(definitions["Pattern"]["format"], "regex");
+        let action = regex::Regex::new(definitions["Action"]["pattern"].as_str().unwrap()).unwrap();
+        assert!(action.is_match("pause_15m") && action.is_match("switch_layout:br"));
+        assert!(!action.is_match("pause"));
+    }
+}
@@ -0,0 +1,78 @@
+//! Detection of other keyboard remappers
+//!
+//! Two programs remapping the same keys produce output that matches neither
+//! (e.g., an AutoHotkey hotstring firing on GhostKeys' injected characters).
+//! Low-level hooks can't be enumerated, so conflicts are recognized by the
+//! process names of well-known tools; platforms add their own signatures
+//! (such as a registry scancode map on Windows).
+
+/// A remapper recognized by its process name
+struct KnownRemapper {
+    /// Lowercase process name prefix, without extension
+    process_prefix: &'static str,
+    /// Product name shown to the user
+    name: &'static str,
+}
+
+/// Remappers known to rewrite keystrokes system-wide
+const KNOWN_REMAPPERS: &[KnownRemapper] = &[
+    KnownRemapper { process_prefix: "autohotkey", name: "AutoHotkey" },
+    KnownRemapper { process_prefix: "powertoys.keyboardmanagerengine", name: "PowerToys Keyboard Manager" },
+    KnownRemapper { process_prefix: "kanata", name: "Kanata" },
+    KnownRemapper { process_prefix: "kmonad", name: "KMonad" },
+    KnownRemapper { process_prefix: "espanso", name: "Espanso" },
+    KnownRemapper { process_prefix: "clavier", name: "Clavier+" },
+    KnownRemapper { process_prefix: "keyremapper", name: "Key Remapper" },
+];
+
+/// Names of the known remappers among running processes
+///
+/// Process names may include a path and extension; each product is listed
+/// once, in the order of the first matching process.
+pub fn detect<'a>(process_names: impl IntoIterator<Item = &'a str>) -> Vec<&'static str> {
+    let mut found = Vec::new();
+    for process in process_names {
+        let file = process.rsplit(['\\', '/']).next().unwrap_or(process).to_lowercase();
+        let stem = file.strip_suffix(".exe").unwrap_or(&file);
+        if let Some(remapper) = KNOWN_REMAPPERS.iter().find(|r| stem.starts_with(r.process_prefix)) {
+            if !found.contains(&remapper.name) {
+                found.push(remapper.name);
+            }
+        }
+    }
+    found
+}
+
+/// Warning shown for a detected remapper
+pub fn warning(name: &str) -> String {
+    format!(
+        "{} is running and may remap the same keys, which produces doubled or wrong characters. \
+         Exit it or pause GhostKeys while using it",
+        name
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_known_remappers() {
+        let processes = [
+            r"C:\Program Files\AutoHotkey\v2\AutoHotkey64.exe",
+            "explorer.exe",
+            "PowerToys.KeyboardManagerEngine.exe",
+            "kanata_wintercept.exe",
+            "AutoHotkeyU64.exe",
+        ];
+        assert_eq!(
+            detect(processes),
+            vec!["AutoHotkey", "PowerToys Keyboard Manager", "Kanata"]
+        );
+    }
+
+    #[test]
+    fn test_detect_ignores_unrelated_processes() {
+        assert!(detect(["PowerToys.exe", "chrome.exe", "ghostkeys.exe"]).is_empty());
+    }
+}
@@ -0,0 +1,187 @@
+//! Crash reports
+//!
+//! A crash takes the keyboard hook down with it, and whatever was queued for
+//! the tray is lost. The panic hook (and the unhandled-exception filter on
+//! Windows) call [`handle_crash`] after releasing the hook: it flushes pending
+//! notifications, writes a report with the mapper state to the `crashes`
+//! folder next to the config file, and relaunches GhostKeys if
+//! `relaunch = true` under `[crash]` (unless a supervisor will restart it).
+//! The process then ends with [`EXIT_CODE`], so it never lingers with the
+//! hook gone and the tray still saying it's active.
+//!
+//! Everything here runs on a dying process, possibly on the thread that holds
+//! the shared state lock, so it never blocks and ignores its own failures.
+
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::error::Platform;
+use crate::notify::{Notification, Severity};
+use crate::paths;
+use crate::platform::system_log;
+use crate::state::SharedState;
+use crate::supervisor;
+
+/// A crash sooner than this after startup isn't relaunched, so a crash at
+/// startup doesn't turn into a restart loop
+pub const RELAUNCH_MIN_UPTIME: Duration = Duration::from_secs(30);
+
+/// Exit code of a crashed process (the one Rust uses for a panic), which the
+/// supervisor takes as a reason to restart it
+pub const EXIT_CODE: i32 = 101;
+
+/// State the crash handler reads, set once at startup
+struct CrashContext {
+    state: SharedState,
+    started: Instant,
+}
+
+static CONTEXT: OnceLock<CrashContext> = OnceLock::new();
+
+/// Set by the first crash so a second fault while handling it isn't reported
+static HANDLING: AtomicBool = AtomicBool::new(false);
+
+/// What is known about a crash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrashReport {
+    /// Panic message and location, or the exception code
+    pub reason: String,
+    /// Mapper state on the crashing thread, if it was the hook thread
+    pub mapper_state: Option<String>,
+    /// Notifications that hadn't been shown yet
+    pub notifications: Vec<Notification>,
+    /// Backtrace of the crashing thread
+    pub backtrace: String,
+}
+
+impl CrashReport {
+    /// Text written to the report file
+    pub fn render(&self) -> String {
+        let mut text = format!(
+            "GhostKeys {} crashed on {}\n\nReason: {}\nMapper state: {}\n",
+            env!("CARGO_PKG_VERSION"),
+            Platform::current(),
+            self.reason,
+            self.mapper_state.as_deref().unwrap_or("unavailable (not the hook thread)")
+        );
+        if !self.notifications.is_empty() {
+            text.push_str("\nPending notifications:\n");
+            for notification in &self.notifications {
+                text.push_str(&format!("  {}\n", notification));
+            }
+        }
+        text.push_str(&format!("\nBacktrace:\n{}\n", self.backtrace));
+        text
+    }
+}
+
+/// Remember what the crash handler needs; call once at startup
+pub fn install(state: SharedState) {
+    let _ = CONTEXT.set(CrashContext { state, started: Instant::now() });
+}
+
+/// Path for a new crash artifact with the given extension, creating the folder
+pub fn artifact_path(extension: &str) -> Option<PathBuf> {
+    let dir = paths::crash_dir()?;
+    std::fs::create_dir_all(&dir).ok()?;
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |t| t.as_secs());
+    Some(dir.join(format!("crash-{}.{}", timestamp, extension)))
+}
+
+/// Whether a process that crashed after running for `uptime` should be relaunched
+pub fn should_relaunch(relaunch: bool, uptime: Duration) -> bool {
+    relaunch && uptime >= RELAUNCH_MIN_UPTIME
+}
+
+/// Flush logs, write the crash report and relaunch if configured
+///
+/// The keyboard hook must already be released, and the caller ends the
+/// process right after; the relaunched instance only installs its hook once
+/// it has started up, by which time this one is gone.
+pub fn handle_crash(reason: &str, mapper_state: Option<String>) {
+    if HANDLING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let context = CONTEXT.get();
+    let config = context.and_then(|c| c.state.try_get_config()).unwrap_or_default();
+
+    let notifications = context.map(|c| c.state.try_take_notifications()).unwrap_or_default();
+    for notification in &notifications {
+        eprintln!("{}", notification);
+        if config.logging.system_log && notification.severity != Severity::Info {
+            system_log::write(notification);
+        }
+    }
+
+    let report = CrashReport {
+        reason: reason.to_string(),
+        mapper_state,
+        notifications,
+        backtrace: std::backtrace::Backtrace::force_capture().to_string(),
+    };
+    let written = artifact_path("txt").filter(|path| std::fs::write(path, report.render()).is_ok());
+    let message = match &written {
+        Some(path) => format!("GhostKeys crashed: {}. Crash report: {}", reason, path.display()),
+        None => format!("GhostKeys crashed: {}", reason),
+    };
+    eprintln!("{}", message);
+    if config.logging.system_log {
+        system_log::write_message(Severity::Error, &message);
+    }
+
+    let uptime = context.map_or(Duration::ZERO, |c| c.started.elapsed());
+    if !supervisor::is_supervised() && should_relaunch(config.crash.relaunch, uptime) {
+        if let Ok(exe) = std::env::current_exe() {
+            match Command::new(exe).args(std::env::args_os().skip(1)).spawn() {
+                Ok(_) => eprintln!("Relaunched GhostKeys"),
+                Err(e) => eprintln!("Failed to relaunch GhostKeys: {}", e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_includes_state_and_notifications() {
+        let report = CrashReport {
+            reason: "panicked at src/mapper.rs:10:5".to_string(),
+            mapper_state: Some("PendingAccent(Acute)".to_string()),
+            notifications: vec![Notification {
+                severity: Severity::Warning,
+                message: "Failed to inject key".to_string(),
+            }],
+            backtrace: "0: main".to_string(),
+        };
+        let text = report.render();
+        assert!(text.contains("Reason: panicked at src/mapper.rs:10:5"));
+        assert!(text.contains("Mapper state: PendingAccent(Acute)"));
+        assert!(text.contains("[warning] Failed to inject key"));
+        assert!(text.contains("Backtrace:\n0: main"));
+    }
+
+    #[test]
+    fn test_render_without_mapper_state() {
+        let report = CrashReport {
+            reason: "unhandled exception 0xC0000005".to_string(),
+            mapper_state: None,
+            notifications: Vec::new(),
+            backtrace: String::new(),
+        };
+        let text = report.render();
+        assert!(text.contains("Mapper state: unavailable"));
+        assert!(!text.contains("Pending notifications"));
+    }
+
+    #[test]
+    fn test_relaunch_needs_opt_in_and_uptime() {
+        assert!(!should_relaunch(false, Duration::from_secs(3600)));
+        assert!(!should_relaunch(true, Duration::from_secs(1)));
+        assert!(should_relaunch(true, RELAUNCH_MIN_UPTIME));
+    }
+}
@@ -0,0 +1,216 @@
+//! `ghostkeys doctor`: a check of everything users commonly get wrong
+//!
+//! Each check reports a finding and, when something is off, how to fix it.
+//! The checks shared by all platforms (config file, running instance, start
+//! at login) live here; hook, injection, conflicting software, OS layout and
+//! permission checks come from `platform::doctor_checks`.
+
+use std::io::IsTerminal;
+
+use crate::config::Config;
+use crate::error::GhostKeysError;
+use crate::ipc;
+use crate::paths;
+use crate::platform;
+
+/// Outcome of a check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// Nothing to do
+    Pass,
+    /// GhostKeys works, but maybe not as expected
+    Warn,
+    /// GhostKeys can't work until this is fixed
+    Fail,
+}
+
+impl Status {
+    /// Fixed-width label shown before each check
+    fn label(self) -> &'static str {
+        match self {
+            Status::Pass => " ok ",
+            Status::Warn => "warn",
+            Status::Fail => "FAIL",
+        }
+    }
+
+    /// ANSI color of the label: green, yellow or red
+    fn color(self) -> &'static str {
+        match self {
+            Status::Pass => "\x1b[32m",
+            Status::Warn => "\x1b[33m",
+            Status::Fail => "\x1b[31m",
+        }
+    }
+}
+
+/// One line of the report
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    /// What was checked
+    pub name: &'static str,
+    /// Outcome
+    pub status: Status,
+    /// What was found
+    pub finding: String,
+    /// What to do about it
+    pub fix: Option<String>,
+}
+
+impl Check {
+    /// A check that found nothing wrong
+    pub fn pass(name: &'static str, finding: impl Into<String>) -> Self {
+        Self { name, status: Status::Pass, finding: finding.into(), fix: None }
+    }
+
+    /// A check that found something worth fixing
+    pub fn warn(name: &'static str, finding: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self { name, status: Status::Warn, finding: finding.into(), fix: Some(fix.into()) }
+    }
+
+    /// A check that found something keeping GhostKeys from working
+    pub fn fail(name: &'static str, finding: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self { name, status: Status::Fail, finding: finding.into(), fix: Some(fix.into()) }
+    }
+
+    /// A failure described by `error`, fixed the way its hint says
+    pub fn from_error(name: &'static str, error: &GhostKeysError) -> Self {
+        Self {
+            name,
+            status: Status::Fail,
+            finding: error.to_string(),
+            fix: error.hint().map(str::to_string),
+        }
+    }
+}
+
+/// Results of all checks, in the order they ran
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    /// Checks that ran
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    /// Whether any check failed
+    pub fn has_failures(&self) -> bool {
+        self.checks.iter().any(|check| check.status == Status::Fail)
+    }
+
+    /// The report as text, with ANSI colors if `color`
+    pub fn render(&self, color: bool) -> String {
+        let mut out = String::new();
+        for check in &self.checks {
+            let label = if color {
+                format!("{}{}\x1b[0m", check.status.color(), check.status.label())
+            } else {
+                check.status.label().to_string()
+            };
+            out.push_str(&format!("[{}] {}: {}\n", label, check.name, check.finding));
+            if let Some(fix) = &check.fix {
+                out.push_str(&format!("       Fix: {}\n", fix));
+            }
+        }
+
+        let count = |status| self.checks.iter().filter(|check| check.status == status).count();
+        let (warnings, failures) = (count(Status::Warn), count(Status::Fail));
+        if warnings == 0 && failures == 0 {
+            out.push_str("\nEverything looks good\n");
+        } else {
+            out.push_str(&format!("\n{} problem(s), {} warning(s)\n", failures, warnings));
+        }
+        out
+    }
+}
+
+/// Run every check for this platform
+pub fn run() -> Report {
+    let mut checks = vec![config_check(), instance_check()];
+    checks.extend(platform::doctor_checks());
+    checks.push(autostart_check());
+    Report { checks }
+}
+
+/// Color the report only on a terminal, and never with `NO_COLOR` set
+pub fn use_color() -> bool {
+    std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+}
+
+/// The config file parses
+fn config_check() -> Check {
+    const NAME: &str = "Configuration";
+    match Config::load() {
+        Ok(_) => match paths::config_file().filter(|path| path.exists()) {
+            Some(path) => Check::pass(NAME, format!("{} is valid", path.display())),
+            None => Check::pass(NAME, "no config file; using the defaults"),
+        },
+        Err(e) => Check::from_error(NAME, &e),
+    }
+}
+
+/// Another GhostKeys answers on the control channel
+fn instance_check() -> Check {
+    const NAME: &str = "Running instance";
+    match ipc::send(ipc::Command::Status) {
+        Ok(mode) => Check::pass(NAME, format!("GhostKeys is running ({})", mode)),
+        Err(_) => Check::warn(NAME, "GhostKeys is not running", "Start GhostKeys"),
+    }
+}
+
+/// GhostKeys starts at login
+fn autostart_check() -> Check {
+    const NAME: &str = "Start at login";
+    if platform::service::is_installed() {
+        Check::pass(NAME, "GhostKeys starts at login")
+    } else {
+        Check::warn(NAME, "GhostKeys doesn't start at login", "Run `ghostkeys service install`")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> Report {
+        Report {
+            checks: vec![
+                Check::pass("Hook", "installed"),
+                Check::warn("Conflicts", "AutoHotkey is running", "Exit it"),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_render_plain() {
+        assert_eq!(
+            report().render(false),
+            "[ ok ] Hook: installed\n\
+             [warn] Conflicts: AutoHotkey is running\n       Fix: Exit it\n\
+             \n0 problem(s), 1 warning(s)\n"
+        );
+        let clean = Report { checks: vec![Check::pass("Hook", "installed")] };
+        assert!(clean.render(false).ends_with("\nEverything looks good\n"));
+    }
+
+    #[test]
+    fn test_render_colors_labels_only() {
+        let rendered = report().render(true);
+        assert!(rendered.starts_with("[\x1b[32m ok \x1b[0m] Hook: installed\n"));
+        assert!(rendered.contains("[\x1b[33mwarn\x1b[0m] Conflicts"));
+        assert!(!report().render(false).contains('\x1b'));
+    }
+
+    #[test]
+    fn test_failure_from_error_uses_its_hint() {
+        let error = GhostKeysError::UinputUnavailable { missing: true };
+        let check = Check::from_error("uinput", &error);
+        assert_eq!(check.status, Status::Fail);
+        assert_eq!(check.finding, error.to_string());
+        assert_eq!(check.fix.as_deref(), error.hint());
+
+        let mut report = report();
+        assert!(!report.has_failures());
+        report.checks.push(check);
+        assert!(report.has_failures());
+    }
+}
@@ -1,7 +1,49 @@
 //! Error types for GhostKeys
 
+use std::fmt;
+use std::path::PathBuf;
+
 use thiserror::Error;
 
+/// Operating system GhostKeys is running on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    /// Windows (the production target)
+    Windows,
+    /// Linux (development only)
+    Linux,
+    /// macOS
+    MacOs,
+    /// Anything else
+    Other,
+}
+
+impl Platform {
+    /// Platform this binary was built for
+    pub fn current() -> Self {
+        if cfg!(target_os = "windows") {
+            Platform::Windows
+        } else if cfg!(target_os = "linux") {
+            Platform::Linux
+        } else if cfg!(target_os = "macos") {
+            Platform::MacOs
+        } else {
+            Platform::Other
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Platform::Windows => write!(f, "Windows"),
+            Platform::Linux => write!(f, "Linux"),
+            Platform::MacOs => write!(f, "macOS"),
+            Platform::Other => write!(f, "this platform"),
+        }
+    }
+}
+
 /// Main error type for GhostKeys operations
 #[derive(Debug, Error)]
 pub enum GhostKeysError {
@@ -13,10 +55,18 @@ pub enum GhostKeysError {
     #[error("Failed to release keyboard hook: {0}")]
     HookReleaseError(String),
 
+    /// The OS removed the keyboard hook (e.g., after it responded too slowly)
+    #[error("Keyboard hook was removed by the system")]
+    HookLost,
+
     /// System tray icon error
     #[error("Tray icon error: {0}")]
     TrayError(String),
 
+    /// The character palette window couldn't be opened
+    #[error("Can't open the character palette: {0}")]
+    PaletteError(String),
+
     /// Shared state lock was poisoned
     #[error("State lock poisoned")]
     StateLockPoisoned,
@@ -24,7 +74,188 @@ pub enum GhostKeysError {
     /// Key injection failed
     #[error("Failed to inject key: {0}")]
     KeyInjectionError(String),
+
+    /// Windows User Interface Privilege Isolation blocked injection into a
+    /// window of a more privileged (elevated) process
+    #[error("Windows blocked typing into an elevated window (os error {code})")]
+    InjectionBlockedByUipi {
+        /// Last OS error after the failed injection
+        code: i32,
+    },
+
+    /// More characters were injected in one second than allowed, so GhostKeys
+    /// paused itself
+    #[error("Stopped typing after more than {limit} characters in one second; GhostKeys is paused")]
+    InjectionFlood {
+        /// Characters allowed per second
+        limit: u32,
+    },
+
+    /// The OS refused an operation for lack of privileges
+    #[error("Permission denied: {message} (os error {code})")]
+    PermissionDenied {
+        /// What was being attempted
+        message: String,
+        /// OS error code
+        code: i32,
+        /// What the user can do about it on this platform
+        platform_hint: &'static str,
+    },
+
+    /// No keyboard under /dev/input can be read (Linux)
+    #[error("Can't read keyboard devices in /dev/input")]
+    InputDevicesUnreadable {
+        /// The user is in the `input` group, but this session doesn't have it yet
+        in_input_group: bool,
+    },
+
+    /// /dev/uinput, needed to type characters, can't be used (Linux)
+    #[error("Can't open /dev/uinput: {}", uinput_problem(.missing))]
+    UinputUnavailable {
+        /// The device node doesn't exist, as opposed to being inaccessible
+        missing: bool,
+    },
+
+    /// Not running inside a graphical session GhostKeys supports (Linux)
+    #[error("Unsupported session type: {0} (expected X11 or Wayland)")]
+    UnsupportedSession(String),
+
+    /// The operation isn't available on this platform
+    #[error("Not supported on {0}")]
+    NotSupported(Platform),
+
+    /// Installing, removing or querying the background service failed
+    #[error("Service management failed: {0}")]
+    ServiceError(String),
+
+    /// `ghostkeys ctl` couldn't talk to the running instance
+    #[error("Can't reach the running GhostKeys: {0}")]
+    IpcError(String),
+
+    /// The OS keyboard layout couldn't be changed
+    #[error("Failed to switch the OS keyboard layout: {0}")]
+    OsLayoutError(String),
+
+    /// Keyboard layout file could not be parsed
+    #[error("Failed to parse layout file: {0}")]
+    LayoutParseError(String),
+
+    /// Configuration file could not be read or parsed
+    #[error("Invalid configuration{}: {message}", config_location(.path, .line))]
+    ConfigError {
+        /// File the configuration came from, if any
+        path: Option<PathBuf>,
+        /// 1-based line of the problem, if known
+        line: Option<usize>,
+        /// What is wrong
+        message: String,
+    },
+}
+
+impl GhostKeysError {
+    /// OS error code behind the failure, if any
+    pub fn os_code(&self) -> Option<i32> {
+        match self {
+            GhostKeysError::InjectionBlockedByUipi { code } | GhostKeysError::PermissionDenied { code, .. } => {
+                Some(*code)
+            }
+            _ => None,
+        }
+    }
+
+    /// What the user can do about the failure, if anything
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            GhostKeysError::HookLost => Some("Pause and resume GhostKeys from the tray, or restart it"),
+            GhostKeysError::StateLockPoisoned => Some("Restart GhostKeys"),
+            GhostKeysError::InjectionBlockedByUipi { .. } => Some(
+                "Run GhostKeys as administrator, or set passthrough_elevated = true under [injection]",
+            ),
+            GhostKeysError::InjectionFlood { .. } => Some(
+                "Check your macros, then resume from the tray; raise max_chars_per_second under \
+                 [injection] if that much typing was intended",
+            ),
+            GhostKeysError::PermissionDenied { platform_hint, .. } => Some(*platform_hint),
+            GhostKeysError::InputDevicesUnreadable { in_input_group: false } => {
+                Some("Run `sudo usermod -aG input $USER`, then log out and back in")
+            }
+            GhostKeysError::InputDevicesUnreadable { in_input_group: true } => {
+                Some("Log out and back in so your new `input` group membership takes effect")
+            }
+            GhostKeysError::UinputUnavailable { missing: true } => {
+                Some("Load the module with `sudo modprobe uinput` (add `uinput` to /etc/modules-load.d/ to keep it)")
+            }
+            GhostKeysError::UinputUnavailable { missing: false } => Some(
+                "Add the udev rule `KERNEL==\"uinput\", GROUP=\"input\", MODE=\"0660\"` to \
+                 /etc/udev/rules.d/99-ghostkeys.rules, then run \
+                 `sudo udevadm control --reload && sudo udevadm trigger`",
+            ),
+            GhostKeysError::UnsupportedSession(_) => {
+                Some("Start GhostKeys from an X11 or Wayland desktop session")
+            }
+            GhostKeysError::NotSupported(_) => Some("GhostKeys currently remaps keys on Windows only"),
+            GhostKeysError::ServiceError(_) => {
+                Some("Check `systemctl --user status ghostkeys` and `journalctl --user -u ghostkeys`")
+            }
+            GhostKeysError::IpcError(_) => {
+                Some("Start GhostKeys first; `ghostkeys ctl` controls a running instance")
+            }
+            GhostKeysError::ConfigError { path: Some(_), .. } => {
+                Some("Fix the file, or delete it to go back to the defaults")
+            }
+            _ => None,
+        }
+    }
+}
+
+/// " in <path> at line <n>", or whichever part is known
+fn config_location(path: &Option<PathBuf>, line: &Option<usize>) -> String {
+    let mut location = String::new();
+    if let Some(path) = path {
+        location.push_str(&format!(" in {}", path.display()));
+    }
+    if let Some(line) = line {
+        location.push_str(&format!(" at line {}", line));
+    }
+    location
+}
+
+/// Why /dev/uinput can't be opened
+fn uinput_problem(missing: &bool) -> &'static str {
+    if *missing {
+        "it doesn't exist"
+    } else {
+        "permission denied"
+    }
 }
 
 /// Result type alias for GhostKeys operations
 pub type Result<T> = std::result::Result<T, GhostKeysError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_location() {
+        let path = Some(PathBuf::from("config.toml"));
+        assert_eq!(config_location(&path, &Some(3)), " in config.toml at line 3");
+        assert_eq!(config_location(&None, &Some(3)), " at line 3");
+        assert_eq!(config_location(&None, &None), "");
+    }
+
+    #[test]
+    fn test_os_code_and_hint() {
+        let denied = GhostKeysError::PermissionDenied {
+            message: "SetWindowsHookExW".to_string(),
+            code: 5,
+            platform_hint: "Run as administrator",
+        };
+        assert_eq!(denied.os_code(), Some(5));
+        assert_eq!(denied.hint(), Some("Run as administrator"));
+
+        let parse_error = GhostKeysError::LayoutParseError("bad".to_string());
+        assert_eq!(parse_error.os_code(), None);
+        assert_eq!(parse_error.hint(), None);
+    }
+}
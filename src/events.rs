@@ -0,0 +1,136 @@
+//! Internal event bus for the keystroke pipeline
+//!
+//! Platform hooks publish what happens to each key press (it reached the
+//! mapper, the mapper decided an action, characters were injected) and
+//! `SharedState` publishes mode changes. Features that cut across the
+//! pipeline, such as statistics, subscribe here instead of being called from
+//! every hook callback.
+//!
+//! Unlike `StateChange` subscribers, which get a channel, event subscribers
+//! are called on the publishing thread: a hook publishes several events per
+//! keystroke, and queues nobody drains would grow without bound. Subscribers
+//! must return quickly and must not publish events themselves.
+
+use std::fmt;
+
+use crate::interceptor::{KeyAction, KeyEvent};
+use crate::state::OperationMode;
+
+/// Something that happened in the keystroke pipeline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// A key press reached the mapper
+    KeyPressed(KeyEvent),
+    /// What will be done about a key press
+    ActionDecided {
+        /// The key press
+        event: KeyEvent,
+        /// The mapper's action, after autocorrect
+        action: KeyAction,
+    },
+    /// Characters were typed in place of a key press
+    Injected(Vec<char>),
+    /// GhostKeys was paused or resumed
+    ModeChanged(OperationMode),
+}
+
+/// A function called with every published event
+type Subscriber = Box<dyn FnMut(&Event) + Send>;
+
+/// Names a subscriber, to take it off the bus again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubscriptionId(u64);
+
+/// Subscribers, called in the order they subscribed
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: Vec<(SubscriptionId, Subscriber)>,
+    next_id: u64,
+}
+
+impl EventBus {
+    /// Create a bus without subscribers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call `subscriber` with every event published from now on, until
+    /// `unsubscribe` is called with the ID returned
+    pub fn subscribe(
+        &mut self,
+        subscriber: impl FnMut(&Event) + Send + 'static,
+    ) -> SubscriptionId {
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
+        self.subscribers.push((id, Box::new(subscriber)));
+        id
+    }
+
+    /// Stop calling the subscriber `id` names; false if it was already gone
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        let before = self.subscribers.len();
+        self.subscribers.retain(|(subscribed, _)| *subscribed != id);
+        self.subscribers.len() != before
+    }
+
+    /// Deliver `event` to every subscriber
+    pub fn publish(&mut self, event: &Event) {
+        for (_, subscriber) in &mut self.subscribers {
+            subscriber(event);
+        }
+    }
+}
+
+impl fmt::Debug for EventBus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventBus").field("subscribers", &self.subscribers.len()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_subscribers_see_events_in_order() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut bus = EventBus::new();
+        for name in ["first", "second"] {
+            let seen = Arc::clone(&seen);
+            bus.subscribe(move |event| seen.lock().unwrap().push((name, event.clone())));
+        }
+
+        bus.publish(&Event::ModeChanged(OperationMode::Passthrough));
+        bus.publish(&Event::Injected(vec!['ç']));
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(
+            *seen,
+            [
+                ("first", Event::ModeChanged(OperationMode::Passthrough)),
+                ("second", Event::ModeChanged(OperationMode::Passthrough)),
+                ("first", Event::Injected(vec!['ç'])),
+                ("second", Event::Injected(vec!['ç'])),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unsubscribed_see_nothing_more() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut bus = EventBus::new();
+        let ids: Vec<SubscriptionId> = ["first", "second"]
+            .into_iter()
+            .map(|name| {
+                let seen = Arc::clone(&seen);
+                bus.subscribe(move |event| seen.lock().unwrap().push((name, event.clone())))
+            })
+            .collect();
+
+        assert!(bus.unsubscribe(ids[0]));
+        assert!(!bus.unsubscribe(ids[0]));
+        bus.publish(&Event::Injected(vec!['ç']));
+        assert_eq!(*seen.lock().unwrap(), [("second", Event::Injected(vec!['ç']))]);
+    }
+}
@@ -0,0 +1,119 @@
+//! Flags shared by the UI and hook threads, and their memory orderings
+//!
+//! The hook reads the pause and reverse flags on every key press, so they are
+//! plain atomics rather than part of the locked `AppState`. Nothing else is
+//! published through them: the hook only needs each flag's own latest value,
+//! and the state it may read after checking them (config, layout) is behind
+//! the state lock, which orders itself. Relaxed operations are enough, and
+//! on x86 and ARM a relaxed load is an ordinary load.
+//!
+//! The exit signal is different: whoever sees it goes on to shut down and
+//! must see everything done before it was raised (a `ctl quit` reply, saved
+//! state), so raising it releases and checking it acquires.
+//!
+//! Built with `RUSTFLAGS="--cfg ghostkeys_loom"`, the flags use loom's
+//! atomics, and `tests/loom.rs` checks these contracts under every
+//! interleaving.
+
+#[cfg(ghostkeys_loom)]
+use loom::sync::atomic::{AtomicBool, Ordering};
+#[cfg(not(ghostkeys_loom))]
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether keys pass through unmodified
+///
+/// Writers hold the state lock while flipping it so subscribers see changes
+/// in order; the hook reads it without the lock.
+#[derive(Debug)]
+pub struct PauseFlag(AtomicBool);
+
+impl PauseFlag {
+    /// A flag that isn't set
+    pub fn new() -> Self {
+        Self(AtomicBool::new(false))
+    }
+
+    /// Whether the flag is set (the hook's per-key check)
+    pub fn is_set(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Set or clear the flag, returning its previous value
+    pub fn swap(&self, paused: bool) -> bool {
+        self.0.swap(paused, Ordering::Relaxed)
+    }
+
+    /// Flip the flag, returning its previous value
+    ///
+    /// Concurrent toggles are never lost: each read-modify-write sees the one
+    /// before it.
+    pub fn toggle(&self) -> bool {
+        self.0.fetch_xor(true, Ordering::Relaxed)
+    }
+}
+
+impl Default for PauseFlag {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether the mapper translates in reverse (ABNT2 keyboard, US output)
+///
+/// Like the pause flag, written under the state lock and read by the hook
+/// without it.
+#[derive(Debug)]
+pub struct ReverseFlag(AtomicBool);
+
+impl ReverseFlag {
+    /// A flag that isn't set
+    pub fn new() -> Self {
+        Self(AtomicBool::new(false))
+    }
+
+    /// Whether the flag is set (the hook's per-key check)
+    pub fn is_set(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Set or clear the flag, returning its previous value
+    pub fn swap(&self, reverse: bool) -> bool {
+        self.0.swap(reverse, Ordering::Relaxed)
+    }
+}
+
+impl Default for ReverseFlag {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// One-way signal to shut down
+///
+/// Everything done before `raise` is visible to a thread once `is_raised`
+/// returns true.
+#[derive(Debug)]
+pub struct ExitSignal(AtomicBool);
+
+impl ExitSignal {
+    /// A signal that hasn't been raised
+    pub fn new() -> Self {
+        Self(AtomicBool::new(false))
+    }
+
+    /// Ask every thread watching the signal to shut down
+    pub fn raise(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    /// Whether shutdown was requested
+    pub fn is_raised(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+impl Default for ExitSignal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
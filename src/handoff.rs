@@ -0,0 +1,54 @@
+//! Values the UI thread replaces while the hook keeps using them
+//!
+//! The hook locks the value for each key press, and `replace` swaps in a new
+//! one between key presses. The old value is handed back rather than dropped
+//! under the lock, so the caller frees it after the swap, never while the
+//! hook is using it, and the hook always sees one value or the other whole.
+//! Anything done before a `replace` (pausing, for instance) is visible to a
+//! key press that gets the new value.
+//!
+//! Built with `RUSTFLAGS="--cfg ghostkeys_loom"`, the lock is loom's, and
+//! `tests/loom.rs` checks these contracts under every interleaving.
+
+use std::fmt;
+
+#[cfg(ghostkeys_loom)]
+use loom::sync::Mutex;
+#[cfg(not(ghostkeys_loom))]
+use std::sync::Mutex;
+
+use crate::error::{GhostKeysError, Result};
+
+/// A value shared with the hook, replaced as a whole
+pub struct Handoff<T> {
+    value: Mutex<T>,
+}
+
+impl<T> Handoff<T> {
+    /// Share `value`
+    pub fn new(value: T) -> Self {
+        Self { value: Mutex::new(value) }
+    }
+
+    /// Use `value` from the next key press on, returning the one it replaces
+    pub fn replace(&self, value: T) -> Result<T> {
+        self.value
+            .lock()
+            .map(|mut current| std::mem::replace(&mut *current, value))
+            .map_err(|_| GhostKeysError::StateLockPoisoned)
+    }
+
+    /// Use the current value (on every key press)
+    pub fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> Result<R> {
+        self.value
+            .lock()
+            .map(|mut current| f(&mut current))
+            .map_err(|_| GhostKeysError::StateLockPoisoned)
+    }
+}
+
+impl<T> fmt::Debug for Handoff<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Handoff").finish_non_exhaustive()
+    }
+}
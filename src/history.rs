@@ -0,0 +1,228 @@
+//! History of mode and layout changes, with when and why they happened
+//!
+//! When GhostKeys "randomly stops working", the answer is usually a pause
+//! nobody noticed: the hotkey pressed by accident, an `[auto_pause]` rule, a
+//! keyboard rule, the injection flood limit. `SharedState` records the last
+//! `CAPACITY` changes and their causes; `ghostkeys ctl history` and "Mode
+//! history" in the tray show them. Diagnostics, so the text stays in English.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::state::OperationMode;
+
+/// Changes kept; older ones are dropped
+pub const CAPACITY: usize = 100;
+
+/// Why a change happened
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cause {
+    /// From the tray or an embedding application
+    User,
+    /// `ghostkeys ctl`
+    Command,
+    /// The pause hotkey
+    Hotkey,
+    /// A rule (OS layout, keyboard rules), with the explanation shown to the user
+    Rule(String),
+    /// `start_paused` under `[startup]`
+    Startup,
+    /// The self-test pausing remapping while it types
+    SelfTest,
+    /// Too many characters injected at once
+    Flood,
+    /// `resume_after_minutes` under `[auto_pause]`, after a rule stopped
+    /// applying without resuming
+    Watchdog,
+    /// Paused when GhostKeys last stopped (see `session`)
+    Restored,
+}
+
+impl fmt::Display for Cause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cause::User => write!(f, "user"),
+            Cause::Command => write!(f, "ghostkeys ctl"),
+            Cause::Hotkey => write!(f, "hotkey"),
+            Cause::Rule(reason) => write!(f, "rule: {}", reason),
+            Cause::Startup => write!(f, "start_paused"),
+            Cause::SelfTest => write!(f, "self-test"),
+            Cause::Flood => write!(f, "injection flood limit"),
+            Cause::Watchdog => write!(f, "resume_after_minutes"),
+            Cause::Restored => write!(f, "last session"),
+        }
+    }
+}
+
+/// What changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// Remapping was paused or resumed
+    Mode(OperationMode),
+    /// A custom layout (by name) replaced the built-in ABNT2 tables, or
+    /// `None` restored them
+    Layout(Option<String>),
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Mode(OperationMode::Active) => write!(f, "resumed"),
+            Change::Mode(OperationMode::Passthrough) => write!(f, "paused"),
+            Change::Layout(Some(name)) => write!(f, "layout {}", name),
+            Change::Layout(None) => write!(f, "built-in layout"),
+        }
+    }
+}
+
+/// One recorded change
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// When it happened
+    pub at: SystemTime,
+    /// What changed
+    pub change: Change,
+    /// Why
+    pub cause: Cause,
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}  {} ({})", utc(self.at), self.change, self.cause)
+    }
+}
+
+/// The most recent changes, oldest first
+#[derive(Debug, Default)]
+pub struct History {
+    entries: VecDeque<Entry>,
+    /// Kept apart from `entries`, which may have dropped it
+    last_mode_cause: Option<Cause>,
+}
+
+impl History {
+    /// Create an empty history
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a change that just happened
+    pub fn record(&mut self, change: Change, cause: Cause) {
+        self.push(Entry { at: SystemTime::now(), change, cause });
+    }
+
+    /// Why the mode last changed, if it changed since startup
+    pub fn last_mode_cause(&self) -> Option<&Cause> {
+        self.last_mode_cause.as_ref()
+    }
+
+    /// Copy of the recorded changes, oldest first
+    pub fn entries(&self) -> Vec<Entry> {
+        self.entries.iter().cloned().collect()
+    }
+
+    fn push(&mut self, entry: Entry) {
+        if let Change::Mode(_) = entry.change {
+            self.last_mode_cause = Some(entry.cause.clone());
+        }
+        if self.entries.len() == CAPACITY {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+}
+
+/// One change per line, oldest first
+pub fn render(entries: &[Entry]) -> String {
+    if entries.is_empty() {
+        return "No mode or layout changes since GhostKeys started".to_string();
+    }
+    entries.iter().map(|entry| format!("{}\n", entry)).collect()
+}
+
+/// `at` as "YYYY-MM-DD hh:mm:ss UTC"
+fn utc(at: SystemTime) -> String {
+    let seconds = at.duration_since(UNIX_EPOCH).map_or(0, |t| t.as_secs());
+    let (days, time) = (seconds / 86_400, seconds % 86_400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn at(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn test_utc() {
+        assert_eq!(utc(at(0)), "1970-01-01 00:00:00 UTC");
+        assert_eq!(utc(at(951_782_400)), "2000-02-29 00:00:00 UTC");
+        assert_eq!(utc(at(1_792_145_045)), "2026-10-16 10:04:05 UTC");
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let mut history = History::new();
+        for i in 0..CAPACITY as u64 + 5 {
+            let change = Change::Mode(OperationMode::Passthrough);
+            history.push(Entry { at: at(i), change, cause: Cause::Hotkey });
+        }
+        let entries = history.entries();
+        assert_eq!(entries.len(), CAPACITY);
+        assert_eq!(entries[0].at, at(5));
+    }
+
+    #[test]
+    fn test_last_mode_cause_outlives_its_entry() {
+        let mut history = History::new();
+        assert_eq!(history.last_mode_cause(), None);
+        history.record(Change::Mode(OperationMode::Passthrough), Cause::Rule("r".to_string()));
+        for _ in 0..CAPACITY {
+            history.record(Change::Layout(None), Cause::User);
+        }
+        assert_eq!(history.last_mode_cause(), Some(&Cause::Rule("r".to_string())));
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(render(&[]), "No mode or layout changes since GhostKeys started");
+        let entries = [
+            Entry {
+                at: at(60),
+                change: Change::Mode(OperationMode::Passthrough),
+                cause: Cause::Rule("Paused for Keychron K2".to_string()),
+            },
+            Entry { at: at(61), change: Change::Layout(None), cause: Cause::User },
+        ];
+        assert_eq!(
+            render(&entries),
+            "1970-01-01 00:01:00 UTC  paused (rule: Paused for Keychron K2)\n\
+             1970-01-01 00:01:01 UTC  built-in layout (user)\n"
+        );
+    }
+}
@@ -0,0 +1,692 @@
+//! What the keyboard hook remembers between key presses
+//!
+//! A low-level hook callback is a bare function the OS calls on the hook
+//! thread, so everything it keeps (the mapper, the keys held down,
+//! autocorrect's word, the macro being recorded, the config it started with)
+//! lives on that thread. `HookContext` owns all of it: the hook thread
+//! creates one when it installs the hook, swaps in a new mapper within it,
+//! and drops it with the hook, so no piece survives a restart or misses one.
+//! Nothing in it calls the OS; the callback does the lookups and hands the
+//! results in, which lets tests drive it the way the callback does.
+
+use std::cell::RefCell;
+
+use crate::autocorrect::{Autocorrect, Suggestion};
+use crate::config::{
+    ComposeTrigger, FeedbackConfig, InjectionConfig, KeyCodes, LearningConfig, NavTrigger,
+    TerminalsConfig,
+};
+use crate::error::Result;
+use crate::hotkeys::{Conflict, Hotkeys};
+use crate::interceptor::{
+    InjectionLimiter, KeyAction, KeyEvent, KeyUpFilter, Modifiers, Replacement, ReplacementLog,
+};
+use crate::learning::{KeyTrail, Lesson};
+use crate::macros::MacroRecorder;
+use crate::mapper::{Mapper, VirtualKey};
+use crate::nav::{NavHold, NavLayer};
+use crate::numpad::NumpadLayer;
+use crate::pipeline::KeyInput;
+use crate::remap::{Remap, RemapKey};
+use crate::state::SharedState;
+
+/// The config as it was when the hook was installed
+///
+/// Settings that can change while running (macro slots, the keyboard
+/// auto-pause rules) are read from the shared state on each key press
+/// instead, and custom hotkeys and the OS layout rule are re-read when the
+/// config changes.
+#[derive(Debug, Clone, Default)]
+pub struct HookSettings {
+    /// What keys are told apart by
+    pub key_codes: KeyCodes,
+    /// Key that starts a compose sequence
+    pub compose: Option<ComposeTrigger>,
+    /// Key held for the nav layer, when `[nav]` turns it on
+    pub nav_trigger: Option<NavTrigger>,
+    /// Whether Ctrl+Alt+Space pauses and resumes
+    pub pause_hotkey: bool,
+    /// Whether Ctrl+Alt+. opens the character palette
+    pub palette_hotkey: bool,
+    /// Whether Ctrl+Alt+Z takes back the last replacement
+    pub undo_hotkey: bool,
+    /// Whether Ctrl+Alt+P switches to the next saved profile
+    pub profile_hotkey: bool,
+    /// Character injection settings
+    pub injection: InjectionConfig,
+    /// Terminal settings
+    pub terminals: TerminalsConfig,
+    /// Dead key sounds
+    pub feedback: FeedbackConfig,
+    /// Learning mode settings
+    pub learning: LearningConfig,
+}
+
+/// Key presses the hook has handled since it was installed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HookCounters {
+    /// Key-downs, auto-repeats included
+    pub key_downs: u64,
+    /// Key-downs kept from applications (remapped, suppressed or a hotkey)
+    pub blocked: u64,
+    /// Key-ups kept from applications along with their key-downs
+    pub blocked_key_ups: u64,
+    /// Injections that failed
+    pub injection_failures: u64,
+}
+
+/// What the hook last saw of the foreground window, to act on changes only
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ForegroundCache {
+    /// Window handle and whether it runs elevated
+    pub elevated: Option<(isize, bool)>,
+    /// Window handle and the executable name of its process
+    pub app: Option<(isize, Option<String>)>,
+    /// Keyboard layout of the foreground thread
+    pub layout: Option<isize>,
+    /// Whether that layout paused GhostKeys
+    pub paused_by_layout: bool,
+    /// Whether its input method was composing
+    pub ime_open: bool,
+}
+
+/// A key the mapper doesn't handle, as far as the output is concerned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnmappedKey {
+    /// Deletes the last character
+    Backspace,
+    /// Types a line break
+    Enter,
+    /// Types a tab
+    Tab,
+    /// Moves away from the word (arrows, Home, ...) or types nothing
+    Other,
+}
+
+/// Everything the hook thread keeps between key presses
+pub struct HookContext {
+    state: SharedState,
+    settings: HookSettings,
+    mapper: Mapper,
+    /// Keys `[remap]` puts in place of others
+    remap: Remap,
+    /// Nav layer, when `[nav]` turns it on
+    nav: Option<NavLayer>,
+    /// Whether the nav layer's trigger is held
+    nav_hold: NavHold,
+    /// Virtual numpad, when `[numpad]` turns it on
+    numpad: Option<NumpadLayer>,
+    autocorrect: Option<Autocorrect>,
+    recorder: Option<MacroRecorder>,
+    key_up_filter: KeyUpFilter,
+    replacements: ReplacementLog,
+    /// Chords from `[hotkeys.custom]` and their actions
+    hotkeys: Hotkeys,
+    /// Whether `when_os_layout_is_abnt2` under `[auto_pause]` is on
+    follows_os_layout: bool,
+    /// Whether the next key goes through as it is (`literal_next_key`)
+    literal_next: bool,
+    /// Keys behind the next lesson, in learning mode
+    trail: Option<KeyTrail>,
+    /// Lesson waiting to be shown
+    lesson: Option<Lesson>,
+    /// Borrowed by the injector for as long as a replacement is injected
+    limiter: RefCell<InjectionLimiter>,
+    counters: HookCounters,
+    foreground: ForegroundCache,
+}
+
+impl HookContext {
+    /// Context for a hook about to be installed with `state`'s config
+    pub fn new(state: SharedState, mapper: Mapper) -> Result<Self> {
+        let config = state.get_config()?;
+        Ok(Self {
+            settings: HookSettings {
+                key_codes: config.key_codes,
+                compose: config.compose.trigger,
+                nav_trigger: config.nav.enabled.then_some(config.nav.trigger),
+                pause_hotkey: config.hotkeys.toggle_pause,
+                palette_hotkey: config.hotkeys.palette,
+                undo_hotkey: config.hotkeys.undo,
+                profile_hotkey: config.hotkeys.cycle_profile,
+                injection: config.injection.clone(),
+                terminals: config.terminals.clone(),
+                feedback: config.feedback.clone(),
+                learning: config.learning.clone(),
+            },
+            mapper,
+            remap: Remap::from_config(&config.remap),
+            nav: NavLayer::from_config(&config.nav),
+            nav_hold: NavHold::default(),
+            numpad: NumpadLayer::from_config(&config.numpad),
+            autocorrect: (config.autocorrect.enabled || config.autocorrect.suggestions).then(|| {
+                Autocorrect::with_options(config.autocorrect.enabled, config.autocorrect.suggestions)
+            }),
+            recorder: config.macros.enabled.then(MacroRecorder::new),
+            key_up_filter: KeyUpFilter::new(),
+            replacements: ReplacementLog::new(),
+            hotkeys: Hotkeys::from_config(&config).0,
+            follows_os_layout: config.auto_pause.when_os_layout_is_abnt2,
+            literal_next: false,
+            trail: config.learning.enabled.then(KeyTrail::new),
+            lesson: None,
+            limiter: RefCell::new(InjectionLimiter::new(config.injection.max_chars_per_second)),
+            counters: HookCounters::default(),
+            foreground: ForegroundCache::default(),
+            state,
+        })
+    }
+
+    /// State shared with the UI
+    pub fn state(&self) -> &SharedState {
+        &self.state
+    }
+
+    /// Config snapshot taken when the hook was installed
+    pub fn settings(&self) -> &HookSettings {
+        &self.settings
+    }
+
+    /// Key presses handled so far
+    pub fn counters(&self) -> HookCounters {
+        self.counters
+    }
+
+    /// The mapper in use
+    pub fn mapper(&self) -> &Mapper {
+        &self.mapper
+    }
+
+    /// Use `mapper` from the next key press on
+    ///
+    /// Held keys are kept, so key-ups of keys pressed before the swap still
+    /// follow their key-downs.
+    pub fn set_mapper(&mut self, mapper: Mapper) {
+        self.mapper = mapper;
+    }
+
+    /// Custom hotkeys in effect
+    pub fn hotkeys(&self) -> &Hotkeys {
+        &self.hotkeys
+    }
+
+    /// Rebuild the custom hotkeys and re-read the OS layout rule from the
+    /// config in the shared state, which may have changed since the hook was
+    /// installed; returns the chords left out
+    pub fn reload_hotkeys(&mut self) -> Result<Vec<Conflict>> {
+        let config = self.state.get_config()?;
+        let (hotkeys, conflicts) = Hotkeys::from_config(&config);
+        self.hotkeys = hotkeys;
+        self.follows_os_layout = config.auto_pause.when_os_layout_is_abnt2;
+        Ok(conflicts)
+    }
+
+    /// Whether to pause while the OS uses its own ABNT2 layout, as of the
+    /// last reload
+    pub fn follows_os_layout(&self) -> bool {
+        self.follows_os_layout
+    }
+
+    /// Let the next key through unmapped, dropping a pending accent
+    pub fn arm_literal_next(&mut self) {
+        self.reset_mapper();
+        self.literal_next = true;
+    }
+
+    /// Whether this key goes through unmapped; only the first key after
+    /// `arm_literal_next` does
+    pub fn take_literal_next(&mut self) -> bool {
+        std::mem::take(&mut self.literal_next)
+    }
+
+    /// Whether a key-down for `code` is an auto-repeat of a held key
+    pub fn is_repeat(&self, code: u32) -> bool {
+        self.key_up_filter.is_held(code)
+    }
+
+    /// Record what happened to a key-down
+    pub fn key_down(&mut self, code: u32, blocked: bool) {
+        self.key_up_filter.key_down(code, blocked);
+        self.counters.key_downs += 1;
+        if blocked {
+            self.counters.blocked += 1;
+        }
+    }
+
+    /// Key pressed in place of `key`, if `[remap]` remaps it
+    pub fn remap_target(&self, key: RemapKey) -> Option<VirtualKey> {
+        self.remap.target(key)
+    }
+
+    /// The nav layer's trigger went down; returns true if it's held back
+    /// (see `NavHold::trigger_down`)
+    pub fn nav_trigger_down(&mut self, repeat: bool, chord: bool) -> bool {
+        self.nav_hold.trigger_down(repeat, chord)
+    }
+
+    /// The nav layer's trigger went up; returns true if it was tapped on its
+    /// own and must do its own job now
+    pub fn nav_trigger_up(&mut self) -> bool {
+        self.nav_hold.trigger_up()
+    }
+
+    /// Another key went down, which makes a held nav trigger a held one
+    /// rather than a tap
+    pub fn nav_key_down(&mut self) {
+        self.nav_hold.key_down();
+    }
+
+    /// Whether the nav layer's trigger is held
+    pub fn nav_held(&self) -> bool {
+        self.nav_hold.is_held()
+    }
+
+    /// Record a key-up; returns true if it must be blocked like its key-downs
+    pub fn key_up(&mut self, code: u32) -> bool {
+        let blocked = self.key_up_filter.key_up(code);
+        if blocked {
+            self.counters.blocked_key_ups += 1;
+        }
+        blocked
+    }
+
+    /// Map a key press and let autocorrect, the macro recorder and the undo
+    /// log see what ends up on screen
+    ///
+    /// The nav layer and the virtual numpad go first, like their stages in
+    /// `pipeline`: moving the cursor or typing a digit drops a pending accent.
+    pub fn map(&mut self, input: KeyInput) -> KeyAction {
+        if let Some(direction) = input.direction {
+            self.mapper.set_direction(direction);
+        }
+        self.mapper.set_dead_keys(input.dead_keys);
+        let layer = match self.nav.and_then(|nav| nav.key(&input)) {
+            Some(key) => Some(KeyAction::InjectKey(key, Modifiers::default())),
+            None => self.numpad.and_then(|numpad| numpad.key(&input)).map(KeyAction::Replace),
+        };
+        let action = match layer {
+            Some(action) => {
+                self.mapper.reset();
+                action
+            }
+            None => self.mapper.process_key(input.event),
+        };
+        if let Some(trail) = &mut self.trail {
+            self.lesson = if input.shortcut {
+                trail.reset();
+                None
+            } else {
+                trail.observe(&input.event, &action)
+            };
+        }
+
+        let action = match &mut self.autocorrect {
+            Some(autocorrect) if input.shortcut => {
+                autocorrect.reset();
+                action
+            }
+            Some(autocorrect) => autocorrect.process(action, input.typed),
+            None => action,
+        };
+        let uncorrected = self.autocorrect.as_ref().and_then(Autocorrect::uncorrected);
+        match (&action, uncorrected) {
+            (KeyAction::Rewrite { chars, .. }, Some(original)) => {
+                self.replacements.push(Replacement {
+                    produced: chars.clone(),
+                    original: original.to_vec(),
+                });
+            }
+            _ => self.replacements.record(&action, input.typed),
+        }
+        if let Some(recorder) = &mut self.recorder {
+            if !input.shortcut {
+                recorder.record(&action, input.typed);
+            }
+        }
+        action
+    }
+
+    /// Map a key press that nothing else may see (latency probes)
+    pub fn map_unobserved(&mut self, event: KeyEvent) -> KeyAction {
+        self.mapper.process_key(event)
+    }
+
+    /// Keep autocorrect's word and the macro recording in sync with a key
+    /// the mapper never saw (or only saw to end a pending accent)
+    pub fn unmapped_key(&mut self, key: UnmappedKey) {
+        self.replacements.clear();
+        if let Some(trail) = &mut self.trail {
+            trail.reset();
+        }
+        if let Some(recorder) = &mut self.recorder {
+            match key {
+                UnmappedKey::Backspace => recorder.backspace(),
+                UnmappedKey::Enter => recorder.push('\n'),
+                UnmappedKey::Tab => recorder.push('\t'),
+                UnmappedKey::Other => {}
+            }
+        }
+        if let Some(autocorrect) = &mut self.autocorrect {
+            if key == UnmappedKey::Backspace {
+                autocorrect.backspace();
+            } else {
+                // Enter, arrows, Home, ... end or move away from the word
+                autocorrect.reset();
+            }
+        }
+    }
+
+    /// Accented spellings offered for the word just typed
+    pub fn suggestion(&self) -> Option<&Suggestion> {
+        self.autocorrect.as_ref()?.suggestion()
+    }
+
+    /// Rewrite the word just typed with its `index`th suggested spelling
+    pub fn accept_suggestion(&mut self, index: usize) -> Option<KeyAction> {
+        let autocorrect = self.autocorrect.as_mut()?;
+        let original = autocorrect.suggestion()?.typed();
+        let action = autocorrect.accept(index)?;
+        if let KeyAction::Rewrite { chars, .. } = &action {
+            self.replacements.push(Replacement { produced: chars.clone(), original });
+        }
+        Some(action)
+    }
+
+    /// The keys behind the character just typed, in learning mode; taken
+    /// so each is shown once
+    pub fn take_lesson(&mut self) -> Option<Lesson> {
+        self.lesson.take()
+    }
+
+    /// Take back the most recent replacement, typing the keys pressed for it
+    /// as they are without GhostKeys
+    pub fn undo_replacement(&mut self) -> Option<KeyAction> {
+        let action = self.replacements.undo()?;
+        // Neither a pending accent nor autocorrect should act on what's restored
+        self.reset_mapper();
+        if let Some(autocorrect) = &mut self.autocorrect {
+            autocorrect.reset();
+        }
+        Some(action)
+    }
+
+    /// Drop a pending accent, so it isn't applied after a pause
+    pub fn reset_mapper(&mut self) {
+        self.mapper.reset();
+        if let Some(trail) = &mut self.trail {
+            trail.reset();
+        }
+    }
+
+    /// Drop the pending accent, the word autocorrect is tracking and the
+    /// replacements to undo
+    pub fn reset(&mut self) {
+        self.reset_mapper();
+        self.replacements.clear();
+        if let Some(autocorrect) = &mut self.autocorrect {
+            autocorrect.reset();
+        }
+    }
+
+    /// Whether macros are enabled
+    pub fn records_macros(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Start recording into `slot`, or stop if `slot` is being recorded;
+    /// returns the finished recording
+    pub fn toggle_recording(&mut self, slot: u8) -> Option<(u8, String)> {
+        let recorder = self.recorder.as_mut()?;
+        if recorder.recording_slot() == Some(slot) {
+            recorder.stop()
+        } else {
+            recorder.start(slot);
+            None
+        }
+    }
+
+    /// Flood limit for injected characters
+    pub fn limiter(&self) -> &RefCell<InjectionLimiter> {
+        &self.limiter
+    }
+
+    /// Count an injection that failed
+    pub fn injection_failed(&mut self) {
+        self.counters.injection_failures += 1;
+    }
+
+    /// What the hook last saw of the foreground window
+    pub fn foreground_mut(&mut self) -> &mut ForegroundCache {
+        &mut self.foreground
+    }
+
+    /// Mapper state and counters, for a crash report
+    pub fn snapshot(&self) -> String {
+        format!(
+            "{:?} ({:?}), {:?}",
+            self.mapper.state(),
+            self.mapper.direction(),
+            self.counters
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hotkeys::{Action, Chord};
+    use crate::layout::us_key;
+    use crate::mapper::{Direction, MapperState};
+    use crate::state::OperationMode;
+
+    /// What the hook callback does with the key typing `c`, minus the OS
+    /// lookups; returns true if the key is kept from applications
+    fn callback(context: &mut HookContext, c: char, down: bool) -> bool {
+        let code = c as u32;
+        if !down {
+            return context.key_up(code);
+        }
+        let repeat = context.is_repeat(code);
+        let blocked = if context.state().is_paused() {
+            context.reset_mapper();
+            false
+        } else {
+            let (key, shift) = us_key(c).unwrap();
+            let event = KeyEvent { repeat, ..KeyEvent::press(key, shift) };
+            let input = KeyInput { typed: Some(c), ..KeyInput::new(event) };
+            context.map(input) != KeyAction::Pass
+        };
+        context.key_down(code, blocked);
+        blocked
+    }
+
+    fn type_keys(context: &mut HookContext, text: &str) {
+        for c in text.chars() {
+            callback(context, c, true);
+            callback(context, c, false);
+        }
+    }
+
+    fn context() -> HookContext {
+        HookContext::new(SharedState::new(), Mapper::new()).unwrap()
+    }
+
+    #[test]
+    fn test_key_ups_follow_key_downs() {
+        let mut context = context();
+        assert!(callback(&mut context, ';', true));
+        // Auto-repeat of the held key
+        assert!(context.is_repeat(';' as u32));
+        assert!(callback(&mut context, ';', true));
+        assert!(callback(&mut context, ';', false));
+        assert!(!context.is_repeat(';' as u32));
+
+        assert!(!callback(&mut context, ' ', true));
+        assert!(!callback(&mut context, ' ', false));
+
+        let counters = context.counters();
+        assert_eq!((counters.key_downs, counters.blocked, counters.blocked_key_ups), (3, 2, 1));
+    }
+
+    #[test]
+    fn test_pausing_drops_a_pending_accent() {
+        let mut context = context();
+        assert!(callback(&mut context, '\'', true));
+        callback(&mut context, '\'', false);
+
+        context.state().set_mode(OperationMode::Passthrough).unwrap();
+        assert!(!callback(&mut context, 'a', true));
+        callback(&mut context, 'a', false);
+
+        context.state().set_mode(OperationMode::Active).unwrap();
+        assert!(!callback(&mut context, 'a', true));
+    }
+
+    #[test]
+    fn test_mapper_swap_keeps_held_keys() {
+        let mut context = context();
+        assert!(callback(&mut context, ';', true));
+        context.set_mapper(Mapper::with_direction(Direction::Abnt2ToUs));
+        assert_eq!(context.mapper().direction(), Direction::Abnt2ToUs);
+        // Released after the swap, still blocked like its key-down
+        assert!(callback(&mut context, ';', false));
+    }
+
+    #[test]
+    fn test_settings_snapshot_the_config() {
+        let state = SharedState::new();
+        let mut config = state.get_config().unwrap();
+        config.autocorrect.enabled = true;
+        config.macros.enabled = true;
+        config.hotkeys.toggle_pause = false;
+        state.set_config(config).unwrap();
+        let mut context = HookContext::new(state, Mapper::new()).unwrap();
+        assert!(!context.settings().pause_hotkey);
+        assert!(context.records_macros());
+
+        // Later changes don't reach a running hook
+        let mut config = context.state().get_config().unwrap();
+        config.hotkeys.toggle_pause = true;
+        context.state().set_config(config).unwrap();
+        assert!(!context.settings().pause_hotkey);
+
+        assert_eq!(context.toggle_recording(2), None);
+        type_keys(&mut context, "nai");
+        context.unmapped_key(UnmappedKey::Backspace);
+        type_keys(&mut context, "o ");
+        assert_eq!(context.toggle_recording(2), Some((2, "não ".to_string())));
+    }
+
+    #[test]
+    fn test_undo_replacements() {
+        let state = SharedState::new();
+        let mut config = state.get_config().unwrap();
+        config.autocorrect.enabled = true;
+        state.set_config(config).unwrap();
+        let mut context = HookContext::new(state, Mapper::new()).unwrap();
+
+        type_keys(&mut context, "a;");
+        assert_eq!(
+            context.undo_replacement(),
+            Some(KeyAction::Rewrite { backspaces: 1, chars: vec![';'] })
+        );
+        assert_eq!(context.undo_replacement(), None);
+
+        type_keys(&mut context, "nao ");
+        assert_eq!(
+            context.undo_replacement(),
+            Some(KeyAction::Rewrite { backspaces: 4, chars: "nao ".chars().collect() })
+        );
+
+        // Moving the cursor forgets what was replaced before
+        type_keys(&mut context, ";");
+        context.unmapped_key(UnmappedKey::Other);
+        assert_eq!(context.undo_replacement(), None);
+    }
+
+    #[test]
+    fn test_lessons_in_learning_mode() {
+        let mut context = context();
+        type_keys(&mut context, ";");
+        assert_eq!(context.take_lesson(), None);
+
+        let state = SharedState::new();
+        let mut config = state.get_config().unwrap();
+        config.learning.enabled = true;
+        state.set_config(config).unwrap();
+        let mut context = HookContext::new(state, Mapper::new()).unwrap();
+
+        type_keys(&mut context, "'");
+        assert_eq!(context.take_lesson(), None);
+        type_keys(&mut context, "a");
+        let lesson = context.take_lesson().unwrap();
+        assert_eq!((lesson.keys.join(" "), lesson.output.as_str()), ("' a".to_string(), "ã"));
+        assert_eq!(context.take_lesson(), None);
+
+        // A pause drops the dead key, and its key with it
+        type_keys(&mut context, "'");
+        context.reset_mapper();
+        type_keys(&mut context, ";");
+        assert_eq!(context.take_lesson().unwrap().keys, [";"]);
+    }
+
+    #[test]
+    fn test_custom_hotkeys_follow_the_config() {
+        let state = SharedState::new();
+        let mut config = state.get_config().unwrap();
+        let chord = Chord::parse("ctrl+alt+v").unwrap();
+        config.hotkeys.custom.insert(chord, Action::LiteralNextKey);
+        state.set_config(config).unwrap();
+        let mut context = HookContext::new(state, Mapper::new()).unwrap();
+        assert_eq!(context.hotkeys().find(&chord), Some(&Action::LiteralNextKey));
+
+        // Unlike the other settings, a reload picks up the new table
+        let mut config = context.state().get_config().unwrap();
+        config.hotkeys.custom.clear();
+        config.hotkeys.custom.insert(Chord::parse("ctrl+alt+space").unwrap(), Action::Toggle);
+        context.state().set_config(config).unwrap();
+        assert!(!context.hotkeys().is_empty());
+        let conflicts = context.reload_hotkeys().unwrap();
+        assert_eq!(conflicts.len(), 1);
+        assert!(context.hotkeys().is_empty());
+
+        assert!(callback(&mut context, '\'', true));
+        context.arm_literal_next();
+        assert!(context.take_literal_next());
+        assert!(!context.take_literal_next());
+        // The pending accent was dropped along the way
+        assert_eq!(context.mapper().state(), &MapperState::Idle);
+    }
+
+    #[test]
+    fn test_os_layout_rule_follows_reloads() {
+        let state = SharedState::new();
+        let mut context = HookContext::new(state, Mapper::new()).unwrap();
+        assert!(context.follows_os_layout());
+
+        let mut config = context.state().get_config().unwrap();
+        config.auto_pause.when_os_layout_is_abnt2 = false;
+        context.state().set_config(config).unwrap();
+        assert!(context.follows_os_layout());
+        context.reload_hotkeys().unwrap();
+        assert!(!context.follows_os_layout());
+    }
+
+    #[test]
+    fn test_suggestions_without_autocorrect() {
+        let state = SharedState::new();
+        let mut config = state.get_config().unwrap();
+        config.autocorrect.suggestions = true;
+        state.set_config(config).unwrap();
+        let mut context = HookContext::new(state, Mapper::new()).unwrap();
+
+        type_keys(&mut context, "nao e ");
+        assert_eq!(context.suggestion().unwrap().alternatives, ["é"]);
+        assert_eq!(
+            context.accept_suggestion(0),
+            Some(KeyAction::Rewrite { backspaces: 2, chars: vec!['é', ' '] })
+        );
+        assert_eq!(context.suggestion(), None);
+        assert_eq!(self::context().accept_suggestion(0), None);
+    }
+}
@@ -0,0 +1,538 @@
+//! Hotkeys defined in the config file
+//!
+//! Besides the built-in hotkeys (Ctrl+Alt+Space and friends), `[hotkeys.custom]`
+//! binds chords to actions:
+//!
+//! ```toml
+//! [hotkeys.custom]
+//! "ctrl+alt+p" = "toggle"
+//! "ctrl+alt+9" = "pause_15m"
+//! "ctrl+alt+l" = "switch_layout:br"
+//! "ctrl+shift+space" = "open_palette"
+//! "ctrl+alt+v" = "literal_next_key"
+//! "ctrl+alt+s" = "run_macro:f1"
+//! ```
+//!
+//! Chords the OS keeps for itself (Alt+Tab, Win+L, Ctrl+Alt+Delete...) or
+//! that a built-in hotkey already uses are left out of the table and
+//! reported as conflicts. The table is rebuilt whenever the config changes,
+//! so edits take effect without restarting (Windows).
+
+use std::fmt;
+
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, Schema, SchemaObject, StringValidation};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::error::{GhostKeysError, Result};
+use crate::layout::{self, Layout};
+use crate::macros::SLOT_COUNT;
+use crate::paths;
+
+/// Highest function key a chord can use (F24)
+const MAX_FUNCTION_KEY: u8 = 24;
+
+/// Chords the OS handles before any application sees them, or that users
+/// rely on too much to give up
+const RESERVED: &[&str] = &[
+    "alt+tab",
+    "alt+shift+tab",
+    "alt+esc",
+    "alt+f4",
+    "ctrl+esc",
+    "ctrl+shift+esc",
+    "ctrl+alt+delete",
+    "win+d",
+    "win+e",
+    "win+i",
+    "win+l",
+    "win+r",
+    "win+v",
+    "win+x",
+    "win+tab",
+    "win+space",
+    "win+shift+s",
+];
+
+/// Layout names that select the built-in ABNT2 tables in `switch_layout:`
+const BUILT_IN_LAYOUTS: &[&str] = &["abnt2", "br"];
+
+/// A key that can end a chord
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Key {
+    /// A letter (lowercase), digit or punctuation key, by what it types on a
+    /// US keyboard without Shift
+    Char(char),
+    /// F1 to F24
+    Function(u8),
+    /// Space bar
+    Space,
+    /// Enter
+    Enter,
+    /// Tab
+    Tab,
+    /// Esc
+    Escape,
+    /// Backspace
+    Backspace,
+    /// Insert
+    Insert,
+    /// Delete
+    Delete,
+    /// Home
+    Home,
+    /// End
+    End,
+    /// Page Up
+    PageUp,
+    /// Page Down
+    PageDown,
+    /// Left arrow
+    Left,
+    /// Right arrow
+    Right,
+    /// Up arrow
+    Up,
+    /// Down arrow
+    Down,
+    /// Pause/Break
+    Pause,
+    /// Print Screen
+    PrintScreen,
+}
+
+/// Named keys, how they're shown and what they can be called in the config file
+const NAMED_KEYS: &[(Key, &str, &[&str])] = &[
+    (Key::Space, "Space", &["space"]),
+    (Key::Enter, "Enter", &["enter", "return"]),
+    (Key::Tab, "Tab", &["tab"]),
+    (Key::Escape, "Esc", &["esc", "escape"]),
+    (Key::Backspace, "Backspace", &["backspace"]),
+    (Key::Insert, "Insert", &["insert", "ins"]),
+    (Key::Delete, "Delete", &["delete", "del"]),
+    (Key::Home, "Home", &["home"]),
+    (Key::End, "End", &["end"]),
+    (Key::PageUp, "PageUp", &["pageup", "pgup"]),
+    (Key::PageDown, "PageDown", &["pagedown", "pgdn"]),
+    (Key::Left, "Left", &["left"]),
+    (Key::Right, "Right", &["right"]),
+    (Key::Up, "Up", &["up"]),
+    (Key::Down, "Down", &["down"]),
+    (Key::Pause, "Pause", &["pause"]),
+    (Key::PrintScreen, "PrintScreen", &["printscreen", "prtsc"]),
+];
+
+/// Punctuation keys, by what they type on a US keyboard without Shift
+const PUNCTUATION: &str = ";'[]\\/-=,.`";
+
+impl Key {
+    /// Parse a key name (`"p"`, `"f9"`, `"space"`, `"."`)
+    fn parse(name: &str) -> Option<Self> {
+        let lower = name.to_ascii_lowercase();
+        let mut chars = lower.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            return (c.is_ascii_alphanumeric() || PUNCTUATION.contains(c)).then_some(Key::Char(c));
+        }
+        if let Some(number) = lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+            return (1..=MAX_FUNCTION_KEY).contains(&number).then_some(Key::Function(number));
+        }
+        NAMED_KEYS
+            .iter()
+            .find(|(_, _, names)| names.contains(&lower.as_str()))
+            .map(|(key, _, _)| *key)
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Key::Char(c) => write!(f, "{}", c.to_ascii_uppercase()),
+            Key::Function(number) => write!(f, "F{}", number),
+            key => {
+                let name = NAMED_KEYS.iter().find(|(named, _, _)| named == key);
+                write!(f, "{}", name.map_or("?", |(_, shown, _)| shown))
+            }
+        }
+    }
+}
+
+/// Modifiers and a key, as written in the config file (`"ctrl+alt+p"`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Chord {
+    /// Either Ctrl key
+    pub ctrl: bool,
+    /// Either Alt key
+    pub alt: bool,
+    /// Either Shift key
+    pub shift: bool,
+    /// The Windows key (Super on Linux)
+    pub win: bool,
+    /// The key pressed with them
+    pub key: Key,
+}
+
+impl Chord {
+    /// Parse a chord such as `"ctrl+alt+p"` or `"Win+Shift+F9"`
+    ///
+    /// A chord needs Ctrl, Alt or Win unless it ends in a function key or
+    /// Pause, so hotkeys can't take over keys used for typing.
+    pub fn parse(text: &str) -> std::result::Result<Self, String> {
+        let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        // "ctrl++" is Ctrl and the key typing + (=)
+        if text.ends_with("++") {
+            parts.truncate(parts.len() - 2);
+            parts.push("=");
+        }
+        let key_name = parts.pop().unwrap_or_default();
+        let key = Key::parse(key_name)
+            .ok_or_else(|| format!("unknown key \"{}\" in hotkey \"{}\"", key_name, text))?;
+        let mut chord = Self { ctrl: false, alt: false, shift: false, win: false, key };
+        for modifier in parts {
+            match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => chord.ctrl = true,
+                "alt" => chord.alt = true,
+                "shift" => chord.shift = true,
+                "win" | "super" | "meta" => chord.win = true,
+                other => {
+                    return Err(format!("unknown modifier \"{}\" in hotkey \"{}\"", other, text))
+                }
+            }
+        }
+        let standalone = matches!(key, Key::Function(_) | Key::Pause);
+        if !(chord.ctrl || chord.alt || chord.win || standalone) {
+            return Err(format!(
+                "hotkey \"{}\" needs Ctrl, Alt or Win, or it would take over a key used for typing",
+                text
+            ));
+        }
+        Ok(chord)
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let modifiers = [(self.ctrl, "Ctrl"), (self.alt, "Alt"), (self.shift, "Shift"), (self.win, "Win")];
+        for (_, name) in modifiers.iter().filter(|(held, _)| *held) {
+            write!(f, "{}+", name)?;
+        }
+        write!(f, "{}", self.key)
+    }
+}
+
+impl TryFrom<String> for Chord {
+    type Error = String;
+
+    fn try_from(text: String) -> std::result::Result<Self, Self::Error> {
+        Self::parse(&text)
+    }
+}
+
+impl From<Chord> for String {
+    fn from(chord: Chord) -> Self {
+        chord.to_string().to_ascii_lowercase()
+    }
+}
+
+/// What a custom hotkey does
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Action {
+    /// Pause or resume, like Ctrl+Alt+Space
+    Toggle,
+    /// Pause, resuming by itself after this many minutes (`pause_15m`)
+    PauseFor(u32),
+    /// Use the layout saved as `layouts/<name>.klc`, or the built-in ABNT2
+    /// tables for `abnt2` or `br`
+    SwitchLayout(String),
+    /// Open the character palette
+    OpenPalette,
+    /// Let the next key through as it is
+    LiteralNextKey,
+    /// Type the macro saved under this name (`f1`...)
+    RunMacro(String),
+}
+
+impl Action {
+    /// Parse an action as written in the config file
+    pub fn parse(text: &str) -> std::result::Result<Self, String> {
+        let (name, argument) = match text.split_once(':') {
+            Some((name, argument)) => (name.trim(), Some(argument.trim())),
+            None => (text.trim(), None),
+        };
+        let minutes = name
+            .strip_prefix("pause_")
+            .and_then(|rest| rest.strip_suffix('m'))
+            .and_then(|minutes| minutes.parse().ok())
+            .filter(|&minutes| minutes > 0);
+        match (name, argument, minutes) {
+            ("toggle", None, _) => Ok(Action::Toggle),
+            (_, None, Some(minutes)) => Ok(Action::PauseFor(minutes)),
+            ("switch_layout", Some(layout), _) if !layout.is_empty() => {
+                Ok(Action::SwitchLayout(layout.to_string()))
+            }
+            ("open_palette", None, _) => Ok(Action::OpenPalette),
+            ("literal_next_key", None, _) => Ok(Action::LiteralNextKey),
+            ("run_macro", Some(slot), _) if !slot.is_empty() => Ok(Action::RunMacro(slot.to_string())),
+            _ => Err(format!(
+                "unknown hotkey action \"{}\" (use toggle, pause_<minutes>m, switch_layout:<name>, \
+                 open_palette, literal_next_key or run_macro:<name>)",
+                text
+            )),
+        }
+    }
+
+    /// Whether the action works while GhostKeys is paused
+    pub fn works_while_paused(&self) -> bool {
+        matches!(self, Action::Toggle)
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Toggle => write!(f, "toggle"),
+            Action::PauseFor(minutes) => write!(f, "pause_{}m", minutes),
+            Action::SwitchLayout(layout) => write!(f, "switch_layout:{}", layout),
+            Action::OpenPalette => write!(f, "open_palette"),
+            Action::LiteralNextKey => write!(f, "literal_next_key"),
+            Action::RunMacro(slot) => write!(f, "run_macro:{}", slot),
+        }
+    }
+}
+
+impl TryFrom<String> for Action {
+    type Error = String;
+
+    fn try_from(text: String) -> std::result::Result<Self, Self::Error> {
+        Self::parse(&text)
+    }
+}
+
+impl From<Action> for String {
+    fn from(action: Action) -> Self {
+        action.to_string()
+    }
+}
+
+impl JsonSchema for Action {
+    fn schema_name() -> String {
+        "Action".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        let pattern = "^(toggle|pause_[0-9]+m|switch_layout:.+|open_palette|literal_next_key\
+                       |run_macro:.+)$";
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            string: Some(Box::new(StringValidation {
+                pattern: Some(pattern.to_string()),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+/// Why a custom hotkey was left out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Conflict {
+    /// The OS keeps the chord for itself
+    Reserved(Chord),
+    /// A built-in hotkey (named by its `[hotkeys]` setting) uses the chord
+    BuiltIn(Chord, &'static str),
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Conflict::Reserved(chord) => {
+                write!(f, "hotkey {} is reserved by the system and was ignored", chord)
+            }
+            Conflict::BuiltIn(chord, setting) => {
+                write!(f, "hotkey {} is already used by `{}` and was ignored", chord, setting)
+            }
+        }
+    }
+}
+
+/// The custom hotkeys in effect
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Hotkeys {
+    bindings: Vec<(Chord, Action)>,
+}
+
+impl Hotkeys {
+    /// The `[hotkeys.custom]` table minus the chords that conflict, and the
+    /// conflicts found
+    pub fn from_config(config: &Config) -> (Self, Vec<Conflict>) {
+        let built_in = built_in_chords(config);
+        let mut hotkeys = Self::default();
+        let mut conflicts = Vec::new();
+        for (chord, action) in &config.hotkeys.custom {
+            if is_reserved(chord) {
+                conflicts.push(Conflict::Reserved(*chord));
+            } else if let Some((_, setting)) = built_in.iter().find(|(taken, _)| taken == chord) {
+                conflicts.push(Conflict::BuiltIn(*chord, setting));
+            } else {
+                hotkeys.bindings.push((*chord, action.clone()));
+            }
+        }
+        (hotkeys, conflicts)
+    }
+
+    /// Action bound to `chord`, if any
+    pub fn find(&self, chord: &Chord) -> Option<&Action> {
+        self.bindings.iter().find(|(bound, _)| bound == chord).map(|(_, action)| action)
+    }
+
+    /// Whether no custom hotkey is in effect
+    pub fn is_empty(&self) -> bool {
+        self.bindings.is_empty()
+    }
+}
+
+/// Whether the OS keeps `chord` for itself
+fn is_reserved(chord: &Chord) -> bool {
+    RESERVED.iter().filter_map(|text| Chord::parse(text).ok()).any(|reserved| reserved == *chord)
+}
+
+/// Chords of the built-in hotkeys turned on in `config`, with their settings
+fn built_in_chords(config: &Config) -> Vec<(Chord, &'static str)> {
+    let chord = |ctrl, alt, shift, key| Chord { ctrl, alt, shift, win: false, key };
+    let mut chords = Vec::new();
+    if config.hotkeys.toggle_pause {
+        chords.push((chord(true, true, false, Key::Space), "toggle_pause"));
+    }
+    if config.hotkeys.palette {
+        chords.push((chord(true, true, false, Key::Char('.')), "palette"));
+    }
+    if config.hotkeys.undo {
+        chords.push((chord(true, true, false, Key::Char('z')), "undo"));
+    }
+    if config.hotkeys.cycle_profile {
+        chords.push((chord(true, true, false, Key::Char('p')), "cycle_profile"));
+    }
+    if config.macros.enabled {
+        for number in 1..=SLOT_COUNT {
+            chords.push((chord(true, false, true, Key::Function(number)), "macros"));
+            chords.push((chord(true, true, false, Key::Function(number)), "macros"));
+        }
+    }
+    if config.autocorrect.suggestions {
+        for digit in '1'..='9' {
+            chords.push((chord(false, true, false, Key::Char(digit)), "suggestions"));
+        }
+    }
+    chords
+}
+
+/// The layout `switch_layout:<name>` selects: `None` for the built-in ABNT2
+/// tables, or the one saved as `layouts/<name>.klc`
+pub fn load_layout(name: &str) -> Result<Option<Layout>> {
+    if BUILT_IN_LAYOUTS.iter().any(|built_in| built_in.eq_ignore_ascii_case(name)) {
+        return Ok(None);
+    }
+    let dir = paths::layouts_dir().ok_or_else(|| GhostKeysError::ConfigError {
+        path: None,
+        line: None,
+        message: "could not determine the config directory".to_string(),
+    })?;
+    layout::klc::load(&dir.join(format!("{}.klc", name))).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_chords() {
+        let chord = Chord::parse("Ctrl+Alt+P").unwrap();
+        assert!(chord.ctrl && chord.alt && !chord.shift && !chord.win);
+        assert_eq!(chord.key, Key::Char('p'));
+        assert_eq!(chord.to_string(), "Ctrl+Alt+P");
+        assert_eq!(Chord::parse("alt+ctrl+p"), Ok(chord));
+
+        assert_eq!(Chord::parse("win+shift+f9").unwrap().to_string(), "Shift+Win+F9");
+        assert_eq!(Chord::parse("ctrl++").unwrap().key, Key::Char('='));
+        assert_eq!(Chord::parse("ctrl+alt+pgup").unwrap().key, Key::PageUp);
+        assert_eq!(Chord::parse("f13").unwrap().key, Key::Function(13));
+
+        assert!(Chord::parse("shift+a").is_err());
+        assert!(Chord::parse("ctrl+hyper+a").is_err());
+        assert!(Chord::parse("ctrl+f25").is_err());
+        assert!(Chord::parse("ctrl+ç").is_err());
+    }
+
+    #[test]
+    fn test_parse_actions() {
+        assert_eq!(Action::parse("toggle"), Ok(Action::Toggle));
+        assert_eq!(Action::parse("pause_15m"), Ok(Action::PauseFor(15)));
+        assert_eq!(Action::parse("switch_layout:br"), Ok(Action::SwitchLayout("br".into())));
+        assert_eq!(Action::parse("open_palette"), Ok(Action::OpenPalette));
+        assert_eq!(Action::parse("literal_next_key"), Ok(Action::LiteralNextKey));
+        assert_eq!(Action::parse("run_macro:f1"), Ok(Action::RunMacro("f1".into())));
+        for action in ["pause_0m", "pause_m", "switch_layout", "run_macro:", "dance"] {
+            assert!(Action::parse(action).is_err(), "{}", action);
+        }
+        assert_eq!(Action::PauseFor(15).to_string(), "pause_15m");
+    }
+
+    #[test]
+    fn test_conflicts_are_left_out() {
+        let mut config = Config::parse(
+            "[hotkeys.custom]\n\
+             \"ctrl+alt+p\" = \"toggle\"\n\
+             \"alt+tab\" = \"open_palette\"\n\
+             \"ctrl+alt+space\" = \"pause_15m\"\n\
+             \"ctrl+shift+f1\" = \"literal_next_key\"\n",
+        )
+        .unwrap();
+        let (hotkeys, conflicts) = Hotkeys::from_config(&config);
+        assert_eq!(hotkeys.find(&Chord::parse("ctrl+alt+p").unwrap()), Some(&Action::Toggle));
+        assert_eq!(hotkeys.find(&Chord::parse("alt+tab").unwrap()), None);
+        // Macros are off, so their chords are free
+        assert_eq!(
+            hotkeys.find(&Chord::parse("ctrl+shift+f1").unwrap()),
+            Some(&Action::LiteralNextKey)
+        );
+        assert_eq!(
+            conflicts,
+            [
+                Conflict::Reserved(Chord::parse("alt+tab").unwrap()),
+                Conflict::BuiltIn(Chord::parse("ctrl+alt+space").unwrap(), "toggle_pause"),
+            ]
+        );
+
+        config.hotkeys.toggle_pause = false;
+        config.macros.enabled = true;
+        let (hotkeys, conflicts) = Hotkeys::from_config(&config);
+        assert_eq!(
+            hotkeys.find(&Chord::parse("ctrl+alt+space").unwrap()),
+            Some(&Action::PauseFor(15))
+        );
+        assert_eq!(conflicts.len(), 2);
+        assert_eq!(
+            conflicts[1].to_string(),
+            "hotkey Ctrl+Shift+F1 is already used by `macros` and was ignored"
+        );
+    }
+
+    #[test]
+    fn test_custom_hotkeys_round_trip() {
+        let config = Config::parse("[hotkeys.custom]\n\"Alt+Ctrl+L\" = \"switch_layout:br\"\n").unwrap();
+        let saved = toml::to_string(&config).unwrap();
+        assert!(saved.contains("\"ctrl+alt+l\" = \"switch_layout:br\""), "{}", saved);
+        assert_eq!(Config::parse(&saved).unwrap(), config);
+        assert!(Config::parse("[hotkeys.custom]\n\"ctrl+alt+l\" = \"fly\"\n").is_err());
+    }
+
+    #[test]
+    fn test_built_in_layout_names() {
+        assert_eq!(load_layout("br").unwrap(), None);
+        assert_eq!(load_layout("ABNT2").unwrap(), None);
+    }
+}
@@ -0,0 +1,639 @@
+//! Localized user interface text
+//!
+//! GhostKeys is mostly used in Brazil, so the tray, dialogs, notifications and
+//! command-line messages come in Brazilian Portuguese and English. The
+//! language follows the OS locale unless `language` is set in the config file.
+//! Strings live in two embedded tables below, one match per language, so a
+//! missing translation is a compile error rather than a runtime fallback.
+//! Console diagnostics and error details (the `GhostKeysError` messages) stay
+//! in English, since they are what users paste into bug reports.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// A user interface language
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+pub enum Language {
+    /// English
+    #[default]
+    #[serde(rename = "en")]
+    English,
+    /// Brazilian Portuguese
+    #[serde(rename = "pt-BR", alias = "pt")]
+    Portuguese,
+}
+
+impl Language {
+    /// Language for a locale name such as `pt_BR.UTF-8` (Linux) or `pt-BR`
+    /// (Windows); every Portuguese locale gets Brazilian Portuguese
+    pub fn from_locale(locale: &str) -> Self {
+        let language = locale.split(['_', '-', '.', '@']).next().unwrap_or_default();
+        if language.eq_ignore_ascii_case("pt") {
+            Language::Portuguese
+        } else {
+            Language::English
+        }
+    }
+
+    /// Language of the OS user interface
+    #[cfg(target_os = "windows")]
+    pub fn detect() -> Self {
+        use windows::Win32::Globalization::GetUserDefaultLocaleName;
+
+        // LOCALE_NAME_MAX_LENGTH
+        let mut name = [0u16; 85];
+        let len = unsafe { GetUserDefaultLocaleName(&mut name) };
+        if len <= 1 {
+            return Language::English;
+        }
+        Self::from_locale(&String::from_utf16_lossy(&name[..len as usize - 1]))
+    }
+
+    /// Language of the session, from the POSIX locale variables in order of precedence
+    #[cfg(not(target_os = "windows"))]
+    pub fn detect() -> Self {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|locale| !locale.is_empty())
+            .map_or(Language::English, |locale| Self::from_locale(&locale))
+    }
+}
+
+/// `Language` as stored in `LANGUAGE`; 0 means not chosen yet
+const UNSET: u8 = 0;
+const ENGLISH: u8 = 1;
+const PORTUGUESE: u8 = 2;
+
+/// Language used by `tr`, shared by the tray and hook threads
+static LANGUAGE: AtomicU8 = AtomicU8::new(UNSET);
+
+/// Use `language` for all text from now on
+pub fn set_language(language: Language) {
+    let value = match language {
+        Language::English => ENGLISH,
+        Language::Portuguese => PORTUGUESE,
+    };
+    LANGUAGE.store(value, Ordering::Relaxed);
+}
+
+/// Language in use: the one set with `set_language`, or the OS language
+pub fn language() -> Language {
+    match LANGUAGE.load(Ordering::Relaxed) {
+        ENGLISH => Language::English,
+        PORTUGUESE => Language::Portuguese,
+        _ => {
+            let detected = Language::detect();
+            set_language(detected);
+            detected
+        }
+    }
+}
+
+/// A piece of user interface text
+///
+/// `{}` in a string is replaced by an argument of `tr_args`, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Text {
+    // Tray menu and tooltips
+    StatusActive,
+    StatusPaused,
+    StatusPausedFor,
+    Pause,
+    Resume,
+    ReverseMode,
+    RunSelfTest,
+    ModeHistory,
+    SwitchToUsLayout,
+    OpenLogs,
+    OpenConfigFolder,
+    HelpMenu,
+    AboutMenu,
+    Exit,
+    TooltipActive,
+    TooltipPaused,
+
+    // Dialogs
+    HelpTitle,
+    HelpBody,
+    AboutTitle,
+    AboutBody,
+    HistoryTitle,
+    PaletteTitle,
+    LearningThen,
+    HelpUnavailable,
+    AboutUnavailable,
+
+    // Notifications
+    PausedByOsLayout,
+    ResumedByOsLayout,
+    PausedByKeyboard,
+    ResumedByKeyboard,
+    ResumedByWatchdog,
+    RuleUncheckable,
+    SelfTestPassed,
+    SelfTestSkipped,
+    SelfTestFailed,
+    InterceptorFailed,
+    OpenLogsFailed,
+    OpenConfigFolderFailed,
+    SwitchedToUsLayout,
+    SwitchLayoutFailed,
+    TrayUnavailable,
+
+    // Command line
+    Banner,
+    UsingDefaults,
+    LayoutLoaded,
+    LayoutSwitched,
+    LayoutFallback,
+    LayoutExported,
+    LayoutExportFailed,
+    ProfileExported,
+    ProfileImported,
+    ProfileLayoutSaved,
+    Profiles,
+    ProfileSwitched,
+    NoProfiles,
+    SafeModeStarted,
+    SafeModeTitle,
+    SafeModeOpenConfig,
+    ConfigValid,
+    ConfigProblems,
+    SchemaWritten,
+    StatsExported,
+    Running,
+    RunningWithoutTray,
+    Exiting,
+    TryHeader,
+    TrainHeader,
+    TrainPrevious,
+    TrainProgress,
+    TrainFinished,
+    TrainStopped,
+
+    // First-run setup
+    SetupWelcome,
+    SetupLayout,
+    SetupTerminals,
+    SetupAutostart,
+    SetupInvalidAnswer,
+    SetupSaved,
+    SetupAutostartFailed,
+    SetupVerify,
+    SetupVerified,
+    SetupRetry,
+    SetupSkipped,
+}
+
+impl Text {
+    /// The text in `language`
+    pub fn get(self, language: Language) -> &'static str {
+        match language {
+            Language::English => self.english(),
+            Language::Portuguese => self.portuguese(),
+        }
+    }
+
+    fn english(self) -> &'static str {
+        match self {
+            Text::StatusActive => "GhostKeys: Active",
+            Text::StatusPaused => "GhostKeys: Paused",
+            Text::StatusPausedFor => "GhostKeys: Paused for {} minutes",
+            Text::Pause => "Pause",
+            Text::Resume => "Resume",
+            Text::ReverseMode => "Reverse mode (ABNT2 → US)",
+            Text::RunSelfTest => "Run self-test",
+            Text::ModeHistory => "Mode history",
+            Text::SwitchToUsLayout => "Switch Windows to US layout",
+            Text::OpenLogs => "Open logs",
+            Text::OpenConfigFolder => "Open config folder",
+            Text::HelpMenu => "Help / Mappings",
+            Text::AboutMenu => "About",
+            Text::Exit => "Exit",
+            Text::TooltipActive => "GhostKeys - ABNT2 Emulation (Active)",
+            Text::TooltipPaused => "GhostKeys - ABNT2 Emulation (Paused)",
+
+            Text::HelpTitle => "GhostKeys - Key Mappings",
+            Text::HelpBody => {
+                "GhostKeys Mappings (US → ABNT2):\n\n\
+                 [ (next to P) = Acute Accent (´)\n\
+                 Shift + [     = Grave Accent (`)\n\
+                 ] (next to [) = [\n\
+                 ' (next to ;) = Tilde (~)\n\
+                 Shift + '     = Circumflex (^)\n\
+                 ; (next to L) = ç\n\
+                 / (next to .) = ;\n\n\
+                 Dead keys combine with vowels:\n\
+                 ´ + a = á    ~ + a = ã    ` + a = à    ^ + a = â"
+            }
+            Text::AboutTitle => "About GhostKeys",
+            Text::AboutBody => {
+                "GhostKeys v{}\n\n\
+                 ABNT2 keyboard layout emulation on US keyboards.\n\n\
+                 Created for Kiroween 2025\n\n\
+                 https://github.com/mclara/ghostkeys"
+            }
+            Text::HistoryTitle => "GhostKeys - Mode History",
+            Text::PaletteTitle => "GhostKeys - Characters",
+            Text::LearningThen => "then",
+            Text::HelpUnavailable => "Help dialog is only available on Windows",
+            Text::AboutUnavailable => "About dialog is only available on Windows",
+
+            Text::PausedByOsLayout => "Paused: Windows is using the ABNT2 layout",
+            Text::ResumedByOsLayout => "Resumed: Windows switched away from ABNT2",
+            Text::PausedByKeyboard => "Paused: keyboard {} attached",
+            Text::ResumedByKeyboard => "Resumed: keyboard removed",
+            Text::ResumedByWatchdog => {
+                "Resumed: the rule that paused GhostKeys stopped applying {} minutes ago"
+            }
+            Text::RuleUncheckable => {
+                "GhostKeys is paused by a rule that can't be checked anymore; \
+                 resume from the tray if it no longer applies"
+            }
+            Text::SelfTestPassed => {
+                "Self-test passed: keys are intercepted and characters injected"
+            }
+            Text::SelfTestSkipped => "Self-test skipped: {}",
+            Text::SelfTestFailed => "Self-test failed: {}",
+            Text::InterceptorFailed => "Failed to start keyboard interceptor: {}",
+            Text::OpenLogsFailed => "Failed to open logs: {}",
+            Text::OpenConfigFolderFailed => "Failed to open the config folder: {}",
+            Text::SwitchedToUsLayout => "Windows now uses the English (US) layout",
+            Text::SwitchLayoutFailed => "Failed to switch the keyboard layout: {}",
+            Text::TrayUnavailable => "Can't show the tray icon ({}); GhostKeys keeps running without it",
+
+            Text::Banner => "GhostKeys - ABNT2 keyboard layout emulation",
+            Text::UsingDefaults => "{}. Using default settings.",
+            Text::LayoutLoaded => "Loaded custom layout: {}",
+            Text::LayoutSwitched => "Switched to layout {}",
+            Text::LayoutFallback => "{}. Falling back to ABNT2.",
+            Text::LayoutExported => "Exported layout \"{}\" to {}",
+            Text::LayoutExportFailed => "Failed to export layout to {}: {}",
+            Text::ProfileExported => "Exported profile \"{}\" to {}",
+            Text::ProfileImported => "Imported profile \"{}\" into your configuration",
+            Text::ProfileLayoutSaved => {
+                "Its layout was saved to {}. Start GhostKeys with --layout \"{}\" to use it"
+            }
+            Text::Profiles => "Profiles",
+            Text::ProfileSwitched => "Switched to profile {}",
+            Text::NoProfiles => "No profiles to switch to; save .gkprofile files in {}",
+            Text::SafeModeStarted => {
+                "GhostKeys didn't exit cleanly the last {} times, so it started in safe mode \
+                 with the default settings. Check {} and restart GhostKeys to use it again"
+            }
+            Text::SafeModeTitle => "GhostKeys - Safe Mode",
+            Text::SafeModeOpenConfig => {
+                "GhostKeys kept crashing, so it started with the default settings this time.\n\n\
+                 Open the configuration file to look for the cause?"
+            }
+            Text::ConfigValid => "No problems found in {} or the files it refers to",
+            Text::ConfigProblems => {
+                "Problems found: {}. Nothing was applied; fix them before reloading GhostKeys"
+            }
+            Text::SchemaWritten => {
+                "Wrote the config schema to {}. Point your editor at it, e.g. with a \
+                 `#:schema` comment at the top of config.toml"
+            }
+            Text::StatsExported => "Exported statistics to {}",
+            Text::Running => "GhostKeys is running...",
+            Text::RunningWithoutTray => {
+                "GhostKeys is running without a tray icon. Ctrl+Alt+Space pauses and resumes; \
+                 `ghostkeys ctl quit` exits."
+            }
+            Text::Exiting => "Exiting GhostKeys...",
+            Text::TryHeader => {
+                "Type as on an ABNT2 keyboard: keys pressed on the left, what GhostKeys types on \
+                 the right. Ctrl+C quits."
+            }
+            Text::TrainHeader => {
+                "Type each sentence as on an ABNT2 keyboard; the next one appears once it's \
+                 right. Ctrl+C stops."
+            }
+            Text::TrainPrevious => "Last session: {} WPM, {}% accuracy",
+            Text::TrainProgress => "Sentence {} of {}",
+            Text::TrainFinished => "Session done: {} WPM, {}% accuracy",
+            Text::TrainStopped => "Session stopped; nothing was saved.",
+
+            Text::SetupWelcome => {
+                "Welcome to GhostKeys! A few questions to set it up (Enter keeps the default)."
+            }
+            Text::SetupLayout => {
+                "Which keyboard are you typing on? 1) US  2) UK (ISO)  3) Dvorak  4) Colemak [1]: "
+            }
+            Text::SetupTerminals => "Should ' and ~ wait for a vowel in terminals too? [Y/n]: ",
+            Text::SetupAutostart => "Start GhostKeys when you log in? [Y/n]: ",
+            Text::SetupInvalidAnswer => "Please answer with one of the options shown.",
+            Text::SetupSaved => "Settings saved to {}",
+            Text::SetupAutostartFailed => "Couldn't set GhostKeys to start at login: {}",
+            Text::SetupVerify => {
+                "Type here to verify: the key right of L, then ' followed by a, then Enter.\n\
+                 You should see ç and ã: "
+            },
+            Text::SetupVerified => "ç and ã came through: GhostKeys is working!",
+            Text::SetupRetry => {
+                "Got \"{}\" instead of ç and ã. Try again, or press Enter to skip: "
+            }
+            Text::SetupSkipped => {
+                "Skipped. \"Run self-test\" in the tray checks GhostKeys at any time."
+            }
+        }
+    }
+
+    fn portuguese(self) -> &'static str {
+        match self {
+            Text::StatusActive => "GhostKeys: Ativo",
+            Text::StatusPaused => "GhostKeys: Pausado",
+            Text::StatusPausedFor => "GhostKeys: Pausado por {} minutos",
+            Text::Pause => "Pausar",
+            Text::Resume => "Retomar",
+            Text::ReverseMode => "Modo reverso (ABNT2 → US)",
+            Text::RunSelfTest => "Executar autoteste",
+            Text::ModeHistory => "Histórico de modos",
+            Text::SwitchToUsLayout => "Mudar o Windows para o layout US",
+            Text::OpenLogs => "Abrir logs",
+            Text::OpenConfigFolder => "Abrir pasta de configuração",
+            Text::HelpMenu => "Ajuda / Mapeamentos",
+            Text::AboutMenu => "Sobre",
+            Text::Exit => "Sair",
+            Text::TooltipActive => "GhostKeys - Emulação ABNT2 (Ativo)",
+            Text::TooltipPaused => "GhostKeys - Emulação ABNT2 (Pausado)",
+
+            Text::HelpTitle => "GhostKeys - Mapeamento de teclas",
+            Text::HelpBody => {
+                "Mapeamento do GhostKeys (US → ABNT2):\n\n\
+                 [ (ao lado do P) = Acento agudo (´)\n\
+                 Shift + [        = Acento grave (`)\n\
+                 ] (ao lado do [) = [\n\
+                 ' (ao lado do ;) = Til (~)\n\
+                 Shift + '        = Circunflexo (^)\n\
+                 ; (ao lado do L) = ç\n\
+                 / (ao lado do .) = ;\n\n\
+                 Acentos se combinam com vogais:\n\
+                 ´ + a = á    ~ + a = ã    ` + a = à    ^ + a = â"
+            }
+            Text::AboutTitle => "Sobre o GhostKeys",
+            Text::AboutBody => {
+                "GhostKeys v{}\n\n\
+                 Emulação do layout de teclado ABNT2 em teclados US.\n\n\
+                 Criado para o Kiroween 2025\n\n\
+                 https://github.com/mclara/ghostkeys"
+            }
+            Text::HistoryTitle => "GhostKeys - Histórico de modos",
+            Text::PaletteTitle => "GhostKeys - Caracteres",
+            Text::LearningThen => "depois",
+            Text::HelpUnavailable => "A janela de ajuda só está disponível no Windows",
+            Text::AboutUnavailable => "A janela Sobre só está disponível no Windows",
+
+            Text::PausedByOsLayout => "Pausado: o Windows está usando o layout ABNT2",
+            Text::ResumedByOsLayout => "Retomado: o Windows deixou de usar o ABNT2",
+            Text::PausedByKeyboard => "Pausado: teclado {} conectado",
+            Text::ResumedByKeyboard => "Retomado: teclado removido",
+            Text::ResumedByWatchdog => {
+                "Retomado: a regra que pausou o GhostKeys deixou de valer há {} minutos"
+            }
+            Text::RuleUncheckable => {
+                "O GhostKeys foi pausado por uma regra que não pode mais ser verificada; \
+                 retome pela bandeja se ela não valer mais"
+            }
+            Text::SelfTestPassed => {
+                "Autoteste concluído: as teclas são interceptadas e os caracteres digitados"
+            }
+            Text::SelfTestSkipped => "Autoteste ignorado: {}",
+            Text::SelfTestFailed => "Autoteste falhou: {}",
+            Text::InterceptorFailed => "Falha ao iniciar a captura do teclado: {}",
+            Text::OpenLogsFailed => "Falha ao abrir os logs: {}",
+            Text::OpenConfigFolderFailed => "Falha ao abrir a pasta de configuração: {}",
+            Text::SwitchedToUsLayout => "O Windows agora usa o layout Inglês (EUA)",
+            Text::SwitchLayoutFailed => "Falha ao mudar o layout do teclado: {}",
+            Text::TrayUnavailable => {
+                "Não foi possível mostrar o ícone na bandeja ({}); o GhostKeys continua sem ele"
+            }
+
+            Text::Banner => "GhostKeys - emulação do layout de teclado ABNT2",
+            Text::UsingDefaults => "{}. Usando as configurações padrão.",
+            Text::LayoutLoaded => "Layout personalizado carregado: {}",
+            Text::LayoutSwitched => "Layout trocado para {}",
+            Text::LayoutFallback => "{}. Usando o ABNT2.",
+            Text::LayoutExported => "Layout \"{}\" exportado para {}",
+            Text::LayoutExportFailed => "Falha ao exportar o layout para {}: {}",
+            Text::ProfileExported => "Perfil \"{}\" exportado para {}",
+            Text::ProfileImported => "Perfil \"{}\" importado para a sua configuração",
+            Text::ProfileLayoutSaved => {
+                "O layout dele foi salvo em {}. Inicie o GhostKeys com --layout \"{}\" para usá-lo"
+            }
+            Text::Profiles => "Perfis",
+            Text::ProfileSwitched => "Perfil trocado para {}",
+            Text::NoProfiles => "Nenhum perfil para trocar; salve arquivos .gkprofile em {}",
+            Text::SafeModeStarted => {
+                "O GhostKeys não fechou corretamente nas últimas {} vezes, então iniciou em modo \
+                 de segurança com as configurações padrão. Verifique {} e reinicie o GhostKeys \
+                 para usá-lo de novo"
+            }
+            Text::SafeModeTitle => "GhostKeys - Modo de Segurança",
+            Text::SafeModeOpenConfig => {
+                "O GhostKeys continuou travando, então desta vez iniciou com as configurações \
+                 padrão.\n\nAbrir o arquivo de configuração para procurar a causa?"
+            }
+            Text::ConfigValid => "Nenhum problema encontrado em {} ou nos arquivos que ele usa",
+            Text::ConfigProblems => {
+                "Problemas encontrados: {}. Nada foi aplicado; corrija-os antes de recarregar o \
+                 GhostKeys"
+            }
+            Text::SchemaWritten => {
+                "Esquema da configuração salvo em {}. Indique-o ao seu editor, por exemplo com \
+                 um comentário `#:schema` no início do config.toml"
+            }
+            Text::StatsExported => "Estatísticas exportadas para {}",
+            Text::Running => "GhostKeys em execução...",
+            Text::RunningWithoutTray => {
+                "GhostKeys em execução sem ícone na bandeja. Ctrl+Alt+Espaço pausa e retoma; \
+                 `ghostkeys ctl quit` encerra."
+            }
+            Text::Exiting => "Saindo do GhostKeys...",
+            Text::TryHeader => {
+                "Digite como num teclado ABNT2: à esquerda as teclas pressionadas, à direita o que \
+                 o GhostKeys digita. Ctrl+C sai."
+            }
+            Text::TrainHeader => {
+                "Digite cada frase como num teclado ABNT2; a próxima aparece quando ela estiver \
+                 certa. Ctrl+C para."
+            }
+            Text::TrainPrevious => "Sessão anterior: {} PPM, {}% de acerto",
+            Text::TrainProgress => "Frase {} de {}",
+            Text::TrainFinished => "Sessão concluída: {} PPM, {}% de acerto",
+            Text::TrainStopped => "Sessão interrompida; nada foi salvo.",
+
+            Text::SetupWelcome => {
+                "Bem-vindo ao GhostKeys! Algumas perguntas para configurá-lo (Enter mantém o padrão)."
+            }
+            Text::SetupLayout => {
+                "Em qual teclado você está digitando? 1) US  2) UK (ISO)  3) Dvorak  4) Colemak [1]: "
+            }
+            Text::SetupTerminals => "' e ~ também devem esperar uma vogal nos terminais? [S/n]: ",
+            Text::SetupAutostart => "Iniciar o GhostKeys ao entrar no sistema? [S/n]: ",
+            Text::SetupInvalidAnswer => "Responda com uma das opções mostradas.",
+            Text::SetupSaved => "Configurações salvas em {}",
+            Text::SetupAutostartFailed => {
+                "Não foi possível iniciar o GhostKeys ao entrar no sistema: {}"
+            }
+            Text::SetupVerify => {
+                "Digite aqui para verificar: a tecla à direita do L, depois ' seguido de a, e Enter.\n\
+                 Deve aparecer ç e ã: "
+            },
+            Text::SetupVerified => "ç e ã apareceram: o GhostKeys está funcionando!",
+            Text::SetupRetry => {
+                "Apareceu \"{}\" em vez de ç e ã. Tente de novo, ou tecle Enter para pular: "
+            }
+            Text::SetupSkipped => {
+                "Verificação pulada. \"Executar autoteste\" na bandeja verifica o GhostKeys a qualquer momento."
+            }
+        }
+    }
+}
+
+/// `text` in the current language
+pub fn tr(text: Text) -> &'static str {
+    text.get(language())
+}
+
+/// `text` in the current language, with each `{}` replaced by the next of `args`
+pub fn tr_args(text: Text, args: &[&dyn fmt::Display]) -> String {
+    fill(tr(text), args)
+}
+
+/// Replace each `{}` in `template` by the next of `args`
+fn fill(template: &str, args: &[&dyn fmt::Display]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut args = args.iter();
+    let mut parts = template.split("{}");
+    if let Some(first) = parts.next() {
+        out.push_str(first);
+    }
+    for part in parts {
+        if let Some(arg) = args.next() {
+            out.push_str(&arg.to_string());
+        }
+        out.push_str(part);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: &[Text] = &[
+        Text::StatusActive,
+        Text::StatusPaused,
+        Text::StatusPausedFor,
+        Text::Pause,
+        Text::Resume,
+        Text::ReverseMode,
+        Text::RunSelfTest,
+        Text::ModeHistory,
+        Text::SwitchToUsLayout,
+        Text::OpenLogs,
+        Text::OpenConfigFolder,
+        Text::HelpMenu,
+        Text::AboutMenu,
+        Text::Exit,
+        Text::TooltipActive,
+        Text::TooltipPaused,
+        Text::HelpTitle,
+        Text::HelpBody,
+        Text::AboutTitle,
+        Text::AboutBody,
+        Text::HistoryTitle,
+        Text::PaletteTitle,
+        Text::LearningThen,
+        Text::HelpUnavailable,
+        Text::AboutUnavailable,
+        Text::PausedByOsLayout,
+        Text::ResumedByOsLayout,
+        Text::PausedByKeyboard,
+        Text::ResumedByKeyboard,
+        Text::ResumedByWatchdog,
+        Text::RuleUncheckable,
+        Text::SelfTestPassed,
+        Text::SelfTestSkipped,
+        Text::SelfTestFailed,
+        Text::InterceptorFailed,
+        Text::OpenLogsFailed,
+        Text::OpenConfigFolderFailed,
+        Text::SwitchedToUsLayout,
+        Text::SwitchLayoutFailed,
+        Text::TrayUnavailable,
+        Text::Banner,
+        Text::UsingDefaults,
+        Text::LayoutLoaded,
+        Text::LayoutSwitched,
+        Text::LayoutFallback,
+        Text::LayoutExported,
+        Text::LayoutExportFailed,
+        Text::ProfileExported,
+        Text::ProfileImported,
+        Text::ProfileLayoutSaved,
+        Text::Profiles,
+        Text::ProfileSwitched,
+        Text::NoProfiles,
+        Text::SafeModeStarted,
+        Text::SafeModeTitle,
+        Text::SafeModeOpenConfig,
+        Text::ConfigValid,
+        Text::ConfigProblems,
+        Text::SchemaWritten,
+        Text::StatsExported,
+        Text::Running,
+        Text::RunningWithoutTray,
+        Text::Exiting,
+        Text::TryHeader,
+        Text::TrainHeader,
+        Text::TrainPrevious,
+        Text::TrainProgress,
+        Text::TrainFinished,
+        Text::TrainStopped,
+        Text::SetupWelcome,
+        Text::SetupLayout,
+        Text::SetupTerminals,
+        Text::SetupAutostart,
+        Text::SetupInvalidAnswer,
+        Text::SetupSaved,
+        Text::SetupAutostartFailed,
+        Text::SetupVerify,
+        Text::SetupVerified,
+        Text::SetupRetry,
+        Text::SetupSkipped,
+    ];
+
+    #[test]
+    fn test_translations_take_the_same_arguments() {
+        for &text in ALL {
+            let english = text.get(Language::English);
+            let portuguese = text.get(Language::Portuguese);
+            assert!(!portuguese.is_empty(), "{:?}", text);
+            let placeholders = |text: &str| text.matches("{}").count();
+            assert_eq!(placeholders(english), placeholders(portuguese), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn test_from_locale() {
+        assert_eq!(Language::from_locale("pt_BR.UTF-8"), Language::Portuguese);
+        assert_eq!(Language::from_locale("pt-BR"), Language::Portuguese);
+        assert_eq!(Language::from_locale("pt_PT@euro"), Language::Portuguese);
+        assert_eq!(Language::from_locale("en_US.UTF-8"), Language::English);
+        assert_eq!(Language::from_locale("C"), Language::English);
+        assert_eq!(Language::from_locale(""), Language::English);
+    }
+
+    #[test]
+    fn test_fill() {
+        assert_eq!(fill("keyboard {} attached", &[&"Dell"]), "keyboard Dell attached");
+        assert_eq!(fill("{} to {}", &[&"ABNT2", &1]), "ABNT2 to 1");
+        assert_eq!(fill("no arguments", &[]), "no arguments");
+    }
+}
@@ -0,0 +1,398 @@
+//! The tray icon
+//!
+//! The built-in icon is drawn here rather than shipped as an image: a key
+//! cap with an accent over a letter, in dark strokes for a light taskbar or
+//! panel and light strokes for a dark one. `[tray] theme` picks one, and by
+//! default GhostKeys follows the OS, switching when the user does. `[tray]
+//! icon` replaces it with the user's own .ico or .png file, scaled to the
+//! tray's 32x32.
+//!
+//! The state shows as badges drawn over either icon, so it reads at a glance
+//! whatever the colors: while paused the icon is greyed out with pause bars
+//! in the bottom-right corner, a dead key waiting for its letter lights a
+//! dot in the top-right corner, and a warning or error (also in the tooltip)
+//! a red mark in the bottom-left one.
+
+use std::io::Cursor;
+use std::path::Path;
+
+use crate::config::{TrayConfig, TrayTheme};
+use crate::error::{GhostKeysError, Result};
+use crate::paths;
+use crate::platform;
+
+/// Width and height of the tray icon, in pixels
+pub const SIZE: u32 = 32;
+
+/// Colors of the taskbar or panel the icon sits on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Theme {
+    /// Light background: the icon is drawn in dark strokes
+    Light,
+    /// Dark background: the icon is drawn in light strokes
+    Dark,
+}
+
+impl Theme {
+    /// The theme `setting` asks for, asking the OS for `auto`
+    pub fn from_setting(setting: TrayTheme) -> Self {
+        match setting {
+            TrayTheme::Auto => platform::system_theme(),
+            TrayTheme::Light => Theme::Light,
+            TrayTheme::Dark => Theme::Dark,
+        }
+    }
+
+    /// Color of the strokes drawn on this background
+    fn ink(self) -> Rgba {
+        match self {
+            Theme::Light => [32, 32, 32, 255],
+            Theme::Dark => [240, 240, 240, 255],
+        }
+    }
+}
+
+/// What the badges show
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Status {
+    /// GhostKeys is paused
+    pub paused: bool,
+    /// A dead key is waiting for its letter
+    pub accent_pending: bool,
+    /// A warning or error is waiting in the tooltip
+    pub problem: bool,
+}
+
+/// A color, not premultiplied
+type Rgba = [u8; 4];
+
+/// Accent of the built-in icon
+const GREEN: Rgba = [50, 205, 50, 255];
+/// Pause badge
+const AMBER: Rgba = [255, 190, 0, 255];
+/// Pending accent badge
+const BLUE: Rgba = [0, 120, 215, 255];
+/// Problem badge
+const RED: Rgba = [220, 40, 40, 255];
+const WHITE: Rgba = [255, 255, 255, 255];
+const BLACK: Rgba = [0, 0, 0, 255];
+
+/// A `SIZE` x `SIZE` picture, as RGBA bytes row by row
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    rgba: Vec<u8>,
+}
+
+impl Image {
+    /// Fully transparent
+    fn blank() -> Self {
+        Self {
+            rgba: vec![0; (SIZE * SIZE * 4) as usize],
+        }
+    }
+
+    /// The RGBA bytes, as `tray_icon::Icon::from_rgba` takes them
+    pub fn rgba(&self) -> &[u8] {
+        &self.rgba
+    }
+
+    /// Paint `color` over every pixel inside `shape`, a signed distance in
+    /// pixels (negative inside), smoothing the edge over one pixel
+    fn fill(&mut self, color: Rgba, shape: impl Fn(f32, f32) -> f32) {
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let distance = shape(x as f32 + 0.5, y as f32 + 0.5);
+                let coverage = (0.5 - distance).clamp(0.0, 1.0);
+                if coverage > 0.0 {
+                    self.blend(x, y, color, coverage);
+                }
+            }
+        }
+    }
+
+    /// Make every pixel inside `shape` transparent, so a badge stands apart
+    /// from whatever is under it
+    fn cut(&mut self, shape: impl Fn(f32, f32) -> f32) {
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let distance = shape(x as f32 + 0.5, y as f32 + 0.5);
+                let keep = (0.5 + distance).clamp(0.0, 1.0);
+                let alpha = &mut self.rgba[((y * SIZE + x) * 4 + 3) as usize];
+                *alpha = (*alpha as f32 * keep).round() as u8;
+            }
+        }
+    }
+
+    /// Source-over blend of `color` at `coverage` onto a pixel
+    fn blend(&mut self, x: u32, y: u32, color: Rgba, coverage: f32) {
+        let at = ((y * SIZE + x) * 4) as usize;
+        let pixel = &mut self.rgba[at..at + 4];
+        let top = color[3] as f32 / 255.0 * coverage;
+        let bottom = pixel[3] as f32 / 255.0;
+        let alpha = top + bottom * (1.0 - top);
+        if alpha <= 0.0 {
+            return;
+        }
+        for channel in 0..3 {
+            let mixed = color[channel] as f32 * top + pixel[channel] as f32 * bottom * (1.0 - top);
+            pixel[channel] = (mixed / alpha).round() as u8;
+        }
+        pixel[3] = (alpha * 255.0).round() as u8;
+    }
+
+    /// Grey the picture out and fade it, as a paused icon
+    fn grey_out(&mut self) {
+        for pixel in self.rgba.chunks_exact_mut(4) {
+            let luma = 0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32;
+            let luma = luma.round() as u8;
+            pixel[..3].fill(luma);
+            pixel[3] = (pixel[3] as f32 * 0.6).round() as u8;
+        }
+    }
+}
+
+/// The built-in icon for `theme`
+pub fn builtin(theme: Theme) -> Image {
+    let mut image = Image::blank();
+    let ink = theme.ink();
+    // Key cap outline
+    image.fill(ink, |x, y| {
+        rounded_rect(x, y, (3.0, 3.0, 29.0, 29.0), 6.0).abs() - 1.25
+    });
+    // Acute accent over a letter
+    image.fill(GREEN, |x, y| {
+        segment(x, y, (18.5, 8.0), (14.0, 13.5)) - 1.75
+    });
+    image.fill(ink, |x, y| segment(x, y, (11.0, 21.5), (21.0, 21.5)) - 1.75);
+    image
+}
+
+/// The user's icon at `path`, a .ico or .png file, scaled to `SIZE`
+///
+/// From an .ico with several sizes, the smallest one at least `SIZE` wide
+/// is used, or the largest if they're all smaller.
+pub fn load(path: &Path) -> Result<Image> {
+    let failed =
+        |e: std::io::Error| GhostKeysError::TrayError(format!("{}: {}", path.display(), e));
+    let bytes = std::fs::read(path).map_err(failed)?;
+    let image = if bytes.starts_with(b"\x89PNG") {
+        ico::IconImage::read_png(Cursor::new(&bytes)).map_err(failed)?
+    } else {
+        let icons = ico::IconDir::read(Cursor::new(&bytes)).map_err(failed)?;
+        let entries = icons.entries();
+        let best = entries
+            .iter()
+            .filter(|entry| entry.width() >= SIZE)
+            .min_by_key(|entry| entry.width())
+            .or_else(|| entries.iter().max_by_key(|entry| entry.width()));
+        let Some(entry) = best else {
+            return Err(GhostKeysError::TrayError(format!(
+                "{}: the icon file has no images",
+                path.display()
+            )));
+        };
+        entry.decode().map_err(failed)?
+    };
+    Ok(resize(image.rgba_data(), image.width(), image.height()))
+}
+
+/// The icon `config` asks for on a `theme` background
+///
+/// A relative `icon` path is taken from the config folder. If the user's
+/// icon can't be loaded, the built-in one is returned with the error.
+pub fn base(config: &TrayConfig, theme: Theme) -> (Image, Option<GhostKeysError>) {
+    let Some(icon) = &config.icon else {
+        return (builtin(theme), None);
+    };
+    let path = match paths::config_dir() {
+        Some(dir) => dir.join(icon),
+        None => icon.clone(),
+    };
+    match load(&path) {
+        Ok(image) => (image, None),
+        Err(e) => (builtin(theme), Some(e)),
+    }
+}
+
+/// `base` with the badges for `status`
+pub fn render(base: &Image, status: Status) -> Image {
+    let mut image = base.clone();
+    if status.paused {
+        image.grey_out();
+        image.cut(|x, y| circle(x, y, (25.0, 25.0), 8.5));
+        image.fill(AMBER, |x, y| circle(x, y, (25.0, 25.0), 7.0));
+        image.fill(BLACK, |x, y| {
+            rounded_rect(x, y, (21.5, 21.5, 23.5, 28.5), 0.5)
+        });
+        image.fill(BLACK, |x, y| {
+            rounded_rect(x, y, (26.5, 21.5, 28.5, 28.5), 0.5)
+        });
+    }
+    if status.accent_pending {
+        image.cut(|x, y| circle(x, y, (26.0, 6.0), 7.0));
+        image.fill(BLUE, |x, y| circle(x, y, (26.0, 6.0), 5.5));
+    }
+    if status.problem {
+        image.cut(|x, y| circle(x, y, (7.0, 25.0), 8.5));
+        image.fill(RED, |x, y| circle(x, y, (7.0, 25.0), 7.0));
+        image.fill(WHITE, |x, y| segment(x, y, (7.0, 20.5), (7.0, 25.5)) - 1.1);
+        image.fill(WHITE, |x, y| circle(x, y, (7.0, 28.8), 1.2));
+    }
+    image
+}
+
+/// Scale a `width` x `height` RGBA picture to `SIZE` x `SIZE`, averaging the
+/// pixels each one covers
+fn resize(rgba: &[u8], width: u32, height: u32) -> Image {
+    let mut image = Image::blank();
+    if width == 0 || height == 0 {
+        return image;
+    }
+    for y in 0..SIZE {
+        let (top, bottom) = span(y, height);
+        for x in 0..SIZE {
+            let (left, right) = span(x, width);
+            // Premultiplied, so transparent pixels don't darken the edges
+            let mut sum = [0.0f32; 4];
+            for source_y in top..bottom {
+                for source_x in left..right {
+                    let at = ((source_y * width + source_x) * 4) as usize;
+                    let alpha = rgba[at + 3] as f32;
+                    for channel in 0..3 {
+                        sum[channel] += rgba[at + channel] as f32 * alpha;
+                    }
+                    sum[3] += alpha;
+                }
+            }
+            let count = ((bottom - top) * (right - left)) as f32;
+            let at = ((y * SIZE + x) * 4) as usize;
+            if sum[3] > 0.0 {
+                for channel in 0..3 {
+                    image.rgba[at + channel] = (sum[channel] / sum[3]).round() as u8;
+                }
+            }
+            image.rgba[at + 3] = (sum[3] / count).round() as u8;
+        }
+    }
+    image
+}
+
+/// Source pixels `[start, end)` that target pixel `index` covers
+fn span(index: u32, source: u32) -> (u32, u32) {
+    let start = index * source / SIZE;
+    let end = ((index + 1) * source / SIZE).max(start + 1);
+    (start, end.min(source))
+}
+
+/// Signed distance from a point to a circle
+fn circle(x: f32, y: f32, center: (f32, f32), radius: f32) -> f32 {
+    (x - center.0).hypot(y - center.1) - radius
+}
+
+/// Distance from a point to the segment from `a` to `b`
+fn segment(x: f32, y: f32, a: (f32, f32), b: (f32, f32)) -> f32 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let t = (((x - a.0) * dx + (y - a.1) * dy) / (dx * dx + dy * dy)).clamp(0.0, 1.0);
+    (x - a.0 - t * dx).hypot(y - a.1 - t * dy)
+}
+
+/// Signed distance from a point to a rectangle (left, top, right, bottom)
+/// with corners rounded by `radius`
+fn rounded_rect(
+    x: f32,
+    y: f32,
+    (left, top, right, bottom): (f32, f32, f32, f32),
+    radius: f32,
+) -> f32 {
+    let (center_x, center_y) = ((left + right) / 2.0, (top + bottom) / 2.0);
+    let qx = (x - center_x).abs() - ((right - left) / 2.0 - radius);
+    let qy = (y - center_y).abs() - ((bottom - top) / 2.0 - radius);
+    qx.max(0.0).hypot(qy.max(0.0)) + qx.max(qy).min(0.0) - radius
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixel(image: &Image, x: u32, y: u32) -> Rgba {
+        let at = ((y * SIZE + x) * 4) as usize;
+        image.rgba()[at..at + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn test_builtin_follows_the_theme() {
+        let light = builtin(Theme::Light);
+        let dark = builtin(Theme::Dark);
+        assert_eq!(light.rgba().len(), (SIZE * SIZE * 4) as usize);
+        // The letter stroke is dark on a light taskbar and light on a dark one
+        assert_eq!(pixel(&light, 16, 21), Theme::Light.ink());
+        assert_eq!(pixel(&dark, 16, 21), Theme::Dark.ink());
+        // Transparent around the key cap
+        assert_eq!(pixel(&light, 0, 0)[3], 0);
+    }
+
+    #[test]
+    fn test_badges() {
+        let base = builtin(Theme::Dark);
+        assert_eq!(render(&base, Status::default()), base);
+
+        let paused = render(
+            &base,
+            Status {
+                paused: true,
+                ..Status::default()
+            },
+        );
+        assert_eq!(pixel(&paused, 25, 19), AMBER);
+        let letter = pixel(&paused, 12, 21);
+        assert!(letter[0] == letter[1] && letter[1] == letter[2] && letter[3] < 255);
+
+        let accent = render(
+            &base,
+            Status {
+                accent_pending: true,
+                ..Status::default()
+            },
+        );
+        assert_eq!(pixel(&accent, 26, 6), BLUE);
+
+        let problem = render(
+            &base,
+            Status {
+                problem: true,
+                ..Status::default()
+            },
+        );
+        assert_eq!(pixel(&problem, 3, 25), RED);
+        assert_eq!(pixel(&problem, 7, 22), WHITE);
+    }
+
+    #[test]
+    fn test_user_icons_are_scaled() {
+        let dir = std::env::temp_dir().join(format!("ghostkeys-icon-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // 64x64: left half red, right half transparent
+        let rgba: Vec<u8> = (0..64 * 64)
+            .flat_map(|i| if i % 64 < 32 { RED } else { [0; 4] })
+            .collect();
+        let image = ico::IconImage::from_rgba_data(64, 64, rgba);
+        let png = dir.join("icon.png");
+        image
+            .write_png(std::fs::File::create(&png).unwrap())
+            .unwrap();
+        let mut icons = ico::IconDir::new(ico::ResourceType::Icon);
+        icons.add_entry(ico::IconDirEntry::encode(&image).unwrap());
+        let ico = dir.join("icon.ico");
+        icons.write(std::fs::File::create(&ico).unwrap()).unwrap();
+
+        for path in [&png, &ico] {
+            let loaded = load(path).unwrap();
+            assert_eq!(pixel(&loaded, 0, 0), RED);
+            assert_eq!(pixel(&loaded, 15, 31), RED);
+            assert_eq!(pixel(&loaded, 16, 0)[3], 0);
+        }
+        assert!(load(&dir.join("missing.png")).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
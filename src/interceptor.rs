@@ -3,8 +3,16 @@
 //! This module defines the platform-agnostic interface for keyboard interception.
 //! Platform-specific implementations are in the `platform` module.
 
-use crate::error::Result;
-use crate::state::SharedState;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::{GhostKeysError, Result};
+use crate::latency::Sample;
+use crate::layout::Layout;
+use crate::mapper::{ComposeTable, Mapper, VirtualKey};
+use crate::state::{OperationMode, SharedState};
 
 /// Action to take after processing a keystroke
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -17,6 +25,380 @@ pub enum KeyAction {
     Replace(char),
     /// Suppress original and inject multiple characters
     ReplaceMultiple(Vec<char>),
+    /// Suppress original and inject a string from a layout or compose table
+    ReplaceStr(String),
+    /// Inject a character, then let the original keystroke act after it
+    /// (e.g., a pending accent flushed by Enter)
+    ReplaceThenPass(char),
+    /// Inject several characters, then let the original keystroke act after
+    /// them (e.g., chained accents flushed by Enter)
+    ReplaceMultipleThenPass(Vec<char>),
+    /// Suppress original, erase characters before the cursor with backspaces,
+    /// then inject replacement characters (e.g., autocorrect)
+    Rewrite {
+        /// Number of characters to erase
+        backspaces: usize,
+        /// Characters to inject after erasing
+        chars: Vec<char>,
+    },
+    /// Suppress original and press and release a key instead, as a key
+    /// event rather than text (e.g., an arrow from the nav layer or a
+    /// `[remap]` entry); Shift, Alt and AltGr in `Modifiers` are held around
+    /// it, on top of those the user holds, and Caps Lock is left as it is
+    InjectKey(VirtualKey, Modifiers),
+}
+
+impl KeyAction {
+    /// Characters this action puts on screen
+    ///
+    /// `typed` is the character the key produces when the action is `Pass`.
+    /// For `Rewrite`, only the injected characters are returned.
+    pub fn output(&self, typed: Option<char>) -> Vec<char> {
+        match self {
+            KeyAction::Pass => typed.into_iter().collect(),
+            KeyAction::Suppress | KeyAction::InjectKey(..) => Vec::new(),
+            KeyAction::Replace(c) => vec![*c],
+            KeyAction::ReplaceThenPass(c) => std::iter::once(*c).chain(typed).collect(),
+            KeyAction::ReplaceMultipleThenPass(chars) => {
+                chars.iter().copied().chain(typed).collect()
+            }
+            KeyAction::ReplaceMultiple(chars) | KeyAction::Rewrite { chars, .. } => chars.clone(),
+            KeyAction::ReplaceStr(text) => text.chars().collect(),
+        }
+    }
+}
+
+/// Modifier keys held during a key press
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    /// Either Shift key
+    pub shift: bool,
+    /// Either Alt key, including the right Alt acting as AltGr
+    pub alt: bool,
+    /// AltGr (the right Alt on layouts that have one), which types characters
+    pub altgr: bool,
+    /// Caps Lock is on (a toggle, not a held key)
+    pub caps_lock: bool,
+}
+
+/// Whether a key went down or came up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyDirection {
+    /// Pressed, or auto-repeated while held
+    Down,
+    /// Released
+    Up,
+}
+
+/// A key event as the mapper sees it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    /// Key position
+    pub key: VirtualKey,
+    /// Pressed or released
+    pub direction: KeyDirection,
+    /// Modifiers held, and Caps Lock
+    pub modifiers: Modifiers,
+    /// When it happened; a dead key's accent times out from here
+    pub timestamp: Instant,
+    /// An auto-repeat of a key that is still held down
+    pub repeat: bool,
+}
+
+impl KeyEvent {
+    /// A key pressed now with the given modifiers
+    pub fn down(key: VirtualKey, modifiers: Modifiers) -> Self {
+        Self {
+            key,
+            direction: KeyDirection::Down,
+            modifiers,
+            timestamp: Instant::now(),
+            repeat: false,
+        }
+    }
+
+    /// A key released now
+    pub fn up(key: VirtualKey, modifiers: Modifiers) -> Self {
+        Self {
+            direction: KeyDirection::Up,
+            ..Self::down(key, modifiers)
+        }
+    }
+
+    /// A key pressed now with or without Shift, and nothing else held
+    pub fn press(key: VirtualKey, shift: bool) -> Self {
+        Self::down(key, Modifiers { shift, ..Modifiers::default() })
+    }
+}
+
+impl Modifiers {
+    /// Check if the key is part of an Alt chord (menu accelerators, Alt+numpad
+    /// character codes) that applications must receive untouched
+    ///
+    /// AltGr selects characters rather than commands, so it doesn't count.
+    pub fn is_alt_chord(&self) -> bool {
+        self.alt && !self.altgr
+    }
+}
+
+/// Tracks blocked key-downs so the matching key-ups can be blocked too
+///
+/// Applications that see a key-up without its key-down (games, Electron apps,
+/// hold-to-repeat logic) can misbehave. A key-up is blocked only if none of the
+/// key-downs since the previous key-up, auto-repeats included, reached
+/// applications.
+#[derive(Debug, Default)]
+pub struct KeyUpFilter {
+    /// Held keys -> whether any of their key-downs was passed through
+    held: HashMap<u32, bool>,
+}
+
+impl KeyUpFilter {
+    /// Create an empty filter
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the outcome of a key-down (initial press or auto-repeat)
+    pub fn key_down(&mut self, key: u32, blocked: bool) {
+        *self.held.entry(key).or_insert(false) |= !blocked;
+    }
+
+    /// Check if a key is held, i.e. a key-down for it would be an auto-repeat
+    pub fn is_held(&self, key: u32) -> bool {
+        self.held.contains_key(&key)
+    }
+
+    /// Record a key-up; returns true if it must be blocked
+    pub fn key_up(&mut self, key: u32) -> bool {
+        self.held.remove(&key) == Some(false)
+    }
+
+    /// Forget all held keys
+    pub fn clear(&mut self) {
+        self.held.clear();
+    }
+}
+
+/// Replacements an undo can take back
+const REPLACEMENT_LOG_SIZE: usize = 16;
+
+/// A replacement GhostKeys typed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replacement {
+    /// What ended up on screen
+    pub produced: Vec<char>,
+    /// What the keys pressed type without GhostKeys
+    pub original: Vec<char>,
+}
+
+/// Replacements right before the cursor, most recent last, for the undo
+/// hotkey
+///
+/// Only replacements typed one after another are kept: once anything else is
+/// typed or the cursor moves, the earlier ones aren't right before the cursor
+/// anymore and backspacing over them would delete the wrong text.
+#[derive(Debug, Default)]
+pub struct ReplacementLog {
+    /// What the swallowed keys since the last output type (a dead key)
+    pending: Vec<char>,
+    entries: VecDeque<Replacement>,
+}
+
+impl ReplacementLog {
+    /// Create an empty log
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the action decided for a key that types `typed` on its own
+    pub fn record(&mut self, action: &KeyAction, typed: Option<char>) {
+        match action {
+            // The cursor moved away from what was replaced
+            KeyAction::Pass | KeyAction::InjectKey(..) => self.clear(),
+            KeyAction::Suppress => self.pending.extend(typed),
+            _ => {
+                let mut original = std::mem::take(&mut self.pending);
+                original.extend(typed);
+                self.push(Replacement { produced: action.output(typed), original });
+            }
+        }
+    }
+
+    /// Record a replacement worked out elsewhere (an autocorrect rewrite)
+    pub fn push(&mut self, replacement: Replacement) {
+        self.pending.clear();
+        if self.entries.len() == REPLACEMENT_LOG_SIZE {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(replacement);
+    }
+
+    /// Take back the most recent replacement: delete what it produced and
+    /// type the original keys
+    pub fn undo(&mut self) -> Option<KeyAction> {
+        let replacement = self.entries.pop_back()?;
+        self.pending.clear();
+        Some(KeyAction::Rewrite {
+            backspaces: replacement.produced.len(),
+            chars: replacement.original,
+        })
+    }
+
+    /// Forget everything (something else was typed or the cursor moved)
+    pub fn clear(&mut self) {
+        self.pending.clear();
+        self.entries.clear();
+    }
+}
+
+/// Backend that delivers replacement text to the focused application
+///
+/// Platforms can offer several backends, since not every application accepts
+/// every kind of synthetic input.
+pub trait Injector {
+    /// Type characters at the cursor
+    fn inject_chars(&self, chars: &[char]) -> Result<()>;
+
+    /// Type a string at the cursor
+    fn inject_str(&self, text: &str) -> Result<()> {
+        self.inject_chars(&text.chars().collect::<Vec<_>>())
+    }
+
+    /// Erase characters before the cursor
+    fn inject_backspaces(&self, count: usize) -> Result<()>;
+}
+
+/// Injects through another backend one character at a time, pausing between
+/// characters
+///
+/// Remote desktop clients and some Electron apps drop the second of two
+/// characters arriving back to back (the `~x` of an accent that doesn't
+/// combine). Without a delay everything goes straight to the inner backend.
+pub struct PacedInjector<'a> {
+    inner: &'a dyn Injector,
+    delay: Duration,
+}
+
+impl<'a> PacedInjector<'a> {
+    /// Wrap a backend, pausing `delay` between characters
+    pub fn new(inner: &'a dyn Injector, delay: Duration) -> Self {
+        Self { inner, delay }
+    }
+
+    /// Run `inject` for each of `count` items, pausing in between
+    fn paced(&self, count: usize, mut inject: impl FnMut(usize) -> Result<()>) -> Result<()> {
+        for i in 0..count {
+            if i > 0 {
+                thread::sleep(self.delay);
+            }
+            inject(i)?;
+        }
+        Ok(())
+    }
+}
+
+impl Injector for PacedInjector<'_> {
+    fn inject_chars(&self, chars: &[char]) -> Result<()> {
+        if self.delay.is_zero() {
+            return self.inner.inject_chars(chars);
+        }
+        self.paced(chars.len(), |i| self.inner.inject_chars(&chars[i..=i]))
+    }
+
+    fn inject_str(&self, text: &str) -> Result<()> {
+        if self.delay.is_zero() {
+            return self.inner.inject_str(text);
+        }
+        self.inject_chars(&text.chars().collect::<Vec<_>>())
+    }
+
+    fn inject_backspaces(&self, count: usize) -> Result<()> {
+        if self.delay.is_zero() {
+            return self.inner.inject_backspaces(count);
+        }
+        self.paced(count, |_| self.inner.inject_backspaces(1))
+    }
+}
+
+/// Counts injected characters, refusing more than a limit in one second
+///
+/// A safety net against runaway injection (e.g., a macro that triggers
+/// itself): when it trips, the platform pauses GhostKeys so the keyboard works
+/// again, and the count starts over.
+#[derive(Debug, Clone)]
+pub struct InjectionLimiter {
+    /// Characters allowed per second; 0 means no limit
+    limit: u32,
+    /// Start of the current one-second window
+    window_start: Option<Instant>,
+    /// Characters injected in the current window
+    count: u32,
+}
+
+impl InjectionLimiter {
+    /// A limiter allowing `limit` characters per second (0: no limit)
+    pub fn new(limit: u32) -> Self {
+        Self { limit, window_start: None, count: 0 }
+    }
+
+    /// Record `count` characters about to be injected at `now`
+    ///
+    /// Fails with `InjectionFlood` (and starts counting over) when they would
+    /// go over the limit; nothing should be injected then.
+    pub fn check(&mut self, count: usize, now: Instant) -> Result<()> {
+        if self.limit == 0 {
+            return Ok(());
+        }
+        match self.window_start {
+            Some(start) if now.saturating_duration_since(start) < Duration::from_secs(1) => {}
+            _ => {
+                self.window_start = Some(now);
+                self.count = 0;
+            }
+        }
+        self.count = self.count.saturating_add(u32::try_from(count).unwrap_or(u32::MAX));
+        if self.count > self.limit {
+            self.window_start = None;
+            self.count = 0;
+            return Err(GhostKeysError::InjectionFlood { limit: self.limit });
+        }
+        Ok(())
+    }
+}
+
+/// Injects through another backend while an `InjectionLimiter` allows it
+pub struct LimitedInjector<'a> {
+    inner: &'a dyn Injector,
+    limiter: &'a RefCell<InjectionLimiter>,
+}
+
+impl<'a> LimitedInjector<'a> {
+    /// Wrap a backend, counting what it injects against `limiter`
+    pub fn new(inner: &'a dyn Injector, limiter: &'a RefCell<InjectionLimiter>) -> Self {
+        Self { inner, limiter }
+    }
+
+    fn check(&self, count: usize) -> Result<()> {
+        self.limiter.borrow_mut().check(count, Instant::now())
+    }
+}
+
+impl Injector for LimitedInjector<'_> {
+    fn inject_chars(&self, chars: &[char]) -> Result<()> {
+        self.check(chars.len())?;
+        self.inner.inject_chars(chars)
+    }
+
+    fn inject_str(&self, text: &str) -> Result<()> {
+        self.check(text.chars().count())?;
+        self.inner.inject_str(text)
+    }
+
+    fn inject_backspaces(&self, count: usize) -> Result<()> {
+        self.check(count)?;
+        self.inner.inject_backspaces(count)
+    }
 }
 
 /// Platform-agnostic keyboard interceptor trait
@@ -37,6 +419,48 @@ pub trait KeyboardInterceptor: Send {
 
     /// Check if the interceptor is currently running
     fn is_running(&self) -> bool;
+
+    /// Pause or resume remapping without releasing the hook
+    ///
+    /// In `Passthrough` mode every key reaches applications untouched. Fails
+    /// if the interceptor isn't running.
+    fn set_mode(&mut self, mode: OperationMode) -> Result<()>;
+
+    /// Replace the mapping tables without reinstalling the hook
+    ///
+    /// The new mapper takes over from the next key press; an accent pending
+    /// in the old one is dropped. Fails if the interceptor isn't running.
+    fn set_mapper(&mut self, mapper: Mapper) -> Result<()>;
+
+    /// Switch to another layout (`None` for the built-in ABNT2) without
+    /// reinstalling the hook
+    ///
+    /// The layout is stored in the shared state, and the mapper is rebuilt
+    /// from it with the current direction and config.
+    fn update_layout(&mut self, layout: Option<Layout>) -> Result<()>;
+
+    /// Verify that the running hook sees keystrokes and that injected
+    /// characters are delivered, without typing anything into applications
+    fn self_test(&self) -> Result<()>;
+
+    /// Time `samples` test key presses through the running hook, without
+    /// typing anything into applications
+    fn measure_latency(&self, samples: usize) -> Result<Vec<Sample>>;
+}
+
+/// Build a mapper from the layout, direction and config in `state`
+pub fn build_mapper(state: &SharedState) -> Result<Mapper> {
+    let mut mapper = match state.get_layout()? {
+        Some(layout) => Mapper::with_layout(layout),
+        None => Mapper::new(),
+    };
+    mapper.set_direction(state.get_direction()?);
+    let config = state.get_config()?;
+    mapper.set_compose_table(ComposeTable::from(&config.compose.sequences));
+    mapper.set_source_layout(config.source_layout);
+    mapper.set_features(config.features);
+    mapper.set_dead_key_triggers(config.dead_key_triggers());
+    Ok(mapper)
 }
 
 /// Create a platform-specific keyboard interceptor
@@ -56,3 +480,178 @@ pub fn create_interceptor() -> Box<dyn KeyboardInterceptor> {
 pub fn create_interceptor() -> Box<dyn KeyboardInterceptor> {
     compile_error!("Unsupported platform. GhostKeys supports Windows and Linux only.")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocked_down_blocks_up() {
+        let mut filter = KeyUpFilter::new();
+        filter.key_down(0xBA, true);
+        assert!(filter.key_up(0xBA));
+        // The key is no longer held
+        assert!(!filter.key_up(0xBA));
+    }
+
+    #[test]
+    fn test_passed_down_passes_up() {
+        let mut filter = KeyUpFilter::new();
+        filter.key_down(0x41, false);
+        assert!(!filter.key_up(0x41));
+    }
+
+    #[test]
+    fn test_unknown_up_passes() {
+        let mut filter = KeyUpFilter::new();
+        assert!(!filter.key_up(0x41));
+    }
+
+    #[test]
+    fn test_undo_replacements_most_recent_first() {
+        let mut log = ReplacementLog::new();
+        assert_eq!(log.undo(), None);
+
+        // ; -> ç, then the acute dead key and a -> á
+        log.record(&KeyAction::Replace('ç'), Some(';'));
+        log.record(&KeyAction::Suppress, Some('['));
+        log.record(&KeyAction::Replace('á'), Some('a'));
+        assert_eq!(log.undo(), Some(KeyAction::Rewrite { backspaces: 1, chars: vec!['[', 'a'] }));
+        assert_eq!(log.undo(), Some(KeyAction::Rewrite { backspaces: 1, chars: vec![';'] }));
+        assert_eq!(log.undo(), None);
+
+        log.record(&KeyAction::ReplaceThenPass('´'), Some('x'));
+        assert_eq!(log.undo(), Some(KeyAction::Rewrite { backspaces: 2, chars: vec!['x'] }));
+    }
+
+    #[test]
+    fn test_typing_forgets_earlier_replacements() {
+        let mut log = ReplacementLog::new();
+        log.record(&KeyAction::Replace('ç'), Some(';'));
+        log.record(&KeyAction::Pass, Some('a'));
+        assert_eq!(log.undo(), None);
+
+        for _ in 0..REPLACEMENT_LOG_SIZE + 4 {
+            log.record(&KeyAction::Replace('ç'), Some(';'));
+        }
+        assert_eq!(std::iter::from_fn(|| log.undo()).count(), REPLACEMENT_LOG_SIZE);
+    }
+
+    #[test]
+    fn test_auto_repeat_passing_any_down_passes_up() {
+        let mut filter = KeyUpFilter::new();
+        filter.key_down(0xDE, true);
+        filter.key_down(0xDE, false);
+        filter.key_down(0xDE, true);
+        assert!(!filter.key_up(0xDE));
+    }
+
+    #[test]
+    fn test_is_held() {
+        let mut filter = KeyUpFilter::new();
+        assert!(!filter.is_held(0x41));
+        filter.key_down(0x41, false);
+        assert!(filter.is_held(0x41));
+        filter.key_up(0x41);
+        assert!(!filter.is_held(0x41));
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut filter = KeyUpFilter::new();
+        filter.key_down(0xBA, true);
+        filter.clear();
+        assert!(!filter.key_up(0xBA));
+    }
+
+    /// Records what each call to the backend injected
+    #[derive(Default)]
+    struct RecordingInjector {
+        calls: RefCell<Vec<String>>,
+    }
+
+    impl Injector for RecordingInjector {
+        fn inject_chars(&self, chars: &[char]) -> Result<()> {
+            self.calls.borrow_mut().push(chars.iter().collect());
+            Ok(())
+        }
+
+        fn inject_backspaces(&self, count: usize) -> Result<()> {
+            self.calls.borrow_mut().push("\u{8}".repeat(count));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_paced_injector_without_delay_passes_through() {
+        let inner = RecordingInjector::default();
+        let paced = PacedInjector::new(&inner, Duration::ZERO);
+        paced.inject_chars(&['~', 'x']).unwrap();
+        paced.inject_backspaces(2).unwrap();
+        assert_eq!(*inner.calls.borrow(), ["~x", "\u{8}\u{8}"]);
+    }
+
+    #[test]
+    fn test_paced_injector_sends_one_at_a_time() {
+        let inner = RecordingInjector::default();
+        let delay = Duration::from_millis(5);
+        let paced = PacedInjector::new(&inner, delay);
+
+        let started = Instant::now();
+        paced.inject_str("~x!").unwrap();
+        assert!(started.elapsed() >= delay * 2);
+        paced.inject_backspaces(2).unwrap();
+        assert_eq!(*inner.calls.borrow(), ["~", "x", "!", "\u{8}", "\u{8}"]);
+    }
+
+    #[test]
+    fn test_injection_limiter_trips_over_the_limit() {
+        let mut limiter = InjectionLimiter::new(10);
+        let start = Instant::now();
+        assert!(limiter.check(6, start).is_ok());
+        assert!(limiter.check(4, start + Duration::from_millis(500)).is_ok());
+        assert!(matches!(
+            limiter.check(1, start + Duration::from_millis(900)),
+            Err(GhostKeysError::InjectionFlood { limit: 10 })
+        ));
+        // Counting starts over after tripping
+        assert!(limiter.check(10, start + Duration::from_millis(950)).is_ok());
+    }
+
+    #[test]
+    fn test_injection_limiter_counts_per_second() {
+        let mut limiter = InjectionLimiter::new(10);
+        let start = Instant::now();
+        for second in 0..5 {
+            assert!(limiter.check(10, start + Duration::from_secs(second)).is_ok());
+        }
+
+        let mut unlimited = InjectionLimiter::new(0);
+        assert!(unlimited.check(usize::MAX, start).is_ok());
+    }
+
+    #[test]
+    fn test_limited_injector_stops_a_flood() {
+        let inner = RecordingInjector::default();
+        let limiter = RefCell::new(InjectionLimiter::new(3));
+        let limited = LimitedInjector::new(&inner, &limiter);
+        limited.inject_str("ab").unwrap();
+        assert!(limited.inject_chars(&['c', 'd']).is_err());
+        assert_eq!(*inner.calls.borrow(), ["ab"]);
+    }
+
+    #[test]
+    fn test_build_mapper_follows_state() {
+        let state = SharedState::new();
+        assert_eq!(build_mapper(&state).unwrap().active_layout().name, Layout::abnt2().name);
+
+        let mut custom = Layout::abnt2();
+        custom.name = "Custom".to_string();
+        state.set_layout(Some(custom)).unwrap();
+        assert_eq!(build_mapper(&state).unwrap().active_layout().name, "Custom");
+
+        state.set_direction(crate::mapper::Direction::Abnt2ToUs).unwrap();
+        let mapper = build_mapper(&state).unwrap();
+        assert_eq!(mapper.direction(), crate::mapper::Direction::Abnt2ToUs);
+    }
+}
@@ -0,0 +1,309 @@
+//! Control a running GhostKeys from the command line
+//!
+//! `ghostkeys ctl pause|resume|toggle|status|history|rules-test|reload|quit` sends one
+//! command to the running instance, which is the only way (besides the
+//! hotkeys) to control GhostKeys when the tray icon is hidden with
+//! `show_tray = false`. Commands travel over a per-user named pipe on Windows
+//! and a Unix socket in `$XDG_RUNTIME_DIR` elsewhere (see
+//! `paths::ipc_endpoint`); both are only reachable by the same user. A command
+//! is one line; the reply is one line (several for `history` and
+//! `rules-test`) and ends when the connection closes.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::thread;
+
+use crate::config::Config;
+use crate::error::{GhostKeysError, Result};
+use crate::history::{self, Cause};
+use crate::paths;
+use crate::platform;
+use crate::rules::{self, Status};
+use crate::state::{OperationMode, SharedState};
+
+/// A command for the running instance
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// Let keys through unmapped
+    Pause,
+    /// Remap keys again
+    Resume,
+    /// Pause if active, resume if paused
+    Toggle,
+    /// Report the current mode
+    Status,
+    /// Report recent mode and layout changes and their causes
+    History,
+    /// Report which rules apply to the foreground window
+    RulesTest,
+    /// Read the config file again
+    Reload,
+    /// Exit GhostKeys
+    Quit,
+}
+
+impl Command {
+    /// Parse a command name as given on the command line or the wire
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "pause" => Some(Command::Pause),
+            "resume" => Some(Command::Resume),
+            "toggle" => Some(Command::Toggle),
+            "status" => Some(Command::Status),
+            "history" => Some(Command::History),
+            "rules-test" => Some(Command::RulesTest),
+            "reload" => Some(Command::Reload),
+            "quit" | "exit" => Some(Command::Quit),
+            _ => None,
+        }
+    }
+
+    /// Name sent on the wire
+    pub fn name(self) -> &'static str {
+        match self {
+            Command::Pause => "pause",
+            Command::Resume => "resume",
+            Command::Toggle => "toggle",
+            Command::Status => "status",
+            Command::History => "history",
+            Command::RulesTest => "rules-test",
+            Command::Reload => "reload",
+            Command::Quit => "quit",
+        }
+    }
+}
+
+/// Mode as reported to clients
+fn mode_name(mode: OperationMode) -> &'static str {
+    match mode {
+        OperationMode::Active => "active",
+        OperationMode::Passthrough => "paused",
+    }
+}
+
+/// Carry out a command line received from a client and return the reply
+pub fn respond(line: &str, state: &SharedState) -> String {
+    let result = match Command::from_name(line) {
+        Some(Command::Pause) => state
+            .set_mode_because(OperationMode::Passthrough, Cause::Command)
+            .map(|()| "paused"),
+        Some(Command::Resume) => state
+            .set_mode_because(OperationMode::Active, Cause::Command)
+            .map(|()| "active"),
+        Some(Command::Toggle) => state.toggle_mode_because(Cause::Command).map(mode_name),
+        Some(Command::Status) => state.get_mode().map(mode_name),
+        Some(Command::History) => {
+            return match state.history() {
+                Ok(entries) => history::render(&entries).trim_end().to_string(),
+                Err(e) => format!("error: {}", e),
+            }
+        }
+        Some(Command::RulesTest) => {
+            return match rules_test(state) {
+                Ok(report) => report,
+                Err(e) => format!("error: {}", e),
+            }
+        }
+        Some(Command::Reload) => Config::load()
+            .and_then(|config| state.set_config(config))
+            .map(|()| "reloaded"),
+        Some(Command::Quit) => {
+            state.signal_exit();
+            Ok("exiting")
+        }
+        None => return format!("error: unknown command \"{}\"", line.trim()),
+    };
+    match result {
+        Ok(reply) => reply.to_string(),
+        Err(e) => format!("error: {}", e),
+    }
+}
+
+/// What the rules make of the foreground window
+fn rules_test(state: &SharedState) -> Result<String> {
+    let status = Status {
+        mode: state.get_mode()?,
+        cause: state.last_mode_cause()?,
+        direction: state.get_direction()?,
+    };
+    let window = platform::foreground_window();
+    Ok(rules::report(&state.get_config()?, window.as_ref(), &status))
+}
+
+/// Answer one client: read a command line, write the reply line
+fn serve_client(reader: impl std::io::Read, mut writer: impl Write, state: &SharedState) {
+    let mut line = String::new();
+    if BufReader::new(reader).read_line(&mut line).is_ok() {
+        let _ = writeln!(writer, "{}", respond(&line, state));
+    }
+}
+
+/// Listen for commands on a background thread for the life of the process
+#[cfg(unix)]
+pub fn serve(state: SharedState) -> Result<()> {
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    let path = endpoint()?;
+    // A socket left behind by a crash can be replaced; a live one can't
+    if UnixStream::connect(&path).is_ok() {
+        return Err(GhostKeysError::IpcError(format!(
+            "another GhostKeys is already listening on {}",
+            path.display()
+        )));
+    }
+    let _ = std::fs::remove_file(&path);
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    let listener = UnixListener::bind(&path)
+        .map_err(|e| GhostKeysError::IpcError(format!("{}: {}", path.display(), e)))?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            serve_client(&stream, &stream, &state);
+        }
+    });
+    Ok(())
+}
+
+/// Listen for commands on a background thread for the life of the process
+#[cfg(target_os = "windows")]
+pub fn serve(state: SharedState) -> Result<()> {
+    use std::os::windows::io::FromRawHandle;
+    use windows::core::HSTRING;
+    use windows::Win32::Storage::FileSystem::PIPE_ACCESS_DUPLEX;
+    use windows::Win32::System::Pipes::{
+        ConnectNamedPipe, CreateNamedPipeW, PIPE_READMODE_BYTE, PIPE_REJECT_REMOTE_CLIENTS,
+        PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+    };
+
+    let name = HSTRING::from(endpoint()?.as_os_str());
+    let create = move || unsafe {
+        CreateNamedPipeW(
+            &name,
+            PIPE_ACCESS_DUPLEX,
+            PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+            PIPE_UNLIMITED_INSTANCES,
+            512,
+            512,
+            0,
+            None,
+        )
+    };
+    // Create the first instance here so a failure is reported to the caller
+    let first = create();
+    if first.is_invalid() {
+        return Err(GhostKeysError::IpcError(format!(
+            "can't create the named pipe: {}",
+            windows::core::Error::from_win32()
+        )));
+    }
+
+    let first = first.0 as isize;
+    thread::spawn(move || {
+        let mut pipe = windows::Win32::Foundation::HANDLE(first as *mut std::ffi::c_void);
+        loop {
+            // Fails with ERROR_PIPE_CONNECTED if the client was quicker, which is fine
+            let _ = unsafe { ConnectNamedPipe(pipe, None) };
+            let file = unsafe { std::fs::File::from_raw_handle(pipe.0) };
+            serve_client(&file, &file, &state);
+            // Wait until the client has read the reply before closing
+            let _ = file.sync_all();
+            drop(file);
+
+            pipe = create();
+            if pipe.is_invalid() {
+                return;
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Commands can't be received on this platform
+#[cfg(not(any(unix, target_os = "windows")))]
+pub fn serve(_state: SharedState) -> Result<()> {
+    Err(GhostKeysError::NotSupported(crate::error::Platform::current()))
+}
+
+/// Send a command to the running instance and return its reply
+pub fn send(command: Command) -> Result<String> {
+    let path = endpoint()?;
+    let unreachable =
+        |e: std::io::Error| GhostKeysError::IpcError(format!("{}: {}", path.display(), e));
+
+    #[cfg(unix)]
+    let stream = std::os::unix::net::UnixStream::connect(&path).map_err(unreachable)?;
+    #[cfg(not(unix))]
+    let stream = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&path)
+        .map_err(unreachable)?;
+
+    writeln!(&stream, "{}", command.name()).map_err(unreachable)?;
+    let mut reply = String::new();
+    (&stream).read_to_string(&mut reply).map_err(unreachable)?;
+    let reply = reply.trim().to_string();
+    match reply.strip_prefix("error: ") {
+        Some(message) => Err(GhostKeysError::IpcError(message.to_string())),
+        None => Ok(reply),
+    }
+}
+
+/// Path of the pipe or socket, if it can be determined
+fn endpoint() -> Result<std::path::PathBuf> {
+    paths::ipc_endpoint().ok_or_else(|| {
+        GhostKeysError::IpcError("could not determine the runtime directory".to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_names_round_trip() {
+        let all = [
+            Command::Pause,
+            Command::Resume,
+            Command::Toggle,
+            Command::Status,
+            Command::History,
+            Command::RulesTest,
+            Command::Reload,
+            Command::Quit,
+        ];
+        for command in all {
+            assert_eq!(Command::from_name(command.name()), Some(command));
+        }
+        assert_eq!(Command::from_name(" Pause\n"), Some(Command::Pause));
+        assert_eq!(Command::from_name("restart"), None);
+    }
+
+    #[test]
+    fn test_respond() {
+        let state = SharedState::new();
+        assert_eq!(respond("status\n", &state), "active");
+        assert_eq!(respond("pause\n", &state), "paused");
+        assert!(state.is_paused());
+        assert_eq!(respond("toggle\n", &state), "active");
+        assert_eq!(respond("resume\n", &state), "active");
+        let history = respond("history\n", &state);
+        assert_eq!(history.lines().count(), 2);
+        assert!(history.lines().all(|line| line.ends_with("(ghostkeys ctl)")));
+        assert!(respond("rules-test\n", &state).starts_with("Mode: active (ghostkeys ctl)\n"));
+        assert_eq!(respond("dance\n", &state), "error: unknown command \"dance\"");
+
+        assert!(!state.should_exit());
+        assert_eq!(respond("quit\n", &state), "exiting");
+        assert!(state.should_exit());
+    }
+
+    #[test]
+    fn test_serve_client_replies_one_line() {
+        let state = SharedState::new();
+        let mut reply = Vec::new();
+        serve_client("toggle\n".as_bytes(), &mut reply, &state);
+        assert_eq!(reply, b"paused\n");
+    }
+}
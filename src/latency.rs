@@ -0,0 +1,157 @@
+//! `ghostkeys --measure-latency`: how long GhostKeys adds to a key press
+//!
+//! The interceptor injects tagged test keys and times each one through the
+//! hook: the OS delivering it to the hook, the mapper deciding what to do,
+//! and the replacement character arriving back through the input queue.
+//! The test keys and their output are swallowed by the hook, so nothing is
+//! typed into applications. Printing the distribution rather than an average
+//! keeps the occasional slow key press (the one users notice) visible, and
+//! makes regressions comparable across releases.
+
+use std::time::Duration;
+
+/// Key presses measured when no count is given
+pub const DEFAULT_SAMPLES: usize = 200;
+
+/// Time spent in each stage by one test key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    /// From injecting the key to the hook receiving it
+    pub receipt: Duration,
+    /// From the hook receiving the key to the mapper's decision
+    pub decision: Duration,
+    /// From the decision to the injected character reaching the input queue
+    pub injection: Duration,
+}
+
+impl Sample {
+    /// From injecting the key to its replacement arriving
+    pub fn total(&self) -> Duration {
+        self.receipt + self.decision + self.injection
+    }
+}
+
+/// Summary of a set of durations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Distribution {
+    /// Fastest
+    pub min: Duration,
+    /// Half were at least this fast
+    pub median: Duration,
+    /// 90th percentile
+    pub p90: Duration,
+    /// 99th percentile
+    pub p99: Duration,
+    /// Slowest
+    pub max: Duration,
+    /// Average
+    pub mean: Duration,
+}
+
+impl Distribution {
+    /// Summarize `durations`; `None` if there are none
+    pub fn of(durations: impl IntoIterator<Item = Duration>) -> Option<Self> {
+        let mut sorted: Vec<Duration> = durations.into_iter().collect();
+        if sorted.is_empty() {
+            return None;
+        }
+        sorted.sort();
+        // Nearest rank: the smallest value at or above `percent` of them
+        let percentile = |percent: usize| sorted[(sorted.len() * percent).div_ceil(100) - 1];
+        let total: Duration = sorted.iter().sum();
+        Some(Self {
+            min: sorted[0],
+            median: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            max: sorted[sorted.len() - 1],
+            mean: total / sorted.len() as u32,
+        })
+    }
+}
+
+/// A stage's time in a sample
+type Stage = fn(&Sample) -> Duration;
+
+/// Distribution of each stage as a table, in microseconds
+pub fn render(samples: &[Sample]) -> String {
+    let stages: [(&str, Stage); 4] = [
+        ("receipt", |sample| sample.receipt),
+        ("decision", |sample| sample.decision),
+        ("injection", |sample| sample.injection),
+        ("total", Sample::total),
+    ];
+    let mut out = format!(
+        "Latency of {} key presses, in microseconds\n\n{:<10}{:>8}{:>8}{:>8}{:>8}{:>8}{:>8}\n",
+        samples.len(),
+        "stage",
+        "min",
+        "median",
+        "p90",
+        "p99",
+        "max",
+        "mean"
+    );
+    for (name, stage) in stages {
+        let Some(distribution) = Distribution::of(samples.iter().map(stage)) else {
+            continue;
+        };
+        let row = [
+            distribution.min,
+            distribution.median,
+            distribution.p90,
+            distribution.p99,
+            distribution.max,
+            distribution.mean,
+        ];
+        out.push_str(&format!("{:<10}", name));
+        for duration in row {
+            out.push_str(&format!("{:>8}", duration.as_micros()));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn micros(values: impl IntoIterator<Item = u64>) -> Vec<Duration> {
+        values.into_iter().map(Duration::from_micros).collect()
+    }
+
+    #[test]
+    fn test_distribution_percentiles() {
+        let distribution = Distribution::of(micros((1..=100).rev())).unwrap();
+        assert_eq!(distribution.min, Duration::from_micros(1));
+        assert_eq!(distribution.median, Duration::from_micros(50));
+        assert_eq!(distribution.p90, Duration::from_micros(90));
+        assert_eq!(distribution.p99, Duration::from_micros(99));
+        assert_eq!(distribution.max, Duration::from_micros(100));
+        assert_eq!(distribution.mean, Duration::from_nanos(50_500));
+
+        let single = Distribution::of(micros([7])).unwrap();
+        assert_eq!((single.min, single.p99, single.max), (single.mean, single.mean, single.mean));
+        assert_eq!(Distribution::of(Vec::new()), None);
+    }
+
+    #[test]
+    fn test_render() {
+        let sample = |receipt, decision, injection| Sample {
+            receipt: Duration::from_micros(receipt),
+            decision: Duration::from_micros(decision),
+            injection: Duration::from_micros(injection),
+        };
+        let samples = [sample(100, 10, 200), sample(300, 30, 400)];
+        assert_eq!(
+            render(&samples),
+            "Latency of 2 key presses, in microseconds\n\n\
+             stage          min  median     p90     p99     max    mean\n\
+             receipt        100     100     300     300     300     200\n\
+             decision        10      10      30      30      30      20\n\
+             injection      200     200     400     400     400     300\n\
+             total          310     310     730     730     730     520\n"
+        );
+    }
+}
@@ -0,0 +1,590 @@
+//! Microsoft Keyboard Layout Creator (.klc) import and export
+//!
+//! MSKLC saves layouts as UTF-16 text with tab-separated sections. We only read
+//! what `Layout` can represent: the base, Shift and AltGr columns of the
+//! `LAYOUT` section, dead keys marked with `@`, and the `DEADKEY` combination
+//! tables. Everything else (Ctrl columns, dead keys on AltGr, ligatures, key
+//! names) is ignored.
+//!
+//! Export goes the other way: a complete US-based layout with the `Layout`
+//! overrides applied, and `encode` saves it as UTF-16 the way MSKLC does,
+//! ready to be opened there and built into an installer.
+
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::error::{GhostKeysError, Result};
+use crate::layout::{scan_code_to_virtual_key, us_char, Layout};
+use crate::mapper::AccentType;
+
+/// Section keywords that end the current section
+const SECTION_KEYWORDS: &[&str] = &[
+    "KBD",
+    "COPYRIGHT",
+    "COMPANY",
+    "LOCALENAME",
+    "LOCALEID",
+    "VERSION",
+    "ATTRIBUTES",
+    "SHIFTSTATE",
+    "LAYOUT",
+    "LIGATURE",
+    "DEADKEY",
+    "KEYNAME",
+    "KEYNAME_EXT",
+    "KEYNAME_DEAD",
+    "DESCRIPTIONS",
+    "LANGUAGENAMES",
+    "ENDKBD",
+];
+
+/// Shift state column values we import (see the SHIFTSTATE section)
+const SHIFT_STATE_BASE: u8 = 0;
+const SHIFT_STATE_SHIFT: u8 = 1;
+/// Ctrl+Alt, which is AltGr
+const SHIFT_STATE_ALTGR: u8 = 6;
+const SHIFT_STATE_SHIFT_ALTGR: u8 = 7;
+
+/// Section currently being parsed
+enum Section {
+    Other,
+    ShiftState,
+    Layout,
+    DeadKey(AccentType),
+    /// Dead key with an accent we cannot represent; its table is skipped
+    UnsupportedDeadKey,
+}
+
+/// A single LAYOUT cell value
+#[derive(Debug, PartialEq, Eq)]
+enum Cell {
+    None,
+    Char(char),
+    DeadKey(char),
+}
+
+/// US keys written on export: (scan code, VK name, base char, shifted char)
+const US_KEYS: &[(u8, &str, char, char)] = &[
+    (0x29, "OEM_3", '`', '~'),
+    (0x02, "1", '1', '!'),
+    (0x03, "2", '2', '@'),
+    (0x04, "3", '3', '#'),
+    (0x05, "4", '4', '$'),
+    (0x06, "5", '5', '%'),
+    (0x07, "6", '6', '^'),
+    (0x08, "7", '7', '&'),
+    (0x09, "8", '8', '*'),
+    (0x0A, "9", '9', '('),
+    (0x0B, "0", '0', ')'),
+    (0x0C, "OEM_MINUS", '-', '_'),
+    (0x0D, "OEM_PLUS", '=', '+'),
+    (0x10, "Q", 'q', 'Q'),
+    (0x11, "W", 'w', 'W'),
+    (0x12, "E", 'e', 'E'),
+    (0x13, "R", 'r', 'R'),
+    (0x14, "T", 't', 'T'),
+    (0x15, "Y", 'y', 'Y'),
+    (0x16, "U", 'u', 'U'),
+    (0x17, "I", 'i', 'I'),
+    (0x18, "O", 'o', 'O'),
+    (0x19, "P", 'p', 'P'),
+    (0x1A, "OEM_4", '[', '{'),
+    (0x1B, "OEM_6", ']', '}'),
+    (0x1E, "A", 'a', 'A'),
+    (0x1F, "S", 's', 'S'),
+    (0x20, "D", 'd', 'D'),
+    (0x21, "F", 'f', 'F'),
+    (0x22, "G", 'g', 'G'),
+    (0x23, "H", 'h', 'H'),
+    (0x24, "J", 'j', 'J'),
+    (0x25, "K", 'k', 'K'),
+    (0x26, "L", 'l', 'L'),
+    (0x27, "OEM_1", ';', ':'),
+    (0x28, "OEM_7", '\'', '"'),
+    (0x2B, "OEM_5", '\\', '|'),
+    (0x2C, "Z", 'z', 'Z'),
+    (0x2D, "X", 'x', 'X'),
+    (0x2E, "C", 'c', 'C'),
+    (0x2F, "V", 'v', 'V'),
+    (0x30, "B", 'b', 'B'),
+    (0x31, "N", 'n', 'N'),
+    (0x32, "M", 'm', 'M'),
+    (0x33, "OEM_COMMA", ',', '<'),
+    (0x34, "OEM_PERIOD", '.', '>'),
+    (0x35, "OEM_2", '/', '?'),
+    (0x39, "SPACE", ' ', ' '),
+];
+
+/// Accents in the order their DEADKEY tables are written
+const ACCENTS: [AccentType; 4] = [
+    AccentType::Acute,
+    AccentType::Grave,
+    AccentType::Circumflex,
+    AccentType::Tilde,
+];
+
+/// Load a .klc file from disk
+///
+/// Handles both UTF-16 (what MSKLC writes) and UTF-8 encoded files.
+pub fn load(path: &Path) -> Result<Layout> {
+    let bytes = std::fs::read(path).map_err(|e| {
+        GhostKeysError::LayoutParseError(format!("{}: {}", path.display(), e))
+    })?;
+    parse(&decode(&bytes)?)
+}
+
+/// Decode raw file bytes, detecting UTF-16 via its byte order mark
+fn decode(bytes: &[u8]) -> Result<String> {
+    let utf16 = |units: Vec<u16>| {
+        String::from_utf16(&units)
+            .map_err(|_| GhostKeysError::LayoutParseError("invalid UTF-16 text".to_string()))
+    };
+
+    match bytes {
+        [0xFF, 0xFE, rest @ ..] => utf16(
+            rest.chunks_exact(2)
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .collect(),
+        ),
+        [0xFE, 0xFF, rest @ ..] => utf16(
+            rest.chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect(),
+        ),
+        _ => {
+            let text = std::str::from_utf8(bytes)
+                .map_err(|_| GhostKeysError::LayoutParseError("invalid UTF-8 text".to_string()))?;
+            Ok(text.trim_start_matches('\u{FEFF}').to_string())
+        }
+    }
+}
+
+/// Parse the text contents of a .klc file into a `Layout`
+pub fn parse(text: &str) -> Result<Layout> {
+    let mut layout = Layout::new("Imported");
+    let mut section = Section::Other;
+    let mut shift_states: Vec<u8> = Vec::new();
+
+    for (index, raw_line) in text.lines().enumerate() {
+        let line_no = index + 1;
+        let line = strip_comment(raw_line);
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some(&first) = tokens.first() else {
+            continue;
+        };
+
+        if SECTION_KEYWORDS.contains(&first) {
+            section = match first {
+                "KBD" => {
+                    if let Some(name) = tokens.get(1) {
+                        layout.name = name.to_string();
+                    }
+                    Section::Other
+                }
+                "SHIFTSTATE" => Section::ShiftState,
+                "LAYOUT" => Section::Layout,
+                "DEADKEY" => {
+                    let accent_char = tokens
+                        .get(1)
+                        .and_then(|code| parse_hex_char(code))
+                        .ok_or_else(|| parse_error(line_no, "DEADKEY without a valid character"))?;
+                    match AccentType::from_char(accent_char) {
+                        Some(accent) => Section::DeadKey(accent),
+                        None => Section::UnsupportedDeadKey,
+                    }
+                }
+                _ => Section::Other,
+            };
+            continue;
+        }
+
+        match section {
+            Section::ShiftState => {
+                let state = first
+                    .parse()
+                    .map_err(|_| parse_error(line_no, "invalid shift state"))?;
+                shift_states.push(state);
+            }
+            Section::Layout => parse_layout_line(&mut layout, &shift_states, &tokens, line_no)?,
+            Section::DeadKey(accent) => {
+                let (Some(base), Some(combined)) = (
+                    tokens.first().and_then(|code| parse_hex_char(code)),
+                    tokens.get(1).and_then(|code| parse_hex_char(code)),
+                ) else {
+                    return Err(parse_error(line_no, "invalid DEADKEY entry"));
+                };
+                // Only letters combine in the mapper; space/self entries are implicit
+                if base.is_alphabetic() {
+                    layout.combinations.insert((accent, base), combined.to_string());
+                }
+            }
+            Section::Other | Section::UnsupportedDeadKey => {}
+        }
+    }
+
+    Ok(layout)
+}
+
+/// Render a `Layout` as a complete .klc file
+///
+/// Keys the layout does not override keep their US characters, and every
+/// dead key gets a DEADKEY table, if only for the accent typed on its own.
+/// The result is text; `encode` gives the bytes of the file.
+pub fn export(layout: &Layout) -> String {
+    let mut out = String::new();
+    let name = klc_name(&layout.name);
+
+    // Writing to a String cannot fail, so the fmt::Results are ignored
+    let _ = writeln!(out, "KBD\t{}\t\"{} (GhostKeys)\"", name, layout.name);
+    out.push_str("\nCOPYRIGHT\t\"(c) GhostKeys contributors\"\n");
+    out.push_str("\nCOMPANY\t\"GhostKeys\"\n");
+    out.push_str("\nLOCALENAME\t\"pt-BR\"\n");
+    out.push_str("\nLOCALEID\t\"00000416\"\n");
+    out.push_str("\nVERSION\t1.0\n");
+    out.push_str("\nSHIFTSTATE\n\n0\t//Column 4\n1\t//Column 5 : Shft\n");
+    out.push_str("6\t//Column 6 :       Ctrl Alt\n7\t//Column 7 : Shft  Ctrl Alt\n");
+    out.push_str("\nLAYOUT\t\t;an extra '@' at the end is a dead key\n\n");
+
+    for &(scan_code, vk_name, us_base, us_shift) in US_KEYS {
+        let key = scan_code_to_virtual_key(scan_code);
+        let cell = |shift: bool, us: char| -> (char, String) {
+            let key_shift = key.map(|key| (key, shift));
+            if let Some(accent) = key_shift.and_then(|ks| layout.dead_keys.get(&ks)) {
+                let c = accent.to_char();
+                (c, format!("{:04x}@", c as u32))
+            } else {
+                let c = key_shift
+                    .and_then(|ks| layout.positions.get(&ks))
+                    .copied()
+                    .unwrap_or(us);
+                (c, format!("{:04x}", c as u32))
+            }
+        };
+        let (base, base_cell) = cell(false, us_base);
+        let (shifted, shift_cell) = cell(true, us_shift);
+        let altgr_cell = |shift: bool| match key.and_then(|key| layout.altgr.get(&(key, shift))) {
+            Some(&c) => format!("{:04x}", c as u32),
+            None => "-1".to_string(),
+        };
+
+        // Cap flag: Caps Lock behaves like Shift for letter-like keys
+        let cap = u8::from(base.is_alphabetic() && base.to_uppercase().eq(shifted.to_uppercase()));
+
+        let _ = writeln!(
+            out,
+            "{:02x}\t{}\t\t{}\t{}\t{}\t{}\t{}",
+            scan_code,
+            vk_name,
+            cap,
+            base_cell,
+            shift_cell,
+            altgr_cell(false),
+            altgr_cell(true)
+        );
+    }
+
+    for accent in ACCENTS {
+        let mut entries: Vec<(char, char)> = layout
+            .combinations
+            .iter()
+            .filter(|((a, _), _)| *a == accent)
+            // A KLC dead key entry is a single character
+            .filter_map(|((_, base), combined)| {
+                let mut chars = combined.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Some((*base, c)),
+                    _ => None,
+                }
+            })
+            .collect();
+        // MSKLC rejects a dead key without a table
+        let used = layout.dead_keys.values().any(|&dead_key| dead_key == accent);
+        if entries.is_empty() && !used {
+            continue;
+        }
+        entries.sort_unstable();
+
+        let accent_char = accent.to_char() as u32;
+        let _ = writeln!(out, "\nDEADKEY\t{:04x}\n", accent_char);
+        for (base, combined) in entries {
+            let _ = writeln!(out, "{:04x}\t{:04x}", base as u32, combined as u32);
+        }
+        // Accent followed by space produces the accent itself
+        let _ = writeln!(out, "0020\t{:04x}", accent_char);
+    }
+
+    out.push_str("\nENDKBD\n");
+    out
+}
+
+/// Encode an exported layout the way MSKLC saves .klc files: UTF-16LE with a
+/// byte order mark, and CRLF line endings
+pub fn encode(text: &str) -> Vec<u8> {
+    let mut bytes = vec![0xFF, 0xFE];
+    for unit in text.replace('\n', "\r\n").encode_utf16() {
+        bytes.extend_from_slice(&unit.to_le_bytes());
+    }
+    bytes
+}
+
+/// MSKLC names are at most 8 alphanumeric characters
+fn klc_name(name: &str) -> String {
+    let sanitized: String = name
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .take(8)
+        .collect();
+    if sanitized.is_empty() {
+        "ghostkey".to_string()
+    } else {
+        sanitized
+    }
+}
+
+/// Parse one row of the LAYOUT section into position/dead key entries
+fn parse_layout_line(
+    layout: &mut Layout,
+    shift_states: &[u8],
+    tokens: &[&str],
+    line_no: usize,
+) -> Result<()> {
+    // Columns: scan code, VK name, Cap flag, then one cell per shift state
+    if tokens.len() < 3 {
+        return Err(parse_error(line_no, "LAYOUT row has too few columns"));
+    }
+    let scan_code = u8::from_str_radix(tokens[0], 16)
+        .map_err(|_| parse_error(line_no, "invalid scan code"))?;
+    let Some(key) = scan_code_to_virtual_key(scan_code) else {
+        return Ok(());
+    };
+
+    for (state, cell) in shift_states.iter().zip(&tokens[3..]) {
+        let (shift, altgr) = match *state {
+            SHIFT_STATE_BASE => (false, false),
+            SHIFT_STATE_SHIFT => (true, false),
+            SHIFT_STATE_ALTGR => (false, true),
+            SHIFT_STATE_SHIFT_ALTGR => (true, true),
+            _ => continue,
+        };
+
+        match parse_cell(cell).ok_or_else(|| parse_error(line_no, "invalid character cell"))? {
+            Cell::None => {}
+            Cell::Char(c) if altgr => {
+                layout.altgr.insert((key, shift), c);
+            }
+            Cell::DeadKey(_) if altgr => {}
+            Cell::Char(c) => {
+                // Skip cells that just reproduce the US layout
+                if us_char(key, shift) != Some(c) {
+                    layout.positions.insert((key, shift), c);
+                }
+            }
+            Cell::DeadKey(c) => {
+                if let Some(accent) = AccentType::from_char(c) {
+                    layout.dead_keys.insert((key, shift), accent);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Remove `//` comments
+fn strip_comment(line: &str) -> &str {
+    match line.find("//") {
+        Some(pos) => &line[..pos],
+        None => line,
+    }
+}
+
+/// Parse a LAYOUT cell: `-1`, `%%`, a literal character, or a hex code,
+/// optionally followed by `@` for dead keys
+fn parse_cell(cell: &str) -> Option<Cell> {
+    if cell == "-1" || cell == "%%" {
+        return Some(Cell::None);
+    }
+
+    let (value, dead) = match cell.strip_suffix('@') {
+        Some(value) => (value, true),
+        None => (cell, false),
+    };
+
+    let mut chars = value.chars();
+    let c = match (chars.next(), chars.next()) {
+        (Some(c), None) => c,
+        _ => parse_hex_char(value)?,
+    };
+
+    Some(if dead { Cell::DeadKey(c) } else { Cell::Char(c) })
+}
+
+/// Parse a hexadecimal code point such as `00e7`
+fn parse_hex_char(code: &str) -> Option<char> {
+    u32::from_str_radix(code, 16).ok().and_then(char::from_u32)
+}
+
+fn parse_error(line_no: usize, message: &str) -> GhostKeysError {
+    GhostKeysError::LayoutParseError(format!("line {}: {}", line_no, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::VirtualKey;
+
+    /// Trimmed-down excerpt of MSKLC's "Portuguese (Brazil ABNT2)" export
+    const ABNT2_KLC: &str = "\
+KBD\tabnt2\t\"Portuguese (Brazil ABNT2)\"
+
+SHIFTSTATE
+
+0\t//Column 4
+1\t//Column 5 : Shft
+2\t//Column 6 :       Ctrl
+6\t//Column 7 :       Ctrl Alt
+
+LAYOUT\t\t;an extra '@' at the end is a dead key
+
+//SC\tVK_\t\tCap\t0\t1\t2\t6
+//--\t----\t\t----\t----\t----\t----\t----
+
+10\tQ\t\t1\tq\tQ\t-1\t002f\t// LATIN SMALL LETTER Q, LATIN CAPITAL LETTER Q, <none>, SOLIDUS
+1a\tOEM_4\t\t0\t00b4@\t0060@\t-1\t-1\t// ACUTE ACCENT, GRAVE ACCENT
+27\tOEM_1\t\t1\t00e7\t00c7\t-1\t-1\t// LATIN SMALL LETTER C WITH CEDILLA
+28\tOEM_7\t\t0\t007e@\t005e@\t-1\t-1\t// TILDE, CIRCUMFLEX ACCENT
+35\tOEM_2\t\t0\t003b\t003a\t-1\t-1\t// SEMICOLON, COLON
+
+DEADKEY\t00b4
+
+0061\t00e1\t// a -> á
+0041\t00c1\t// A -> Á
+0020\t00b4\t//   -> ´
+
+DEADKEY\t00a8
+
+0061\t00e4\t// a -> ä
+
+ENDKBD
+";
+
+    #[test]
+    fn test_parse_name() {
+        let layout = parse(ABNT2_KLC).unwrap();
+        assert_eq!(layout.name, "abnt2");
+    }
+
+    #[test]
+    fn test_parse_positions_skip_us_identity() {
+        let layout = parse(ABNT2_KLC).unwrap();
+        assert_eq!(layout.positions.get(&(VirtualKey::Semicolon, false)), Some(&'ç'));
+        assert_eq!(layout.positions.get(&(VirtualKey::Semicolon, true)), Some(&'Ç'));
+        assert_eq!(layout.positions.get(&(VirtualKey::Slash, false)), Some(&';'));
+        // q/Q are what US already types at that position
+        assert!(!layout.positions.contains_key(&(VirtualKey::Char('Q'), false)));
+    }
+
+    #[test]
+    fn test_parse_altgr_column() {
+        let layout = parse(ABNT2_KLC).unwrap();
+        assert_eq!(layout.altgr.get(&(VirtualKey::Char('Q'), false)), Some(&'/'));
+        // The Ctrl column is not AltGr
+        assert_eq!(layout.altgr.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_dead_keys() {
+        let layout = parse(ABNT2_KLC).unwrap();
+        assert_eq!(
+            layout.dead_keys.get(&(VirtualKey::LeftBracket, false)),
+            Some(&AccentType::Acute)
+        );
+        assert_eq!(
+            layout.dead_keys.get(&(VirtualKey::Apostrophe, true)),
+            Some(&AccentType::Circumflex)
+        );
+    }
+
+    #[test]
+    fn test_parse_combinations_skip_unsupported_accents() {
+        let layout = parse(ABNT2_KLC).unwrap();
+        let combined = |base| layout.combinations.get(&(AccentType::Acute, base)).map(String::as_str);
+        assert_eq!(combined('a'), Some("á"));
+        assert_eq!(combined('A'), Some("Á"));
+        // Space entries and the diaeresis table are not imported
+        assert_eq!(layout.combinations.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_cell() {
+        assert_eq!(parse_cell("-1"), Some(Cell::None));
+        assert_eq!(parse_cell("q"), Some(Cell::Char('q')));
+        assert_eq!(parse_cell("00e7"), Some(Cell::Char('ç')));
+        assert_eq!(parse_cell("007e@"), Some(Cell::DeadKey('~')));
+        assert_eq!(parse_cell("zz"), None);
+    }
+
+    #[test]
+    fn test_parse_reports_line_numbers() {
+        let err = parse("LAYOUT\nxx\tOEM_1\t0\t00e7\n").unwrap_err();
+        assert!(err.to_string().contains("line 2"));
+    }
+
+    #[test]
+    fn test_export_round_trip() {
+        let original = Layout::abnt2();
+        let exported = parse(&export(&original)).unwrap();
+
+        assert_eq!(exported.name, "ABNT2");
+        assert_eq!(exported.positions, original.positions);
+        assert_eq!(exported.dead_keys, original.dead_keys);
+        assert_eq!(exported.combinations, original.combinations);
+        assert_eq!(exported.altgr, original.altgr);
+    }
+
+    #[test]
+    fn test_export_keeps_us_keys() {
+        let exported = export(&Layout::abnt2());
+        // Digit row untouched, cedilla at the ; position, acute as a dead key
+        assert!(exported.contains("02\t1\t\t0\t0031\t0021"));
+        assert!(exported.contains("27\tOEM_1\t\t1\t00e7\t00c7"));
+        assert!(exported.contains("1a\tOEM_4\t\t0\t00b4@\t0060@\t-1\t-1"));
+        // AltGr symbols, with the ordinals on Shift+AltGr as well
+        assert!(exported.contains("05\t4\t\t0\t0034\t0024\t00a3\t-1"));
+        assert!(exported.contains("1b\tOEM_6\t\t0\t005b\t007b\t00aa\t00aa"));
+    }
+
+    #[test]
+    fn test_export_writes_a_table_for_every_dead_key() {
+        let mut layout = Layout::new("Bare");
+        layout.dead_keys.insert((VirtualKey::Apostrophe, false), AccentType::Tilde);
+        let exported = export(&layout);
+        assert!(exported.contains("\nDEADKEY\t007e\n\n0020\t007e\n"), "{}", exported);
+        assert!(!exported.contains("DEADKEY\t00b4"));
+    }
+
+    #[test]
+    fn test_encode_is_utf16le_with_bom() {
+        let bytes = encode("KBD\tç\n");
+        assert_eq!(&bytes[..4], &[0xFF, 0xFE, b'K', 0]);
+        assert_eq!(decode(&bytes).unwrap(), "KBD\tç\r\n");
+        let saved = decode(&encode(&export(&Layout::abnt2()))).unwrap();
+        assert_eq!(parse(&saved).unwrap().name, "ABNT2");
+    }
+
+    #[test]
+    fn test_klc_name() {
+        assert_eq!(klc_name("ABNT2"), "ABNT2");
+        assert_eq!(klc_name("My Custom Layout"), "MyCustom");
+        assert_eq!(klc_name("---"), "ghostkey");
+    }
+
+    #[test]
+    fn test_decode_utf16le() {
+        let mut bytes = vec![0xFF, 0xFE];
+        for unit in "KBD\tx".encode_utf16() {
+            bytes.extend_from_slice(&unit.to_le_bytes());
+        }
+        assert_eq!(decode(&bytes).unwrap(), "KBD\tx");
+    }
+}
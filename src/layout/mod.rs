@@ -0,0 +1,508 @@
+//! Keyboard layout descriptions
+//!
+//! A `Layout` is the data half of the mapper: which characters each US key
+//! position produces, which positions act as dead keys, and how accents combine
+//! with base characters. The `Mapper` owns the state machine and consults the
+//! active layout for every lookup, so custom layouts (e.g., imported from .klc
+//! files) need no code changes.
+
+pub mod klc;
+pub mod source;
+pub mod xkb;
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::mapper::{AccentType, VirtualKey};
+
+pub use source::SourceLayout;
+
+/// Data-driven description of a keyboard layout
+///
+/// All keys are expressed as US key positions (`VirtualKey`) plus the shift
+/// state, matching the position-based mapping strategy (see ADR 0002).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    /// Human-readable layout name
+    pub name: String,
+    /// Direct position mappings: (key, shift) -> output char
+    pub positions: HashMap<(VirtualKey, bool), char>,
+    /// Characters typed with AltGr held: (key, shift) -> output char
+    pub altgr: HashMap<(VirtualKey, bool), char>,
+    /// Dead key triggers: (key, shift) -> accent
+    pub dead_keys: HashMap<(VirtualKey, bool), AccentType>,
+    /// Accent combinations: (accent, base char) -> combined text
+    pub combinations: HashMap<(AccentType, char), String>,
+}
+
+impl Layout {
+    /// Create an empty layout with the given name
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            positions: HashMap::new(),
+            altgr: HashMap::new(),
+            dead_keys: HashMap::new(),
+            combinations: HashMap::new(),
+        }
+    }
+
+    /// The built-in ABNT2 layout emulated on US hardware
+    /// Based on ABNT2 Positional Mapping Reference Table
+    pub fn abnt2() -> Self {
+        let mut layout = Self::new("ABNT2");
+
+        // ; (next to L) -> ç/Ç (ABNT2 Cedilla Position)
+        layout.positions.insert((VirtualKey::Semicolon, false), 'ç');
+        layout.positions.insert((VirtualKey::Semicolon, true), 'Ç');
+
+        // ] (next to [) -> [/{ (ABNT2 Bracket Key Position)
+        layout.positions.insert((VirtualKey::RightBracket, false), '[');
+        layout.positions.insert((VirtualKey::RightBracket, true), '{');
+
+        // \ (above Enter) -> ]/} (ABNT2 Close Bracket Position)
+        layout.positions.insert((VirtualKey::Backslash, false), ']');
+        layout.positions.insert((VirtualKey::Backslash, true), '}');
+
+        // / (next to .) -> ;/: (ABNT2 Semicolon Position)
+        layout.positions.insert((VirtualKey::Slash, false), ';');
+        layout.positions.insert((VirtualKey::Slash, true), ':');
+
+        // AltGr + ] and AltGr + \ -> ª and º (printed on the ABNT2 bracket
+        // keys); ordinals have no case, so Shift doesn't change them
+        for shift in [false, true] {
+            layout.altgr.insert((VirtualKey::RightBracket, shift), 'ª');
+            layout.altgr.insert((VirtualKey::Backslash, shift), 'º');
+        }
+
+        // The other AltGr symbols printed on ABNT2 keys
+        for (key, symbol) in [
+            (VirtualKey::Digit('1'), '¹'),
+            (VirtualKey::Digit('2'), '²'),
+            (VirtualKey::Digit('3'), '³'),
+            (VirtualKey::Digit('4'), '£'),
+            (VirtualKey::Digit('5'), '¢'),
+            (VirtualKey::Digit('6'), '¬'),
+            (VirtualKey::Equals, '§'),
+            (VirtualKey::Char('E'), '°'),
+            (VirtualKey::Char('C'), '₢'),
+        ] {
+            layout.altgr.insert((key, false), symbol);
+        }
+
+        // ' (next to ;) -> Tilde (~) unshifted, Circumflex (^) shifted
+        layout.dead_keys.insert((VirtualKey::Apostrophe, false), AccentType::Tilde);
+        layout.dead_keys.insert((VirtualKey::Apostrophe, true), AccentType::Circumflex);
+
+        // [ (next to P) -> Acute (´) unshifted, Grave (`) shifted
+        layout.dead_keys.insert((VirtualKey::LeftBracket, false), AccentType::Acute);
+        layout.dead_keys.insert((VirtualKey::LeftBracket, true), AccentType::Grave);
+
+        layout.init_portuguese_combinations();
+        layout
+    }
+
+    /// The inverse (ABNT2 -> US) layout used by reverse mode
+    ///
+    /// The virtual key codes are positional, so each key outputs the character
+    /// printed at that position on a US keyboard. The two ABNT2 dead keys are
+    /// included here as plain characters (dead-key flattening).
+    pub fn us() -> Self {
+        let mut layout = Self::new("US");
+
+        // ç key (next to L) -> ;/:
+        layout.positions.insert((VirtualKey::Semicolon, false), ';');
+        layout.positions.insert((VirtualKey::Semicolon, true), ':');
+
+        // ~^ dead key (next to ç) -> '/"
+        layout.positions.insert((VirtualKey::Apostrophe, false), '\'');
+        layout.positions.insert((VirtualKey::Apostrophe, true), '"');
+
+        // ´` dead key (next to P) -> [/{
+        layout.positions.insert((VirtualKey::LeftBracket, false), '[');
+        layout.positions.insert((VirtualKey::LeftBracket, true), '{');
+
+        // [{ key -> ]/}
+        layout.positions.insert((VirtualKey::RightBracket, false), ']');
+        layout.positions.insert((VirtualKey::RightBracket, true), '}');
+
+        // ]} key (above Enter) -> \/|
+        layout.positions.insert((VirtualKey::Backslash, false), '\\');
+        layout.positions.insert((VirtualKey::Backslash, true), '|');
+
+        // ;: key (next to .) -> //?
+        layout.positions.insert((VirtualKey::Slash, false), '/');
+        layout.positions.insert((VirtualKey::Slash, true), '?');
+
+        // '" key (left of 1) -> `/~
+        layout.positions.insert((VirtualKey::Backquote, false), '`');
+        layout.positions.insert((VirtualKey::Backquote, true), '~');
+
+        // The keys US keyboards don't have type what ABNT2 prints on them,
+        // whatever the OS layout makes of them: \| (next to left Shift) and
+        // /? (next to right Shift)
+        layout.positions.insert((VirtualKey::IntlBackslash, false), '\\');
+        layout.positions.insert((VirtualKey::IntlBackslash, true), '|');
+        layout.positions.insert((VirtualKey::IntlRo, false), '/');
+        layout.positions.insert((VirtualKey::IntlRo, true), '?');
+
+        layout
+    }
+
+    /// Initialize the accent combinations used in Portuguese
+    fn init_portuguese_combinations(&mut self) {
+        // Tilde combinations
+        self.combinations.insert((AccentType::Tilde, 'a'), "ã".into());
+        self.combinations.insert((AccentType::Tilde, 'A'), "Ã".into());
+        self.combinations.insert((AccentType::Tilde, 'o'), "õ".into());
+        self.combinations.insert((AccentType::Tilde, 'O'), "Õ".into());
+        self.combinations.insert((AccentType::Tilde, 'n'), "ñ".into());
+        self.combinations.insert((AccentType::Tilde, 'N'), "Ñ".into());
+
+        // Acute combinations
+        self.combinations.insert((AccentType::Acute, 'a'), "á".into());
+        self.combinations.insert((AccentType::Acute, 'A'), "Á".into());
+        self.combinations.insert((AccentType::Acute, 'e'), "é".into());
+        self.combinations.insert((AccentType::Acute, 'E'), "É".into());
+        self.combinations.insert((AccentType::Acute, 'i'), "í".into());
+        self.combinations.insert((AccentType::Acute, 'I'), "Í".into());
+        self.combinations.insert((AccentType::Acute, 'o'), "ó".into());
+        self.combinations.insert((AccentType::Acute, 'O'), "Ó".into());
+        self.combinations.insert((AccentType::Acute, 'u'), "ú".into());
+        self.combinations.insert((AccentType::Acute, 'U'), "Ú".into());
+
+        // Grave combinations
+        self.combinations.insert((AccentType::Grave, 'a'), "à".into());
+        self.combinations.insert((AccentType::Grave, 'A'), "À".into());
+
+        // Circumflex combinations
+        self.combinations.insert((AccentType::Circumflex, 'a'), "â".into());
+        self.combinations.insert((AccentType::Circumflex, 'A'), "Â".into());
+        self.combinations.insert((AccentType::Circumflex, 'e'), "ê".into());
+        self.combinations.insert((AccentType::Circumflex, 'E'), "Ê".into());
+        self.combinations.insert((AccentType::Circumflex, 'o'), "ô".into());
+        self.combinations.insert((AccentType::Circumflex, 'O'), "Ô".into());
+
+        // y and w, for loanwords and other languages
+        for (base, tilde, acute, grave, circumflex) in [
+            ('y', "ỹ", "ý", "ỳ", "ŷ"),
+            ('Y', "Ỹ", "Ý", "Ỳ", "Ŷ"),
+            ('w', "", "ẃ", "ẁ", "ŵ"),
+            ('W', "", "Ẃ", "Ẁ", "Ŵ"),
+        ] {
+            for (accent, combined) in [
+                (AccentType::Tilde, tilde),
+                (AccentType::Acute, acute),
+                (AccentType::Grave, grave),
+                (AccentType::Circumflex, circumflex),
+            ] {
+                if !combined.is_empty() {
+                    self.combinations.insert((accent, base), combined.into());
+                }
+            }
+        }
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self::abnt2()
+    }
+}
+
+/// Character a plain US layout produces at the given position, if any
+///
+/// Used by importers to skip entries that would just reproduce what the OS
+/// already types, so only real differences get remapped.
+pub fn us_char(key: VirtualKey, shift: bool) -> Option<char> {
+    let (base, shifted) = match key {
+        VirtualKey::Char(c) => (c.to_ascii_lowercase(), c.to_ascii_uppercase()),
+        VirtualKey::Semicolon => (';', ':'),
+        VirtualKey::Apostrophe => ('\'', '"'),
+        VirtualKey::LeftBracket => ('[', '{'),
+        VirtualKey::RightBracket => (']', '}'),
+        VirtualKey::Backslash => ('\\', '|'),
+        VirtualKey::Slash => ('/', '?'),
+        VirtualKey::Minus => ('-', '_'),
+        VirtualKey::Equals => ('=', '+'),
+        VirtualKey::Comma => (',', '<'),
+        VirtualKey::Period => ('.', '>'),
+        VirtualKey::Backquote => ('`', '~'),
+        VirtualKey::Digit(d) => {
+            const SHIFTED: &[u8; 10] = b")!@#$%^&*(";
+            let index = d.to_digit(10)? as usize;
+            (d, SHIFTED[index] as char)
+        }
+        VirtualKey::Space => (' ', ' '),
+        // Enter, Tab and the keys outside the typing block
+        _ => return None,
+    };
+    Some(if shift { shifted } else { base })
+}
+
+/// Key and Shift state that type a character on a US keyboard, if any
+///
+/// The inverse of `us_char`, plus Enter (`\n`) and Tab (`\t`).
+pub fn us_key(c: char) -> Option<(VirtualKey, bool)> {
+    match c {
+        ' ' => Some((VirtualKey::Space, false)),
+        '\n' => Some((VirtualKey::Enter, false)),
+        '\t' => Some((VirtualKey::Tab, false)),
+        _ => KeyPosition::parse(c.encode_utf8(&mut [0; 4]))
+            .map(|position| (position.key, position.shift)),
+    }
+}
+
+/// Keys that type a character on a US keyboard, other than Space
+const CHARACTER_KEYS: &[VirtualKey] = &[
+    VirtualKey::Semicolon,
+    VirtualKey::Apostrophe,
+    VirtualKey::LeftBracket,
+    VirtualKey::RightBracket,
+    VirtualKey::Backslash,
+    VirtualKey::Slash,
+    VirtualKey::Minus,
+    VirtualKey::Equals,
+    VirtualKey::Comma,
+    VirtualKey::Period,
+    VirtualKey::Backquote,
+];
+
+/// A US key position and shift state, as used in the config file
+///
+/// Written as the character a US keyboard types there (`"'"`, `"["`, `"^"`),
+/// or as `"shift+"` and the unshifted character (`"shift+6"` is `"^"`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct KeyPosition {
+    /// US key position
+    pub key: VirtualKey,
+    /// Whether Shift is held
+    pub shift: bool,
+}
+
+impl KeyPosition {
+    /// Parse a key as written in the config file
+    pub fn parse(name: &str) -> Option<Self> {
+        let (shift, name) = match name.get(..6) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("shift+") => (true, &name[6..]),
+            _ => (false, name),
+        };
+        let mut chars = name.chars();
+        let (Some(c), None) = (chars.next(), chars.next()) else {
+            return None;
+        };
+        let letters = ('A'..='Z').map(VirtualKey::Char);
+        let digits = ('0'..='9').map(VirtualKey::Digit);
+        let keys = CHARACTER_KEYS.iter().copied().chain(letters).chain(digits);
+        if shift {
+            keys.filter(|&key| us_char(key, false) == Some(c.to_ascii_lowercase()))
+                .map(|key| Self { key, shift: true })
+                .next()
+        } else {
+            keys.flat_map(|key| [Self { key, shift: false }, Self { key, shift: true }])
+                .find(|position| us_char(position.key, position.shift) == Some(c))
+        }
+    }
+}
+
+impl fmt::Display for KeyPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match us_char(self.key, self.shift) {
+            Some(c) => write!(f, "{}", c),
+            None => write!(f, "{:?}", self.key),
+        }
+    }
+}
+
+impl TryFrom<String> for KeyPosition {
+    type Error = String;
+
+    fn try_from(name: String) -> std::result::Result<Self, Self::Error> {
+        Self::parse(&name).ok_or_else(|| {
+            format!("unknown key \"{}\" (use the character it types, e.g. \"'\" or \"^\")", name)
+        })
+    }
+}
+
+impl From<KeyPosition> for String {
+    fn from(position: KeyPosition) -> Self {
+        position.to_string()
+    }
+}
+
+/// Map a Set 1 scan code to the US key position it represents
+///
+/// Scan codes identify physical keys independently of the OS layout, so this
+/// is how positions are recovered both from .klc files and from live key
+/// events on hosts whose layout isn't plain US.
+pub fn scan_code_to_virtual_key(scan_code: u8) -> Option<VirtualKey> {
+    let key = match scan_code {
+        0x27 => VirtualKey::Semicolon,
+        0x28 => VirtualKey::Apostrophe,
+        0x1A => VirtualKey::LeftBracket,
+        0x1B => VirtualKey::RightBracket,
+        0x2B => VirtualKey::Backslash,
+        0x35 => VirtualKey::Slash,
+        0x0C => VirtualKey::Minus,
+        0x0D => VirtualKey::Equals,
+        0x33 => VirtualKey::Comma,
+        0x34 => VirtualKey::Period,
+        0x39 => VirtualKey::Space,
+        0x1C => VirtualKey::Enter,
+        0x0F => VirtualKey::Tab,
+        0x01 => VirtualKey::Escape,
+        0x29 => VirtualKey::Backquote,
+        0x56 => VirtualKey::IntlBackslash,
+        0x73 => VirtualKey::IntlRo,
+        0x02..=0x0A => VirtualKey::Digit((b'1' + scan_code - 0x02) as char),
+        0x0B => VirtualKey::Digit('0'),
+        _ => {
+            const LETTERS: &[(u8, char)] = &[
+                (0x10, 'Q'), (0x11, 'W'), (0x12, 'E'), (0x13, 'R'), (0x14, 'T'),
+                (0x15, 'Y'), (0x16, 'U'), (0x17, 'I'), (0x18, 'O'), (0x19, 'P'),
+                (0x1E, 'A'), (0x1F, 'S'), (0x20, 'D'), (0x21, 'F'), (0x22, 'G'),
+                (0x23, 'H'), (0x24, 'J'), (0x25, 'K'), (0x26, 'L'), (0x2C, 'Z'),
+                (0x2D, 'X'), (0x2E, 'C'), (0x2F, 'V'), (0x30, 'B'), (0x31, 'N'),
+                (0x32, 'M'),
+            ];
+            let &(_, letter) = LETTERS.iter().find(|(code, _)| *code == scan_code)?;
+            VirtualKey::Char(letter)
+        }
+    };
+    Some(key)
+}
+
+/// Map a Linux key code (evdev, and IBus's) to the US key position it
+/// represents
+///
+/// Key codes are the Set 1 scan codes for the typing block, except the ABNT2
+/// /? key: it's KEY_RO (89), and 0x73 is Volume Up.
+pub fn evdev_code_to_virtual_key(code: u16) -> Option<VirtualKey> {
+    match code {
+        89 => Some(VirtualKey::IntlRo),
+        0x73 => None,
+        code => u8::try_from(code).ok().and_then(scan_code_to_virtual_key),
+    }
+}
+
+/// Set 1 scan code of a key, and whether it's an extended (E0) one
+///
+/// The inverse of `scan_code_to_virtual_key`, plus the navigation block and
+/// the other named keys, for injecting key presses the way the keyboard
+/// sends them. Pause (a sequence of its own), Compose and `Other` have none.
+pub fn virtual_key_to_scan_code(key: VirtualKey) -> Option<(u8, bool)> {
+    let extended = match key {
+        VirtualKey::Backspace => return Some((0x0E, false)),
+        VirtualKey::CapsLock => return Some((0x3A, false)),
+        VirtualKey::ScrollLock => return Some((0x46, false)),
+        VirtualKey::PrintScreen => 0x37,
+        VirtualKey::Home => 0x47,
+        VirtualKey::Up => 0x48,
+        VirtualKey::PageUp => 0x49,
+        VirtualKey::Left => 0x4B,
+        VirtualKey::Right => 0x4D,
+        VirtualKey::End => 0x4F,
+        VirtualKey::Down => 0x50,
+        VirtualKey::PageDown => 0x51,
+        VirtualKey::Insert => 0x52,
+        VirtualKey::Delete => 0x53,
+        VirtualKey::Menu => 0x5D,
+        VirtualKey::Pause | VirtualKey::Compose | VirtualKey::Other => return None,
+        key => {
+            let code = (0x01..=0x73).find(|&code| scan_code_to_virtual_key(code) == Some(key))?;
+            return Some((code, false));
+        }
+    };
+    Some((extended, true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_abnt2_has_no_overlap_between_positions_and_dead_keys() {
+        let layout = Layout::abnt2();
+        for key in layout.dead_keys.keys() {
+            assert!(!layout.positions.contains_key(key));
+        }
+    }
+
+    #[test]
+    fn test_us_layout_has_no_dead_keys() {
+        assert!(Layout::us().dead_keys.is_empty());
+    }
+
+    #[test]
+    fn test_us_layout_flattens_abnt2_only_keys() {
+        let layout = Layout::us();
+        assert_eq!(layout.positions.get(&(VirtualKey::Backquote, false)), Some(&'`'));
+        assert_eq!(layout.positions.get(&(VirtualKey::Backquote, true)), Some(&'~'));
+        assert_eq!(layout.positions.get(&(VirtualKey::IntlBackslash, false)), Some(&'\\'));
+        assert_eq!(layout.positions.get(&(VirtualKey::IntlRo, true)), Some(&'?'));
+        assert_eq!(scan_code_to_virtual_key(0x56), Some(VirtualKey::IntlBackslash));
+        assert_eq!(scan_code_to_virtual_key(0x73), Some(VirtualKey::IntlRo));
+    }
+
+    #[test]
+    fn test_scan_code_to_virtual_key() {
+        // The key next to L, whatever the OS layout calls it
+        assert_eq!(scan_code_to_virtual_key(0x27), Some(VirtualKey::Semicolon));
+        assert_eq!(scan_code_to_virtual_key(0x10), Some(VirtualKey::Char('Q')));
+        assert_eq!(scan_code_to_virtual_key(0x1C), Some(VirtualKey::Enter));
+        assert_eq!(scan_code_to_virtual_key(0x02), Some(VirtualKey::Digit('1')));
+        assert_eq!(scan_code_to_virtual_key(0x0B), Some(VirtualKey::Digit('0')));
+        // Function keys aren't remapped positions
+        assert_eq!(scan_code_to_virtual_key(0x3B), None);
+        // The ABNT2 /? key has a code of its own on Linux
+        assert_eq!(evdev_code_to_virtual_key(89), Some(VirtualKey::IntlRo));
+        assert_eq!(evdev_code_to_virtual_key(0x73), None);
+        assert_eq!(evdev_code_to_virtual_key(0x56), Some(VirtualKey::IntlBackslash));
+    }
+
+    #[test]
+    fn test_virtual_key_to_scan_code() {
+        for code in 0x01..=0x39 {
+            if let Some(key) = scan_code_to_virtual_key(code) {
+                assert_eq!(virtual_key_to_scan_code(key), Some((code, false)));
+            }
+        }
+        assert_eq!(virtual_key_to_scan_code(VirtualKey::Left), Some((0x4B, true)));
+        assert_eq!(virtual_key_to_scan_code(VirtualKey::Backspace), Some((0x0E, false)));
+        assert_eq!(virtual_key_to_scan_code(VirtualKey::Compose), None);
+    }
+
+    #[test]
+    fn test_key_position() {
+        let key = |key, shift| Some(KeyPosition { key, shift });
+        assert_eq!(KeyPosition::parse("'"), key(VirtualKey::Apostrophe, false));
+        assert_eq!(KeyPosition::parse("\""), key(VirtualKey::Apostrophe, true));
+        assert_eq!(KeyPosition::parse("^"), key(VirtualKey::Digit('6'), true));
+        assert_eq!(KeyPosition::parse("Shift+6"), key(VirtualKey::Digit('6'), true));
+        assert_eq!(KeyPosition::parse("shift+q"), key(VirtualKey::Char('Q'), true));
+        assert_eq!(KeyPosition::parse("q"), key(VirtualKey::Char('Q'), false));
+        assert_eq!(KeyPosition::parse("ç"), None);
+        assert_eq!(KeyPosition::parse(" "), None);
+        assert_eq!(KeyPosition::parse("ab"), None);
+
+        // Written back the way a US keyboard labels it
+        let circumflex = KeyPosition::parse("shift+6").unwrap();
+        assert_eq!(String::from(circumflex), "^");
+        assert_eq!(KeyPosition::try_from(String::from(circumflex)), Ok(circumflex));
+    }
+
+    #[test]
+    fn test_us_char() {
+        assert_eq!(us_char(VirtualKey::Semicolon, false), Some(';'));
+        assert_eq!(us_char(VirtualKey::Char('A'), false), Some('a'));
+        assert_eq!(us_char(VirtualKey::Char('a'), true), Some('A'));
+        assert_eq!(us_char(VirtualKey::Minus, true), Some('_'));
+        assert_eq!(us_char(VirtualKey::Digit('6'), true), Some('^'));
+        assert_eq!(us_char(VirtualKey::Digit('0'), true), Some(')'));
+        assert_eq!(us_char(VirtualKey::Backquote, false), Some('`'));
+        assert_eq!(us_char(VirtualKey::Other, false), None);
+    }
+}
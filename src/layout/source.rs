@@ -0,0 +1,141 @@
+//! Physical source layouts
+//!
+//! The position map assumes the keyboard under the user's fingers is a US
+//! ANSI board whose keys send the scan codes printed on them. Hardware-remapped
+//! Dvorak and Colemak boards send the scan code of the *label* instead, so
+//! before the position map runs, each reported key is translated back to the
+//! US position it physically sits at. The ABNT2 targets then land where the
+//! user's muscle memory expects them.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::mapper::VirtualKey;
+
+/// Physical layout of the keyboard GhostKeys is remapping
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceLayout {
+    /// US ANSI keyboard (no translation)
+    #[default]
+    Us,
+    /// UK/ISO keyboard
+    ///
+    /// ISO boards report the same scan codes as ANSI ones for every remapped
+    /// key, and their tall Enter already puts the `#` key where ABNT2 has `]`,
+    /// so positions are used as-is.
+    UkIso,
+    /// Keyboard whose keys send Dvorak characters
+    Dvorak,
+    /// Keyboard whose keys send Colemak characters
+    Colemak,
+}
+
+/// Dvorak: key reported -> US position it sits at
+const DVORAK: &[(VirtualKey, VirtualKey)] = &[
+    (VirtualKey::LeftBracket, VirtualKey::Minus),
+    (VirtualKey::RightBracket, VirtualKey::Equals),
+    (VirtualKey::Apostrophe, VirtualKey::Char('Q')),
+    (VirtualKey::Comma, VirtualKey::Char('W')),
+    (VirtualKey::Period, VirtualKey::Char('E')),
+    (VirtualKey::Char('P'), VirtualKey::Char('R')),
+    (VirtualKey::Char('Y'), VirtualKey::Char('T')),
+    (VirtualKey::Char('F'), VirtualKey::Char('Y')),
+    (VirtualKey::Char('G'), VirtualKey::Char('U')),
+    (VirtualKey::Char('C'), VirtualKey::Char('I')),
+    (VirtualKey::Char('R'), VirtualKey::Char('O')),
+    (VirtualKey::Char('L'), VirtualKey::Char('P')),
+    (VirtualKey::Slash, VirtualKey::LeftBracket),
+    (VirtualKey::Equals, VirtualKey::RightBracket),
+    (VirtualKey::Char('O'), VirtualKey::Char('S')),
+    (VirtualKey::Char('E'), VirtualKey::Char('D')),
+    (VirtualKey::Char('U'), VirtualKey::Char('F')),
+    (VirtualKey::Char('I'), VirtualKey::Char('G')),
+    (VirtualKey::Char('D'), VirtualKey::Char('H')),
+    (VirtualKey::Char('H'), VirtualKey::Char('J')),
+    (VirtualKey::Char('T'), VirtualKey::Char('K')),
+    (VirtualKey::Char('N'), VirtualKey::Char('L')),
+    (VirtualKey::Char('S'), VirtualKey::Semicolon),
+    (VirtualKey::Minus, VirtualKey::Apostrophe),
+    (VirtualKey::Semicolon, VirtualKey::Char('Z')),
+    (VirtualKey::Char('Q'), VirtualKey::Char('X')),
+    (VirtualKey::Char('J'), VirtualKey::Char('C')),
+    (VirtualKey::Char('K'), VirtualKey::Char('V')),
+    (VirtualKey::Char('X'), VirtualKey::Char('B')),
+    (VirtualKey::Char('B'), VirtualKey::Char('N')),
+    (VirtualKey::Char('W'), VirtualKey::Comma),
+    (VirtualKey::Char('V'), VirtualKey::Period),
+    (VirtualKey::Char('Z'), VirtualKey::Slash),
+];
+
+/// Colemak: key reported -> US position it sits at
+const COLEMAK: &[(VirtualKey, VirtualKey)] = &[
+    (VirtualKey::Char('F'), VirtualKey::Char('E')),
+    (VirtualKey::Char('P'), VirtualKey::Char('R')),
+    (VirtualKey::Char('G'), VirtualKey::Char('T')),
+    (VirtualKey::Char('J'), VirtualKey::Char('Y')),
+    (VirtualKey::Char('L'), VirtualKey::Char('U')),
+    (VirtualKey::Char('U'), VirtualKey::Char('I')),
+    (VirtualKey::Char('Y'), VirtualKey::Char('O')),
+    (VirtualKey::Semicolon, VirtualKey::Char('P')),
+    (VirtualKey::Char('R'), VirtualKey::Char('S')),
+    (VirtualKey::Char('S'), VirtualKey::Char('D')),
+    (VirtualKey::Char('T'), VirtualKey::Char('F')),
+    (VirtualKey::Char('D'), VirtualKey::Char('G')),
+    (VirtualKey::Char('N'), VirtualKey::Char('J')),
+    (VirtualKey::Char('E'), VirtualKey::Char('K')),
+    (VirtualKey::Char('I'), VirtualKey::Char('L')),
+    (VirtualKey::Char('O'), VirtualKey::Semicolon),
+    (VirtualKey::Char('K'), VirtualKey::Char('N')),
+];
+
+impl SourceLayout {
+    /// US position of the physical key that reported `key`
+    ///
+    /// Keys the source layout doesn't move are returned unchanged.
+    pub fn position(self, key: VirtualKey) -> VirtualKey {
+        let table = match self {
+            SourceLayout::Us | SourceLayout::UkIso => return key,
+            SourceLayout::Dvorak => DVORAK,
+            SourceLayout::Colemak => COLEMAK,
+        };
+        table
+            .iter()
+            .find(|(reported, _)| *reported == key)
+            .map_or(key, |&(_, position)| position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_us_and_iso_are_identity() {
+        for key in [VirtualKey::Semicolon, VirtualKey::Char('S'), VirtualKey::Backslash] {
+            assert_eq!(SourceLayout::Us.position(key), key);
+            assert_eq!(SourceLayout::UkIso.position(key), key);
+        }
+    }
+
+    #[test]
+    fn test_dvorak_and_colemak_find_the_semicolon_position() {
+        // The key right of L, where ABNT2 has ç
+        assert_eq!(SourceLayout::Dvorak.position(VirtualKey::Char('S')), VirtualKey::Semicolon);
+        assert_eq!(SourceLayout::Colemak.position(VirtualKey::Char('O')), VirtualKey::Semicolon);
+        // Keys that stay put
+        assert_eq!(SourceLayout::Dvorak.position(VirtualKey::Char('A')), VirtualKey::Char('A'));
+        assert_eq!(SourceLayout::Colemak.position(VirtualKey::Char('Q')), VirtualKey::Char('Q'));
+    }
+
+    #[test]
+    fn test_tables_are_permutations() {
+        for table in [DVORAK, COLEMAK] {
+            let mut reported: Vec<_> = table.iter().map(|(key, _)| format!("{:?}", key)).collect();
+            let mut positions: Vec<_> = table.iter().map(|(_, key)| format!("{:?}", key)).collect();
+            reported.sort();
+            positions.sort();
+            assert_eq!(reported, positions);
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! XKB symbols export
+//!
+//! Generates a partial `xkb_symbols` block that includes `us(basic)` and
+//! overrides only the keys the `Layout` changes, so Linux users can install
+//! what GhostKeys was emulating as a native layout.
+//!
+//! XKB symbols files have no place for dead key combinations; those come from
+//! the system Compose table, which already covers the Portuguese accents.
+//! The AltGr layer becomes levels 3 and 4, with Right Alt as AltGr.
+
+use std::fmt::Write;
+
+use crate::layout::{us_char, Layout};
+use crate::mapper::{AccentType, VirtualKey};
+
+/// Key positions we can express, with their XKB key names
+const KEY_NAMES: &[(VirtualKey, &str)] = &[
+    (VirtualKey::Digit('1'), "AE01"),
+    (VirtualKey::Digit('2'), "AE02"),
+    (VirtualKey::Digit('3'), "AE03"),
+    (VirtualKey::Digit('4'), "AE04"),
+    (VirtualKey::Digit('5'), "AE05"),
+    (VirtualKey::Digit('6'), "AE06"),
+    (VirtualKey::Digit('7'), "AE07"),
+    (VirtualKey::Digit('8'), "AE08"),
+    (VirtualKey::Digit('9'), "AE09"),
+    (VirtualKey::Digit('0'), "AE10"),
+    (VirtualKey::Minus, "AE11"),
+    (VirtualKey::Equals, "AE12"),
+    (VirtualKey::Char('Q'), "AD01"),
+    (VirtualKey::Char('W'), "AD02"),
+    (VirtualKey::Char('E'), "AD03"),
+    (VirtualKey::Char('R'), "AD04"),
+    (VirtualKey::Char('T'), "AD05"),
+    (VirtualKey::Char('Y'), "AD06"),
+    (VirtualKey::Char('U'), "AD07"),
+    (VirtualKey::Char('I'), "AD08"),
+    (VirtualKey::Char('O'), "AD09"),
+    (VirtualKey::Char('P'), "AD10"),
+    (VirtualKey::LeftBracket, "AD11"),
+    (VirtualKey::RightBracket, "AD12"),
+    (VirtualKey::Char('A'), "AC01"),
+    (VirtualKey::Char('S'), "AC02"),
+    (VirtualKey::Char('D'), "AC03"),
+    (VirtualKey::Char('F'), "AC04"),
+    (VirtualKey::Char('G'), "AC05"),
+    (VirtualKey::Char('H'), "AC06"),
+    (VirtualKey::Char('J'), "AC07"),
+    (VirtualKey::Char('K'), "AC08"),
+    (VirtualKey::Char('L'), "AC09"),
+    (VirtualKey::Semicolon, "AC10"),
+    (VirtualKey::Apostrophe, "AC11"),
+    (VirtualKey::Backslash, "BKSL"),
+    (VirtualKey::Char('Z'), "AB01"),
+    (VirtualKey::Char('X'), "AB02"),
+    (VirtualKey::Char('C'), "AB03"),
+    (VirtualKey::Char('V'), "AB04"),
+    (VirtualKey::Char('B'), "AB05"),
+    (VirtualKey::Char('N'), "AB06"),
+    (VirtualKey::Char('M'), "AB07"),
+    (VirtualKey::Comma, "AB08"),
+    (VirtualKey::Period, "AB09"),
+    (VirtualKey::Slash, "AB10"),
+    (VirtualKey::Space, "SPCE"),
+];
+
+/// Render a `Layout` as an XKB symbols file
+pub fn export(layout: &Layout) -> String {
+    let mut out = String::new();
+
+    // Writing to a String cannot fail, so the fmt::Results are ignored
+    let _ = writeln!(out, "// Generated by GhostKeys from layout \"{}\"", layout.name);
+    out.push_str("// Install under /usr/share/X11/xkb/symbols/ or ~/.config/xkb/symbols/\n\n");
+    out.push_str("default partial alphanumeric_keys\n");
+    out.push_str("xkb_symbols \"ghostkeys\" {\n");
+    out.push_str("    include \"us(basic)\"\n");
+    if !layout.altgr.is_empty() {
+        out.push_str("    include \"level3(ralt_switch)\"\n");
+    }
+    let _ = writeln!(out, "    name[Group1] = \"{} (GhostKeys)\";\n", layout.name);
+
+    for &(key, name) in KEY_NAMES {
+        let changed = [false, true].iter().any(|&shift| {
+            layout.dead_keys.contains_key(&(key, shift))
+                || layout.positions.contains_key(&(key, shift))
+                || layout.altgr.contains_key(&(key, shift))
+        });
+        if !changed {
+            continue;
+        }
+
+        let level = |shift: bool| -> String {
+            if let Some(&accent) = layout.dead_keys.get(&(key, shift)) {
+                return dead_keysym(accent).to_string();
+            }
+            match layout.positions.get(&(key, shift)).copied().or_else(|| us_char(key, shift)) {
+                Some(c) => keysym(c),
+                None => "NoSymbol".to_string(),
+            }
+        };
+
+        let altgr = |shift: bool| match layout.altgr.get(&(key, shift)) {
+            Some(&c) => keysym(c),
+            None => "NoSymbol".to_string(),
+        };
+        if [false, true].iter().any(|&shift| layout.altgr.contains_key(&(key, shift))) {
+            let _ = writeln!(
+                out,
+                "    key <{}> {{ [ {}, {}, {}, {} ] }};",
+                name,
+                level(false),
+                level(true),
+                altgr(false),
+                altgr(true)
+            );
+        } else {
+            let _ = writeln!(out, "    key <{}> {{ [ {}, {} ] }};", name, level(false), level(true));
+        }
+    }
+
+    out.push_str("};\n");
+    out
+}
+
+/// XKB dead keysym for an accent
+fn dead_keysym(accent: AccentType) -> &'static str {
+    match accent {
+        AccentType::Tilde => "dead_tilde",
+        AccentType::Acute => "dead_acute",
+        AccentType::Grave => "dead_grave",
+        AccentType::Circumflex => "dead_circumflex",
+    }
+}
+
+/// XKB keysym for a character
+///
+/// Alphanumerics and common punctuation use their symbolic names for
+/// readability; everything else uses the `Uxxxx` Unicode form.
+fn keysym(c: char) -> String {
+    let name = match c {
+        ' ' => "space",
+        ';' => "semicolon",
+        ':' => "colon",
+        '\'' => "apostrophe",
+        '"' => "quotedbl",
+        '[' => "bracketleft",
+        ']' => "bracketright",
+        '{' => "braceleft",
+        '}' => "braceright",
+        '\\' => "backslash",
+        '|' => "bar",
+        '/' => "slash",
+        '?' => "question",
+        '-' => "minus",
+        '_' => "underscore",
+        '=' => "equal",
+        '+' => "plus",
+        ',' => "comma",
+        '<' => "less",
+        '.' => "period",
+        '>' => "greater",
+        c if c.is_ascii_alphanumeric() => return c.to_string(),
+        c => return format!("U{:04X}", c as u32),
+    };
+    name.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_abnt2() {
+        let exported = export(&Layout::abnt2());
+        assert!(exported.contains("include \"us(basic)\""));
+        assert!(exported.contains("key <AC10> { [ U00E7, U00C7 ] };"));
+        assert!(exported.contains("key <AC11> { [ dead_tilde, dead_circumflex ] };"));
+        assert!(exported.contains("key <AD11> { [ dead_acute, dead_grave ] };"));
+        assert!(exported.contains("key <AB10> { [ semicolon, colon ] };"));
+        assert!(exported.contains("include \"level3(ralt_switch)\""));
+        assert!(exported.contains("key <AE04> { [ 4, U0024, U00A3, NoSymbol ] };"));
+        assert!(exported.contains("key <AD12> { [ bracketleft, braceleft, U00AA, U00AA ] };"));
+        // Keys the layout leaves alone come from us(basic)
+        assert!(!exported.contains("<AD01>"));
+    }
+
+    #[test]
+    fn test_partial_override_fills_other_level_from_us() {
+        let mut layout = Layout::new("Custom");
+        layout.positions.insert((VirtualKey::Char('Q'), true), '@');
+        let exported = export(&layout);
+        assert!(exported.contains("key <AD01> { [ q, U0040 ] };"));
+    }
+
+    #[test]
+    fn test_keysym() {
+        assert_eq!(keysym('a'), "a");
+        assert_eq!(keysym('ç'), "U00E7");
+        assert_eq!(keysym(';'), "semicolon");
+    }
+}
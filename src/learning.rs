@@ -0,0 +1,174 @@
+//! Learning mode: which US keys typed each remapped character
+//!
+//! With `enabled = true` under `[learning]`, every character GhostKeys types
+//! in place of a key flashes on screen with the keys that produced it
+//! ("; → ç", "' then a → ã"), so new users pick up where ABNT2 characters
+//! live on a US keyboard. `KeyTrail` follows the mapper's actions and
+//! builds those lessons; the platform decides how to show them.
+
+use std::fmt;
+
+use crate::i18n::{tr, Text};
+use crate::interceptor::{KeyAction, KeyEvent};
+use crate::layout::us_char;
+use crate::mapper::VirtualKey;
+
+/// Keys kept for a single lesson; a compose sequence longer than this is
+/// named by its last keys
+const MAX_KEYS: usize = 8;
+
+/// Keys pressed and the characters they typed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lesson {
+    /// Keys in the order pressed, as printed on a US keyboard
+    pub keys: Vec<String>,
+    /// What GhostKeys typed for them
+    pub output: String,
+}
+
+impl fmt::Display for Lesson {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let then = format!(" {} ", tr(Text::LearningThen));
+        write!(f, "{} → {}", self.keys.join(&then), self.output)
+    }
+}
+
+/// The keys of a dead key or compose sequence still waiting for its end
+#[derive(Debug, Default)]
+pub struct KeyTrail {
+    keys: Vec<String>,
+}
+
+impl KeyTrail {
+    /// Start with no keys
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Follow a key press and what the mapper did with it; returns the lesson
+    /// once the keys pressed so far typed something of their own
+    pub fn observe(&mut self, event: &KeyEvent, action: &KeyAction) -> Option<Lesson> {
+        match action {
+            // Typed as on a US keyboard: nothing to learn
+            KeyAction::Pass | KeyAction::Rewrite { .. } | KeyAction::InjectKey(..) => {
+                self.reset();
+                None
+            }
+            // A dead key or compose sequence waiting for more keys
+            KeyAction::Suppress => {
+                if self.keys.len() == MAX_KEYS {
+                    self.keys.remove(0);
+                }
+                self.keys.push(key_label(event));
+                None
+            }
+            _ => {
+                let mut keys = std::mem::take(&mut self.keys);
+                keys.push(key_label(event));
+                let output: String = action.output(None).into_iter().collect();
+                (!output.is_empty()).then_some(Lesson { keys, output })
+            }
+        }
+    }
+
+    /// Forget the keys of an unfinished sequence
+    pub fn reset(&mut self) {
+        self.keys.clear();
+    }
+}
+
+/// A key as printed on a US keyboard, with the modifiers that matter
+fn key_label(event: &KeyEvent) -> String {
+    let modifiers = event.modifiers;
+    let key = match event.key {
+        VirtualKey::Space => "Space".to_string(),
+        VirtualKey::Enter => "Enter".to_string(),
+        VirtualKey::Tab => "Tab".to_string(),
+        VirtualKey::Escape => "Esc".to_string(),
+        VirtualKey::Compose => "Compose".to_string(),
+        // Letters show their case; other keys show Shift
+        VirtualKey::Char(c) if modifiers.shift => c.to_ascii_uppercase().to_string(),
+        key => us_char(key, false).map_or_else(|| "?".to_string(), String::from),
+    };
+    let shift = modifiers.shift && !matches!(event.key, VirtualKey::Char(_));
+    match (modifiers.altgr, shift) {
+        (true, true) => format!("AltGr+Shift+{}", key),
+        (true, false) => format!("AltGr+{}", key),
+        (false, true) => format!("Shift+{}", key),
+        (false, false) => key,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::Mapper;
+
+    fn press(
+        mapper: &mut Mapper,
+        trail: &mut KeyTrail,
+        key: VirtualKey,
+        shift: bool,
+    ) -> Option<Lesson> {
+        let event = KeyEvent::press(key, shift);
+        let action = mapper.process_key(event);
+        trail.observe(&event, &action)
+    }
+
+    fn lesson(keys: &[&str], output: &str) -> Option<Lesson> {
+        Some(Lesson {
+            keys: keys.iter().map(|key| key.to_string()).collect(),
+            output: output.into(),
+        })
+    }
+
+    #[test]
+    fn test_remapped_key() {
+        let mut mapper = Mapper::new();
+        let mut trail = KeyTrail::new();
+        assert_eq!(
+            press(&mut mapper, &mut trail, VirtualKey::Semicolon, false),
+            lesson(&[";"], "ç")
+        );
+        assert_eq!(
+            press(&mut mapper, &mut trail, VirtualKey::Semicolon, true),
+            lesson(&["Shift+;"], "Ç")
+        );
+        assert_eq!(
+            press(&mut mapper, &mut trail, VirtualKey::Char('a'), false),
+            None
+        );
+    }
+
+    #[test]
+    fn test_dead_key_then_letter() {
+        let mut mapper = Mapper::new();
+        let mut trail = KeyTrail::new();
+        assert_eq!(
+            press(&mut mapper, &mut trail, VirtualKey::Apostrophe, false),
+            None
+        );
+        assert_eq!(
+            press(&mut mapper, &mut trail, VirtualKey::Char('a'), true),
+            lesson(&["'", "A"], "Ã")
+        );
+        // The trail starts over after each lesson
+        assert_eq!(
+            press(&mut mapper, &mut trail, VirtualKey::Semicolon, false),
+            lesson(&[";"], "ç")
+        );
+    }
+
+    #[test]
+    fn test_reset_forgets_pending_keys() {
+        let mut mapper = Mapper::new();
+        let mut trail = KeyTrail::new();
+        press(&mut mapper, &mut trail, VirtualKey::Apostrophe, false);
+        trail.reset();
+        mapper.reset();
+        assert_eq!(
+            press(&mut mapper, &mut trail, VirtualKey::Semicolon, false),
+            lesson(&[";"], "ç")
+        );
+    }
+}
@@ -3,14 +3,61 @@
 //! This library provides the core functionality for intercepting keyboard input
 //! and translating US key positions to ABNT2 characters.
 
+#[cfg(feature = "tokio")]
+pub mod async_interceptor;
+pub mod autocorrect;
+pub mod check;
+pub mod config;
+pub mod conflicts;
+pub mod crash;
+pub mod doctor;
 pub mod error;
+pub mod events;
+pub mod flags;
+pub mod handoff;
+pub mod history;
+pub mod hook_context;
+pub mod hotkeys;
+pub mod i18n;
+pub mod icon;
 pub mod interceptor;
+pub mod ipc;
+pub mod latency;
+pub mod layout;
+pub mod learning;
+pub mod macros;
+pub mod nav;
+pub mod notify;
+pub mod numpad;
+pub mod onboarding;
+pub mod os_layout;
 pub mod mapper;
+pub mod palette;
+pub mod paths;
+pub mod pipeline;
 pub mod platform;
+pub mod profile;
+pub mod remap;
+pub mod rules;
+pub mod safe_mode;
+pub mod session;
 pub mod state;
+pub mod stats;
+pub mod statemachine;
+pub mod supervisor;
+pub mod trainer;
+pub mod tutor;
+pub mod watchdog;
 
 // Re-export commonly used types
+#[cfg(feature = "tokio")]
+pub use async_interceptor::AsyncInterceptor;
+pub use config::Config;
 pub use error::{GhostKeysError, Result};
-pub use interceptor::{KeyAction, KeyboardInterceptor};
-pub use mapper::{AccentType, Mapper, MapperState, VirtualKey};
-pub use state::{OperationMode, SharedState};
+pub use interceptor::{
+    Injector, KeyAction, KeyDirection, KeyEvent, KeyboardInterceptor, Modifiers,
+};
+pub use layout::Layout;
+pub use mapper::{AccentType, ComposeTable, Direction, Mapper, MapperState, VirtualKey};
+pub use pipeline::{KeyInput, KeyMiddleware, Pipeline, PipelineBuilder, Step};
+pub use state::{OperationMode, SharedState, StateChange};
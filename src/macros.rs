@@ -0,0 +1,128 @@
+//! Macro recording and playback
+//!
+//! A macro is the text GhostKeys put on screen while recording, so it captures
+//! mapped output (ç, accented vowels, compose results) rather than raw key
+//! positions. Macros are stored in the `[macros.slots]` config table, keyed by
+//! the function key that replays them ("f1" through "f12").
+
+use crate::interceptor::KeyAction;
+
+/// Number of macro slots (one per function key)
+pub const SLOT_COUNT: u8 = 12;
+
+/// Config key for a slot number (1-based)
+pub fn slot_name(slot: u8) -> String {
+    format!("f{}", slot)
+}
+
+/// Records the output of keystrokes into a slot
+#[derive(Debug, Default)]
+pub struct MacroRecorder {
+    /// Slot being recorded and the text captured so far
+    recording: Option<(u8, Vec<char>)>,
+}
+
+impl MacroRecorder {
+    /// Create an idle recorder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Slot currently being recorded, if any
+    pub fn recording_slot(&self) -> Option<u8> {
+        self.recording.as_ref().map(|(slot, _)| *slot)
+    }
+
+    /// Start recording into a slot, discarding any unfinished recording
+    pub fn start(&mut self, slot: u8) {
+        self.recording = Some((slot, Vec::new()));
+    }
+
+    /// Stop recording, returning the slot and the captured text
+    pub fn stop(&mut self) -> Option<(u8, String)> {
+        self.recording
+            .take()
+            .map(|(slot, chars)| (slot, chars.into_iter().collect()))
+    }
+
+    /// Append a character typed outside the mapper (Enter, Tab)
+    pub fn push(&mut self, c: char) {
+        if let Some((_, chars)) = &mut self.recording {
+            chars.push(c);
+        }
+    }
+
+    /// Account for a backspace typed by the user
+    pub fn backspace(&mut self) {
+        if let Some((_, chars)) = &mut self.recording {
+            chars.pop();
+        }
+    }
+
+    /// Capture what an action puts on screen
+    ///
+    /// `typed` is the character the key produces when the action is `Pass`.
+    pub fn record(&mut self, action: &KeyAction, typed: Option<char>) {
+        let Some((_, chars)) = &mut self.recording else {
+            return;
+        };
+        if let KeyAction::Rewrite { backspaces, .. } = action {
+            chars.truncate(chars.len().saturating_sub(*backspaces));
+        }
+        chars.extend(action.output(typed));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idle_recorder_ignores_input() {
+        let mut recorder = MacroRecorder::new();
+        recorder.record(&KeyAction::Pass, Some('a'));
+        recorder.push('\n');
+        assert_eq!(recorder.stop(), None);
+    }
+
+    #[test]
+    fn test_records_mapped_output() {
+        let mut recorder = MacroRecorder::new();
+        recorder.start(3);
+        assert_eq!(recorder.recording_slot(), Some(3));
+
+        recorder.record(&KeyAction::Pass, Some('a'));
+        recorder.record(&KeyAction::Replace('ç'), None);
+        recorder.record(&KeyAction::Suppress, None);
+        recorder.record(&KeyAction::ReplaceMultiple(vec!['~', 'x']), None);
+        recorder.push('\n');
+
+        assert_eq!(recorder.stop(), Some((3, "aç~x\n".to_string())));
+        assert_eq!(recorder.recording_slot(), None);
+    }
+
+    #[test]
+    fn test_backspace_and_rewrite_edit_recording() {
+        let mut recorder = MacroRecorder::new();
+        recorder.start(1);
+        for c in "naox".chars() {
+            recorder.record(&KeyAction::Pass, Some(c));
+        }
+        recorder.backspace();
+        recorder.record(
+            &KeyAction::Rewrite {
+                backspaces: 3,
+                chars: "não ".chars().collect(),
+            },
+            Some(' '),
+        );
+
+        assert_eq!(recorder.stop(), Some((1, "não ".to_string())));
+    }
+
+    #[test]
+    fn test_slot_name() {
+        assert_eq!(slot_name(1), "f1");
+        assert_eq!(slot_name(SLOT_COUNT), "f12");
+    }
+}
@@ -4,41 +4,107 @@
 //! to ABNT2 characters, allowing users with ABNT2 muscle memory to type
 //! Portuguese naturally on US hardware.
 
+mod autocorrect;
+mod check;
+mod config;
+mod conflicts;
+mod crash;
+mod doctor;
 mod error;
+mod events;
+mod flags;
+mod handoff;
+mod history;
+mod hook_context;
+mod hotkeys;
+mod i18n;
+mod icon;
 mod interceptor;
+mod ipc;
+mod latency;
+mod layout;
+mod learning;
+mod macros;
+mod nav;
+mod notify;
+mod numpad;
+mod onboarding;
+mod os_layout;
 mod mapper;
+// Only the Windows palette window uses it
+#[cfg(target_os = "windows")]
+mod palette;
+mod paths;
+mod pipeline;
 mod platform;
+mod profile;
+mod remap;
+mod rules;
+mod safe_mode;
+mod session;
 mod state;
+mod stats;
+mod statemachine;
+mod supervisor;
+mod trainer;
+mod tutor;
+mod watchdog;
 
-use interceptor::create_interceptor;
-use state::SharedState;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use error::GhostKeysError;
+use i18n::{tr, tr_args, Text};
+use interceptor::{create_interceptor, KeyboardInterceptor};
+use mapper::Direction;
+use state::{OperationMode, SharedState, StateChange};
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
 use tao::event::{Event, StartCause};
 use tao::event_loop::{ControlFlow, EventLoopBuilder};
 use tray_icon::{
-    menu::{Menu, MenuEvent, MenuItem},
-    TrayIconBuilder,
+    menu::{CheckMenuItem, Menu, MenuEvent, MenuItem, Submenu},
+    TrayIcon, TrayIconBuilder,
 };
 
+/// How often the tray icon checks whether the OS switched between light and
+/// dark
+const THEME_CHECK: Duration = Duration::from_secs(5);
+
 /// Sets up a panic handler that releases the keyboard hook on panic.
 /// This prevents the user's keyboard from being "frozen" if the app crashes.
-fn setup_panic_handler() {
+/// Crashes that don't unwind are caught by an unhandled-exception filter on
+/// Windows; both end in `crash::handle_crash`.
+fn setup_panic_handler(state: &SharedState) {
+    crash::install(state.clone());
+
     let original_hook = std::panic::take_hook();
     std::panic::set_hook(Box::new(move |panic_info| {
         eprintln!("GhostKeys panic detected! Releasing keyboard hook...");
         
         // Release the keyboard hook to restore normal keyboard operation
         #[cfg(target_os = "windows")]
-        {
+        let mapper_state = {
             platform::windows::release_hook_on_panic();
-        }
+            platform::windows::mapper_snapshot()
+        };
+        #[cfg(not(target_os = "windows"))]
+        let mapper_state = None;
         
         // Call the original panic handler
         original_hook(panic_info);
+
+        crash::handle_crash(&panic_info.to_string(), mapper_state);
+
+        // A panic on any thread ends GhostKeys: with the hook released nothing
+        // is remapped anymore, and the supervisor only restarts what exits
+        std::process::exit(crash::EXIT_CODE);
     }));
+
+    #[cfg(target_os = "windows")]
+    platform::windows::install_crash_filter();
 }
 
 /// Shows a native Windows message box with the key mappings help
@@ -47,19 +113,8 @@ fn show_help_dialog() {
     use windows::core::PCWSTR;
     use windows::Win32::UI::WindowsAndMessaging::{MessageBoxW, MB_ICONINFORMATION, MB_OK};
     
-    let title: Vec<u16> = "GhostKeys - Key Mappings\0".encode_utf16().collect();
-    let content: Vec<u16> = "GhostKeys Mappings (US → ABNT2):\n\n\
-        [ (next to P) = Acute Accent (´)\n\
-        Shift + [     = Grave Accent (`)\n\
-        ] (next to [) = [\n\
-        ' (next to ;) = Tilde (~)\n\
-        Shift + '     = Circumflex (^)\n\
-        ; (next to L) = ç\n\
-        / (next to .) = ;\n\n\
-        Dead keys combine with vowels:\n\
-        ´ + a = á    ~ + a = ã    ` + a = à    ^ + a = â\0"
-        .encode_utf16()
-        .collect();
+    let title: Vec<u16> = tr(Text::HelpTitle).encode_utf16().chain([0]).collect();
+    let content: Vec<u16> = tr(Text::HelpBody).encode_utf16().chain([0]).collect();
     
     unsafe {
         MessageBoxW(
@@ -73,7 +128,7 @@ fn show_help_dialog() {
 
 #[cfg(not(target_os = "windows"))]
 fn show_help_dialog() {
-    println!("Help dialog is only available on Windows");
+    println!("{}", tr(Text::HelpUnavailable));
 }
 
 /// Shows a native Windows message box with about information
@@ -82,12 +137,10 @@ fn show_about_dialog() {
     use windows::core::PCWSTR;
     use windows::Win32::UI::WindowsAndMessaging::{MessageBoxW, MB_ICONINFORMATION, MB_OK};
     
-    let title: Vec<u16> = "About GhostKeys\0".encode_utf16().collect();
-    let content: Vec<u16> = "GhostKeys v0.1.0\n\n\
-        ABNT2 keyboard layout emulation on US keyboards.\n\n\
-        Created for Kiroween 2025\n\n\
-        https://github.com/mclara/ghostkeys\0"
+    let title: Vec<u16> = tr(Text::AboutTitle).encode_utf16().chain([0]).collect();
+    let content: Vec<u16> = tr_args(Text::AboutBody, &[&env!("CARGO_PKG_VERSION")])
         .encode_utf16()
+        .chain([0])
         .collect();
     
     unsafe {
@@ -102,119 +155,996 @@ fn show_about_dialog() {
 
 #[cfg(not(target_os = "windows"))]
 fn show_about_dialog() {
-    println!("About dialog is only available on Windows");
-}
-
-/// Creates a simple 32x32 colored icon as RGBA bytes
-fn create_icon_rgba(active: bool) -> Vec<u8> {
-    let mut rgba = Vec::with_capacity(32 * 32 * 4);
-    for y in 0..32 {
-        for x in 0..32 {
-            let is_border = x < 2 || x >= 30 || y < 2 || y >= 30;
-            if is_border {
-                // Border color
-                if active {
-                    rgba.extend_from_slice(&[0, 100, 0, 255]); // Dark green
-                } else {
-                    rgba.extend_from_slice(&[100, 100, 0, 255]); // Dark yellow
+    println!("{}", tr(Text::AboutUnavailable));
+}
+
+/// Shows a native Windows message box with recent mode and layout changes
+#[cfg(target_os = "windows")]
+fn show_history_dialog(state: &SharedState) {
+    use windows::core::PCWSTR;
+    use windows::Win32::UI::WindowsAndMessaging::{MessageBoxW, MB_ICONINFORMATION, MB_OK};
+
+    let history = state.history().map(|entries| history::render(&entries));
+    let body = history.unwrap_or_else(|e| e.to_string());
+    let title: Vec<u16> = tr(Text::HistoryTitle).encode_utf16().chain([0]).collect();
+    let content: Vec<u16> = body.encode_utf16().chain([0]).collect();
+
+    unsafe {
+        MessageBoxW(
+            None,
+            PCWSTR::from_raw(content.as_ptr()),
+            PCWSTR::from_raw(title.as_ptr()),
+            MB_OK | MB_ICONINFORMATION,
+        );
+    }
+}
+
+/// Prints recent mode and layout changes to the console
+#[cfg(not(target_os = "windows"))]
+fn show_history_dialog(state: &SharedState) {
+    match state.history() {
+        Ok(entries) => print!("{}", history::render(&entries)),
+        Err(e) => print_error(&e),
+    }
+}
+
+/// Switches the OS to the plain US layout and reports the outcome through the
+/// notification pipeline
+fn switch_os_layout(state: &SharedState) {
+    let (severity, message) = match platform::switch_to_us_layout() {
+        Ok(()) => (notify::Severity::Info, tr(Text::SwitchedToUsLayout).to_string()),
+        Err(e) => (notify::Severity::Warning, tr_args(Text::SwitchLayoutFailed, &[&e])),
+    };
+    let _ = state.notify(severity, message);
+}
+
+/// Switches to a saved profile picked in the tray, rebuilds the mapper with
+/// its layout and reports the outcome through the notification pipeline
+fn switch_profile(state: &SharedState, interceptor: &Mutex<Box<dyn KeyboardInterceptor>>, name: &str) {
+    let result = profile::switch(state, name).and_then(|layout| {
+        interceptor
+            .lock()
+            .map_err(|_| GhostKeysError::StateLockPoisoned)
+            .and_then(|mut interceptor| interceptor.update_layout(layout))
+    });
+    let (severity, message) = match result {
+        Ok(()) => (notify::Severity::Info, tr_args(Text::ProfileSwitched, &[&name])),
+        Err(e) => (notify::Severity::Warning, e.to_string()),
+    };
+    let _ = state.notify(severity, message);
+}
+
+/// Checks the tray item of the active profile and unchecks the others
+fn check_active_profile(state: &SharedState, items: &[(CheckMenuItem, String)]) {
+    let active = state.get_config().ok().and_then(|config| config.profiles.active);
+    for (item, name) in items {
+        item.set_checked(active.as_deref() == Some(name.as_str()));
+    }
+}
+
+/// Runs the interceptor self-test on a worker thread and reports the outcome
+/// through the notification pipeline
+fn spawn_self_test(interceptor: Arc<Mutex<Box<dyn KeyboardInterceptor>>>, state: SharedState) {
+    thread::spawn(move || {
+        let result = interceptor
+            .lock()
+            .map_err(|_| GhostKeysError::StateLockPoisoned)
+            .and_then(|interceptor| interceptor.self_test());
+        let (severity, message) = match result {
+            Ok(()) => (notify::Severity::Info, tr(Text::SelfTestPassed).to_string()),
+            Err(e @ GhostKeysError::NotSupported(_)) => {
+                (notify::Severity::Info, tr_args(Text::SelfTestSkipped, &[&e]))
+            }
+            Err(e) => {
+                let message = match e.hint() {
+                    Some(hint) => tr_args(Text::SelfTestFailed, &[&format!("{}. {}", e, hint)]),
+                    None => tr_args(Text::SelfTestFailed, &[&e]),
+                };
+                (notify::Severity::Error, message)
+            }
+        };
+        let _ = state.notify(severity, message);
+    });
+}
+
+/// Shows a GhostKeys file or folder in the file manager, creating it first
+/// so there's always something to show (e.g., before anything was logged)
+fn open_in_file_manager(path: Option<PathBuf>, folder: bool) -> std::io::Result<()> {
+    let path = path.ok_or_else(|| {
+        let message = "could not determine the config directory";
+        std::io::Error::new(std::io::ErrorKind::NotFound, message)
+    })?;
+    if folder {
+        std::fs::create_dir_all(&path)?;
+    } else {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::OpenOptions::new().create(true).append(true).open(&path)?;
+    }
+    platform::show_in_file_manager(&path)
+}
+
+/// Load the config, the active profile's layout, the last session and
+/// `--layout`, in that order so each can override the one before
+fn load_user_settings(state: &SharedState, args: &[String]) {
+    // Load user configuration (defaults if the file doesn't exist)
+    match config::Config::load() {
+        Ok(config) => {
+            let _ = state.set_config(config);
+        }
+        Err(e) => eprintln!("{}", tr_args(Text::UsingDefaults, &[&e])),
+    }
+
+    // The profile switched to last brings its layout back; --layout wins
+    match profile::active_layout(&state.get_config().unwrap_or_default()) {
+        Ok(Some(custom)) => {
+            println!("{}", tr_args(Text::LayoutLoaded, &[&custom.name]));
+            let _ = state.set_layout(Some(custom));
+        }
+        Ok(None) => {}
+        Err(e) => eprintln!("{}", tr_args(Text::LayoutFallback, &[&e])),
+    }
+
+    // Paused, reverse mode or a custom layout carry on from the last run
+    match session::Session::load() {
+        Ok(last) => {
+            if let Err(e) = last.restore(state, session::now()) {
+                print_error(&e);
+            }
+        }
+        Err(e) => print_error(&e),
+    }
+
+    // Optional custom layout: ghostkeys --layout <file.klc>
+    if let Some(path) = args
+        .iter()
+        .position(|arg| arg == "--layout")
+        .and_then(|i| args.get(i + 1))
+    {
+        match layout::klc::load(std::path::Path::new(path)) {
+            Ok(custom) => {
+                println!("{}", tr_args(Text::LayoutLoaded, &[&custom.name]));
+                let _ = state.set_layout(Some(custom));
+            }
+            Err(e) => eprintln!("{}", tr_args(Text::LayoutFallback, &[&e])),
+        }
+    }
+}
+
+/// Tells the user GhostKeys started in safe mode and offers to open the
+/// config file, the likeliest cause of the crashes
+fn offer_config_fix(state: &SharedState, crashes: u32) {
+    let path = paths::config_file().unwrap_or_default();
+    let message = tr_args(Text::SafeModeStarted, &[&crashes, &path.display()]);
+    let _ = state.notify(notify::Severity::Warning, message);
+    // The dialog waits for an answer; startup doesn't
+    thread::spawn(|| {
+        if ask_to_open_config() {
+            if let Err(e) = open_in_file_manager(paths::config_file(), false) {
+                eprintln!("{}", e);
+            }
+        }
+    });
+}
+
+/// Asks in a native Windows message box whether to open the config file
+#[cfg(target_os = "windows")]
+fn ask_to_open_config() -> bool {
+    use windows::core::PCWSTR;
+    use windows::Win32::UI::WindowsAndMessaging::{MessageBoxW, IDYES, MB_ICONWARNING, MB_YESNO};
+
+    let title: Vec<u16> = tr(Text::SafeModeTitle).encode_utf16().chain([0]).collect();
+    let content: Vec<u16> = tr(Text::SafeModeOpenConfig).encode_utf16().chain([0]).collect();
+
+    unsafe {
+        MessageBoxW(
+            None,
+            PCWSTR::from_raw(content.as_ptr()),
+            PCWSTR::from_raw(title.as_ptr()),
+            MB_YESNO | MB_ICONWARNING,
+        ) == IDYES
+    }
+}
+
+/// Elsewhere the notification names the config file, which is enough
+#[cfg(not(target_os = "windows"))]
+fn ask_to_open_config() -> bool {
+    false
+}
+
+/// Asks the first-run questions, saves the answers and sets up autostart
+fn run_setup(state: &SharedState) {
+    let Ok(answers) = onboarding::ask(&mut std::io::stdin().lock(), &mut std::io::stdout()) else {
+        return;
+    };
+    let mut config = state.get_config().unwrap_or_default();
+    answers.apply(&mut config);
+    match config.save() {
+        Ok(()) => {
+            if let Some(path) = paths::config_file() {
+                println!("{}", tr_args(Text::SetupSaved, &[&path.display()]));
+            }
+        }
+        Err(e) => print_error(&e),
+    }
+    let _ = state.set_config(config);
+
+    if answers.autostart {
+        if let Err(e) = platform::service::enable_at_login() {
+            eprintln!("{}", tr_args(Text::SetupAutostartFailed, &[&e]));
+        }
+    }
+}
+
+/// Shows why a rule paused or resumed GhostKeys, if the user wants toasts
+fn show_mode_reason(state: &SharedState, reason: &str) {
+    if state.get_config().is_ok_and(|config| config.notifications.mode_changes) {
+        platform::notifications::show_message(reason);
+    }
+}
+
+/// Prints and logs queued notifications; returns the latest warning or error
+/// so the tray can surface it in the tooltip
+fn drain_notifications(state: &SharedState) -> Option<String> {
+    let notifications = state.take_notifications().unwrap_or_default();
+    let system_log = !notifications.is_empty()
+        && state.get_config().is_ok_and(|config| config.logging.system_log);
+    let mut latest = None;
+    for notification in notifications {
+        eprintln!("{}", notification);
+        if notification.severity != notify::Severity::Info {
+            if system_log {
+                platform::system_log::write(&notification);
+            }
+            notify::write_log(&notification);
+            latest = Some(notification.message);
+        }
+    }
+    latest
+}
+
+/// Runs without a tray icon until `ghostkeys ctl quit`; the hotkeys and
+/// `ghostkeys ctl` are the only controls
+fn run_without_tray(state: &SharedState, state_changes: &Receiver<StateChange>) {
+    println!("{}", tr(Text::RunningWithoutTray));
+    while !state.should_exit() {
+        match state_changes.recv_timeout(Duration::from_millis(500)) {
+            Ok(StateChange::Mode(OperationMode::Active)) => println!("GhostKeys resumed"),
+            Ok(StateChange::Mode(OperationMode::Passthrough)) => println!("GhostKeys paused"),
+            Ok(StateChange::ModeReason(reason)) => show_mode_reason(state, &reason),
+            Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        drain_notifications(state);
+    }
+    println!("{}", tr(Text::Exiting));
+    save_stats(state);
+    save_session(state);
+    safe_mode::record_exit();
+}
+
+/// Save the remap counts if statistics are enabled
+fn save_stats(state: &SharedState) {
+    if !state.get_config().is_ok_and(|config| config.statistics.enabled) {
+        return;
+    }
+    if let Err(e) = state.get_stats().and_then(|stats| stats.save()) {
+        print_error(&e);
+    }
+}
+
+/// Save the mode, direction and layout of the moment for the next start;
+/// safe mode leaves the last session as it was
+fn save_session(state: &SharedState) {
+    if safe_mode::is_active() {
+        return;
+    }
+    if let Err(e) = session::Session::capture(state).and_then(|session| session.save()) {
+        print_error(&e);
+    }
+}
+
+/// Why a tray icon can't be shown here, if it can't
+///
+/// On Linux the event loop can't even be created without a graphical session
+/// (e.g., over SSH, or from a service started before login).
+fn tray_unavailable() -> Option<&'static str> {
+    let no_display =
+        std::env::var_os("WAYLAND_DISPLAY").is_none() && std::env::var_os("DISPLAY").is_none();
+    (cfg!(target_os = "linux") && no_display).then_some("DISPLAY and WAYLAND_DISPLAY are not set")
+}
+
+/// Creates the tray icon with its menu
+fn build_tray_icon(menu: Menu, image: &icon::Image) -> std::result::Result<TrayIcon, String> {
+    let icon = tray_icon::Icon::from_rgba(image.rgba().to_vec(), icon::SIZE, icon::SIZE)
+        .map_err(|e| e.to_string())?;
+    TrayIconBuilder::new()
+        .with_menu(Box::new(menu))
+        .with_tooltip(tr(Text::TooltipActive))
+        .with_icon(icon)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// The tray icon `tray` asks for on a `theme` background, before badges;
+/// the built-in one, with a warning, if the user's icon can't be loaded
+fn tray_base(state: &SharedState, tray: &config::TrayConfig, theme: icon::Theme) -> icon::Image {
+    let (base, error) = icon::base(tray, theme);
+    if let Some(e) = error {
+        let _ = state.notify(notify::Severity::Warning, e.to_string());
+    }
+    base
+}
+
+/// Show `base` with the badges for `status` in the tray
+fn show_tray_image(tray_icon: &TrayIcon, base: &icon::Image, status: icon::Status) {
+    let image = icon::render(base, status);
+    if let Ok(icon) = tray_icon::Icon::from_rgba(image.rgba().to_vec(), icon::SIZE, icon::SIZE) {
+        let _ = tray_icon.set_icon(Some(icon));
+    }
+}
+
+/// Reports that the tray icon can't be shown, then carries on without it
+///
+/// Remapping doesn't depend on the tray; the hotkeys and `ghostkeys ctl`
+/// still control it.
+fn run_without_tray_after(
+    problem: &dyn std::fmt::Display,
+    state: &SharedState,
+    state_changes: &Receiver<StateChange>,
+) {
+    let _ = state.notify(notify::Severity::Warning, tr_args(Text::TrayUnavailable, &[problem]));
+    run_without_tray(state, state_changes);
+}
+
+/// ghostkeys ctl pause|resume|toggle|status|history|rules-test [--follow]|reload|quit
+fn ctl_command(args: &[String]) -> i32 {
+    let Some(command) = args.get(2).and_then(|name| ipc::Command::from_name(name)) else {
+        eprintln!(
+            "Usage: ghostkeys ctl pause | resume | toggle | status | history \
+             | rules-test [--follow] | reload | quit"
+        );
+        return 2;
+    };
+    if command == ipc::Command::RulesTest {
+        return rules_test_command(args.get(3).is_some_and(|arg| arg == "--follow"));
+    }
+    match ipc::send(command) {
+        Ok(reply) => {
+            println!("{}", reply);
+            0
+        }
+        Err(e) => {
+            print_error(&e);
+            1
+        }
+    }
+}
+
+/// ghostkeys ctl rules-test [--follow]
+///
+/// The console running this is in front when the command starts, so the
+/// one-shot report waits for the user to switch to the window to test;
+/// `--follow` prints a report whenever it changes until Ctrl+C.
+fn rules_test_command(follow: bool) -> i32 {
+    if !follow {
+        println!("Switch to the window to test; reporting in 3 seconds...");
+        std::thread::sleep(std::time::Duration::from_secs(3));
+        return match ipc::send(ipc::Command::RulesTest) {
+            Ok(reply) => {
+                println!("{}", reply);
+                0
+            }
+            Err(e) => {
+                print_error(&e);
+                1
+            }
+        };
+    }
+
+    println!("Reporting as focus changes; press Ctrl+C to stop.");
+    let mut last = String::new();
+    loop {
+        match ipc::send(ipc::Command::RulesTest) {
+            Ok(reply) if reply != last => {
+                println!("\n{}", reply);
+                last = reply;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                print_error(&e);
+                return 1;
+            }
+        }
+        std::thread::sleep(std::time::Duration::from_millis(500));
+    }
+}
+
+/// Print a command-line failure with what to do about it
+fn print_error(e: &GhostKeysError) {
+    match e.hint() {
+        Some(hint) => eprintln!("{}. {}", e, hint),
+        None => eprintln!("{}", e),
+    }
+}
+
+/// Value following a command-line flag
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a String> {
+    args.iter().position(|arg| arg == flag).and_then(|i| args.get(i + 1))
+}
+
+/// ghostkeys check-config [path]
+fn check_config_command(args: &[String]) -> i32 {
+    let problems = match args.get(2) {
+        Some(path) => check::run(std::path::Path::new(path)),
+        None => match check::run_default() {
+            Ok(problems) => problems,
+            Err(e) => {
+                print_error(&e);
+                return 1;
+            }
+        },
+    };
+    if problems.is_empty() {
+        let path = args.get(2).map(PathBuf::from).or_else(paths::config_file);
+        let shown = path.map(|path| path.display().to_string()).unwrap_or_default();
+        println!("{}", tr_args(Text::ConfigValid, &[&shown]));
+        return 0;
+    }
+    for problem in &problems {
+        eprintln!("{}", problem);
+    }
+    eprintln!("{}", tr_args(Text::ConfigProblems, &[&problems.len()]));
+    1
+}
+
+/// ghostkeys config schema [--out <file>]
+fn config_command(args: &[String]) -> i32 {
+    if args.get(2).map(String::as_str) != Some("schema") {
+        eprintln!("Usage: ghostkeys config schema [--out <file>]");
+        return 2;
+    }
+    let schema = config::Config::json_schema();
+    let Some(path) = flag_value(args, "--out") else {
+        println!("{}", schema);
+        return 0;
+    };
+    match std::fs::write(path, schema) {
+        Ok(()) => {
+            println!("{}", tr_args(Text::SchemaWritten, &[path]));
+            0
+        }
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            1
+        }
+    }
+}
+
+/// ghostkeys profile export <name> [--out <file>] [--layout <file.klc>]
+/// ghostkeys profile import <file>
+fn profile_command(args: &[String]) -> i32 {
+    let result = match (args.get(2).map(String::as_str), args.get(3)) {
+        (Some("export"), Some(name)) => {
+            let out = flag_value(args, "--out")
+                .map(std::path::PathBuf::from)
+                .unwrap_or_else(|| format!("{}.{}", name, profile::EXTENSION).into());
+            let layout = flag_value(args, "--layout").map(std::path::Path::new);
+            profile::export(name, &out, layout)
+                .map(|()| println!("{}", tr_args(Text::ProfileExported, &[name, &out.display()])))
+        }
+        (Some("import"), Some(path)) => {
+            profile::import(std::path::Path::new(path)).map(|(imported, layout)| {
+                println!("{}", tr_args(Text::ProfileImported, &[&imported.name]));
+                if let Some(layout) = layout {
+                    let layout = layout.display();
+                    println!("{}", tr_args(Text::ProfileLayoutSaved, &[&layout, &layout]));
                 }
-            } else {
-                // Center color
-                if active {
-                    rgba.extend_from_slice(&[50, 205, 50, 255]); // Bright green
-                } else {
-                    rgba.extend_from_slice(&[255, 200, 0, 255]); // Yellow (paused)
+            })
+        }
+        _ => {
+            eprintln!("Usage: ghostkeys profile export <name> [--out <file>] [--layout <file.klc>] | import <file>");
+            return 2;
+        }
+    };
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            print_error(&e);
+            1
+        }
+    }
+}
+
+/// ghostkeys stats export <file.svg|file.json>
+fn stats_command(args: &[String]) -> i32 {
+    let (Some("export"), Some(path)) = (args.get(2).map(String::as_str), args.get(3)) else {
+        eprintln!("Usage: ghostkeys stats export <file.svg|file.json>");
+        return 2;
+    };
+    let result = stats::Stats::load().and_then(|stats| {
+        let contents = if path.to_lowercase().ends_with(".svg") {
+            stats.to_svg()
+        } else {
+            stats.to_json()
+        };
+        std::fs::write(path, contents).map_err(|e| config::io_error(std::path::Path::new(path), e))
+    });
+    match result {
+        Ok(()) => {
+            println!("{}", tr_args(Text::StatsExported, &[path]));
+            0
+        }
+        Err(e) => {
+            print_error(&e);
+            1
+        }
+    }
+}
+
+/// ghostkeys --measure-latency [KEY PRESSES]
+///
+/// Starts a hook of its own, so it works without a running GhostKeys.
+fn measure_latency(args: &[String], state: SharedState) -> i32 {
+    let count = flag_value(args, "--measure-latency").filter(|arg| !arg.starts_with("--"));
+    let samples = match count {
+        Some(count) => match count.parse() {
+            Ok(count) if count > 0 => count,
+            _ => {
+                eprintln!("Usage: ghostkeys --measure-latency [KEY PRESSES]");
+                return 2;
+            }
+        },
+        None => latency::DEFAULT_SAMPLES,
+    };
+    let mut interceptor = create_interceptor();
+    let measured = interceptor.start(state).and_then(|()| interceptor.measure_latency(samples));
+    let _ = interceptor.stop();
+    match measured {
+        Ok(samples) => {
+            print!("{}", latency::render(&samples));
+            0
+        }
+        Err(e) => {
+            print_error(&e);
+            1
+        }
+    }
+}
+
+/// Print the state machine of the configured mapper to stdout
+///
+/// Status messages go to stderr so the output can be piped into `dot` or
+/// pasted into Markdown as is.
+fn dump_state_machine(args: &[String]) -> i32 {
+    let format = match args.get(2).filter(|arg| !arg.starts_with("--")) {
+        None => statemachine::Format::default(),
+        Some(name) => match statemachine::Format::from_name(name) {
+            Some(format) => format,
+            None => {
+                eprintln!("Usage: ghostkeys dump-statemachine [dot|mermaid] [--layout <file.klc>] [--reverse]");
+                return 2;
+            }
+        },
+    };
+
+    print!("{}", statemachine::export(&mapper_from_args(args), format));
+    0
+}
+
+/// Mapper from the config file, `--layout <file.klc>` and `--reverse`, with
+/// status messages on stderr
+fn mapper_from_args(args: &[String]) -> mapper::Mapper {
+    let config = config::Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", tr_args(Text::UsingDefaults, &[&e]));
+        config::Config::default()
+    });
+    let custom = args
+        .iter()
+        .position(|arg| arg == "--layout")
+        .and_then(|i| args.get(i + 1))
+        .and_then(|path| match layout::klc::load(std::path::Path::new(path)) {
+            Ok(custom) => Some(custom),
+            Err(e) => {
+                eprintln!("{}", tr_args(Text::LayoutFallback, &[&e]));
+                None
+            }
+        });
+
+    let mut mapper = match custom {
+        Some(custom) => mapper::Mapper::with_layout(custom),
+        None => mapper::Mapper::new(),
+    };
+    if args.iter().any(|arg| arg == "--reverse") {
+        mapper.set_direction(Direction::Abnt2ToUs);
+    }
+    mapper.set_compose_table(mapper::ComposeTable::from(&config.compose.sequences));
+    mapper.set_features(config.features);
+    mapper.set_dead_key_triggers(config.dead_key_triggers());
+    mapper
+}
+
+/// ghostkeys try [--layout <file.klc>] [--reverse]
+fn try_command(args: &[String]) -> i32 {
+    if !std::io::stdin().is_terminal() {
+        eprintln!("`ghostkeys try` reads keys from a terminal; run it in one");
+        return 2;
+    }
+    match trainer::run(mapper_from_args(args)) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+/// ghostkeys train [--layout <file.klc>]
+fn train_command(args: &[String]) -> i32 {
+    if !std::io::stdin().is_terminal() {
+        eprintln!("`ghostkeys train` reads keys from a terminal; run it in one");
+        return 2;
+    }
+    let mut saved = match stats::Stats::load() {
+        Ok(saved) => saved,
+        Err(e) => {
+            print_error(&e);
+            return 1;
+        }
+    };
+    let previous = saved.drills.last().copied();
+    match tutor::run(mapper_from_args(args), saved.drills.len(), previous.as_ref()) {
+        Ok(Some(result)) => {
+            println!("{}", tr_args(Text::TrainFinished, &[&result.wpm, &result.accuracy]));
+            if let Some(previous) = previous {
+                println!("{}", tr_args(Text::TrainPrevious, &[&previous.wpm, &previous.accuracy]));
+            }
+            saved.record_drill(result);
+            match saved.save() {
+                Ok(()) => 0,
+                Err(e) => {
+                    print_error(&e);
+                    1
                 }
             }
         }
+        Ok(None) => {
+            println!("{}", tr(Text::TrainStopped));
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
     }
-    rgba
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    // Text follows the OS locale unless the config file picks a language
+    if let Some(language) = config::Config::load().ok().and_then(|config| config.language) {
+        i18n::set_language(language);
+    }
+
+    // Background service management: ghostkeys service install|uninstall|status
+    if args.get(1).is_some_and(|arg| arg == "service") {
+        let result = match args.get(2).map(String::as_str) {
+            Some("install") => platform::service::install(&args[3..]),
+            Some("uninstall") => platform::service::uninstall(),
+            Some("status") => platform::service::status(),
+            _ => {
+                eprintln!("Usage: ghostkeys service install [ARGS...] | uninstall | status");
+                std::process::exit(2);
+            }
+        };
+        if let Err(e) = result {
+            print_error(&e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Shareable settings bundles: ghostkeys profile export|import
+    if args.get(1).is_some_and(|arg| arg == "profile") {
+        std::process::exit(profile_command(&args));
+    }
+
+    // Control a running instance: ghostkeys ctl pause|resume|toggle|status|history|rules-test|quit
+    if args.get(1).is_some_and(|arg| arg == "ctl") {
+        std::process::exit(ctl_command(&args));
+    }
+
+    // Check the setup and print what to fix: ghostkeys doctor
+    if args.get(1).is_some_and(|arg| arg == "doctor") {
+        let report = doctor::run();
+        print!("{}", report.render(doctor::use_color()));
+        std::process::exit(if report.has_failures() { 1 } else { 0 });
+    }
+
+    // Validate the config and the files it refers to: ghostkeys check-config [path]
+    if args.get(1).is_some_and(|arg| arg == "check-config") {
+        std::process::exit(check_config_command(&args));
+    }
+
+    // JSON Schema of the config file for editors: ghostkeys config schema [--out <file>]
+    if args.get(1).is_some_and(|arg| arg == "config") {
+        std::process::exit(config_command(&args));
+    }
+
+    // Export the remap counts: ghostkeys stats export <file.svg|file.json>
+    if args.get(1).is_some_and(|arg| arg == "stats") {
+        std::process::exit(stats_command(&args));
+    }
+
+    // Type into the mapper without hooking the system: ghostkeys try
+    if args.get(1).is_some_and(|arg| arg == "try") {
+        std::process::exit(try_command(&args));
+    }
+
+    // Accent drills with speed and accuracy kept between sessions: ghostkeys train
+    if args.get(1).is_some_and(|arg| arg == "train") {
+        std::process::exit(train_command(&args));
+    }
+
+    // Print the mapper state machine: ghostkeys dump-statemachine [dot|mermaid]
+    if args.get(1).is_some_and(|arg| arg == "dump-statemachine") {
+        std::process::exit(dump_state_machine(&args));
+    }
+
+    // IBus engine: print the component file IBus starts the engine from
+    #[cfg(all(target_os = "linux", feature = "ibus"))]
+    if args.iter().any(|arg| arg == "--ibus-component") {
+        match std::env::current_exe() {
+            Ok(exe) => print!("{}", platform::ibus::component_xml(&exe)),
+            Err(e) => eprintln!("Can't find the GhostKeys executable: {}", e),
+        }
+        return;
+    }
+
+    // Supervisor mode: restart the real GhostKeys if it crashes
+    if args.iter().any(|arg| arg == supervisor::SUPERVISE_FLAG) {
+        std::process::exit(supervisor::run(&args[1..]));
+    }
+
+    // Initialize shared state
+    let state = SharedState::new();
+
     // Set up panic handler FIRST to ensure keyboard hook is released on crash
-    setup_panic_handler();
+    setup_panic_handler(&state);
     
-    println!("GhostKeys - ABNT2 keyboard layout emulation");
+    println!("{}", tr(Text::Banner));
     println!("Platform: {}", std::env::consts::OS);
 
-    // Initialize shared state
-    let state = SharedState::new();
+    // Remap counts carry on from earlier runs
+    match stats::Stats::load() {
+        Ok(saved) => {
+            let _ = state.set_stats(saved);
+        }
+        Err(e) => print_error(&e),
+    }
+
+    // Several launches in a row crashed: start with the built-in defaults so
+    // a bad config or layout can't break the keyboard on every launch.
+    // --export and --measure-latency exit on their own and aren't counted.
+    let one_shot = args.iter().any(|arg| arg == "--export" || arg == "--measure-latency");
+    match if one_shot { None } else { safe_mode::record_start() } {
+        None => load_user_settings(&state, &args),
+        Some(crashes) => offer_config_fix(&state, crashes),
+    }
+
+    // Export the active layout and exit: ghostkeys --export <file.klc|file.xkb>
+    if let Some(path) = args
+        .iter()
+        .position(|arg| arg == "--export")
+        .and_then(|i| args.get(i + 1))
+    {
+        // The layout as config.toml builds it: features, moved dead keys, ...
+        let active = interceptor::build_mapper(&state)
+            .map(|mapper| mapper.effective_layout())
+            .unwrap_or_else(|_| layout::Layout::abnt2());
+        let contents = if path.to_lowercase().ends_with(".klc") {
+            layout::klc::encode(&layout::klc::export(&active))
+        } else {
+            layout::xkb::export(&active).into_bytes()
+        };
+        match std::fs::write(path, contents) {
+            Ok(()) => println!("{}", tr_args(Text::LayoutExported, &[&active.name, path])),
+            Err(e) => eprintln!("{}", tr_args(Text::LayoutExportFailed, &[path, &e])),
+        }
+        return;
+    }
+
+    // Time key presses through the hook and exit: ghostkeys --measure-latency
+    if args.iter().any(|arg| arg == "--measure-latency") {
+        std::process::exit(measure_latency(&args, state));
+    }
+
+    // Run as an IBus engine instead of grabbing keys (started by IBus)
+    #[cfg(all(target_os = "linux", feature = "ibus"))]
+    if args.iter().any(|arg| arg == "--ibus") {
+        if let Err(e) = platform::ibus::run(state) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        safe_mode::record_exit();
+        return;
+    }
+
+    // First run from a console (or --setup): ask a few questions and save
+    // the answers as the config file
+    let setup = !safe_mode::is_active()
+        && (args.iter().any(|arg| arg == "--setup")
+            || (onboarding::is_first_run() && std::io::stdin().is_terminal()));
+    if setup {
+        run_setup(&state);
+    }
+
     let state_for_hook = state.clone();
-    let is_active = Arc::new(AtomicBool::new(true));
+
+    // Subscribe before the hook starts so no change is missed
+    let state_changes = state.subscribe().expect("Failed to subscribe to state changes");
+
+    let startup = state.get_config().unwrap_or_default().startup;
+    if startup.start_paused {
+        let _ = state.set_mode_because(OperationMode::Passthrough, history::Cause::Startup);
+    }
+
+    // `ghostkeys ctl` still works from a console if this fails, just not here
+    if let Err(e) = ipc::serve(state.clone()) {
+        eprintln!("Command-line control unavailable: {}", e);
+    }
+
+    // Keeps session.toml up to date for the next start; in safe mode the
+    // last session is left for when the user's config works again, and the
+    // crashes stay counted until a clean exit
+    if !safe_mode::is_active() {
+        session::spawn(state.clone());
+        safe_mode::spawn_stable_check(state.clone());
+    }
+
+    // Resumes if an [auto_pause] rule stopped applying but GhostKeys stayed paused
+    watchdog::spawn(state.clone(), platform::auto_pause_condition);
+
+    // Layouts that make the OS translate keys a second time or double accents
+    for finding in platform::layout_findings() {
+        let severity = if finding.active {
+            notify::Severity::Warning
+        } else {
+            notify::Severity::Info
+        };
+        let _ = state.notify(severity, finding.to_string());
+    }
+
+    // The interceptor owns the hook; the tray pauses and resumes it
+    let interceptor = Arc::new(Mutex::new(create_interceptor()));
+    let interceptor_for_hook = Arc::clone(&interceptor);
+    let state_for_reports = state.clone();
+    let (started_tx, started_rx) = std::sync::mpsc::channel();
 
     // Spawn keyboard interceptor thread
     let _hook_thread = thread::spawn(move || {
-        let mut interceptor = create_interceptor();
-        
-        if let Err(e) = interceptor.start(state_for_hook) {
-            eprintln!("Failed to start keyboard interceptor: {:?}", e);
+        let started = interceptor_for_hook
+            .lock()
+            .map_err(|_| GhostKeysError::StateLockPoisoned)
+            .and_then(|mut interceptor| interceptor.start(state_for_hook));
+        if let Err(e) = started {
+            let message = match e.hint() {
+                Some(hint) => tr_args(Text::InterceptorFailed, &[&format!("{}. {}", e, hint)]),
+                None => tr_args(Text::InterceptorFailed, &[&e]),
+            };
+            let _ = state_for_reports.notify(notify::Severity::Error, message);
             return;
         }
         
         println!("Keyboard interceptor started successfully!");
-        
-        // Keep thread alive - on Windows the hook needs a message loop
-        // The main thread's event loop handles this, but we park here
-        // to keep the interceptor alive
-        #[cfg(target_os = "windows")]
-        {
-            use windows::Win32::UI::WindowsAndMessaging::{GetMessageW, MSG};
-            unsafe {
-                let mut msg = MSG::default();
-                while GetMessageW(&mut msg, None, 0, 0).as_bool() {
-                    // Process messages to keep hook alive
-                }
-            }
-        }
-        
-        #[cfg(not(target_os = "windows"))]
-        {
-            // On Linux, just park the thread
-            loop {
-                thread::park();
-            }
-        }
+        let _ = started_tx.send(());
+
+        // The interceptor runs on threads of its own from here on
+        spawn_self_test(Arc::clone(&interceptor_for_hook), state_for_reports);
     });
 
+    // Setup ends with typing ç and ã through the running hook; the sender is
+    // dropped without a message if the hook fails to start
+    if setup && started_rx.recv().is_ok() {
+        let _ = onboarding::verify(&mut std::io::stdin().lock(), &mut std::io::stdout());
+    }
+
+    if !startup.show_tray {
+        run_without_tray(&state, &state_changes);
+        return;
+    }
+    if let Some(problem) = tray_unavailable() {
+        run_without_tray_after(&problem, &state, &state_changes);
+        return;
+    }
+
     // Build event loop
     let event_loop = EventLoopBuilder::new().build();
 
-    // Create tray menu
+    // Create tray menu; remapping settings are greyed out where keys can't be blocked
+    let capabilities = platform::capabilities(&state.get_config().unwrap_or_default());
     let menu = Menu::new();
-    let status_item = MenuItem::new("GhostKeys: Active", false, None);
-    let pause_item = MenuItem::new("Pause", true, None);
+    let status_item = MenuItem::new(tr(Text::StatusActive), false, None);
+    let pause_item = MenuItem::new(tr(Text::Pause), capabilities.suppression, None);
+    let reverse_item = CheckMenuItem::new(
+        tr(Text::ReverseMode),
+        capabilities.suppression,
+        state.get_direction().is_ok_and(|direction| direction == Direction::Abnt2ToUs),
+        None,
+    );
+    let self_test_item = MenuItem::new(tr(Text::RunSelfTest), true, None);
+    let history_item = MenuItem::new(tr(Text::ModeHistory), true, None);
+    let us_layout_item =
+        MenuItem::new(tr(Text::SwitchToUsLayout), cfg!(target_os = "windows"), None);
+    // Saved profiles, the active one checked; left out while there are none
+    let active_profile = state.get_config().unwrap_or_default().profiles.active;
+    let profiles_menu = Submenu::new(tr(Text::Profiles), true);
+    // Safe mode keeps to the defaults, so profiles aren't offered
+    let profiles = if safe_mode::is_active() { Vec::new() } else { profile::list() };
+    let profile_items: Vec<(CheckMenuItem, String)> = profiles
+        .into_iter()
+        .map(|name| {
+            let checked = active_profile.as_deref() == Some(name.as_str());
+            (CheckMenuItem::new(&name, true, checked, None), name)
+        })
+        .collect();
+    for (item, _) in &profile_items {
+        let _ = profiles_menu.append(item);
+    }
     let separator1 = tray_icon::menu::PredefinedMenuItem::separator();
-    let help_item = MenuItem::new("Help / Mappings", true, None);
-    let about_item = MenuItem::new("About", true, None);
+    let open_logs_item = MenuItem::new(tr(Text::OpenLogs), true, None);
+    let open_config_item = MenuItem::new(tr(Text::OpenConfigFolder), true, None);
+    let help_item = MenuItem::new(tr(Text::HelpMenu), true, None);
+    let about_item = MenuItem::new(tr(Text::AboutMenu), true, None);
     let separator2 = tray_icon::menu::PredefinedMenuItem::separator();
-    let exit_item = MenuItem::new("Exit", true, None);
+    let exit_item = MenuItem::new(tr(Text::Exit), true, None);
 
     let _ = menu.append(&status_item);
     let _ = menu.append(&pause_item);
+    let _ = menu.append(&reverse_item);
+    let _ = menu.append(&self_test_item);
+    let _ = menu.append(&history_item);
+    let _ = menu.append(&us_layout_item);
+    if !profile_items.is_empty() {
+        let _ = menu.append(&profiles_menu);
+    }
     let _ = menu.append(&separator1);
+    let _ = menu.append(&open_logs_item);
+    let _ = menu.append(&open_config_item);
     let _ = menu.append(&help_item);
     let _ = menu.append(&about_item);
     let _ = menu.append(&separator2);
     let _ = menu.append(&exit_item);
 
-    // Create icon from RGBA data
-    let icon_rgba = create_icon_rgba(true);
-    let icon = tray_icon::Icon::from_rgba(icon_rgba, 32, 32)
-        .expect("Failed to create icon");
+    // The icon follows the OS theme (checked every few seconds) unless the
+    // config sets one, and carries badges for the state
+    let mut tray_config = state.get_config().unwrap_or_default().tray;
+    let mut theme = icon::Theme::from_setting(tray_config.theme);
+    let mut theme_checked = Instant::now();
+    let mut base = tray_base(&state, &tray_config, theme);
+    let mut status = icon::Status { paused: state.is_paused(), ..icon::Status::default() };
 
-    // Build tray icon
-    let tray_icon = TrayIconBuilder::new()
-        .with_menu(Box::new(menu))
-        .with_tooltip("GhostKeys - ABNT2 Emulation (Active)")
-        .with_icon(icon)
-        .build()
-        .expect("Failed to create tray icon");
+    // No system tray (e.g., Windows Server Core): keep remapping without it
+    let tray_icon = match build_tray_icon(menu, &icon::render(&base, status)) {
+        Ok(tray_icon) => tray_icon,
+        Err(e) => {
+            run_without_tray_after(&e, &state, &state_changes);
+            return;
+        }
+    };
 
     println!("System tray initialized. Right-click the tray icon for options.");
 
     // Store menu item IDs for event handling
     let pause_id = pause_item.id().clone();
+    let reverse_id = reverse_item.id().clone();
+    let self_test_id = self_test_item.id().clone();
+    let history_id = history_item.id().clone();
+    let us_layout_id = us_layout_item.id().clone();
+    let open_logs_id = open_logs_item.id().clone();
+    let open_config_id = open_config_item.id().clone();
     let help_id = help_item.id().clone();
     let about_id = about_item.id().clone();
     let exit_id = exit_item.id().clone();
@@ -222,54 +1152,142 @@ fn main() {
 
     // Run event loop
     event_loop.run(move |event, _, control_flow| {
-        *control_flow = ControlFlow::Wait;
+        // Wake up periodically to show notifications queued by the hook thread
+        *control_flow = ControlFlow::WaitUntil(Instant::now() + Duration::from_millis(500));
 
         match event {
             Event::NewEvents(StartCause::Init) => {
-                println!("GhostKeys is running...");
+                println!("{}", tr(Text::Running));
             }
             _ => {}
         }
 
+        // `ghostkeys ctl quit`
+        if state.should_exit() {
+            println!("{}", tr(Text::Exiting));
+            save_stats(&state);
+            save_session(&state);
+            safe_mode::record_exit();
+            *control_flow = ControlFlow::Exit;
+            return;
+        }
+
         // Handle menu events
         if let Ok(menu_event) = MenuEvent::receiver().try_recv() {
             if menu_event.id == pause_id {
-                let currently_active = is_active.load(Ordering::SeqCst);
-                is_active.store(!currently_active, Ordering::SeqCst);
-                
-                if currently_active {
-                    println!("GhostKeys paused");
-                    status_item.set_text("GhostKeys: Paused");
-                    pause_item.set_text("Resume");
-                    let _ = state.set_mode(state::OperationMode::Passthrough);
-                    
-                    // Update icon to yellow (paused)
-                    let paused_icon = create_icon_rgba(false);
-                    if let Ok(icon) = tray_icon::Icon::from_rgba(paused_icon, 32, 32) {
-                        let _ = tray_icon.set_icon(Some(icon));
-                        let _ = tray_icon.set_tooltip(Some("GhostKeys - ABNT2 Emulation (Paused)"));
-                    }
+                let mode = match state.get_mode() {
+                    Ok(OperationMode::Passthrough) => OperationMode::Active,
+                    _ => OperationMode::Passthrough,
+                };
+                let result = interceptor
+                    .lock()
+                    .map_err(|_| GhostKeysError::StateLockPoisoned)
+                    .and_then(|mut interceptor| interceptor.set_mode(mode));
+                if let Err(e) = result {
+                    eprintln!("Failed to change mode: {}", e);
+                }
+            } else if menu_event.id == reverse_id {
+                let direction = if reverse_item.is_checked() {
+                    Direction::Abnt2ToUs
                 } else {
-                    println!("GhostKeys resumed");
-                    status_item.set_text("GhostKeys: Active");
-                    pause_item.set_text("Pause");
-                    let _ = state.set_mode(state::OperationMode::Active);
-                    
-                    // Update icon to green (active)
-                    let active_icon = create_icon_rgba(true);
-                    if let Ok(icon) = tray_icon::Icon::from_rgba(active_icon, 32, 32) {
-                        let _ = tray_icon.set_icon(Some(icon));
-                        let _ = tray_icon.set_tooltip(Some("GhostKeys - ABNT2 Emulation (Active)"));
-                    }
+                    Direction::UsToAbnt2
+                };
+                let _ = state.set_direction(direction);
+            } else if menu_event.id == self_test_id {
+                spawn_self_test(Arc::clone(&interceptor), state.clone());
+            } else if menu_event.id == history_id {
+                show_history_dialog(&state);
+            } else if menu_event.id == us_layout_id {
+                switch_os_layout(&state);
+            } else if menu_event.id == open_logs_id {
+                if let Err(e) = open_in_file_manager(paths::log_file(), false) {
+                    let message = tr_args(Text::OpenLogsFailed, &[&e]);
+                    let _ = state.notify(notify::Severity::Warning, message);
+                }
+            } else if menu_event.id == open_config_id {
+                if let Err(e) = open_in_file_manager(paths::config_dir(), true) {
+                    let message = tr_args(Text::OpenConfigFolderFailed, &[&e]);
+                    let _ = state.notify(notify::Severity::Warning, message);
                 }
             } else if menu_event.id == help_id {
                 show_help_dialog();
             } else if menu_event.id == about_id {
                 show_about_dialog();
+            } else if let Some((_, name)) =
+                profile_items.iter().find(|(item, _)| item.id() == &menu_event.id)
+            {
+                switch_profile(&state, &interceptor, name);
+                // Clicking toggled the item's check; a failed switch leaves the old profile
+                check_active_profile(&state, &profile_items);
             } else if menu_event.id == exit_id {
-                println!("Exiting GhostKeys...");
+                println!("{}", tr(Text::Exiting));
+                save_stats(&state);
+                save_session(&state);
+                safe_mode::record_exit();
                 *control_flow = ControlFlow::Exit;
             }
         }
+
+        // Keep the tray in sync with changes from any source (e.g., the
+        // interceptor pausing when an ABNT2 keyboard is plugged in)
+        let shown = status;
+        let mut redraw = false;
+        for change in state_changes.try_iter() {
+            match change {
+                StateChange::Mode(OperationMode::Active) => {
+                    println!("GhostKeys resumed");
+                    status_item.set_text(tr(Text::StatusActive));
+                    pause_item.set_text(tr(Text::Pause));
+                    let _ = tray_icon.set_tooltip(Some(tr(Text::TooltipActive)));
+                    // The new tooltip replaces the problem the badge pointed to
+                    status = icon::Status { paused: false, problem: false, ..status };
+                }
+                StateChange::Mode(OperationMode::Passthrough) => {
+                    println!("GhostKeys paused");
+                    status_item.set_text(tr(Text::StatusPaused));
+                    pause_item.set_text(tr(Text::Resume));
+                    let _ = tray_icon.set_tooltip(Some(tr(Text::TooltipPaused)));
+                    status = icon::Status { paused: true, problem: false, ..status };
+                }
+                StateChange::Direction(direction) => {
+                    reverse_item.set_checked(direction == Direction::Abnt2ToUs);
+                }
+                StateChange::ModeReason(reason) => show_mode_reason(&state, &reason),
+                // Ctrl+Alt+P switches profiles from the hook thread
+                StateChange::Config => {
+                    check_active_profile(&state, &profile_items);
+                    let tray = state.get_config().unwrap_or_default().tray;
+                    if tray != tray_config {
+                        tray_config = tray;
+                        theme = icon::Theme::from_setting(tray_config.theme);
+                        base = tray_base(&state, &tray_config, theme);
+                        redraw = true;
+                    }
+                }
+                StateChange::AccentPending(pending) => status.accent_pending = pending,
+                StateChange::Layout => {}
+            }
+        }
+
+        // The user may switch between light and dark at any time
+        let auto = tray_config.theme == config::TrayTheme::Auto;
+        if auto && theme_checked.elapsed() >= THEME_CHECK {
+            theme_checked = Instant::now();
+            let current = icon::Theme::from_setting(tray_config.theme);
+            if current != theme {
+                theme = current;
+                base = tray_base(&state, &tray_config, theme);
+                redraw = true;
+            }
+        }
+
+        // Log queued notifications and surface the latest problem in the tooltip
+        if let Some(message) = drain_notifications(&state) {
+            let _ = tray_icon.set_tooltip(Some(format!("GhostKeys - {}", message)));
+            status.problem = true;
+        }
+        if redraw || status != shown {
+            show_tray_image(&tray_icon, &base, status);
+        }
     });
 }
@@ -4,18 +4,23 @@
 //! positions to ABNT2 characters. It is pure Rust with no platform dependencies,
 //! making it testable on any OS.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::time::{Duration, Instant};
 
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::layout::{us_char, us_key, Layout, SourceLayout};
+
 // Re-export KeyAction for convenience
-pub use crate::interceptor::KeyAction;
+pub use crate::interceptor::{KeyAction, KeyDirection, KeyEvent, Modifiers};
 
 /// Timeout for pending accent state (500ms)
-const ACCENT_TIMEOUT: Duration = Duration::from_millis(500);
+pub const ACCENT_TIMEOUT: Duration = Duration::from_millis(500);
 
-/// Virtual key codes for keys we intercept
+/// Virtual key codes for keys we intercept or inject
 /// These are platform-agnostic representations
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum VirtualKey {
     /// Semicolon key (;) - maps to ç on ABNT2
     Semicolon,
@@ -29,16 +34,92 @@ pub enum VirtualKey {
     Backslash,
     /// Slash key (/) - maps to ; or : on ABNT2
     Slash,
+    /// Minus key (-) - same on both layouts
+    Minus,
+    /// Equals key (=) - same on both layouts
+    Equals,
+    /// Comma key (,) - same on both layouts
+    Comma,
+    /// Period key (.) - same on both layouts
+    Period,
+    /// Backquote key (`) - same on both layouts
+    Backquote,
+    /// Digit row key ('0'-'9')
+    Digit(char),
+    /// Whichever physical key the platform was configured to use as Compose
+    Compose,
     /// Regular character key
     Char(char),
     /// Space key
     Space,
+    /// Enter key (main or numpad)
+    Enter,
+    /// Tab key
+    Tab,
+    /// Escape key
+    Escape,
+    /// Backspace key
+    Backspace,
+    /// Delete key
+    Delete,
+    /// Insert key
+    Insert,
+    /// Home key
+    Home,
+    /// End key
+    End,
+    /// Page Up key
+    PageUp,
+    /// Page Down key
+    PageDown,
+    /// Left arrow
+    Left,
+    /// Right arrow
+    Right,
+    /// Up arrow
+    Up,
+    /// Down arrow
+    Down,
+    /// Caps Lock key
+    CapsLock,
+    /// Context menu (Application) key
+    Menu,
+    /// Scroll Lock key
+    ScrollLock,
+    /// Pause/Break key
+    Pause,
+    /// Print Screen key
+    PrintScreen,
+    /// Extra key between left Shift and Z on ISO keyboards (\| on ABNT2)
+    IntlBackslash,
+    /// Extra key next to right Shift on ABNT2 keyboards (/?)
+    IntlRo,
     /// Other keys we don't handle
     Other,
 }
 
+/// Translation direction between physical and logical layouts
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize, Serialize, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    /// US physical keyboard, ABNT2 muscle memory (the original use case)
+    #[default]
+    UsToAbnt2,
+    /// ABNT2 physical keyboard (OS layout set to ABNT2), US muscle memory
+    ///
+    /// Every remapped key produces the character printed on a US keyboard at
+    /// the same position. ABNT2 dead keys are flattened into plain US
+    /// characters, so this direction never enters `PendingAccent`.
+    Abnt2ToUs,
+}
+
 /// Accent types for dead key handling
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize, JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
 pub enum AccentType {
     /// Tilde accent (~) - triggered by ' key on US (unshifted)
     Tilde,
@@ -51,6 +132,20 @@ pub enum AccentType {
 }
 
 impl AccentType {
+    /// Get the accent type for a spacing accent character, if any
+    ///
+    /// Accepts both the spacing acute (´) and the apostrophe, since layouts
+    /// commonly label the acute dead key with either.
+    pub fn from_char(c: char) -> Option<Self> {
+        match c {
+            '~' => Some(AccentType::Tilde),
+            '´' | '\'' => Some(AccentType::Acute),
+            '`' => Some(AccentType::Grave),
+            '^' => Some(AccentType::Circumflex),
+            _ => None,
+        }
+    }
+
     /// Get the character representation of this accent
     pub fn to_char(self) -> char {
         match self {
@@ -60,6 +155,119 @@ impl AccentType {
             AccentType::Circumflex => '^',
         }
     }
+
+    /// Get the Unicode combining mark for this accent
+    pub fn combining_char(self) -> char {
+        match self {
+            AccentType::Tilde => '\u{0303}',
+            AccentType::Acute => '\u{0301}',
+            AccentType::Grave => '\u{0300}',
+            AccentType::Circumflex => '\u{0302}',
+        }
+    }
+}
+
+/// Parts of the layout that can be turned off independently
+///
+/// Configured under `[features]`. A turned-off dead key types what the
+/// keyboard prints on it, and with positions off ç and the brackets do too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct MappingFeatures {
+    /// Direct position remaps (ç, brackets, ...)
+    pub positions: bool,
+    /// Tilde dead key
+    pub tilde: bool,
+    /// Acute dead key
+    pub acute: bool,
+    /// Grave dead key
+    pub grave: bool,
+    /// Circumflex dead key
+    pub circumflex: bool,
+    /// Remap keys typed with AltGr; when off they reach the OS layout's
+    /// AltGr layer untouched
+    pub altgr: bool,
+    /// A dead key pressed while another accent is pending adds to it
+    /// instead of typing it, so both go on the next letter (^ ´ a → ấ)
+    pub chain_accents: bool,
+    /// Letters without a precomposed accented form get a Unicode combining
+    /// mark (x̃) instead of the accent typed before them (~x)
+    pub combining_marks: bool,
+    /// Which keyboard's dead keys to imitate where the two disagree
+    pub dead_key_semantics: DeadKeySemantics,
+    /// Whether the acute then c types ç (and C types Ç); unless set, it does
+    /// with US-International dead keys and doesn't with ABNT2 ones
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub apostrophe_c_cedilla: Option<bool>,
+    /// Where `/` and `?` go, since US keyboards lack the ABNT2 key for them
+    pub slash_question: SlashPlacement,
+}
+
+impl Default for MappingFeatures {
+    fn default() -> Self {
+        Self {
+            positions: true,
+            tilde: true,
+            acute: true,
+            grave: true,
+            circumflex: true,
+            altgr: true,
+            chain_accents: false,
+            combining_marks: false,
+            dead_key_semantics: DeadKeySemantics::default(),
+            apostrophe_c_cedilla: None,
+            slash_question: SlashPlacement::default(),
+        }
+    }
+}
+
+impl MappingFeatures {
+    /// Whether the dead key for `accent` is on
+    pub fn accent(&self, accent: AccentType) -> bool {
+        match accent {
+            AccentType::Tilde => self.tilde,
+            AccentType::Acute => self.acute,
+            AccentType::Grave => self.grave,
+            AccentType::Circumflex => self.circumflex,
+        }
+    }
+
+    /// Whether the acute then c types ç, as `apostrophe_c_cedilla` or
+    /// `dead_key_semantics` says
+    pub fn cedilla(&self) -> bool {
+        self.apostrophe_c_cedilla
+            .unwrap_or(self.dead_key_semantics == DeadKeySemantics::UsInternational)
+    }
+}
+
+/// How dead keys behave where ABNT2 and US-International differ
+///
+/// Only the acute is affected: on US-International it lives on the
+/// apostrophe key, so when it doesn't combine it types `'`, and `'` then `c`
+/// is the usual way to type ç there. Every other combination is the same.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DeadKeySemantics {
+    /// A native ABNT2 keyboard: a lone acute is `´`, and ´ then c is `´c`
+    /// (ç has a key of its own)
+    #[default]
+    Abnt2,
+    /// Windows US-International: a lone acute is `'`, and ' then c is ç
+    UsInternational,
+}
+
+/// Where `/` and `?` are typed in the US -> ABNT2 direction
+///
+/// ABNT2 has them on an extra key next to the right Shift, and puts `;` and
+/// `:` on the key US keyboards have there for `/` and `?`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SlashPlacement {
+    /// AltGr + Q and AltGr + W, as printed on ABNT2 keyboards
+    #[default]
+    AltgrQw,
+    /// The US `/` key keeps `/` and `?`; `;` and `:` move to AltGr on it
+    UsKey,
 }
 
 /// State of the mapper state machine
@@ -69,111 +277,384 @@ pub enum MapperState {
     Idle,
     /// Pending accent, waiting for next character
     PendingAccent(AccentType),
+    /// Compose key pressed, collecting the sequence typed so far
+    Composing(Vec<char>),
+}
+
+/// Result of looking up a compose sequence
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ComposeMatch {
+    /// The sequence is complete and produces this output
+    Complete(String),
+    /// The sequence is a prefix of at least one longer sequence
+    Prefix,
+    /// No sequence starts like this
+    NoMatch,
+}
+
+/// Node of the compose prefix tree
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct ComposeNode {
+    output: Option<String>,
+    children: HashMap<char, ComposeNode>,
+}
+
+/// Compose sequences stored as a prefix tree
+///
+/// A sequence fires as soon as it is complete, so when one sequence is a
+/// prefix of another (e.g., `--` and `---`) the longer one is unreachable.
+/// This matches the X11 Compose convention.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ComposeTable {
+    root: ComposeNode,
+}
+
+impl ComposeTable {
+    /// Create an empty compose table
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a sequence producing the given output
+    pub fn insert(&mut self, sequence: &str, output: &str) {
+        let node = sequence
+            .chars()
+            .fold(&mut self.root, |node, c| node.children.entry(c).or_default());
+        node.output = Some(output.to_string());
+    }
+
+    /// Check whether the table has any sequences
+    pub fn is_empty(&self) -> bool {
+        self.root.children.is_empty()
+    }
+
+    /// All complete sequences and their outputs, sorted by sequence
+    pub fn sequences(&self) -> impl Iterator<Item = (String, &str)> + '_ {
+        let mut sequences = Vec::new();
+        let mut pending = vec![(String::new(), &self.root)];
+        while let Some((prefix, node)) = pending.pop() {
+            if let Some(output) = &node.output {
+                sequences.push((prefix.clone(), output.as_str()));
+            }
+            for (&c, child) in &node.children {
+                let mut sequence = prefix.clone();
+                sequence.push(c);
+                pending.push((sequence, child));
+            }
+        }
+        sorted(sequences)
+    }
+
+    /// Look up a (possibly partial) sequence
+    fn lookup(&self, sequence: &[char]) -> ComposeMatch {
+        let mut node = &self.root;
+        for c in sequence {
+            match node.children.get(c) {
+                Some(child) => node = child,
+                None => return ComposeMatch::NoMatch,
+            }
+        }
+        match &node.output {
+            Some(output) => ComposeMatch::Complete(output.clone()),
+            None => ComposeMatch::Prefix,
+        }
+    }
+}
+
+impl From<&BTreeMap<String, String>> for ComposeTable {
+    fn from(sequences: &BTreeMap<String, String>) -> Self {
+        let mut table = Self::new();
+        for (sequence, output) in sequences {
+            table.insert(sequence, output);
+        }
+        table
+    }
+}
+
+/// Collect items into a Vec sorted for stable enumeration
+fn sorted<T: Ord>(items: impl IntoIterator<Item = T>) -> std::vec::IntoIter<T> {
+    let mut items: Vec<T> = items.into_iter().collect();
+    items.sort();
+    items.into_iter()
+}
+
+/// Apply Caps Lock to a character: letters swap case, anything else stays
+///
+/// Swapping rather than uppercasing is how Caps Lock combines with Shift on
+/// letters (Caps+Shift+a → a).
+fn with_caps_lock(c: char, caps_lock: bool) -> char {
+    if !caps_lock {
+        return c;
+    }
+    let swapped: Vec<char> = if c.is_lowercase() {
+        c.to_uppercase().collect()
+    } else {
+        c.to_lowercase().collect()
+    };
+    match swapped[..] {
+        [single] => single,
+        _ => c,
+    }
+}
+
+/// Build the action that injects a string
+fn replace_with(text: &str) -> KeyAction {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => KeyAction::Replace(c),
+        _ => KeyAction::ReplaceStr(text.to_string()),
+    }
 }
 
 /// ABNT2 position mapper
 ///
 /// Handles position-based character mapping and dead key state machine.
+/// The mapping tables come from a `Layout`; the mapper only owns the state.
 pub struct Mapper {
+    direction: Direction,
     state: MapperState,
     last_accent_time: Option<Instant>,
-    position_map: HashMap<(VirtualKey, bool), char>,
-    accent_combinations: HashMap<(AccentType, char), char>,
+    /// Layout emulated in the US -> ABNT2 direction (ABNT2 unless replaced)
+    layout: Layout,
+    /// Inverse table used in the ABNT2 -> US direction
+    reverse_layout: Layout,
+    /// Sequences available after the Compose key
+    compose: ComposeTable,
+    /// Physical layout of the keyboard, translated before position lookups
+    source_layout: SourceLayout,
+    /// Whether dead keys wait for the next key; when off they type their accent
+    dead_keys: bool,
+    /// Parts of the layout in use
+    features: MappingFeatures,
+    /// Dead keys chosen by the user, replacing the layout's own
+    dead_key_triggers: Option<HashMap<(VirtualKey, bool), AccentType>>,
+    /// Accents typed before the pending one, in order, when chaining
+    chained: Vec<AccentType>,
 }
 
 impl Mapper {
     /// Create a new mapper with default ABNT2 mappings
     pub fn new() -> Self {
-        let mut mapper = Self {
+        Self::with_direction(Direction::default())
+    }
+
+    /// Create a new mapper translating in the given direction
+    pub fn with_direction(direction: Direction) -> Self {
+        Self {
+            direction,
             state: MapperState::Idle,
             last_accent_time: None,
-            position_map: HashMap::new(),
-            accent_combinations: HashMap::new(),
-        };
-        mapper.init_position_map();
-        mapper.init_accent_combinations();
-        mapper
+            layout: Layout::abnt2(),
+            reverse_layout: Layout::us(),
+            compose: ComposeTable::new(),
+            source_layout: SourceLayout::default(),
+            dead_keys: true,
+            features: MappingFeatures::default(),
+            dead_key_triggers: None,
+            chained: Vec::new(),
+        }
+    }
+
+    /// Create a new mapper emulating a custom layout instead of ABNT2
+    pub fn with_layout(layout: Layout) -> Self {
+        Self {
+            layout,
+            ..Self::new()
+        }
+    }
+
+    /// Replace the compose sequence table
+    pub fn set_compose_table(&mut self, compose: ComposeTable) {
+        self.compose = compose;
+        if matches!(self.state, MapperState::Composing(_)) {
+            self.reset();
+        }
+    }
+
+    /// Set the physical layout of the keyboard being remapped
+    ///
+    /// Only position and dead key lookups are translated; characters typed
+    /// after a dead key or Compose keep the meaning the keyboard gave them.
+    pub fn set_source_layout(&mut self, source_layout: SourceLayout) {
+        self.source_layout = source_layout;
+    }
+
+    /// Put the dead keys on other keys (e.g., acute on `'` and tilde on `[`)
+    ///
+    /// The given triggers replace the layout's dead keys in the US -> ABNT2
+    /// direction; an empty set goes back to the layout's own. A trigger wins
+    /// over a position mapping on the same key. Drops a pending accent.
+    pub fn set_dead_key_triggers(
+        &mut self,
+        triggers: impl IntoIterator<Item = ((VirtualKey, bool), AccentType)>,
+    ) {
+        let triggers: HashMap<_, _> = triggers.into_iter().collect();
+        self.dead_key_triggers = (!triggers.is_empty()).then_some(triggers);
+        self.reset();
+    }
+
+    /// Choose which parts of the layout are used
+    ///
+    /// Drops a pending accent whose dead key was turned off.
+    pub fn set_features(&mut self, features: MappingFeatures) {
+        if let MapperState::PendingAccent(accent) = self.state {
+            if !features.accent(accent) {
+                self.reset();
+            }
+        }
+        self.features = features;
+    }
+
+    /// Parts of the layout in use
+    pub fn features(&self) -> MappingFeatures {
+        self.features
+    }
+
+    /// Turn dead keys on or off (e.g., off in terminals, where `'` and `~`
+    /// are typed far more often than accented letters)
+    ///
+    /// With dead keys off, a dead key types its accent right away.
+    pub fn set_dead_keys(&mut self, enabled: bool) {
+        if !enabled && matches!(self.state, MapperState::PendingAccent(_)) {
+            self.reset();
+        }
+        self.dead_keys = enabled;
     }
 
-    /// Initialize the position mapping table
-    /// Based on ABNT2 Positional Mapping Reference Table
-    fn init_position_map(&mut self) {
-        // Direct position mappings: (key, shift) -> output char
-
-        // ; (next to L) -> ç/Ç (ABNT2 Cedilla Position)
-        self.position_map.insert((VirtualKey::Semicolon, false), 'ç');
-        self.position_map.insert((VirtualKey::Semicolon, true), 'Ç');
-
-        // ] (next to [) -> [/{ (ABNT2 Bracket Key Position)
-        self.position_map.insert((VirtualKey::RightBracket, false), '[');
-        self.position_map.insert((VirtualKey::RightBracket, true), '{');
-
-        // \ (above Enter) -> ]/} (ABNT2 Close Bracket Position)
-        self.position_map.insert((VirtualKey::Backslash, false), ']');
-        self.position_map.insert((VirtualKey::Backslash, true), '}');
-
-        // / (next to .) -> ;/: (ABNT2 Semicolon Position)
-        self.position_map.insert((VirtualKey::Slash, false), ';');
-        self.position_map.insert((VirtualKey::Slash, true), ':');
-    }
-
-    /// Initialize the accent combination table
-    fn init_accent_combinations(&mut self) {
-        // Tilde combinations
-        self.accent_combinations.insert((AccentType::Tilde, 'a'), 'ã');
-        self.accent_combinations.insert((AccentType::Tilde, 'A'), 'Ã');
-        self.accent_combinations.insert((AccentType::Tilde, 'o'), 'õ');
-        self.accent_combinations.insert((AccentType::Tilde, 'O'), 'Õ');
-        self.accent_combinations.insert((AccentType::Tilde, 'n'), 'ñ');
-        self.accent_combinations.insert((AccentType::Tilde, 'N'), 'Ñ');
-
-        // Acute combinations
-        self.accent_combinations.insert((AccentType::Acute, 'a'), 'á');
-        self.accent_combinations.insert((AccentType::Acute, 'A'), 'Á');
-        self.accent_combinations.insert((AccentType::Acute, 'e'), 'é');
-        self.accent_combinations.insert((AccentType::Acute, 'E'), 'É');
-        self.accent_combinations.insert((AccentType::Acute, 'i'), 'í');
-        self.accent_combinations.insert((AccentType::Acute, 'I'), 'Í');
-        self.accent_combinations.insert((AccentType::Acute, 'o'), 'ó');
-        self.accent_combinations.insert((AccentType::Acute, 'O'), 'Ó');
-        self.accent_combinations.insert((AccentType::Acute, 'u'), 'ú');
-        self.accent_combinations.insert((AccentType::Acute, 'U'), 'Ú');
-
-        // Grave combinations
-        self.accent_combinations.insert((AccentType::Grave, 'a'), 'à');
-        self.accent_combinations.insert((AccentType::Grave, 'A'), 'À');
-
-        // Circumflex combinations
-        self.accent_combinations.insert((AccentType::Circumflex, 'a'), 'â');
-        self.accent_combinations.insert((AccentType::Circumflex, 'A'), 'Â');
-        self.accent_combinations.insert((AccentType::Circumflex, 'e'), 'ê');
-        self.accent_combinations.insert((AccentType::Circumflex, 'E'), 'Ê');
-        self.accent_combinations.insert((AccentType::Circumflex, 'o'), 'ô');
-        self.accent_combinations.insert((AccentType::Circumflex, 'O'), 'Ô');
-    }
-
-    /// Process a key press and return the action to take
-    pub fn process_key(&mut self, key: VirtualKey, shift: bool) -> KeyAction {
+    /// Get the layout in effect for the current direction
+    pub fn active_layout(&self) -> &Layout {
+        match self.direction {
+            Direction::UsToAbnt2 => &self.layout,
+            Direction::Abnt2ToUs => &self.reverse_layout,
+        }
+    }
+
+    /// The layout as this mapper applies it, for exporting: parts turned off
+    /// in `features` left out, the configured dead keys in place of the
+    /// layout's, and `/` and `?` where `slash_question` puts them
+    pub fn effective_layout(&self) -> Layout {
+        let active = self.active_layout();
+        let mut layout = Layout::new(active.name.clone());
+
+        let mut keys: Vec<(VirtualKey, bool)> = active.positions.keys().copied().collect();
+        keys.extend(active.altgr.keys().copied());
+        keys.extend(self.dead_key_table().keys().copied());
+        for key in [VirtualKey::Slash, VirtualKey::Char('Q'), VirtualKey::Char('W')] {
+            keys.extend([(key, false), (key, true)]);
+        }
+        for (key, shift) in keys {
+            let plain = Modifiers { shift, ..Modifiers::default() };
+            if let Some(accent) = self.get_dead_key_accent(key, shift) {
+                layout.dead_keys.insert((key, shift), accent);
+            } else if let Some(output) = self.get_output(key, plain) {
+                layout.positions.insert((key, shift), output);
+            }
+            // AltGr characters of their own, not the position typed with AltGr
+            let altgr = Modifiers { altgr: true, ..plain };
+            if let Some(output) = self.get_output(key, altgr) {
+                if self.features.altgr && Some(output) != self.get_output(key, plain) {
+                    layout.altgr.insert((key, shift), output);
+                }
+            }
+        }
+
+        // What the dead keys in use combine with, ç included where it's on
+        let accents: Vec<AccentType> = layout.dead_keys.values().copied().collect();
+        let bases: Vec<char> =
+            active.combinations.keys().map(|&(_, base)| base).chain(['c', 'C']).collect();
+        for accent in accents {
+            for &base in &bases {
+                if let Some(combined) = self.combine(accent, base) {
+                    layout.combinations.insert((accent, base), combined);
+                }
+            }
+        }
+        layout
+    }
+
+    /// Process a key event and return the action to take
+    ///
+    /// Alt chords bypass the mapper entirely and leave any pending accent or
+    /// compose sequence armed, so Alt+numpad codes and menu accelerators work
+    /// in the middle of typing. Key releases pass through untouched.
+    pub fn process_key(&mut self, event: KeyEvent) -> KeyAction {
+        let modifiers = event.modifiers;
+        if event.direction == KeyDirection::Up
+            || modifiers.is_alt_chord()
+            || (modifiers.altgr && !self.features.altgr)
+        {
+            KeyAction::Pass
+        } else if event.repeat {
+            self.process_repeat(&event)
+        } else {
+            self.dispatch(&event)
+        }
+    }
+
+    /// Process a key press in the current state
+    ///
+    /// Caps Lock only changes letters: mapped letters (ç) and the letter after
+    /// a dead key. Compose sequences are matched as typed without it.
+    fn dispatch(&mut self, event: &KeyEvent) -> KeyAction {
         match &self.state {
-            MapperState::Idle => self.process_idle(key, shift),
+            MapperState::Idle => self.process_idle(event),
             MapperState::PendingAccent(accent) => {
                 let accent = *accent;
-                self.process_pending_accent(accent, key, shift)
+                self.process_pending_accent(accent, event)
+            }
+            MapperState::Composing(sequence) => {
+                let sequence = sequence.clone();
+                self.process_composing(sequence, event)
             }
         }
     }
 
+    /// Process an auto-repeated key press (the key is still held down)
+    ///
+    /// Replaced keys repeat like any other key, but repeats of a dead key or
+    /// the Compose key are swallowed so holding one doesn't flush and re-arm
+    /// the accent at the OS repeat rate.
+    fn process_repeat(&mut self, event: &KeyEvent) -> KeyAction {
+        let (key, shift) = (event.key, event.modifiers.shift);
+        let position = self.source_layout.position(key);
+        // Shift pressed or released while holding the key that armed the accent
+        let armed_by_key = matches!(
+            self.state,
+            MapperState::PendingAccent(accent)
+                if self.get_dead_key_accent(position, !shift) == Some(accent)
+        );
+        let is_dead_key = self.get_dead_key_accent(position, shift).is_some() || armed_by_key;
+        if (is_dead_key && self.dead_keys) || key == VirtualKey::Compose {
+            return KeyAction::Suppress;
+        }
+        self.dispatch(event)
+    }
+
     /// Process a key in Idle state
-    fn process_idle(&mut self, key: VirtualKey, shift: bool) -> KeyAction {
+    fn process_idle(&mut self, event: &KeyEvent) -> KeyAction {
+        let (key, shift) = (event.key, event.modifiers.shift);
+        // Start a compose sequence
+        if key == VirtualKey::Compose {
+            if self.compose.is_empty() {
+                return KeyAction::Pass;
+            }
+            self.state = MapperState::Composing(Vec::new());
+            return KeyAction::Suppress;
+        }
+
         // Check for dead key triggers
-        if let Some(accent) = self.get_dead_key_accent(key, shift) {
+        let position = self.source_layout.position(key);
+        if let Some(accent) = self.get_dead_key_accent(position, shift) {
+            if !self.dead_keys {
+                return KeyAction::Replace(self.spacing_char(accent));
+            }
             self.state = MapperState::PendingAccent(accent);
-            self.last_accent_time = Some(Instant::now());
+            self.last_accent_time = Some(event.timestamp);
             return KeyAction::Suppress;
         }
 
-        // Check for direct position mappings
-        if let Some(&output) = self.position_map.get(&(key, shift)) {
+        // Check for the AltGr layer, then direct position mappings
+        if let Some(output) = self.get_output(position, event.modifiers) {
             return KeyAction::Replace(output);
         }
 
@@ -181,63 +662,339 @@ impl Mapper {
         KeyAction::Pass
     }
 
-    /// Get the accent type for a dead key trigger, if any
-    /// Based on ABNT2 Positional Mapping Reference Table
+    /// Get the accent type for a dead key trigger, if any and turned on
     fn get_dead_key_accent(&self, key: VirtualKey, shift: bool) -> Option<AccentType> {
-        match (key, shift) {
-            // ' (next to ;) -> Tilde (~) unshifted, Circumflex (^) shifted
-            (VirtualKey::Apostrophe, false) => Some(AccentType::Tilde),
-            (VirtualKey::Apostrophe, true) => Some(AccentType::Circumflex),
-            // [ (next to P) -> Acute (´) unshifted, Grave (`) shifted
-            (VirtualKey::LeftBracket, false) => Some(AccentType::Acute),
-            (VirtualKey::LeftBracket, true) => Some(AccentType::Grave),
+        let accent = *self.dead_key_table().get(&(key, shift))?;
+        self.features.accent(accent).then_some(accent)
+    }
+
+    /// Dead key triggers for the current direction
+    fn dead_key_table(&self) -> &HashMap<(VirtualKey, bool), AccentType> {
+        match (&self.dead_key_triggers, self.direction) {
+            (Some(triggers), Direction::UsToAbnt2) => triggers,
+            _ => &self.active_layout().dead_keys,
+        }
+    }
+
+    /// The character an accent types when it doesn't combine
+    fn spacing_char(&self, accent: AccentType) -> char {
+        match (self.features.dead_key_semantics, accent) {
+            (DeadKeySemantics::UsInternational, AccentType::Acute) => '\'',
+            _ => accent.to_char(),
+        }
+    }
+
+    /// Build the action that types accents on their own
+    fn type_accents(&self, accents: &[AccentType]) -> KeyAction {
+        match accents {
+            [accent] => KeyAction::Replace(self.spacing_char(*accent)),
+            _ => KeyAction::ReplaceMultiple(self.spacing_chars(accents)),
+        }
+    }
+
+    /// The accents as typed on their own, in order
+    fn spacing_chars(&self, accents: &[AccentType]) -> Vec<char> {
+        accents.iter().map(|&accent| self.spacing_char(accent)).collect()
+    }
+
+    /// An accent combined with a character, if the two combine
+    fn combine(&self, accent: AccentType, base: char) -> Option<String> {
+        let cedilla_on = self.features.cedilla();
+        let cedilla = match (accent, base) {
+            (AccentType::Acute, 'c') if cedilla_on => Some("ç"),
+            (AccentType::Acute, 'C') if cedilla_on => Some("Ç"),
+            _ => None,
+        };
+        let combinations = &self.active_layout().combinations;
+        cedilla.map(String::from).or_else(|| combinations.get(&(accent, base)).cloned())
+    }
+
+    /// Get what a key types on its own: its AltGr character while AltGr is
+    /// held, if it has one, and otherwise its position mapping
+    fn get_output(&self, key: VirtualKey, modifiers: Modifiers) -> Option<char> {
+        let shift = modifiers.shift;
+        if modifiers.altgr {
+            if let Some(&output) = self.active_layout().altgr.get(&(key, shift)) {
+                return Some(output);
+            }
+        }
+        if let Some(output) = self.get_slash_question(key, modifiers) {
+            return Some(output);
+        }
+        let output = self.get_position(key, shift)?;
+        Some(with_caps_lock(output, modifiers.caps_lock))
+    }
+
+    /// Get `/`, `?`, or the `;` and `:` they displace, where `slash_question`
+    /// puts them; only in the US -> ABNT2 direction, with positions on
+    fn get_slash_question(&self, key: VirtualKey, modifiers: Modifiers) -> Option<char> {
+        if self.direction != Direction::UsToAbnt2 || !self.features.positions {
+            return None;
+        }
+        let (altgr, shift) = (modifiers.altgr, modifiers.shift);
+        match (self.features.slash_question, key) {
+            (SlashPlacement::AltgrQw, VirtualKey::Char('Q')) if altgr && !shift => Some('/'),
+            (SlashPlacement::AltgrQw, VirtualKey::Char('W')) if altgr && !shift => Some('?'),
+            (SlashPlacement::UsKey, VirtualKey::Slash) => Some(match (altgr, shift) {
+                (false, false) => '/',
+                (false, true) => '?',
+                (true, false) => ';',
+                (true, true) => ':',
+            }),
             _ => None,
         }
     }
 
+    /// Get the output of a position mapping, if any and positions are on
+    fn get_position(&self, key: VirtualKey, shift: bool) -> Option<char> {
+        if !self.features.positions {
+            return None;
+        }
+        self.active_layout().positions.get(&(key, shift)).copied()
+    }
+
     /// Process a key in PendingAccent state
-    fn process_pending_accent(&mut self, accent: AccentType, key: VirtualKey, shift: bool) -> KeyAction {
+    ///
+    /// Accents chained before this one (with `chain_accents` on) go on the
+    /// same character, e.g. ^ then ´ then a → ấ.
+    fn process_pending_accent(&mut self, accent: AccentType, event: &KeyEvent) -> KeyAction {
+        let (key, shift) = (event.key, event.modifiers.shift);
+        let caps_lock = event.modifiers.caps_lock;
         self.state = MapperState::Idle;
         self.last_accent_time = None;
+        let mut accents = std::mem::take(&mut self.chained);
+        accents.push(accent);
+
+        // Escape abandons the accent: nothing is typed and Esc does its job
+        if key == VirtualKey::Escape {
+            return KeyAction::Pass;
+        }
 
         // Handle space: output just the accent character
         if key == VirtualKey::Space {
-            return KeyAction::Replace(accent.to_char());
+            return self.type_accents(&accents);
+        }
+
+        // Compose interrupts the accent: flush it and start composing
+        if key == VirtualKey::Compose && !self.compose.is_empty() {
+            self.state = MapperState::Composing(Vec::new());
+            return self.type_accents(&accents);
         }
 
-        // Get the character for this key
+        // AltGr symbols don't take accents
+        let position = self.source_layout.position(key);
+        if event.modifiers.altgr && self.get_output(position, event.modifiers).is_some() {
+            return self.flush_accents_before(accents, event);
+        }
+
+        // Get the character for this key (letters and plain punctuation)
         let char_key = match key {
-            VirtualKey::Char(c) => {
-                if shift {
-                    c.to_ascii_uppercase()
-                } else {
-                    c.to_ascii_lowercase()
-                }
-            }
-            _ => {
-                // Non-character key: output accent + original key action
-                return KeyAction::Replace(accent.to_char());
+            VirtualKey::Char(_) => us_char(key, shift != caps_lock),
+            VirtualKey::Minus
+            | VirtualKey::Equals
+            | VirtualKey::Comma
+            | VirtualKey::Period => us_char(key, shift),
+            _ => None,
+        };
+        let Some(char_key) = char_key else {
+            return self.flush_accents_before(accents, event);
+        };
+
+        self.apply_accents(&accents, char_key)
+    }
+
+    /// Put accents, in the order typed, on a character
+    ///
+    /// Each accent is precomposed while the layout has the combination. The
+    /// rest become combining marks on letters if `combining_marks` is on, and
+    /// are otherwise typed before the character the typewriter way (`~x`).
+    fn apply_accents(&self, accents: &[AccentType], base: char) -> KeyAction {
+        let mut text = base.to_string();
+        let mut rest = accents;
+        while let Some((&accent, tail)) = rest.split_first() {
+            let mut chars = text.chars();
+            let single = match (chars.next(), chars.next()) {
+                (Some(c), None) => Some(c),
+                _ => None,
+            };
+            let Some(combined) = single.and_then(|c| self.combine(accent, c)) else {
+                break;
+            };
+            text = combined;
+            rest = tail;
+        }
+
+        if rest.is_empty() {
+            return replace_with(&text);
+        }
+        if self.features.combining_marks && base.is_alphabetic() {
+            text.extend(rest.iter().map(|accent| accent.combining_char()));
+            return replace_with(&text);
+        }
+        let mut chars = self.spacing_chars(rest);
+        chars.extend(text.chars());
+        KeyAction::ReplaceMultiple(chars)
+    }
+
+    /// Output the accents, then whatever the key does on its own
+    ///
+    /// Used for keys that never combine (digits, remapped punctuation, Enter),
+    /// so the keystroke isn't lost.
+    fn flush_accents_before(&mut self, accents: Vec<AccentType>, event: &KeyEvent) -> KeyAction {
+        let (key, shift) = (event.key, event.modifiers.shift);
+        // Another dead key starts a new accent, or joins the chain
+        let position = self.source_layout.position(key);
+        if let Some(next) = self.get_dead_key_accent(position, shift) {
+            self.state = MapperState::PendingAccent(next);
+            self.last_accent_time = Some(event.timestamp);
+            if self.features.chain_accents && !accents.contains(&next) {
+                self.chained = accents;
+                return KeyAction::Suppress;
             }
+            return self.type_accents(&accents);
+        }
+
+        let mut chars = self.spacing_chars(&accents);
+        if let Some(output) = self.get_output(position, event.modifiers) {
+            chars.push(output);
+            return KeyAction::ReplaceMultiple(chars);
+        }
+
+        // The OS types unmapped keys (and Enter and Tab act) after the accent
+        if matches!(key, VirtualKey::Enter | VirtualKey::Tab) || us_char(key, shift).is_some() {
+            return match chars[..] {
+                [accent] => KeyAction::ReplaceThenPass(accent),
+                _ => KeyAction::ReplaceMultipleThenPass(chars),
+            };
+        }
+
+        // Non-character key: output accent + original key action
+        self.type_accents(&accents)
+    }
+
+    /// Process a key while collecting a compose sequence
+    ///
+    /// Unlike X11, a sequence that stops matching is typed out literally
+    /// instead of being discarded, so no keystroke is ever lost.
+    fn process_composing(&mut self, mut sequence: Vec<char>, event: &KeyEvent) -> KeyAction {
+        let (key, shift) = (event.key, event.modifiers.shift);
+        // Pressing Compose again cancels the sequence
+        if key == VirtualKey::Compose {
+            self.state = MapperState::Idle;
+            return KeyAction::Suppress;
+        }
+
+        // Backspace takes back the last character, or the Compose press
+        if key == VirtualKey::Backspace {
+            self.state = match sequence.pop() {
+                Some(_) => MapperState::Composing(sequence),
+                None => MapperState::Idle,
+            };
+            return KeyAction::Suppress;
+        }
+
+        // The character the key types outside the sequence, ç included
+        let position = self.source_layout.position(key);
+        let typed = self.get_output(position, event.modifiers).or_else(|| us_char(key, shift));
+        // Keys without a character (arrows, Enter, ...) abort the sequence,
+        // typing what was held back before they act
+        let Some(c) = typed else {
+            self.state = MapperState::Idle;
+            return match sequence[..] {
+                [] => KeyAction::Pass,
+                [c] => KeyAction::ReplaceThenPass(c),
+                _ => KeyAction::ReplaceMultipleThenPass(sequence),
+            };
         };
+        sequence.push(c);
+
+        match self.compose.lookup(&sequence) {
+            ComposeMatch::Complete(output) => {
+                self.state = MapperState::Idle;
+                replace_with(&output)
+            }
+            ComposeMatch::Prefix => {
+                self.state = MapperState::Composing(sequence);
+                KeyAction::Suppress
+            }
+            ComposeMatch::NoMatch => {
+                self.state = MapperState::Idle;
+                KeyAction::ReplaceMultiple(sequence)
+            }
+        }
+    }
 
-        // Check for accent combination
-        if let Some(&combined) = self.accent_combinations.get(&(accent, char_key)) {
-            return KeyAction::Replace(combined);
+    /// Type key presses (key, Shift) and return the text that ends up on screen
+    ///
+    /// Keys the mapper lets through type what a US layout gives them, Enter
+    /// and Tab included. Whatever is still pending at the end is typed, as
+    /// if the accent timed out or the compose sequence was abandoned.
+    pub fn map_keys(&mut self, keys: impl IntoIterator<Item = (VirtualKey, bool)>) -> String {
+        let mut text = String::new();
+        for (key, shift) in keys {
+            let typed = match key {
+                VirtualKey::Enter => Some('\n'),
+                VirtualKey::Tab => Some('\t'),
+                _ => us_char(key, shift),
+            };
+            text.extend(self.process_key(KeyEvent::press(key, shift)).output(typed));
         }
+        text.extend(self.finish());
+        text
+    }
+
+    /// Type text as if on a US keyboard and return what ends up on screen
+    ///
+    /// `"n'ao"` becomes `"não"`. Characters a US keyboard can't type go
+    /// straight through like unknown keys do on every platform, leaving a
+    /// pending accent armed.
+    pub fn map_text(&mut self, text: &str) -> String {
+        let mut output = String::new();
+        for c in text.chars() {
+            match us_key(c) {
+                Some((key, shift)) => {
+                    output.extend(self.process_key(KeyEvent::press(key, shift)).output(Some(c)))
+                }
+                None => output.push(c),
+            }
+        }
+        output.extend(self.finish());
+        output
+    }
 
-        // Non-combinable character: output accent + character
-        KeyAction::ReplaceMultiple(vec![accent.to_char(), char_key])
+    /// Characters still held back (a pending accent or compose sequence),
+    /// returning to Idle
+    fn finish(&mut self) -> Vec<char> {
+        let held = match std::mem::replace(&mut self.state, MapperState::Idle) {
+            MapperState::Idle => Vec::new(),
+            MapperState::PendingAccent(accent) => {
+                let mut accents = std::mem::take(&mut self.chained);
+                accents.push(accent);
+                self.spacing_chars(&accents)
+            }
+            MapperState::Composing(sequence) => sequence,
+        };
+        self.reset();
+        held
     }
 
     /// Check for timeout and return action if timeout occurred
+    ///
+    /// A timed-out accent is typed, since the user may have meant the accent
+    /// on its own. Pressing Esc instead discards it without typing anything.
     pub fn check_timeout(&mut self) -> Option<KeyAction> {
-        if let MapperState::PendingAccent(accent) = &self.state {
+        self.check_timeout_at(Instant::now())
+    }
+
+    /// Check for timeout as of `now`, measured from the dead key's timestamp
+    pub fn check_timeout_at(&mut self, now: Instant) -> Option<KeyAction> {
+        if let MapperState::PendingAccent(accent) = self.state {
             if let Some(time) = self.last_accent_time {
-                if time.elapsed() >= ACCENT_TIMEOUT {
-                    let accent_char = accent.to_char();
-                    self.state = MapperState::Idle;
-                    self.last_accent_time = None;
-                    return Some(KeyAction::Replace(accent_char));
+                if now.saturating_duration_since(time) >= ACCENT_TIMEOUT {
+                    let mut accents = std::mem::take(&mut self.chained);
+                    accents.push(accent);
+                    self.reset();
+                    return Some(self.type_accents(&accents));
                 }
             }
         }
@@ -248,12 +1005,68 @@ impl Mapper {
     pub fn reset(&mut self) {
         self.state = MapperState::Idle;
         self.last_accent_time = None;
+        self.chained.clear();
+    }
+
+    /// Get the current translation direction
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// Switch the translation direction
+    ///
+    /// Drops any pending accent, since an accent armed in one direction is
+    /// meaningless in the other.
+    pub fn set_direction(&mut self, direction: Direction) {
+        if self.direction == direction {
+            return;
+        }
+        self.direction = direction;
+        self.reset();
     }
 
     /// Get the current state (for testing)
     pub fn state(&self) -> &MapperState {
         &self.state
     }
+
+    /// Position mappings in effect for the current direction, in key order
+    ///
+    /// Keys are US positions, before the source layout is applied.
+    pub fn mappings(&self) -> impl Iterator<Item = ((VirtualKey, bool), char)> + '_ {
+        sorted(
+            self.active_layout()
+                .positions
+                .iter()
+                .filter(|_| self.features.positions)
+                .map(|(&key, &output)| (key, output)),
+        )
+    }
+
+    /// Dead keys in effect for the current direction, in key order
+    pub fn dead_keys(&self) -> impl Iterator<Item = ((VirtualKey, bool), AccentType)> + '_ {
+        sorted(
+            self.dead_key_table()
+                .iter()
+                .filter(|(_, &accent)| self.features.accent(accent))
+                .map(|(&key, &accent)| (key, accent)),
+        )
+    }
+
+    /// Accent combinations in effect for the current direction
+    pub fn combinations(&self) -> impl Iterator<Item = ((AccentType, char), &str)> + '_ {
+        sorted(
+            self.active_layout()
+                .combinations
+                .iter()
+                .map(|(&pair, combined)| (pair, combined.as_str())),
+        )
+    }
+
+    /// Compose sequences in effect
+    pub fn compose(&self) -> &ComposeTable {
+        &self.compose
+    }
 }
 
 impl Default for Mapper {
@@ -265,6 +1078,12 @@ impl Default for Mapper {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::ComposeConfig;
+
+    /// The same key event as an auto-repeat
+    fn repeated(event: KeyEvent) -> KeyEvent {
+        KeyEvent { repeat: true, ..event }
+    }
 
     // === Direct Position Mapping Tests ===
 
@@ -273,12 +1092,12 @@ mod tests {
         let mut mapper = Mapper::new();
         // ; -> ç (ABNT2 Cedilla Position)
         assert_eq!(
-            mapper.process_key(VirtualKey::Semicolon, false),
+            mapper.process_key(KeyEvent::press(VirtualKey::Semicolon, false)),
             KeyAction::Replace('ç')
         );
         // Shift+; -> Ç
         assert_eq!(
-            mapper.process_key(VirtualKey::Semicolon, true),
+            mapper.process_key(KeyEvent::press(VirtualKey::Semicolon, true)),
             KeyAction::Replace('Ç')
         );
     }
@@ -288,12 +1107,12 @@ mod tests {
         let mut mapper = Mapper::new();
         // ] -> [ (ABNT2 Bracket Key Position)
         assert_eq!(
-            mapper.process_key(VirtualKey::RightBracket, false),
+            mapper.process_key(KeyEvent::press(VirtualKey::RightBracket, false)),
             KeyAction::Replace('[')
         );
         // Shift+] -> {
         assert_eq!(
-            mapper.process_key(VirtualKey::RightBracket, true),
+            mapper.process_key(KeyEvent::press(VirtualKey::RightBracket, true)),
             KeyAction::Replace('{')
         );
     }
@@ -303,12 +1122,12 @@ mod tests {
         let mut mapper = Mapper::new();
         // \ -> ] (ABNT2 Close Bracket Position)
         assert_eq!(
-            mapper.process_key(VirtualKey::Backslash, false),
+            mapper.process_key(KeyEvent::press(VirtualKey::Backslash, false)),
             KeyAction::Replace(']')
         );
         // Shift+\ -> }
         assert_eq!(
-            mapper.process_key(VirtualKey::Backslash, true),
+            mapper.process_key(KeyEvent::press(VirtualKey::Backslash, true)),
             KeyAction::Replace('}')
         );
     }
@@ -318,12 +1137,12 @@ mod tests {
         let mut mapper = Mapper::new();
         // / -> ; (ABNT2 Semicolon Position)
         assert_eq!(
-            mapper.process_key(VirtualKey::Slash, false),
+            mapper.process_key(KeyEvent::press(VirtualKey::Slash, false)),
             KeyAction::Replace(';')
         );
         // Shift+/ -> :
         assert_eq!(
-            mapper.process_key(VirtualKey::Slash, true),
+            mapper.process_key(KeyEvent::press(VirtualKey::Slash, true)),
             KeyAction::Replace(':')
         );
     }
@@ -336,14 +1155,14 @@ mod tests {
 
         // ' (unshifted) -> tilde dead key
         assert_eq!(
-            mapper.process_key(VirtualKey::Apostrophe, false),
+            mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false)),
             KeyAction::Suppress
         );
         assert_eq!(mapper.state(), &MapperState::PendingAccent(AccentType::Tilde));
 
         // Press 'a' -> should produce ã
         assert_eq!(
-            mapper.process_key(VirtualKey::Char('a'), false),
+            mapper.process_key(KeyEvent::press(VirtualKey::Char('a'), false)),
             KeyAction::Replace('ã')
         );
         assert_eq!(mapper.state(), &MapperState::Idle);
@@ -355,14 +1174,14 @@ mod tests {
 
         // Shift+' -> circumflex dead key
         assert_eq!(
-            mapper.process_key(VirtualKey::Apostrophe, true),
+            mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, true)),
             KeyAction::Suppress
         );
         assert_eq!(mapper.state(), &MapperState::PendingAccent(AccentType::Circumflex));
 
         // Press 'a' -> should produce â
         assert_eq!(
-            mapper.process_key(VirtualKey::Char('a'), false),
+            mapper.process_key(KeyEvent::press(VirtualKey::Char('a'), false)),
             KeyAction::Replace('â')
         );
         assert_eq!(mapper.state(), &MapperState::Idle);
@@ -374,14 +1193,14 @@ mod tests {
 
         // [ (unshifted) -> acute dead key
         assert_eq!(
-            mapper.process_key(VirtualKey::LeftBracket, false),
+            mapper.process_key(KeyEvent::press(VirtualKey::LeftBracket, false)),
             KeyAction::Suppress
         );
         assert_eq!(mapper.state(), &MapperState::PendingAccent(AccentType::Acute));
 
         // Press 'e' -> should produce é
         assert_eq!(
-            mapper.process_key(VirtualKey::Char('e'), false),
+            mapper.process_key(KeyEvent::press(VirtualKey::Char('e'), false)),
             KeyAction::Replace('é')
         );
     }
@@ -392,14 +1211,14 @@ mod tests {
 
         // Shift+[ -> grave dead key
         assert_eq!(
-            mapper.process_key(VirtualKey::LeftBracket, true),
+            mapper.process_key(KeyEvent::press(VirtualKey::LeftBracket, true)),
             KeyAction::Suppress
         );
         assert_eq!(mapper.state(), &MapperState::PendingAccent(AccentType::Grave));
 
         // Press 'a' -> should produce à
         assert_eq!(
-            mapper.process_key(VirtualKey::Char('a'), false),
+            mapper.process_key(KeyEvent::press(VirtualKey::Char('a'), false)),
             KeyAction::Replace('à')
         );
     }
@@ -411,11 +1230,11 @@ mod tests {
         let mut mapper = Mapper::new();
 
         // Press apostrophe (tilde dead key)
-        mapper.process_key(VirtualKey::Apostrophe, false);
+        mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false));
 
         // Press 'x' (non-combinable) -> should produce ~ followed by x
         assert_eq!(
-            mapper.process_key(VirtualKey::Char('x'), false),
+            mapper.process_key(KeyEvent::press(VirtualKey::Char('x'), false)),
             KeyAction::ReplaceMultiple(vec!['~', 'x'])
         );
     }
@@ -425,20 +1244,1111 @@ mod tests {
         let mut mapper = Mapper::new();
 
         // Press apostrophe (tilde dead key)
-        mapper.process_key(VirtualKey::Apostrophe, false);
+        mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false));
 
         // Press space -> should produce just ~
         assert_eq!(
-            mapper.process_key(VirtualKey::Space, false),
+            mapper.process_key(KeyEvent::press(VirtualKey::Space, false)),
             KeyAction::Replace('~')
         );
     }
 
+    #[test]
+    fn test_dead_key_enter_and_tab() {
+        let mut mapper = Mapper::new();
+
+        // The accent is typed, then Enter/Tab still does its job
+        mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false));
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Enter, false)),
+            KeyAction::ReplaceThenPass('~')
+        );
+        assert_eq!(mapper.state(), &MapperState::Idle);
+
+        mapper.process_key(KeyEvent::press(VirtualKey::LeftBracket, false));
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Tab, false)),
+            KeyAction::ReplaceThenPass('´')
+        );
+
+        // Without a pending accent they pass untouched
+        assert_eq!(mapper.process_key(KeyEvent::press(VirtualKey::Enter, false)), KeyAction::Pass);
+        assert_eq!(mapper.process_key(KeyEvent::press(VirtualKey::Tab, true)), KeyAction::Pass);
+    }
+
+    #[test]
+    fn test_combination_can_produce_text() {
+        let mut layout = Layout::abnt2();
+        layout.combinations.insert((AccentType::Tilde, 'e'), "e\u{303}".into());
+        let mut mapper = Mapper::with_layout(layout);
+
+        mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false));
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Char('E'), false)),
+            KeyAction::ReplaceStr("e\u{303}".to_string())
+        );
+        assert_eq!(
+            KeyAction::ReplaceStr("e\u{303}".to_string()).output(None),
+            vec!['e', '\u{303}']
+        );
+    }
+
+    #[test]
+    fn test_introspection_lists_active_tables() {
+        let mapper = Mapper::new();
+        let mappings: Vec<_> = mapper.mappings().collect();
+        assert_eq!(mappings.len(), 8);
+        assert!(mappings.contains(&((VirtualKey::Semicolon, false), 'ç')));
+        assert!(mappings.windows(2).all(|pair| pair[0].0 < pair[1].0));
+
+        let dead_keys: Vec<_> = mapper.dead_keys().collect();
+        assert_eq!(
+            dead_keys,
+            vec![
+                ((VirtualKey::Apostrophe, false), AccentType::Tilde),
+                ((VirtualKey::Apostrophe, true), AccentType::Circumflex),
+                ((VirtualKey::LeftBracket, false), AccentType::Acute),
+                ((VirtualKey::LeftBracket, true), AccentType::Grave),
+            ]
+        );
+        assert!(mapper.combinations().any(|entry| entry == ((AccentType::Tilde, 'a'), "ã")));
+
+        // Reverse mode flattens the dead keys
+        let reverse = Mapper::with_direction(Direction::Abnt2ToUs);
+        assert_eq!(reverse.dead_keys().count(), 0);
+        assert!(reverse.mappings().any(|entry| entry == ((VirtualKey::Apostrophe, false), '\'')));
+    }
+
+    #[test]
+    fn test_escape_cancels_pending_accent() {
+        let mut mapper = Mapper::new();
+
+        mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false));
+        assert_eq!(mapper.process_key(KeyEvent::press(VirtualKey::Escape, false)), KeyAction::Pass);
+        assert_eq!(mapper.state(), &MapperState::Idle);
+        assert_eq!(mapper.check_timeout(), None);
+
+        // The next letter isn't accented
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Char('A'), false)),
+            KeyAction::Pass
+        );
+    }
+
+    #[test]
+    fn test_dead_key_digits_and_punctuation() {
+        let mut mapper = Mapper::new();
+
+        // Unmapped keys are typed by the OS after the accent
+        mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false));
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Digit('1'), false)),
+            KeyAction::ReplaceThenPass('~')
+        );
+        mapper.process_key(KeyEvent::press(VirtualKey::LeftBracket, false));
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Comma, false)),
+            KeyAction::ReplaceMultiple(vec!['´', ','])
+        );
+
+        // Remapped keys keep their ABNT2 output
+        mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false));
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Semicolon, false)),
+            KeyAction::ReplaceMultiple(vec!['~', 'ç'])
+        );
+        assert_eq!(mapper.state(), &MapperState::Idle);
+
+        // Another dead key flushes the first accent and arms its own
+        mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false));
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::LeftBracket, true)),
+            KeyAction::Replace('~')
+        );
+        assert_eq!(mapper.state(), &MapperState::PendingAccent(AccentType::Grave));
+    }
+
+    // === Reverse Mode (ABNT2 -> US) Tests ===
+
+    #[test]
+    fn test_reverse_mode_position_mapping() {
+        let mut mapper = Mapper::with_direction(Direction::Abnt2ToUs);
+        // ç key -> ;
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Semicolon, false)),
+            KeyAction::Replace(';')
+        );
+        // Shift + ;: key -> ?
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Slash, true)),
+            KeyAction::Replace('?')
+        );
+    }
+
+    #[test]
+    fn test_reverse_mode_flattens_dead_keys() {
+        let mut mapper = Mapper::with_direction(Direction::Abnt2ToUs);
+
+        // ~^ dead key -> plain apostrophe, no pending accent
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false)),
+            KeyAction::Replace('\'')
+        );
+        assert_eq!(mapper.state(), &MapperState::Idle);
+
+        // Shift + ´` dead key -> plain {
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::LeftBracket, true)),
+            KeyAction::Replace('{')
+        );
+        assert_eq!(mapper.state(), &MapperState::Idle);
+    }
+
+    #[test]
+    fn test_effective_layout_follows_features_and_triggers() {
+        let mut mapper = Mapper::new();
+        let layout = mapper.effective_layout();
+        assert_eq!(layout.dead_keys, Layout::abnt2().dead_keys);
+        assert_eq!(layout.positions.get(&(VirtualKey::Semicolon, false)), Some(&'ç'));
+
+        let features = MappingFeatures { positions: false, tilde: false, ..MappingFeatures::default() };
+        mapper.set_features(features);
+        mapper.set_dead_key_triggers([((VirtualKey::Apostrophe, false), AccentType::Acute)]);
+        let layout = mapper.effective_layout();
+        assert_eq!(layout.dead_keys.len(), 1);
+        let dead_key = layout.dead_keys.get(&(VirtualKey::Apostrophe, false));
+        assert_eq!(dead_key, Some(&AccentType::Acute));
+        assert!(!layout.positions.contains_key(&(VirtualKey::Semicolon, false)));
+        assert!(!layout.combinations.keys().any(|&(accent, _)| accent == AccentType::Tilde));
+        let combined = layout.combinations.get(&(AccentType::Acute, 'e'));
+        assert_eq!(combined.map(String::as_str), Some("é"));
+    }
+
+    #[test]
+    fn test_set_direction_drops_pending_accent() {
+        let mut mapper = Mapper::new();
+        mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false));
+        assert_eq!(mapper.state(), &MapperState::PendingAccent(AccentType::Tilde));
+
+        mapper.set_direction(Direction::Abnt2ToUs);
+        assert_eq!(mapper.state(), &MapperState::Idle);
+        assert_eq!(mapper.direction(), Direction::Abnt2ToUs);
+
+        mapper.set_direction(Direction::UsToAbnt2);
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Semicolon, false)),
+            KeyAction::Replace('ç')
+        );
+    }
+
+    #[test]
+    fn test_custom_layout() {
+        let mut layout = Layout::new("Custom");
+        layout.positions.insert((VirtualKey::Char('Q'), false), '@');
+        let mut mapper = Mapper::with_layout(layout);
+
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Char('Q'), false)),
+            KeyAction::Replace('@')
+        );
+        // No ABNT2 fallback for keys the custom layout leaves alone
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Semicolon, false)),
+            KeyAction::Pass
+        );
+    }
+
+    // === Compose Tests ===
+
+    #[test]
+    fn test_compose_sequences_are_sorted() {
+        let mapper = compose_mapper();
+        let sequences: Vec<_> = mapper.compose().sequences().collect();
+        assert_eq!(
+            sequences,
+            vec![
+                ("--".to_string(), "–"),
+                ("oc".to_string(), "©"),
+                ("tm".to_string(), "™"),
+            ]
+        );
+    }
+
+    fn compose_mapper() -> Mapper {
+        let mut table = ComposeTable::new();
+        table.insert("oc", "©");
+        table.insert("--", "–");
+        table.insert("tm", "™");
+        let mut mapper = Mapper::new();
+        mapper.set_compose_table(table);
+        mapper
+    }
+
+    #[test]
+    fn test_compose_sequence() {
+        let mut mapper = compose_mapper();
+
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Compose, false)),
+            KeyAction::Suppress
+        );
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Char('O'), false)),
+            KeyAction::Suppress
+        );
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Char('C'), false)),
+            KeyAction::Replace('©')
+        );
+        assert_eq!(mapper.state(), &MapperState::Idle);
+
+        mapper.process_key(KeyEvent::press(VirtualKey::Compose, false));
+        mapper.process_key(KeyEvent::press(VirtualKey::Minus, false));
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Minus, false)),
+            KeyAction::Replace('–')
+        );
+    }
+
+    #[test]
+    fn test_compose_mismatch_types_sequence_literally() {
+        let mut mapper = compose_mapper();
+
+        mapper.process_key(KeyEvent::press(VirtualKey::Compose, false));
+        mapper.process_key(KeyEvent::press(VirtualKey::Char('O'), false));
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Char('X'), false)),
+            KeyAction::ReplaceMultiple(vec!['o', 'x'])
+        );
+        assert_eq!(mapper.state(), &MapperState::Idle);
+    }
+
+    #[test]
+    fn test_compose_cancel_and_abort() {
+        let mut mapper = compose_mapper();
+
+        // Compose twice cancels silently
+        mapper.process_key(KeyEvent::press(VirtualKey::Compose, false));
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Compose, false)),
+            KeyAction::Suppress
+        );
+        assert_eq!(mapper.state(), &MapperState::Idle);
+
+        // A key without a character aborts and passes through
+        mapper.process_key(KeyEvent::press(VirtualKey::Compose, false));
+        assert_eq!(mapper.process_key(KeyEvent::press(VirtualKey::Other, false)), KeyAction::Pass);
+        assert_eq!(mapper.state(), &MapperState::Idle);
+    }
+
+    #[test]
+    fn test_compose_abort_types_pending_characters_first() {
+        let mut mapper = compose_mapper();
+        mapper.process_key(KeyEvent::press(VirtualKey::Compose, false));
+        mapper.process_key(KeyEvent::press(VirtualKey::Char('O'), false));
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Enter, false)),
+            KeyAction::ReplaceThenPass('o')
+        );
+        assert_eq!(mapper.state(), &MapperState::Idle);
+
+        let mut table = ComposeTable::new();
+        table.insert("ooo", "∞");
+        mapper.set_compose_table(table);
+        mapper.process_key(KeyEvent::press(VirtualKey::Compose, false));
+        mapper.process_key(KeyEvent::press(VirtualKey::Char('O'), false));
+        mapper.process_key(KeyEvent::press(VirtualKey::Char('O'), false));
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Left, false)),
+            KeyAction::ReplaceMultipleThenPass(vec!['o', 'o'])
+        );
+    }
+
+    #[test]
+    fn test_compose_backspace_removes_last_character() {
+        let mut mapper = compose_mapper();
+        mapper.process_key(KeyEvent::press(VirtualKey::Compose, false));
+        mapper.process_key(KeyEvent::press(VirtualKey::Char('O'), false));
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Backspace, false)),
+            KeyAction::Suppress
+        );
+        assert_eq!(mapper.state(), &MapperState::Composing(Vec::new()));
+        mapper.process_key(KeyEvent::press(VirtualKey::Char('T'), false));
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Char('M'), false)),
+            KeyAction::Replace('™')
+        );
+
+        // With nothing held back, it takes back the Compose press
+        mapper.process_key(KeyEvent::press(VirtualKey::Compose, false));
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Backspace, false)),
+            KeyAction::Suppress
+        );
+        assert_eq!(mapper.state(), &MapperState::Idle);
+    }
+
+    #[test]
+    fn test_compose_sequence_with_cedilla() {
+        let mut table = ComposeTable::new();
+        table.insert("ç,", "¸");
+        let mut mapper = Mapper::new();
+        mapper.set_compose_table(table);
+
+        mapper.process_key(KeyEvent::press(VirtualKey::Compose, false));
+        // The key next to L types ç, and takes part in sequences as ç
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Semicolon, false)),
+            KeyAction::Suppress
+        );
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Comma, false)),
+            KeyAction::Replace('¸')
+        );
+    }
+
+    #[test]
+    fn test_compose_without_table_passes_through() {
+        let mut mapper = Mapper::new();
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Compose, false)),
+            KeyAction::Pass
+        );
+        assert_eq!(mapper.state(), &MapperState::Idle);
+    }
+
+    #[test]
+    fn test_compose_flushes_pending_accent() {
+        let mut mapper = compose_mapper();
+
+        mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false));
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Compose, false)),
+            KeyAction::Replace('~')
+        );
+        assert_eq!(mapper.state(), &MapperState::Composing(Vec::new()));
+    }
+
+    // === Auto-Repeat Tests ===
+
+    #[test]
+    fn test_repeat_of_replaced_key_repeats() {
+        let mut mapper = Mapper::new();
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Semicolon, false)),
+            KeyAction::Replace('ç')
+        );
+        assert_eq!(
+            mapper.process_key(repeated(KeyEvent::press(VirtualKey::Semicolon, false))),
+            KeyAction::Replace('ç')
+        );
+        assert_eq!(
+            mapper.process_key(repeated(KeyEvent::press(VirtualKey::Semicolon, false))),
+            KeyAction::Replace('ç')
+        );
+    }
+
+    #[test]
+    fn test_dead_keys_can_be_turned_off() {
+        let mut mapper = Mapper::new();
+        mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false));
+        mapper.set_dead_keys(false);
+        assert_eq!(mapper.state(), &MapperState::Idle);
+
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false)),
+            KeyAction::Replace('~')
+        );
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Char('a'), false)),
+            KeyAction::Pass
+        );
+        assert_eq!(
+            mapper.process_key(repeated(KeyEvent::press(VirtualKey::Apostrophe, false))),
+            KeyAction::Replace('~')
+        );
+        // Other mappings are unaffected
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Semicolon, false)),
+            KeyAction::Replace('ç')
+        );
+
+        mapper.set_dead_keys(true);
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false)),
+            KeyAction::Suppress
+        );
+    }
+
+    #[test]
+    fn test_accents_can_be_turned_off_one_by_one() {
+        let mut mapper = Mapper::new();
+        mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false));
+        mapper.set_features(MappingFeatures { tilde: false, ..MappingFeatures::default() });
+        assert_eq!(mapper.state(), &MapperState::Idle);
+
+        // The key types what's printed on it, and its repeats aren't swallowed
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false)),
+            KeyAction::Pass
+        );
+        assert_eq!(
+            mapper.process_key(repeated(KeyEvent::press(VirtualKey::Apostrophe, false))),
+            KeyAction::Pass
+        );
+        // Shift+' is the circumflex, still on
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, true)),
+            KeyAction::Suppress
+        );
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Char('a'), false)),
+            KeyAction::Replace('â')
+        );
+        assert!(mapper.dead_keys().all(|(_, accent)| accent != AccentType::Tilde));
+    }
+
+    #[test]
+    fn test_positions_can_be_turned_off() {
+        let mut mapper = Mapper::new();
+        mapper.set_features(MappingFeatures { positions: false, ..MappingFeatures::default() });
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Semicolon, false)),
+            KeyAction::Pass
+        );
+        assert_eq!(mapper.mappings().count(), 0);
+
+        // Dead keys still work, and flush before the key's own character
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::LeftBracket, false)),
+            KeyAction::Suppress
+        );
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Char('e'), false)),
+            KeyAction::Replace('é')
+        );
+        mapper.process_key(KeyEvent::press(VirtualKey::LeftBracket, false));
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Semicolon, false)),
+            KeyAction::ReplaceThenPass('´')
+        );
+    }
+
+    #[test]
+    fn test_altgr_layer_can_be_left_to_the_os() {
+        let mut mapper = Mapper::new();
+        mapper.set_features(MappingFeatures { altgr: false, ..MappingFeatures::default() });
+        let altgr = Modifiers { altgr: true, alt: true, ..Modifiers::default() };
+        assert_eq!(
+            mapper.process_key(KeyEvent::down(VirtualKey::Semicolon, altgr)),
+            KeyAction::Pass
+        );
+        assert_eq!(
+            mapper.process_key(KeyEvent::down(VirtualKey::Semicolon, Modifiers::default())),
+            KeyAction::Replace('ç')
+        );
+    }
+
+    #[test]
+    fn test_dead_keys_can_be_moved() {
+        let mut mapper = Mapper::new();
+        mapper.set_dead_key_triggers([
+            ((VirtualKey::Apostrophe, false), AccentType::Acute),
+            ((VirtualKey::LeftBracket, false), AccentType::Tilde),
+            ((VirtualKey::Digit('6'), true), AccentType::Circumflex),
+        ]);
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false)),
+            KeyAction::Suppress
+        );
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Char('E'), false)),
+            KeyAction::Replace('é')
+        );
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::LeftBracket, false)),
+            KeyAction::Suppress
+        );
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Char('A'), false)),
+            KeyAction::Replace('ã')
+        );
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Digit('6'), true)),
+            KeyAction::Suppress
+        );
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Char('O'), false)),
+            KeyAction::Replace('ô')
+        );
+
+        // Keys that lost their dead key type what's printed on them
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, true)),
+            KeyAction::Pass
+        );
+        assert_eq!(mapper.dead_keys().count(), 3);
+
+        // Reverse mode has no dead keys, moved or not
+        mapper.set_direction(Direction::Abnt2ToUs);
+        assert_eq!(mapper.dead_keys().count(), 0);
+
+        mapper.set_direction(Direction::UsToAbnt2);
+        mapper.set_dead_key_triggers([]);
+        assert_eq!(mapper.dead_keys().count(), Layout::abnt2().dead_keys.len());
+    }
+
+    #[test]
+    fn test_combining_marks_for_letters_without_precomposed_form() {
+        let mut mapper = Mapper::new();
+        mapper.set_features(MappingFeatures { combining_marks: true, ..MappingFeatures::default() });
+        mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false));
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Char('X'), false)),
+            KeyAction::ReplaceStr("x\u{0303}".to_string())
+        );
+        // Precomposed characters are still preferred
+        mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false));
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Char('A'), false)),
+            KeyAction::Replace('ã')
+        );
+        // Punctuation keeps the typewriter fallback
+        mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false));
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Comma, false)),
+            KeyAction::ReplaceMultiple(vec!['~', ','])
+        );
+    }
+
+    fn with_semantics(semantics: DeadKeySemantics) -> Mapper {
+        let mut mapper = Mapper::new();
+        mapper.set_features(MappingFeatures {
+            dead_key_semantics: semantics,
+            ..MappingFeatures::default()
+        });
+        mapper
+    }
+
+    #[test]
+    fn test_dead_key_semantics_acute_and_space() {
+        // ABNT2 types the spacing acute, US-International the apostrophe
+        let mut abnt2 = with_semantics(DeadKeySemantics::Abnt2);
+        assert_eq!(abnt2.map_text("[ "), "´");
+        let mut international = with_semantics(DeadKeySemantics::UsInternational);
+        assert_eq!(international.map_text("[ "), "'");
+        // Accents on other keys are the same on both
+        assert_eq!(international.map_text("' \" { "), "~^`");
+    }
+
+    #[test]
+    fn test_dead_key_semantics_acute_and_consonant() {
+        // Both type the accent, then the consonant; only the accent differs
+        let mut abnt2 = with_semantics(DeadKeySemantics::Abnt2);
+        assert_eq!(abnt2.map_text("[t[1["), "´t´1´");
+        let mut international = with_semantics(DeadKeySemantics::UsInternational);
+        assert_eq!(international.map_text("[t[1["), "'t'1'");
+        // Vowels combine the same way
+        assert_eq!(international.map_text("[a[E"), "áÉ");
+    }
+
+    #[test]
+    fn test_dead_key_semantics_acute_and_c() {
+        // ç has its own key on ABNT2, so ´ then c doesn't make one
+        let mut abnt2 = with_semantics(DeadKeySemantics::Abnt2);
+        assert_eq!(abnt2.map_text("[c[C"), "´c´C");
+        // On US-International ' then c is the usual way to type ç
+        let mut international = with_semantics(DeadKeySemantics::UsInternational);
+        assert_eq!(international.map_text("[c[C"), "çÇ");
+        assert_eq!(international.map_text(";"), "ç");
+    }
+
+    #[test]
+    fn test_apostrophe_c_cedilla() {
+        let mut mapper = Mapper::new();
+        mapper.set_features(MappingFeatures {
+            apostrophe_c_cedilla: Some(true),
+            ..MappingFeatures::default()
+        });
+        assert_eq!(mapper.map_text("[c[C"), "çÇ");
+        // Nothing else about the acute changes
+        assert_eq!(mapper.map_text("[ [t"), "´´t");
+    }
+
+    #[test]
+    fn test_apostrophe_c_cedilla_off_with_us_international() {
+        let mut mapper = Mapper::new();
+        mapper.set_features(MappingFeatures {
+            dead_key_semantics: DeadKeySemantics::UsInternational,
+            apostrophe_c_cedilla: Some(false),
+            ..MappingFeatures::default()
+        });
+        assert_eq!(mapper.map_text("[c[C"), "'c'C");
+        // The rest of US-International stays
+        assert_eq!(mapper.map_text("[ [a"), "'á");
+        assert!(!mapper.effective_layout().combinations.contains_key(&(AccentType::Acute, 'c')));
+    }
+
+    #[test]
+    fn test_accents_on_y_and_w() {
+        let mut mapper = Mapper::new();
+        for (dead_key, shift_dead, letter, shift, expected) in [
+            (VirtualKey::LeftBracket, false, 'Y', false, 'ý'),
+            (VirtualKey::LeftBracket, true, 'Y', true, 'Ỳ'),
+            (VirtualKey::Apostrophe, true, 'W', false, 'ŵ'),
+            (VirtualKey::Apostrophe, false, 'Y', false, 'ỹ'),
+            (VirtualKey::LeftBracket, false, 'W', true, 'Ẃ'),
+        ] {
+            mapper.process_key(KeyEvent::press(dead_key, shift_dead));
+            assert_eq!(
+                mapper.process_key(KeyEvent::press(VirtualKey::Char(letter), shift)),
+                KeyAction::Replace(expected)
+            );
+        }
+
+        // There's no precomposed w with tilde: typewriter or combining mark
+        mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false));
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Char('W'), false)),
+            KeyAction::ReplaceMultiple(vec!['~', 'w'])
+        );
+        mapper.set_features(MappingFeatures { combining_marks: true, ..MappingFeatures::default() });
+        mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false));
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Char('W'), false)),
+            KeyAction::ReplaceStr("w\u{0303}".to_string())
+        );
+    }
+
+    #[test]
+    fn test_map_text() {
+        let mut mapper = Mapper::new();
+        assert_eq!(mapper.map_text("n'ao a;[ucar, [e\"ca"), "não açúcar, é^ca");
+        // Keys after an accent that don't take it are all still typed
+        assert_eq!(mapper.map_text("'\n'1'/ '"), "~\n~1~; ~");
+        assert_eq!(mapper.state(), &MapperState::Idle);
+        // Text the US layout can't type goes around the mapper
+        assert_eq!(mapper.map_text("ß'€a"), "ß€ã");
+    }
+
+    #[test]
+    fn test_map_keys() {
+        let mut mapper = Mapper::new();
+        let keys = [
+            (VirtualKey::LeftBracket, true),
+            (VirtualKey::Char('A'), true),
+            (VirtualKey::Tab, false),
+            (VirtualKey::Semicolon, true),
+            (VirtualKey::Escape, false),
+        ];
+        assert_eq!(mapper.map_keys(keys), "À\tÇ");
+    }
+
+    #[test]
+    fn test_chained_accents_go_on_one_letter() {
+        let chain = MappingFeatures { chain_accents: true, ..MappingFeatures::default() };
+        let mut mapper = Mapper::new();
+        mapper.set_features(MappingFeatures { combining_marks: true, ..chain });
+
+        // ^ then ´ then a: â precomposed, then a combining acute (ấ)
+        mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, true));
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::LeftBracket, false)),
+            KeyAction::Suppress
+        );
+        assert_eq!(mapper.state(), &MapperState::PendingAccent(AccentType::Acute));
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Char('A'), false)),
+            KeyAction::ReplaceStr("â\u{0301}".to_string())
+        );
+        assert_eq!(mapper.state(), &MapperState::Idle);
+
+        // Without combining marks, the accents that don't fit are typed first
+        mapper.set_features(chain);
+        mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, true));
+        mapper.process_key(KeyEvent::press(VirtualKey::LeftBracket, false));
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Char('A'), false)),
+            KeyAction::ReplaceMultiple(vec!['´', 'â'])
+        );
+
+        // A chain flushed by Enter types every accent before Enter acts
+        mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false));
+        mapper.process_key(KeyEvent::press(VirtualKey::LeftBracket, false));
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Enter, false)),
+            KeyAction::ReplaceMultipleThenPass(vec!['~', '´'])
+        );
+
+        // The same accent twice doesn't chain
+        mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false));
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false)),
+            KeyAction::Replace('~')
+        );
+        mapper.reset();
+
+        // Esc drops the whole chain
+        mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false));
+        mapper.process_key(KeyEvent::press(VirtualKey::LeftBracket, false));
+        assert_eq!(mapper.process_key(KeyEvent::press(VirtualKey::Escape, false)), KeyAction::Pass);
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Char('A'), false)),
+            KeyAction::Pass
+        );
+    }
+
+    #[test]
+    fn test_repeat_of_dead_key_is_swallowed() {
+        let mut mapper = Mapper::new();
+        mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false));
+        assert_eq!(
+            mapper.process_key(repeated(KeyEvent::press(VirtualKey::Apostrophe, false))),
+            KeyAction::Suppress
+        );
+        // Shift pressed while holding the key doesn't change the armed accent
+        assert_eq!(
+            mapper.process_key(repeated(KeyEvent::press(VirtualKey::Apostrophe, true))),
+            KeyAction::Suppress
+        );
+        assert_eq!(mapper.state(), &MapperState::PendingAccent(AccentType::Tilde));
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Char('A'), false)),
+            KeyAction::Replace('ã')
+        );
+    }
+
+    #[test]
+    fn test_repeat_of_compose_is_swallowed() {
+        let mut mapper = compose_mapper();
+        mapper.process_key(KeyEvent::press(VirtualKey::Compose, false));
+        assert_eq!(
+            mapper.process_key(repeated(KeyEvent::press(VirtualKey::Compose, false))),
+            KeyAction::Suppress
+        );
+        assert_eq!(mapper.state(), &MapperState::Composing(Vec::new()));
+    }
+
+    // === Key Event Tests ===
+
+    #[test]
+    fn test_key_up_passes_through() {
+        let mut mapper = Mapper::new();
+        let release = KeyEvent::up(VirtualKey::Semicolon, Modifiers::default());
+        assert_eq!(mapper.process_key(release), KeyAction::Pass);
+
+        // Releasing the dead key leaves the accent armed
+        mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false));
+        let release = KeyEvent::up(VirtualKey::Apostrophe, Modifiers::default());
+        assert_eq!(mapper.process_key(release), KeyAction::Pass);
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Char('A'), false)),
+            KeyAction::Replace('ã')
+        );
+    }
+
+    #[test]
+    fn test_timeout_counts_from_event_timestamp() {
+        let mut mapper = Mapper::new();
+        let pressed = Instant::now();
+        let dead_key = KeyEvent::press(VirtualKey::Apostrophe, false);
+        mapper.process_key(KeyEvent { timestamp: pressed, ..dead_key });
+
+        assert_eq!(mapper.check_timeout_at(pressed + ACCENT_TIMEOUT / 2), None);
+        assert_eq!(
+            mapper.check_timeout_at(pressed + ACCENT_TIMEOUT),
+            Some(KeyAction::Replace('~'))
+        );
+        assert_eq!(mapper.state(), &MapperState::Idle);
+
+        // A clock reading from before the key press never times out
+        mapper.process_key(KeyEvent { timestamp: pressed + ACCENT_TIMEOUT, ..dead_key });
+        assert_eq!(mapper.check_timeout_at(pressed), None);
+    }
+
+    const ALT: Modifiers = Modifiers {
+        shift: false,
+        alt: true,
+        altgr: false,
+        caps_lock: false,
+    };
+
+    #[test]
+    fn test_alt_code_sequence_bypasses_mapper() {
+        let mut mapper = Mapper::new();
+        mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false));
+
+        // Alt+0231 on the numpad: digits aren't positions, and nothing held
+        // with Alt may be remapped
+        for key in [VirtualKey::Other, VirtualKey::Semicolon, VirtualKey::Char('A')] {
+            assert_eq!(mapper.process_key(KeyEvent::down(key, ALT)), KeyAction::Pass);
+            assert_eq!(mapper.process_key(repeated(KeyEvent::down(key, ALT))), KeyAction::Pass);
+        }
+
+        // The accent armed before the Alt code still applies afterwards
+        assert_eq!(mapper.state(), &MapperState::PendingAccent(AccentType::Tilde));
+        assert_eq!(
+            mapper.process_key(KeyEvent::down(VirtualKey::Char('A'), Modifiers::default())),
+            KeyAction::Replace('ã')
+        );
+    }
+
+    #[test]
+    fn test_alt_chord_keeps_compose_sequence() {
+        let mut mapper = compose_mapper();
+        mapper.process_key(KeyEvent::press(VirtualKey::Compose, false));
+        mapper.process_key(KeyEvent::press(VirtualKey::Char('O'), false));
+        assert_eq!(mapper.process_key(KeyEvent::down(VirtualKey::Char('F'), ALT)), KeyAction::Pass);
+        assert_eq!(mapper.state(), &MapperState::Composing(vec!['o']));
+    }
+
+    #[test]
+    fn test_altgr_is_not_an_alt_chord() {
+        let altgr = Modifiers {
+            altgr: true,
+            ..ALT
+        };
+        let mut mapper = Mapper::new();
+        assert_eq!(
+            mapper.process_key(KeyEvent::down(VirtualKey::Semicolon, altgr)),
+            KeyAction::Replace('ç')
+        );
+
+        let shift = Modifiers {
+            shift: true,
+            ..Modifiers::default()
+        };
+        assert_eq!(
+            mapper.process_key(KeyEvent::down(VirtualKey::Semicolon, shift)),
+            KeyAction::Replace('Ç')
+        );
+    }
+
+    #[test]
+    fn test_ordinal_indicators_on_altgr() {
+        let mut mapper = Mapper::new();
+        let altgr = Modifiers { altgr: true, ..ALT };
+        let shift_altgr = Modifiers { shift: true, ..altgr };
+        assert_eq!(
+            mapper.process_key(KeyEvent::down(VirtualKey::RightBracket, altgr)),
+            KeyAction::Replace('ª')
+        );
+        assert_eq!(
+            mapper.process_key(KeyEvent::down(VirtualKey::Backslash, shift_altgr)),
+            KeyAction::Replace('º')
+        );
+        // Without AltGr the keys are the brackets
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Backslash, false)),
+            KeyAction::Replace(']')
+        );
+
+        // A pending accent is typed first
+        mapper.process_key(KeyEvent::press(VirtualKey::LeftBracket, false));
+        assert_eq!(
+            mapper.process_key(KeyEvent::down(VirtualKey::Backslash, altgr)),
+            KeyAction::ReplaceMultiple(vec!['´', 'º'])
+        );
+    }
+
+    #[test]
+    fn test_altgr_symbols() {
+        let mut mapper = Mapper::new();
+        let altgr = Modifiers { altgr: true, ..ALT };
+        for (key, symbol) in [
+            (VirtualKey::Digit('1'), '¹'),
+            (VirtualKey::Digit('2'), '²'),
+            (VirtualKey::Digit('3'), '³'),
+            (VirtualKey::Digit('4'), '£'),
+            (VirtualKey::Digit('5'), '¢'),
+            (VirtualKey::Digit('6'), '¬'),
+            (VirtualKey::Equals, '§'),
+            (VirtualKey::Char('E'), '°'),
+            (VirtualKey::Char('C'), '₢'),
+        ] {
+            assert_eq!(mapper.process_key(KeyEvent::down(key, altgr)), KeyAction::Replace(symbol));
+        }
+
+        // AltGr + E after a dead key is the degree sign, not é
+        mapper.process_key(KeyEvent::press(VirtualKey::LeftBracket, false));
+        assert_eq!(
+            mapper.process_key(KeyEvent::down(VirtualKey::Char('E'), altgr)),
+            KeyAction::ReplaceMultiple(vec!['´', '°'])
+        );
+        // Keys without an AltGr symbol are remapped as usual
+        assert_eq!(
+            mapper.process_key(KeyEvent::down(VirtualKey::Char('R'), altgr)),
+            KeyAction::Pass
+        );
+    }
+
+    #[test]
+    fn test_slash_question_placement() {
+        let altgr = Modifiers { altgr: true, ..ALT };
+        let shift_altgr = Modifiers { shift: true, ..altgr };
+        let mut mapper = Mapper::new();
+        assert_eq!(
+            mapper.process_key(KeyEvent::down(VirtualKey::Char('Q'), altgr)),
+            KeyAction::Replace('/')
+        );
+        assert_eq!(
+            mapper.process_key(KeyEvent::down(VirtualKey::Char('W'), altgr)),
+            KeyAction::Replace('?')
+        );
+        assert_eq!(mapper.map_text("/?"), ";:");
+
+        mapper.set_features(MappingFeatures {
+            slash_question: SlashPlacement::UsKey,
+            ..MappingFeatures::default()
+        });
+        assert_eq!(mapper.map_text("/?"), "/?");
+        assert_eq!(
+            mapper.process_key(KeyEvent::down(VirtualKey::Slash, altgr)),
+            KeyAction::Replace(';')
+        );
+        assert_eq!(
+            mapper.process_key(KeyEvent::down(VirtualKey::Slash, shift_altgr)),
+            KeyAction::Replace(':')
+        );
+        assert_eq!(
+            mapper.process_key(KeyEvent::down(VirtualKey::Char('Q'), altgr)),
+            KeyAction::Pass
+        );
+
+        // An ABNT2 keyboard has the key, so reverse mode leaves it alone
+        let mut reverse = Mapper::with_direction(Direction::Abnt2ToUs);
+        assert_eq!(
+            reverse.process_key(KeyEvent::down(VirtualKey::Char('Q'), altgr)),
+            KeyAction::Pass
+        );
+    }
+
+    #[test]
+    fn test_ordinal_indicators_by_compose() {
+        let mut mapper = Mapper::new();
+        mapper.set_compose_table(ComposeTable::from(&ComposeConfig::default().sequences));
+        for (letter, shift, ordinal) in [('O', false, 'º'), ('A', false, 'ª'), ('O', true, 'º')] {
+            mapper.process_key(KeyEvent::press(VirtualKey::Compose, false));
+            mapper.process_key(KeyEvent::press(VirtualKey::Char(letter), shift));
+            assert_eq!(
+                mapper.process_key(KeyEvent::press(VirtualKey::Minus, true)),
+                KeyAction::Replace(ordinal)
+            );
+        }
+    }
+
+    /// Shift and Caps Lock as held while typing
+    fn shift_caps(shift: bool, caps_lock: bool) -> Modifiers {
+        Modifiers { shift, caps_lock, ..Modifiers::default() }
+    }
+
+    #[test]
+    fn test_caps_lock_on_mapped_letters() {
+        let mut mapper = Mapper::new();
+        // Caps Lock changes ç like any other letter; Shift undoes it
+        for (shift, caps_lock, expected) in
+            [(false, false, 'ç'), (true, false, 'Ç'), (false, true, 'Ç'), (true, true, 'ç')]
+        {
+            let event = KeyEvent::down(VirtualKey::Semicolon, shift_caps(shift, caps_lock));
+            let action = mapper.process_key(event);
+            assert_eq!(action, KeyAction::Replace(expected), "shift={} caps={}", shift, caps_lock);
+            let repeat = mapper.process_key(repeated(event));
+            assert_eq!(repeat, KeyAction::Replace(expected));
+        }
+
+        // Punctuation positions ignore Caps Lock
+        let event = KeyEvent::down(VirtualKey::RightBracket, shift_caps(false, true));
+        let action = mapper.process_key(event);
+        assert_eq!(action, KeyAction::Replace('['));
+    }
+
+    #[test]
+    fn test_caps_lock_with_dead_keys() {
+        // (Shift on the dead key, Shift on the letter, Caps Lock, output)
+        let matrix = [
+            (false, false, false, 'á'),
+            (false, true, false, 'Á'),
+            (false, false, true, 'Á'),
+            (false, true, true, 'á'),
+            // Shift on the dead key picks the accent, not the case
+            (true, false, false, 'à'),
+            (true, true, false, 'À'),
+            (true, false, true, 'À'),
+            (true, true, true, 'à'),
+        ];
+        let mut mapper = Mapper::new();
+        for (shift_dead, shift, caps_lock, expected) in matrix {
+            let dead_key = KeyEvent::down(VirtualKey::LeftBracket, shift_caps(shift_dead, caps_lock));
+            assert_eq!(mapper.process_key(dead_key), KeyAction::Suppress);
+            let letter = KeyEvent::down(VirtualKey::Char('A'), shift_caps(shift, caps_lock));
+            let action = mapper.process_key(letter);
+            assert_eq!(
+                action,
+                KeyAction::Replace(expected),
+                "shift_dead={} shift={} caps={}",
+                shift_dead,
+                shift,
+                caps_lock
+            );
+        }
+    }
+
+    #[test]
+    fn test_caps_lock_after_dead_key_on_non_letters() {
+        let mut mapper = Mapper::new();
+        let caps = shift_caps(false, true);
+
+        // The mapped letter after a stray accent follows Caps Lock too
+        mapper.process_key(KeyEvent::down(VirtualKey::Apostrophe, caps));
+        let action = mapper.process_key(KeyEvent::down(VirtualKey::Semicolon, caps));
+        assert_eq!(action, KeyAction::ReplaceMultiple(vec!['~', 'Ç']));
+
+        // A letter without a precomposed form is typed after the accent
+        mapper.process_key(KeyEvent::down(VirtualKey::Apostrophe, caps));
+        let action = mapper.process_key(KeyEvent::down(VirtualKey::Char('X'), caps));
+        assert_eq!(action, KeyAction::ReplaceMultiple(vec!['~', 'X']));
+
+        // Space types the accent alone
+        mapper.process_key(KeyEvent::down(VirtualKey::Apostrophe, caps));
+        assert_eq!(
+            mapper.process_key(KeyEvent::down(VirtualKey::Space, caps)),
+            KeyAction::Replace('~')
+        );
+    }
+
+    #[test]
+    fn test_source_layout_translates_positions_only() {
+        let mut mapper = Mapper::new();
+        mapper.set_source_layout(SourceLayout::Dvorak);
+
+        // Dvorak S sits where US has ;, the ABNT2 ç position
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Char('S'), false)),
+            KeyAction::Replace('ç')
+        );
+        // Dvorak - sits where US has ', the ABNT2 tilde dead key
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Minus, false)),
+            KeyAction::Suppress
+        );
+        // The accented letter is the one the keyboard reported
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Char('O'), false)),
+            KeyAction::Replace('õ')
+        );
+        // Letters moved onto unmapped positions still type themselves
+        assert_eq!(
+            mapper.process_key(KeyEvent::press(VirtualKey::Char('E'), false)),
+            KeyAction::Pass
+        );
+    }
+
     #[test]
     fn test_passthrough_unhandled_keys() {
         let mut mapper = Mapper::new();
         assert_eq!(
-            mapper.process_key(VirtualKey::Other, false),
+            mapper.process_key(KeyEvent::press(VirtualKey::Other, false)),
             KeyAction::Pass
         );
     }
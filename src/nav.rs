@@ -0,0 +1,185 @@
+//! Navigation layer: arrows on the home row
+//!
+//! With `enabled = true` under `[nav]`, holding Caps Lock (or Tab, with
+//! `trigger = "tab"`) turns a few letter keys into the navigation block, so
+//! the cursor moves without leaving the home row:
+//!
+//! ```text
+//! keys = "hjkl"                 keys = "ijkl"
+//! y u i o   Home PgUp PgDn End    y u i o   PgUp Home Up End
+//! h j k l   Left Down Up Right    h j k l   PgDn Left Down Right
+//! ```
+//!
+//! Shift still selects (Shift+Left); Ctrl and Alt chords go through as
+//! usual. The trigger does its own job when tapped: released without a key
+//! pressed meanwhile, it toggles Caps Lock or types a Tab. A key pressed
+//! while it's held, nav key or not, makes it a held trigger, so typing a Tab
+//! and the next letter at once (rollover) loses the Tab; Caps Lock, rarely
+//! pressed while typing, is the default for that reason.
+
+use crate::config::{NavConfig, NavKeys, NavTrigger};
+use crate::mapper::VirtualKey;
+use crate::pipeline::KeyInput;
+
+/// Letters of the hjkl layout (vim) and the keys they press
+const HJKL: &[(char, VirtualKey)] = &[
+    ('H', VirtualKey::Left),
+    ('J', VirtualKey::Down),
+    ('K', VirtualKey::Up),
+    ('L', VirtualKey::Right),
+    ('Y', VirtualKey::Home),
+    ('U', VirtualKey::PageUp),
+    ('I', VirtualKey::PageDown),
+    ('O', VirtualKey::End),
+];
+
+/// Letters of the ijkl layout (an inverted T) and the keys they press
+const IJKL: &[(char, VirtualKey)] = &[
+    ('I', VirtualKey::Up),
+    ('J', VirtualKey::Left),
+    ('K', VirtualKey::Down),
+    ('L', VirtualKey::Right),
+    ('U', VirtualKey::Home),
+    ('O', VirtualKey::End),
+    ('Y', VirtualKey::PageUp),
+    ('H', VirtualKey::PageDown),
+];
+
+/// The nav keys over the letter block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NavLayer {
+    keys: NavKeys,
+}
+
+impl NavLayer {
+    /// The layer `config` describes, if it's turned on
+    pub fn from_config(config: &NavConfig) -> Option<Self> {
+        config.enabled.then_some(Self { keys: config.keys })
+    }
+
+    /// What a key press moves with, if the trigger is held and the key is
+    /// part of the layer
+    pub fn key(&self, input: &KeyInput) -> Option<VirtualKey> {
+        if !input.nav || input.shortcut || input.event.modifiers.is_alt_chord() {
+            return None;
+        }
+        let VirtualKey::Char(letter) = input.event.key else {
+            return None;
+        };
+        let table = match self.keys {
+            NavKeys::Hjkl => HJKL,
+            NavKeys::Ijkl => IJKL,
+        };
+        table.iter().find(|(c, _)| *c == letter).map(|&(_, key)| key)
+    }
+}
+
+/// Key that holds the layer up, for platforms to recognize and to press
+/// when it's tapped
+pub fn trigger_key(trigger: NavTrigger) -> VirtualKey {
+    match trigger {
+        NavTrigger::CapsLock => VirtualKey::CapsLock,
+        NavTrigger::Tab => VirtualKey::Tab,
+    }
+}
+
+/// Whether the trigger is held, and whether it was used while held
+///
+/// Kept by the platform next to the modifiers it tracks; it tells the
+/// pipeline through `KeyInput::nav`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NavHold {
+    held: bool,
+    used: bool,
+}
+
+impl NavHold {
+    /// Whether the trigger is held down for the layer
+    pub fn is_held(&self) -> bool {
+        self.held
+    }
+
+    /// The trigger went down (or repeats); returns true if the layer takes
+    /// it and the key must be held back
+    ///
+    /// Pressed with Ctrl, Alt or the Windows key (`chord`), it's left alone
+    /// (Alt+Tab), and so are the repeats of a press that was.
+    pub fn trigger_down(&mut self, repeat: bool, chord: bool) -> bool {
+        if !self.held && (repeat || chord) {
+            return false;
+        }
+        if !self.held {
+            self.held = true;
+            self.used = false;
+        }
+        true
+    }
+
+    /// The trigger went up; returns true if it was tapped on its own and
+    /// must do its own job now
+    pub fn trigger_up(&mut self) -> bool {
+        let tapped = self.held && !self.used;
+        *self = Self::default();
+        tapped
+    }
+
+    /// Another key went down (not a modifier)
+    pub fn key_down(&mut self) {
+        if self.held {
+            self.used = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interceptor::{KeyEvent, Modifiers};
+
+    fn held(key: VirtualKey, modifiers: Modifiers) -> KeyInput {
+        KeyInput { nav: true, ..KeyInput::new(KeyEvent::down(key, modifiers)) }
+    }
+
+    #[test]
+    fn test_layouts() {
+        let hjkl = NavLayer::from_config(&NavConfig { enabled: true, ..NavConfig::default() })
+            .unwrap();
+        let ijkl = NavLayer { keys: NavKeys::Ijkl };
+        let none = Modifiers::default();
+        assert_eq!(hjkl.key(&held(VirtualKey::Char('H'), none)), Some(VirtualKey::Left));
+        assert_eq!(hjkl.key(&held(VirtualKey::Char('O'), none)), Some(VirtualKey::End));
+        assert_eq!(ijkl.key(&held(VirtualKey::Char('I'), none)), Some(VirtualKey::Up));
+        assert_eq!(ijkl.key(&held(VirtualKey::Char('H'), none)), Some(VirtualKey::PageDown));
+        assert_eq!(hjkl.key(&held(VirtualKey::Char('A'), none)), None);
+
+        // Shift selects; without the trigger, or with Ctrl, letters are letters
+        let shift = Modifiers { shift: true, ..none };
+        assert_eq!(hjkl.key(&held(VirtualKey::Char('L'), shift)), Some(VirtualKey::Right));
+        let free = KeyInput::new(KeyEvent::down(VirtualKey::Char('L'), none));
+        assert_eq!(hjkl.key(&free), None);
+        let ctrl = KeyInput { shortcut: true, ..held(VirtualKey::Char('L'), none) };
+        assert_eq!(hjkl.key(&ctrl), None);
+        assert_eq!(NavLayer::from_config(&NavConfig::default()), None);
+    }
+
+    #[test]
+    fn test_trigger_tap_and_hold() {
+        let mut hold = NavHold::default();
+        // Tapped: does its own job on release
+        assert!(hold.trigger_down(false, false));
+        assert!(hold.trigger_down(true, false));
+        assert!(hold.is_held());
+        assert!(hold.trigger_up());
+        assert!(!hold.is_held());
+
+        // Held while another key went down
+        assert!(hold.trigger_down(false, false));
+        hold.key_down();
+        assert!(!hold.trigger_up());
+
+        // Alt+Tab, and its repeats, are left alone
+        assert!(!hold.trigger_down(false, true));
+        assert!(!hold.trigger_down(true, false));
+        assert!(!hold.trigger_up());
+    }
+}
@@ -0,0 +1,180 @@
+//! Logging and notification pipeline
+//!
+//! The keyboard hook must return quickly and can't show UI, so problems are
+//! queued here and drained by the tray event loop, which logs them and
+//! surfaces them to the user. Repeated messages (e.g., one failure per
+//! keystroke) are collapsed so the user sees each problem once per cooldown.
+//! The tray also appends them to `ghostkeys.log` in the config folder, which
+//! "Open logs" shows.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::paths;
+
+/// How long an identical message is suppressed after being queued
+pub const REPEAT_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Size past which the log file is moved aside to `ghostkeys.log.old`
+const LOG_FILE_LIMIT: u64 = 1024 * 1024;
+
+/// Severity of a notification
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Informational message
+    Info,
+    /// Something the user should know about (e.g., characters not typed)
+    Warning,
+    /// A feature stopped working
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A message for the user
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    /// Severity of the message
+    pub severity: Severity,
+    /// Human-readable message
+    pub message: String,
+}
+
+impl fmt::Display for Notification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.severity, self.message)
+    }
+}
+
+/// Queue of pending notifications with repeat suppression
+#[derive(Debug, Default)]
+pub struct Notifications {
+    /// Notifications not yet drained
+    pending: Vec<Notification>,
+    /// Message -> when it was last queued
+    last_queued: HashMap<String, Instant>,
+}
+
+impl Notifications {
+    /// Create an empty queue
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a notification unless the same message was queued recently
+    ///
+    /// Returns true if the notification was queued.
+    pub fn push(&mut self, severity: Severity, message: impl Into<String>) -> bool {
+        self.push_at(severity, message.into(), Instant::now())
+    }
+
+    /// Take all pending notifications
+    pub fn drain(&mut self) -> Vec<Notification> {
+        std::mem::take(&mut self.pending)
+    }
+
+    fn push_at(&mut self, severity: Severity, message: String, now: Instant) -> bool {
+        if let Some(&last) = self.last_queued.get(&message) {
+            if now.duration_since(last) < REPEAT_COOLDOWN {
+                return false;
+            }
+        }
+
+        // Keep the repeat table small
+        self.last_queued
+            .retain(|_, &mut last| now.duration_since(last) < REPEAT_COOLDOWN);
+        self.last_queued.insert(message.clone(), now);
+        self.pending.push(Notification { severity, message });
+        true
+    }
+}
+
+/// Append a notification to the log file
+///
+/// Best effort, like the system log: a log that can't be written has nowhere
+/// to report that.
+pub fn write_log(notification: &Notification) {
+    if let Some(path) = paths::log_file() {
+        let _ = append_to_log(&path, notification, LOG_FILE_LIMIT);
+    }
+}
+
+/// Append a timestamped line to `path`, first moving it aside if it's over `limit` bytes
+fn append_to_log(path: &Path, notification: &Notification, limit: u64) -> std::io::Result<()> {
+    if std::fs::metadata(path).is_ok_and(|metadata| metadata.len() > limit) {
+        std::fs::rename(path, path.with_extension("log.old"))?;
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |t| t.as_secs());
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{} {}", timestamp, notification)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_and_drain() {
+        let mut notifications = Notifications::new();
+        assert!(notifications.push(Severity::Warning, "first"));
+        assert!(notifications.push(Severity::Error, "second"));
+
+        let drained = notifications.drain();
+        assert_eq!(drained.len(), 2);
+        assert_eq!(drained[0].to_string(), "[warning] first");
+        assert!(notifications.drain().is_empty());
+    }
+
+    #[test]
+    fn test_repeats_are_collapsed() {
+        let mut notifications = Notifications::new();
+        let start = Instant::now();
+
+        assert!(notifications.push_at(Severity::Warning, "blocked".into(), start));
+        assert!(!notifications.push_at(Severity::Warning, "blocked".into(), start + Duration::from_secs(1)));
+        assert_eq!(notifications.drain().len(), 1);
+
+        // Allowed again once the cooldown has passed
+        assert!(notifications.push_at(Severity::Warning, "blocked".into(), start + REPEAT_COOLDOWN));
+    }
+
+    #[test]
+    fn test_log_file_is_appended_and_rotated() {
+        let dir = std::env::temp_dir().join(format!("ghostkeys-log-test-{}", std::process::id()));
+        let path = dir.join("ghostkeys.log");
+        let warning = |message: &str| Notification {
+            severity: Severity::Warning,
+            message: message.to_string(),
+        };
+
+        append_to_log(&path, &warning("first"), 64).unwrap();
+        append_to_log(&path, &warning("second"), 64).unwrap();
+        let log = std::fs::read_to_string(&path).unwrap();
+        // Past the limit: the next line starts a new file
+        append_to_log(&path, &warning("x".repeat(64).as_str()), 64).unwrap();
+        append_to_log(&path, &warning("third"), 64).unwrap();
+        let rotated = std::fs::read_to_string(&path).unwrap();
+        let old = std::fs::read_to_string(dir.join("ghostkeys.log.old")).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(log.lines().count(), 2);
+        assert!(log.lines().next().unwrap().ends_with(" [warning] first"));
+        assert!(old.contains("second") && !old.contains("third"));
+        assert!(rotated.ends_with(" [warning] third\n"));
+    }
+}
@@ -0,0 +1,133 @@
+//! Virtual numpad for keyboards without one
+//!
+//! Tenkeyless and laptop keyboards have no numeric keypad, and figures typed
+//! on the top row get a period where ABNT2 numbers take a comma. With
+//! `enabled = true` under `[numpad]`, holding AltGr (or keeping Caps Lock on,
+//! with `trigger = "caps_lock"`) turns the right hand's letter block into
+//! one, laid out like a numpad:
+//!
+//! ```text
+//! u i o p        7 8 9 /
+//! j k l ;   ->   4 5 6 *
+//! m , . /        1 2 3 -
+//! space n '      0 , +
+//! ```
+//!
+//! The decimal key types a comma, as on an ABNT2 numpad, unless `decimal`
+//! says otherwise. The layer runs before the mapper, so dead keys and the
+//! ABNT2 layout never see the keys it types; an accent waiting for its
+//! letter is dropped.
+
+use crate::config::{NumpadConfig, NumpadTrigger};
+use crate::mapper::VirtualKey;
+use crate::pipeline::KeyInput;
+
+/// Where the decimal key sits in `KEYS`
+const DECIMAL: char = '\0';
+
+/// Keys of the block and what they type, the decimal key as `DECIMAL`
+const KEYS: &[(VirtualKey, char)] = &[
+    (VirtualKey::Char('U'), '7'),
+    (VirtualKey::Char('I'), '8'),
+    (VirtualKey::Char('O'), '9'),
+    (VirtualKey::Char('P'), '/'),
+    (VirtualKey::Char('J'), '4'),
+    (VirtualKey::Char('K'), '5'),
+    (VirtualKey::Char('L'), '6'),
+    (VirtualKey::Semicolon, '*'),
+    (VirtualKey::Char('M'), '1'),
+    (VirtualKey::Comma, '2'),
+    (VirtualKey::Period, '3'),
+    (VirtualKey::Slash, '-'),
+    (VirtualKey::Space, '0'),
+    (VirtualKey::Char('N'), DECIMAL),
+    (VirtualKey::Apostrophe, '+'),
+];
+
+/// The numpad layer over the letter block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumpadLayer {
+    trigger: NumpadTrigger,
+    decimal: char,
+}
+
+impl NumpadLayer {
+    /// The layer `config` describes, if it's turned on
+    pub fn from_config(config: &NumpadConfig) -> Option<Self> {
+        config.enabled.then_some(Self { trigger: config.trigger, decimal: config.decimal })
+    }
+
+    /// What a key press types on the numpad, if the layer is active and the
+    /// key is part of it
+    ///
+    /// Windows reports AltGr as Ctrl+Alt, so the AltGr trigger doesn't look
+    /// at shortcuts; with Caps Lock, Ctrl and Alt chords go on as usual.
+    pub fn key(&self, input: &KeyInput) -> Option<char> {
+        let modifiers = input.event.modifiers;
+        let active = match self.trigger {
+            NumpadTrigger::Altgr => modifiers.altgr,
+            NumpadTrigger::CapsLock => {
+                modifiers.caps_lock && !input.shortcut && !modifiers.is_alt_chord()
+            }
+        };
+        if !active {
+            return None;
+        }
+        KEYS.iter()
+            .find(|(key, _)| *key == input.event.key)
+            .map(|&(_, c)| if c == DECIMAL { self.decimal } else { c })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interceptor::{KeyEvent, Modifiers};
+
+    fn press(key: VirtualKey, modifiers: Modifiers) -> KeyInput {
+        KeyInput::new(KeyEvent::down(key, modifiers))
+    }
+
+    #[test]
+    fn test_altgr_turns_the_letter_block_into_a_numpad() {
+        let layer = NumpadLayer::from_config(&NumpadConfig {
+            enabled: true,
+            ..NumpadConfig::default()
+        })
+        .unwrap();
+        let altgr = Modifiers { alt: true, altgr: true, ..Modifiers::default() };
+        let typed: String = [
+            VirtualKey::Char('M'),
+            VirtualKey::Space,
+            VirtualKey::Char('N'),
+            VirtualKey::Char('O'),
+            VirtualKey::Apostrophe,
+        ]
+        .iter()
+        .filter_map(|&key| layer.key(&press(key, altgr)))
+        .collect();
+        assert_eq!(typed, "10,9+");
+
+        // Windows sees AltGr as Ctrl+Alt
+        let windows = KeyInput { shortcut: true, ..press(VirtualKey::Char('K'), altgr) };
+        assert_eq!(layer.key(&windows), Some('5'));
+        // Outside the block, or without AltGr, keys are left to the mapper
+        assert_eq!(layer.key(&press(VirtualKey::Char('A'), altgr)), None);
+        assert_eq!(layer.key(&press(VirtualKey::Char('K'), Modifiers::default())), None);
+    }
+
+    #[test]
+    fn test_caps_lock_trigger() {
+        let config = NumpadConfig {
+            enabled: true,
+            trigger: NumpadTrigger::CapsLock,
+            decimal: '.',
+        };
+        let layer = NumpadLayer::from_config(&config).unwrap();
+        let caps = Modifiers { caps_lock: true, ..Modifiers::default() };
+        assert_eq!(layer.key(&press(VirtualKey::Char('N'), caps)), Some('.'));
+        let copy = KeyInput { shortcut: true, ..press(VirtualKey::Char('K'), caps) };
+        assert_eq!(layer.key(&copy), None);
+        assert_eq!(NumpadLayer::from_config(&NumpadConfig::default()), None);
+    }
+}
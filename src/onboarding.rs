@@ -0,0 +1,195 @@
+//! First-run setup
+//!
+//! The first time GhostKeys starts from a console without a config file (or
+//! with `--setup`), it asks which keyboard is being used, whether dead keys
+//! apply in terminals and whether to start at login, and saves the answers
+//! as the config file. Once the keyboard hook is running it asks the user to
+//! type ç and ã, so a broken setup shows up right away instead of the first
+//! time they write an email.
+//!
+//! Questions are read from any `BufRead` and written to any `Write` so the
+//! flow can be tested with scripted answers.
+
+use std::io::{self, BufRead, Write};
+
+use crate::config::Config;
+use crate::i18n::{tr, tr_args, Text};
+use crate::layout::SourceLayout;
+use crate::paths;
+
+/// How many times the typing check is offered before giving up
+const VERIFY_ATTEMPTS: usize = 3;
+
+/// What the user chose during setup
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Answers {
+    /// Physical keyboard layout
+    pub source_layout: SourceLayout,
+    /// Whether dead keys wait for a vowel in terminals
+    pub terminal_dead_keys: bool,
+    /// Whether GhostKeys should start at login
+    pub autostart: bool,
+}
+
+impl Default for Answers {
+    fn default() -> Self {
+        Self { source_layout: SourceLayout::Us, terminal_dead_keys: true, autostart: true }
+    }
+}
+
+impl Answers {
+    /// Store the answers in `config`; autostart is set up by the caller
+    pub fn apply(&self, config: &mut Config) {
+        config.source_layout = self.source_layout;
+        config.terminals.dead_keys = self.terminal_dead_keys;
+    }
+}
+
+/// Whether this is the first run: the config file location is known but
+/// nothing has been saved there yet
+pub fn is_first_run() -> bool {
+    paths::config_file().is_some_and(|path| !path.exists())
+}
+
+/// Ask the setup questions; an empty answer (or end of input) keeps the default
+pub fn ask(input: &mut impl BufRead, output: &mut impl Write) -> io::Result<Answers> {
+    let defaults = Answers::default();
+    writeln!(output, "{}", tr(Text::SetupWelcome))?;
+    let source_layout =
+        prompt(input, output, Text::SetupLayout, defaults.source_layout, parse_layout)?;
+    let terminal_dead_keys =
+        prompt(input, output, Text::SetupTerminals, defaults.terminal_dead_keys, parse_yes_no)?;
+    let autostart = prompt(input, output, Text::SetupAutostart, defaults.autostart, parse_yes_no)?;
+    Ok(Answers { source_layout, terminal_dead_keys, autostart })
+}
+
+/// Ask the user to type ç and ã until they come through, they give up or
+/// the attempts run out; returns whether typing works
+pub fn verify(input: &mut impl BufRead, output: &mut impl Write) -> io::Result<bool> {
+    write!(output, "{}", tr(Text::SetupVerify))?;
+    output.flush()?;
+    for attempt in 1..=VERIFY_ATTEMPTS {
+        let Some(line) = read_answer(input)? else {
+            break;
+        };
+        if line.is_empty() {
+            break;
+        }
+        if typed_accents(&line) {
+            writeln!(output, "{}", tr(Text::SetupVerified))?;
+            return Ok(true);
+        }
+        if attempt < VERIFY_ATTEMPTS {
+            write!(output, "{}", tr_args(Text::SetupRetry, &[&line]))?;
+            output.flush()?;
+        }
+    }
+    writeln!(output, "{}", tr(Text::SetupSkipped))?;
+    Ok(false)
+}
+
+/// Whether a line typed during the check contains both test characters
+fn typed_accents(line: &str) -> bool {
+    line.contains('ç') && line.contains('ã')
+}
+
+/// Ask `question` until the answer parses; empty or missing answers give `default`
+fn prompt<T>(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    question: Text,
+    default: T,
+    parse: fn(&str) -> Option<T>,
+) -> io::Result<T> {
+    loop {
+        write!(output, "{}", tr(question))?;
+        output.flush()?;
+        match read_answer(input)? {
+            None => return Ok(default),
+            Some(answer) if answer.is_empty() => return Ok(default),
+            Some(answer) => match parse(&answer) {
+                Some(value) => return Ok(value),
+                None => writeln!(output, "{}", tr(Text::SetupInvalidAnswer))?,
+            },
+        }
+    }
+}
+
+/// Next line of input without surrounding whitespace; `None` at end of input
+fn read_answer(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim().to_string()))
+}
+
+/// Keyboard layout by menu number or name
+fn parse_layout(answer: &str) -> Option<SourceLayout> {
+    match answer.to_lowercase().as_str() {
+        "1" | "us" => Some(SourceLayout::Us),
+        "2" | "uk" | "uk_iso" | "iso" => Some(SourceLayout::UkIso),
+        "3" | "dvorak" => Some(SourceLayout::Dvorak),
+        "4" | "colemak" => Some(SourceLayout::Colemak),
+        _ => None,
+    }
+}
+
+/// Yes or no, in English or Portuguese
+fn parse_yes_no(answer: &str) -> Option<bool> {
+    match answer.to_lowercase().as_str() {
+        "y" | "yes" | "s" | "sim" => Some(true),
+        "n" | "no" | "não" | "nao" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_on_empty_answers() {
+        let mut output = Vec::new();
+        let answers = ask(&mut "\n\n\n".as_bytes(), &mut output).unwrap();
+        assert_eq!(answers, Answers::default());
+
+        // End of input (e.g., stdin closed) keeps the defaults too
+        let answers = ask(&mut "".as_bytes(), &mut output).unwrap();
+        assert_eq!(answers, Answers::default());
+    }
+
+    #[test]
+    fn test_answers_are_parsed_and_applied() {
+        let mut output = Vec::new();
+        let answers = ask(&mut "dvorak\nnão\nn\n".as_bytes(), &mut output).unwrap();
+        assert_eq!(answers.source_layout, SourceLayout::Dvorak);
+        assert!(!answers.terminal_dead_keys && !answers.autostart);
+
+        let mut config = Config::default();
+        answers.apply(&mut config);
+        assert_eq!(config.source_layout, SourceLayout::Dvorak);
+        assert!(!config.terminals.dead_keys);
+    }
+
+    #[test]
+    fn test_invalid_answers_are_asked_again() {
+        let mut output = Vec::new();
+        let answers = ask(&mut "7\n2\nmaybe\ny\n\n".as_bytes(), &mut output).unwrap();
+        assert_eq!(answers.source_layout, SourceLayout::UkIso);
+        assert!(answers.terminal_dead_keys);
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.matches(tr(Text::SetupInvalidAnswer)).count(), 2);
+    }
+
+    #[test]
+    fn test_verify() {
+        let mut output = Vec::new();
+        assert!(verify(&mut "çã\n".as_bytes(), &mut output).unwrap());
+        // A miss, then success
+        assert!(verify(&mut ";'a\nç ã\n".as_bytes(), &mut output).unwrap());
+        // Skipped with an empty line, or after too many misses
+        assert!(!verify(&mut "\n".as_bytes(), &mut output).unwrap());
+        assert!(!verify(&mut "a\nb\nc\nçã\n".as_bytes(), &mut output).unwrap());
+    }
+}
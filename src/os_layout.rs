@@ -0,0 +1,169 @@
+//! OS keyboard layouts that conflict with emulation
+//!
+//! GhostKeys expects the OS to type what a plain US layout types. On an ABNT2
+//! layout the OS translates the keys a second time, and the dead keys of
+//! US-International (and similar variants) turn GhostKeys' accents into
+//! doubled ones (`''`, `´á`). Platforms list the layouts the user has and
+//! which one is in use; this module tells which of them are a problem.
+
+use std::fmt;
+
+/// Why a layout doesn't work under GhostKeys
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conflict {
+    /// The OS already produces ABNT2 characters, so keys are translated twice
+    Abnt2,
+    /// The OS has dead keys of its own, so accents are doubled
+    DeadKeys,
+}
+
+/// A conflicting layout the user has
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    /// Name of the layout as the OS shows it
+    pub name: String,
+    /// What goes wrong with it
+    pub conflict: Conflict,
+    /// Whether it is the layout in use, rather than one the user can switch to
+    pub active: bool,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let problem = match self.conflict {
+            Conflict::Abnt2 => "characters are translated twice",
+            Conflict::DeadKeys => "its dead keys double GhostKeys' accents",
+        };
+        if self.active {
+            write!(f, "The OS keyboard layout is {}: {}", self.name, problem)
+        } else {
+            write!(f, "The {} layout is installed; while it's in use, {}", self.name, problem)
+        }
+    }
+}
+
+/// KLIDs of the Windows layout variants GhostKeys knows; an HKL names a
+/// variant by its registry "Layout Id" rather than by its KLID
+pub const VARIANT_KLIDS: [&str; 2] = ["00010416", "00020409"];
+
+/// What's wrong with a Windows keyboard layout, by its KLID (the name of its
+/// registry key, e.g. "00010416" for Portuguese (Brazil ABNT2))
+///
+/// The low word is the layout's language and the high word tells its
+/// variants apart: 00000416 and 00010416 are both ABNT, 00020409 is
+/// United States-International.
+pub fn classify_klid(klid: &str) -> Option<Conflict> {
+    match u32::from_str_radix(klid, 16).ok()? {
+        0x0000_0416 | 0x0001_0416 => Some(Conflict::Abnt2),
+        0x0002_0409 => Some(Conflict::DeadKeys),
+        _ => None,
+    }
+}
+
+/// What's wrong with a Windows keyboard layout handle (HKL), if anything
+///
+/// The high word of an HKL is the layout: the KLID's language word for
+/// primary layouts (0x0416 is Portuguese (Brazil ABNT)), or 0xFnnn for a
+/// variant with registry "Layout Id" nnn. The low word is the language the
+/// layout is installed under. `variant_klid` is the KLID the platform found
+/// for a variant's Layout Id; without it, 0001 is taken for
+/// United States-International, whatever language it's installed under, and
+/// any other variant installed under Portuguese (Brazil) for ABNT2.
+pub fn classify_hkl(hkl: isize, variant_klid: Option<&str>) -> Option<Conflict> {
+    let layout = (hkl as usize >> 16) & 0xFFFF;
+    let language = hkl as usize & 0xFFFF;
+    if layout & 0xF000 != 0xF000 {
+        return classify_klid(&format!("{:08X}", layout));
+    }
+    if let Some(klid) = variant_klid {
+        return classify_klid(klid);
+    }
+    match (layout, language) {
+        (0xF001, _) => Some(Conflict::DeadKeys),
+        (_, 0x0416) => Some(Conflict::Abnt2),
+        _ => None,
+    }
+}
+
+/// Name Windows shows for a conflicting layout
+pub fn windows_name(conflict: Conflict) -> &'static str {
+    match conflict {
+        Conflict::Abnt2 => "Portuguese (Brazil ABNT)",
+        Conflict::DeadKeys => "United States-International",
+    }
+}
+
+/// What's wrong with an xkb layout and variant (e.g., from `setxkbmap -query`)
+pub fn classify_xkb(layout: &str, variant: &str) -> Option<Conflict> {
+    match (layout, variant) {
+        ("br", _) => Some(Conflict::Abnt2),
+        ("us", "intl" | "alt-intl") => Some(Conflict::DeadKeys),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_klid() {
+        assert_eq!(classify_klid("00000416"), Some(Conflict::Abnt2));
+        assert_eq!(classify_klid("00010416"), Some(Conflict::Abnt2));
+        assert_eq!(classify_klid("00020409"), Some(Conflict::DeadKeys));
+        assert_eq!(classify_klid("00000409"), None);
+        assert_eq!(classify_klid("00010409"), None);
+        assert_eq!(classify_klid("not a klid"), None);
+    }
+
+    #[test]
+    fn test_classify_hkl() {
+        assert_eq!(classify_hkl(0x0416_0416, None), Some(Conflict::Abnt2));
+        // ABNT under English
+        assert_eq!(classify_hkl(0x0416_0409, None), Some(Conflict::Abnt2));
+        // US-International, under English and under Portuguese
+        assert_eq!(classify_hkl(0xF001_0409_u32 as isize, None), Some(Conflict::DeadKeys));
+        assert_eq!(classify_hkl(0xF001_0416_u32 as isize, None), Some(Conflict::DeadKeys));
+        // US, and US under Portuguese
+        assert_eq!(classify_hkl(0x0409_0409, None), None);
+        assert_eq!(classify_hkl(0x0409_0416, None), None);
+    }
+
+    #[test]
+    fn test_classify_hkl_variant() {
+        // ABNT2 (00010416) is a variant, named by its Layout Id
+        let abnt2 = 0xF021_0416_u32 as isize;
+        assert_eq!(classify_hkl(abnt2, Some("00010416")), Some(Conflict::Abnt2));
+        assert_eq!(classify_hkl(abnt2, None), Some(Conflict::Abnt2));
+        // The KLID wins over the guesses
+        assert_eq!(classify_hkl(abnt2, Some("00010409")), None);
+        let us_intl = 0xF001_0409_u32 as isize;
+        assert_eq!(classify_hkl(us_intl, Some("00020409")), Some(Conflict::DeadKeys));
+        // A variant the platform couldn't name, under English
+        assert_eq!(classify_hkl(0xF002_0409_u32 as isize, None), None);
+    }
+
+    #[test]
+    fn test_classify_xkb() {
+        assert_eq!(classify_xkb("br", ""), Some(Conflict::Abnt2));
+        assert_eq!(classify_xkb("br", "abnt2"), Some(Conflict::Abnt2));
+        assert_eq!(classify_xkb("us", "intl"), Some(Conflict::DeadKeys));
+        assert_eq!(classify_xkb("us", ""), None);
+        assert_eq!(classify_xkb("us", "dvorak"), None);
+    }
+
+    #[test]
+    fn test_finding_text() {
+        let mut finding = Finding {
+            name: "br".to_string(),
+            conflict: Conflict::Abnt2,
+            active: true,
+        };
+        assert_eq!(
+            finding.to_string(),
+            "The OS keyboard layout is br: characters are translated twice"
+        );
+        finding.active = false;
+        assert!(finding.to_string().starts_with("The br layout is installed"));
+    }
+}
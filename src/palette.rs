@@ -0,0 +1,125 @@
+//! Character palette
+//!
+//! ABNT2 has no key for the em dash, curly quotes, the ellipsis or emoji.
+//! With `palette = true` under `[hotkeys]`, Ctrl+Alt+. opens a small window
+//! listing them: typing filters the list by name (in English or Portuguese),
+//! and Enter types the selected character into the window that was in front,
+//! through the same injector as every remapped key. The characters picked
+//! most often come first, counted in `stats.toml` while `[statistics]` is
+//! enabled.
+
+use std::collections::BTreeMap;
+
+/// A character the palette offers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    /// What is typed when it's picked
+    pub text: &'static str,
+    /// Name shown next to it
+    pub name: &'static str,
+    /// Other words it can be found by (Portuguese names, without accents)
+    pub keywords: &'static str,
+}
+
+impl Entry {
+    /// How the entry is listed: `— em dash`
+    pub fn label(&self) -> String {
+        format!("{}  {}", self.text, self.name)
+    }
+
+    /// Whether every word of `query` is part of the name or keywords, or the
+    /// query is the character itself
+    fn matches(&self, query: &str) -> bool {
+        let query = query.trim().to_lowercase();
+        if query == self.text {
+            return true;
+        }
+        let words = format!("{} {}", self.name, self.keywords).to_lowercase();
+        query.split_whitespace().all(|word| words.contains(word))
+    }
+}
+
+/// Characters in the palette, in the order listed before any are picked
+pub const ENTRIES: &[Entry] = &[
+    Entry { text: "—", name: "em dash", keywords: "travessao" },
+    Entry { text: "–", name: "en dash", keywords: "meia-risca traco" },
+    Entry { text: "“", name: "left double quotation mark", keywords: "aspas abre" },
+    Entry { text: "”", name: "right double quotation mark", keywords: "aspas fecha" },
+    Entry { text: "‘", name: "left single quotation mark", keywords: "aspas simples abre" },
+    Entry { text: "’", name: "right single quotation mark", keywords: "aspas simples apostrofo" },
+    Entry { text: "«", name: "left guillemet", keywords: "aspas angulares" },
+    Entry { text: "»", name: "right guillemet", keywords: "aspas angulares" },
+    Entry { text: "…", name: "ellipsis", keywords: "reticencias" },
+    Entry { text: "•", name: "bullet", keywords: "marcador" },
+    Entry { text: "·", name: "middle dot", keywords: "ponto medio" },
+    Entry { text: "×", name: "multiplication sign", keywords: "vezes" },
+    Entry { text: "÷", name: "division sign", keywords: "dividido" },
+    Entry { text: "±", name: "plus-minus sign", keywords: "mais ou menos" },
+    Entry { text: "≠", name: "not equal to", keywords: "diferente" },
+    Entry { text: "≤", name: "less-than or equal to", keywords: "menor ou igual" },
+    Entry { text: "≥", name: "greater-than or equal to", keywords: "maior ou igual" },
+    Entry { text: "→", name: "rightwards arrow", keywords: "seta direita" },
+    Entry { text: "←", name: "leftwards arrow", keywords: "seta esquerda" },
+    Entry { text: "↑", name: "upwards arrow", keywords: "seta cima" },
+    Entry { text: "↓", name: "downwards arrow", keywords: "seta baixo" },
+    Entry { text: "€", name: "euro sign", keywords: "moeda" },
+    Entry { text: "©", name: "copyright sign", keywords: "direitos autorais" },
+    Entry { text: "®", name: "registered sign", keywords: "marca registrada" },
+    Entry { text: "™", name: "trade mark sign", keywords: "marca" },
+    Entry { text: "✓", name: "check mark", keywords: "visto certo" },
+    Entry { text: "★", name: "star", keywords: "estrela" },
+    Entry { text: "😀", name: "grinning face", keywords: "emoji sorriso" },
+    Entry { text: "😂", name: "face with tears of joy", keywords: "emoji rindo chorando" },
+    Entry { text: "😊", name: "smiling face", keywords: "emoji feliz" },
+    Entry { text: "😉", name: "winking face", keywords: "emoji piscadela" },
+    Entry { text: "😍", name: "heart eyes", keywords: "emoji apaixonado" },
+    Entry { text: "😢", name: "crying face", keywords: "emoji triste chorando" },
+    Entry { text: "🤔", name: "thinking face", keywords: "emoji pensando" },
+    Entry { text: "👍", name: "thumbs up", keywords: "emoji joinha positivo" },
+    Entry { text: "👏", name: "clapping hands", keywords: "emoji palmas" },
+    Entry { text: "🙏", name: "folded hands", keywords: "emoji obrigado por favor" },
+    Entry { text: "❤️", name: "red heart", keywords: "emoji coracao amor" },
+    Entry { text: "🎉", name: "party popper", keywords: "emoji festa parabens" },
+    Entry { text: "🔥", name: "fire", keywords: "emoji fogo" },
+    Entry { text: "✅", name: "check mark button", keywords: "emoji feito certo" },
+    Entry { text: "🇧🇷", name: "flag of Brazil", keywords: "emoji bandeira brasil" },
+];
+
+/// Entries matching `query`, most picked first, then in palette order
+///
+/// `picks` counts how often each text was picked (`Stats::palette`).
+pub fn search(query: &str, picks: &BTreeMap<String, u64>) -> Vec<&'static Entry> {
+    let mut found: Vec<&'static Entry> =
+        ENTRIES.iter().filter(|entry| entry.matches(query)).collect();
+    // Stable, so equally picked entries keep the palette order
+    found.sort_by_key(|entry| std::cmp::Reverse(picks.get(entry.text).copied().unwrap_or(0)));
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(entries: &[&Entry]) -> Vec<&'static str> {
+        entries.iter().map(|entry| entry.text).collect()
+    }
+
+    #[test]
+    fn test_search_by_name_keyword_or_character() {
+        let picks = BTreeMap::new();
+        assert_eq!(texts(&search("dash", &picks)), ["—", "–"]);
+        assert_eq!(texts(&search("Travessao", &picks)), ["—"]);
+        assert_eq!(texts(&search("seta dir", &picks)), ["→"]);
+        assert_eq!(texts(&search("…", &picks)), ["…"]);
+        assert_eq!(search("", &picks).len(), ENTRIES.len());
+        assert!(search("no such thing", &picks).is_empty());
+    }
+
+    #[test]
+    fn test_most_picked_come_first() {
+        let picks = BTreeMap::from([("–".to_string(), 2), ("😀".to_string(), 5)]);
+        let all = texts(&search("", &picks));
+        assert_eq!(all[..4], ["😀", "–", "—", "“"]);
+        assert_eq!(texts(&search("dash", &picks)), ["–", "—"]);
+    }
+}
@@ -0,0 +1,103 @@
+//! Where GhostKeys keeps its files
+//!
+//! Everything lives in one per-user folder (`%APPDATA%\ghostkeys` on Windows,
+//! `$XDG_CONFIG_HOME/ghostkeys` on Linux) so "Open config folder" in the tray
+//! shows the user all of it. Each accessor returns `None` when the folder
+//! can't be determined (no `APPDATA` or `HOME`).
+
+use std::path::PathBuf;
+
+/// Per-user GhostKeys folder
+pub fn config_dir() -> Option<PathBuf> {
+    #[cfg(target_os = "windows")]
+    let base = std::env::var_os("APPDATA").map(PathBuf::from);
+
+    #[cfg(not(target_os = "windows"))]
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+
+    base.map(|dir| dir.join("ghostkeys"))
+}
+
+/// Configuration file
+pub fn config_file() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("config.toml"))
+}
+
+/// Log file the tray writes warnings and errors to
+pub fn log_file() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("ghostkeys.log"))
+}
+
+/// Folder for crash reports and minidumps
+pub fn crash_dir() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("crashes"))
+}
+
+/// Remap counts kept when `[statistics]` is enabled
+pub fn stats_file() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("stats.toml"))
+}
+
+/// Mode, direction and layout of the moment, restored at startup
+pub fn session_file() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("session.toml"))
+}
+
+/// Launches that haven't exited cleanly, counted for safe mode
+pub fn launches_file() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("launches.toml"))
+}
+
+/// Folder for layouts imported from profiles
+pub fn layouts_dir() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("layouts"))
+}
+
+/// Folder of the profiles the tray switches between
+pub fn profiles_dir() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("profiles"))
+}
+
+/// Where `ghostkeys ctl` reaches the running instance: a named pipe on
+/// Windows, a Unix socket in the runtime directory elsewhere
+#[cfg(target_os = "windows")]
+pub fn ipc_endpoint() -> Option<PathBuf> {
+    let user = std::env::var("USERNAME").unwrap_or_default();
+    Some(PathBuf::from(format!(r"\\.\pipe\ghostkeys-{}", user)))
+}
+
+/// Where `ghostkeys ctl` reaches the running instance: a named pipe on
+/// Windows, a Unix socket in the runtime directory elsewhere
+#[cfg(not(target_os = "windows"))]
+pub fn ipc_endpoint() -> Option<PathBuf> {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(|dir| PathBuf::from(dir).join("ghostkeys.sock"))
+        .or_else(|| config_dir().map(|dir| dir.join("ghostkeys.sock")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_everything_is_in_the_config_dir() {
+        let Some(dir) = config_dir() else {
+            return;
+        };
+        assert!(dir.ends_with("ghostkeys"));
+        let files = [
+            config_file(),
+            log_file(),
+            crash_dir(),
+            layouts_dir(),
+            profiles_dir(),
+            session_file(),
+            launches_file(),
+        ];
+        for path in files {
+            assert_eq!(path.unwrap().parent(), Some(dir.as_path()));
+        }
+    }
+}
@@ -0,0 +1,619 @@
+//! Key processing as an ordered pipeline of middleware
+//!
+//! Each feature that looks at a key press is a `KeyMiddleware` stage, run in
+//! order: the modifier filter, per-app rules, the nav layer, the virtual
+//! numpad, the mapper (compose sequences and the ABNT2 layout), then stages
+//! that watch or rewrite what the mapper
+//! decided, such as autocorrect and snippet (macro) recording. A stage sees
+//! the key press and the action decided so far, and either hands a new
+//! action to the next stage or ends the pipeline with it.
+//!
+//! Platform hooks decide whether a key reaches the pipeline at all (paused,
+//! unmapped keys, pause hotkey) and carry out the action it returns.
+//! Embedders build their own pipeline with `PipelineBuilder`, leaving stages
+//! out or adding their own.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::autocorrect::Autocorrect;
+use crate::config::{NavConfig, NumpadConfig, TerminalsConfig, WindowInfo};
+use crate::interceptor::{KeyAction, KeyEvent, Modifiers};
+use crate::macros::MacroRecorder;
+use crate::mapper::{Direction, Mapper};
+use crate::nav::NavLayer;
+use crate::numpad::NumpadLayer;
+
+/// A key press and what the platform knows about it
+#[derive(Debug, Clone, Copy)]
+pub struct KeyInput {
+    /// The key press
+    pub event: KeyEvent,
+    /// What the key types if passed through, for stages watching the output
+    pub typed: Option<char>,
+    /// Ctrl or the Windows/Super key is held (a shortcut, never remapped)
+    pub shortcut: bool,
+    /// Direction to map in, if the platform tracks it per key press
+    pub direction: Option<Direction>,
+    /// Whether dead keys wait for a vowel; per-app rules turn this off
+    pub dead_keys: bool,
+    /// The nav layer's trigger is held (see `nav`)
+    pub nav: bool,
+}
+
+impl KeyInput {
+    /// A key press with nothing else known about it
+    pub fn new(event: KeyEvent) -> Self {
+        Self {
+            event,
+            typed: None,
+            shortcut: false,
+            direction: None,
+            dead_keys: true,
+            nav: false,
+        }
+    }
+}
+
+/// What a stage does with a key press
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// Hand this action to the next stage
+    Next(KeyAction),
+    /// Skip the remaining stages; this is the final action
+    Done(KeyAction),
+}
+
+/// One stage of the pipeline
+pub trait KeyMiddleware: Send {
+    /// Name used to find the stage in a pipeline (to insert, remove or
+    /// replace it)
+    fn name(&self) -> &'static str;
+
+    /// Handle a key press, given the action decided by earlier stages
+    /// (`KeyAction::Pass` for the first one)
+    ///
+    /// Stages may change `input` for the stages after them.
+    fn process(&mut self, input: &mut KeyInput, action: KeyAction) -> Step;
+
+    /// Forget anything tracked between key presses (focus change, IME)
+    fn reset(&mut self) {}
+}
+
+/// Stages run in order for every key press
+#[derive(Default)]
+pub struct Pipeline {
+    stages: Vec<Box<dyn KeyMiddleware>>,
+}
+
+impl Pipeline {
+    /// A pipeline without stages, which passes every key
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builder for the standard stages around `mapper`
+    pub fn builder(mapper: Mapper) -> PipelineBuilder {
+        PipelineBuilder::new(mapper)
+    }
+
+    /// Names of the stages, in order
+    pub fn names(&self) -> Vec<&'static str> {
+        self.stages.iter().map(|stage| stage.name()).collect()
+    }
+
+    /// Add a stage after all others
+    pub fn push(&mut self, stage: impl KeyMiddleware + 'static) {
+        self.stages.push(Box::new(stage));
+    }
+
+    /// Add a stage right before the one named `before`; false if there is
+    /// no such stage
+    pub fn insert_before(&mut self, before: &str, stage: impl KeyMiddleware + 'static) -> bool {
+        match self.position(before) {
+            Some(index) => {
+                self.stages.insert(index, Box::new(stage));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Add a stage right after the one named `after`; false if there is no
+    /// such stage
+    pub fn insert_after(&mut self, after: &str, stage: impl KeyMiddleware + 'static) -> bool {
+        match self.position(after) {
+            Some(index) => {
+                self.stages.insert(index + 1, Box::new(stage));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Take out the stage named `name`
+    pub fn remove(&mut self, name: &str) -> Option<Box<dyn KeyMiddleware>> {
+        self.position(name).map(|index| self.stages.remove(index))
+    }
+
+    /// Put `stage` in place of the stage with the same name; false (and
+    /// nothing changed) if there is none
+    pub fn replace(&mut self, stage: impl KeyMiddleware + 'static) -> bool {
+        match self.position(stage.name()) {
+            Some(index) => {
+                self.stages[index] = Box::new(stage);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Map with `mapper` from the next key press on
+    pub fn set_mapper(&mut self, mapper: Mapper) -> bool {
+        self.replace(MapperStage::new(mapper))
+    }
+
+    /// Run a key press through the stages and return the final action
+    pub fn process(&mut self, mut input: KeyInput) -> KeyAction {
+        let mut action = KeyAction::Pass;
+        for stage in &mut self.stages {
+            match stage.process(&mut input, action) {
+                Step::Next(next) => action = next,
+                Step::Done(done) => return done,
+            }
+        }
+        action
+    }
+
+    /// Reset every stage
+    pub fn reset(&mut self) {
+        for stage in &mut self.stages {
+            stage.reset();
+        }
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.stages.iter().position(|stage| stage.name() == name)
+    }
+}
+
+impl fmt::Debug for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pipeline").field("stages", &self.names()).finish()
+    }
+}
+
+/// Builds a pipeline in the standard order: modifier filter, per-app rules,
+/// nav layer, numpad, mapper, autocorrect, snippets, then any extra stages
+pub struct PipelineBuilder {
+    mapper: Mapper,
+    modifier_filter: bool,
+    app_rules: Option<AppRules>,
+    nav: Option<NavLayer>,
+    numpad: Option<NumpadLayer>,
+    autocorrect: bool,
+    snippets: Option<Arc<Mutex<MacroRecorder>>>,
+    extra: Vec<Box<dyn KeyMiddleware>>,
+}
+
+impl PipelineBuilder {
+    /// The mapper behind the modifier filter, and nothing else
+    pub fn new(mapper: Mapper) -> Self {
+        Self {
+            mapper,
+            modifier_filter: true,
+            app_rules: None,
+            nav: None,
+            numpad: None,
+            autocorrect: false,
+            snippets: None,
+            extra: Vec::new(),
+        }
+    }
+
+    /// Whether shortcuts and Alt chords skip the other stages (the default)
+    pub fn modifier_filter(mut self, enabled: bool) -> Self {
+        self.modifier_filter = enabled;
+        self
+    }
+
+    /// Apply per-app rules, looking up the foreground window with `foreground`
+    pub fn app_rules(
+        mut self,
+        terminals: TerminalsConfig,
+        foreground: impl Fn() -> WindowInfo + Send + 'static,
+    ) -> Self {
+        self.app_rules = Some(AppRules::new(terminals, foreground));
+        self
+    }
+
+    /// Press arrows with letter keys as `config` says (`[nav]`)
+    pub fn nav(mut self, config: &NavConfig) -> Self {
+        self.nav = NavLayer::from_config(config);
+        self
+    }
+
+    /// Turn the letter block into a numpad as `config` says (`[numpad]`)
+    pub fn numpad(mut self, config: &NumpadConfig) -> Self {
+        self.numpad = NumpadLayer::from_config(config);
+        self
+    }
+
+    /// Correct common words missing their accents
+    pub fn autocorrect(mut self, enabled: bool) -> Self {
+        self.autocorrect = enabled;
+        self
+    }
+
+    /// Record output into `recorder` while it is recording
+    pub fn snippets(mut self, recorder: Arc<Mutex<MacroRecorder>>) -> Self {
+        self.snippets = Some(recorder);
+        self
+    }
+
+    /// Add a stage after the standard ones
+    pub fn stage(mut self, stage: impl KeyMiddleware + 'static) -> Self {
+        self.extra.push(Box::new(stage));
+        self
+    }
+
+    /// The pipeline, stages in order
+    pub fn build(self) -> Pipeline {
+        let mut pipeline = Pipeline::new();
+        if self.modifier_filter {
+            pipeline.push(ModifierFilter);
+        }
+        if let Some(app_rules) = self.app_rules {
+            pipeline.push(app_rules);
+        }
+        if let Some(layer) = self.nav {
+            pipeline.push(NavStage::new(layer));
+        }
+        if let Some(layer) = self.numpad {
+            pipeline.push(NumpadStage::new(layer));
+        }
+        pipeline.push(MapperStage::new(self.mapper));
+        if self.autocorrect {
+            pipeline.push(AutocorrectStage::default());
+        }
+        if let Some(recorder) = self.snippets {
+            pipeline.push(Snippets::new(recorder));
+        }
+        pipeline.stages.extend(self.extra);
+        pipeline
+    }
+}
+
+/// Lets shortcuts (Ctrl, Windows/Super) and Alt chords through untouched
+#[derive(Debug, Default)]
+pub struct ModifierFilter;
+
+impl KeyMiddleware for ModifierFilter {
+    fn name(&self) -> &'static str {
+        "modifier-filter"
+    }
+
+    fn process(&mut self, input: &mut KeyInput, action: KeyAction) -> Step {
+        if input.shortcut || input.event.modifiers.is_alt_chord() {
+            Step::Done(KeyAction::Pass)
+        } else {
+            Step::Next(action)
+        }
+    }
+}
+
+/// Per-app rules: dead keys can be turned off in terminals
+pub struct AppRules {
+    terminals: TerminalsConfig,
+    /// The foreground window
+    foreground: Box<dyn Fn() -> WindowInfo + Send>,
+}
+
+impl AppRules {
+    /// Rules from `[terminals]`, with `foreground` describing the window in front
+    pub fn new(
+        terminals: TerminalsConfig,
+        foreground: impl Fn() -> WindowInfo + Send + 'static,
+    ) -> Self {
+        Self { terminals, foreground: Box::new(foreground) }
+    }
+}
+
+impl KeyMiddleware for AppRules {
+    fn name(&self) -> &'static str {
+        "app-rules"
+    }
+
+    fn process(&mut self, input: &mut KeyInput, action: KeyAction) -> Step {
+        // Only then is the foreground window looked up
+        if !self.terminals.dead_keys && self.terminals.is_terminal_window(&(self.foreground)()) {
+            input.dead_keys = false;
+        }
+        Step::Next(action)
+    }
+}
+
+/// The nav layer: arrows, Home and End on letter keys while its trigger is
+/// held
+pub struct NavStage {
+    layer: NavLayer,
+}
+
+impl NavStage {
+    /// Move with `layer`
+    pub fn new(layer: NavLayer) -> Self {
+        Self { layer }
+    }
+}
+
+impl KeyMiddleware for NavStage {
+    fn name(&self) -> &'static str {
+        "nav"
+    }
+
+    fn process(&mut self, input: &mut KeyInput, action: KeyAction) -> Step {
+        match self.layer.key(input) {
+            Some(key) => Step::Next(KeyAction::InjectKey(key, Modifiers::default())),
+            None => Step::Next(action),
+        }
+    }
+}
+
+/// The virtual numpad over the letter block
+pub struct NumpadStage {
+    layer: NumpadLayer,
+}
+
+impl NumpadStage {
+    /// Type digits with `layer`
+    pub fn new(layer: NumpadLayer) -> Self {
+        Self { layer }
+    }
+}
+
+impl KeyMiddleware for NumpadStage {
+    fn name(&self) -> &'static str {
+        "numpad"
+    }
+
+    fn process(&mut self, input: &mut KeyInput, action: KeyAction) -> Step {
+        match self.layer.key(input) {
+            Some(c) => Step::Next(KeyAction::Replace(c)),
+            None => Step::Next(action),
+        }
+    }
+}
+
+/// The mapper: compose sequences, dead keys and the ABNT2 layout
+///
+/// Compose sequences stay inside the mapper, which decides per key whether
+/// it continues a sequence, completes an accent or maps on its own. A key
+/// an earlier stage already decided on (the nav layer, the numpad) keeps
+/// that action, and ends whatever the mapper was waiting on.
+pub struct MapperStage {
+    mapper: Mapper,
+}
+
+impl MapperStage {
+    /// Map with `mapper`
+    pub fn new(mapper: Mapper) -> Self {
+        Self { mapper }
+    }
+}
+
+impl KeyMiddleware for MapperStage {
+    fn name(&self) -> &'static str {
+        "mapper"
+    }
+
+    fn process(&mut self, input: &mut KeyInput, action: KeyAction) -> Step {
+        if action != KeyAction::Pass {
+            self.mapper.reset();
+            return Step::Next(action);
+        }
+        if let Some(direction) = input.direction {
+            self.mapper.set_direction(direction);
+        }
+        self.mapper.set_dead_keys(input.dead_keys);
+        Step::Next(self.mapper.process_key(input.event))
+    }
+
+    fn reset(&mut self) {
+        self.mapper.reset();
+    }
+}
+
+/// Autocorrect, watching everything that ends up on screen
+#[derive(Debug, Default)]
+pub struct AutocorrectStage {
+    autocorrect: Autocorrect,
+}
+
+impl KeyMiddleware for AutocorrectStage {
+    fn name(&self) -> &'static str {
+        "autocorrect"
+    }
+
+    fn process(&mut self, input: &mut KeyInput, action: KeyAction) -> Step {
+        Step::Next(self.autocorrect.process(action, input.typed))
+    }
+
+    fn reset(&mut self) {
+        self.autocorrect.reset();
+    }
+}
+
+/// Records what ends up on screen into the macro being recorded
+///
+/// The recorder is shared with whatever starts and stops recordings (the
+/// macro hotkeys).
+#[derive(Debug)]
+pub struct Snippets {
+    recorder: Arc<Mutex<MacroRecorder>>,
+}
+
+impl Snippets {
+    /// Record into `recorder`
+    pub fn new(recorder: Arc<Mutex<MacroRecorder>>) -> Self {
+        Self { recorder }
+    }
+}
+
+impl KeyMiddleware for Snippets {
+    fn name(&self) -> &'static str {
+        "snippets"
+    }
+
+    fn process(&mut self, input: &mut KeyInput, action: KeyAction) -> Step {
+        if let Ok(mut recorder) = self.recorder.lock() {
+            recorder.record(&action, input.typed);
+        }
+        Step::Next(action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::VirtualKey;
+
+    fn press(key: VirtualKey, typed: char) -> KeyInput {
+        KeyInput { typed: Some(typed), ..KeyInput::new(KeyEvent::press(key, false)) }
+    }
+
+    /// Types every key it sees as `*`
+    struct Stars;
+
+    impl KeyMiddleware for Stars {
+        fn name(&self) -> &'static str {
+            "stars"
+        }
+
+        fn process(&mut self, _input: &mut KeyInput, _action: KeyAction) -> Step {
+            Step::Next(KeyAction::Replace('*'))
+        }
+    }
+
+    #[test]
+    fn test_builder_orders_standard_stages() {
+        let recorder = Arc::new(Mutex::new(MacroRecorder::new()));
+        let pipeline = Pipeline::builder(Mapper::new())
+            .stage(Stars)
+            .snippets(recorder)
+            .autocorrect(true)
+            .numpad(&NumpadConfig { enabled: true, ..NumpadConfig::default() })
+            .nav(&NavConfig { enabled: true, ..NavConfig::default() })
+            .app_rules(TerminalsConfig::default(), WindowInfo::default)
+            .build();
+        assert_eq!(
+            pipeline.names(),
+            [
+                "modifier-filter",
+                "app-rules",
+                "nav",
+                "numpad",
+                "mapper",
+                "autocorrect",
+                "snippets",
+                "stars"
+            ]
+        );
+        let bare = Pipeline::builder(Mapper::new()).modifier_filter(false).build();
+        assert_eq!(bare.names(), ["mapper"]);
+    }
+
+    #[test]
+    fn test_stages_can_be_inserted_replaced_and_removed() {
+        let mut pipeline = Pipeline::builder(Mapper::new()).build();
+        assert_eq!(pipeline.process(press(VirtualKey::Semicolon, ';')), KeyAction::Replace('ç'));
+
+        assert!(pipeline.insert_after("mapper", Stars));
+        assert_eq!(pipeline.process(press(VirtualKey::Semicolon, ';')), KeyAction::Replace('*'));
+        assert!(pipeline.remove("stars").is_some());
+        assert!(!pipeline.insert_before("stars", Stars));
+
+        assert!(pipeline.set_mapper(Mapper::with_direction(Direction::Abnt2ToUs)));
+        assert_eq!(pipeline.names(), ["modifier-filter", "mapper"]);
+        assert!(!Pipeline::new().set_mapper(Mapper::new()));
+        assert_eq!(Pipeline::new().process(press(VirtualKey::Semicolon, ';')), KeyAction::Pass);
+    }
+
+    #[test]
+    fn test_modifier_filter_ends_the_pipeline() {
+        let mut pipeline = Pipeline::builder(Mapper::new()).stage(Stars).build();
+        let shortcut = KeyInput { shortcut: true, ..press(VirtualKey::Semicolon, ';') };
+        assert_eq!(pipeline.process(shortcut), KeyAction::Pass);
+
+        let alt = Modifiers { alt: true, ..Modifiers::default() };
+        let chord = KeyInput::new(KeyEvent::down(VirtualKey::Semicolon, alt));
+        assert_eq!(pipeline.process(chord), KeyAction::Pass);
+    }
+
+    #[test]
+    fn test_app_rules_turn_dead_keys_off_in_terminals() {
+        let terminals = TerminalsConfig { dead_keys: false, ..TerminalsConfig::default() };
+        let mut pipeline = Pipeline::builder(Mapper::new())
+            .app_rules(terminals, || WindowInfo::app("WindowsTerminal.exe"))
+            .build();
+        assert_eq!(pipeline.process(press(VirtualKey::Apostrophe, '\'')), KeyAction::Replace('~'));
+
+        // Dead keys are on in terminals by default
+        let mut with_dead_keys = Pipeline::builder(Mapper::new())
+            .app_rules(TerminalsConfig::default(), || WindowInfo::app("WindowsTerminal.exe"))
+            .build();
+        assert_eq!(
+            with_dead_keys.process(press(VirtualKey::Apostrophe, '\'')),
+            KeyAction::Suppress
+        );
+    }
+
+    #[test]
+    fn test_numpad_comes_before_the_mapper() {
+        let mut pipeline = Pipeline::builder(Mapper::new())
+            .numpad(&NumpadConfig { enabled: true, ..NumpadConfig::default() })
+            .build();
+        let altgr = Modifiers { alt: true, altgr: true, ..Modifiers::default() };
+        // An acute accent waits for its letter, then the numpad types a 3
+        assert_eq!(pipeline.process(press(VirtualKey::LeftBracket, '[')), KeyAction::Suppress);
+        let period = KeyInput::new(KeyEvent::down(VirtualKey::Period, altgr));
+        assert_eq!(pipeline.process(period), KeyAction::Replace('3'));
+        // The accent was dropped
+        let e = press(VirtualKey::Char('E'), 'e');
+        assert_eq!(pipeline.process(e), KeyAction::Pass);
+    }
+
+    #[test]
+    fn test_nav_layer_moves_instead_of_typing() {
+        let mut pipeline = Pipeline::builder(Mapper::new())
+            .nav(&NavConfig { enabled: true, ..NavConfig::default() })
+            .build();
+        let held = |input: KeyInput| KeyInput { nav: true, ..input };
+        // A pending accent is dropped when the cursor moves
+        assert_eq!(pipeline.process(press(VirtualKey::LeftBracket, '[')), KeyAction::Suppress);
+        assert_eq!(
+            pipeline.process(held(press(VirtualKey::Char('J'), 'j'))),
+            KeyAction::InjectKey(VirtualKey::Down, Modifiers::default())
+        );
+        assert_eq!(pipeline.process(press(VirtualKey::Char('E'), 'e')), KeyAction::Pass);
+        // Other keys are mapped as usual while the trigger is held
+        assert_eq!(
+            pipeline.process(held(press(VirtualKey::Semicolon, ';'))),
+            KeyAction::Replace('ç')
+        );
+    }
+
+    #[test]
+    fn test_snippets_record_corrected_output() {
+        let recorder = Arc::new(Mutex::new(MacroRecorder::new()));
+        recorder.lock().unwrap().start(1);
+        let mut pipeline = Pipeline::builder(Mapper::new())
+            .autocorrect(true)
+            .snippets(Arc::clone(&recorder))
+            .build();
+        for c in "nao ".chars() {
+            let key = crate::layout::us_key(c).unwrap().0;
+            pipeline.process(press(key, c));
+        }
+        assert_eq!(recorder.lock().unwrap().stop(), Some((1, "não ".to_string())));
+    }
+}
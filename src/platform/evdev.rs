@@ -0,0 +1,759 @@
+//! evdev keyboard backend (Linux)
+//!
+//! rdev's global listener can't suppress keys on every setup, so this backend
+//! takes keyboards over the way kmonad and kanata do: each keyboard under
+//! `/dev/input` is grabbed exclusively (`EVIOCGRAB`), so nothing else sees its
+//! keys, and whatever should reach applications is re-emitted through a
+//! uinput virtual keyboard. Characters a US keyboard can't type (ç, á, ...)
+//! are entered with Ctrl+Shift+U and their code point, which GTK and IBus
+//! understand.
+//!
+//! Selected with `backend = "evdev"` under `[linux]`, where `keyboards`
+//! limits it to particular keyboards (by name or USB ids) so remotes and
+//! other devices that register as keyboards are left alone. Keyboards plugged
+//! in later are picked up by polling `/dev/input`.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use evdev::uinput::{VirtualDevice, VirtualDeviceBuilder};
+use evdev::{AttributeSet, Device, EventType, InputEvent, InputEventKind, Key, LedType};
+
+use crate::config::LinuxConfig;
+use crate::error::{GhostKeysError, Result};
+use crate::events::Event;
+use crate::handoff::Handoff;
+use crate::history::Cause;
+use crate::i18n::{tr, Text};
+use crate::interceptor::{InjectionLimiter, KeyAction, KeyEvent, KeyUpFilter, Modifiers};
+use crate::layout::{evdev_code_to_virtual_key, virtual_key_to_scan_code};
+use crate::mapper::VirtualKey;
+use crate::nav::{self, NavHold};
+use crate::notify::Severity;
+use crate::pipeline::{KeyInput, Pipeline};
+use crate::remap::{Remap, RemapKey};
+use crate::state::{OperationMode, SharedState};
+
+/// Name of the uinput keyboard, also used to avoid grabbing it
+const VIRTUAL_KEYBOARD_NAME: &str = "GhostKeys virtual keyboard";
+
+/// How often /dev/input is checked for newly plugged keyboards
+const HOTPLUG_INTERVAL: Duration = Duration::from_secs(2);
+
+/// evdev key event values
+const KEY_UP: i32 = 0;
+const KEY_DOWN: i32 = 1;
+const KEY_REPEAT: i32 = 2;
+
+/// Everything the device threads share
+struct Backend {
+    state: SharedState,
+    /// Shared with the interceptor, which can swap in a new mapper
+    pipeline: Arc<Handoff<Pipeline>>,
+    output: Mutex<VirtualDevice>,
+    running: Arc<AtomicBool>,
+    /// Whether Ctrl+Alt+Space pauses and resumes
+    pause_hotkey: bool,
+    /// Key held for the nav layer, when `[nav]` turns it on
+    nav_trigger: Option<Key>,
+    /// Keys `[remap]` puts in place of others
+    remap: Remap,
+    /// Pause between typed characters (`char_delay_ms` under `[injection]`)
+    char_delay: Duration,
+    /// Stops runaway typing (`max_chars_per_second` under `[injection]`)
+    limiter: Mutex<InjectionLimiter>,
+    /// Which keyboards to remap
+    selection: LinuxConfig,
+    /// Event nodes already looked at (remapped, not a selected keyboard, or
+    /// one that couldn't be grabbed)
+    seen: Mutex<HashSet<PathBuf>>,
+}
+
+/// Modifier keys held on one keyboard
+#[derive(Debug, Default)]
+struct HeldModifiers {
+    left_shift: bool,
+    right_shift: bool,
+    alt: bool,
+    altgr: bool,
+    ctrl: bool,
+    meta: bool,
+    /// Caps Lock toggle, seeded from the keyboard's LED
+    caps_lock: bool,
+    /// The nav layer's trigger
+    nav: NavHold,
+}
+
+impl HeldModifiers {
+    /// Track a modifier key; returns false for other keys
+    fn update(&mut self, key: Key, down: bool) -> bool {
+        let held = match key {
+            Key::KEY_LEFTSHIFT => &mut self.left_shift,
+            Key::KEY_RIGHTSHIFT => &mut self.right_shift,
+            Key::KEY_LEFTALT => &mut self.alt,
+            Key::KEY_RIGHTALT => &mut self.altgr,
+            Key::KEY_LEFTCTRL | Key::KEY_RIGHTCTRL => &mut self.ctrl,
+            Key::KEY_LEFTMETA | Key::KEY_RIGHTMETA => &mut self.meta,
+            _ => return false,
+        };
+        *held = down;
+        true
+    }
+
+    /// Modifiers as the mapper sees them
+    fn for_mapper(&self) -> Modifiers {
+        Modifiers {
+            shift: self.left_shift || self.right_shift,
+            alt: self.alt,
+            altgr: self.altgr,
+            caps_lock: self.caps_lock,
+        }
+    }
+
+    /// Shortcuts (Ctrl+C, Super+L) are never remapped
+    fn is_shortcut(&self) -> bool {
+        self.ctrl || self.meta
+    }
+
+    /// Ctrl+Alt+Space, which pauses and resumes GhostKeys
+    fn is_pause_hotkey(&self, key: Key) -> bool {
+        key == Key::KEY_SPACE
+            && self.ctrl
+            && self.alt
+            && !self.left_shift
+            && !self.right_shift
+            && !self.meta
+    }
+}
+
+/// Whether a device looks like a keyboard (has letters, Enter and Space)
+fn is_keyboard(device: &Device) -> bool {
+    device.name() != Some(VIRTUAL_KEYBOARD_NAME)
+        && device.supported_keys().is_some_and(|keys| {
+            [Key::KEY_A, Key::KEY_Z, Key::KEY_ENTER, Key::KEY_SPACE]
+                .into_iter()
+                .all(|key| keys.contains(key))
+        })
+}
+
+/// Start remapping the selected keyboards, each on its own thread
+///
+/// A watcher thread picks up keyboards plugged in later. All threads run
+/// until `running` is cleared; a thread blocked waiting for its keyboard
+/// notices on the next event, which it passes through before releasing the
+/// grab. `pipeline` is used for every key, so replacing its mapper takes
+/// effect on the next key press.
+pub fn start(
+    state: &SharedState,
+    pipeline: Arc<Handoff<Pipeline>>,
+    running: Arc<AtomicBool>,
+) -> Result<JoinHandle<()>> {
+    let mut keys = AttributeSet::<Key>::new();
+    for code in 1..=255 {
+        keys.insert(Key::new(code));
+    }
+    let output = VirtualDeviceBuilder::new()
+        .and_then(|builder| builder.name(VIRTUAL_KEYBOARD_NAME).with_keys(&keys))
+        .and_then(|builder| builder.build())
+        .map_err(|e| GhostKeysError::HookInstallError(format!("Failed to create uinput keyboard: {}", e)))?;
+
+    let config = state.get_config()?;
+    let backend = Arc::new(Backend {
+        state: state.clone(),
+        pipeline,
+        output: Mutex::new(output),
+        running,
+        pause_hotkey: config.hotkeys.toggle_pause,
+        nav_trigger: key_code(nav::trigger_key(config.nav.trigger)).filter(|_| config.nav.enabled),
+        remap: Remap::from_config(&config.remap),
+        char_delay: config.injection.char_delay_for(None),
+        limiter: Mutex::new(InjectionLimiter::new(config.injection.max_chars_per_second)),
+        selection: config.linux,
+        seen: Mutex::new(HashSet::new()),
+    });
+
+    if scan(&backend) == 0 {
+        let _ = state.notify(
+            Severity::Warning,
+            "No keyboard to remap yet; GhostKeys will pick one up when it's plugged in",
+        );
+    }
+    Ok(thread::spawn(move || {
+        while backend.running.load(Ordering::Relaxed) {
+            thread::sleep(HOTPLUG_INTERVAL);
+            scan(&backend);
+        }
+    }))
+}
+
+/// Start remapping keyboards not seen before; returns how many were started
+fn scan(backend: &Arc<Backend>) -> usize {
+    let Ok(entries) = fs::read_dir("/dev/input") else {
+        return 0;
+    };
+    let present: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.file_name().is_some_and(|name| name.to_string_lossy().starts_with("event")))
+        .collect();
+    let Ok(mut seen) = backend.seen.lock() else {
+        return 0;
+    };
+    // Event node numbers are reused after a device is unplugged
+    seen.retain(|path| present.contains(path));
+
+    let mut started = 0;
+    for path in present {
+        if seen.contains(&path) {
+            continue;
+        }
+        // Permissions may not be applied yet to a device just plugged in
+        let Ok(device) = Device::open(&path) else {
+            continue;
+        };
+        seen.insert(path.clone());
+        if !is_keyboard(&device) {
+            continue;
+        }
+
+        let name = device.name().unwrap_or("keyboard").to_string();
+        let id = device.input_id();
+        let remap = backend.selection.remaps(&name, id.vendor(), id.product());
+        println!(
+            "Keyboard \"{}\" (vendor {:#06x}, product {:#06x}) at {}: {}",
+            name,
+            id.vendor(),
+            id.product(),
+            path.display(),
+            if remap { "remapping" } else { "not selected under [linux] keyboards" }
+        );
+        if !remap {
+            continue;
+        }
+
+        let backend = Arc::clone(backend);
+        thread::spawn(move || {
+            // A keyboard that can't be grabbed stays seen, so it's reported
+            // once rather than on every scan; unplugging it forgets it
+            if !run_device(&backend, device, &name) {
+                return;
+            }
+            // Pick the keyboard up again if it comes back
+            if let Ok(mut seen) = backend.seen.lock() {
+                seen.remove(&path);
+            }
+        });
+        started += 1;
+    }
+    started
+}
+
+/// Remap one grabbed keyboard until the backend stops; returns false if it
+/// couldn't be grabbed
+fn run_device(backend: &Backend, mut device: Device, name: &str) -> bool {
+    if let Err(e) = device.grab() {
+        let _ = backend.state.notify(
+            Severity::Warning,
+            format!("Can't grab {}, it won't be remapped: {}", name, e),
+        );
+        return false;
+    }
+
+    let mut modifiers = HeldModifiers {
+        caps_lock: device
+            .get_led_state()
+            .is_ok_and(|leds| leds.contains(LedType::LED_CAPSL)),
+        ..HeldModifiers::default()
+    };
+    let mut key_up_filter = KeyUpFilter::new();
+
+    while backend.running.load(Ordering::Relaxed) {
+        let events: Vec<InputEvent> = match device.fetch_events() {
+            Ok(events) => events.collect(),
+            Err(e) => {
+                let _ = backend
+                    .state
+                    .notify(Severity::Warning, format!("Stopped remapping {}: {}", name, e));
+                break;
+            }
+        };
+        for event in events {
+            let InputEventKind::Key(key) = event.kind() else {
+                // The virtual keyboard sends its own sync reports
+                continue;
+            };
+            if backend.running.load(Ordering::Relaxed) {
+                handle_key(backend, &mut modifiers, &mut key_up_filter, key, event.value());
+            } else {
+                emit(backend, &[event]);
+            }
+        }
+    }
+
+    let _ = device.ungrab();
+    true
+}
+
+/// Remap one key event, passing through whatever isn't remapped
+fn handle_key(
+    backend: &Backend,
+    modifiers: &mut HeldModifiers,
+    key_up_filter: &mut KeyUpFilter,
+    key: Key,
+    value: i32,
+) {
+    let event = InputEvent::new(EventType::KEY, key.code(), value);
+    let code = u32::from(key.code());
+
+    // [remap] comes first: a remapped key is no longer a trigger, a modifier
+    // or a key of its own
+    let remapped = remap_key(key)
+        .filter(|_| !backend.state.is_paused())
+        .and_then(|source| backend.remap.target(source));
+
+    // The nav layer's trigger is held back until it's released; tapped on
+    // its own, it's pressed then. With Ctrl, Alt or Super (Alt+Tab) it goes
+    // through as usual
+    if remapped.is_none() && backend.nav_trigger == Some(key) {
+        if value == KEY_UP {
+            if modifiers.nav.trigger_up() {
+                if key == Key::KEY_CAPSLOCK {
+                    modifiers.caps_lock = !modifiers.caps_lock;
+                }
+                tap(backend, key);
+            }
+        } else {
+            let chord = modifiers.is_shortcut() || modifiers.alt;
+            if !backend.state.is_paused() && modifiers.nav.trigger_down(value == KEY_REPEAT, chord) {
+                key_up_filter.key_down(code, true);
+                return;
+            }
+        }
+    }
+
+    if remapped.is_none() {
+        // Caps Lock passes through as usual; the mapper needs to know it's on
+        if key == Key::KEY_CAPSLOCK && value == KEY_DOWN {
+            modifiers.caps_lock = !modifiers.caps_lock;
+        }
+        if modifiers.update(key, value != KEY_UP) {
+            emit(backend, &[event]);
+            return;
+        }
+    }
+    if value == KEY_UP {
+        if !key_up_filter.key_up(code) {
+            emit(backend, &[event]);
+        }
+        return;
+    }
+    modifiers.nav.key_down();
+
+    // Works while paused, so it's the way back when the tray icon is hidden
+    if backend.pause_hotkey && modifiers.is_pause_hotkey(key) {
+        key_up_filter.key_down(code, true);
+        if value != KEY_REPEAT {
+            let status = match backend.state.toggle_mode_because(Cause::Hotkey) {
+                Ok(OperationMode::Active) => Text::StatusActive,
+                _ => Text::StatusPaused,
+            };
+            let _ = backend.state.notify(Severity::Info, tr(status));
+        }
+        return;
+    }
+
+    let action = if backend.state.is_paused() {
+        KeyAction::Pass
+    } else if let Some(target) = remapped.filter(|&target| target != VirtualKey::Compose) {
+        // Whatever the pipeline was waiting on is dropped
+        let _ = backend.pipeline.with(Pipeline::reset);
+        KeyAction::InjectKey(target, Modifiers::default())
+    } else {
+        let virtual_key = match remapped {
+            Some(_) => VirtualKey::Compose,
+            None => evdev_code_to_virtual_key(key.code()).unwrap_or(VirtualKey::Other),
+        };
+        match virtual_key {
+            VirtualKey::Other => KeyAction::Pass,
+            virtual_key => {
+                let event = KeyEvent {
+                    repeat: value == KEY_REPEAT,
+                    ..KeyEvent::down(virtual_key, modifiers.for_mapper())
+                };
+                let _ = backend.state.publish(Event::KeyPressed(event));
+                let input = KeyInput {
+                    shortcut: modifiers.is_shortcut(),
+                    direction: backend.state.get_direction().ok(),
+                    nav: modifiers.nav.is_held(),
+                    ..KeyInput::new(event)
+                };
+                let action = backend
+                    .pipeline
+                    .with(|pipeline| pipeline.process(input))
+                    .unwrap_or(KeyAction::Pass);
+                let decided = Event::ActionDecided { event, action: action.clone() };
+                let _ = backend.state.publish(decided);
+                action
+            }
+        }
+    };
+
+    let blocked = !matches!(
+        action,
+        KeyAction::Pass | KeyAction::ReplaceThenPass(_) | KeyAction::ReplaceMultipleThenPass(_)
+    );
+    key_up_filter.key_down(code, blocked);
+    match action {
+        KeyAction::Pass => emit(backend, &[event]),
+        KeyAction::Suppress => {}
+        KeyAction::Replace(c) => type_chars(backend, modifiers, &[c]),
+        KeyAction::ReplaceMultiple(chars) => type_chars(backend, modifiers, &chars),
+        KeyAction::ReplaceStr(text) => {
+            type_chars(backend, modifiers, &text.chars().collect::<Vec<_>>())
+        }
+        KeyAction::ReplaceThenPass(c) => {
+            type_chars(backend, modifiers, &[c]);
+            emit(backend, &[event]);
+        }
+        KeyAction::ReplaceMultipleThenPass(chars) => {
+            type_chars(backend, modifiers, &chars);
+            emit(backend, &[event]);
+        }
+        KeyAction::Rewrite { backspaces, chars } => {
+            if within_flood_limit(backend, backspaces) {
+                for _ in 0..backspaces {
+                    tap(backend, Key::KEY_BACKSPACE);
+                }
+                type_chars(backend, modifiers, &chars);
+            }
+        }
+        KeyAction::InjectKey(key, with) => inject_key(backend, modifiers, key, with),
+    }
+}
+
+/// evdev key of a key, if it has one
+fn key_code(key: VirtualKey) -> Option<Key> {
+    let code = match key {
+        VirtualKey::Insert => Key::KEY_INSERT,
+        VirtualKey::Delete => Key::KEY_DELETE,
+        VirtualKey::Home => Key::KEY_HOME,
+        VirtualKey::End => Key::KEY_END,
+        VirtualKey::PageUp => Key::KEY_PAGEUP,
+        VirtualKey::PageDown => Key::KEY_PAGEDOWN,
+        VirtualKey::Left => Key::KEY_LEFT,
+        VirtualKey::Right => Key::KEY_RIGHT,
+        VirtualKey::Up => Key::KEY_UP,
+        VirtualKey::Down => Key::KEY_DOWN,
+        // The menu key is KEY_COMPOSE to the kernel
+        VirtualKey::Menu => Key::KEY_COMPOSE,
+        VirtualKey::Pause => Key::KEY_PAUSE,
+        VirtualKey::PrintScreen => Key::KEY_SYSRQ,
+        VirtualKey::IntlRo => Key::KEY_RO,
+        // Outside the navigation block, evdev codes are the scan codes
+        key => {
+            let (code, _) = virtual_key_to_scan_code(key)?;
+            Key::new(u16::from(code))
+        }
+    };
+    Some(code)
+}
+
+/// evdev key of a key named in `[remap]`; none for Compose
+fn remap_key_code(key: RemapKey) -> Option<Key> {
+    let code = match key {
+        RemapKey::LeftCtrl => Key::KEY_LEFTCTRL,
+        RemapKey::RightCtrl => Key::KEY_RIGHTCTRL,
+        RemapKey::LeftAlt => Key::KEY_LEFTALT,
+        RemapKey::RightAlt => Key::KEY_RIGHTALT,
+        RemapKey::LeftShift => Key::KEY_LEFTSHIFT,
+        RemapKey::RightShift => Key::KEY_RIGHTSHIFT,
+        RemapKey::LeftWin => Key::KEY_LEFTMETA,
+        RemapKey::RightWin => Key::KEY_RIGHTMETA,
+        key => return key.virtual_key().and_then(key_code),
+    };
+    Some(code)
+}
+
+/// Key named in `[remap]` for an evdev key, if any
+fn remap_key(key: Key) -> Option<RemapKey> {
+    RemapKey::all().find(|&named| remap_key_code(named) == Some(key))
+}
+
+/// Press and release a key, holding the modifiers `with` asks for that the
+/// user doesn't hold already
+fn inject_key(backend: &Backend, modifiers: &mut HeldModifiers, key: VirtualKey, with: Modifiers) {
+    let Some(code) = key_code(key) else {
+        return;
+    };
+    if key == VirtualKey::CapsLock {
+        modifiers.caps_lock = !modifiers.caps_lock;
+    }
+    let held: Vec<Key> = [
+        (with.shift && !modifiers.left_shift && !modifiers.right_shift, Key::KEY_LEFTSHIFT),
+        (with.altgr && !modifiers.altgr, Key::KEY_RIGHTALT),
+        (with.alt && !with.altgr && !modifiers.alt, Key::KEY_LEFTALT),
+    ]
+    .into_iter()
+    .filter_map(|(press, key)| press.then_some(key))
+    .collect();
+
+    for &modifier in &held {
+        emit(backend, &[key_event(modifier, true)]);
+    }
+    tap(backend, code);
+    for &modifier in held.iter().rev() {
+        emit(backend, &[key_event(modifier, false)]);
+    }
+}
+
+/// Send events through the virtual keyboard (followed by a sync report)
+fn emit(backend: &Backend, events: &[InputEvent]) {
+    let result = match backend.output.lock() {
+        Ok(mut output) => output.emit(events).map_err(|e| e.to_string()),
+        Err(_) => Err("output lock poisoned".to_string()),
+    };
+    if let Err(e) = result {
+        let _ = backend.state.notify(
+            Severity::Warning,
+            GhostKeysError::KeyInjectionError(e).to_string(),
+        );
+    }
+}
+
+/// Count characters about to be typed against the flood limit
+///
+/// When it trips, nothing is typed and GhostKeys pauses so the keyboard works
+/// again.
+fn within_flood_limit(backend: &Backend, count: usize) -> bool {
+    let result = match backend.limiter.lock() {
+        Ok(mut limiter) => limiter.check(count, Instant::now()),
+        Err(_) => Ok(()),
+    };
+    let Err(e) = result else {
+        return true;
+    };
+    let _ = backend.state.set_mode_because(OperationMode::Passthrough, Cause::Flood);
+    let message = match e.hint() {
+        Some(hint) => format!("{}. {}", e, hint),
+        None => e.to_string(),
+    };
+    let _ = backend.state.notify(Severity::Warning, message);
+    false
+}
+
+/// Press or release a key
+fn key_event(key: Key, down: bool) -> InputEvent {
+    InputEvent::new(EventType::KEY, key.code(), if down { KEY_DOWN } else { KEY_UP })
+}
+
+/// Press and release a key
+fn tap(backend: &Backend, key: Key) {
+    emit(backend, &[key_event(key, true)]);
+    emit(backend, &[key_event(key, false)]);
+}
+
+/// Type characters, releasing the user's Shift meanwhile so it doesn't
+/// change them
+///
+/// Caps Lock can't be released, so letters typed by key code flip Shift to
+/// come out in the requested case.
+fn type_chars(backend: &Backend, modifiers: &HeldModifiers, chars: &[char]) {
+    if !within_flood_limit(backend, chars.len()) {
+        return;
+    }
+    let shifts: Vec<Key> = [
+        (modifiers.left_shift, Key::KEY_LEFTSHIFT),
+        (modifiers.right_shift, Key::KEY_RIGHTSHIFT),
+    ]
+    .into_iter()
+    .filter_map(|(held, key)| held.then_some(key))
+    .collect();
+
+    for &shift in &shifts {
+        emit(backend, &[key_event(shift, false)]);
+    }
+    for (i, &c) in chars.iter().enumerate() {
+        if i > 0 && !backend.char_delay.is_zero() {
+            thread::sleep(backend.char_delay);
+        }
+        let caps_lock = modifiers.caps_lock && c.is_ascii_alphabetic();
+        match us_key(c).map(|(key, shift)| (key, shift != caps_lock)) {
+            Some((key, false)) => tap(backend, key),
+            Some((key, true)) => {
+                emit(backend, &[key_event(Key::KEY_LEFTSHIFT, true)]);
+                tap(backend, key);
+                emit(backend, &[key_event(Key::KEY_LEFTSHIFT, false)]);
+            }
+            None => type_unicode(backend, c),
+        }
+    }
+    for &shift in &shifts {
+        emit(backend, &[key_event(shift, true)]);
+    }
+    let _ = backend.state.publish(Event::Injected(chars.to_vec()));
+}
+
+/// Enter a character by code point: Ctrl+Shift+U, hex digits, Space
+fn type_unicode(backend: &Backend, c: char) {
+    emit(
+        backend,
+        &[
+            key_event(Key::KEY_LEFTCTRL, true),
+            key_event(Key::KEY_LEFTSHIFT, true),
+            key_event(Key::KEY_U, true),
+            key_event(Key::KEY_U, false),
+            key_event(Key::KEY_LEFTSHIFT, false),
+            key_event(Key::KEY_LEFTCTRL, false),
+        ],
+    );
+    for digit in format!("{:x}", u32::from(c)).chars() {
+        if let Some((key, _)) = us_key(digit) {
+            tap(backend, key);
+        }
+    }
+    tap(backend, Key::KEY_SPACE);
+}
+
+/// Letters in alphabetical order
+const LETTER_KEYS: [Key; 26] = [
+    Key::KEY_A, Key::KEY_B, Key::KEY_C, Key::KEY_D, Key::KEY_E, Key::KEY_F, Key::KEY_G,
+    Key::KEY_H, Key::KEY_I, Key::KEY_J, Key::KEY_K, Key::KEY_L, Key::KEY_M, Key::KEY_N,
+    Key::KEY_O, Key::KEY_P, Key::KEY_Q, Key::KEY_R, Key::KEY_S, Key::KEY_T, Key::KEY_U,
+    Key::KEY_V, Key::KEY_W, Key::KEY_X, Key::KEY_Y, Key::KEY_Z,
+];
+
+/// Digits 0-9
+const DIGIT_KEYS: [Key; 10] = [
+    Key::KEY_0, Key::KEY_1, Key::KEY_2, Key::KEY_3, Key::KEY_4,
+    Key::KEY_5, Key::KEY_6, Key::KEY_7, Key::KEY_8, Key::KEY_9,
+];
+
+/// Key and Shift state that type `c` on a US layout, if any
+fn us_key(c: char) -> Option<(Key, bool)> {
+    if c.is_ascii_lowercase() {
+        return Some((LETTER_KEYS[(c as u8 - b'a') as usize], false));
+    }
+    if c.is_ascii_uppercase() {
+        return Some((LETTER_KEYS[(c as u8 - b'A') as usize], true));
+    }
+    if c.is_ascii_digit() {
+        return Some((DIGIT_KEYS[(c as u8 - b'0') as usize], false));
+    }
+    let key = match c {
+        ' ' => (Key::KEY_SPACE, false),
+        '\n' => (Key::KEY_ENTER, false),
+        '\t' => (Key::KEY_TAB, false),
+        '-' => (Key::KEY_MINUS, false),
+        '_' => (Key::KEY_MINUS, true),
+        '=' => (Key::KEY_EQUAL, false),
+        '+' => (Key::KEY_EQUAL, true),
+        '[' => (Key::KEY_LEFTBRACE, false),
+        '{' => (Key::KEY_LEFTBRACE, true),
+        ']' => (Key::KEY_RIGHTBRACE, false),
+        '}' => (Key::KEY_RIGHTBRACE, true),
+        '\\' => (Key::KEY_BACKSLASH, false),
+        '|' => (Key::KEY_BACKSLASH, true),
+        ';' => (Key::KEY_SEMICOLON, false),
+        ':' => (Key::KEY_SEMICOLON, true),
+        '\'' => (Key::KEY_APOSTROPHE, false),
+        '"' => (Key::KEY_APOSTROPHE, true),
+        '`' => (Key::KEY_GRAVE, false),
+        '~' => (Key::KEY_GRAVE, true),
+        ',' => (Key::KEY_COMMA, false),
+        '<' => (Key::KEY_COMMA, true),
+        '.' => (Key::KEY_DOT, false),
+        '>' => (Key::KEY_DOT, true),
+        '/' => (Key::KEY_SLASH, false),
+        '?' => (Key::KEY_SLASH, true),
+        '!' => (Key::KEY_1, true),
+        '@' => (Key::KEY_2, true),
+        '#' => (Key::KEY_3, true),
+        '$' => (Key::KEY_4, true),
+        '%' => (Key::KEY_5, true),
+        '^' => (Key::KEY_6, true),
+        '&' => (Key::KEY_7, true),
+        '*' => (Key::KEY_8, true),
+        '(' => (Key::KEY_9, true),
+        ')' => (Key::KEY_0, true),
+        _ => return None,
+    };
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_us_key() {
+        assert_eq!(us_key('a'), Some((Key::KEY_A, false)));
+        assert_eq!(us_key('Q'), Some((Key::KEY_Q, true)));
+        assert_eq!(us_key('7'), Some((Key::KEY_7, false)));
+        assert_eq!(us_key('{'), Some((Key::KEY_LEFTBRACE, true)));
+        assert_eq!(us_key('ç'), None);
+    }
+
+    #[test]
+    fn test_evdev_codes_are_scan_codes() {
+        // The mapper's scan code table applies to evdev key codes as-is
+        assert_eq!(evdev_code_to_virtual_key(Key::KEY_SEMICOLON.code()), Some(VirtualKey::Semicolon));
+        assert_eq!(evdev_code_to_virtual_key(Key::KEY_LEFTBRACE.code()), Some(VirtualKey::LeftBracket));
+        assert_eq!(evdev_code_to_virtual_key(Key::KEY_SPACE.code()), Some(VirtualKey::Space));
+        // ...but for the ABNT2 /? key
+        assert_eq!(evdev_code_to_virtual_key(Key::KEY_RO.code()), Some(VirtualKey::IntlRo));
+        assert_eq!(evdev_code_to_virtual_key(Key::KEY_VOLUMEUP.code()), None);
+    }
+
+    #[test]
+    fn test_modifiers() {
+        let mut modifiers = HeldModifiers::default();
+        assert!(modifiers.update(Key::KEY_RIGHTSHIFT, true));
+        assert!(modifiers.update(Key::KEY_RIGHTALT, true));
+        assert!(!modifiers.update(Key::KEY_A, true));
+        assert_eq!(
+            modifiers.for_mapper(),
+            Modifiers { shift: true, alt: false, altgr: true, caps_lock: false }
+        );
+        assert!(!modifiers.is_shortcut());
+        modifiers.update(Key::KEY_LEFTCTRL, true);
+        assert!(modifiers.is_shortcut());
+    }
+
+    #[test]
+    fn test_pause_hotkey() {
+        let mut modifiers = HeldModifiers::default();
+        modifiers.update(Key::KEY_LEFTCTRL, true);
+        assert!(!modifiers.is_pause_hotkey(Key::KEY_SPACE));
+        modifiers.update(Key::KEY_LEFTALT, true);
+        assert!(modifiers.is_pause_hotkey(Key::KEY_SPACE));
+        assert!(!modifiers.is_pause_hotkey(Key::KEY_A));
+        modifiers.update(Key::KEY_LEFTSHIFT, true);
+        assert!(!modifiers.is_pause_hotkey(Key::KEY_SPACE));
+    }
+
+    #[test]
+    fn test_remap_keys() {
+        assert_eq!(remap_key(Key::KEY_CAPSLOCK), Some(RemapKey::CapsLock));
+        assert_eq!(remap_key(Key::KEY_COMPOSE), Some(RemapKey::Menu));
+        assert_eq!(remap_key(Key::KEY_A), None);
+        // Every key but Compose is a key of its own
+        for key in RemapKey::all().filter(|&key| key != RemapKey::Compose) {
+            assert_eq!(remap_key_code(key).and_then(remap_key), Some(key));
+        }
+    }
+
+    #[test]
+    fn test_key_codes() {
+        assert_eq!(key_code(VirtualKey::Char('A')), Some(Key::KEY_A));
+        assert_eq!(key_code(VirtualKey::Semicolon), Some(Key::KEY_SEMICOLON));
+        assert_eq!(key_code(VirtualKey::Backspace), Some(Key::KEY_BACKSPACE));
+        assert_eq!(key_code(VirtualKey::CapsLock), Some(Key::KEY_CAPSLOCK));
+        assert_eq!(key_code(VirtualKey::Left), Some(Key::KEY_LEFT));
+        assert_eq!(key_code(VirtualKey::Other), None);
+    }
+}
@@ -0,0 +1,358 @@
+//! IBus input-method engine (Linux, `ibus` feature)
+//!
+//! Instead of grabbing keyboards, GhostKeys can run as an IBus engine
+//! ("GhostKeys ABNT2") that the desktop's input-method framework calls for
+//! every key in the focused input context. Remapped characters are committed
+//! as text, so this works on Wayland and respects per-application input
+//! contexts, at the cost of only affecting applications that use IBus.
+//!
+//! IBus starts the engine itself from a component file:
+//! `ghostkeys --ibus-component | sudo tee /usr/share/ibus/component/ghostkeys.xml`,
+//! then `ibus restart` and add "GhostKeys ABNT2" in the input sources. IBus
+//! runs `ghostkeys --ibus`, which connects to its bus and serves the engine.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+use zbus::blocking::connection;
+use zbus::fdo;
+use zbus::interface;
+use zbus::object_server::SignalContext;
+use zbus::zvariant::{OwnedObjectPath, Value};
+use zbus::ObjectServer;
+
+use crate::error::{GhostKeysError, Result};
+use crate::events::Event;
+use crate::interceptor::{build_mapper, KeyAction, KeyEvent, KeyUpFilter, Modifiers};
+use crate::layout::evdev_code_to_virtual_key;
+use crate::mapper::{Mapper, VirtualKey};
+use crate::state::SharedState;
+
+/// Bus name the engine owns on the IBus bus
+const BUS_NAME: &str = "org.freedesktop.IBus.GhostKeys";
+
+/// Object path IBus asks for engines at
+const FACTORY_PATH: &str = "/org/freedesktop/IBus/Factory";
+
+/// Name of the engine in the component file
+const ENGINE_NAME: &str = "ghostkeys";
+
+/// IBus modifier state bits
+const SHIFT_MASK: u32 = 1 << 0;
+const LOCK_MASK: u32 = 1 << 1; // Caps Lock
+const CONTROL_MASK: u32 = 1 << 2;
+const MOD1_MASK: u32 = 1 << 3; // Alt
+const MOD4_MASK: u32 = 1 << 6; // Super
+const MOD5_MASK: u32 = 1 << 7; // AltGr (ISO_Level3_Shift)
+const RELEASE_MASK: u32 = 1 << 30;
+
+/// BackSpace keysym and evdev key code, for erasing before a rewrite
+const KEYVAL_BACKSPACE: u32 = 0xff08;
+const KEYCODE_BACKSPACE: u32 = 14;
+
+/// What the engine does with a key event
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EngineOutput {
+    /// The key was consumed and must not reach the application
+    pub handled: bool,
+    /// Characters to erase before committing
+    pub backspaces: usize,
+    /// Text to commit
+    pub commit: String,
+}
+
+/// Key handling of one engine instance, independent of D-Bus
+pub struct Engine {
+    state: SharedState,
+    mapper: Mapper,
+    key_up_filter: KeyUpFilter,
+}
+
+impl Engine {
+    /// Create an engine with the layout and settings in `state`
+    pub fn new(state: &SharedState) -> Result<Self> {
+        Ok(Self {
+            state: state.clone(),
+            mapper: build_mapper(state)?,
+            key_up_filter: KeyUpFilter::new(),
+        })
+    }
+
+    /// Handle a key event; `keycode` is the evdev key code IBus reports
+    pub fn process(&mut self, keycode: u32, modifiers: u32) -> EngineOutput {
+        if modifiers & RELEASE_MASK != 0 {
+            return EngineOutput {
+                handled: self.key_up_filter.key_up(keycode),
+                ..Default::default()
+            };
+        }
+
+        let repeat = self.key_up_filter.is_held(keycode);
+        let shortcut = modifiers & (CONTROL_MASK | MOD4_MASK) != 0;
+        let action = if self.state.is_paused() || shortcut {
+            KeyAction::Pass
+        } else {
+            match u16::try_from(keycode).ok().and_then(evdev_code_to_virtual_key) {
+                None | Some(VirtualKey::Other) => KeyAction::Pass,
+                Some(key) => {
+                    if let Ok(direction) = self.state.get_direction() {
+                        self.mapper.set_direction(direction);
+                    }
+                    let modifiers = Modifiers {
+                        shift: modifiers & SHIFT_MASK != 0,
+                        alt: modifiers & MOD1_MASK != 0,
+                        altgr: modifiers & MOD5_MASK != 0,
+                        caps_lock: modifiers & LOCK_MASK != 0,
+                    };
+                    let event = KeyEvent { repeat, ..KeyEvent::down(key, modifiers) };
+                    let _ = self.state.publish(Event::KeyPressed(event));
+                    let action = self.mapper.process_key(event);
+                    let decided = Event::ActionDecided { event, action: action.clone() };
+                    let _ = self.state.publish(decided);
+                    action
+                }
+            }
+        };
+
+        // The accent flushed before Enter is committed, and the key forwarded
+        let handled = !matches!(
+            action,
+            KeyAction::Pass | KeyAction::ReplaceThenPass(_) | KeyAction::ReplaceMultipleThenPass(_)
+        );
+        self.key_up_filter.key_down(keycode, handled);
+        let (backspaces, commit) = match action {
+            KeyAction::Pass | KeyAction::Suppress | KeyAction::InjectKey(..) => (0, String::new()),
+            KeyAction::Replace(c) | KeyAction::ReplaceThenPass(c) => (0, c.to_string()),
+            KeyAction::ReplaceMultiple(chars) | KeyAction::ReplaceMultipleThenPass(chars) => {
+                (0, chars.into_iter().collect())
+            }
+            KeyAction::ReplaceStr(text) => (0, text),
+            KeyAction::Rewrite { backspaces, chars } => (backspaces, chars.into_iter().collect()),
+        };
+        if !commit.is_empty() {
+            let _ = self.state.publish(Event::Injected(commit.chars().collect()));
+        }
+        EngineOutput { handled, backspaces, commit }
+    }
+
+    /// Drop a pending accent (focus change, reset from IBus)
+    pub fn reset(&mut self) {
+        self.mapper.reset();
+        self.key_up_filter.clear();
+    }
+}
+
+/// IBusText serialized as IBus expects: ("IBusText", {}, text, IBusAttrList)
+fn ibus_text(text: &str) -> Value<'_> {
+    let attributes = ("IBusAttrList", HashMap::<&str, Value>::new(), Vec::<Value>::new());
+    Value::from(("IBusText", HashMap::<&str, Value>::new(), text, Value::from(attributes)))
+}
+
+/// org.freedesktop.IBus.Engine object for one engine instance
+struct EngineObject {
+    engine: Engine,
+}
+
+#[interface(name = "org.freedesktop.IBus.Engine")]
+impl EngineObject {
+    async fn process_key_event(
+        &mut self,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        _keyval: u32,
+        keycode: u32,
+        state: u32,
+    ) -> bool {
+        let output = self.engine.process(keycode, state);
+        for _ in 0..output.backspaces {
+            let _ = Self::forward_key_event(&ctxt, KEYVAL_BACKSPACE, KEYCODE_BACKSPACE, 0).await;
+            let _ = Self::forward_key_event(&ctxt, KEYVAL_BACKSPACE, KEYCODE_BACKSPACE, RELEASE_MASK).await;
+        }
+        if !output.commit.is_empty() {
+            let _ = Self::commit_text(&ctxt, ibus_text(&output.commit)).await;
+        }
+        output.handled
+    }
+
+    fn focus_in(&mut self) {}
+
+    fn focus_out(&mut self) {
+        self.engine.reset();
+    }
+
+    fn reset(&mut self) {
+        self.engine.reset();
+    }
+
+    fn enable(&mut self) {}
+
+    fn disable(&mut self) {
+        self.engine.reset();
+    }
+
+    fn set_cursor_location(&mut self, _x: i32, _y: i32, _w: i32, _h: i32) {}
+
+    fn set_capabilities(&mut self, _caps: u32) {}
+
+    #[zbus(signal)]
+    async fn commit_text(ctxt: &SignalContext<'_>, text: Value<'_>) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn forward_key_event(
+        ctxt: &SignalContext<'_>,
+        keyval: u32,
+        keycode: u32,
+        state: u32,
+    ) -> zbus::Result<()>;
+}
+
+/// org.freedesktop.IBus.Factory, which IBus asks for engine instances
+struct Factory {
+    state: SharedState,
+    created: u32,
+}
+
+#[interface(name = "org.freedesktop.IBus.Factory")]
+impl Factory {
+    async fn create_engine(
+        &mut self,
+        #[zbus(object_server)] server: &ObjectServer,
+        name: &str,
+    ) -> fdo::Result<OwnedObjectPath> {
+        if name != ENGINE_NAME {
+            return Err(fdo::Error::InvalidArgs(format!("Unknown engine {}", name)));
+        }
+        let engine = Engine::new(&self.state).map_err(|e| fdo::Error::Failed(e.to_string()))?;
+        self.created += 1;
+        let path = format!("/org/freedesktop/IBus/Engine/{}", self.created);
+        server.at(path.as_str(), EngineObject { engine }).await?;
+        OwnedObjectPath::try_from(path).map_err(|e| fdo::Error::Failed(e.to_string()))
+    }
+}
+
+/// Address of the running IBus daemon's bus
+fn ibus_address() -> Result<String> {
+    if let Ok(address) = std::env::var("IBUS_ADDRESS") {
+        return Ok(address);
+    }
+    let output = Command::new("ibus")
+        .arg("address")
+        .output()
+        .map_err(|e| GhostKeysError::HookInstallError(format!("Failed to run `ibus address`: {}", e)))?;
+    let address = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || address.is_empty() || address == "(null)" {
+        return Err(GhostKeysError::HookInstallError("IBus is not running".to_string()));
+    }
+    Ok(address)
+}
+
+/// Serve the engine on the IBus bus until the application exits
+pub fn run(state: SharedState) -> Result<()> {
+    let address = ibus_address()?;
+    let factory = Factory {
+        state: state.clone(),
+        created: 0,
+    };
+    let _connection = connection::Builder::address(address.as_str())
+        .and_then(|builder| builder.name(BUS_NAME))
+        .and_then(|builder| builder.serve_at(FACTORY_PATH, factory))
+        .and_then(|builder| builder.build())
+        .map_err(|e| GhostKeysError::HookInstallError(format!("Failed to connect to IBus: {}", e)))?;
+    println!("GhostKeys IBus engine ready");
+
+    while !state.should_exit() {
+        thread::sleep(Duration::from_millis(500));
+    }
+    Ok(())
+}
+
+/// IBus component file for an engine started as `exe --ibus`
+pub fn component_xml(exe: &Path) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <component>\n\
+         \x20 <name>{bus_name}</name>\n\
+         \x20 <description>GhostKeys ABNT2 keyboard layout emulation</description>\n\
+         \x20 <exec>{exe} --ibus</exec>\n\
+         \x20 <version>{version}</version>\n\
+         \x20 <license>MIT</license>\n\
+         \x20 <homepage>https://github.com/marcelocra/ghostkeys</homepage>\n\
+         \x20 <textdomain>ghostkeys</textdomain>\n\
+         \x20 <engines>\n\
+         \x20   <engine>\n\
+         \x20     <name>{engine}</name>\n\
+         \x20     <language>pt</language>\n\
+         \x20     <license>MIT</license>\n\
+         \x20     <longname>GhostKeys ABNT2</longname>\n\
+         \x20     <description>ABNT2 characters on a US keyboard</description>\n\
+         \x20     <layout>us</layout>\n\
+         \x20     <rank>0</rank>\n\
+         \x20   </engine>\n\
+         \x20 </engines>\n\
+         </component>\n",
+        bus_name = BUS_NAME,
+        exe = xml_escape(&exe.to_string_lossy()),
+        version = env!("CARGO_PKG_VERSION"),
+        engine = ENGINE_NAME,
+    )
+}
+
+/// Escape text for an XML element
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// evdev key codes
+    const KEY_SEMICOLON: u32 = 39;
+    const KEY_LEFTBRACE: u32 = 26;
+    const KEY_A: u32 = 30;
+    const KEY_ENTER: u32 = 28;
+    const KEY_F1: u32 = 59;
+
+    #[test]
+    fn test_engine_commits_remapped_characters() {
+        let mut engine = Engine::new(&SharedState::new()).unwrap();
+
+        let down = engine.process(KEY_SEMICOLON, 0);
+        assert_eq!(down, EngineOutput { handled: true, backspaces: 0, commit: "ç".to_string() });
+        assert!(engine.process(KEY_SEMICOLON, RELEASE_MASK).handled);
+
+        // Dead key, then the letter it accents
+        assert_eq!(engine.process(KEY_LEFTBRACE, 0).commit, "");
+        assert_eq!(engine.process(KEY_A, 0).commit, "á");
+    }
+
+    #[test]
+    fn test_engine_flushes_accent_before_enter() {
+        let mut engine = Engine::new(&SharedState::new()).unwrap();
+
+        engine.process(KEY_LEFTBRACE, 0);
+        let enter = engine.process(KEY_ENTER, 0);
+        assert_eq!(enter, EngineOutput { handled: false, backspaces: 0, commit: "´".to_string() });
+        assert!(!engine.process(KEY_ENTER, RELEASE_MASK).handled);
+    }
+
+    #[test]
+    fn test_engine_passes_shortcuts_and_unmapped_keys() {
+        let mut engine = Engine::new(&SharedState::new()).unwrap();
+
+        assert!(!engine.process(KEY_SEMICOLON, CONTROL_MASK).handled);
+        assert!(!engine.process(KEY_SEMICOLON, CONTROL_MASK | RELEASE_MASK).handled);
+        assert!(!engine.process(KEY_F1, 0).handled);
+        assert!(!engine.process(KEY_F1, RELEASE_MASK).handled);
+    }
+
+    #[test]
+    fn test_component_xml() {
+        let xml = component_xml(Path::new("/usr/bin/ghostkeys"));
+        assert!(xml.contains("<exec>/usr/bin/ghostkeys --ibus</exec>"));
+        assert!(xml.contains("<name>org.freedesktop.IBus.GhostKeys</name>"));
+        assert!(xml.contains("<name>ghostkeys</name>"));
+    }
+}
@@ -0,0 +1,214 @@
+//! Windows learning mode on-screen display
+//!
+//! With `enabled = true` under `[learning]`, each lesson ("' then a → ã")
+//! flashes in large text just above the text cursor for `duration_ms` (near
+//! the bottom of the screen in applications without a caret), sized for the
+//! DPI of that monitor. It never takes the focus and a new lesson replaces
+//! the one shown. Like the suggestion popup, it lives on the hook thread.
+
+#![cfg(target_os = "windows")]
+
+use std::cell::Cell;
+
+use windows::core::{w, PCWSTR};
+use windows::Win32::Foundation::{HINSTANCE, HWND, LPARAM, LRESULT, POINT, WPARAM};
+use windows::Win32::Graphics::Gdi::{DeleteObject, FW_SEMIBOLD, HFONT};
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, DefWindowProcW, GetForegroundWindow, KillTimer, RegisterClassW, SendMessageW,
+    SetTimer, SetWindowPos, SetWindowTextW, ShowWindow, HMENU, HWND_TOPMOST,
+    SWP_NOACTIVATE, SWP_NOZORDER, SW_HIDE, SW_SHOWNOACTIVATE, WINDOW_EX_STYLE, WINDOW_STYLE,
+    WM_SETFONT, WM_TIMER, WNDCLASSW, WS_BORDER, WS_CHILD, WS_EX_NOACTIVATE, WS_EX_TOOLWINDOW,
+    WS_EX_TOPMOST, WS_EX_TRANSPARENT, WS_POPUP, WS_VISIBLE,
+};
+
+use crate::platform::ui::{self, caret, Monitor, Side};
+
+/// Static control style that centers its text (winuser.h)
+const SS_CENTER: u32 = 0x1;
+
+/// Height of the display, in pixels at 96 DPI
+const HEIGHT: i32 = 44;
+/// Rough width of a character of its font, in pixels at 96 DPI
+const CHAR_WIDTH: i32 = 15;
+/// Height of its font, in pixels at 96 DPI
+const FONT_HEIGHT: i32 = 28;
+/// Space between the display and the text cursor, in pixels at 96 DPI
+const CARET_GAP: i32 = 24;
+/// Distance from the bottom of the screen without a text cursor, in pixels
+/// at 96 DPI
+const BOTTOM_MARGIN: i32 = 140;
+/// Timer that hides the display
+const HIDE_TIMER: usize = 1;
+
+/// The display and its text, created on first use and hidden in between
+#[derive(Clone, Copy)]
+struct Display {
+    hwnd: HWND,
+    text: HWND,
+    /// Font of the text, made for `dpi`
+    font: HFONT,
+    dpi: u32,
+}
+
+thread_local! {
+    static DISPLAY: Cell<Option<Display>> = const { Cell::new(None) };
+}
+
+/// Show `lesson` for `duration_ms`, replacing the one on screen
+pub fn show(lesson: &str, duration_ms: u64) {
+    let Some(display) = display() else {
+        return;
+    };
+    let wide: Vec<u16> = lesson.encode_utf16().chain(Some(0)).collect();
+    let caret = caret::rect();
+    let monitor = match caret {
+        Some(caret) => Monitor::at(POINT {
+            x: caret.left,
+            y: caret.top,
+        }),
+        None => Monitor::of_window(unsafe { GetForegroundWindow() }),
+    };
+    let display = with_font_for(display, monitor.dpi);
+    let width = monitor.scale(lesson.chars().count() as i32 * CHAR_WIDTH + 32);
+    let height = monitor.scale(HEIGHT);
+    let work_area = monitor.work_area;
+    // Above the text so it doesn't hide what's being typed, or the
+    // suggestion popup under it
+    let position = match caret {
+        Some(caret) => {
+            let gap = monitor.scale(CARET_GAP);
+            ui::place_near(caret, width, height, gap, Side::Above, work_area)
+        }
+        None => POINT {
+            x: work_area.left + (work_area.right - work_area.left - width) / 2,
+            y: work_area.bottom - monitor.scale(BOTTOM_MARGIN) - height,
+        },
+    };
+
+    unsafe {
+        let _ = SetWindowTextW(display.text, PCWSTR(wide.as_ptr()));
+        let _ = SetWindowPos(
+            display.text,
+            HWND::default(),
+            0,
+            monitor.scale(5),
+            width - monitor.scale(2),
+            height - monitor.scale(10),
+            SWP_NOACTIVATE | SWP_NOZORDER,
+        );
+        let _ = SetWindowPos(
+            display.hwnd,
+            HWND_TOPMOST,
+            position.x,
+            position.y,
+            width,
+            height,
+            SWP_NOACTIVATE,
+        );
+        let _ = ShowWindow(display.hwnd, SW_SHOWNOACTIVATE);
+        // Restarts the timer of a lesson still on screen
+        SetTimer(
+            display.hwnd,
+            HIDE_TIMER,
+            duration_ms.min(u32::MAX as u64) as u32,
+            None,
+        );
+    }
+}
+
+/// The display window, created the first time it's needed
+fn display() -> Option<Display> {
+    if let Some(display) = DISPLAY.with(Cell::get) {
+        return Some(display);
+    }
+    unsafe {
+        let instance = GetModuleHandleW(None).map(HINSTANCE::from).ok()?;
+        let class_name = w!("GhostKeysLearning");
+        let class = WNDCLASSW {
+            lpfnWndProc: Some(window_proc),
+            hInstance: instance,
+            lpszClassName: class_name,
+            ..Default::default()
+        };
+        // Fails harmlessly if the class is still registered from an earlier hook
+        RegisterClassW(&class);
+
+        // Clicks go through to the window underneath
+        let hwnd = CreateWindowExW(
+            WS_EX_TOPMOST | WS_EX_TOOLWINDOW | WS_EX_NOACTIVATE | WS_EX_TRANSPARENT,
+            class_name,
+            PCWSTR::null(),
+            WS_POPUP | WS_BORDER,
+            0,
+            0,
+            0,
+            HEIGHT,
+            HWND::default(),
+            HMENU::default(),
+            instance,
+            None,
+        )
+        .ok()?;
+        let text = CreateWindowExW(
+            WINDOW_EX_STYLE::default(),
+            w!("STATIC"),
+            PCWSTR::null(),
+            WS_CHILD | WS_VISIBLE | WINDOW_STYLE(SS_CENTER),
+            0,
+            5,
+            0,
+            HEIGHT - 10,
+            hwnd,
+            HMENU::default(),
+            instance,
+            None,
+        )
+        .ok()?;
+
+        let display = Display {
+            hwnd,
+            text,
+            font: HFONT::default(),
+            dpi: 0,
+        };
+        DISPLAY.with(|slot| slot.set(Some(display)));
+        Some(display)
+    }
+}
+
+/// `display` with a font made for `dpi`, replacing one made for another DPI
+fn with_font_for(display: Display, dpi: u32) -> Display {
+    if display.dpi == dpi {
+        return display;
+    }
+    let font = ui::font(FONT_HEIGHT, FW_SEMIBOLD.0 as i32, dpi);
+    unsafe {
+        SendMessageW(display.text, WM_SETFONT, WPARAM(font.0 as usize), LPARAM(0));
+        if !display.font.is_invalid() {
+            let _ = DeleteObject(display.font);
+        }
+    }
+    let display = Display {
+        font,
+        dpi,
+        ..display
+    };
+    DISPLAY.with(|slot| slot.set(Some(display)));
+    display
+}
+
+/// Window procedure of the display, which hides it when its time is up
+unsafe extern "system" fn window_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    if msg == WM_TIMER && wparam.0 == HIDE_TIMER {
+        let _ = KillTimer(hwnd, HIDE_TIMER);
+        let _ = ShowWindow(hwnd, SW_HIDE);
+        return LRESULT(0);
+    }
+    DefWindowProcW(hwnd, msg, wparam, lparam)
+}
@@ -1,20 +1,41 @@
 //! Linux keyboard interceptor implementation
 //!
-//! Uses rdev for keyboard hooks on X11/Wayland.
+//! Uses rdev for keyboard hooks on X11/Wayland, or the evdev backend
+//! (`backend = "evdev"` under `[linux]`).
 //! This implementation is for development and testing only, NOT for production.
 
+use std::process::Command;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
-use crate::error::{GhostKeysError, Result};
-use crate::interceptor::KeyboardInterceptor;
-use crate::state::SharedState;
+use crate::config::LinuxBackend;
+use crate::conflicts;
+use crate::doctor::Check;
+use crate::error::{GhostKeysError, Platform, Result};
+use crate::handoff::Handoff;
+use crate::interceptor::{build_mapper, KeyboardInterceptor};
+use crate::latency::Sample;
+use crate::layout::Layout;
+use crate::mapper::Mapper;
+use crate::os_layout::{self, Finding};
+use crate::pipeline::Pipeline;
+use crate::platform::evdev;
+use crate::platform::preflight::Preflight;
+use crate::state::{OperationMode, SharedState};
 
 /// Linux keyboard interceptor using rdev
 ///
 /// NOTE: This is for development/testing only. Production builds target Windows.
 pub struct LinuxInterceptor {
+    /// Checked by the evdev threads on every event; a standalone flag (the
+    /// pipeline is behind its own lock), so relaxed operations are enough
     running: Arc<AtomicBool>,
+    state: Option<SharedState>,
+    /// Pipeline shared with the evdev threads, whose mapper `set_mapper`
+    /// replaces
+    pipeline: Arc<Handoff<Pipeline>>,
+    /// Check device permissions before starting
+    preflight: bool,
 }
 
 impl LinuxInterceptor {
@@ -22,8 +43,19 @@ impl LinuxInterceptor {
     pub fn new() -> Self {
         Self {
             running: Arc::new(AtomicBool::new(false)),
+            state: None,
+            pipeline: Arc::new(Handoff::new(Pipeline::builder(Mapper::new()).build())),
+            preflight: true,
         }
     }
+
+    /// Create an interceptor that starts without checking device permissions
+    /// (for tests, which run without access to input devices)
+    pub fn without_preflight() -> Self {
+        let mut interceptor = Self::new();
+        interceptor.preflight = false;
+        interceptor
+    }
 }
 
 impl Default for LinuxInterceptor {
@@ -33,34 +65,91 @@ impl Default for LinuxInterceptor {
 }
 
 impl KeyboardInterceptor for LinuxInterceptor {
-    fn start(&mut self, _state: SharedState) -> Result<()> {
-        if self.running.load(Ordering::SeqCst) {
+    fn start(&mut self, state: SharedState) -> Result<()> {
+        if self.running.load(Ordering::Relaxed) {
             return Err(GhostKeysError::HookInstallError(
                 "Interceptor already running".to_string(),
             ));
         }
 
+        if self.preflight {
+            Preflight::detect().check()?;
+        }
+
+        // The evdev threads run while the flag is set
+        let config = state.get_config()?;
+        let backend = config.linux.backend;
+        let pipeline = Pipeline::builder(build_mapper(&state)?)
+            .nav(&config.nav)
+            .numpad(&config.numpad)
+            .build();
+        self.pipeline.replace(pipeline)?;
+        self.running.store(true, Ordering::Relaxed);
+        if backend == LinuxBackend::Evdev {
+            let started =
+                evdev::start(&state, Arc::clone(&self.pipeline), Arc::clone(&self.running));
+            if let Err(e) = started {
+                self.running.store(false, Ordering::Relaxed);
+                return Err(e);
+            }
+        }
+
         // TODO: Implement Linux keyboard hook using rdev
         // - rdev::listen for key events
         // - rdev::simulate for key injection
 
-        self.running.store(true, Ordering::SeqCst);
+        self.state = Some(state);
         Ok(())
     }
 
     fn stop(&mut self) -> Result<()> {
-        if !self.running.load(Ordering::SeqCst) {
+        if !self.running.load(Ordering::Relaxed) {
             return Ok(());
         }
 
         // TODO: Implement hook release
+        // evdev threads release their grab after the next key event
 
-        self.running.store(false, Ordering::SeqCst);
+        self.state = None;
+        self.running.store(false, Ordering::Relaxed);
         Ok(())
     }
 
     fn is_running(&self) -> bool {
-        self.running.load(Ordering::SeqCst)
+        self.running.load(Ordering::Relaxed)
+    }
+
+    fn set_mode(&mut self, mode: OperationMode) -> Result<()> {
+        let state = self.state.as_ref().ok_or_else(|| {
+            GhostKeysError::HookInstallError("Interceptor not running".to_string())
+        })?;
+        state.set_mode(mode)
+    }
+
+    fn set_mapper(&mut self, mapper: Mapper) -> Result<()> {
+        if !self.is_running() {
+            return Err(GhostKeysError::HookInstallError("Interceptor not running".to_string()));
+        }
+        self.pipeline.with(|pipeline| {
+            pipeline.set_mapper(mapper);
+        })
+    }
+
+    fn update_layout(&mut self, layout: Option<Layout>) -> Result<()> {
+        let state = self.state.as_ref().ok_or_else(|| {
+            GhostKeysError::HookInstallError("Interceptor not running".to_string())
+        })?;
+        state.set_layout(layout)?;
+        let mapper = build_mapper(state)?;
+        self.set_mapper(mapper)
+    }
+
+    fn self_test(&self) -> Result<()> {
+        Err(GhostKeysError::NotSupported(Platform::Linux))
+    }
+
+    fn measure_latency(&self, _samples: usize) -> Result<Vec<Sample>> {
+        Err(GhostKeysError::NotSupported(Platform::Linux))
     }
 }
 
@@ -70,3 +159,120 @@ impl Drop for LinuxInterceptor {
         let _ = self.stop();
     }
 }
+
+/// `ghostkeys doctor` checks: device permissions, session, other remappers
+/// and the X keyboard layout
+pub fn doctor_checks() -> Vec<Check> {
+    let mut checks = Preflight::detect().doctor_checks();
+
+    let processes = running_process_names();
+    let remappers = conflicts::detect(processes.iter().map(String::as_str));
+    checks.push(if remappers.is_empty() {
+        Check::pass("Conflicting software", "no other keyboard remapper is running")
+    } else {
+        Check::warn(
+            "Conflicting software",
+            format!("{} running", remappers.join(", ")),
+            "Exit them, or pause GhostKeys while using them",
+        )
+    });
+
+    if let Some(query) = xkb_query() {
+        let findings = xkb_findings(&query);
+        checks.push(if findings.is_empty() {
+            Check::pass("OS keyboard layout", "no ABNT2 or US-International layout configured")
+        } else {
+            let details: Vec<String> = findings.iter().map(Finding::to_string).collect();
+            Check::warn(
+                "OS keyboard layout",
+                details.join("; "),
+                "Switch to the US layout (`setxkbmap us`, or in your desktop's keyboard settings)",
+            )
+        });
+    }
+    checks
+}
+
+/// Configured layouts that conflict with emulation, the one in use first
+pub fn layout_findings() -> Vec<Finding> {
+    xkb_query().map(|query| xkb_findings(&query)).unwrap_or_default()
+}
+
+/// Output of `setxkbmap -query`, which only answers under X11 (or XWayland,
+/// where it may not match the compositor)
+fn xkb_query() -> Option<String> {
+    let output = Command::new("setxkbmap").arg("-query").output().ok()?;
+    Some(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Conflicting layouts of a `setxkbmap -query`; the first group is the one
+/// in use until the user switches
+fn xkb_findings(query: &str) -> Vec<Finding> {
+    let variants = xkb_list(query, "variant:");
+    xkb_layouts(query)
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, layout)| {
+            let variant = variants.get(i).copied().unwrap_or_default();
+            let conflict = os_layout::classify_xkb(layout, variant)?;
+            let name = if variant.is_empty() {
+                layout.to_string()
+            } else {
+                format!("{}({})", layout, variant)
+            };
+            Some(Finding { name, conflict, active: i == 0 })
+        })
+        .collect()
+}
+
+/// Command names of all running processes
+fn running_process_names() -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| std::fs::read_to_string(entry.path().join("comm")).ok())
+        .map(|comm| comm.trim_end().to_string())
+        .collect()
+}
+
+/// Layouts listed by `setxkbmap -query` (`layout:     us,br`)
+fn xkb_layouts(query: &str) -> Vec<&str> {
+    xkb_list(query, "layout:")
+}
+
+/// Comma-separated values of a `setxkbmap -query` field, one per group
+fn xkb_list<'a>(query: &'a str, field: &str) -> Vec<&'a str> {
+    query
+        .lines()
+        .find_map(|line| line.strip_prefix(field))
+        .map(|values| values.trim().split(',').collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xkb_layouts() {
+        let query = "model:      pc105\nlayout:     us,br\nvariant:    ,abnt2\n";
+        assert_eq!(xkb_layouts(query), ["us", "br"]);
+        assert!(xkb_layouts("rules:      evdev\n").is_empty());
+    }
+
+    #[test]
+    fn test_xkb_findings() {
+        let query = "layout:     us,br\nvariant:    ,abnt2\n";
+        let findings = xkb_findings(query);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].name, "br(abnt2)");
+        assert!(!findings[0].active);
+
+        let findings = xkb_findings("layout:     us\nvariant:    intl\n");
+        assert_eq!(findings[0].conflict, os_layout::Conflict::DeadKeys);
+        assert!(findings[0].active);
+        assert!(xkb_findings("layout:     us\n").is_empty());
+    }
+}
@@ -3,9 +3,268 @@
 //! This module contains platform-specific keyboard interceptor implementations.
 //! - `windows.rs` - Windows implementation using windows-rs (primary target)
 //! - `linux.rs` - Linux implementation using rdev (development/testing)
+//! - `preflight.rs` - Linux input device permission checks
+//! - `evdev.rs` - Linux evdev/uinput backend with exclusive keyboard grabs
+//! - `ibus.rs` - Linux IBus input-method engine (`ibus` feature)
+//! - `raw_input.rs` - Windows Raw Input keyboard tracking
+//! - `palette_window.rs` - Windows character palette window
+//! - `suggestion_popup.rs` - Windows accent suggestion popup
+//! - `learning_osd.rs` - Windows learning mode on-screen display
+//! - `ui/` - Windows DPI scaling and text cursor tracking for the overlays
+//! - `system_log.rs` - Windows Event Log / journald sink
+//! - `notifications.rs` - Windows toast / freedesktop notifications
+//! - `service.rs` - systemd user service management
 
 #[cfg(target_os = "windows")]
 pub mod windows;
 
+#[cfg(target_os = "windows")]
+pub mod raw_input;
+
+#[cfg(target_os = "windows")]
+pub mod palette_window;
+
+#[cfg(target_os = "windows")]
+pub mod suggestion_popup;
+
+#[cfg(target_os = "windows")]
+pub mod learning_osd;
+
+#[cfg(target_os = "windows")]
+pub mod ui;
+
 #[cfg(target_os = "linux")]
 pub mod linux;
+
+#[cfg(target_os = "linux")]
+pub mod preflight;
+
+#[cfg(target_os = "linux")]
+pub mod evdev;
+
+#[cfg(all(target_os = "linux", feature = "ibus"))]
+pub mod ibus;
+
+pub mod service;
+
+pub mod system_log;
+
+pub mod notifications;
+
+use std::path::Path;
+use std::process::Command;
+
+use crate::config::{Config, WindowInfo};
+use crate::doctor::Check;
+use crate::icon::Theme;
+use crate::os_layout::Finding;
+use crate::watchdog::Condition;
+
+/// What the active keyboard backend can do
+///
+/// Lets the UI grey out settings that would have no effect.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Original keystrokes can be blocked (required for any remapping)
+    pub suppression: bool,
+    /// Arbitrary Unicode characters can be typed
+    pub unicode_injection: bool,
+    /// Keyboards can be told apart, for per-device rules
+    pub per_device_filtering: bool,
+    /// AltGr is distinguished from Alt
+    pub altgr_detection: bool,
+    /// Windows where typing is blocked (elevated or secure input) are detected
+    pub secure_input_detection: bool,
+}
+
+/// Capabilities of the backend `config` selects on this platform
+#[cfg(target_os = "windows")]
+pub fn capabilities(_config: &Config) -> Capabilities {
+    Capabilities {
+        suppression: true,
+        unicode_injection: true,
+        per_device_filtering: true,
+        altgr_detection: true,
+        secure_input_detection: true,
+    }
+}
+
+/// Capabilities of the backend `config` selects on this platform
+#[cfg(target_os = "linux")]
+pub fn capabilities(config: &Config) -> Capabilities {
+    match config.linux.backend {
+        // Not implemented yet
+        crate::config::LinuxBackend::Rdev => Capabilities::default(),
+        // Unicode through Ctrl+Shift+U, which GTK and IBus applications understand
+        crate::config::LinuxBackend::Evdev => Capabilities {
+            suppression: true,
+            unicode_injection: true,
+            per_device_filtering: true,
+            altgr_detection: true,
+            secure_input_detection: false,
+        },
+    }
+}
+
+/// Capabilities of the backend `config` selects on this platform
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+pub fn capabilities(_config: &Config) -> Capabilities {
+    Capabilities::default()
+}
+
+/// Platform checks for `ghostkeys doctor`
+#[cfg(target_os = "windows")]
+pub fn doctor_checks() -> Vec<Check> {
+    windows::doctor_checks()
+}
+
+/// Platform checks for `ghostkeys doctor`
+#[cfg(target_os = "linux")]
+pub fn doctor_checks() -> Vec<Check> {
+    linux::doctor_checks()
+}
+
+/// Platform checks for `ghostkeys doctor`: GhostKeys can't remap keys here
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+pub fn doctor_checks() -> Vec<Check> {
+    let error = crate::error::GhostKeysError::NotSupported(crate::error::Platform::current());
+    vec![Check::from_error("Platform", &error)]
+}
+
+/// Installed OS keyboard layouts that conflict with emulation
+#[cfg(target_os = "windows")]
+pub fn layout_findings() -> Vec<Finding> {
+    windows::layout_findings()
+}
+
+/// Configured OS keyboard layouts that conflict with emulation
+#[cfg(target_os = "linux")]
+pub fn layout_findings() -> Vec<Finding> {
+    linux::layout_findings()
+}
+
+/// OS keyboard layouts that conflict with emulation: not checked here
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+pub fn layout_findings() -> Vec<Finding> {
+    Vec::new()
+}
+
+/// The window in front, for per-app and per-window rules
+#[cfg(target_os = "windows")]
+pub fn foreground_window() -> Option<WindowInfo> {
+    Some(windows::foreground_window(true))
+}
+
+/// The window in front: not looked up here, where no per-app rules apply
+#[cfg(not(target_os = "windows"))]
+pub fn foreground_window() -> Option<WindowInfo> {
+    None
+}
+
+/// Switch the OS to the plain US layout
+#[cfg(target_os = "windows")]
+pub fn switch_to_us_layout() -> crate::error::Result<()> {
+    windows::switch_to_us_layout()
+}
+
+/// Switch the OS to the plain US layout: only offered on Windows
+#[cfg(not(target_os = "windows"))]
+pub fn switch_to_us_layout() -> crate::error::Result<()> {
+    Err(crate::error::GhostKeysError::NotSupported(crate::error::Platform::current()))
+}
+
+/// Re-evaluate the `[auto_pause]` rules, for the resume safeguard
+#[cfg(target_os = "windows")]
+pub fn auto_pause_condition(config: &Config) -> Condition {
+    windows::auto_pause_condition(config)
+}
+
+/// Re-evaluate the `[auto_pause]` rules: only Windows has rules that pause
+#[cfg(not(target_os = "windows"))]
+pub fn auto_pause_condition(_config: &Config) -> Condition {
+    Condition::Unknown
+}
+
+/// Theme of the taskbar, for the tray icon's colors
+#[cfg(target_os = "windows")]
+pub fn system_theme() -> Theme {
+    windows::system_theme()
+}
+
+/// Theme of the panel, for the tray icon's colors
+///
+/// GNOME's top bar stays dark whatever the color scheme, so the panel is
+/// taken as dark unless the desktop asks for light themes.
+#[cfg(target_os = "linux")]
+pub fn system_theme() -> Theme {
+    let scheme = Command::new("gsettings")
+        .args(["get", "org.gnome.desktop.interface", "color-scheme"])
+        .output();
+    match scheme {
+        Ok(output) if String::from_utf8_lossy(&output.stdout).contains("prefer-light") => {
+            Theme::Light
+        }
+        _ => Theme::Dark,
+    }
+}
+
+/// Theme of the menu bar, for the tray icon's colors
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+pub fn system_theme() -> Theme {
+    Theme::Dark
+}
+
+/// Show `path` in Explorer: a folder is opened, a file is selected in its folder
+#[cfg(target_os = "windows")]
+pub fn show_in_file_manager(path: &Path) -> std::io::Result<()> {
+    use std::os::windows::process::CommandExt;
+
+    let mut explorer = Command::new("explorer");
+    if path.is_dir() {
+        explorer.arg(path);
+    } else {
+        // Explorer wants the quotes around the path only, not the whole argument
+        explorer.raw_arg(format!("/select,\"{}\"", path.display()));
+    }
+    // Explorer's exit code is 1 even when it succeeds, so only spawning is checked
+    explorer.spawn().map(|_| ())
+}
+
+/// Show `path` in the desktop's file manager: a folder is opened, and a file's
+/// folder is opened (xdg-open can't select a file)
+#[cfg(target_os = "linux")]
+pub fn show_in_file_manager(path: &Path) -> std::io::Result<()> {
+    let folder = if path.is_dir() { path } else { path.parent().unwrap_or(path) };
+    Command::new("xdg-open").arg(folder).spawn().map(|_| ())
+}
+
+/// Show `path` in the platform file manager
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+pub fn show_in_file_manager(path: &Path) -> std::io::Result<()> {
+    Command::new("open").arg("-R").arg(path).spawn().map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_os = "windows")]
+    fn test_windows_capabilities() {
+        let capabilities = capabilities(&Config::default());
+        assert!(capabilities.suppression && capabilities.unicode_injection);
+        assert!(capabilities.per_device_filtering && capabilities.secure_input_detection);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_linux_capabilities_follow_backend() {
+        let mut config = Config::default();
+        assert!(!capabilities(&config).suppression);
+
+        config.linux.backend = crate::config::LinuxBackend::Evdev;
+        let capabilities = capabilities(&config);
+        assert!(capabilities.suppression && capabilities.per_device_filtering);
+        assert!(!capabilities.secure_input_detection);
+    }
+}
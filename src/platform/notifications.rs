@@ -0,0 +1,77 @@
+//! Desktop notifications
+//!
+//! Shows a toast (Windows) or a freedesktop notification (Linux) when an
+//! automatic rule pauses or resumes GhostKeys, so a sudden change in what the
+//! keyboard types doesn't go unexplained. Enabled with `mode_changes = true`
+//! under `[notifications]` (the default). Like the system log, this is best
+//! effort: a notification that can't be shown is dropped.
+
+/// Name notifications are shown under
+const APP_NAME: &str = "GhostKeys";
+
+/// Show a WinRT toast notification
+#[cfg(target_os = "windows")]
+pub fn show(title: &str, message: &str) {
+    use windows::core::HSTRING;
+    use windows::Data::Xml::Dom::XmlDocument;
+    use windows::UI::Notifications::{ToastNotification, ToastNotificationManager};
+
+    // Toasts need an AppUserModelID with a Start menu shortcut. GhostKeys has
+    // no installer to create one, so borrow PowerShell's, which every Windows
+    // install has; the toast is then attributed to "Windows PowerShell".
+    const POWERSHELL_AUMID: &str =
+        "{1AC14E77-02E7-4E5D-B744-2EB1AE5198B7}\\WindowsPowerShell\\v1.0\\powershell.exe";
+
+    let xml = format!(
+        "<toast><visual><binding template=\"ToastGeneric\">\
+         <text>{}</text><text>{}</text>\
+         </binding></visual></toast>",
+        xml_escape(title),
+        xml_escape(message)
+    );
+
+    let show = || -> windows::core::Result<()> {
+        let document = XmlDocument::new()?;
+        document.LoadXml(&HSTRING::from(xml))?;
+        let toast = ToastNotification::CreateToastNotification(&document)?;
+        let notifier =
+            ToastNotificationManager::CreateToastNotifierWithId(&HSTRING::from(POWERSHELL_AUMID))?;
+        notifier.Show(&toast)
+    };
+    let _ = show();
+}
+
+/// Show a notification through the freedesktop notification service
+#[cfg(target_os = "linux")]
+pub fn show(title: &str, message: &str) {
+    let _ = notify_rust::Notification::new()
+        .summary(title)
+        .body(message)
+        .appname(APP_NAME)
+        .show();
+}
+
+/// Notifications aren't supported on this platform
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+pub fn show(_title: &str, _message: &str) {}
+
+/// Show a notification titled with the application name
+pub fn show_message(message: &str) {
+    show(APP_NAME, message);
+}
+
+/// Escape text for an XML element
+#[cfg(any(target_os = "windows", test))]
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xml_escape() {
+        assert_eq!(xml_escape("Paused: <Dell> & co"), "Paused: &lt;Dell&gt; &amp; co");
+    }
+}
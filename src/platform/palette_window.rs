@@ -0,0 +1,337 @@
+//! Windows character palette window
+//!
+//! A small always-on-top window with a search box over a list of
+//! `palette::ENTRIES`. It lives on the hook thread, whose message loop
+//! forwards its key presses here first: arrows move the selection, Enter
+//! closes the window and types the selected character into the window that
+//! was in front before, and Esc (or clicking elsewhere) closes it. It opens
+//! on the monitor of that window, sized for its DPI, and is laid out again
+//! when dragged to a monitor with another DPI.
+
+#![cfg(target_os = "windows")]
+
+use std::cell::{Cell, RefCell};
+
+use windows::core::{w, PCWSTR};
+use windows::Win32::Foundation::{HINSTANCE, HWND, LPARAM, LRESULT, RECT, WPARAM};
+use windows::Win32::Graphics::Gdi::{DeleteObject, COLOR_WINDOW, FW_NORMAL, HBRUSH, HFONT};
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::UI::Input::KeyboardAndMouse::{SetFocus, VK_DOWN, VK_ESCAPE, VK_RETURN, VK_UP};
+use windows::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, DefWindowProcW, DestroyWindow, GetForegroundWindow, GetWindowTextLengthW,
+    GetWindowTextW, RegisterClassW, SendMessageW, SetForegroundWindow, SetWindowPos,
+    ShowWindow, EN_CHANGE, ES_AUTOHSCROLL, HMENU, HWND_TOPMOST, LBN_DBLCLK,
+    LBS_NOTIFY, LB_ADDSTRING, LB_GETCURSEL, LB_GETCOUNT, LB_RESETCONTENT, LB_SETCURSEL, MSG,
+    SWP_NOACTIVATE, SWP_NOZORDER, SW_SHOW, WA_INACTIVE, WINDOW_EX_STYLE, WINDOW_STYLE, WM_ACTIVATE, WM_CLOSE,
+    WM_COMMAND, WM_DPICHANGED, WM_KEYDOWN, WM_SETFONT, WNDCLASSW, WS_BORDER, WS_CAPTION,
+    WS_CHILD, WS_EX_TOOLWINDOW, WS_EX_TOPMOST, WS_POPUP, WS_SYSMENU, WS_VISIBLE, WS_VSCROLL,
+};
+
+use crate::error::{GhostKeysError, Result};
+use crate::i18n::{tr, Text};
+use crate::palette::{self, Entry};
+use crate::state::SharedState;
+
+use crate::platform::ui::{self, Monitor};
+
+/// Window size, in pixels at 96 DPI
+const WIDTH: i32 = 360;
+const HEIGHT: i32 = 320;
+/// Height of the search box, in pixels at 96 DPI
+const SEARCH_HEIGHT: i32 = 24;
+/// Height of the font, in pixels at 96 DPI
+const FONT_HEIGHT: i32 = 15;
+
+/// Control IDs
+const SEARCH_ID: usize = 1;
+const LIST_ID: usize = 2;
+
+/// The open palette
+#[derive(Clone, Copy)]
+struct Window {
+    hwnd: HWND,
+    search: HWND,
+    list: HWND,
+    /// Where the picked character is typed
+    target: HWND,
+    /// Font of the controls, made for the DPI they're laid out for
+    font: HFONT,
+}
+
+thread_local! {
+    static WINDOW: Cell<Option<Window>> = const { Cell::new(None) };
+    static STATE: RefCell<Option<SharedState>> = const { RefCell::new(None) };
+    /// Types the picked character through the hook's injector
+    static TYPE_TEXT: Cell<Option<fn(&str)>> = const { Cell::new(None) };
+    /// Entries shown, in list order
+    static RESULTS: RefCell<Vec<&'static Entry>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Open the palette over the foreground window, or bring it back to front
+///
+/// Must run on the hook thread; `type_text` types the picked character once
+/// the window that was in front has the focus again.
+pub fn open(state: &SharedState, type_text: fn(&str)) -> Result<()> {
+    if let Some(window) = WINDOW.with(Cell::get) {
+        unsafe {
+            let _ = SetForegroundWindow(window.hwnd);
+            let _ = SetFocus(window.search);
+        }
+        return Ok(());
+    }
+
+    unsafe {
+        let target = GetForegroundWindow();
+        let instance = GetModuleHandleW(None)
+            .map(HINSTANCE::from)
+            .map_err(|e| GhostKeysError::PaletteError(format!("GetModuleHandleW failed: {}", e)))?;
+
+        let class_name = w!("GhostKeysPalette");
+        let class = WNDCLASSW {
+            lpfnWndProc: Some(window_proc),
+            hInstance: instance,
+            lpszClassName: class_name,
+            hbrBackground: HBRUSH((COLOR_WINDOW.0 + 1) as isize as *mut std::ffi::c_void),
+            ..Default::default()
+        };
+        // Fails harmlessly if the class is still registered from an earlier opening
+        RegisterClassW(&class);
+
+        let title: Vec<u16> = tr(Text::PaletteTitle).encode_utf16().chain(Some(0)).collect();
+        let monitor = Monitor::of_window(target);
+        let (width, height) = (monitor.scale(WIDTH), monitor.scale(HEIGHT));
+        let work_area = monitor.work_area;
+        let x = work_area.left + (work_area.right - work_area.left - width) / 2;
+        let y = work_area.top + (work_area.bottom - work_area.top - height) / 3;
+        let hwnd = CreateWindowExW(
+            WS_EX_TOPMOST | WS_EX_TOOLWINDOW,
+            class_name,
+            PCWSTR(title.as_ptr()),
+            WS_POPUP | WS_CAPTION | WS_SYSMENU,
+            x,
+            y,
+            width,
+            height,
+            HWND::default(),
+            HMENU::default(),
+            instance,
+            None,
+        )
+        .map_err(|e| GhostKeysError::PaletteError(format!("CreateWindowExW failed: {}", e)))?;
+
+        // Placed by `lay_out`
+        let child = |class: PCWSTR, style: WINDOW_STYLE, id: usize| {
+            CreateWindowExW(
+                WINDOW_EX_STYLE::default(),
+                class,
+                PCWSTR::null(),
+                WS_CHILD | WS_VISIBLE | WS_BORDER | style,
+                0,
+                0,
+                0,
+                0,
+                hwnd,
+                HMENU(id as *mut std::ffi::c_void),
+                instance,
+                None,
+            )
+        };
+        let search_style = WINDOW_STYLE(ES_AUTOHSCROLL as u32);
+        let list_style = WS_VSCROLL | WINDOW_STYLE(LBS_NOTIFY as u32);
+        let controls = child(w!("EDIT"), search_style, SEARCH_ID).and_then(|search| {
+            let list = child(w!("LISTBOX"), list_style, LIST_ID)?;
+            Ok((search, list))
+        });
+        let (search, list) = match controls {
+            Ok(controls) => controls,
+            Err(e) => {
+                let _ = DestroyWindow(hwnd);
+                return Err(GhostKeysError::PaletteError(format!("CreateWindowExW failed: {}", e)));
+            }
+        };
+        let font = HFONT::default();
+        let window = lay_out(Window { hwnd, search, list, target, font }, monitor.dpi);
+
+        STATE.with(|s| *s.borrow_mut() = Some(state.clone()));
+        TYPE_TEXT.with(|f| f.set(Some(type_text)));
+        WINDOW.with(|slot| slot.set(Some(window)));
+        refresh();
+
+        let _ = ShowWindow(hwnd, SW_SHOW);
+        let _ = SetForegroundWindow(hwnd);
+        let _ = SetFocus(search);
+    }
+    Ok(())
+}
+
+/// Size and place the controls for `dpi`, with a font made for it; returns
+/// the window with its new font
+fn lay_out(window: Window, dpi: u32) -> Window {
+    let scale = |pixels| ui::scale(pixels, dpi);
+    let font = ui::font(FONT_HEIGHT, FW_NORMAL.0 as i32, dpi);
+    let (width, search_height) = (scale(WIDTH - 16), scale(SEARCH_HEIGHT));
+    let list_height = scale(HEIGHT - SEARCH_HEIGHT - 48);
+    let place = |control: HWND, y: i32, height: i32| unsafe {
+        let flags = SWP_NOACTIVATE | SWP_NOZORDER;
+        let _ = SetWindowPos(control, HWND::default(), scale(4), y, width, height, flags);
+    };
+    place(window.search, scale(4), search_height);
+    place(window.list, search_height + scale(8), list_height);
+    unsafe {
+        SendMessageW(window.search, WM_SETFONT, WPARAM(font.0 as usize), LPARAM(1));
+        SendMessageW(window.list, WM_SETFONT, WPARAM(font.0 as usize), LPARAM(1));
+        if !window.font.is_invalid() {
+            let _ = DeleteObject(window.font);
+        }
+    }
+    Window { font, ..window }
+}
+
+/// Handle a key press meant for the palette before it's dispatched
+///
+/// Returns true if it was handled and must not be dispatched.
+pub fn handle_message(msg: &MSG) -> bool {
+    let Some(window) = WINDOW.with(Cell::get) else {
+        return false;
+    };
+    if msg.message != WM_KEYDOWN || (msg.hwnd != window.search && msg.hwnd != window.list) {
+        return false;
+    }
+    match msg.wParam.0 as u16 {
+        vk if vk == VK_RETURN.0 => pick(),
+        vk if vk == VK_ESCAPE.0 => {
+            close();
+        }
+        // Arrows move through the list while typing in the search box
+        vk if vk == VK_UP.0 && msg.hwnd == window.search => move_selection(window.list, -1),
+        vk if vk == VK_DOWN.0 && msg.hwnd == window.search => move_selection(window.list, 1),
+        _ => return false,
+    }
+    true
+}
+
+/// Close the palette without typing anything; returns the window that was in front
+fn close() -> Option<HWND> {
+    // Taken first: destroying the window deactivates it, which closes it again
+    let window = WINDOW.with(Cell::take)?;
+    unsafe {
+        let _ = DestroyWindow(window.hwnd);
+        let _ = DeleteObject(window.font);
+    }
+    RESULTS.with(|results| results.borrow_mut().clear());
+    Some(window.target)
+}
+
+/// Close the palette and type the selected character where it was opened
+fn pick() {
+    let Some(window) = WINDOW.with(Cell::get) else {
+        return;
+    };
+    let selected = unsafe { SendMessageW(window.list, LB_GETCURSEL, WPARAM(0), LPARAM(0)) }.0;
+    let entry = RESULTS.with(|results| {
+        usize::try_from(selected)
+            .ok()
+            .and_then(|i| results.borrow().get(i).copied())
+    });
+    let Some(entry) = entry else {
+        return;
+    };
+
+    if let Some(target) = close() {
+        unsafe {
+            let _ = SetForegroundWindow(target);
+        }
+    }
+    if let Some(type_text) = TYPE_TEXT.with(Cell::get) {
+        type_text(entry.text);
+    }
+    STATE.with(|s| {
+        if let Some(state) = s.borrow().as_ref() {
+            let _ = state.record_palette_pick(entry.text);
+        }
+    });
+}
+
+/// List the entries matching the search box, most picked first
+fn refresh() {
+    let Some(window) = WINDOW.with(Cell::get) else {
+        return;
+    };
+    let query = unsafe {
+        let mut buffer = vec![0u16; GetWindowTextLengthW(window.search) as usize + 1];
+        let len = GetWindowTextW(window.search, &mut buffer);
+        String::from_utf16_lossy(&buffer[..len as usize])
+    };
+    let picks = STATE
+        .with(|s| s.borrow().as_ref().and_then(|state| state.get_stats().ok()))
+        .map(|stats| stats.palette)
+        .unwrap_or_default();
+    let found = palette::search(&query, &picks);
+
+    unsafe {
+        SendMessageW(window.list, LB_RESETCONTENT, WPARAM(0), LPARAM(0));
+        for entry in &found {
+            let label: Vec<u16> = entry.label().encode_utf16().chain(Some(0)).collect();
+            SendMessageW(window.list, LB_ADDSTRING, WPARAM(0), LPARAM(label.as_ptr() as isize));
+        }
+        SendMessageW(window.list, LB_SETCURSEL, WPARAM(0), LPARAM(0));
+    }
+    RESULTS.with(|results| *results.borrow_mut() = found);
+}
+
+/// Move the list selection up or down, staying within the list
+fn move_selection(list: HWND, step: isize) {
+    unsafe {
+        let count = SendMessageW(list, LB_GETCOUNT, WPARAM(0), LPARAM(0)).0;
+        let current = SendMessageW(list, LB_GETCURSEL, WPARAM(0), LPARAM(0)).0;
+        if count > 0 {
+            let next = (current + step).clamp(0, count - 1);
+            SendMessageW(list, LB_SETCURSEL, WPARAM(next as usize), LPARAM(0));
+        }
+    }
+}
+
+/// Window procedure of the palette
+unsafe extern "system" fn window_proc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    match msg {
+        WM_COMMAND => {
+            let id = wparam.0 & 0xFFFF;
+            let notification = (wparam.0 >> 16) as u32;
+            if id == SEARCH_ID && notification == EN_CHANGE {
+                refresh();
+            } else if id == LIST_ID && notification == LBN_DBLCLK {
+                pick();
+            }
+            LRESULT(0)
+        }
+        // Clicking elsewhere dismisses the palette
+        WM_ACTIVATE if (wparam.0 & 0xFFFF) as u32 == WA_INACTIVE => {
+            close();
+            LRESULT(0)
+        }
+        WM_CLOSE => {
+            close();
+            LRESULT(0)
+        }
+        // Dragged to a monitor with another DPI: take the size Windows
+        // suggests for it and lay out the controls again
+        WM_DPICHANGED => {
+            let suggested = &*(lparam.0 as *const RECT);
+            let _ = SetWindowPos(
+                hwnd,
+                HWND_TOPMOST,
+                suggested.left,
+                suggested.top,
+                suggested.right - suggested.left,
+                suggested.bottom - suggested.top,
+                SWP_NOACTIVATE,
+            );
+            if let Some(window) = WINDOW.with(Cell::get) {
+                let dpi = (wparam.0 & 0xFFFF) as u32;
+                WINDOW.with(|slot| slot.set(Some(lay_out(window, dpi))));
+            }
+            LRESULT(0)
+        }
+        _ => DefWindowProcW(hwnd, msg, wparam, lparam),
+    }
+}
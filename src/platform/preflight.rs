@@ -0,0 +1,228 @@
+//! Linux permissions preflight
+//!
+//! Reading keyboards needs access to `/dev/input/event*` (usually through the
+//! `input` group) and typing characters needs write access to `/dev/uinput`.
+//! Without them the interceptor would fail with a bare "permission denied",
+//! so `LinuxInterceptor::start` checks first and names the exact fix.
+
+use std::fs::{self, OpenOptions};
+use std::io::ErrorKind;
+
+use crate::doctor::Check;
+use crate::error::{GhostKeysError, Result};
+
+/// Whether the user is in the `input` group
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupMembership {
+    /// This process runs with the group
+    Active,
+    /// The user was added, but this login session predates it
+    NeedsRelogin,
+    /// The user isn't in the group
+    Missing,
+}
+
+/// Whether a device node can be opened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceAccess {
+    /// Opened successfully
+    Granted,
+    /// The node doesn't exist (e.g., kernel module not loaded)
+    Missing,
+    /// The node exists but can't be opened
+    Denied,
+}
+
+/// What the preflight found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preflight {
+    /// At least one keyboard event device is readable
+    pub input_readable: bool,
+    /// Membership in the `input` group
+    pub input_group: GroupMembership,
+    /// Write access to `/dev/uinput`
+    pub uinput: DeviceAccess,
+    /// `XDG_SESSION_TYPE`, or what the display variables imply
+    pub session: Option<String>,
+}
+
+impl Preflight {
+    /// Inspect this process and system
+    pub fn detect() -> Self {
+        Self {
+            input_readable: input_readable(),
+            input_group: input_group(),
+            uinput: uinput_access(),
+            session: session_type(),
+        }
+    }
+
+    /// First problem that would stop GhostKeys from working
+    pub fn check(&self) -> Result<()> {
+        match self.requirements().into_iter().find_map(|(_, _, problem)| problem) {
+            Some(problem) => Err(problem),
+            None => Ok(()),
+        }
+    }
+
+    /// One `ghostkeys doctor` check per requirement
+    pub fn doctor_checks(&self) -> Vec<Check> {
+        self.requirements()
+            .into_iter()
+            .map(|(name, met, problem)| match problem {
+                Some(problem) => Check::from_error(name, &problem),
+                None => Check::pass(name, met),
+            })
+            .collect()
+    }
+
+    /// Each requirement with what it looks like when met, and the problem
+    /// if it isn't
+    fn requirements(&self) -> [(&'static str, &'static str, Option<GhostKeysError>); 3] {
+        // ACLs (e.g., logind's uaccess) can grant access without the group
+        let input = (!self.input_readable).then(|| GhostKeysError::InputDevicesUnreadable {
+            in_input_group: self.input_group != GroupMembership::Missing,
+        });
+        let uinput = (self.uinput != DeviceAccess::Granted).then(|| {
+            GhostKeysError::UinputUnavailable { missing: self.uinput == DeviceAccess::Missing }
+        });
+        let session = match self.session.as_deref() {
+            Some("x11") | Some("wayland") => None,
+            other => Some(GhostKeysError::UnsupportedSession(other.unwrap_or("none").to_string())),
+        };
+        [
+            ("Keyboard access", "keyboards under /dev/input can be read", input),
+            ("Typing access", "/dev/uinput can be written", uinput),
+            ("Desktop session", "X11 or Wayland", session),
+        ]
+    }
+}
+
+/// Whether any `/dev/input/event*` device can be opened for reading
+fn input_readable() -> bool {
+    let Ok(entries) = fs::read_dir("/dev/input") else {
+        return false;
+    };
+    entries
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("event"))
+        .any(|entry| fs::File::open(entry.path()).is_ok())
+}
+
+/// Membership in the `input` group, from /etc/group and this process' groups
+fn input_group() -> GroupMembership {
+    let group = fs::read_to_string("/etc/group").unwrap_or_default();
+    let status = fs::read_to_string("/proc/self/status").unwrap_or_default();
+    let user = std::env::var("USER").unwrap_or_default();
+    group_membership(&group, &status, &user)
+}
+
+/// Decide `input` group membership from the contents of /etc/group and
+/// /proc/self/status
+fn group_membership(etc_group: &str, proc_status: &str, user: &str) -> GroupMembership {
+    // input:x:<gid>:<user>,<user>
+    let Some(fields) = etc_group
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.len() >= 4 && fields[0] == "input")
+    else {
+        return GroupMembership::Missing;
+    };
+    let gid = fields[2];
+
+    let active = proc_status
+        .lines()
+        .find_map(|line| line.strip_prefix("Groups:"))
+        .is_some_and(|groups| groups.split_whitespace().any(|g| g == gid));
+    if active {
+        GroupMembership::Active
+    } else if !user.is_empty() && fields[3].split(',').any(|member| member == user) {
+        GroupMembership::NeedsRelogin
+    } else {
+        GroupMembership::Missing
+    }
+}
+
+/// Whether `/dev/uinput` can be opened for writing
+fn uinput_access() -> DeviceAccess {
+    match OpenOptions::new().write(true).open("/dev/uinput") {
+        Ok(_) => DeviceAccess::Granted,
+        Err(e) if e.kind() == ErrorKind::NotFound => DeviceAccess::Missing,
+        Err(_) => DeviceAccess::Denied,
+    }
+}
+
+/// Graphical session type: x11, wayland, or whatever logind reports
+fn session_type() -> Option<String> {
+    match std::env::var("XDG_SESSION_TYPE") {
+        Ok(session) if !session.is_empty() => Some(session.to_lowercase()),
+        _ if std::env::var_os("WAYLAND_DISPLAY").is_some() => Some("wayland".to_string()),
+        _ if std::env::var_os("DISPLAY").is_some() => Some("x11".to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::doctor::Status;
+
+    fn ready() -> Preflight {
+        Preflight {
+            input_readable: true,
+            input_group: GroupMembership::Active,
+            uinput: DeviceAccess::Granted,
+            session: Some("wayland".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_check_passes_when_ready() {
+        assert!(ready().check().is_ok());
+    }
+
+    #[test]
+    fn test_check_reports_each_problem() {
+        let unreadable = Preflight {
+            input_readable: false,
+            input_group: GroupMembership::NeedsRelogin,
+            ..ready()
+        };
+        assert!(matches!(
+            unreadable.check(),
+            Err(GhostKeysError::InputDevicesUnreadable { in_input_group: true })
+        ));
+
+        let no_uinput = Preflight { uinput: DeviceAccess::Missing, ..ready() };
+        assert!(matches!(no_uinput.check(), Err(GhostKeysError::UinputUnavailable { missing: true })));
+
+        let tty = Preflight { session: Some("tty".to_string()), ..ready() };
+        assert!(matches!(tty.check(), Err(GhostKeysError::UnsupportedSession(s)) if s == "tty"));
+    }
+
+    #[test]
+    fn test_doctor_checks_each_requirement() {
+        let checks = ready().doctor_checks();
+        assert_eq!(checks.len(), 3);
+        assert!(checks.iter().all(|check| check.status == Status::Pass));
+
+        let broken = Preflight { uinput: DeviceAccess::Denied, session: None, ..ready() };
+        let checks = broken.doctor_checks();
+        let failed: Vec<_> = checks.iter().filter(|check| check.status == Status::Fail).collect();
+        assert_eq!(failed.len(), 2);
+        assert_eq!(failed[0].name, "Typing access");
+        assert!(failed[0].fix.as_deref().is_some_and(|fix| fix.contains("udev rule")));
+        assert_eq!(failed[1].name, "Desktop session");
+    }
+
+    #[test]
+    fn test_group_membership() {
+        let etc_group = "root:x:0:\ninput:x:104:ana,bruno\nvideo:x:44:ana\n";
+        let status = |groups: &str| format!("Name:\tghostkeys\nGroups:\t{}\n", groups);
+
+        assert_eq!(group_membership(etc_group, &status("27 104"), "ana"), GroupMembership::Active);
+        assert_eq!(group_membership(etc_group, &status("27 44"), "bruno"), GroupMembership::NeedsRelogin);
+        assert_eq!(group_membership(etc_group, &status("27 44"), "carla"), GroupMembership::Missing);
+        assert_eq!(group_membership("root:x:0:\n", &status("0"), "root"), GroupMembership::Missing);
+    }
+}
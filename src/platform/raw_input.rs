@@ -0,0 +1,278 @@
+//! Windows Raw Input keyboard tracking
+//!
+//! A message-only window registered for Raw Input receives keyboard
+//! arrival/removal notifications, which drive the `[auto_pause]` rules and
+//! the `passthrough` rules of `[devices]` (pause while an ABNT2 keyboard is
+//! plugged in).
+//!
+//! Keystrokes aren't attributed to keyboards: Raw Input is delivered after the
+//! low-level hook has already decided what to do with a key, and a key the
+//! hook swallows never reaches Raw Input at all, so only which keyboards are
+//! present is known.
+
+#![cfg(target_os = "windows")]
+
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+
+use windows::core::{w, PCWSTR};
+use windows::Win32::Foundation::{HANDLE, HINSTANCE, HWND, LPARAM, LRESULT, WPARAM};
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::UI::Input::{
+    GetRawInputDeviceInfoW, GetRawInputDeviceList, RegisterRawInputDevices, RAWINPUTDEVICE,
+    RAWINPUTDEVICELIST, RIDEV_DEVNOTIFY, RIDI_DEVICENAME, RIM_TYPEKEYBOARD,
+};
+use windows::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, DefWindowProcW, DestroyWindow, RegisterClassW, HMENU, HWND_MESSAGE, WINDOW_EX_STYLE,
+    WINDOW_STYLE, WM_INPUT_DEVICE_CHANGE, WNDCLASSW,
+};
+
+use crate::error::{GhostKeysError, Result};
+use crate::history::Cause;
+use crate::i18n::{tr, tr_args, Text};
+use crate::notify::Severity;
+use crate::state::{OperationMode, SharedState};
+
+/// HID usage page and usage for keyboards
+const USAGE_PAGE_GENERIC: u16 = 0x01;
+const USAGE_KEYBOARD: u16 = 0x06;
+
+/// WM_INPUT_DEVICE_CHANGE wParam values
+const GIDC_ARRIVAL: usize = 1;
+const GIDC_REMOVAL: usize = 2;
+
+thread_local! {
+    static WINDOW: Cell<Option<HWND>> = const { Cell::new(None) };
+    static STATE: RefCell<Option<SharedState>> = const { RefCell::new(None) };
+    /// Device handle -> device instance path, for every keyboard seen so far
+    static DEVICE_NAMES: RefCell<HashMap<isize, String>> = RefCell::new(HashMap::new());
+    /// Keyboards currently plugged in
+    static ATTACHED: RefCell<HashSet<isize>> = RefCell::new(HashSet::new());
+    /// Whether an [auto_pause] rule paused GhostKeys
+    static AUTO_PAUSED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Start tracking keyboards; must run on the hook thread, whose message loop
+/// delivers the Raw Input messages
+pub fn start(state: &SharedState) -> Result<()> {
+    unsafe {
+        let instance = GetModuleHandleW(None)
+            .map(HINSTANCE::from)
+            .map_err(|e| GhostKeysError::HookInstallError(format!("GetModuleHandleW failed: {}", e)))?;
+
+        let class_name = w!("GhostKeysRawInput");
+        let class = WNDCLASSW {
+            lpfnWndProc: Some(window_proc),
+            hInstance: instance,
+            lpszClassName: class_name,
+            ..Default::default()
+        };
+        // Fails harmlessly if the class is still registered from a previous start
+        RegisterClassW(&class);
+
+        let hwnd = CreateWindowExW(
+            WINDOW_EX_STYLE::default(),
+            class_name,
+            PCWSTR::null(),
+            WINDOW_STYLE::default(),
+            0,
+            0,
+            0,
+            0,
+            HWND_MESSAGE,
+            HMENU::default(),
+            instance,
+            None,
+        )
+        .map_err(|e| GhostKeysError::HookInstallError(format!("CreateWindowExW failed: {}", e)))?;
+
+        // DEVNOTIFY: get arrival/removal messages, starting with one arrival
+        // for each keyboard already plugged in. Without INPUTSINK the window,
+        // never in the foreground, gets no WM_INPUT for the keys themselves
+        let device = RAWINPUTDEVICE {
+            usUsagePage: USAGE_PAGE_GENERIC,
+            usUsage: USAGE_KEYBOARD,
+            dwFlags: RIDEV_DEVNOTIFY,
+            hwndTarget: hwnd,
+        };
+        if let Err(e) = RegisterRawInputDevices(&[device], std::mem::size_of::<RAWINPUTDEVICE>() as u32) {
+            let _ = DestroyWindow(hwnd);
+            return Err(GhostKeysError::HookInstallError(format!(
+                "RegisterRawInputDevices failed: {}",
+                e
+            )));
+        }
+
+        // Messages are only dispatched once the hook thread pumps its queue,
+        // after start() returns
+        STATE.with(|s| {
+            *s.borrow_mut() = Some(state.clone());
+        });
+        WINDOW.with(|window| window.set(Some(hwnd)));
+    }
+
+    Ok(())
+}
+
+/// Stop tracking keyboards
+pub fn stop() {
+    if let Some(hwnd) = WINDOW.with(|window| window.take()) {
+        unsafe {
+            let _ = DestroyWindow(hwnd);
+        }
+    }
+    STATE.with(|s| {
+        *s.borrow_mut() = None;
+    });
+    DEVICE_NAMES.with(|names| names.borrow_mut().clear());
+    ATTACHED.with(|attached| attached.borrow_mut().clear());
+    AUTO_PAUSED.with(|paused| paused.set(false));
+}
+
+/// Window procedure of the message-only window
+unsafe extern "system" fn window_proc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    if msg == WM_INPUT_DEVICE_CHANGE {
+        device_changed(HANDLE(lparam.0 as *mut std::ffi::c_void), wparam.0);
+    }
+    DefWindowProcW(hwnd, msg, wparam, lparam)
+}
+
+/// Device instance path of a keyboard (e.g., `\\?\HID#VID_046D&PID_C31C...`)
+fn device_name(device: HANDLE) -> Option<String> {
+    unsafe {
+        let mut len = 0u32;
+        GetRawInputDeviceInfoW(device, RIDI_DEVICENAME, None, &mut len);
+        if len == 0 {
+            return None;
+        }
+
+        let mut buffer = vec![0u16; len as usize];
+        let copied = GetRawInputDeviceInfoW(
+            device,
+            RIDI_DEVICENAME,
+            Some(buffer.as_mut_ptr() as *mut std::ffi::c_void),
+            &mut len,
+        );
+        if copied == u32::MAX {
+            return None;
+        }
+
+        let end = buffer.iter().position(|&unit| unit == 0).unwrap_or(buffer.len());
+        Some(String::from_utf16_lossy(&buffer[..end]))
+    }
+}
+
+/// Device paths of the keyboards plugged in right now, asked from Windows
+/// rather than tracked, so it works from any thread
+pub fn attached_keyboards() -> Option<Vec<String>> {
+    let size = std::mem::size_of::<RAWINPUTDEVICELIST>() as u32;
+    let mut count = 0u32;
+    unsafe {
+        if GetRawInputDeviceList(None, &mut count, size) == u32::MAX {
+            return None;
+        }
+        let mut devices = vec![RAWINPUTDEVICELIST::default(); count as usize];
+        let copied = GetRawInputDeviceList(Some(devices.as_mut_ptr()), &mut count, size);
+        if copied == u32::MAX {
+            return None;
+        }
+        devices.truncate(copied as usize);
+
+        Some(
+            devices
+                .iter()
+                .filter(|device| device.dwType == RIM_TYPEKEYBOARD)
+                .filter_map(|device| device_name(device.hDevice))
+                .collect(),
+        )
+    }
+}
+
+/// Device path of a keyboard, cached so it's still known after removal
+fn cached_device_name(device: HANDLE) -> String {
+    DEVICE_NAMES.with(|names| {
+        names
+            .borrow_mut()
+            .entry(device.0 as isize)
+            .or_insert_with(|| device_name(device).unwrap_or_default())
+            .clone()
+    })
+}
+
+/// Track a keyboard being plugged in or unplugged
+fn device_changed(device: HANDLE, change: usize) {
+    let handle = device.0 as isize;
+    let name = cached_device_name(device);
+
+    match change {
+        GIDC_ARRIVAL => {
+            ATTACHED.with(|attached| attached.borrow_mut().insert(handle));
+            // Name keyboards so users can copy them into [devices] rules
+            notify(Severity::Info, format!("Keyboard attached: {}", name));
+        }
+        GIDC_REMOVAL => {
+            ATTACHED.with(|attached| attached.borrow_mut().remove(&handle));
+            DEVICE_NAMES.with(|names| names.borrow_mut().remove(&handle));
+            notify(Severity::Info, format!("Keyboard removed: {}", name));
+        }
+        _ => return,
+    }
+
+    update_auto_pause();
+}
+
+/// Pause or resume when the [auto_pause] or [devices] rules start or stop
+/// matching
+///
+/// Only transitions change the mode, so pausing by hand from the tray isn't
+/// undone by unrelated device changes.
+fn update_auto_pause() {
+    STATE.with(|state| {
+        let state = state.borrow();
+        let Some(state) = state.as_ref() else {
+            return;
+        };
+        let Ok(config) = state.get_config() else {
+            return;
+        };
+
+        let matching = ATTACHED.with(|attached| {
+            DEVICE_NAMES.with(|names| {
+                let names = names.borrow();
+                attached
+                    .borrow()
+                    .iter()
+                    .filter_map(|handle| names.get(handle))
+                    .find(|name| config.pauses_for_keyboard(name))
+                    .cloned()
+            })
+        });
+
+        // The resume safeguard found the rule had stopped applying
+        if state.last_mode_cause().is_ok_and(|cause| cause == Some(Cause::Watchdog)) {
+            AUTO_PAUSED.with(|paused| paused.set(false));
+        }
+        let pause = matching.is_some();
+        if AUTO_PAUSED.with(|paused| paused.replace(pause)) == pause {
+            return;
+        }
+
+        if let Some(name) = matching {
+            let _ = state.set_mode_by_rule(
+                OperationMode::Passthrough,
+                tr_args(Text::PausedByKeyboard, &[&name]),
+            );
+        } else {
+            let _ = state.set_mode_by_rule(OperationMode::Active, tr(Text::ResumedByKeyboard));
+        }
+    });
+}
+
+/// Queue a notification for the tray
+fn notify(severity: Severity, message: String) {
+    STATE.with(|state| {
+        if let Some(state) = state.borrow().as_ref() {
+            let _ = state.notify(severity, message);
+        }
+    });
+}
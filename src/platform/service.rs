@@ -0,0 +1,356 @@
+//! Background service integration
+//!
+//! `ghostkeys service install|uninstall|status` manages a systemd user unit on
+//! Linux, so GhostKeys starts with the graphical session and is restarted if
+//! it fails instead of living in a terminal. A user unit can't grant groups or
+//! capabilities, so the unit documents what the user account needs: the
+//! `input` group to read `/dev/input/event*` and write access to `/dev/uinput`
+//! to type characters. On Windows the same commands manage a value under the
+//! `Run` registry key, which starts GhostKeys at login.
+
+use std::path::Path;
+#[cfg(target_os = "linux")]
+use std::path::PathBuf;
+
+use crate::error::{GhostKeysError, Result};
+
+/// Name of the systemd user unit
+pub const UNIT_NAME: &str = "ghostkeys.service";
+
+/// Quote a path or argument for a systemd `ExecStart=` line
+///
+/// systemd expands `%` specifiers and `$` variables even inside quotes, so
+/// both are doubled to be taken literally.
+fn quote(arg: &str) -> String {
+    let arg = arg.replace('%', "%%").replace('$', "$$");
+    if arg.is_empty() || arg.chars().any(|c| c.is_whitespace() || c == '"' || c == '\\') {
+        format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        arg
+    }
+}
+
+/// Contents of the unit file running `exe` with `args`
+pub fn unit_file(exe: &Path, args: &[String]) -> String {
+    let mut exec_start = quote(&exe.to_string_lossy());
+    for arg in args {
+        exec_start.push(' ');
+        exec_start.push_str(&quote(arg));
+    }
+    format!(
+        "[Unit]\n\
+         Description=GhostKeys ABNT2 keyboard layout emulation\n\
+         Documentation=https://github.com/marcelocra/ghostkeys\n\
+         PartOf=graphical-session.target\n\
+         After=graphical-session.target\n\
+         \n\
+         # The user needs to be in the `input` group (reading /dev/input/event*)\n\
+         # and have write access to /dev/uinput (typing characters). User units\n\
+         # can't grant either: run `sudo usermod -aG input $USER` and log in again.\n\
+         [Service]\n\
+         ExecStart={}\n\
+         Restart=on-failure\n\
+         RestartSec=2\n\
+         \n\
+         [Install]\n\
+         WantedBy=graphical-session.target\n",
+        exec_start
+    )
+}
+
+/// Command line for the `Run` registry key: `exe` and `args`, quoted the way
+/// Windows splits command lines
+pub fn run_command(exe: &Path, args: &[String]) -> String {
+    let quote = |arg: &str| {
+        if arg.is_empty() || arg.chars().any(|c| c.is_whitespace() || c == '"') {
+            format!("\"{}\"", arg.replace('"', "\\\""))
+        } else {
+            arg.to_string()
+        }
+    };
+    let mut command = quote(&exe.to_string_lossy());
+    for arg in args {
+        command.push(' ');
+        command.push_str(&quote(arg));
+    }
+    command
+}
+
+/// Where the unit file is installed
+#[cfg(target_os = "linux")]
+fn unit_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("systemd").join("user").join(UNIT_NAME))
+}
+
+/// Run `systemctl --user` with `args`, failing on a non-zero exit
+#[cfg(target_os = "linux")]
+fn systemctl(args: &[&str]) -> Result<()> {
+    let status = std::process::Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .status()
+        .map_err(|e| GhostKeysError::ServiceError(format!("failed to run systemctl: {}", e)))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(GhostKeysError::ServiceError(format!(
+            "systemctl --user {} exited with {}",
+            args.join(" "),
+            status
+        )))
+    }
+}
+
+/// Write the unit for this executable and start it now and at every login
+///
+/// `args` are passed to GhostKeys when the service starts.
+#[cfg(target_os = "linux")]
+pub fn install(args: &[String]) -> Result<()> {
+    write_unit(args)?;
+    systemctl(&["enable", "--now", UNIT_NAME])
+}
+
+/// Start GhostKeys at every login, without starting a second copy now
+#[cfg(target_os = "linux")]
+pub fn enable_at_login() -> Result<()> {
+    write_unit(&[])?;
+    systemctl(&["enable", UNIT_NAME])
+}
+
+/// Write the unit for this executable and have systemd reread it
+#[cfg(target_os = "linux")]
+fn write_unit(args: &[String]) -> Result<()> {
+    let exe = current_exe()?;
+    let path = unit_path()
+        .ok_or_else(|| GhostKeysError::ServiceError("can't find the config directory".to_string()))?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| GhostKeysError::ServiceError(format!("{}: {}", dir.display(), e)))?;
+    }
+    std::fs::write(&path, unit_file(&exe, args))
+        .map_err(|e| GhostKeysError::ServiceError(format!("{}: {}", path.display(), e)))?;
+    println!("Wrote {}", path.display());
+    systemctl(&["daemon-reload"])
+}
+
+/// Path of this executable
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+fn current_exe() -> Result<std::path::PathBuf> {
+    std::env::current_exe()
+        .map_err(|e| GhostKeysError::ServiceError(format!("can't find the executable: {}", e)))
+}
+
+/// Stop and disable the service and remove its unit
+#[cfg(target_os = "linux")]
+pub fn uninstall() -> Result<()> {
+    let path = unit_path()
+        .ok_or_else(|| GhostKeysError::ServiceError("can't find the config directory".to_string()))?;
+    if !path.exists() {
+        println!("{} is not installed", UNIT_NAME);
+        return Ok(());
+    }
+    // Disabling a unit that isn't running still succeeds
+    systemctl(&["disable", "--now", UNIT_NAME])?;
+    std::fs::remove_file(&path)
+        .map_err(|e| GhostKeysError::ServiceError(format!("{}: {}", path.display(), e)))?;
+    println!("Removed {}", path.display());
+    systemctl(&["daemon-reload"])
+}
+
+/// Print whether the unit is installed and what systemd says about it
+#[cfg(target_os = "linux")]
+pub fn status() -> Result<()> {
+    match unit_path() {
+        Some(path) if path.exists() => println!("Installed: {}", path.display()),
+        _ => {
+            println!("{} is not installed. Run `ghostkeys service install`", UNIT_NAME);
+            return Ok(());
+        }
+    }
+    // `systemctl status` exits non-zero for a stopped unit, which isn't an error here
+    let _ = systemctl(&["status", "--no-pager", UNIT_NAME]);
+    Ok(())
+}
+
+/// Whether the unit is installed
+#[cfg(target_os = "linux")]
+pub fn is_installed() -> bool {
+    unit_path().is_some_and(|path| path.exists())
+}
+
+/// Registry key of the programs Windows starts at login
+#[cfg(target_os = "windows")]
+const RUN_KEY: &str = r"Software\Microsoft\Windows\CurrentVersion\Run";
+
+/// Name of GhostKeys' value under `RUN_KEY`
+#[cfg(target_os = "windows")]
+const RUN_VALUE: &str = "GhostKeys";
+
+/// Start this executable with `args` at every login
+///
+/// Unlike the systemd unit, nothing restarts GhostKeys if it fails; pass
+/// `--supervise` for that.
+#[cfg(target_os = "windows")]
+pub fn install(args: &[String]) -> Result<()> {
+    use windows::core::HSTRING;
+    use windows::Win32::System::Registry::{RegSetKeyValueW, HKEY_CURRENT_USER, REG_SZ};
+
+    let command = run_command(&current_exe()?, args);
+    let data: Vec<u16> = command.encode_utf16().chain([0]).collect();
+    unsafe {
+        RegSetKeyValueW(
+            HKEY_CURRENT_USER,
+            &HSTRING::from(RUN_KEY),
+            &HSTRING::from(RUN_VALUE),
+            REG_SZ.0,
+            Some(data.as_ptr().cast()),
+            (data.len() * 2) as u32,
+        )
+    }
+    .ok()
+    .map_err(|e| GhostKeysError::ServiceError(format!("can't write the Run registry key: {}", e)))?;
+    println!("GhostKeys will start at login: {}", command);
+    Ok(())
+}
+
+/// Start GhostKeys at every login
+#[cfg(target_os = "windows")]
+pub fn enable_at_login() -> Result<()> {
+    install(&[])
+}
+
+/// Stop starting GhostKeys at login
+#[cfg(target_os = "windows")]
+pub fn uninstall() -> Result<()> {
+    use windows::core::HSTRING;
+    use windows::Win32::Foundation::ERROR_FILE_NOT_FOUND;
+    use windows::Win32::System::Registry::{RegDeleteKeyValueW, HKEY_CURRENT_USER};
+
+    let result = unsafe {
+        RegDeleteKeyValueW(HKEY_CURRENT_USER, &HSTRING::from(RUN_KEY), &HSTRING::from(RUN_VALUE))
+    };
+    if result == ERROR_FILE_NOT_FOUND {
+        println!("GhostKeys is not set to start at login");
+        return Ok(());
+    }
+    result.ok().map_err(|e| {
+        GhostKeysError::ServiceError(format!("can't edit the Run registry key: {}", e))
+    })?;
+    println!("GhostKeys will no longer start at login");
+    Ok(())
+}
+
+/// Command Windows runs at login, if any
+#[cfg(target_os = "windows")]
+fn run_value() -> Option<String> {
+    use windows::core::HSTRING;
+    use windows::Win32::System::Registry::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_SZ};
+
+    let mut data = [0u16; 1024];
+    let mut size = (data.len() * 2) as u32;
+    let result = unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            &HSTRING::from(RUN_KEY),
+            &HSTRING::from(RUN_VALUE),
+            RRF_RT_REG_SZ,
+            None,
+            Some(data.as_mut_ptr().cast()),
+            Some(&mut size),
+        )
+    };
+    result.is_ok().then(|| {
+        let len = (size as usize / 2).saturating_sub(1);
+        String::from_utf16_lossy(&data[..len])
+    })
+}
+
+/// Print the command Windows runs at login, if any
+#[cfg(target_os = "windows")]
+pub fn status() -> Result<()> {
+    match run_value() {
+        Some(command) => println!("Starts at login: {}", command),
+        None => println!("GhostKeys is not set to start at login. Run `ghostkeys service install`"),
+    }
+    Ok(())
+}
+
+/// Whether Windows starts GhostKeys at login
+#[cfg(target_os = "windows")]
+pub fn is_installed() -> bool {
+    run_value().is_some()
+}
+
+/// Services are managed with systemd on Linux and the Run key on Windows only
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+pub fn install(_args: &[String]) -> Result<()> {
+    Err(GhostKeysError::NotSupported(crate::error::Platform::current()))
+}
+
+/// Services are managed with systemd on Linux and the Run key on Windows only
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+pub fn enable_at_login() -> Result<()> {
+    Err(GhostKeysError::NotSupported(crate::error::Platform::current()))
+}
+
+/// Services are managed with systemd on Linux and the Run key on Windows only
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+pub fn uninstall() -> Result<()> {
+    Err(GhostKeysError::NotSupported(crate::error::Platform::current()))
+}
+
+/// Services are managed with systemd on Linux and the Run key on Windows only
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+pub fn status() -> Result<()> {
+    Err(GhostKeysError::NotSupported(crate::error::Platform::current()))
+}
+
+/// Services are managed with systemd on Linux and the Run key on Windows only
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+pub fn is_installed() -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unit_file_runs_the_executable() {
+        let unit = unit_file(Path::new("/usr/bin/ghostkeys"), &[]);
+        assert!(unit.contains("\nExecStart=/usr/bin/ghostkeys\n"));
+        assert!(unit.contains("\nRestart=on-failure\n"));
+        assert!(unit.contains("\nWantedBy=graphical-session.target\n"));
+    }
+
+    #[test]
+    fn test_unit_file_quotes_arguments() {
+        let args = vec!["--layout".to_string(), "/home/me/My Layouts/br.klc".to_string()];
+        let unit = unit_file(Path::new("/opt/ghost keys/ghostkeys"), &args);
+        assert!(unit.contains(
+            "\nExecStart=\"/opt/ghost keys/ghostkeys\" --layout \"/home/me/My Layouts/br.klc\"\n"
+        ));
+    }
+
+    #[test]
+    fn test_unit_file_escapes_specifiers() {
+        let args = vec!["--layout".to_string(), "/home/me/100%/$HOME.klc".to_string()];
+        let unit = unit_file(Path::new("/opt/ghostkeys%h/ghostkeys"), &args);
+        assert!(unit.contains(
+            "\nExecStart=/opt/ghostkeys%%h/ghostkeys --layout /home/me/100%%/$$HOME.klc\n"
+        ));
+    }
+
+    #[test]
+    fn test_run_command_quotes_arguments() {
+        let args = vec!["--layout".to_string(), r"C:\My Layouts\br.klc".to_string()];
+        let command = run_command(Path::new(r"C:\Program Files\GhostKeys\ghostkeys.exe"), &args);
+        assert_eq!(
+            command,
+            r#""C:\Program Files\GhostKeys\ghostkeys.exe" --layout "C:\My Layouts\br.klc""#
+        );
+    }
+}
@@ -0,0 +1,190 @@
+//! Windows accent suggestion popup
+//!
+//! With `suggestions = true` under `[autocorrect]`, a word with several valid
+//! spellings (esta/está, e/é) gets a small popup under the text cursor once
+//! it's ended, listing them as "Alt+1 está". It never takes the focus and
+//! disappears with the next key; the hook handles Alt+1..9 while it's shown.
+//! Like the palette, it lives on the hook thread, and it's sized for the
+//! DPI of the monitor the text cursor is on.
+
+#![cfg(target_os = "windows")]
+
+use std::cell::Cell;
+
+use windows::core::{w, PCWSTR};
+use windows::Win32::Foundation::{HINSTANCE, HWND, LPARAM, LRESULT, POINT, WPARAM};
+use windows::Win32::Graphics::Gdi::{DeleteObject, FW_NORMAL, HFONT};
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, DefWindowProcW, RegisterClassW, SendMessageW, SetWindowPos, SetWindowTextW,
+    ShowWindow, HMENU, HWND_TOPMOST, SWP_NOACTIVATE, SWP_NOZORDER, SW_HIDE, SW_SHOWNOACTIVATE,
+    WINDOW_EX_STYLE, WM_SETFONT, WNDCLASSW, WS_BORDER, WS_CHILD, WS_EX_NOACTIVATE,
+    WS_EX_TOOLWINDOW, WS_EX_TOPMOST, WS_POPUP, WS_VISIBLE,
+};
+
+use crate::platform::ui::{self, caret, Monitor, Side};
+
+/// Height of the popup, in pixels at 96 DPI
+const HEIGHT: i32 = 22;
+/// Rough width of a character of its font, in pixels at 96 DPI
+const CHAR_WIDTH: i32 = 7;
+/// Height of its font, in pixels at 96 DPI
+const FONT_HEIGHT: i32 = 15;
+
+/// The popup and its text, created on first use and hidden in between
+#[derive(Clone, Copy)]
+struct Popup {
+    hwnd: HWND,
+    text: HWND,
+    /// Font of the text, made for `dpi`
+    font: HFONT,
+    dpi: u32,
+}
+
+thread_local! {
+    static POPUP: Cell<Option<Popup>> = const { Cell::new(None) };
+    static VISIBLE: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Whether the popup is on screen
+pub fn is_visible() -> bool {
+    VISIBLE.with(Cell::get)
+}
+
+/// Show `alternatives` under the text cursor of the foreground window
+pub fn show(alternatives: &[String]) {
+    let Some(popup) = popup() else {
+        return;
+    };
+    let label = alternatives
+        .iter()
+        .take(9)
+        .enumerate()
+        .map(|(i, spelling)| format!("Alt+{} {}", i + 1, spelling))
+        .collect::<Vec<_>>()
+        .join("   ");
+    let wide: Vec<u16> = label.encode_utf16().chain(Some(0)).collect();
+    let anchor = caret::anchor();
+    let monitor = Monitor::at(POINT { x: anchor.left, y: anchor.bottom });
+    let popup = with_font_for(popup, monitor.dpi);
+    let width = monitor.scale(label.chars().count() as i32 * CHAR_WIDTH + 12);
+    let height = monitor.scale(HEIGHT);
+    let position = ui::place_near(
+        anchor,
+        width,
+        height,
+        monitor.scale(2),
+        Side::Below,
+        monitor.work_area,
+    );
+
+    unsafe {
+        let _ = SetWindowTextW(popup.text, PCWSTR(wide.as_ptr()));
+        let _ = SetWindowPos(
+            popup.text,
+            HWND::default(),
+            monitor.scale(4),
+            monitor.scale(3),
+            width - monitor.scale(10),
+            height - monitor.scale(6),
+            SWP_NOACTIVATE | SWP_NOZORDER,
+        );
+        let _ = SetWindowPos(
+            popup.hwnd,
+            HWND_TOPMOST,
+            position.x,
+            position.y,
+            width,
+            height,
+            SWP_NOACTIVATE,
+        );
+        let _ = ShowWindow(popup.hwnd, SW_SHOWNOACTIVATE);
+    }
+    VISIBLE.with(|visible| visible.set(true));
+}
+
+/// Take the popup off screen
+pub fn hide() {
+    if let Some(popup) = POPUP.with(Cell::get) {
+        unsafe {
+            let _ = ShowWindow(popup.hwnd, SW_HIDE);
+        }
+    }
+    VISIBLE.with(|visible| visible.set(false));
+}
+
+/// The popup window, created the first time it's needed
+fn popup() -> Option<Popup> {
+    if let Some(popup) = POPUP.with(Cell::get) {
+        return Some(popup);
+    }
+    unsafe {
+        let instance = GetModuleHandleW(None).map(HINSTANCE::from).ok()?;
+        let class_name = w!("GhostKeysSuggestions");
+        let class = WNDCLASSW {
+            lpfnWndProc: Some(window_proc),
+            hInstance: instance,
+            lpszClassName: class_name,
+            ..Default::default()
+        };
+        // Fails harmlessly if the class is still registered from an earlier hook
+        RegisterClassW(&class);
+
+        let hwnd = CreateWindowExW(
+            WS_EX_TOPMOST | WS_EX_TOOLWINDOW | WS_EX_NOACTIVATE,
+            class_name,
+            PCWSTR::null(),
+            WS_POPUP | WS_BORDER,
+            0,
+            0,
+            0,
+            HEIGHT,
+            HWND::default(),
+            HMENU::default(),
+            instance,
+            None,
+        )
+        .ok()?;
+        let text = CreateWindowExW(
+            WINDOW_EX_STYLE::default(),
+            w!("STATIC"),
+            PCWSTR::null(),
+            WS_CHILD | WS_VISIBLE,
+            4,
+            3,
+            0,
+            HEIGHT - 2,
+            hwnd,
+            HMENU::default(),
+            instance,
+            None,
+        )
+        .ok()?;
+
+        let popup = Popup { hwnd, text, font: HFONT::default(), dpi: 0 };
+        POPUP.with(|slot| slot.set(Some(popup)));
+        Some(popup)
+    }
+}
+
+/// `popup` with a font made for `dpi`, replacing one made for another DPI
+fn with_font_for(popup: Popup, dpi: u32) -> Popup {
+    if popup.dpi == dpi {
+        return popup;
+    }
+    let font = ui::font(FONT_HEIGHT, FW_NORMAL.0 as i32, dpi);
+    unsafe {
+        SendMessageW(popup.text, WM_SETFONT, WPARAM(font.0 as usize), LPARAM(0));
+        if !popup.font.is_invalid() {
+            let _ = DeleteObject(popup.font);
+        }
+    }
+    let popup = Popup { font, dpi, ..popup };
+    POPUP.with(|slot| slot.set(Some(popup)));
+    popup
+}
+
+/// Window procedure of the popup, which only displays its text
+unsafe extern "system" fn window_proc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    DefWindowProcW(hwnd, msg, wparam, lparam)
+}
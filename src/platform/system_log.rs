@@ -0,0 +1,76 @@
+//! System log sink
+//!
+//! Copies warnings and errors to the platform's standard log (the Windows
+//! Event Log, journald on Linux) so administrators can diagnose GhostKeys with
+//! the tools they already use. Enabled with `system_log = true` under
+//! `[logging]`. Writing is best effort: if the system log is unavailable there
+//! is nowhere left to report that, so failures are ignored.
+
+use crate::notify::{Notification, Severity};
+
+/// Name GhostKeys logs under (event source / syslog identifier)
+const SOURCE: &str = "GhostKeys";
+
+/// Write a notification to the system log
+pub fn write(notification: &Notification) {
+    write_message(notification.severity, &notification.message);
+}
+
+/// Write a message to the Windows Event Log (Application log)
+///
+/// The source isn't registered with an installer, so Event Viewer prefixes
+/// the text with a note about a missing event description.
+#[cfg(target_os = "windows")]
+pub fn write_message(severity: Severity, message: &str) {
+    use windows::core::{HSTRING, PCWSTR};
+    use windows::Win32::Security::PSID;
+    use windows::Win32::System::EventLog::{
+        DeregisterEventSource, RegisterEventSourceW, ReportEventW, EVENTLOG_ERROR_TYPE,
+        EVENTLOG_INFORMATION_TYPE, EVENTLOG_WARNING_TYPE,
+    };
+
+    let kind = match severity {
+        Severity::Info => EVENTLOG_INFORMATION_TYPE,
+        Severity::Warning => EVENTLOG_WARNING_TYPE,
+        Severity::Error => EVENTLOG_ERROR_TYPE,
+    };
+    let source = HSTRING::from(SOURCE);
+    let text = HSTRING::from(message);
+
+    unsafe {
+        let Ok(log) = RegisterEventSourceW(PCWSTR::null(), &source) else {
+            return;
+        };
+        let strings = [PCWSTR(text.as_ptr())];
+        let _ = ReportEventW(log, kind, 0, 0, PSID::default(), 0, Some(&strings), None);
+        let _ = DeregisterEventSource(log);
+    }
+}
+
+/// Write a message to journald through its native socket
+#[cfg(target_os = "linux")]
+pub fn write_message(severity: Severity, message: &str) {
+    use std::os::unix::net::UnixDatagram;
+
+    // syslog priorities
+    let priority = match severity {
+        Severity::Info => 6,
+        Severity::Warning => 4,
+        Severity::Error => 3,
+    };
+    // The simple KEY=value form can't carry newlines
+    let entry = format!(
+        "PRIORITY={}\nSYSLOG_IDENTIFIER={}\nMESSAGE={}\n",
+        priority,
+        SOURCE.to_lowercase(),
+        message.replace('\n', " ")
+    );
+
+    if let Ok(socket) = UnixDatagram::unbound() {
+        let _ = socket.send_to(entry.as_bytes(), "/run/systemd/journal/socket");
+    }
+}
+
+/// No system log elsewhere
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+pub fn write_message(_severity: Severity, _message: &str) {}
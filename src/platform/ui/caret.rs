@@ -0,0 +1,72 @@
+//! Where the user is typing
+//!
+//! `GetGUIThreadInfo` reports the system caret of the foreground window's
+//! thread, which most applications move along with the text cursor. Its
+//! rectangle is in the client coordinates of the caret's window; converted
+//! on a per-monitor DPI aware thread, they come out in physical screen
+//! pixels whatever the monitor's scaling. Applications that draw their own
+//! cursor (some browsers and terminals) have no caret, so the mouse pointer
+//! stands in for it.
+
+use windows::Win32::Foundation::{POINT, RECT};
+use windows::Win32::Graphics::Gdi::ClientToScreen;
+use windows::Win32::UI::WindowsAndMessaging::{
+    GetCursorPos, GetForegroundWindow, GetGUIThreadInfo, GetWindowThreadProcessId, GUITHREADINFO,
+};
+
+use super::Monitor;
+
+/// Height given to the mouse pointer when it stands in for the caret, in
+/// pixels at 96 DPI
+const POINTER_HEIGHT: i32 = 16;
+
+/// The text cursor of the foreground window, in screen pixels, if it has one
+pub fn rect() -> Option<RECT> {
+    unsafe {
+        let thread = GetWindowThreadProcessId(GetForegroundWindow(), None);
+        let mut info = GUITHREADINFO {
+            cbSize: std::mem::size_of::<GUITHREADINFO>() as u32,
+            ..Default::default()
+        };
+        if GetGUIThreadInfo(thread, &mut info).is_err() || info.hwndCaret.is_invalid() {
+            return None;
+        }
+        let caret = info.rcCaret;
+        let mut top_left = POINT {
+            x: caret.left,
+            y: caret.top,
+        };
+        let mut bottom_right = POINT {
+            x: caret.right,
+            y: caret.bottom,
+        };
+        if !ClientToScreen(info.hwndCaret, &mut top_left).as_bool()
+            || !ClientToScreen(info.hwndCaret, &mut bottom_right).as_bool()
+        {
+            return None;
+        }
+        Some(RECT {
+            left: top_left.x,
+            top: top_left.y,
+            right: bottom_right.x,
+            bottom: bottom_right.y,
+        })
+    }
+}
+
+/// The text cursor, or the mouse pointer where there's none
+pub fn anchor() -> RECT {
+    rect().unwrap_or_else(|| {
+        let mut point = POINT::default();
+        unsafe {
+            let _ = GetCursorPos(&mut point);
+        }
+        let height = Monitor::at(point).scale(POINTER_HEIGHT);
+        RECT {
+            left: point.x,
+            top: point.y,
+            right: point.x + 1,
+            bottom: point.y + height,
+        }
+    })
+}
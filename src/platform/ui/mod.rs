@@ -0,0 +1,147 @@
+//! Pieces shared by the Windows overlays
+//!
+//! The learning display, the suggestion popup and the palette are created on
+//! the hook thread, which is made per-monitor DPI aware (v2) before any of
+//! them exist. Windows scales nothing for such windows, so sizes and fonts
+//! are written here for 96 DPI (100%) and scaled to the DPI of the monitor
+//! the window opens on: otherwise they show up tiny on a 200% laptop screen,
+//! or blurry and in the wrong corner next to a 100% external monitor. They
+//! open next to the text cursor (`caret`), on whichever monitor it is, and
+//! any window added later (e.g., settings) should do the same.
+
+#![cfg(target_os = "windows")]
+
+pub mod caret;
+
+use windows::core::w;
+use windows::Win32::Foundation::{HWND, POINT, RECT};
+use windows::Win32::Graphics::Gdi::{
+    CreateFontW, GetMonitorInfoW, MonitorFromPoint, MonitorFromWindow, CLEARTYPE_QUALITY,
+    CLIP_DEFAULT_PRECIS, DEFAULT_CHARSET, HFONT, HMONITOR, MONITORINFO, MONITOR_DEFAULTTONEAREST,
+    OUT_DEFAULT_PRECIS,
+};
+use windows::Win32::UI::HiDpi::{
+    GetDpiForMonitor, SetThreadDpiAwarenessContext, DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2,
+    MDT_EFFECTIVE_DPI,
+};
+
+/// DPI the sizes in the overlays are written for (100% scaling)
+pub const BASE_DPI: u32 = 96;
+
+/// Which side of the text cursor a window goes on, if there's room
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    /// Under the line being typed
+    Below,
+    /// Over the line being typed
+    Above,
+}
+
+/// A monitor, in physical pixels
+#[derive(Debug, Clone, Copy)]
+pub struct Monitor {
+    /// The screen minus the taskbar
+    pub work_area: RECT,
+    /// Effective DPI, 96 at 100% scaling
+    pub dpi: u32,
+}
+
+impl Monitor {
+    /// The monitor showing `point`, or the one nearest to it
+    pub fn at(point: POINT) -> Self {
+        Self::from_handle(unsafe { MonitorFromPoint(point, MONITOR_DEFAULTTONEAREST) })
+    }
+
+    /// The monitor showing most of `hwnd`
+    pub fn of_window(hwnd: HWND) -> Self {
+        Self::from_handle(unsafe { MonitorFromWindow(hwnd, MONITOR_DEFAULTTONEAREST) })
+    }
+
+    fn from_handle(monitor: HMONITOR) -> Self {
+        let mut info = MONITORINFO {
+            cbSize: std::mem::size_of::<MONITORINFO>() as u32,
+            ..Default::default()
+        };
+        let (mut dpi, mut dpi_y) = (BASE_DPI, BASE_DPI);
+        unsafe {
+            let _ = GetMonitorInfoW(monitor, &mut info);
+            // Fails before Windows 8.1, where everything is at the system DPI
+            if GetDpiForMonitor(monitor, MDT_EFFECTIVE_DPI, &mut dpi, &mut dpi_y).is_err() {
+                dpi = BASE_DPI;
+            }
+        }
+        Self {
+            work_area: info.rcWork,
+            dpi,
+        }
+    }
+
+    /// `pixels` at 96 DPI, in this monitor's pixels
+    pub fn scale(&self, pixels: i32) -> i32 {
+        scale(pixels, self.dpi)
+    }
+}
+
+/// Make the windows the calling thread creates from now on per-monitor DPI
+/// aware; harmless where Windows doesn't support it (before 10 1703)
+pub fn enable_per_monitor_dpi() {
+    unsafe {
+        let _ = SetThreadDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2);
+    }
+}
+
+/// `pixels` at 96 DPI, at `dpi`, rounded to the nearest pixel
+pub fn scale(pixels: i32, dpi: u32) -> i32 {
+    (pixels * dpi as i32 + BASE_DPI as i32 / 2) / BASE_DPI as i32
+}
+
+/// Segoe UI `height` pixels tall at 96 DPI, for a window at `dpi`
+///
+/// The caller deletes it with `DeleteObject` once no window uses it.
+pub fn font(height: i32, weight: i32, dpi: u32) -> HFONT {
+    unsafe {
+        CreateFontW(
+            scale(height, dpi),
+            0,
+            0,
+            0,
+            weight,
+            0,
+            0,
+            0,
+            DEFAULT_CHARSET.0 as u32,
+            OUT_DEFAULT_PRECIS.0 as u32,
+            CLIP_DEFAULT_PRECIS.0 as u32,
+            CLEARTYPE_QUALITY.0 as u32,
+            0,
+            w!("Segoe UI"),
+        )
+    }
+}
+
+/// Top-left corner for a `width` x `height` window `gap` pixels from
+/// `anchor` (the text cursor) on `side`, or on the other side if there's no
+/// room, and kept inside `work_area`
+pub fn place_near(
+    anchor: RECT,
+    width: i32,
+    height: i32,
+    gap: i32,
+    side: Side,
+    work_area: RECT,
+) -> POINT {
+    let below = anchor.bottom + gap;
+    let above = anchor.top - gap - height;
+    let fits_below = below + height <= work_area.bottom;
+    let fits_above = above >= work_area.top;
+    let y = match side {
+        Side::Below if fits_below || !fits_above => below,
+        Side::Above if fits_above || !fits_below => above,
+        Side::Below => above,
+        Side::Above => below,
+    };
+    POINT {
+        x: anchor.left.min(work_area.right - width).max(work_area.left),
+        y: y.min(work_area.bottom - height).max(work_area.top),
+    }
+}
@@ -5,30 +5,116 @@
 
 #![cfg(target_os = "windows")]
 
-use std::cell::RefCell;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::cell::{Cell, RefCell};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::{mpsc, Arc, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
-use windows::Win32::Foundation::{HINSTANCE, LPARAM, LRESULT, WPARAM};
+use windows::core::{w, HSTRING, PWSTR};
+use windows::Win32::Foundation::{
+    CloseHandle, GetLastError, E_ACCESSDENIED, ERROR_SUCCESS, HANDLE, HINSTANCE, HWND, LPARAM, LRESULT,
+    WPARAM,
+};
+use windows::Win32::Security::{
+    GetSidSubAuthority, GetSidSubAuthorityCount, GetTokenInformation, TokenIntegrityLevel,
+    TOKEN_MANDATORY_LABEL, TOKEN_QUERY,
+};
+use windows::Win32::System::Diagnostics::Debug::{
+    MessageBeep, MiniDumpNormal, MiniDumpWriteDump, SetUnhandledExceptionFilter, EXCEPTION_POINTERS,
+    MINIDUMP_EXCEPTION_INFORMATION,
+};
+use windows::Win32::System::Diagnostics::ToolHelp::{
+    CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W, TH32CS_SNAPPROCESS,
+};
+use windows::Win32::System::Registry::{
+    RegGetValueW, HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE, RRF_RT_REG_BINARY, RRF_RT_REG_DWORD,
+    RRF_RT_REG_SZ,
+};
+use windows::Win32::System::Threading::{
+    GetCurrentProcess, GetCurrentProcessId, GetCurrentThreadId, OpenProcess, OpenProcessToken,
+    QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+};
+use windows::Win32::UI::Input::Ime::ImmGetDefaultIMEWnd;
 use windows::Win32::UI::Input::KeyboardAndMouse::{
-    GetAsyncKeyState, SendInput, INPUT, INPUT_0, INPUT_KEYBOARD, KEYBDINPUT,
-    KEYEVENTF_KEYUP, KEYEVENTF_UNICODE, VK_LSHIFT, VK_RSHIFT, VK_SHIFT,
+    GetAsyncKeyState, GetKeyState, GetKeyboardLayout, GetKeyboardLayoutList, LoadKeyboardLayoutW,
+    SendInput, ToUnicodeEx, HKL, INPUT, INPUT_0, INPUT_KEYBOARD, KEYBDINPUT, KEYBD_EVENT_FLAGS,
+    KEYEVENTF_EXTENDEDKEY, KEYEVENTF_KEYUP, KEYEVENTF_SCANCODE, KEYEVENTF_UNICODE, KLF_ACTIVATE,
+    VIRTUAL_KEY, VK_BACK, VK_CAPITAL, VK_CONTROL, VK_LCONTROL, VK_LSHIFT, VK_LWIN, VK_MENU,
+    VK_PAUSE, VK_RMENU, VK_RSHIFT, VK_RWIN, VK_SHIFT,
 };
 use windows::Win32::UI::WindowsAndMessaging::{
-    CallNextHookEx, SetWindowsHookExW, UnhookWindowsHookEx, HHOOK, KBDLLHOOKSTRUCT,
-    WH_KEYBOARD_LL, WM_KEYDOWN, WM_SYSKEYDOWN,
+    CallNextHookEx, DispatchMessageW, GetClassNameW, GetForegroundWindow, GetGUIThreadInfo,
+    GetMessageW, GetWindowTextW, GetWindowThreadProcessId, KillTimer, PeekMessageW,
+    PostMessageW, PostThreadMessageW, SendMessageTimeoutW, SetTimer, SetWindowsHookExW,
+    SystemParametersInfoW, TranslateMessage, UnhookWindowsHookEx, GUITHREADINFO, HHOOK,
+    HWND_BROADCAST, KBDLLHOOKSTRUCT, LLKHF_EXTENDED, LLKHF_INJECTED, MB_ICONASTERISK,
+    MB_ICONEXCLAMATION, MB_ICONQUESTION, MB_OK, MESSAGEBOX_STYLE, MSG, PM_NOREMOVE,
+    SMTO_ABORTIFHUNG, SPIF_SENDCHANGE, SPI_SETDEFAULTINPUTLANG, WH_KEYBOARD_LL, WM_APP, WM_CHAR,
+    WM_INPUTLANGCHANGEREQUEST, WM_KEYDOWN, WM_KEYUP, WM_SYSKEYDOWN, WM_SYSKEYUP, WM_TIMER, WM_USER,
 };
 
+use crate::config::{
+    ComposeTrigger, Config, InjectionBackend, KeyCodes, NavTrigger, Sound, WindowInfo,
+    WindowRule,
+};
+use crate::conflicts;
+use crate::doctor::Check;
 use crate::error::{GhostKeysError, Result};
-use crate::interceptor::{KeyAction, KeyboardInterceptor};
-use crate::mapper::{Mapper, VirtualKey};
-use crate::state::SharedState;
+use crate::events::Event;
+use crate::history::Cause;
+use crate::hook_context::{HookContext, UnmappedKey};
+use crate::hotkeys::{self, Action, Chord, Conflict, Key};
+use crate::icon::Theme;
+use crate::interceptor::{
+    build_mapper, Injector, KeyAction, KeyEvent, KeyboardInterceptor, LimitedInjector, Modifiers,
+    PacedInjector,
+};
+use crate::latency::Sample;
+use crate::layout::{scan_code_to_virtual_key, virtual_key_to_scan_code, Layout};
+use crate::macros::{slot_name, SLOT_COUNT};
+use crate::i18n::{tr, tr_args, Text};
+use crate::notify::Severity;
+use crate::os_layout::{self, Finding};
+use crate::platform::{learning_osd, palette_window, raw_input, suggestion_popup, ui};
+use crate::mapper::{AccentType, Mapper, MapperState, VirtualKey, ACCENT_TIMEOUT};
+use crate::nav;
+use crate::pipeline::KeyInput;
+use crate::remap::RemapKey;
+use crate::{paths, profile, session};
+use crate::state::{OperationMode, SharedState, StateChange};
+use crate::watchdog::Condition;
 
-// Thread-local storage for the mapper and hook handle
+// State of the hook thread, while the hook is installed
 thread_local! {
-    static MAPPER: RefCell<Option<Mapper>> = RefCell::new(None);
-    static HOOK_HANDLE: RefCell<Option<HHOOK>> = RefCell::new(None);
-    static IS_INJECTING: RefCell<bool> = RefCell::new(false);
+    static CONTEXT: RefCell<Option<HookContext>> = const { RefCell::new(None) };
+    /// Accent whose dead key armed last, and the timer for its timeout sound
+    static ACCENT_TIMER: Cell<Option<(AccentType, usize)>> = const { Cell::new(None) };
+    /// Timer that ends the pause of a `pause_<n>m` hotkey
+    static PAUSE_TIMER: Cell<Option<usize>> = const { Cell::new(None) };
+    /// Layout a `switch_layout:` hotkey asked for, loaded from the message loop
+    static PENDING_LAYOUT: RefCell<Option<String>> = const { RefCell::new(None) };
+    /// Custom hotkey conflicts already reported, so a reload only warns of new ones
+    static REPORTED_CONFLICTS: RefCell<Vec<Conflict>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Run `f` with the hook thread's context
+///
+/// `None` off the hook thread, while the hook isn't installed, or if the
+/// context is already in use further up the stack.
+fn with_context<R>(f: impl FnOnce(&mut HookContext) -> R) -> Option<R> {
+    CONTEXT
+        .try_with(|context| {
+            let mut context = context.try_borrow_mut().ok()?;
+            Some(f(context.as_mut()?))
+        })
+        .ok()
+        .flatten()
+}
+
+/// State shared with the UI, if this is the hook thread
+fn shared_state() -> Option<SharedState> {
+    with_context(|context| context.state().clone())
 }
 
 // Global hook handle for panic handler access (separate from thread-local)
@@ -47,6 +133,194 @@ pub fn release_hook_on_panic() {
     }
 }
 
+/// Mapper state and key counts for a crash report, if this is the hook thread
+///
+/// Never panics: the context may be mid-update when the crash happens.
+pub fn mapper_snapshot() -> Option<String> {
+    CONTEXT
+        .try_with(|context| Some(context.try_borrow().ok()?.as_ref()?.snapshot()))
+        .ok()
+        .flatten()
+}
+
+/// Install the filter for crashes that don't unwind (access violations,
+/// stack overflows in native code), which the panic hook never sees
+pub fn install_crash_filter() {
+    unsafe {
+        SetUnhandledExceptionFilter(Some(crash_filter));
+    }
+}
+
+/// Let Windows carry on with its default crash handling (WER)
+const EXCEPTION_CONTINUE_SEARCH: i32 = 0;
+
+/// Release the hook, write a minidump and hand over to the crash handler
+unsafe extern "system" fn crash_filter(info: *const EXCEPTION_POINTERS) -> i32 {
+    release_hook_on_panic();
+
+    let code = info
+        .as_ref()
+        .and_then(|info| info.ExceptionRecord.as_ref())
+        .map_or(0, |record| record.ExceptionCode.0 as u32);
+    if let Some(path) = crate::crash::artifact_path("dmp") {
+        write_minidump(&path, info);
+    }
+    crate::crash::handle_crash(&format!("unhandled exception 0x{:08X}", code), mapper_snapshot());
+
+    EXCEPTION_CONTINUE_SEARCH
+}
+
+/// Write a minidump of this process for the exception being handled
+unsafe fn write_minidump(path: &std::path::Path, info: *const EXCEPTION_POINTERS) {
+    use std::os::windows::io::AsRawHandle;
+
+    let Ok(file) = std::fs::File::create(path) else {
+        return;
+    };
+    let exception = MINIDUMP_EXCEPTION_INFORMATION {
+        ThreadId: GetCurrentThreadId(),
+        ExceptionPointers: info as *mut EXCEPTION_POINTERS,
+        ClientPointers: false.into(),
+    };
+    let _ = MiniDumpWriteDump(
+        GetCurrentProcess(),
+        GetCurrentProcessId(),
+        HANDLE(file.as_raw_handle()),
+        MiniDumpNormal,
+        Some(&exception as *const _),
+        None,
+        None,
+    );
+}
+
+/// Decode the US key position of a key event
+///
+/// VK codes follow the OS layout (the key next to L is VK_OEM_3 on a UK
+/// layout), so punctuation and dead-key keys are identified by scan code
+/// instead, unless `key_codes = "virtual_key"` asks to follow the layout.
+/// Letters are whatever the OS layout types on the key, so an AZERTY or
+/// software Dvorak host keeps its letters where it has them, unless
+/// `key_codes = "scan_code"` asks for positions only. Injected keys without
+/// a scan code fall back to the VK code.
+fn decode_position(kb_struct: &KBDLLHOOKSTRUCT) -> VirtualKey {
+    // Unicode input from other programs carries a UTF-16 unit, not a scan code
+    if kb_struct.vkCode == VK_PACKET {
+        return VirtualKey::Other;
+    }
+    let extended = kb_struct.flags.0 & LLKHF_EXTENDED.0 != 0;
+    match u8::try_from(kb_struct.scanCode) {
+        Ok(scan_code) if scan_code != 0 && !extended && key_codes() != KeyCodes::VirtualKey => {
+            let position = scan_code_to_virtual_key(scan_code).unwrap_or(VirtualKey::Other);
+            if key_codes() == KeyCodes::ScanCode {
+                return position;
+            }
+            layout_letter(kb_struct, position).unwrap_or(position)
+        }
+        _ => vk_to_virtual_key(kb_struct.vkCode),
+    }
+}
+
+/// Letter the OS layout types on a letter or punctuation key, if it types one
+fn layout_letter(kb_struct: &KBDLLHOOKSTRUCT, position: VirtualKey) -> Option<VirtualKey> {
+    let typing = matches!(
+        position,
+        VirtualKey::Char(_)
+            | VirtualKey::Semicolon
+            | VirtualKey::Apostrophe
+            | VirtualKey::LeftBracket
+            | VirtualKey::RightBracket
+            | VirtualKey::Backslash
+            | VirtualKey::Slash
+            | VirtualKey::Minus
+            | VirtualKey::Equals
+            | VirtualKey::Comma
+            | VirtualKey::Period
+            | VirtualKey::Backquote
+    );
+    if !typing {
+        return None;
+    }
+    typed_char(kb_struct, false)
+        .filter(char::is_ascii_alphabetic)
+        .map(|c| VirtualKey::Char(c.to_ascii_uppercase()))
+}
+
+/// Virtual key code the hook goes by for a key event
+///
+/// With `key_codes = "scan_code"`, keys outside the typing block (modifiers,
+/// Caps Lock, the menu key...) get theirs from the scan code, as on a US
+/// keyboard, for keyboards that report virtual keys of their own for them.
+/// Keys of the typing block keep the one the OS layout gives them, which
+/// hotkeys go by.
+fn key_vk(kb_struct: &KBDLLHOOKSTRUCT) -> u32 {
+    if kb_struct.vkCode == VK_PACKET || key_codes() != KeyCodes::ScanCode {
+        return kb_struct.vkCode;
+    }
+    let extended = kb_struct.flags.0 & LLKHF_EXTENDED.0 != 0;
+    u8::try_from(kb_struct.scanCode)
+        .ok()
+        .filter(|&scan_code| scan_code != 0)
+        .and_then(|scan_code| scan_code_vk(scan_code, extended))
+        .unwrap_or(kb_struct.vkCode)
+}
+
+/// US virtual key code of a key outside the typing block, by scan code
+fn scan_code_vk(scan_code: u8, extended: bool) -> Option<u32> {
+    let vk = match (scan_code, extended) {
+        (0x2A, false) => 0xA0, // VK_LSHIFT
+        (0x36, false) => 0xA1, // VK_RSHIFT
+        (0x1D, false) => 0xA2, // VK_LCONTROL
+        (0x1D, true) => 0xA3,  // VK_RCONTROL
+        (0x38, false) => 0xA4, // VK_LMENU
+        (0x38, true) => 0xA5,  // VK_RMENU
+        (0x5B, true) => 0x5B,  // VK_LWIN
+        (0x5C, true) => 0x5C,  // VK_RWIN
+        _ => {
+            let key = RemapKey::all()
+                .filter_map(RemapKey::virtual_key)
+                .find(|&key| virtual_key_to_scan_code(key) == Some((scan_code, extended)))?;
+            return named_key_vk(key);
+        }
+    };
+    Some(vk)
+}
+
+/// What keys are told apart by, as `key_codes` says
+fn key_codes() -> KeyCodes {
+    with_context(|context| context.settings().key_codes).unwrap_or_default()
+}
+
+/// Character the foreground window's OS layout produces for a key
+///
+/// Flag 0x4 keeps ToUnicodeEx from consuming the OS dead-key state, so the
+/// lookup doesn't change what the key types when passed through.
+fn typed_char(kb_struct: &KBDLLHOOKSTRUCT, shift: bool) -> Option<char> {
+    // Only Shift and Caps Lock matter; shortcuts never reach this point
+    let mut key_state = [0u8; 256];
+    if shift {
+        key_state[VK_SHIFT.0 as usize] = 0x80;
+    }
+    if is_caps_lock_on() {
+        key_state[VK_CAPITAL.0 as usize] = 0x01;
+    }
+    unsafe {
+        let layout = GetKeyboardLayout(GetWindowThreadProcessId(GetForegroundWindow(), None));
+        let mut buffer = [0u16; 4];
+        let len = ToUnicodeEx(
+            kb_struct.vkCode,
+            kb_struct.scanCode,
+            &key_state,
+            &mut buffer,
+            0x4,
+            layout,
+        );
+        match len {
+            1 => char::from_u32(buffer[0] as u32),
+            _ => None,
+        }
+    }
+}
+
 /// Convert Windows virtual key code to our VirtualKey enum
 fn vk_to_virtual_key(vk: u32) -> VirtualKey {
     match vk {
@@ -56,12 +330,90 @@ fn vk_to_virtual_key(vk: u32) -> VirtualKey {
         0xDD => VirtualKey::RightBracket, // VK_OEM_6 (]})
         0xDC => VirtualKey::Backslash,    // VK_OEM_5 (\|)
         0xBF => VirtualKey::Slash,        // VK_OEM_2 (/?)
+        0xBD => VirtualKey::Minus,        // VK_OEM_MINUS (-_)
+        0xBB => VirtualKey::Equals,       // VK_OEM_PLUS (=+)
+        0xBC => VirtualKey::Comma,        // VK_OEM_COMMA (,<)
+        0xBE => VirtualKey::Period,       // VK_OEM_PERIOD (.>)
         0x20 => VirtualKey::Space,        // VK_SPACE
+        0x0D => VirtualKey::Enter,        // VK_RETURN
+        0x09 => VirtualKey::Tab,          // VK_TAB
+        0x1B => VirtualKey::Escape,       // VK_ESCAPE
+        0xC0 => VirtualKey::Backquote,    // VK_OEM_3 (`~)
+        0xE2 => VirtualKey::IntlBackslash, // VK_OEM_102 (\| on ISO boards)
+        0xC1 => VirtualKey::IntlRo,       // VK_ABNT_C1 (/? on ABNT2)
+        0x30..=0x39 => VirtualKey::Digit((vk as u8) as char), // 0-9
         0x41..=0x5A => VirtualKey::Char((vk as u8) as char), // A-Z
         _ => VirtualKey::Other,
     }
 }
 
+/// Windows virtual key code for a configured Compose key
+fn compose_trigger_vk(trigger: ComposeTrigger) -> u32 {
+    match trigger {
+        ComposeTrigger::Menu => 0x5D,       // VK_APPS
+        ComposeTrigger::RightCtrl => 0xA3,  // VK_RCONTROL
+        ComposeTrigger::ScrollLock => 0x91, // VK_SCROLL
+        ComposeTrigger::Pause => 0x13,      // VK_PAUSE
+    }
+}
+
+/// Windows virtual key code for the nav layer's trigger
+fn nav_trigger_vk(trigger: NavTrigger) -> u32 {
+    match trigger {
+        NavTrigger::CapsLock => 0x14, // VK_CAPITAL
+        NavTrigger::Tab => 0x09,      // VK_TAB
+    }
+}
+
+/// Windows virtual key code of a key outside the typing block, which
+/// doesn't change with the layout
+fn named_key_vk(key: VirtualKey) -> Option<u32> {
+    let vk = match key {
+        VirtualKey::Escape => 0x1B,      // VK_ESCAPE
+        VirtualKey::CapsLock => 0x14,    // VK_CAPITAL
+        VirtualKey::Tab => 0x09,         // VK_TAB
+        VirtualKey::Enter => 0x0D,       // VK_RETURN
+        VirtualKey::Backspace => 0x08,   // VK_BACK
+        VirtualKey::Delete => 0x2E,      // VK_DELETE
+        VirtualKey::Insert => 0x2D,      // VK_INSERT
+        VirtualKey::Home => 0x24,        // VK_HOME
+        VirtualKey::End => 0x23,         // VK_END
+        VirtualKey::PageUp => 0x21,      // VK_PRIOR
+        VirtualKey::PageDown => 0x22,    // VK_NEXT
+        VirtualKey::Left => 0x25,        // VK_LEFT
+        VirtualKey::Right => 0x27,       // VK_RIGHT
+        VirtualKey::Up => 0x26,          // VK_UP
+        VirtualKey::Down => 0x28,        // VK_DOWN
+        VirtualKey::Menu => 0x5D,        // VK_APPS
+        VirtualKey::ScrollLock => 0x91,  // VK_SCROLL
+        VirtualKey::Pause => 0x13,       // VK_PAUSE
+        VirtualKey::PrintScreen => 0x2C, // VK_SNAPSHOT
+        _ => return None,
+    };
+    Some(vk)
+}
+
+/// Windows virtual key code of a key named in `[remap]`; none for Compose
+fn remap_vk(key: RemapKey) -> Option<u32> {
+    let vk = match key {
+        RemapKey::LeftCtrl => 0xA2,   // VK_LCONTROL
+        RemapKey::RightCtrl => 0xA3,  // VK_RCONTROL
+        RemapKey::LeftAlt => 0xA4,    // VK_LMENU
+        RemapKey::RightAlt => 0xA5,   // VK_RMENU
+        RemapKey::LeftShift => 0xA0,  // VK_LSHIFT
+        RemapKey::RightShift => 0xA1, // VK_RSHIFT
+        RemapKey::LeftWin => 0x5B,    // VK_LWIN
+        RemapKey::RightWin => 0x5C,   // VK_RWIN
+        key => return key.virtual_key().and_then(named_key_vk),
+    };
+    Some(vk)
+}
+
+/// Key named in `[remap]` for a virtual key code, if any
+fn remap_key(vk: u32) -> Option<RemapKey> {
+    RemapKey::all().find(|&key| remap_vk(key) == Some(vk))
+}
+
 /// Check if shift is currently pressed
 fn is_shift_pressed() -> bool {
     unsafe {
@@ -71,210 +423,2335 @@ fn is_shift_pressed() -> bool {
     }
 }
 
-/// Inject a Unicode character using SendInput
-fn inject_char(c: char) {
-    IS_INJECTING.with(|injecting| {
-        *injecting.borrow_mut() = true;
-    });
+/// Modifiers currently held
+///
+/// Layouts with AltGr report the right Alt as LCtrl+RAlt; on layouts without
+/// one (plain US) the right Alt is just another Alt.
+fn current_modifiers() -> Modifiers {
+    Modifiers {
+        shift: is_shift_pressed(),
+        alt: is_key_pressed(VK_MENU),
+        altgr: is_key_pressed(VK_RMENU) && is_key_pressed(VK_LCONTROL),
+        caps_lock: is_caps_lock_on(),
+    }
+}
 
-    let mut inputs: Vec<INPUT> = Vec::new();
+/// Check if Caps Lock is toggled on
+fn is_caps_lock_on() -> bool {
+    unsafe { GetKeyState(VK_CAPITAL.0 as i32) & 1 != 0 }
+}
 
-    // Key down
-    inputs.push(INPUT {
-        r#type: INPUT_KEYBOARD,
-        Anonymous: INPUT_0 {
-            ki: KEYBDINPUT {
-                wVk: windows::Win32::UI::Input::KeyboardAndMouse::VIRTUAL_KEY(0),
-                wScan: c as u16,
-                dwFlags: KEYEVENTF_UNICODE,
-                time: 0,
-                dwExtraInfo: 0,
-            },
-        },
-    });
+/// Check if a shortcut modifier (Ctrl, Alt, Win) is currently pressed
+fn is_shortcut_modifier_pressed() -> bool {
+    unsafe {
+        GetAsyncKeyState(VK_CONTROL.0 as i32) < 0
+            || GetAsyncKeyState(VK_MENU.0 as i32) < 0
+            || GetAsyncKeyState(VK_LWIN.0 as i32) < 0
+            || GetAsyncKeyState(VK_RWIN.0 as i32) < 0
+    }
+}
 
-    // Key up
-    inputs.push(INPUT {
-        r#type: INPUT_KEYBOARD,
-        Anonymous: INPUT_0 {
-            ki: KEYBDINPUT {
-                wVk: windows::Win32::UI::Input::KeyboardAndMouse::VIRTUAL_KEY(0),
-                wScan: c as u16,
-                dwFlags: KEYEVENTF_UNICODE | KEYEVENTF_KEYUP,
-                time: 0,
-                dwExtraInfo: 0,
-            },
-        },
-    });
+/// Check if a virtual key code is a modifier or lock key
+fn is_modifier_vk(vk: u32) -> bool {
+    matches!(vk, 0x10..=0x12 | 0x14 | 0x5B | 0x5C | 0xA0..=0xA5)
+}
 
-    unsafe {
-        SendInput(&inputs, std::mem::size_of::<INPUT>() as i32);
+/// Check if a single virtual key is currently pressed
+fn is_key_pressed(vk: VIRTUAL_KEY) -> bool {
+    unsafe { GetAsyncKeyState(vk.0 as i32) < 0 }
+}
+
+/// Macro slot for a function key (F1 -> 1, ..., F12 -> 12)
+fn macro_slot(vk: u32) -> Option<u8> {
+    // VK_F1 is 0x70
+    (0x70..0x70 + SLOT_COUNT as u32)
+        .contains(&vk)
+        .then(|| (vk - 0x6F) as u8)
+}
+
+/// Handle Ctrl+Alt+Space, which pauses and resumes GhostKeys
+///
+/// Returns true if the key was the hotkey and must be blocked. Works while
+/// paused, so it's the way back when the tray icon is hidden.
+fn handle_pause_hotkey(vk: u32, repeat: bool) -> bool {
+    // VK_SPACE is 0x20
+    if vk != 0x20
+        || !with_context(|context| context.settings().pause_hotkey).unwrap_or(false)
+        || !is_key_pressed(VK_CONTROL)
+        || !is_key_pressed(VK_MENU)
+        || is_shift_pressed()
+    {
+        return false;
+    }
+    if !repeat {
+        toggle_from_hotkey();
     }
+    true
+}
 
-    IS_INJECTING.with(|injecting| {
-        *injecting.borrow_mut() = false;
-    });
+/// Pause or resume from a hotkey and say which
+fn toggle_from_hotkey() {
+    // A timed pause ends here instead
+    stop_pause_timer();
+    if let Some(state) = shared_state() {
+        let _ = state.set_paused_until(None);
+        let status = match state.toggle_mode_because(Cause::Hotkey) {
+            Ok(OperationMode::Active) => Text::StatusActive,
+            _ => Text::StatusPaused,
+        };
+        let _ = state.notify(Severity::Info, tr(status));
+    }
 }
 
-/// Inject multiple Unicode characters
-fn inject_chars(chars: &[char]) {
-    for &c in chars {
-        inject_char(c);
+/// Key a chord can end in, by virtual key code
+fn hotkey_key(vk: u32) -> Option<Key> {
+    let key = match vk {
+        // 'A'..'Z' and '0'..'9'
+        0x41..=0x5A => Key::Char((vk as u8 + 0x20) as char),
+        0x30..=0x39 => Key::Char(vk as u8 as char),
+        // VK_F1..VK_F24
+        0x70..=0x87 => Key::Function((vk - 0x6F) as u8),
+        0x20 => Key::Space,
+        0x0D => Key::Enter,
+        0x09 => Key::Tab,
+        0x1B => Key::Escape,
+        0x08 => Key::Backspace,
+        0x2D => Key::Insert,
+        0x2E => Key::Delete,
+        0x24 => Key::Home,
+        0x23 => Key::End,
+        0x21 => Key::PageUp,
+        0x22 => Key::PageDown,
+        0x25 => Key::Left,
+        0x26 => Key::Up,
+        0x27 => Key::Right,
+        0x28 => Key::Down,
+        0x13 => Key::Pause,
+        0x2C => Key::PrintScreen,
+        // VK_OEM_* keys, by what they type on a US keyboard
+        0xBA => Key::Char(';'),
+        0xDE => Key::Char('\''),
+        0xDB => Key::Char('['),
+        0xDD => Key::Char(']'),
+        0xDC => Key::Char('\\'),
+        0xBF => Key::Char('/'),
+        0xBD => Key::Char('-'),
+        0xBB => Key::Char('='),
+        0xBC => Key::Char(','),
+        0xBE => Key::Char('.'),
+        0xC0 => Key::Char('`'),
+        _ => return None,
+    };
+    Some(key)
+}
+
+/// Handle a chord from `[hotkeys.custom]`
+///
+/// Returns true if the key ended one and must be blocked. Repeats of a held
+/// hotkey are blocked without acting again; while paused, only `toggle`
+/// hotkeys work.
+fn handle_custom_hotkey(vk: u32, repeat: bool, paused: bool) -> bool {
+    if with_context(|context| context.hotkeys().is_empty()).unwrap_or(true) {
+        return false;
+    }
+    let Some(key) = hotkey_key(vk) else {
+        return false;
+    };
+    let chord = Chord {
+        ctrl: is_key_pressed(VK_CONTROL),
+        alt: is_key_pressed(VK_MENU),
+        shift: is_shift_pressed(),
+        win: is_key_pressed(VK_LWIN) || is_key_pressed(VK_RWIN),
+        key,
+    };
+    let action = with_context(|context| context.hotkeys().find(&chord).cloned()).flatten();
+    let Some(action) = action else {
+        return false;
+    };
+    if paused && !action.works_while_paused() {
+        return false;
+    }
+    if !repeat {
+        run_hotkey_action(action);
     }
+    true
 }
 
-/// Low-level keyboard procedure callback
-unsafe extern "system" fn low_level_keyboard_proc(
-    code: i32,
-    wparam: WPARAM,
-    lparam: LPARAM,
-) -> LRESULT {
-    // If code < 0, pass to next hook
-    if code < 0 {
-        return CallNextHookEx(None, code, wparam, lparam);
+/// Carry out a custom hotkey's action; anything that opens a window or
+/// reads a file is left to the message loop
+fn run_hotkey_action(action: Action) {
+    let post = |message| unsafe {
+        let _ = PostThreadMessageW(GetCurrentThreadId(), message, WPARAM(0), LPARAM(0));
+    };
+    match action {
+        Action::Toggle => toggle_from_hotkey(),
+        Action::PauseFor(minutes) => pause_for(minutes),
+        Action::SwitchLayout(name) => {
+            PENDING_LAYOUT.with(|pending| *pending.borrow_mut() = Some(name));
+            post(WM_SWITCH_LAYOUT);
+        }
+        Action::OpenPalette => post(WM_OPEN_PALETTE),
+        Action::LiteralNextKey => {
+            with_context(HookContext::arm_literal_next);
+        }
+        Action::RunMacro(name) => {
+            let text = shared_state()
+                .and_then(|state| state.get_config().ok())
+                .and_then(|config| config.macros.slots.get(&name).cloned());
+            if let Some(text) = text {
+                report_injection_failure(inject_text(&text));
+            }
+        }
     }
+}
 
-    // Check if we're injecting (avoid recursion)
-    let is_injecting = IS_INJECTING.with(|injecting| *injecting.borrow());
-    if is_injecting {
-        return CallNextHookEx(None, code, wparam, lparam);
+/// Pause for `minutes`, then resume unless something else changed the mode
+fn pause_for(minutes: u32) {
+    let Some(state) = shared_state() else {
+        return;
+    };
+    // Set first, so the session saved for the change has it
+    let seconds = u64::from(minutes) * 60;
+    let _ = state.set_paused_until(Some(session::now() + seconds));
+    if state.set_mode_because(OperationMode::Passthrough, Cause::Hotkey).is_err() {
+        return;
     }
+    let _ = state.notify(Severity::Info, tr_args(Text::StatusPausedFor, &[&minutes]));
+    start_pause_timer(seconds);
+}
 
-    // Only process key down events
-    let msg = wparam.0 as u32;
-    if msg != WM_KEYDOWN && msg != WM_SYSKEYDOWN {
-        return CallNextHookEx(None, code, wparam, lparam);
+/// Carry on with a timed pause restored from the last session
+fn resume_pause_timer() {
+    let Some(state) = shared_state() else {
+        return;
+    };
+    if let (true, Ok(Some(until))) = (state.is_paused(), state.paused_until()) {
+        start_pause_timer(until.saturating_sub(session::now()).max(1));
     }
+}
 
-    // Get key info from lparam
-    let kb_struct = &*(lparam.0 as *const KBDLLHOOKSTRUCT);
-    let vk_code = kb_struct.vkCode;
+/// End the pause after `seconds`, replacing a timer already running
+fn start_pause_timer(seconds: u64) {
+    stop_pause_timer();
+    let milliseconds = seconds.saturating_mul(1000).min(u64::from(u32::MAX)) as u32;
+    let timer = unsafe { SetTimer(HWND::default(), 0, milliseconds, None) };
+    if timer != 0 {
+        PAUSE_TIMER.with(|pause| pause.set(Some(timer)));
+    }
+}
 
-    // Convert to our VirtualKey
-    let virtual_key = vk_to_virtual_key(vk_code);
+/// Handle the timer started by `pause_for`; returns false for other timers
+///
+/// Only a pause still standing from the hotkey (or restored from the last
+/// session) is ended: one the tray, a rule or a command took over is left
+/// alone.
+fn pause_timed_out(timer: usize) -> bool {
+    if PAUSE_TIMER.with(Cell::get) != Some(timer) {
+        return false;
+    }
+    stop_pause_timer();
+    let Some(state) = shared_state() else {
+        return true;
+    };
+    let _ = state.set_paused_until(None);
+    let by_hotkey = matches!(state.last_mode_cause(), Ok(Some(Cause::Hotkey | Cause::Restored)));
+    if state.is_paused()
+        && by_hotkey
+        && state.set_mode_because(OperationMode::Active, Cause::Hotkey).is_ok()
+    {
+        let _ = state.notify(Severity::Info, tr(Text::StatusActive));
+    }
+    true
+}
 
-    // Skip keys we don't handle
-    if matches!(virtual_key, VirtualKey::Other) {
-        return CallNextHookEx(None, code, wparam, lparam);
+/// Cancel the end of a timed pause
+fn stop_pause_timer() {
+    if let Some(timer) = PAUSE_TIMER.with(Cell::take) {
+        unsafe {
+            let _ = KillTimer(HWND::default(), timer);
+        }
     }
+}
 
-    // Check shift state
-    let shift = is_shift_pressed();
+/// Load the layout a `switch_layout:` hotkey asked for and use it; runs
+/// from the hook thread's message loop
+fn switch_layout() {
+    let Some(name) = PENDING_LAYOUT.with(|pending| pending.borrow_mut().take()) else {
+        return;
+    };
+    let Some(state) = shared_state() else {
+        return;
+    };
+    let mapper = hotkeys::load_layout(&name)
+        .and_then(|layout| state.set_layout(layout))
+        .and_then(|()| build_mapper(&state));
+    match mapper {
+        Ok(mapper) => {
+            with_context(|context| context.set_mapper(mapper));
+            let _ = state.notify(Severity::Info, tr_args(Text::LayoutSwitched, &[&name]));
+        }
+        Err(e) => {
+            let _ = state.notify(Severity::Warning, e.to_string());
+        }
+    }
+}
 
-    // Process through mapper
-    let action = MAPPER.with(|mapper| {
-        if let Some(ref mut m) = *mapper.borrow_mut() {
-            m.process_key(virtual_key, shift)
-        } else {
-            KeyAction::Pass
+/// Rebuild the custom hotkeys from the current config and warn about
+/// chords left out that weren't reported before
+fn reload_hotkeys() {
+    let Some(Ok(conflicts)) = with_context(HookContext::reload_hotkeys) else {
+        return;
+    };
+    let Some(state) = shared_state() else {
+        return;
+    };
+    REPORTED_CONFLICTS.with(|reported| {
+        let mut reported = reported.borrow_mut();
+        for conflict in conflicts.iter().filter(|conflict| !reported.contains(conflict)) {
+            let _ = state.notify(Severity::Warning, conflict.to_string());
         }
+        *reported = conflicts;
     });
+}
 
-    // Handle the action
-    match action {
-        KeyAction::Pass => CallNextHookEx(None, code, wparam, lparam),
-        KeyAction::Suppress => LRESULT(1), // Block the key
-        KeyAction::Replace(c) => {
-            inject_char(c);
-            LRESULT(1) // Block original key
-        }
-        KeyAction::ReplaceMultiple(chars) => {
-            inject_chars(&chars);
-            LRESULT(1) // Block original key
+/// Handle Ctrl+Alt+., which opens the character palette
+///
+/// Returns true if the key was the hotkey and must be blocked. The window is
+/// opened from the message loop once the hook callback has returned.
+fn handle_palette_hotkey(vk: u32, repeat: bool) -> bool {
+    // VK_OEM_PERIOD is 0xBE
+    if vk != 0xBE
+        || !with_context(|context| context.settings().palette_hotkey).unwrap_or(false)
+        || !is_key_pressed(VK_CONTROL)
+        || !is_key_pressed(VK_MENU)
+        || is_shift_pressed()
+    {
+        return false;
+    }
+    if !repeat {
+        unsafe {
+            let _ = PostThreadMessageW(GetCurrentThreadId(), WM_OPEN_PALETTE, WPARAM(0), LPARAM(0));
         }
     }
+    true
 }
 
+/// Handle Ctrl+Alt+Z, which takes back the last replacement
+///
+/// Returns true if the key was the hotkey and must be blocked, even when
+/// there's nothing left to undo.
+fn handle_undo_hotkey(vk: u32) -> bool {
+    // 'Z' is 0x5A
+    if vk != 0x5A
+        || !with_context(|context| context.settings().undo_hotkey).unwrap_or(false)
+        || !is_key_pressed(VK_CONTROL)
+        || !is_key_pressed(VK_MENU)
+        || is_shift_pressed()
+    {
+        return false;
+    }
+    // Auto-repeats keep undoing, like a held Ctrl+Z
+    let action = with_context(HookContext::undo_replacement).flatten();
+    if let Some(KeyAction::Rewrite { backspaces, chars }) = action {
+        report_injection_failure(inject_backspaces(backspaces).and_then(|()| inject_chars(&chars)));
+        refresh_suggestions();
+    }
+    true
+}
 
-/// Windows keyboard interceptor using low-level keyboard hooks
-pub struct WindowsInterceptor {
-    running: Arc<AtomicBool>,
+/// Handle Ctrl+Alt+P, which switches to the next saved profile
+///
+/// Returns true if the key was the hotkey and must be blocked. The profile
+/// is read and the config saved from the message loop, once the hook
+/// callback has returned.
+fn handle_profile_hotkey(vk: u32, repeat: bool) -> bool {
+    // 'P' is 0x50
+    if vk != 0x50
+        || !with_context(|context| context.settings().profile_hotkey).unwrap_or(false)
+        || !is_key_pressed(VK_CONTROL)
+        || !is_key_pressed(VK_MENU)
+        || is_shift_pressed()
+    {
+        return false;
+    }
+    if !repeat {
+        unsafe {
+            let _ = PostThreadMessageW(GetCurrentThreadId(), WM_CYCLE_PROFILE, WPARAM(0), LPARAM(0));
+        }
+    }
+    true
 }
 
-impl WindowsInterceptor {
-    /// Create a new Windows interceptor
-    pub fn new() -> Self {
-        Self {
-            running: Arc::new(AtomicBool::new(false)),
+/// Switch to the profile after the active one and use its layout; runs
+/// from the hook thread's message loop
+fn cycle_profile() {
+    let Some(state) = shared_state() else {
+        return;
+    };
+    let names = profile::list();
+    let active = state.get_config().ok().and_then(|config| config.profiles.active);
+    let Some(name) = profile::next(&names, active.as_deref()) else {
+        let folder = paths::profiles_dir().unwrap_or_default();
+        let _ = state.notify(Severity::Info, tr_args(Text::NoProfiles, &[&folder.display()]));
+        return;
+    };
+    let mapper = profile::switch(&state, name)
+        .and_then(|layout| state.set_layout(layout))
+        .and_then(|()| build_mapper(&state));
+    match mapper {
+        Ok(mapper) => {
+            with_context(|context| context.set_mapper(mapper));
+            let _ = state.notify(Severity::Info, tr_args(Text::ProfileSwitched, &[&name]));
         }
+        Err(e) => {
+            let _ = state.notify(Severity::Warning, e.to_string());
+        }
+    }
+}
+
+/// Open the character palette; runs from the hook thread's message loop
+fn open_palette() {
+    let Some(state) = shared_state() else {
+        return;
+    };
+    let type_text = |text: &str| report_injection_failure(inject_str(text));
+    if let Err(e) = palette_window::open(&state, type_text) {
+        let _ = state.notify(Severity::Warning, e.to_string());
+    }
+}
+
+/// Handle Alt+1..9, which picks a spelling from the suggestion popup
+///
+/// Returns true if the key picked one and must be blocked. Without a popup
+/// on screen Alt+digit reaches the application as usual.
+fn handle_suggestion_hotkey(vk: u32, repeat: bool) -> bool {
+    // '1' is 0x31
+    if !(0x31..=0x39).contains(&vk)
+        || !suggestion_popup::is_visible()
+        || !is_key_pressed(VK_MENU)
+        || is_key_pressed(VK_CONTROL)
+        || is_shift_pressed()
+    {
+        return false;
+    }
+    if repeat {
+        return true;
     }
+    let action = with_context(|context| context.accept_suggestion((vk - 0x31) as usize)).flatten();
+    let Some(KeyAction::Rewrite { backspaces, chars }) = action else {
+        return false;
+    };
+    report_injection_failure(inject_backspaces(backspaces).and_then(|()| inject_chars(&chars)));
+    refresh_suggestions();
+    true
+}
 
-    /// Install the low-level keyboard hook
-    fn install_hook(&self) -> Result<HHOOK> {
+/// Have the suggestion popup follow the word just typed
+///
+/// The popup is shown or hidden from the message loop once the hook callback
+/// has returned; nothing is posted while there's nothing to show or hide.
+fn refresh_suggestions() {
+    let offered = with_context(|context| context.suggestion().is_some()).unwrap_or(false);
+    if offered || suggestion_popup::is_visible() {
         unsafe {
-            let hook = SetWindowsHookExW(WH_KEYBOARD_LL, Some(low_level_keyboard_proc), HINSTANCE::default(), 0)
-                .map_err(|e| GhostKeysError::HookInstallError(format!("SetWindowsHookExW failed: {}", e)))?;
-            Ok(hook)
+            let thread = GetCurrentThreadId();
+            let _ = PostThreadMessageW(thread, WM_UPDATE_SUGGESTIONS, WPARAM(0), LPARAM(0));
         }
     }
 }
 
-impl Default for WindowsInterceptor {
-    fn default() -> Self {
-        Self::new()
+/// Show the spellings offered for the word just typed, or hide the popup;
+/// runs from the hook thread's message loop
+fn update_suggestions() {
+    let offered = with_context(|context| {
+        context.suggestion().map(|suggestion| suggestion.alternatives.clone())
+    });
+    match offered.flatten() {
+        Some(alternatives) => suggestion_popup::show(&alternatives),
+        None => suggestion_popup::hide(),
     }
 }
 
-impl KeyboardInterceptor for WindowsInterceptor {
-    fn start(&mut self, _state: SharedState) -> Result<()> {
-        if self.running.load(Ordering::SeqCst) {
-            return Err(GhostKeysError::HookInstallError(
-                "Interceptor already running".to_string(),
-            ));
+/// Flash the keys behind the character just typed, if it was remapped;
+/// runs from the hook thread's message loop
+fn show_lesson() {
+    let lesson = with_context(|context| {
+        let duration_ms = context.settings().learning.duration_ms;
+        context.take_lesson().map(|lesson| (lesson.to_string(), duration_ms))
+    });
+    if let Some((lesson, duration_ms)) = lesson.flatten() {
+        learning_osd::show(&lesson, duration_ms);
+    }
+}
+
+/// Accent waiting for its letter, if any
+fn pending_accent() -> Option<AccentType> {
+    with_context(|context| match context.mapper().state() {
+        MapperState::PendingAccent(accent) => Some(*accent),
+        _ => None,
+    })
+    .flatten()
+}
+
+/// Play the `[feedback]` sound if the key just mapped armed a dead key, time
+/// how long the accent then waits for its letter, and badge the tray icon
+/// while it does
+fn accent_feedback(before: Option<AccentType>) {
+    let after = pending_accent();
+    if after == before {
+        return;
+    }
+    if let Some(state) = shared_state() {
+        let _ = state.set_accent_pending(after.is_some());
+    }
+    stop_accent_timer();
+    let Some(accent) = after else {
+        return;
+    };
+    let feedback = with_context(|context| context.settings().feedback.is_enabled());
+    if !feedback.unwrap_or(false) {
+        return;
+    }
+    let sound = with_context(|context| context.settings().feedback.armed_sound(accent));
+    play(sound.unwrap_or_default());
+    let timer = unsafe { SetTimer(HWND::default(), 0, ACCENT_TIMEOUT.as_millis() as u32, None) };
+    if timer != 0 {
+        ACCENT_TIMER.with(|armed| armed.set(Some((accent, timer))));
+    }
+}
+
+/// Handle the timer started by `accent_feedback`; returns false for other timers
+///
+/// The timeout sound only plays if the same accent is still waiting.
+fn accent_timed_out(timer: usize) -> bool {
+    let Some((accent, id)) = ACCENT_TIMER.with(Cell::get) else {
+        return false;
+    };
+    if id != timer {
+        return false;
+    }
+    stop_accent_timer();
+    if pending_accent() == Some(accent) {
+        let sound = with_context(|context| context.settings().feedback.timeout_sound(accent));
+        play(sound.unwrap_or_default());
+    }
+    true
+}
+
+/// Stop timing the last armed accent
+fn stop_accent_timer() {
+    if let Some((_, timer)) = ACCENT_TIMER.with(Cell::take) {
+        unsafe {
+            let _ = KillTimer(HWND::default(), timer);
         }
+    }
+}
 
-        // Initialize the mapper
-        MAPPER.with(|mapper| {
-            *mapper.borrow_mut() = Some(Mapper::new());
-        });
+/// Play a cue; MessageBeep queues the sound and returns at once, so it's
+/// safe in the hook callback
+fn play(sound: Sound) {
+    let style = match sound {
+        Sound::None => return,
+        // 0xFFFFFFFF: the plain speaker beep
+        Sound::Beep => MESSAGEBOX_STYLE(0xFFFF_FFFF),
+        Sound::Default => MB_OK,
+        Sound::Asterisk => MB_ICONASTERISK,
+        Sound::Exclamation => MB_ICONEXCLAMATION,
+        Sound::Question => MB_ICONQUESTION,
+    };
+    unsafe {
+        let _ = MessageBeep(style);
+    }
+}
 
-        // Install the hook
-        let hook = self.install_hook()?;
-        
-        // Store in thread-local
-        HOOK_HANDLE.with(|h| {
-            *h.borrow_mut() = Some(hook);
-        });
-        
-        // Store raw handle in global for panic handler
-        if let Ok(mut global) = GLOBAL_HOOK_HANDLE.lock() {
-            *global = Some(hook.0 as isize);
+/// Handle Ctrl+Shift+F<n> (record) and Ctrl+Alt+F<n> (play)
+///
+/// Returns true if the key was a macro hotkey and must be blocked. Repeats of
+/// a held hotkey are blocked without toggling or replaying again.
+fn handle_macro_hotkey(slot: u8, repeat: bool) -> bool {
+    let ctrl = is_key_pressed(VK_CONTROL);
+    let alt = is_key_pressed(VK_MENU);
+    let shift = is_shift_pressed();
+
+    let records_macros = with_context(|context| context.records_macros()).unwrap_or(false);
+    if repeat && ctrl && (shift != alt) {
+        return records_macros;
+    }
+
+    if ctrl && shift && !alt {
+        let finished = with_context(|context| context.toggle_recording(slot)).flatten();
+        if let Some((slot, text)) = finished {
+            save_macro(slot, text);
         }
+        return records_macros;
+    }
 
-        self.running.store(true, Ordering::SeqCst);
-        Ok(())
+    if ctrl && alt && !shift {
+        if !records_macros {
+            return false;
+        }
+        let text = shared_state()
+            .and_then(|state| state.get_config().ok())
+            .and_then(|config| config.macros.slots.get(&slot_name(slot)).cloned());
+        if let Some(text) = text {
+            report_injection_failure(inject_text(&text));
+        }
+        return true;
     }
 
-    fn stop(&mut self) -> Result<()> {
-        if !self.running.load(Ordering::SeqCst) {
-            return Ok(());
+    false
+}
+
+/// Store a finished recording in the shared config and persist it
+fn save_macro(slot: u8, text: String) {
+    let Some(state) = shared_state() else {
+        return;
+    };
+
+    // Keep the config lock and file I/O out of the hook callback
+    thread::spawn(move || {
+        let Ok(mut config) = state.get_config() else {
+            return;
+        };
+        // Stopping an empty recording clears the slot
+        if text.is_empty() {
+            config.macros.slots.remove(&slot_name(slot));
+        } else {
+            config.macros.slots.insert(slot_name(slot), text);
         }
+        let _ = state.set_config(config.clone());
 
-        // Unhook
-        HOOK_HANDLE.with(|h| {
-            if let Some(hook) = h.borrow_mut().take() {
-                unsafe {
-                    let _ = UnhookWindowsHookEx(hook);
-                }
+        // Edit only [macros] in place, so the user's comments are kept
+        if let Err(e) = config.save_sections(&["macros"]) {
+            let _ = state.notify(Severity::Error, format!("Failed to save macro: {}", e));
+        }
+    });
+}
+
+/// Keep the autocorrect word and macro recording in sync with keys the
+/// mapper never sees
+fn track_unmapped_key(vk: u32) {
+    if is_modifier_vk(vk) {
+        return;
+    }
+    let key = match vk {
+        0x08 => UnmappedKey::Backspace, // VK_BACK
+        0x0D => UnmappedKey::Enter,     // VK_RETURN
+        0x09 => UnmappedKey::Tab,       // VK_TAB
+        _ => UnmappedKey::Other,
+    };
+    with_context(|context| context.unmapped_key(key));
+    refresh_suggestions();
+}
+
+/// Keyboard INPUT for a virtual key or, with KEYEVENTF_UNICODE, a UTF-16 unit
+///
+/// Tagged so the hook lets it through even when Windows calls the hook after
+/// the injection has finished.
+fn keyboard_input(vk: VIRTUAL_KEY, scan: u16, flags: KEYBD_EVENT_FLAGS) -> INPUT {
+    tagged_keyboard_input(vk, scan, flags, INJECTED_TAG)
+}
+
+/// Keyboard INPUT carrying a tag the hook can recognize in dwExtraInfo
+fn tagged_keyboard_input(vk: VIRTUAL_KEY, scan: u16, flags: KEYBD_EVENT_FLAGS, tag: usize) -> INPUT {
+    INPUT {
+        r#type: INPUT_KEYBOARD,
+        Anonymous: INPUT_0 {
+            ki: KEYBDINPUT {
+                wVk: vk,
+                wScan: scan,
+                dwFlags: flags,
+                time: 0,
+                dwExtraInfo: tag,
+            },
+        },
+    }
+}
+
+/// Injects synthetic key presses with SendInput (the default backend)
+struct SendInputInjector;
+
+impl SendInputInjector {
+    /// Send inputs, tagged so our own hook lets them through (see
+    /// `INJECTED_TAG`)
+    ///
+    /// SendInput returns how many events it inserted; if it stops short, the
+    /// rest is retried once before giving up.
+    fn send(inputs: &[INPUT]) -> Result<()> {
+        let mut sent = 0;
+        for _ in 0..2 {
+            sent += unsafe { SendInput(&inputs[sent..], std::mem::size_of::<INPUT>() as i32) } as usize;
+            if sent >= inputs.len() {
+                break;
             }
-        });
-        
-        // Clear global handle
-        if let Ok(mut global) = GLOBAL_HOOK_HANDLE.lock() {
-            *global = None;
         }
 
-        // Clear mapper
-        MAPPER.with(|mapper| {
-            *mapper.borrow_mut() = None;
-        });
+        if sent >= inputs.len() {
+            Ok(())
+        } else if is_foreground_elevated() {
+            // UIPI drops the input without saying so; elevation is the tell
+            Err(GhostKeysError::InjectionBlockedByUipi {
+                code: unsafe { GetLastError() }.0 as i32,
+            })
+        } else {
+            Err(GhostKeysError::KeyInjectionError(format!(
+                "SendInput inserted {} of {} events",
+                sent,
+                inputs.len()
+            )))
+        }
+    }
+}
 
-        self.running.store(false, Ordering::SeqCst);
+impl Injector for SendInputInjector {
+    fn inject_chars(&self, chars: &[char]) -> Result<()> {
+        for &c in chars {
+            // Characters outside the BMP are sent as a surrogate pair
+            let mut units = [0u16; 2];
+            let inputs: Vec<INPUT> = c
+                .encode_utf16(&mut units)
+                .iter()
+                .flat_map(|&unit| {
+                    [
+                        keyboard_input(VIRTUAL_KEY(0), unit, KEYEVENTF_UNICODE),
+                        keyboard_input(VIRTUAL_KEY(0), unit, KEYEVENTF_UNICODE | KEYEVENTF_KEYUP),
+                    ]
+                })
+                .collect();
+            Self::send(&inputs)?;
+        }
         Ok(())
     }
 
-    fn is_running(&self) -> bool {
-        self.running.load(Ordering::SeqCst)
+    fn inject_backspaces(&self, count: usize) -> Result<()> {
+        let inputs: Vec<INPUT> = (0..count)
+            .flat_map(|_| {
+                [
+                    keyboard_input(VK_BACK, 0, KEYBD_EVENT_FLAGS(0)),
+                    keyboard_input(VK_BACK, 0, KEYEVENTF_KEYUP),
+                ]
+            })
+            .collect();
+        Self::send(&inputs)
+    }
+}
+
+/// Posts WM_CHAR messages straight to the focused window
+///
+/// For applications that ignore KEYEVENTF_UNICODE input (some Java/Swing and
+/// old Win32 apps). The messages bypass the input queue, so the hook never
+/// sees them.
+struct WmCharInjector;
+
+impl WmCharInjector {
+    /// Post UTF-16 units as WM_CHAR messages
+    fn post(units: impl IntoIterator<Item = u16>) -> Result<()> {
+        let hwnd = focused_window().ok_or_else(|| {
+            GhostKeysError::KeyInjectionError("no focused window to post WM_CHAR to".to_string())
+        })?;
+        for unit in units {
+            unsafe {
+                // lParam: repeat count 1
+                PostMessageW(hwnd, WM_CHAR, WPARAM(unit as usize), LPARAM(1)).map_err(|e| {
+                    GhostKeysError::KeyInjectionError(format!("PostMessageW failed: {}", e))
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Injector for WmCharInjector {
+    fn inject_chars(&self, chars: &[char]) -> Result<()> {
+        self.inject_str(&chars.iter().collect::<String>())
+    }
+
+    fn inject_str(&self, text: &str) -> Result<()> {
+        Self::post(text.encode_utf16())
+    }
+
+    fn inject_backspaces(&self, count: usize) -> Result<()> {
+        // Edit controls treat a backspace character as a delete-left
+        Self::post(std::iter::repeat_n(0x08, count))
+    }
+}
+
+/// Window with keyboard focus in the foreground application
+fn focused_window() -> Option<HWND> {
+    unsafe {
+        let foreground = GetForegroundWindow();
+        if foreground.is_invalid() {
+            return None;
+        }
+
+        let thread = GetWindowThreadProcessId(foreground, None);
+        let mut info = GUITHREADINFO {
+            cbSize: std::mem::size_of::<GUITHREADINFO>() as u32,
+            ..Default::default()
+        };
+        if GetGUIThreadInfo(thread, &mut info).is_ok() && !info.hwndFocus.is_invalid() {
+            Some(info.hwndFocus)
+        } else {
+            Some(foreground)
+        }
+    }
+}
+
+/// Process ID of the foreground application
+fn foreground_pid() -> Option<u32> {
+    let mut pid = 0u32;
+    unsafe {
+        GetWindowThreadProcessId(GetForegroundWindow(), Some(&mut pid));
+    }
+    (pid != 0).then_some(pid)
+}
+
+/// Executable file name of the foreground application (e.g., "javaw.exe")
+///
+/// Looked up once per foreground window on the hook thread, like
+/// `is_foreground_elevated`, since per-app rules ask on every injection.
+fn foreground_process_name() -> Option<String> {
+    let hwnd = unsafe { GetForegroundWindow() }.0 as isize;
+    if let Some((cached_hwnd, app)) =
+        with_context(|context| context.foreground_mut().app.clone()).flatten()
+    {
+        if cached_hwnd == hwnd {
+            return app;
+        }
+    }
+
+    let app = foreground_pid().and_then(process_name);
+    with_context(|context| context.foreground_mut().app = Some((hwnd, app.clone())));
+    app
+}
+
+/// Executable file name of a process
+fn process_name(pid: u32) -> Option<String> {
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid).ok()?;
+        let mut buffer = [0u16; 260];
+        let mut len = buffer.len() as u32;
+        let queried = QueryFullProcessImageNameW(
+            process,
+            PROCESS_NAME_WIN32,
+            PWSTR(buffer.as_mut_ptr()),
+            &mut len,
+        );
+        let _ = CloseHandle(process);
+        queried.ok()?;
+
+        let path = String::from_utf16_lossy(&buffer[..len as usize]);
+        path.rsplit('\\').next().map(str::to_string)
+    }
+}
+
+/// The foreground window, with its class and title only if `details`
+pub fn foreground_window(details: bool) -> WindowInfo {
+    let app = foreground_process_name();
+    if !details {
+        return WindowInfo { app, ..WindowInfo::default() };
+    }
+
+    let text = |buffer: &[u16], len: i32| {
+        (len > 0).then(|| String::from_utf16_lossy(&buffer[..len as usize]))
+    };
+    unsafe {
+        let hwnd = GetForegroundWindow();
+        let mut class = [0u16; 256];
+        let class_len = GetClassNameW(hwnd, &mut class);
+        // Other processes' windows aren't sent WM_GETTEXT, so a hung window
+        // can't stall the hook
+        let mut title = [0u16; 512];
+        let title_len = GetWindowTextW(hwnd, &mut title);
+        WindowInfo { app, class: text(&class, class_len), title: text(&title, title_len) }
+    }
+}
+
+/// Integrity level RIDs (winnt.h)
+const MEDIUM_INTEGRITY: u32 = 0x2000;
+const HIGH_INTEGRITY: u32 = 0x3000;
+
+/// Mandatory integrity level of a process (e.g., 0x2000 medium, 0x3000 high)
+fn process_integrity_level(process: HANDLE) -> Option<u32> {
+    unsafe {
+        let mut token = HANDLE::default();
+        OpenProcessToken(process, TOKEN_QUERY, &mut token).ok()?;
+
+        // First call reports the required size; u64s keep the buffer aligned
+        let mut len = 0u32;
+        let _ = GetTokenInformation(token, TokenIntegrityLevel, None, 0, &mut len);
+        let mut buffer = vec![0u64; (len as usize).div_ceil(8)];
+        let queried = GetTokenInformation(
+            token,
+            TokenIntegrityLevel,
+            Some(buffer.as_mut_ptr() as *mut std::ffi::c_void),
+            len,
+            &mut len,
+        );
+        let _ = CloseHandle(token);
+        queried.ok()?;
+
+        // The level is the last sub-authority of the label SID
+        let label = &*(buffer.as_ptr() as *const TOKEN_MANDATORY_LABEL);
+        let count = *GetSidSubAuthorityCount(label.Label.Sid);
+        if count == 0 {
+            return None;
+        }
+        Some(*GetSidSubAuthority(label.Label.Sid, count as u32 - 1))
+    }
+}
+
+/// Integrity level GhostKeys itself runs at
+fn own_integrity_level() -> u32 {
+    static LEVEL: OnceLock<u32> = OnceLock::new();
+    *LEVEL.get_or_init(|| {
+        process_integrity_level(unsafe { GetCurrentProcess() }).unwrap_or(MEDIUM_INTEGRITY)
+    })
+}
+
+/// Check if the foreground application runs at a higher integrity level than
+/// GhostKeys, in which case UIPI silently blocks injected input
+///
+/// A process whose token we aren't allowed to open is assumed elevated,
+/// since being denied is itself a sign of elevation. The result is cached
+/// per foreground window.
+fn is_foreground_elevated() -> bool {
+    let hwnd = unsafe { GetForegroundWindow() };
+    if let Some((cached_hwnd, elevated)) =
+        with_context(|context| context.foreground_mut().elevated).flatten()
+    {
+        if cached_hwnd == hwnd.0 as isize {
+            return elevated;
+        }
+    }
+
+    let own = own_integrity_level();
+    let elevated = match foreground_pid() {
+        Some(pid) => {
+            let level = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid) }
+                .ok()
+                .and_then(|process| {
+                    let level = process_integrity_level(process);
+                    unsafe {
+                        let _ = CloseHandle(process);
+                    }
+                    level
+                });
+            match level {
+                Some(level) => level > own,
+                None => own < HIGH_INTEGRITY,
+            }
+        }
+        None => false,
+    };
+
+    with_context(|context| context.foreground_mut().elevated = Some((hwnd.0 as isize, elevated)));
+    if elevated {
+        warn_elevated_foreground();
+    }
+    elevated
+}
+
+/// Tell the user why remapped characters won't appear in an elevated window
+fn warn_elevated_foreground() {
+    let app = foreground_process_name().unwrap_or_else(|| "The active window".to_string());
+    let passthrough =
+        with_context(|context| context.settings().injection.passthrough_elevated).unwrap_or(false);
+    let message = if passthrough {
+        format!("{} runs as administrator; keys pass through unmapped there", app)
+    } else {
+        format!(
+            "{} runs as administrator, so Windows blocks remapped characters. \
+             Run GhostKeys as administrator, or set passthrough_elevated = true under [injection]",
+            app
+        )
+    };
+
+    if let Some(state) = shared_state() {
+        let _ = state.notify(Severity::Warning, message);
+    }
+}
+
+/// Executable names of all running processes
+fn running_process_names() -> Vec<String> {
+    let mut names = Vec::new();
+    unsafe {
+        let Ok(snapshot) = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) else {
+            return names;
+        };
+        let mut entry = PROCESSENTRY32W {
+            dwSize: std::mem::size_of::<PROCESSENTRY32W>() as u32,
+            ..Default::default()
+        };
+        let mut more = Process32FirstW(snapshot, &mut entry).is_ok();
+        while more {
+            let end = entry.szExeFile.iter().position(|&unit| unit == 0).unwrap_or(entry.szExeFile.len());
+            names.push(String::from_utf16_lossy(&entry.szExeFile[..end]));
+            more = Process32NextW(snapshot, &mut entry).is_ok();
+        }
+        let _ = CloseHandle(snapshot);
+    }
+    names
+}
+
+/// Check for a registry "Scancode Map", which remaps keys below every hook
+/// (SharpKeys and KeyTweak write one)
+fn has_scancode_map() -> bool {
+    let mut size = 0u32;
+    let status = unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            w!("SYSTEM\\CurrentControlSet\\Control\\Keyboard Layout"),
+            w!("Scancode Map"),
+            RRF_RT_REG_BINARY,
+            None,
+            None,
+            Some(&mut size),
+        )
+    };
+    status == ERROR_SUCCESS && size > 0
+}
+
+/// Warn about other software remapping the keyboard
+fn warn_conflicts(state: &SharedState) {
+    let processes = running_process_names();
+    for name in conflicts::detect(processes.iter().map(String::as_str)) {
+        let _ = state.notify(Severity::Warning, conflicts::warning(name));
+    }
+    if has_scancode_map() {
+        let _ = state.notify(
+            Severity::Warning,
+            "A registry Scancode Map (e.g., from SharpKeys) remaps keys before GhostKeys sees them; \
+             remove it if characters land on unexpected keys",
+        );
+    }
+}
+
+/// `ghostkeys doctor` checks: keyboard hook and injection, other remappers
+/// and the Windows keyboard layout
+pub fn doctor_checks() -> Vec<Check> {
+    vec![hook_check(), conflicts_check(), scancode_map_check(), os_layout_check()]
+}
+
+/// Install a hook of our own and run the self-test through it
+fn hook_check() -> Check {
+    const NAME: &str = "Keyboard hook and typing";
+    // Paused, so keys pressed during the test reach applications unchanged
+    let state = SharedState::new();
+    let tested = state
+        .set_mode_because(OperationMode::Passthrough, Cause::SelfTest)
+        .and_then(|()| {
+            let mut interceptor = WindowsInterceptor::new();
+            interceptor.start(state)?;
+            let result = interceptor.self_test();
+            interceptor.stop()?;
+            result
+        });
+    match tested {
+        Ok(()) => Check::pass(NAME, "the hook sees keystrokes and Unicode characters can be typed"),
+        Err(e) => Check::from_error(NAME, &e),
+    }
+}
+
+/// Other remappers among running processes
+fn conflicts_check() -> Check {
+    const NAME: &str = "Conflicting software";
+    let processes = running_process_names();
+    let remappers = conflicts::detect(processes.iter().map(String::as_str));
+    if remappers.is_empty() {
+        Check::pass(NAME, "no other keyboard remapper is running")
+    } else {
+        Check::warn(
+            NAME,
+            format!("{} running", remappers.join(", ")),
+            "Exit them, or pause GhostKeys while using them",
+        )
+    }
+}
+
+/// A registry Scancode Map remapping keys before any hook
+fn scancode_map_check() -> Check {
+    const NAME: &str = "Scancode Map";
+    if has_scancode_map() {
+        Check::warn(
+            NAME,
+            "the registry remaps keys before GhostKeys sees them (e.g., SharpKeys or KeyTweak)",
+            "Remove the mapping in the tool that created it, or delete the \"Scancode Map\" value \
+             under HKLM\\SYSTEM\\CurrentControlSet\\Control\\Keyboard Layout, then restart Windows",
+        )
+    } else {
+        Check::pass(NAME, "no keys remapped in the registry")
+    }
+}
+
+/// The foreground window (the terminal running the doctor) uses the OS's ABNT2 layout
+fn os_layout_check() -> Check {
+    const NAME: &str = "OS keyboard layout";
+    let findings = layout_findings();
+    if findings.is_empty() {
+        Check::pass(NAME, "no ABNT2 or US-International layout installed")
+    } else {
+        let details: Vec<String> = findings.iter().map(Finding::to_string).collect();
+        Check::warn(
+            NAME,
+            details.join("; "),
+            "Switch to English (US) with Win+Space or from the tray, and remove the other \
+             layouts under Settings > Time & language > Language & region",
+        )
+    }
+}
+
+/// Keyboard layouts the user can switch between with Win+Space
+fn installed_layouts() -> Vec<isize> {
+    unsafe {
+        let count = GetKeyboardLayoutList(None).max(0) as usize;
+        let mut layouts = vec![HKL::default(); count];
+        let copied = GetKeyboardLayoutList(Some(&mut layouts)).max(0) as usize;
+        layouts.truncate(copied);
+        layouts.into_iter().map(|hkl| hkl.0 as isize).collect()
+    }
+}
+
+/// Installed layouts that conflict with emulation, the foreground window's first
+pub fn layout_findings() -> Vec<Finding> {
+    let active = foreground_layout();
+    let mut findings: Vec<Finding> = Vec::new();
+    for hkl in std::iter::once(active).chain(installed_layouts()) {
+        let Some(conflict) = classify_layout(hkl) else {
+            continue;
+        };
+        let name = os_layout::windows_name(conflict);
+        if !findings.iter().any(|finding| finding.name == name) {
+            findings.push(Finding { name: name.to_string(), conflict, active: hkl == active });
+        }
+    }
+    findings
+}
+
+/// Theme of the taskbar, where the tray icon sits; dark unless Windows says
+/// it's light (Windows 10 1903 and later can have a light taskbar)
+pub fn system_theme() -> Theme {
+    let mut light = 0u32;
+    let mut size = std::mem::size_of::<u32>() as u32;
+    let status = unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            w!("Software\\Microsoft\\Windows\\CurrentVersion\\Themes\\Personalize"),
+            w!("SystemUsesLightTheme"),
+            RRF_RT_REG_DWORD,
+            None,
+            Some(&mut light as *mut u32 as *mut std::ffi::c_void),
+            Some(&mut size),
+        )
+    };
+    if status == ERROR_SUCCESS && light != 0 {
+        Theme::Light
+    } else {
+        Theme::Dark
+    }
+}
+
+/// Make English (US) the default layout and ask open windows to switch to it
+pub fn switch_to_us_layout() -> Result<()> {
+    let failed = |call: &str, e: windows::core::Error| {
+        GhostKeysError::OsLayoutError(format!("{} failed: {}", call, e))
+    };
+    unsafe {
+        let mut hkl = LoadKeyboardLayoutW(w!("00000409"), KLF_ACTIVATE)
+            .map_err(|e| failed("LoadKeyboardLayoutW", e))?;
+        SystemParametersInfoW(
+            SPI_SETDEFAULTINPUTLANG,
+            0,
+            Some(&mut hkl as *mut HKL as *mut std::ffi::c_void),
+            SPIF_SENDCHANGE,
+        )
+        .map_err(|e| failed("SystemParametersInfoW", e))?;
+        // Windows already open keep their layout unless asked
+        let lparam = LPARAM(hkl.0 as isize);
+        let _ = PostMessageW(HWND_BROADCAST, WM_INPUTLANGCHANGEREQUEST, WPARAM(0), lparam);
+    }
+    Ok(())
+}
+
+/// Injection backend and pause between characters for the foreground application
+fn injection_settings() -> (InjectionBackend, Duration) {
+    with_context(|context| {
+        let injection = &context.settings().injection;
+        // Only look up the foreground process when there are rules to match
+        let app = if injection.apps.is_empty() && injection.delays.is_empty() {
+            None
+        } else {
+            foreground_process_name()
+        };
+        (injection.backend_for(app.as_deref()), injection.char_delay_for(app.as_deref()))
+    })
+    .unwrap_or_default()
+}
+
+/// Run `f` with the injection backend and pacing configured for the
+/// foreground application, within the flood limit
+fn with_injector(f: impl FnOnce(&dyn Injector) -> Result<()>) -> Result<()> {
+    let (backend, delay) = injection_settings();
+    let injector: &dyn Injector = match backend {
+        InjectionBackend::SendInput => &SendInputInjector,
+        InjectionBackend::WmChar => &WmCharInjector,
+    };
+    let paced = PacedInjector::new(injector, delay);
+    with_context(|context| f(&LimitedInjector::new(&paced, context.limiter()))).unwrap_or_else(|| {
+        Err(GhostKeysError::KeyInjectionError("The keyboard hook isn't installed".to_string()))
+    })
+}
+
+/// Report a failed injection through the notification pipeline
+///
+/// Failures are usually specific to the target application (e.g., an elevated
+/// window), so the foreground process is named in the message.
+fn report_injection_failure(result: Result<()>) {
+    let Err(e) = result else {
+        return;
+    };
+    let app = foreground_process_name().unwrap_or_else(|| "unknown application".to_string());
+    let message = match e.hint() {
+        Some(hint) => format!("{} (foreground: {}). {}", e, app, hint),
+        None => format!("{} (foreground: {})", e, app),
+    };
+
+    with_context(HookContext::injection_failed);
+    match shared_state() {
+        Some(state) => {
+            // Runaway injection: pause so the keyboard is usable again
+            if matches!(e, GhostKeysError::InjectionFlood { .. }) {
+                let _ = state.set_mode_because(OperationMode::Passthrough, Cause::Flood);
+            }
+            let _ = state.notify(Severity::Warning, message);
+        }
+        None => eprintln!("{}", message),
+    }
+}
+
+/// Inject backspaces
+fn inject_backspaces(count: usize) -> Result<()> {
+    with_injector(|injector| injector.inject_backspaces(count))
+}
+
+/// Inject multiple Unicode characters
+fn inject_chars(chars: &[char]) -> Result<()> {
+    with_injector(|injector| injector.inject_chars(chars))
+}
+
+/// Press and release the key of a blocked event again, after any injection
+///
+/// Always goes through SendInput: a key press can't be posted as WM_CHAR.
+fn replay_key(kb_struct: &KBDLLHOOKSTRUCT) -> Result<()> {
+    // Paced like one more injected character
+    let (_, delay) = injection_settings();
+    if !delay.is_zero() {
+        thread::sleep(delay);
+    }
+
+    let mut flags = KEYBD_EVENT_FLAGS(0);
+    if kb_struct.flags.0 & LLKHF_EXTENDED.0 != 0 {
+        flags |= KEYEVENTF_EXTENDEDKEY;
+    }
+    let vk = VIRTUAL_KEY(kb_struct.vkCode as u16);
+    let scan = kb_struct.scanCode as u16;
+    SendInputInjector::send(&[
+        keyboard_input(vk, scan, flags),
+        keyboard_input(vk, scan, flags | KEYEVENTF_KEYUP),
+    ])
+}
+
+/// Press and release a key through SendInput by its scan code, holding
+/// Shift, Alt or AltGr around it as `with` asks (unless the user already
+/// does)
+///
+/// Scan codes are what the keyboard itself sends, so games and other apps
+/// reading them see the key too, whatever the OS layout; the navigation
+/// block is sent as extended keys, or it would read as the numpad. Pause has
+/// no scan code of its own and goes by virtual key.
+fn inject_key(key: VirtualKey, with: Modifiers) -> Result<()> {
+    let scan_input = |scan: u8, extended: bool, up: bool| {
+        let mut flags = KEYEVENTF_SCANCODE;
+        if extended {
+            flags |= KEYEVENTF_EXTENDEDKEY;
+        }
+        if up {
+            flags |= KEYEVENTF_KEYUP;
+        }
+        keyboard_input(VIRTUAL_KEY(0), u16::from(scan), flags)
+    };
+    let (down, up) = match virtual_key_to_scan_code(key) {
+        Some((scan, extended)) => {
+            (scan_input(scan, extended, false), scan_input(scan, extended, true))
+        }
+        None if key == VirtualKey::Pause => (
+            keyboard_input(VK_PAUSE, 0, KEYBD_EVENT_FLAGS(0)),
+            keyboard_input(VK_PAUSE, 0, KEYEVENTF_KEYUP),
+        ),
+        None => return Ok(()),
+    };
+
+    // Left Shift, the right Alt (AltGr) and the left Alt
+    let held = current_modifiers();
+    let modifiers: Vec<(u8, bool)> = [
+        (with.shift && !held.shift, (0x2A, false)),
+        (with.altgr && !held.altgr, (0x38, true)),
+        (with.alt && !with.altgr && !held.alt, (0x38, false)),
+    ]
+    .into_iter()
+    .filter_map(|(press, code)| press.then_some(code))
+    .collect();
+
+    let mut inputs: Vec<INPUT> = modifiers
+        .iter()
+        .map(|&(scan, extended)| scan_input(scan, extended, false))
+        .collect();
+    inputs.extend([down, up]);
+    inputs.extend(modifiers.iter().rev().map(|&(scan, extended)| scan_input(scan, extended, true)));
+    SendInputInjector::send(&inputs)
+}
+
+/// Inject a string
+fn inject_str(text: &str) -> Result<()> {
+    with_injector(|injector| injector.inject_str(text))
+}
+
+/// Inject macro text
+///
+/// Line breaks are sent as a carriage return character rather than an Enter
+/// key press, so the held hotkey modifiers can't turn them into Ctrl+Enter.
+fn inject_text(text: &str) -> Result<()> {
+    let chars: Vec<char> = text
+        .chars()
+        .map(|c| if c == '\n' { '\r' } else { c })
+        .collect();
+    inject_chars(&chars)
+}
+
+/// dwExtraInfo of keystrokes injected by the self-test ("GHKT")
+const SELF_TEST_TAG: usize = 0x4748_4B54;
+
+/// dwExtraInfo of characters and replayed keys GhostKeys injects ("GHKI")
+const INJECTED_TAG: usize = 0x4748_4B49;
+
+/// Self-test keystrokes seen by the hook
+///
+/// Only the bits themselves are read back, so relaxed operations are enough.
+const SELF_TEST_KEY_SEEN: u8 = 1;
+const SELF_TEST_UNICODE_SEEN: u8 = 2;
+static SELF_TEST_SEEN: AtomicU8 = AtomicU8::new(0);
+
+/// How long the self-test waits for the hook to observe its keystrokes
+const SELF_TEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// VK_F24 (bound to nothing on most systems) and VK_PACKET (Unicode input)
+const VK_SELF_TEST: VIRTUAL_KEY = VIRTUAL_KEY(0x87);
+const VK_PACKET: u32 = 0xE7;
+
+/// Check that the hook receives keystrokes and that Unicode injection works
+///
+/// A tagged F24 press and a tagged Unicode character are injected; the hook
+/// records and swallows them, so no application sees either. Windows only
+/// delivers keyboard input to the focused window, so a hidden window can't
+/// receive the character; the hook, which sees it on the way there, stands
+/// in for one. Must not run on the hook thread, which has to keep pumping
+/// messages for the hook to be called.
+pub fn run_self_test() -> Result<()> {
+    let installed = GLOBAL_HOOK_HANDLE.lock().map(|handle| handle.is_some()).unwrap_or(false);
+    if !installed {
+        return Err(GhostKeysError::HookInstallError("the keyboard hook is not installed".to_string()));
+    }
+    SELF_TEST_SEEN.store(0, Ordering::Relaxed);
+
+    let mut units = [0u16; 2];
+    let unit = 'ç'.encode_utf16(&mut units)[0];
+    let inputs = [
+        tagged_keyboard_input(VK_SELF_TEST, 0, KEYBD_EVENT_FLAGS(0), SELF_TEST_TAG),
+        tagged_keyboard_input(VK_SELF_TEST, 0, KEYEVENTF_KEYUP, SELF_TEST_TAG),
+        tagged_keyboard_input(VIRTUAL_KEY(0), unit, KEYEVENTF_UNICODE, SELF_TEST_TAG),
+        tagged_keyboard_input(VIRTUAL_KEY(0), unit, KEYEVENTF_UNICODE | KEYEVENTF_KEYUP, SELF_TEST_TAG),
+    ];
+    let sent = unsafe { SendInput(&inputs, std::mem::size_of::<INPUT>() as i32) } as usize;
+    if sent < inputs.len() {
+        return Err(GhostKeysError::KeyInjectionError(format!(
+            "self-test: SendInput inserted {} of {} events (os error {})",
+            sent,
+            inputs.len(),
+            unsafe { GetLastError() }.0
+        )));
+    }
+
+    let expected = SELF_TEST_KEY_SEEN | SELF_TEST_UNICODE_SEEN;
+    let deadline = Instant::now() + SELF_TEST_TIMEOUT;
+    while SELF_TEST_SEEN.load(Ordering::Relaxed) != expected && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let seen = SELF_TEST_SEEN.load(Ordering::Relaxed);
+    if seen & SELF_TEST_KEY_SEEN == 0 {
+        // Installed but not called: Windows dropped it
+        Err(GhostKeysError::HookLost)
+    } else if seen & SELF_TEST_UNICODE_SEEN == 0 {
+        Err(GhostKeysError::KeyInjectionError(
+            "self-test: injected Unicode characters never arrived".to_string(),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// dwExtraInfo of the latency probe's test keys and its output ("GHKL")
+const LATENCY_TAG: usize = 0x4748_4B4C;
+
+/// The latency probe presses `;` (VK_OEM_1), which ABNT2 replaces with `ç`
+const VK_LATENCY_PROBE: VIRTUAL_KEY = VIRTUAL_KEY(0xBA);
+const LATENCY_PROBE_SCAN: u16 = 0x27;
+
+/// How long a latency probe may take before the measurement gives up
+const LATENCY_PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// When the current latency probe reached each stage
+#[derive(Debug, Clone, Copy)]
+struct Probe {
+    sent: Instant,
+    received: Option<Instant>,
+    decided: Option<Instant>,
+    delivered: Option<Instant>,
+}
+
+impl Probe {
+    /// A probe about to be injected
+    fn new() -> Self {
+        Self { sent: Instant::now(), received: None, decided: None, delivered: None }
+    }
+
+    /// Time spent in each stage, once the probe went through all of them
+    fn sample(&self) -> Option<Sample> {
+        let (received, decided, delivered) = (self.received?, self.decided?, self.delivered?);
+        Some(Sample {
+            receipt: received.saturating_duration_since(self.sent),
+            decision: decided.saturating_duration_since(received),
+            injection: delivered.saturating_duration_since(decided),
+        })
+    }
+}
+
+/// Latency probe in flight, shared by the measuring thread and the hook
+static LATENCY_PROBE: std::sync::Mutex<Option<Probe>> = std::sync::Mutex::new(None);
+
+/// Update the probe in flight, if any
+fn update_probe(update: impl FnOnce(&mut Probe)) {
+    if let Ok(mut probe) = LATENCY_PROBE.lock() {
+        if let Some(probe) = probe.as_mut() {
+            update(probe);
+        }
+    }
+}
+
+/// Time `samples` key presses from injection, through the hook and the
+/// mapper, to the replacement character arriving
+///
+/// Each probe is a tagged `;` press. The hook maps it with the running
+/// mapper and answers with a tagged Unicode character sent with SendInput,
+/// whatever the configured injection backend; both are swallowed by the hook,
+/// so nothing reaches applications. Must not run on the hook thread, like
+/// the self-test.
+pub fn run_latency_measurement(samples: usize) -> Result<Vec<Sample>> {
+    let installed = GLOBAL_HOOK_HANDLE.lock().map(|handle| handle.is_some()).unwrap_or(false);
+    if !installed {
+        return Err(GhostKeysError::HookInstallError("the keyboard hook is not installed".to_string()));
+    }
+
+    let inputs = [
+        tagged_keyboard_input(VK_LATENCY_PROBE, LATENCY_PROBE_SCAN, KEYBD_EVENT_FLAGS(0), LATENCY_TAG),
+        tagged_keyboard_input(VK_LATENCY_PROBE, LATENCY_PROBE_SCAN, KEYEVENTF_KEYUP, LATENCY_TAG),
+    ];
+    let mut measured = Vec::with_capacity(samples);
+    for _ in 0..samples {
+        if let Ok(mut probe) = LATENCY_PROBE.lock() {
+            *probe = Some(Probe::new());
+        }
+        let sent = unsafe { SendInput(&inputs, std::mem::size_of::<INPUT>() as i32) } as usize;
+        if sent < inputs.len() {
+            return Err(GhostKeysError::KeyInjectionError(format!(
+                "latency probe: SendInput inserted {} of {} events (os error {})",
+                sent,
+                inputs.len(),
+                unsafe { GetLastError() }.0
+            )));
+        }
+
+        let deadline = Instant::now() + LATENCY_PROBE_TIMEOUT;
+        let probe = loop {
+            let probe = LATENCY_PROBE.lock().ok().and_then(|probe| *probe);
+            if probe.is_none_or(|probe| probe.delivered.is_some()) || Instant::now() >= deadline {
+                break probe;
+            }
+            thread::sleep(Duration::from_millis(1));
+        };
+        match probe {
+            Some(probe) if probe.received.is_none() => return Err(GhostKeysError::HookLost),
+            Some(probe) => match probe.sample() {
+                Some(sample) => measured.push(sample),
+                None => {
+                    return Err(GhostKeysError::KeyInjectionError(
+                        "latency probe: injected characters never arrived".to_string(),
+                    ))
+                }
+            },
+            None => return Err(GhostKeysError::StateLockPoisoned),
+        }
+    }
+    if let Ok(mut probe) = LATENCY_PROBE.lock() {
+        *probe = None;
+    }
+    Ok(measured)
+}
+
+/// Time a latency probe key, mapping it like any key press but answering
+/// with output only the hook sees
+fn handle_latency_probe(kb_struct: &KBDLLHOOKSTRUCT, msg: u32) {
+    if msg != WM_KEYDOWN && msg != WM_SYSKEYDOWN {
+        return;
+    }
+    let now = Instant::now();
+    if kb_struct.vkCode == VK_PACKET {
+        update_probe(|probe| probe.delivered = Some(now));
+        return;
+    }
+    update_probe(|probe| probe.received = Some(now));
+
+    let event = KeyEvent::down(decode_position(kb_struct), current_modifiers());
+    let action = with_context(|context| context.map_unobserved(event)).unwrap_or(KeyAction::Pass);
+    update_probe(|probe| probe.decided = Some(Instant::now()));
+
+    // With nothing to replace (e.g., a custom layout), the key itself stands in
+    let c = action.output(None).first().copied().unwrap_or(';');
+    let mut units = [0u16; 2];
+    let unit = c.encode_utf16(&mut units)[0];
+    let inputs = [
+        tagged_keyboard_input(VIRTUAL_KEY(0), unit, KEYEVENTF_UNICODE, LATENCY_TAG),
+        tagged_keyboard_input(VIRTUAL_KEY(0), unit, KEYEVENTF_UNICODE | KEYEVENTF_KEYUP, LATENCY_TAG),
+    ];
+    unsafe {
+        SendInput(&inputs, std::mem::size_of::<INPUT>() as i32);
+    }
+}
+
+/// Low-level keyboard procedure callback
+unsafe extern "system" fn low_level_keyboard_proc(
+    code: i32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    // If code < 0, pass to next hook
+    if code < 0 {
+        return CallNextHookEx(None, code, wparam, lparam);
+    }
+
+    // Self-test keystrokes are recorded and never reach applications
+    let kb_struct = &*(lparam.0 as *const KBDLLHOOKSTRUCT);
+    if kb_struct.dwExtraInfo == SELF_TEST_TAG {
+        let seen = if kb_struct.vkCode == VK_PACKET {
+            SELF_TEST_UNICODE_SEEN
+        } else {
+            SELF_TEST_KEY_SEEN
+        };
+        SELF_TEST_SEEN.fetch_or(seen, Ordering::Relaxed);
+        return LRESULT(1);
+    }
+
+    // So are latency probes and the characters answering them
+    if kb_struct.dwExtraInfo == LATENCY_TAG {
+        handle_latency_probe(kb_struct, wparam.0 as u32);
+        return LRESULT(1);
+    }
+
+    // Our own output, delivered after SendInput returned. Input injected by
+    // other programs (on-screen keyboards, AutoHotkey) is remapped as usual
+    let injected = kb_struct.flags.0 & LLKHF_INJECTED.0 != 0;
+    if injected && kb_struct.dwExtraInfo == INJECTED_TAG {
+        return CallNextHookEx(None, code, wparam, lparam);
+    }
+
+    let vk_code = key_vk(kb_struct);
+
+    let msg = wparam.0 as u32;
+    let blocked = if msg == WM_KEYDOWN || msg == WM_SYSKEYDOWN {
+        // The low-level hook has no repeat flag: a key-down for a key that is
+        // still held is an auto-repeat
+        let repeat = with_context(|context| context.is_repeat(vk_code)).unwrap_or(false);
+        let blocked = handle_key_down(kb_struct, repeat);
+        with_context(|context| context.key_down(vk_code, blocked));
+        blocked
+    } else if msg == WM_KEYUP || msg == WM_SYSKEYUP {
+        handle_nav_trigger_up(vk_code);
+        // Key-ups follow whatever happened to their key-downs
+        with_context(|context| context.key_up(vk_code)).unwrap_or(false)
+    } else {
+        false
+    };
+
+    if blocked {
+        LRESULT(1)
+    } else {
+        CallNextHookEx(None, code, wparam, lparam)
+    }
+}
+
+/// Check if a keyboard layout handle is the native Portuguese (Brazil ABNT)
+/// layout, or its ABNT2 variant
+fn is_abnt2_layout(hkl: isize) -> bool {
+    classify_layout(hkl) == Some(os_layout::Conflict::Abnt2)
+}
+
+/// What's wrong with a keyboard layout handle, naming variants by the KLID
+/// their registry "Layout Id" belongs to
+fn classify_layout(hkl: isize) -> Option<os_layout::Conflict> {
+    let layout = (hkl as usize >> 16) & 0xFFFF;
+    let variant_klid = if layout & 0xF000 == 0xF000 {
+        os_layout::VARIANT_KLIDS
+            .into_iter()
+            .find(|klid| registry_layout_id(klid) == Some(layout & 0x0FFF))
+    } else {
+        None
+    };
+    os_layout::classify_hkl(hkl, variant_klid)
+}
+
+/// "Layout Id" of an installed keyboard layout, from its registry key
+fn registry_layout_id(klid: &str) -> Option<usize> {
+    let key = format!("SYSTEM\\CurrentControlSet\\Control\\Keyboard Layouts\\{}", klid);
+    let mut data = [0u16; 16];
+    let mut size = (data.len() * 2) as u32;
+    let status = unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            &HSTRING::from(key),
+            w!("Layout Id"),
+            RRF_RT_REG_SZ,
+            None,
+            Some(data.as_mut_ptr().cast()),
+            Some(&mut size),
+        )
+    };
+    if status != ERROR_SUCCESS {
+        return None;
+    }
+    let len = (size as usize / 2).saturating_sub(1);
+    usize::from_str_radix(String::from_utf16_lossy(&data[..len]).trim(), 16).ok()
+}
+
+/// Keyboard layout of the foreground window's thread
+fn foreground_layout() -> isize {
+    unsafe {
+        let thread = GetWindowThreadProcessId(GetForegroundWindow(), None);
+        GetKeyboardLayout(thread).0 as isize
+    }
+}
+
+/// Re-evaluate the `[auto_pause]` and `[devices]` rules for the resume safeguard; works from
+/// any thread, unlike the hook's own tracking
+pub fn auto_pause_condition(config: &Config) -> Condition {
+    let installed = GLOBAL_HOOK_HANDLE.lock().map(|handle| handle.is_some()).unwrap_or(false);
+    if !installed {
+        return Condition::Unknown;
+    }
+    let Some(keyboards) = raw_input::attached_keyboards() else {
+        return Condition::Unknown;
+    };
+
+    let keyboard = keyboards.iter().any(|name| config.pauses_for_keyboard(name));
+    let layout = config.auto_pause.when_os_layout_is_abnt2 && is_abnt2_layout(foreground_layout());
+    if keyboard || layout {
+        Condition::Holds
+    } else {
+        Condition::Cleared
+    }
+}
+
+/// Pause while the foreground window uses the OS's own ABNT2 layout
+///
+/// With the OS already producing ABNT2 characters, remapping on top would
+/// translate twice. Windows has no global notification for another window's
+/// input language, so the foreground thread's layout is checked on each key
+/// press and only changes are acted on. Switching away from ABNT2 resumes
+/// only a pause this rule made, never one the user chose.
+fn follow_os_layout() {
+    let Some(state) = shared_state() else {
+        return;
+    };
+    if !with_context(|context| context.follows_os_layout()).unwrap_or(false) {
+        return;
+    }
+
+    let hkl = foreground_layout();
+    // The resume safeguard found the layout had stopped applying
+    let resumed = state.last_mode_cause().is_ok_and(|cause| cause == Some(Cause::Watchdog));
+    let switched = with_context(|context| {
+        let foreground = context.foreground_mut();
+        if resumed {
+            foreground.paused_by_layout = false;
+        }
+        foreground.layout.replace(hkl) != Some(hkl)
+    });
+    if switched != Some(true) {
+        return;
+    }
+
+    let abnt2 = is_abnt2_layout(hkl);
+    let changed = with_context(|context| {
+        std::mem::replace(&mut context.foreground_mut().paused_by_layout, abnt2) != abnt2
+    });
+    if changed != Some(true) {
+        return;
+    }
+
+    if abnt2 {
+        let reason = tr(Text::PausedByOsLayout);
+        let _ = state.set_mode_by_rule(OperationMode::Passthrough, reason);
+    } else if paused_by_os_layout(&state) {
+        let _ = state.set_mode_by_rule(OperationMode::Active, tr(Text::ResumedByOsLayout));
+    }
+}
+
+/// Whether the current pause is the OS layout rule's, rather than one the
+/// user (or anything else) asked for
+fn paused_by_os_layout(state: &SharedState) -> bool {
+    state.is_paused()
+        && matches!(
+            state.last_mode_cause(),
+            Ok(Some(Cause::Rule(reason))) if reason == tr(Text::PausedByOsLayout)
+        )
+}
+
+/// Primary language IDs of layouts that come with an IME (Chinese, Japanese, Korean)
+const IME_LANGUAGES: [usize; 3] = [0x04, 0x11, 0x12];
+
+/// WM_IME_CONTROL request for whether the IME is converting input
+const WM_IME_CONTROL: u32 = 0x0283;
+const IMC_GETOPENSTATUS: usize = 0x0005;
+
+/// How long to wait for the foreground IME to answer
+const IME_QUERY_TIMEOUT_MS: u32 = 50;
+
+/// Check if the focused window's IME is open and converting keystrokes
+///
+/// ImmGetContext only works for windows of our own process, so the IME's
+/// default window is asked instead; both IMM32 and TSF input methods answer
+/// it. Only layouts that ship with an IME are queried, keeping the hook fast
+/// for everyone else.
+fn is_ime_open() -> bool {
+    let Some(hwnd) = focused_window() else {
+        return false;
+    };
+    unsafe {
+        let thread = GetWindowThreadProcessId(hwnd, None);
+        let language = GetKeyboardLayout(thread).0 as usize & 0xFF;
+        if !IME_LANGUAGES.contains(&language) {
+            return false;
+        }
+
+        let ime_window = ImmGetDefaultIMEWnd(hwnd);
+        if ime_window.is_invalid() {
+            return false;
+        }
+        let mut open = 0usize;
+        let answered = SendMessageTimeoutW(
+            ime_window,
+            WM_IME_CONTROL,
+            WPARAM(IMC_GETOPENSTATUS),
+            LPARAM(0),
+            SMTO_ABORTIFHUNG,
+            IME_QUERY_TIMEOUT_MS,
+            Some(&mut open as *mut usize),
+        );
+        answered.0 != 0 && open != 0
+    }
+}
+
+/// Pass keys through while an IME composition may be in progress
+///
+/// Suppressed keys and injected characters would corrupt the composition.
+/// Pending accents and tracked words are dropped when the IME opens so
+/// nothing typed before leaks into what follows it.
+fn ime_passthrough() -> bool {
+    let open = is_ime_open();
+    with_context(|context| {
+        if !std::mem::replace(&mut context.foreground_mut().ime_open, open) && open {
+            context.reset();
+            let _ = context.state().set_accent_pending(false);
+        }
+    });
+    open
+}
+
+/// Process a key-down event; returns true if the original key must be blocked
+fn handle_key_down(kb_struct: &KBDLLHOOKSTRUCT, repeat: bool) -> bool {
+    let vk_code = key_vk(kb_struct);
+
+    follow_os_layout();
+
+    if handle_pause_hotkey(vk_code, repeat) {
+        return true;
+    }
+
+    // Paused from the tray or by an [auto_pause] rule
+    let paused = with_context(|context| {
+        let paused = context.state().is_paused();
+        if paused {
+            // Don't resume with an accent armed before pausing
+            context.reset_mapper();
+            let _ = context.state().set_accent_pending(false);
+        }
+        paused
+    });
+    let paused = paused.unwrap_or(false);
+    if handle_custom_hotkey(vk_code, repeat, paused) {
+        return true;
+    }
+    if paused {
+        return false;
+    }
+
+    // Palette and macro hotkeys take precedence over everything else
+    if handle_palette_hotkey(vk_code, repeat)
+        || handle_profile_hotkey(vk_code, repeat)
+        || handle_undo_hotkey(vk_code)
+        || handle_suggestion_hotkey(vk_code, repeat)
+    {
+        return true;
+    }
+    if let Some(slot) = macro_slot(vk_code) {
+        if handle_macro_hotkey(slot, repeat) {
+            return true;
+        }
+    }
+
+    // The key after a `literal_next_key` hotkey goes through as it is
+    if !is_modifier_vk(vk_code) && with_context(HookContext::take_literal_next).unwrap_or(false) {
+        track_unmapped_key(vk_code);
+        return false;
+    }
+
+    // CJK input methods need the raw keys for their compositions
+    if ime_passthrough() {
+        return false;
+    }
+
+    // Injection into elevated windows is blocked; optionally don't even try
+    if is_foreground_elevated()
+        && with_context(|context| context.settings().injection.passthrough_elevated)
+            .unwrap_or(false)
+    {
+        return false;
+    }
+
+    // [remap] comes first: a remapped key is no longer a trigger or a key of
+    // its own
+    let remapped = remap_key(vk_code)
+        .and_then(|key| with_context(|context| context.remap_target(key)).flatten());
+    if let Some(target) = remapped.filter(|&target| target != VirtualKey::Compose) {
+        return press_remapped(kb_struct, target);
+    }
+
+    // The nav layer's trigger is held back until it's released
+    if remapped.is_none() && handle_nav_trigger(vk_code, repeat) {
+        return true;
+    }
+
+    // Convert to our VirtualKey
+    let compose_vk = with_context(|context| context.settings().compose.map(compose_trigger_vk));
+    let compose = compose_vk.flatten() == Some(vk_code) || remapped == Some(VirtualKey::Compose);
+    let virtual_key = if compose {
+        VirtualKey::Compose
+    } else {
+        decode_position(kb_struct)
+    };
+
+    // Skip keys we don't handle
+    if matches!(virtual_key, VirtualKey::Other) {
+        track_unmapped_key(vk_code);
+        return false;
+    }
+
+    // Alt chords (Alt+0231, menu accelerators) bypass the mapper
+    let modifiers = current_modifiers();
+
+    // Enter, Tab and Esc only reach the mapper to end a pending accent; what
+    // they do is tracked like any other unmapped key
+    let control_key = matches!(
+        virtual_key,
+        VirtualKey::Enter | VirtualKey::Tab | VirtualKey::Escape
+    );
+
+    // What the key types if passed through, for autocorrect and macros
+    let typed = if control_key { None } else { typed_char(kb_struct, modifiers.shift) };
+
+    // Direction can be switched from the tray at any time
+    let direction = with_context(|context| context.state().get_direction().ok()).flatten();
+
+    // Dead keys can be turned off in terminals; only then is the foreground
+    // window looked up, and its class and title only for window rules
+    let dead_keys = with_context(|context| {
+        let terminals = &context.settings().terminals;
+        terminals.dead_keys || {
+            let details = terminals.windows.iter().any(WindowRule::needs_class_or_title);
+            !terminals.is_terminal_window(&foreground_window(details))
+        }
+    })
+    .unwrap_or(true);
+
+    // Process through the mapper; autocorrect and the macro recorder watch
+    // everything that ends up on screen
+    let event = KeyEvent { repeat, ..KeyEvent::down(virtual_key, modifiers) };
+    publish(Event::KeyPressed(event));
+    let input = KeyInput {
+        typed,
+        shortcut: is_shortcut_modifier_pressed(),
+        direction,
+        dead_keys,
+        nav: with_context(|context| context.nav_held()).unwrap_or(false),
+        ..KeyInput::new(event)
+    };
+    let accent_before = pending_accent();
+    let action = with_context(|context| context.map(input)).unwrap_or(KeyAction::Pass);
+    accent_feedback(accent_before);
+    if control_key {
+        track_unmapped_key(vk_code);
+    } else {
+        refresh_suggestions();
+    }
+    if with_context(|context| context.settings().learning.enabled).unwrap_or(false) {
+        unsafe {
+            let _ = PostThreadMessageW(GetCurrentThreadId(), WM_SHOW_LESSON, WPARAM(0), LPARAM(0));
+        }
+    }
+
+    publish(Event::ActionDecided { event, action: action.clone() });
+    carry_out(kb_struct, &action)
+}
+
+/// Press the key a `[remap]` entry puts in place of the one pressed
+///
+/// A pending accent is dropped, and autocorrect and the macro recorder see
+/// the new key as if it had been pressed.
+fn press_remapped(kb_struct: &KBDLLHOOKSTRUCT, target: VirtualKey) -> bool {
+    let accent_before = pending_accent();
+    with_context(HookContext::reset_mapper);
+    accent_feedback(accent_before);
+    if let Some(vk) = named_key_vk(target) {
+        track_unmapped_key(vk);
+    }
+    carry_out(kb_struct, &KeyAction::InjectKey(target, Modifiers::default()))
+}
+
+/// Carry out the action decided for a key press; returns true if the
+/// original key must be blocked, which is everything but Pass
+fn carry_out(kb_struct: &KBDLLHOOKSTRUCT, action: &KeyAction) -> bool {
+    let injected = match action {
+        KeyAction::Pass => return false,
+        KeyAction::Suppress => return true,
+        KeyAction::Replace(c) => inject_chars(&[*c]),
+        KeyAction::ReplaceMultiple(chars) => inject_chars(chars),
+        KeyAction::ReplaceStr(text) => inject_str(text),
+        // Passing the original would let it overtake the queued injection
+        KeyAction::ReplaceThenPass(c) => inject_chars(&[*c]).and_then(|()| replay_key(kb_struct)),
+        KeyAction::ReplaceMultipleThenPass(chars) => {
+            inject_chars(chars).and_then(|()| replay_key(kb_struct))
+        }
+        KeyAction::Rewrite { backspaces, chars } => {
+            inject_backspaces(*backspaces).and_then(|()| inject_chars(chars))
+        }
+        KeyAction::InjectKey(key, with) => inject_key(*key, *with),
+    };
+    if injected.is_ok() {
+        publish(Event::Injected(action.output(None)));
+    }
+    report_injection_failure(injected);
+    true
+}
+
+/// Hold back the nav layer's trigger; returns true if the key was it
+///
+/// Any other key (but a modifier) pressed while it's held makes it a held
+/// trigger rather than a tap. Pressed with Ctrl, Alt or the Windows key
+/// (Alt+Tab), the trigger goes through as usual.
+fn handle_nav_trigger(vk: u32, repeat: bool) -> bool {
+    let trigger = with_context(|context| context.settings().nav_trigger.map(nav_trigger_vk));
+    if trigger.flatten() != Some(vk) {
+        if !is_modifier_vk(vk) {
+            with_context(HookContext::nav_key_down);
+        }
+        return false;
+    }
+    let chord = is_shortcut_modifier_pressed();
+    with_context(|context| context.nav_trigger_down(repeat, chord)).unwrap_or(false)
+}
+
+/// Release the nav layer's trigger; tapped on its own, it's pressed again
+/// so it toggles Caps Lock or types its Tab after all
+fn handle_nav_trigger_up(vk: u32) {
+    let trigger = with_context(|context| context.settings().nav_trigger).flatten();
+    let Some(trigger) = trigger.filter(|&trigger| nav_trigger_vk(trigger) == vk) else {
+        return;
+    };
+    if with_context(HookContext::nav_trigger_up).unwrap_or(false) {
+        report_injection_failure(inject_key(nav::trigger_key(trigger), Modifiers::default()));
+    }
+}
+
+/// Publish a pipeline event to the subscribers of the hook's state
+fn publish(event: Event) {
+    with_context(|context| {
+        let _ = context.state().publish(event);
+    });
+}
+
+
+/// Posted to the hook thread when commands are waiting in its channel
+const WM_HOOK_COMMAND: u32 = WM_APP + 1;
+
+/// Thread message asking the hook thread to open the character palette
+const WM_OPEN_PALETTE: u32 = WM_APP + 2;
+
+/// Thread message asking the hook thread to update the suggestion popup
+const WM_UPDATE_SUGGESTIONS: u32 = WM_APP + 3;
+
+/// Thread message asking the hook thread to show the learning mode lesson
+const WM_SHOW_LESSON: u32 = WM_APP + 4;
+
+/// Thread message asking the hook thread to switch to a hotkey's layout
+const WM_SWITCH_LAYOUT: u32 = WM_APP + 5;
+
+/// Thread message asking the hook thread to switch to the next profile
+const WM_CYCLE_PROFILE: u32 = WM_APP + 6;
+
+/// Requests to the thread that owns the hook
+enum HookCommand {
+    /// Use a new mapper from the next key press on (boxed, as it's far
+    /// bigger than the other commands)
+    SetMapper(Box<Mapper>),
+    /// Rebuild the custom hotkeys from a changed config
+    ReloadHotkeys,
+    /// Remove the hook and end the thread
+    Stop,
+}
+
+/// The thread that installs the hook, pumps its messages and removes it
+///
+/// Windows calls a low-level hook on the thread that installed it, and only
+/// that thread can reliably remove it, so everything happens there. Other
+/// threads talk to it through a channel, waking it with a thread message.
+struct HookThread {
+    thread_id: u32,
+    commands: mpsc::Sender<HookCommand>,
+    handle: JoinHandle<()>,
+}
+
+impl HookThread {
+    /// Start the thread, returning once the hook is installed
+    fn spawn(state: SharedState, mapper: Mapper) -> Result<Self> {
+        let (commands, receiver) = mpsc::channel();
+        let (ready, installed) = mpsc::channel();
+        let state_for_watcher = state.clone();
+        let handle = thread::Builder::new()
+            .name("ghostkeys-hook".to_string())
+            .spawn(move || run_hook_thread(state, mapper, receiver, ready))
+            .map_err(|e| GhostKeysError::HookInstallError(format!("Can't start the hook thread: {}", e)))?;
+
+        match installed.recv() {
+            Ok(Ok(thread_id)) => {
+                watch_config(&state_for_watcher, thread_id, commands.clone());
+                Ok(Self { thread_id, commands, handle })
+            }
+            Ok(Err(e)) => {
+                let _ = handle.join();
+                Err(e)
+            }
+            Err(_) => {
+                let _ = handle.join();
+                Err(GhostKeysError::HookInstallError("The hook thread exited".to_string()))
+            }
+        }
+    }
+
+    /// Queue a command and wake the thread to run it
+    fn send(&self, command: HookCommand) -> Result<()> {
+        self.commands
+            .send(command)
+            .map_err(|_| GhostKeysError::HookInstallError("The hook thread exited".to_string()))?;
+        unsafe { PostThreadMessageW(self.thread_id, WM_HOOK_COMMAND, WPARAM(0), LPARAM(0)) }
+            .map_err(|e| GhostKeysError::HookInstallError(format!("PostThreadMessageW failed: {}", e)))
+    }
+
+    /// Remove the hook and wait for the thread to end
+    fn stop(self) -> Result<()> {
+        if unsafe { GetCurrentThreadId() } == self.thread_id {
+            // Joining itself would deadlock; a hook callback can't stop the hook
+            return Err(GhostKeysError::HookReleaseError(
+                "stop() called on the hook thread".to_string(),
+            ));
+        }
+        // Already gone if the thread ended on its own
+        let _ = self.send(HookCommand::Stop);
+        self.handle
+            .join()
+            .map_err(|_| GhostKeysError::HookReleaseError("The hook thread panicked".to_string()))
+    }
+}
+
+/// Have the hook thread rebuild its custom hotkeys whenever the config
+/// changes (`ghostkeys ctl reload`, the settings window...)
+///
+/// The watcher ends with the hook thread, once its channel is gone.
+fn watch_config(state: &SharedState, thread_id: u32, commands: mpsc::Sender<HookCommand>) {
+    let Ok(changes) = state.subscribe() else {
+        return;
+    };
+    let _ = thread::Builder::new().name("ghostkeys-hotkeys".to_string()).spawn(move || {
+        for change in changes {
+            if change != StateChange::Config {
+                continue;
+            }
+            if commands.send(HookCommand::ReloadHotkeys).is_err() {
+                break;
+            }
+            unsafe {
+                let _ = PostThreadMessageW(thread_id, WM_HOOK_COMMAND, WPARAM(0), LPARAM(0));
+            }
+        }
+    });
+}
+
+/// Body of the hook thread: install, pump messages until stopped, uninstall
+///
+/// Reports the thread ID (or why the hook couldn't be installed) on `ready`.
+fn run_hook_thread(
+    state: SharedState,
+    mapper: Mapper,
+    commands: mpsc::Receiver<HookCommand>,
+    ready: mpsc::Sender<Result<u32>>,
+) {
+    let mut msg = MSG::default();
+    unsafe {
+        // Create the message queue before anyone can post to it
+        let _ = PeekMessageW(&mut msg, None, WM_USER, WM_USER, PM_NOREMOVE);
+    }
+
+    let hook = match install_hook(state, mapper) {
+        Ok(hook) => hook,
+        Err(e) => {
+            uninstall_hook(None);
+            let _ = ready.send(Err(e));
+            return;
+        }
+    };
+    let _ = ready.send(Ok(unsafe { GetCurrentThreadId() }));
+    // Report custom hotkeys left out of the table
+    reload_hotkeys();
+    resume_pause_timer();
+
+    'pump: while unsafe { GetMessageW(&mut msg, None, 0, 0) }.as_bool() {
+        if msg.hwnd.is_invalid() && msg.message == WM_HOOK_COMMAND {
+            for command in commands.try_iter() {
+                match command {
+                    HookCommand::SetMapper(mapper) => {
+                        with_context(|context| context.set_mapper(*mapper));
+                    }
+                    HookCommand::ReloadHotkeys => reload_hotkeys(),
+                    HookCommand::Stop => break 'pump,
+                }
+            }
+            continue;
+        }
+        if msg.hwnd.is_invalid() && msg.message == WM_OPEN_PALETTE {
+            open_palette();
+            continue;
+        }
+        if msg.hwnd.is_invalid() && msg.message == WM_UPDATE_SUGGESTIONS {
+            update_suggestions();
+            continue;
+        }
+        if msg.hwnd.is_invalid() && msg.message == WM_SHOW_LESSON {
+            show_lesson();
+            continue;
+        }
+        if msg.hwnd.is_invalid() && msg.message == WM_SWITCH_LAYOUT {
+            switch_layout();
+            continue;
+        }
+        if msg.hwnd.is_invalid() && msg.message == WM_CYCLE_PROFILE {
+            cycle_profile();
+            continue;
+        }
+        if msg.hwnd.is_invalid()
+            && msg.message == WM_TIMER
+            && (accent_timed_out(msg.wParam.0) || pause_timed_out(msg.wParam.0))
+        {
+            continue;
+        }
+        if palette_window::handle_message(&msg) {
+            continue;
+        }
+        // Raw Input, the palette, the suggestion popup, the learning display
+        // and the conflict checks' windows live on this thread; the palette's search box needs
+        // the translated characters
+        unsafe {
+            let _ = TranslateMessage(&msg);
+            DispatchMessageW(&msg);
+        }
+    }
+
+    uninstall_hook(Some(hook));
+}
+
+/// Set up the hook thread's state and install the low-level keyboard hook
+fn install_hook(state: SharedState, mapper: Mapper) -> Result<HHOOK> {
+    // Before the palette and popups this thread shows are created
+    ui::enable_per_monitor_dpi();
+    let context = HookContext::new(state.clone(), mapper)?;
+    // Without Raw Input keyboards plugged in don't pause, which is still usable
+    if let Err(e) = raw_input::start(&state) {
+        let _ = state.notify(Severity::Warning, format!("Per-keyboard pause rules disabled: {}", e));
+    }
+    warn_conflicts(&state);
+    CONTEXT.with(|slot| *slot.borrow_mut() = Some(context));
+
+    let hook = unsafe {
+        SetWindowsHookExW(WH_KEYBOARD_LL, Some(low_level_keyboard_proc), HINSTANCE::default(), 0)
+    }
+    .map_err(|e| {
+        if e.code() == E_ACCESSDENIED {
+            GhostKeysError::PermissionDenied {
+                message: "SetWindowsHookExW".to_string(),
+                code: e.code().0,
+                platform_hint: "Security software may be blocking keyboard hooks; allow GhostKeys there",
+            }
+        } else {
+            GhostKeysError::HookInstallError(format!("SetWindowsHookExW failed: {}", e))
+        }
+    })?;
+
+    // Store raw handle in global for panic handler
+    if let Ok(mut global) = GLOBAL_HOOK_HANDLE.lock() {
+        *global = Some(hook.0 as isize);
+    }
+    Ok(hook)
+}
+
+/// Remove the hook (if installed) and clear the hook thread's state
+fn uninstall_hook(hook: Option<HHOOK>) {
+    if let Some(hook) = hook {
+        unsafe {
+            let _ = UnhookWindowsHookEx(hook);
+        }
+    }
+    if let Ok(mut global) = GLOBAL_HOOK_HANDLE.lock() {
+        *global = None;
+    }
+
+    CONTEXT.with(|context| *context.borrow_mut() = None);
+    raw_input::stop();
+}
+
+/// Windows keyboard interceptor using low-level keyboard hooks
+///
+/// The hook lives on a thread the interceptor owns, so it can be started and
+/// stopped from any thread.
+pub struct WindowsInterceptor {
+    /// A standalone flag; the hook thread is handed everything else through
+    /// `HookThread`'s channel
+    running: Arc<AtomicBool>,
+    /// State shared with the hook, which reads the mode on every key press
+    state: Option<SharedState>,
+    /// Thread owning the hook while running
+    hook_thread: Option<HookThread>,
+}
+
+impl WindowsInterceptor {
+    /// Create a new Windows interceptor
+    pub fn new() -> Self {
+        Self {
+            running: Arc::new(AtomicBool::new(false)),
+            state: None,
+            hook_thread: None,
+        }
+    }
+
+    fn hook_thread(&self) -> Result<&HookThread> {
+        self.hook_thread
+            .as_ref()
+            .ok_or_else(|| GhostKeysError::HookInstallError("Interceptor not running".to_string()))
+    }
+}
+
+impl Default for WindowsInterceptor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KeyboardInterceptor for WindowsInterceptor {
+    fn start(&mut self, state: SharedState) -> Result<()> {
+        if self.running.load(Ordering::Relaxed) {
+            return Err(GhostKeysError::HookInstallError(
+                "Interceptor already running".to_string(),
+            ));
+        }
+
+        let mapper = build_mapper(&state)?;
+        self.hook_thread = Some(HookThread::spawn(state.clone(), mapper)?);
+        self.state = Some(state);
+        self.running.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        let Some(hook_thread) = self.hook_thread.take() else {
+            return Ok(());
+        };
+        self.state = None;
+        self.running.store(false, Ordering::Relaxed);
+        hook_thread.stop()
+    }
+
+    fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    fn set_mode(&mut self, mode: OperationMode) -> Result<()> {
+        let state = self.state.as_ref().ok_or_else(|| {
+            GhostKeysError::HookInstallError("Interceptor not running".to_string())
+        })?;
+        state.set_mode(mode)
+    }
+
+    fn set_mapper(&mut self, mapper: Mapper) -> Result<()> {
+        self.hook_thread()?.send(HookCommand::SetMapper(Box::new(mapper)))
+    }
+
+    fn update_layout(&mut self, layout: Option<Layout>) -> Result<()> {
+        let state = self.state.as_ref().ok_or_else(|| {
+            GhostKeysError::HookInstallError("Interceptor not running".to_string())
+        })?;
+        state.set_layout(layout)?;
+        let mapper = build_mapper(state)?;
+        self.set_mapper(mapper)
+    }
+
+    fn self_test(&self) -> Result<()> {
+        if !self.is_running() {
+            return Err(GhostKeysError::HookInstallError("Interceptor not running".to_string()));
+        }
+        run_self_test()
+    }
+
+    fn measure_latency(&self, samples: usize) -> Result<Vec<Sample>> {
+        if !self.is_running() {
+            return Err(GhostKeysError::HookInstallError("Interceptor not running".to_string()));
+        }
+        run_latency_measurement(samples)
     }
 }
 
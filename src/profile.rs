@@ -0,0 +1,397 @@
+//! Shareable settings profiles
+//!
+//! `ghostkeys profile export <name>` bundles the settings worth sharing into
+//! a single `.gkprofile` file (TOML): the layout features in use, moved dead
+//! keys, compose sequences, per-application injection rules, the macro hotkeys and their
+//! snippets, autocorrect, the global hotkeys, key remaps, the numpad and nav
+//! layers, and optionally a custom KLC layout. `ghostkeys profile import <file>` merges a
+//! bundle into the local config key by key: what the bundle sets wins, and
+//! local entries it doesn't mention (a compose sequence, a macro slot) stay.
+//! Only the bundled sections of the config file are rewritten, in place, so
+//! its comments are kept.
+//!
+//! Settings tied to one machine (devices, Linux keyboards, the physical
+//! layout, logging, crash handling and statistics) are never exported, so importing a
+//! profile can't break someone else's hardware setup.
+//!
+//! Profiles saved in the `profiles` folder can also be switched between from
+//! the tray or with Ctrl+Alt+P; the one switched to last is remembered under
+//! `[profiles]` and its layout loaded again on the next start.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{
+    io_error, line_number, AutocorrectConfig, ComposeConfig, Config, HotkeysConfig,
+    InjectionConfig, MacrosConfig, NavConfig, NumpadConfig,
+};
+use crate::error::{GhostKeysError, Result};
+use crate::layout::{klc, KeyPosition, Layout};
+use crate::mapper::{AccentType, MappingFeatures};
+use crate::paths;
+use crate::remap::{self, RemapKey};
+use crate::state::SharedState;
+
+/// File extension of profile bundles
+pub const EXTENSION: &str = "gkprofile";
+
+/// Config sections a profile carries
+pub const SECTIONS: &[&str] = &[
+    "features",
+    "dead_keys",
+    "compose",
+    "injection",
+    "macros",
+    "autocorrect",
+    "hotkeys",
+    "remap",
+    "numpad",
+    "nav",
+];
+
+/// A bundle of shareable settings
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    /// Name given when the profile was exported
+    pub name: String,
+    /// GhostKeys version that exported the profile
+    pub version: String,
+    /// Custom layout, as the contents of a KLC file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layout: Option<String>,
+    /// Position remaps, dead keys and AltGr turned on or off
+    pub features: MappingFeatures,
+    /// Keys moved to act as dead keys
+    pub dead_keys: BTreeMap<KeyPosition, AccentType>,
+    /// Compose key and sequences
+    pub compose: ComposeConfig,
+    /// Per-application injection rules
+    pub injection: InjectionConfig,
+    /// Macro hotkeys and the snippets in their slots
+    pub macros: MacrosConfig,
+    /// Accent autocorrect
+    pub autocorrect: AutocorrectConfig,
+    /// Global hotkeys
+    pub hotkeys: HotkeysConfig,
+    /// Keys pressed in place of others
+    #[serde(deserialize_with = "remap::deserialize_table")]
+    pub remap: BTreeMap<RemapKey, RemapKey>,
+    /// Virtual numpad
+    pub numpad: NumpadConfig,
+    /// Navigation layer
+    pub nav: NavConfig,
+}
+
+impl Profile {
+    /// Bundle the shareable parts of `config`
+    pub fn from_config(name: &str, config: &Config, layout: Option<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            layout,
+            features: config.features,
+            dead_keys: config.dead_keys.clone(),
+            compose: config.compose.clone(),
+            injection: config.injection.clone(),
+            macros: config.macros.clone(),
+            autocorrect: config.autocorrect.clone(),
+            hotkeys: config.hotkeys.clone(),
+            remap: config.remap.clone(),
+            numpad: config.numpad.clone(),
+            nav: config.nav.clone(),
+        }
+    }
+
+    /// Parse a profile, naming the file and line in errors
+    pub fn parse(text: &str, path: Option<&Path>) -> Result<Self> {
+        toml::from_str(text).map_err(|e| GhostKeysError::ConfigError {
+            path: path.map(Path::to_path_buf),
+            line: e.span().map(|span| line_number(text, span.start)),
+            message: e.message().to_string(),
+        })
+    }
+
+    /// Replace the bundled sections of `config`, keeping everything else
+    pub fn apply(&self, config: &mut Config) {
+        config.features = self.features;
+        config.dead_keys = self.dead_keys.clone();
+        config.compose = self.compose.clone();
+        config.injection = self.injection.clone();
+        config.macros = self.macros.clone();
+        config.autocorrect = self.autocorrect.clone();
+        config.hotkeys = self.hotkeys.clone();
+        config.remap = self.remap.clone();
+        config.numpad = self.numpad.clone();
+        config.nav = self.nav.clone();
+    }
+
+    /// Merge the bundled sections into `config` key by key: the profile's
+    /// values win, and map entries only `config` has are kept
+    pub fn merge(&self, config: &mut Config) -> Result<()> {
+        let error = |message: String| GhostKeysError::ConfigError { path: None, line: None, message };
+        let bundle = toml::Value::try_from(self).map_err(|e| error(e.to_string()))?;
+        let mut local = toml::Value::try_from(&*config).map_err(|e| error(e.to_string()))?;
+        if let (Some(bundle), Some(local)) = (bundle.as_table(), local.as_table_mut()) {
+            for &section in SECTIONS {
+                match (bundle.get(section), local.get_mut(section)) {
+                    (Some(value), Some(existing)) => merge_value(existing, value),
+                    (Some(value), None) => {
+                        local.insert(section.to_string(), value.clone());
+                    }
+                    (None, _) => {}
+                }
+            }
+        }
+        *config = local.try_into().map_err(|e: toml::de::Error| error(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Apply the profile saved as `name` and remember it as the active one;
+    /// returns its layout, `None` for the built-in ABNT2 tables
+    pub fn activate(&self, name: &str, config: &mut Config) -> Result<Option<Layout>> {
+        // Check the layout before anything changes
+        let layout = self.layout.as_deref().map(klc::parse).transpose()?;
+        self.apply(config);
+        config.profiles.active = Some(name.to_string());
+        Ok(layout)
+    }
+
+    /// Name of the file an imported layout is saved as
+    pub fn layout_file_name(&self) -> String {
+        let stem: String = self
+            .name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        if stem.is_empty() {
+            "profile.klc".to_string()
+        } else {
+            format!("{}.klc", stem)
+        }
+    }
+}
+
+/// Write the current configuration (and `layout`, a KLC file) as a profile
+pub fn export(name: &str, out: &Path, layout: Option<&Path>) -> Result<()> {
+    let config = Config::load()?;
+    // Re-exported so the bundle holds UTF-8 text whatever the source encoding
+    let layout = layout.map(|path| klc::load(path).map(|layout| klc::export(&layout))).transpose()?;
+
+    let profile = Profile::from_config(name, &config, layout);
+    let text = toml::to_string_pretty(&profile).map_err(|e| GhostKeysError::ConfigError {
+        path: Some(out.to_path_buf()),
+        line: None,
+        message: e.to_string(),
+    })?;
+    std::fs::write(out, text).map_err(|e| io_error(out, e))
+}
+
+/// Merge a profile into the configuration file
+///
+/// A bundled layout is saved to the `layouts` folder next to the config file;
+/// its path is returned so the user can pass it to `--layout`.
+pub fn import(path: &Path) -> Result<(Profile, Option<PathBuf>)> {
+    let text = std::fs::read_to_string(path).map_err(|e| io_error(path, e))?;
+    let profile = Profile::parse(&text, Some(path))?;
+
+    let mut config = Config::load()?;
+    profile.merge(&mut config)?;
+
+    let layout_path = match &profile.layout {
+        Some(layout) => {
+            // Check the layout before anything is written
+            klc::parse(layout)?;
+            let dir = paths::layouts_dir().ok_or_else(no_config_dir)?;
+            std::fs::create_dir_all(&dir).map_err(|e| io_error(&dir, e))?;
+            let layout_path = dir.join(profile.layout_file_name());
+            std::fs::write(&layout_path, layout).map_err(|e| io_error(&layout_path, e))?;
+            Some(layout_path)
+        }
+        None => None,
+    };
+
+    config.save_sections(SECTIONS)?;
+    Ok((profile, layout_path))
+}
+
+/// Names of the profiles to switch between: the `.gkprofile` files in the
+/// profiles folder, in alphabetical order
+pub fn list() -> Vec<String> {
+    let Some(entries) = paths::profiles_dir().and_then(|dir| std::fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == EXTENSION))
+        .filter_map(|path| path.file_stem()?.to_str().map(String::from))
+        .collect();
+    names.sort();
+    names
+}
+
+/// Profile after `active` in `names`, wrapping around; the first one when
+/// none of them is active
+pub fn next<'a>(names: &'a [String], active: Option<&str>) -> Option<&'a str> {
+    let after = active
+        .and_then(|active| names.iter().position(|name| name == active))
+        .map_or(0, |i| i + 1);
+    names.get(after % names.len().max(1)).map(String::as_str)
+}
+
+/// Read the profile saved as `name` in the profiles folder
+pub fn load(name: &str) -> Result<Profile> {
+    let dir = paths::profiles_dir().ok_or_else(no_config_dir)?;
+    let path = dir.join(format!("{}.{}", name, EXTENSION));
+    let text = std::fs::read_to_string(&path).map_err(|e| io_error(&path, e))?;
+    Profile::parse(&text, Some(&path))
+}
+
+/// Switch to the saved profile `name`: its settings are used from now on
+/// and saved to the config file, so it's still active after a restart
+///
+/// Returns its layout; the caller builds the mapper with it.
+pub fn switch(state: &SharedState, name: &str) -> Result<Option<Layout>> {
+    let mut config = state.get_config()?;
+    let layout = load(name)?.activate(name, &mut config)?;
+    config.save_sections(&[SECTIONS, &["profiles"]].concat())?;
+    state.set_config(config)?;
+    Ok(layout)
+}
+
+/// Layout of the active profile, to use it again at startup
+pub fn active_layout(config: &Config) -> Result<Option<Layout>> {
+    match &config.profiles.active {
+        Some(name) => Ok(load(name)?.layout.as_deref().map(klc::parse).transpose()?),
+        None => Ok(None),
+    }
+}
+
+/// Merge `value` into `existing`: tables key by key, anything else replaced
+fn merge_value(existing: &mut toml::Value, value: &toml::Value) {
+    match (existing, value) {
+        (toml::Value::Table(existing), toml::Value::Table(table)) => {
+            for (key, value) in table {
+                match existing.get_mut(key) {
+                    Some(old) => merge_value(old, value),
+                    None => {
+                        existing.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (existing, value) => *existing = value.clone(),
+    }
+}
+
+/// Error for a config folder that can't be determined
+fn no_config_dir() -> GhostKeysError {
+    GhostKeysError::ConfigError {
+        path: None,
+        line: None,
+        message: "could not determine the config directory".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::InjectionBackend;
+
+    fn shared_config() -> Config {
+        let mut config = Config::default();
+        config.compose.sequences.insert("ao".to_string(), "ão".to_string());
+        config.injection.apps.insert("javaw.exe".to_string(), InjectionBackend::WmChar);
+        config.macros.enabled = true;
+        config.macros.slots.insert("f1".to_string(), "Atenciosamente".to_string());
+        config.features.grave = false;
+        config.nav.enabled = true;
+        config.remap.insert(RemapKey::CapsLock, RemapKey::Escape);
+        config
+    }
+
+    #[test]
+    fn test_apply_keeps_machine_settings() {
+        let profile = Profile::from_config("team", &shared_config(), None);
+
+        let mut local = Config::default();
+        local.logging.system_log = true;
+        local.crash.relaunch = true;
+        profile.apply(&mut local);
+
+        assert_eq!(local.features, shared_config().features);
+        assert_eq!(local.compose, shared_config().compose);
+        assert_eq!(local.injection, shared_config().injection);
+        assert_eq!(local.macros, shared_config().macros);
+        assert_eq!(local.nav, shared_config().nav);
+        assert_eq!(local.remap, shared_config().remap);
+        assert!(local.logging.system_log);
+        assert!(local.crash.relaunch);
+    }
+
+    #[test]
+    fn test_merge_keeps_local_entries() {
+        let profile = Profile::from_config("team", &shared_config(), None);
+
+        let mut local = Config::default();
+        local.compose.sequences.insert("oe".to_string(), "œ".to_string());
+        local.macros.slots.insert("f1".to_string(), "Abraços".to_string());
+        local.macros.slots.insert("f2".to_string(), "Obrigado".to_string());
+        local.logging.system_log = true;
+        profile.merge(&mut local).unwrap();
+
+        // The profile's entries win, the local ones it doesn't have stay
+        assert_eq!(local.compose.sequences.get("ao").map(String::as_str), Some("ão"));
+        assert_eq!(local.compose.sequences.get("oe").map(String::as_str), Some("œ"));
+        assert_eq!(local.macros.slots.get("f1").map(String::as_str), Some("Atenciosamente"));
+        assert_eq!(local.macros.slots.get("f2").map(String::as_str), Some("Obrigado"));
+        assert!(local.macros.enabled);
+        assert!(!local.features.grave);
+        assert_eq!(local.remap, shared_config().remap);
+        assert!(local.logging.system_log);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let profile = Profile::from_config("team", &shared_config(), Some("KBD\tbr".to_string()));
+        let text = toml::to_string_pretty(&profile).unwrap();
+        assert_eq!(Profile::parse(&text, None).unwrap(), profile);
+    }
+
+    #[test]
+    fn test_activate_remembers_the_profile() {
+        let profile = Profile::from_config("team", &shared_config(), None);
+        let mut local = Config::default();
+        assert_eq!(profile.activate("work", &mut local).unwrap(), None);
+        assert_eq!(local.profiles.active.as_deref(), Some("work"));
+        assert_eq!(local.macros, shared_config().macros);
+
+        // A broken layout changes nothing
+        let broken = Profile { layout: Some("LAYOUT\nxx\tOEM_1\t0\t00e7\n".to_string()), ..profile };
+        let mut local = Config::default();
+        assert!(broken.activate("broken", &mut local).is_err());
+        assert_eq!(local, Config::default());
+    }
+
+    #[test]
+    fn test_next_profile_wraps_around() {
+        let names = vec!["home".to_string(), "work".to_string()];
+        assert_eq!(next(&names, None), Some("home"));
+        assert_eq!(next(&names, Some("home")), Some("work"));
+        assert_eq!(next(&names, Some("work")), Some("home"));
+        // Deleted since it was switched to
+        assert_eq!(next(&names, Some("old")), Some("home"));
+        assert_eq!(next(&[], Some("home")), None);
+    }
+
+    #[test]
+    fn test_layout_file_name() {
+        let named = |name: &str| Profile { name: name.to_string(), ..Profile::default() };
+        assert_eq!(named("abnt2-dev").layout_file_name(), "abnt2-dev.klc");
+        assert_eq!(named("../Equipe São Paulo").layout_file_name(), "___Equipe_S_o_Paulo.klc");
+        assert_eq!(named("").layout_file_name(), "profile.klc");
+    }
+}
@@ -0,0 +1,297 @@
+//! Key-to-key remapping: `[remap]`
+//!
+//! Some keys earn their place on the keyboard only for some users: Caps
+//! Lock, the right Ctrl, the menu key. `[remap]` puts another key in their
+//! place, before anything else (the nav layer, compose, the ABNT2 layout)
+//! looks at them:
+//!
+//! ```toml
+//! [remap]
+//! caps_lock = "escape"
+//! right_ctrl = "compose"
+//! ```
+//!
+//! The new key is pressed and released when the remapped one goes down, and
+//! again on each auto-repeat, so modifiers (Ctrl, Alt, Shift, Win) can be
+//! remapped to other keys but no key can become one: the config refuses a
+//! modifier as a target, with its line. `compose` makes the key start compose
+//! sequences, whatever `[compose] trigger` says. Like the rest of GhostKeys,
+//! remapping stops while paused.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::mapper::VirtualKey;
+
+/// A key named in `[remap]`
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum RemapKey {
+    /// Esc
+    Escape,
+    /// Caps Lock
+    CapsLock,
+    /// Tab
+    Tab,
+    /// Enter
+    Enter,
+    /// Backspace
+    Backspace,
+    /// Delete
+    Delete,
+    /// Insert
+    Insert,
+    /// Home
+    Home,
+    /// End
+    End,
+    /// Page Up
+    PageUp,
+    /// Page Down
+    PageDown,
+    /// Left arrow
+    Left,
+    /// Right arrow
+    Right,
+    /// Up arrow
+    Up,
+    /// Down arrow
+    Down,
+    /// The context menu (Application) key
+    Menu,
+    /// Scroll Lock
+    ScrollLock,
+    /// Pause/Break
+    Pause,
+    /// Print Screen
+    PrintScreen,
+    /// Left Ctrl
+    LeftCtrl,
+    /// Right Ctrl
+    RightCtrl,
+    /// Left Alt
+    LeftAlt,
+    /// Right Alt (AltGr)
+    RightAlt,
+    /// Left Shift
+    LeftShift,
+    /// Right Shift
+    RightShift,
+    /// Left Windows (Super) key
+    LeftWin,
+    /// Right Windows (Super) key
+    RightWin,
+    /// Not a key: what a key is remapped to so it starts compose sequences
+    Compose,
+}
+
+/// Every key, with its name in the config file
+const NAMES: &[(RemapKey, &str)] = &[
+    (RemapKey::Escape, "escape"),
+    (RemapKey::CapsLock, "caps_lock"),
+    (RemapKey::Tab, "tab"),
+    (RemapKey::Enter, "enter"),
+    (RemapKey::Backspace, "backspace"),
+    (RemapKey::Delete, "delete"),
+    (RemapKey::Insert, "insert"),
+    (RemapKey::Home, "home"),
+    (RemapKey::End, "end"),
+    (RemapKey::PageUp, "page_up"),
+    (RemapKey::PageDown, "page_down"),
+    (RemapKey::Left, "left"),
+    (RemapKey::Right, "right"),
+    (RemapKey::Up, "up"),
+    (RemapKey::Down, "down"),
+    (RemapKey::Menu, "menu"),
+    (RemapKey::ScrollLock, "scroll_lock"),
+    (RemapKey::Pause, "pause"),
+    (RemapKey::PrintScreen, "print_screen"),
+    (RemapKey::LeftCtrl, "left_ctrl"),
+    (RemapKey::RightCtrl, "right_ctrl"),
+    (RemapKey::LeftAlt, "left_alt"),
+    (RemapKey::RightAlt, "right_alt"),
+    (RemapKey::LeftShift, "left_shift"),
+    (RemapKey::RightShift, "right_shift"),
+    (RemapKey::LeftWin, "left_win"),
+    (RemapKey::RightWin, "right_win"),
+    (RemapKey::Compose, "compose"),
+];
+
+impl RemapKey {
+    /// Every key, for platforms looking up the one pressed
+    pub fn all() -> impl Iterator<Item = RemapKey> {
+        NAMES.iter().map(|&(key, _)| key)
+    }
+
+    /// The key to press in its place; `None` for the modifiers, which only
+    /// change what other keys do
+    pub fn virtual_key(self) -> Option<VirtualKey> {
+        let key = match self {
+            RemapKey::Escape => VirtualKey::Escape,
+            RemapKey::CapsLock => VirtualKey::CapsLock,
+            RemapKey::Tab => VirtualKey::Tab,
+            RemapKey::Enter => VirtualKey::Enter,
+            RemapKey::Backspace => VirtualKey::Backspace,
+            RemapKey::Delete => VirtualKey::Delete,
+            RemapKey::Insert => VirtualKey::Insert,
+            RemapKey::Home => VirtualKey::Home,
+            RemapKey::End => VirtualKey::End,
+            RemapKey::PageUp => VirtualKey::PageUp,
+            RemapKey::PageDown => VirtualKey::PageDown,
+            RemapKey::Left => VirtualKey::Left,
+            RemapKey::Right => VirtualKey::Right,
+            RemapKey::Up => VirtualKey::Up,
+            RemapKey::Down => VirtualKey::Down,
+            RemapKey::Menu => VirtualKey::Menu,
+            RemapKey::ScrollLock => VirtualKey::ScrollLock,
+            RemapKey::Pause => VirtualKey::Pause,
+            RemapKey::PrintScreen => VirtualKey::PrintScreen,
+            RemapKey::Compose => VirtualKey::Compose,
+            RemapKey::LeftCtrl
+            | RemapKey::RightCtrl
+            | RemapKey::LeftAlt
+            | RemapKey::RightAlt
+            | RemapKey::LeftShift
+            | RemapKey::RightShift
+            | RemapKey::LeftWin
+            | RemapKey::RightWin => return None,
+        };
+        Some(key)
+    }
+}
+
+impl fmt::Display for RemapKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = NAMES.iter().find(|(key, _)| key == self);
+        write!(f, "{}", name.map_or("?", |(_, name)| name))
+    }
+}
+
+/// A key remapped in the config: any key but `compose`
+#[derive(PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "RemapKey")]
+struct Source(RemapKey);
+
+impl TryFrom<RemapKey> for Source {
+    type Error = String;
+
+    fn try_from(key: RemapKey) -> Result<Self, Self::Error> {
+        if key == RemapKey::Compose {
+            return Err(
+                "\"compose\" isn't a key; it can only be what a key is remapped to".to_string()
+            );
+        }
+        Ok(Self(key))
+    }
+}
+
+/// What a key is remapped to in the config: any key but a modifier
+#[derive(Deserialize)]
+#[serde(try_from = "RemapKey")]
+struct Target(RemapKey);
+
+impl TryFrom<RemapKey> for Target {
+    type Error = String;
+
+    fn try_from(key: RemapKey) -> Result<Self, Self::Error> {
+        match key.virtual_key() {
+            Some(_) => Ok(Self(key)),
+            None => Err(format!(
+                "a key can't be remapped to \"{}\": remapped keys are pressed and released, \
+                 and a modifier has to be held",
+                key
+            )),
+        }
+    }
+}
+
+/// Read a `[remap]` table, refusing entries that can't work so the mistake
+/// is reported with its line
+pub fn deserialize_table<'de, D>(deserializer: D) -> Result<BTreeMap<RemapKey, RemapKey>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let table = BTreeMap::<Source, Target>::deserialize(deserializer)?;
+    Ok(table.into_iter().map(|(source, target)| (source.0, target.0)).collect())
+}
+
+/// The keys `[remap]` puts in place of others
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Remap {
+    table: BTreeMap<RemapKey, VirtualKey>,
+}
+
+impl Remap {
+    /// The table `config` describes
+    ///
+    /// Entries a config file refuses (see `deserialize_table`) are left out
+    /// when they come from elsewhere.
+    pub fn from_config(config: &BTreeMap<RemapKey, RemapKey>) -> Self {
+        let table = config
+            .iter()
+            .filter(|(&source, &target)| source != RemapKey::Compose && source != target)
+            .filter_map(|(&source, target)| Some((source, target.virtual_key()?)))
+            .collect();
+        Self { table }
+    }
+
+    /// Key pressed in place of `key`, if it's remapped; `VirtualKey::Compose`
+    /// if it starts compose sequences
+    pub fn target(&self, key: RemapKey) -> Option<VirtualKey> {
+        self.table.get(&key).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names_match_the_config_file() {
+        for key in RemapKey::all() {
+            let written = toml::Value::try_from(key).unwrap();
+            assert_eq!(written.as_str(), Some(key.to_string().as_str()));
+        }
+        assert_eq!(RemapKey::all().count(), NAMES.len());
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Table {
+        #[serde(deserialize_with = "deserialize_table")]
+        remap: BTreeMap<RemapKey, RemapKey>,
+    }
+
+    #[test]
+    fn test_unusable_entries_are_refused() {
+        let table: Table =
+            toml::from_str("[remap]\ncaps_lock = \"escape\"\nright_ctrl = \"compose\"\n").unwrap();
+        let remap = Remap::from_config(&table.remap);
+        assert_eq!(remap.target(RemapKey::CapsLock), Some(VirtualKey::Escape));
+        assert_eq!(remap.target(RemapKey::RightCtrl), Some(VirtualKey::Compose));
+
+        let error = toml::from_str::<Table>("[remap]\nmenu = \"left_ctrl\"\n").unwrap_err();
+        assert!(error.to_string().contains("a modifier has to be held"), "{}", error);
+        let error = toml::from_str::<Table>("[remap]\ncompose = \"tab\"\n").unwrap_err();
+        assert!(error.to_string().contains("isn't a key"), "{}", error);
+    }
+
+    #[test]
+    fn test_entries_from_elsewhere_are_left_out() {
+        let config = BTreeMap::from([
+            (RemapKey::Menu, RemapKey::LeftCtrl),
+            (RemapKey::Compose, RemapKey::Tab),
+            (RemapKey::Insert, RemapKey::Insert),
+            (RemapKey::CapsLock, RemapKey::Escape),
+        ]);
+        let remap = Remap::from_config(&config);
+        assert_eq!(remap.target(RemapKey::Menu), None);
+        assert_eq!(remap.target(RemapKey::Compose), None);
+        assert_eq!(remap.target(RemapKey::Insert), None);
+        assert_eq!(remap.target(RemapKey::CapsLock), Some(VirtualKey::Escape));
+    }
+}
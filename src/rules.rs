@@ -0,0 +1,182 @@
+//! What the configured rules make of a window
+//!
+//! Per-app and per-window rules (`[terminals]`, `[injection.apps]`,
+//! `[injection.delays]`) fail silently: a title regex that never matches or
+//! a misspelled executable just leaves the default in place. `ghostkeys ctl
+//! rules-test` asks the running instance for this report on the foreground
+//! window, and `--follow` asks again as focus moves. Diagnostics, so the text
+//! stays in English.
+
+use crate::config::{Config, InjectionBackend, TerminalMatch, WindowInfo, WindowRule};
+use crate::history::Cause;
+use crate::mapper::Direction;
+use crate::state::OperationMode;
+
+/// What the running instance is doing, whatever the window
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Status {
+    /// Whether keys are remapped
+    pub mode: OperationMode,
+    /// Why the mode last changed, if it did
+    pub cause: Option<Cause>,
+    /// Translation direction
+    pub direction: Direction,
+}
+
+/// One line per setting in effect for `window`, and the rule that decided
+/// it; `window` is `None` where windows can't be looked up
+pub fn report(config: &Config, window: Option<&WindowInfo>, status: &Status) -> String {
+    let mode = match status.mode {
+        OperationMode::Active => "active",
+        OperationMode::Passthrough => "paused",
+    };
+    let mut lines = vec![match &status.cause {
+        Some(cause) => format!("Mode: {} ({})", mode, cause),
+        None => format!("Mode: {}", mode),
+    }];
+    lines.push(match status.direction {
+        Direction::UsToAbnt2 => "Direction: US keyboard, ABNT2 typing".to_string(),
+        Direction::Abnt2ToUs => "Direction: ABNT2 keyboard, US typing".to_string(),
+    });
+
+    let Some(window) = window else {
+        lines.push("Window: not looked up on this platform, where no per-app rules apply".into());
+        return lines.join("\n");
+    };
+    lines.push(format!("Window: {}", describe_window(window)));
+
+    let terminals = &config.terminals;
+    let terminal = terminals.terminal_match(window);
+    lines.push(match terminal {
+        Some(TerminalMatch::BuiltIn) => "Terminal: yes, one GhostKeys knows".to_string(),
+        Some(TerminalMatch::App) => "Terminal: yes, listed in [terminals] apps".to_string(),
+        Some(TerminalMatch::Window(index)) => format!(
+            "Terminal: yes, by [[terminals.windows]] rule {} ({})",
+            index + 1,
+            describe_rule(&terminals.windows[index])
+        ),
+        None if terminals.windows.is_empty() => "Terminal: no".to_string(),
+        None => format!(
+            "Terminal: no, none of the {} [[terminals.windows]] rules match",
+            terminals.windows.len()
+        ),
+    });
+    lines.push(match (terminals.dead_keys, terminal) {
+        (true, _) => "Dead keys: on".to_string(),
+        (false, Some(_)) => "Dead keys: off, a terminal with [terminals] dead_keys = false".into(),
+        (false, None) => "Dead keys: on, not a terminal".to_string(),
+    });
+
+    let injection = &config.injection;
+    let app = window.app.as_deref();
+    lines.push(match injection.backend_rule(app) {
+        Some((rule, backend)) => {
+            format!("Injection: {}, by [injection.apps] \"{}\"", backend_name(backend), rule)
+        }
+        None => format!("Injection: {}, the default", backend_name(injection.backend)),
+    });
+    let delay = injection.char_delay_for(app).as_millis();
+    lines.push(match injection.delay_rule(app) {
+        Some((rule, _)) => {
+            format!("Delay between characters: {} ms, by [injection.delays] \"{}\"", delay, rule)
+        }
+        None => format!("Delay between characters: {} ms, the default", delay),
+    });
+    lines.join("\n")
+}
+
+/// `chrome.exe, class "Chrome_WidgetWin_1", title "Inbox"`
+fn describe_window(window: &WindowInfo) -> String {
+    let quoted = |value: &Option<String>| match value {
+        Some(value) => format!("\"{}\"", value),
+        None => "unknown".to_string(),
+    };
+    format!(
+        "{}, class {}, title {}",
+        window.app.as_deref().unwrap_or("unknown application"),
+        quoted(&window.class),
+        quoted(&window.title)
+    )
+}
+
+/// A window rule as it would be written in the config
+fn describe_rule(rule: &WindowRule) -> String {
+    let mut parts = Vec::new();
+    if let Some(app) = &rule.app {
+        parts.push(format!("app = \"{}\"", app));
+    }
+    if let Some(class) = &rule.class {
+        parts.push(format!("class = \"{}\"", class.as_str()));
+    }
+    if let Some(title) = &rule.title {
+        parts.push(format!("title = \"{}\"", title.as_str()));
+    }
+    parts.join(", ")
+}
+
+/// Name of a backend in the config
+fn backend_name(backend: InjectionBackend) -> &'static str {
+    match backend {
+        InjectionBackend::SendInput => "send_input",
+        InjectionBackend::WmChar => "wm_char",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Pattern;
+
+    fn active() -> Status {
+        Status { mode: OperationMode::Active, cause: None, direction: Direction::UsToAbnt2 }
+    }
+
+    #[test]
+    fn test_report_names_the_matching_rules() {
+        let mut config = Config::default();
+        config.terminals.dead_keys = false;
+        config.terminals.windows.push(WindowRule {
+            title: Some(Pattern::new("(?i)ssh").unwrap()),
+            ..WindowRule::default()
+        });
+        config.injection.delays.insert("Chrome.exe".to_string(), 5);
+        let window = WindowInfo {
+            app: Some("chrome.exe".to_string()),
+            class: Some("Chrome_WidgetWin_1".to_string()),
+            title: Some("SSH".to_string()),
+        };
+
+        let text = report(&config, Some(&window), &active());
+        assert_eq!(
+            text.lines().collect::<Vec<_>>(),
+            [
+                "Mode: active",
+                "Direction: US keyboard, ABNT2 typing",
+                "Window: chrome.exe, class \"Chrome_WidgetWin_1\", title \"SSH\"",
+                "Terminal: yes, by [[terminals.windows]] rule 1 (title = \"(?i)ssh\")",
+                "Dead keys: off, a terminal with [terminals] dead_keys = false",
+                "Injection: send_input, the default",
+                "Delay between characters: 5 ms, by [injection.delays] \"Chrome.exe\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_report_without_matches() {
+        let mut config = Config::default();
+        config.terminals.windows.push(WindowRule::default());
+        config.terminals.windows[0].title = Some(Pattern::new("ssh").unwrap());
+        let status = Status {
+            mode: OperationMode::Passthrough,
+            cause: Some(Cause::Hotkey),
+            ..active()
+        };
+
+        let text = report(&config, Some(&WindowInfo::app("notepad.exe")), &status);
+        assert!(text.starts_with("Mode: paused (hotkey)\n"));
+        assert!(text.contains("Terminal: no, none of the 1 [[terminals.windows]] rules match"));
+        assert!(text.contains("Dead keys: on\n"));
+
+        assert_eq!(report(&config, None, &status).lines().count(), 3);
+    }
+}
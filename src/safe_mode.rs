@@ -0,0 +1,188 @@
+//! Safe mode after repeated crashes
+//!
+//! A config or layout that crashes GhostKeys at startup would crash it on
+//! every launch, and with autostart or a supervisor that means a keyboard
+//! acting up at every login. Each launch is recorded in `launches.toml` next
+//! to the config file until it exits cleanly or stays up for
+//! `STABLE_UPTIME`; after `CRASHES_BEFORE_SAFE_MODE` launches in a row that
+//! did neither, GhostKeys starts with the built-in defaults instead (plain
+//! ABNT2, no compose, macros, profiles or custom layout) and offers to open
+//! the config file. Nothing is saved over the user's files in safe mode, and
+//! the next launch after a clean exit tries their config again.
+
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{io_error, line_number};
+use crate::error::{GhostKeysError, Result};
+use crate::paths;
+use crate::state::SharedState;
+
+/// Launches in a row that ended abnormally before starting in safe mode
+pub const CRASHES_BEFORE_SAFE_MODE: u32 = 3;
+
+/// A launch that stays up this long isn't part of a crash loop
+pub const STABLE_UPTIME: Duration = Duration::from_secs(60);
+
+/// Set at startup when GhostKeys runs in safe mode
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Launches that haven't ended cleanly, saved between runs
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Launches {
+    /// A launch is running, or ended without a clean exit
+    pub running: bool,
+    /// Launches in a row that ended without a clean exit
+    pub abnormal_exits: u32,
+}
+
+impl Launches {
+    /// Count a new launch; returns true if it must run in safe mode
+    pub fn start(&mut self) -> bool {
+        if self.running {
+            self.abnormal_exits += 1;
+        }
+        self.running = true;
+        self.abnormal_exits >= CRASHES_BEFORE_SAFE_MODE
+    }
+
+    /// The launch stayed up long enough: if it ends abnormally now, it's the
+    /// first in a row
+    pub fn stable(&mut self) {
+        self.abnormal_exits = 0;
+    }
+
+    /// The launch exited cleanly
+    pub fn exit(&mut self) {
+        self.running = false;
+        self.abnormal_exits = 0;
+    }
+
+    /// Parse saved launches, naming the file and line in errors
+    pub fn parse(text: &str, path: Option<&Path>) -> Result<Self> {
+        toml::from_str(text).map_err(|e| GhostKeysError::ConfigError {
+            path: path.map(Path::to_path_buf),
+            line: e.span().map(|span| line_number(text, span.start)),
+            message: e.message().to_string(),
+        })
+    }
+
+    /// Load the saved launches; none yet is not an error
+    pub fn load() -> Result<Self> {
+        let Some(path) = paths::launches_file() else {
+            return Ok(Self::default());
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text, Some(&path)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(io_error(&path, e)),
+        }
+    }
+
+    /// Save the launches next to the config file
+    pub fn save(&self) -> Result<()> {
+        let path = paths::launches_file().ok_or_else(|| GhostKeysError::ConfigError {
+            path: None,
+            line: None,
+            message: "could not determine the config directory".to_string(),
+        })?;
+        let text = toml::to_string_pretty(self).map_err(|e| GhostKeysError::ConfigError {
+            path: Some(path.clone()),
+            line: None,
+            message: e.to_string(),
+        })?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| io_error(dir, e))?;
+        }
+        std::fs::write(&path, text).map_err(|e| io_error(&path, e))
+    }
+}
+
+/// Record that GhostKeys is starting; returns the abnormal exits in a row if
+/// there were enough to start in safe mode
+///
+/// A launches file that can't be read counts as none, so it can't keep
+/// GhostKeys in safe mode.
+pub fn record_start() -> Option<u32> {
+    let mut launches = Launches::load().unwrap_or_default();
+    let safe = launches.start();
+    let _ = launches.save();
+    ACTIVE.store(safe, Ordering::Relaxed);
+    safe.then_some(launches.abnormal_exits)
+}
+
+/// Record a clean exit
+pub fn record_exit() {
+    let mut launches = Launches::load().unwrap_or_default();
+    launches.exit();
+    let _ = launches.save();
+}
+
+/// Whether this launch runs in safe mode
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+/// Forget earlier crashes once this launch has been up for `STABLE_UPTIME`
+pub fn spawn_stable_check(state: SharedState) {
+    thread::spawn(move || {
+        thread::sleep(STABLE_UPTIME);
+        if state.should_exit() {
+            return;
+        }
+        let mut launches = Launches::load().unwrap_or_default();
+        launches.stable();
+        let _ = launches.save();
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Launch, then exit cleanly or crash
+    fn run(launches: &mut Launches, clean: bool) -> bool {
+        let safe = launches.start();
+        if clean {
+            launches.exit();
+        }
+        safe
+    }
+
+    #[test]
+    fn test_safe_mode_after_crashes_in_a_row() {
+        let mut launches = Launches::default();
+        assert!(!run(&mut launches, false));
+        assert!(!run(&mut launches, false));
+        assert!(!run(&mut launches, false));
+        // Three crashed in a row before this one
+        assert!(run(&mut launches, true));
+        assert_eq!(launches, Launches::default());
+        assert!(!run(&mut launches, false));
+    }
+
+    #[test]
+    fn test_stable_launch_starts_the_count_over() {
+        let mut launches = Launches::default();
+        for _ in 0..3 {
+            run(&mut launches, false);
+        }
+        launches.start();
+        launches.stable();
+        // Crashed after running for a while
+        assert!(!launches.start());
+        assert_eq!(launches.abnormal_exits, 1);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let launches = Launches { running: true, abnormal_exits: 2 };
+        let text = toml::to_string_pretty(&launches).unwrap();
+        assert_eq!(Launches::parse(&text, None).unwrap(), launches);
+    }
+}
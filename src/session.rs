@@ -0,0 +1,200 @@
+//! What GhostKeys was doing when it last stopped
+//!
+//! Choices made while running (pausing, perhaps for 15 minutes, reverse
+//! mode, a custom layout) would be lost on every restart, so they're kept in
+//! `session.toml` next to the config file whenever they change and again at
+//! exit, and applied at startup. Pauses made by rules, the self-test or the
+//! flood limit aren't kept: the rules decide again on the next start. The
+//! active profile lives in the config (`[profiles]`) and the remap counts in
+//! `stats.toml`.
+
+use std::path::Path;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{io_error, line_number};
+use crate::error::{GhostKeysError, Result};
+use crate::history::Cause;
+use crate::layout::klc;
+use crate::mapper::Direction;
+use crate::paths;
+use crate::state::{OperationMode, SharedState, StateChange};
+
+/// Choices of the moment, saved between runs
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Session {
+    /// Paused by the user (tray, hotkey or `ghostkeys ctl`)
+    pub paused: bool,
+    /// When a timed pause (`pause_15m`) ends, in seconds since the Unix epoch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub paused_until: Option<u64>,
+    /// Reverse mode: ABNT2 keyboard, US muscle memory
+    pub reverse: bool,
+    /// Custom layout in use, as the contents of a KLC file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layout: Option<String>,
+}
+
+impl Session {
+    /// The choices in effect in `state`
+    pub fn capture(state: &SharedState) -> Result<Self> {
+        let cause = state.last_mode_cause()?;
+        let chosen = matches!(
+            cause,
+            Some(Cause::User | Cause::Command | Cause::Hotkey | Cause::Restored)
+        );
+        let paused = state.is_paused() && chosen;
+        // Only a hotkey starts a timed pause; a later pause from elsewhere has no end
+        let timed = matches!(cause, Some(Cause::Hotkey | Cause::Restored));
+        Ok(Self {
+            paused,
+            paused_until: if paused && timed { state.paused_until()? } else { None },
+            reverse: state.get_direction()? == Direction::Abnt2ToUs,
+            layout: state.get_layout()?.map(|layout| klc::export(&layout)),
+        })
+    }
+
+    /// Apply the saved choices to `state`; `now` is in seconds since the Unix
+    /// epoch, and a timed pause that ended in the meantime isn't restored
+    pub fn restore(&self, state: &SharedState, now: u64) -> Result<()> {
+        // Checked before anything changes
+        let layout = self.layout.as_deref().map(klc::parse).transpose()?;
+        if layout.is_some() {
+            state.set_layout(layout)?;
+        }
+        if self.reverse {
+            state.set_direction(Direction::Abnt2ToUs)?;
+        }
+        let ended = self.paused_until.is_some_and(|until| until <= now);
+        if self.paused && !ended {
+            state.set_paused_until(self.paused_until)?;
+            state.set_mode_because(OperationMode::Passthrough, Cause::Restored)?;
+        }
+        Ok(())
+    }
+
+    /// Parse a saved session, naming the file and line in errors
+    pub fn parse(text: &str, path: Option<&Path>) -> Result<Self> {
+        toml::from_str(text).map_err(|e| GhostKeysError::ConfigError {
+            path: path.map(Path::to_path_buf),
+            line: e.span().map(|span| line_number(text, span.start)),
+            message: e.message().to_string(),
+        })
+    }
+
+    /// Load the last session; none yet is not an error
+    pub fn load() -> Result<Self> {
+        let Some(path) = paths::session_file() else {
+            return Ok(Self::default());
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text, Some(&path)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(io_error(&path, e)),
+        }
+    }
+
+    /// Save the session next to the config file
+    pub fn save(&self) -> Result<()> {
+        let path = paths::session_file().ok_or_else(|| GhostKeysError::ConfigError {
+            path: None,
+            line: None,
+            message: "could not determine the config directory".to_string(),
+        })?;
+        let text = toml::to_string_pretty(self).map_err(|e| GhostKeysError::ConfigError {
+            path: Some(path.clone()),
+            line: None,
+            message: e.to_string(),
+        })?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| io_error(dir, e))?;
+        }
+        std::fs::write(&path, text).map_err(|e| io_error(&path, e))
+    }
+}
+
+/// Seconds since the Unix epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |t| t.as_secs())
+}
+
+/// Save the session on a background thread whenever the mode, direction or
+/// layout changes, so it survives a crash or a shutdown that skips the exit
+pub fn spawn(state: SharedState) {
+    let Ok(changes) = state.subscribe() else {
+        return;
+    };
+    thread::spawn(move || {
+        for change in changes {
+            if matches!(
+                change,
+                StateChange::ModeReason(_) | StateChange::Config | StateChange::AccentPending(_)
+            ) {
+                continue;
+            }
+            if let Err(e) = Session::capture(&state).and_then(|session| session.save()) {
+                eprintln!("Failed to save the session: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::Layout;
+
+    #[test]
+    fn test_round_trip_through_state() {
+        let state = SharedState::new();
+        state.set_mode_because(OperationMode::Passthrough, Cause::Hotkey).unwrap();
+        state.set_paused_until(Some(1_000)).unwrap();
+        state.set_direction(Direction::Abnt2ToUs).unwrap();
+        state.set_layout(Some(Layout::abnt2())).unwrap();
+        let session = Session::capture(&state).unwrap();
+        assert!(session.paused && session.reverse && session.layout.is_some());
+        assert_eq!(session.paused_until, Some(1_000));
+
+        let text = toml::to_string_pretty(&session).unwrap();
+        let saved = Session::parse(&text, None).unwrap();
+        assert_eq!(saved, session);
+
+        let restored = SharedState::new();
+        saved.restore(&restored, 500).unwrap();
+        assert!(restored.is_paused());
+        assert_eq!(restored.last_mode_cause().unwrap(), Some(Cause::Restored));
+        assert_eq!(restored.paused_until().unwrap(), Some(1_000));
+        assert_eq!(restored.get_direction().unwrap(), Direction::Abnt2ToUs);
+        assert!(restored.get_layout().unwrap().is_some());
+        // Restored again on the next start
+        assert_eq!(Session::capture(&restored).unwrap(), session);
+    }
+
+    #[test]
+    fn test_ended_pause_is_not_restored() {
+        let session = Session { paused: true, paused_until: Some(1_000), ..Session::default() };
+        let state = SharedState::new();
+        session.restore(&state, 1_000).unwrap();
+        assert!(!state.is_paused());
+    }
+
+    #[test]
+    fn test_pauses_by_rules_are_not_kept() {
+        let state = SharedState::new();
+        state.set_mode_by_rule(OperationMode::Passthrough, "ABNT2 keyboard").unwrap();
+        state.set_paused_until(Some(1_000)).unwrap();
+        assert_eq!(Session::capture(&state).unwrap(), Session::default());
+
+        // A pause from the tray has no end, even after a timed one
+        state.set_mode_because(OperationMode::Active, Cause::User).unwrap();
+        state.set_mode_because(OperationMode::Passthrough, Cause::User).unwrap();
+        let session = Session::capture(&state).unwrap();
+        assert!(session.paused);
+        assert_eq!(session.paused_until, None);
+    }
+}
@@ -1,9 +1,17 @@
 //! Shared state types for GhostKeys
 
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::{Arc, Mutex};
 
+use crate::config::Config;
 use crate::error::{GhostKeysError, Result};
+use crate::events::{Event, EventBus, SubscriptionId};
+use crate::flags::{ExitSignal, PauseFlag, ReverseFlag};
+use crate::history::{Cause, Change, Entry, History};
+use crate::layout::Layout;
+use crate::mapper::Direction;
+use crate::notify::{Notification, Notifications, Severity};
+use crate::stats::Stats;
 
 /// Operation mode for GhostKeys
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
@@ -15,76 +23,441 @@ pub enum OperationMode {
     Passthrough,
 }
 
+/// A change to the shared state, delivered to subscribers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateChange {
+    /// The operation mode changed
+    Mode(OperationMode),
+    /// Why the mode just changed, when a rule changed it rather than the user
+    ModeReason(String),
+    /// The translation direction changed
+    Direction(Direction),
+    /// The custom layout was replaced
+    Layout,
+    /// The user configuration was replaced
+    Config,
+    /// A dead key started or stopped waiting for its letter
+    AccentPending(bool),
+}
+
 /// Application state shared between threads
 #[derive(Debug)]
 pub struct AppState {
-    /// Current operation mode
-    pub mode: OperationMode,
+    /// Custom layout replacing the built-in ABNT2 tables, if any
+    pub layout: Option<Layout>,
+    /// User configuration
+    pub config: Config,
+    /// Messages waiting to be shown by the tray
+    pub notifications: Notifications,
+    /// Remap counts, recorded when `[statistics]` is enabled
+    pub stats: Stats,
+    /// Recent mode and layout changes
+    pub history: History,
+    /// When a timed pause (`pause_15m`) ends, in seconds since the Unix epoch
+    pub paused_until: Option<u64>,
+    /// A dead key is waiting for its letter
+    pub accent_pending: bool,
+    /// Channels notified of every state change
+    subscribers: Vec<Sender<StateChange>>,
+}
+
+impl AppState {
+    /// Send a change to every subscriber, forgetting those that hung up
+    fn publish(&mut self, change: StateChange) {
+        self.subscribers
+            .retain(|subscriber| subscriber.send(change.clone()).is_ok());
+    }
 }
 
 impl Default for AppState {
     fn default() -> Self {
         Self {
-            mode: OperationMode::Active,
+            layout: None,
+            config: Config::default(),
+            notifications: Notifications::new(),
+            stats: Stats::default(),
+            history: History::new(),
+            paused_until: None,
+            accent_pending: false,
+            subscribers: Vec::new(),
+        }
+    }
+}
+
+/// An event subscriber, taken off the bus when this is dropped
+///
+/// Must not be dropped by the subscriber itself, which runs with the bus
+/// locked.
+#[derive(Debug)]
+#[must_use = "the subscriber is taken off the bus as soon as this is dropped"]
+pub struct EventSubscription {
+    events: Arc<Mutex<EventBus>>,
+    id: SubscriptionId,
+}
+
+impl EventSubscription {
+    /// Take the subscriber off the bus now
+    pub fn unsubscribe(self) {}
+}
+
+impl Drop for EventSubscription {
+    fn drop(&mut self) {
+        if let Ok(mut events) = self.events.lock() {
+            events.unsubscribe(self.id);
         }
     }
 }
 
 /// Thread-safe wrapper for shared application state
+///
+/// The operation mode and direction live in atomics outside the lock so the
+/// keyboard hook can check them on every key press without contending with
+/// the tray (see `flags` for the memory orderings).
 #[derive(Debug, Clone)]
 pub struct SharedState {
     inner: Arc<Mutex<AppState>>,
-    exit_flag: Arc<AtomicBool>,
+    paused: Arc<PauseFlag>,
+    reverse: Arc<ReverseFlag>,
+    exit_flag: Arc<ExitSignal>,
+    /// Behind its own lock, so subscribers can read the state
+    events: Arc<Mutex<EventBus>>,
 }
 
 impl SharedState {
     /// Create a new shared state with default values
     pub fn new() -> Self {
+        let inner = Arc::new(Mutex::new(AppState::default()));
+        let mut events = EventBus::new();
+        events.subscribe(count_remaps(Arc::clone(&inner)));
         Self {
-            inner: Arc::new(Mutex::new(AppState::default())),
-            exit_flag: Arc::new(AtomicBool::new(false)),
+            inner,
+            paused: Arc::new(PauseFlag::new()),
+            reverse: Arc::new(ReverseFlag::new()),
+            exit_flag: Arc::new(ExitSignal::new()),
+            events: Arc::new(Mutex::new(events)),
         }
     }
 
     /// Get the current operation mode
     pub fn get_mode(&self) -> Result<OperationMode> {
-        self.inner
-            .lock()
-            .map(|state| state.mode)
-            .map_err(|_| GhostKeysError::StateLockPoisoned)
+        Ok(if self.is_paused() {
+            OperationMode::Passthrough
+        } else {
+            OperationMode::Active
+        })
+    }
+
+    /// Check if keys must pass through unmodified, without taking the lock
+    pub fn is_paused(&self) -> bool {
+        self.paused.is_set()
     }
 
-    /// Set the operation mode
+    /// Set the operation mode at the user's request
     pub fn set_mode(&self, mode: OperationMode) -> Result<()> {
-        self.inner
+        self.set_mode_because(mode, Cause::User)
+    }
+
+    /// Set the operation mode, recording why in the history
+    pub fn set_mode_because(&self, mode: OperationMode, cause: Cause) -> Result<()> {
+        // Held while flipping so subscribers see changes in order
+        let mut state = self
+            .inner
             .lock()
-            .map(|mut state| state.mode = mode)
-            .map_err(|_| GhostKeysError::StateLockPoisoned)
+            .map_err(|_| GhostKeysError::StateLockPoisoned)?;
+
+        let paused = mode == OperationMode::Passthrough;
+        if self.paused.swap(paused) != paused {
+            state.publish(StateChange::Mode(mode));
+            state.history.record(Change::Mode(mode), cause);
+            drop(state);
+            self.publish(Event::ModeChanged(mode))?;
+        }
+        Ok(())
     }
 
-    /// Toggle between Active and Passthrough modes
+    /// Set the operation mode on behalf of a rule (e.g., auto-pause)
+    ///
+    /// The reason is queued as a warning and, if the mode actually changed,
+    /// published right after the change so the tray can explain it.
+    pub fn set_mode_by_rule(&self, mode: OperationMode, reason: impl Into<String>) -> Result<()> {
+        let reason = reason.into();
+        let mut state = self
+            .inner
+            .lock()
+            .map_err(|_| GhostKeysError::StateLockPoisoned)?;
+
+        let paused = mode == OperationMode::Passthrough;
+        let changed = self.paused.swap(paused) != paused;
+        if changed {
+            state.publish(StateChange::Mode(mode));
+            state.publish(StateChange::ModeReason(reason.clone()));
+            state.history.record(Change::Mode(mode), Cause::Rule(reason.clone()));
+        }
+        state.notifications.push(Severity::Warning, reason);
+        drop(state);
+        if changed {
+            self.publish(Event::ModeChanged(mode))?;
+        }
+        Ok(())
+    }
+
+    /// Toggle between Active and Passthrough modes at the user's request
     pub fn toggle_mode(&self) -> Result<OperationMode> {
+        self.toggle_mode_because(Cause::User)
+    }
+
+    /// Toggle between Active and Passthrough modes, recording why in the
+    /// history
+    pub fn toggle_mode_because(&self, cause: Cause) -> Result<OperationMode> {
         let mut state = self
             .inner
             .lock()
             .map_err(|_| GhostKeysError::StateLockPoisoned)?;
 
-        state.mode = match state.mode {
-            OperationMode::Active => OperationMode::Passthrough,
-            OperationMode::Passthrough => OperationMode::Active,
+        let mode = if self.paused.toggle() {
+            OperationMode::Active
+        } else {
+            OperationMode::Passthrough
         };
+        state.publish(StateChange::Mode(mode));
+        state.history.record(Change::Mode(mode), cause);
+        drop(state);
+        self.publish(Event::ModeChanged(mode))?;
+
+        Ok(mode)
+    }
+
+    /// Get the current translation direction, without taking the lock
+    pub fn get_direction(&self) -> Result<Direction> {
+        Ok(if self.reverse.is_set() {
+            Direction::Abnt2ToUs
+        } else {
+            Direction::UsToAbnt2
+        })
+    }
+
+    /// Set the translation direction
+    pub fn set_direction(&self, direction: Direction) -> Result<()> {
+        self.inner
+            .lock()
+            .map(|mut state| self.swap_direction(&mut state, direction))
+            .map_err(|_| GhostKeysError::StateLockPoisoned)
+    }
+
+    /// Flip the reverse flag while holding the lock, so subscribers see
+    /// changes in order
+    fn swap_direction(&self, state: &mut AppState, direction: Direction) {
+        let reverse = direction == Direction::Abnt2ToUs;
+        if self.reverse.swap(reverse) != reverse {
+            state.publish(StateChange::Direction(direction));
+        }
+    }
+
+    /// Get a copy of the custom layout, if one was loaded
+    pub fn get_layout(&self) -> Result<Option<Layout>> {
+        self.inner
+            .lock()
+            .map(|state| state.layout.clone())
+            .map_err(|_| GhostKeysError::StateLockPoisoned)
+    }
+
+    /// Replace the built-in ABNT2 tables with a custom layout
+    pub fn set_layout(&self, layout: Option<Layout>) -> Result<()> {
+        self.inner
+            .lock()
+            .map(|mut state| {
+                if state.layout != layout {
+                    let name = layout.as_ref().map(|layout| layout.name.clone());
+                    state.layout = layout;
+                    state.publish(StateChange::Layout);
+                    state.history.record(Change::Layout(name), Cause::User);
+                }
+            })
+            .map_err(|_| GhostKeysError::StateLockPoisoned)
+    }
+
+    /// Recent mode and layout changes, oldest first
+    pub fn history(&self) -> Result<Vec<Entry>> {
+        self.inner
+            .lock()
+            .map(|state| state.history.entries())
+            .map_err(|_| GhostKeysError::StateLockPoisoned)
+    }
+
+    /// Why the mode last changed, if it changed since startup
+    pub fn last_mode_cause(&self) -> Result<Option<Cause>> {
+        self.inner
+            .lock()
+            .map(|state| state.history.last_mode_cause().cloned())
+            .map_err(|_| GhostKeysError::StateLockPoisoned)
+    }
+
+    /// When the current timed pause ends, if one was started
+    pub fn paused_until(&self) -> Result<Option<u64>> {
+        self.inner
+            .lock()
+            .map(|state| state.paused_until)
+            .map_err(|_| GhostKeysError::StateLockPoisoned)
+    }
+
+    /// Remember when a timed pause ends, or that none is running
+    pub fn set_paused_until(&self, until: Option<u64>) -> Result<()> {
+        self.inner
+            .lock()
+            .map(|mut state| state.paused_until = until)
+            .map_err(|_| GhostKeysError::StateLockPoisoned)
+    }
+
+    /// Record whether a dead key is waiting for its letter, for the tray badge
+    pub fn set_accent_pending(&self, pending: bool) -> Result<()> {
+        self.inner
+            .lock()
+            .map(|mut state| {
+                if state.accent_pending != pending {
+                    state.accent_pending = pending;
+                    state.publish(StateChange::AccentPending(pending));
+                }
+            })
+            .map_err(|_| GhostKeysError::StateLockPoisoned)
+    }
+
+    /// Get a copy of the user configuration
+    pub fn get_config(&self) -> Result<Config> {
+        self.inner
+            .lock()
+            .map(|state| state.config.clone())
+            .map_err(|_| GhostKeysError::StateLockPoisoned)
+    }
+
+    /// Replace the user configuration
+    ///
+    /// A `direction` that differs from the old config's is switched to; an
+    /// unchanged one leaves a direction picked from the tray alone.
+    pub fn set_config(&self, config: Config) -> Result<()> {
+        self.inner
+            .lock()
+            .map(|mut state| {
+                if state.config != config {
+                    if state.config.direction != config.direction {
+                        self.swap_direction(&mut state, config.direction);
+                    }
+                    state.config = config;
+                    state.publish(StateChange::Config);
+                }
+            })
+            .map_err(|_| GhostKeysError::StateLockPoisoned)
+    }
+
+    /// Receive every future change to the mode, direction, layout, config or
+    /// pending accent
+    ///
+    /// Changes are delivered whichever thread makes them (tray, hook,
+    /// device rules), so listeners don't need to poll. Setting a value to
+    /// what it already is doesn't count as a change.
+    pub fn subscribe(&self) -> Result<Receiver<StateChange>> {
+        let (sender, receiver) = mpsc::channel();
+        self.inner
+            .lock()
+            .map(|mut state| state.subscribers.push(sender))
+            .map_err(|_| GhostKeysError::StateLockPoisoned)?;
+        Ok(receiver)
+    }
+
+    /// Queue a message for the tray to log and show
+    pub fn notify(&self, severity: Severity, message: impl Into<String>) -> Result<()> {
+        self.inner
+            .lock()
+            .map(|mut state| {
+                state.notifications.push(severity, message);
+            })
+            .map_err(|_| GhostKeysError::StateLockPoisoned)
+    }
+
+    /// Deliver `event` to every event subscriber, on this thread
+    pub fn publish(&self, event: Event) -> Result<()> {
+        self.events
+            .lock()
+            .map(|mut events| events.publish(&event))
+            .map_err(|_| GhostKeysError::StateLockPoisoned)
+    }
+
+    /// Call `subscriber` with every event published from now on, until the
+    /// subscription returned is dropped
+    ///
+    /// It runs on the publishing thread (often the keyboard hook), so it must
+    /// return quickly, and must not publish itself.
+    pub fn subscribe_events(
+        &self,
+        subscriber: impl FnMut(&Event) + Send + 'static,
+    ) -> Result<EventSubscription> {
+        let id = self
+            .events
+            .lock()
+            .map(|mut events| events.subscribe(subscriber))
+            .map_err(|_| GhostKeysError::StateLockPoisoned)?;
+        Ok(EventSubscription { events: Arc::clone(&self.events), id })
+    }
+
+    /// Remap counts so far
+    pub fn get_stats(&self) -> Result<Stats> {
+        self.inner
+            .lock()
+            .map(|state| state.stats.clone())
+            .map_err(|_| GhostKeysError::StateLockPoisoned)
+    }
+
+    /// Replace the remap counts (e.g., with those saved by an earlier run)
+    pub fn set_stats(&self, stats: Stats) -> Result<()> {
+        self.inner
+            .lock()
+            .map(|mut state| state.stats = stats)
+            .map_err(|_| GhostKeysError::StateLockPoisoned)
+    }
 
-        Ok(state.mode)
+    /// Count a character picked from the palette while `[statistics]` is enabled
+    pub fn record_palette_pick(&self, text: &str) -> Result<()> {
+        self.inner
+            .lock()
+            .map(|mut state| {
+                if state.config.statistics.enabled {
+                    state.stats.record_pick(text);
+                }
+            })
+            .map_err(|_| GhostKeysError::StateLockPoisoned)
+    }
+
+    /// Take all queued messages
+    pub fn take_notifications(&self) -> Result<Vec<Notification>> {
+        self.inner
+            .lock()
+            .map(|mut state| state.notifications.drain())
+            .map_err(|_| GhostKeysError::StateLockPoisoned)
+    }
+
+    /// Take all queued messages without waiting for the lock
+    ///
+    /// For the crash handler: the crashing thread may be the one holding the
+    /// lock, so it gets nothing rather than deadlocking.
+    pub fn try_take_notifications(&self) -> Vec<Notification> {
+        self.inner
+            .try_lock()
+            .map(|mut state| state.notifications.drain())
+            .unwrap_or_default()
+    }
+
+    /// Current configuration, if the lock is free (see `try_take_notifications`)
+    pub fn try_get_config(&self) -> Option<Config> {
+        self.inner.try_lock().ok().map(|state| state.config.clone())
     }
 
     /// Signal that the application should exit
     pub fn signal_exit(&self) {
-        self.exit_flag.store(true, Ordering::SeqCst);
+        self.exit_flag.raise();
     }
 
     /// Check if the application should exit
     pub fn should_exit(&self) -> bool {
-        self.exit_flag.load(Ordering::SeqCst)
+        self.exit_flag.is_raised()
     }
 }
 
@@ -94,6 +467,20 @@ impl Default for SharedState {
     }
 }
 
+/// Statistics subscriber: counts decided actions while `[statistics]` is enabled
+fn count_remaps(inner: Arc<Mutex<AppState>>) -> impl FnMut(&Event) + Send {
+    move |event| {
+        let Event::ActionDecided { event, action } = event else {
+            return;
+        };
+        if let Ok(mut state) = inner.lock() {
+            if state.config.statistics.enabled {
+                state.stats.record(event, action);
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -117,6 +504,189 @@ mod tests {
         assert_eq!(mode, OperationMode::Active);
     }
 
+    #[test]
+    fn test_default_direction_is_us_to_abnt2() {
+        let state = SharedState::new();
+        assert_eq!(state.get_direction().unwrap(), Direction::UsToAbnt2);
+
+        state.set_direction(Direction::Abnt2ToUs).unwrap();
+        assert_eq!(state.get_direction().unwrap(), Direction::Abnt2ToUs);
+    }
+
+    #[test]
+    fn test_config_direction_is_applied_when_it_changes() {
+        let state = SharedState::new();
+        let mut config = Config { direction: Direction::Abnt2ToUs, ..Config::default() };
+        state.set_config(config.clone()).unwrap();
+        assert_eq!(state.get_direction().unwrap(), Direction::Abnt2ToUs);
+
+        // Picked from the tray, and kept through a reload that doesn't touch it
+        state.set_direction(Direction::UsToAbnt2).unwrap();
+        config.macros.enabled = true;
+        state.set_config(config).unwrap();
+        assert_eq!(state.get_direction().unwrap(), Direction::UsToAbnt2);
+    }
+
+    #[test]
+    fn test_notifications() {
+        let state = SharedState::new();
+        state.notify(Severity::Warning, "injection blocked").unwrap();
+        state.notify(Severity::Warning, "injection blocked").unwrap();
+
+        let notifications = state.take_notifications().unwrap();
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].message, "injection blocked");
+        assert!(state.take_notifications().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_subscribe_receives_changes() {
+        let state = SharedState::new();
+        let changes = state.subscribe().unwrap();
+
+        state.set_mode(OperationMode::Passthrough).unwrap();
+        state.set_mode(OperationMode::Passthrough).unwrap();
+        state.toggle_mode().unwrap();
+        state.set_direction(Direction::Abnt2ToUs).unwrap();
+        state.set_layout(Some(Layout::us())).unwrap();
+        state.set_config(Config::default()).unwrap();
+        state.set_accent_pending(true).unwrap();
+        state.set_accent_pending(true).unwrap();
+
+        let received: Vec<_> = changes.try_iter().collect();
+        assert_eq!(
+            received,
+            vec![
+                StateChange::Mode(OperationMode::Passthrough),
+                StateChange::Mode(OperationMode::Active),
+                StateChange::Direction(Direction::Abnt2ToUs),
+                StateChange::Layout,
+                StateChange::AccentPending(true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mode_change_by_rule_is_explained() {
+        let state = SharedState::new();
+        let changes = state.subscribe().unwrap();
+
+        state.set_mode_by_rule(OperationMode::Passthrough, "Paused: keyboard attached").unwrap();
+        // Already paused: nothing to explain, but the reason is still logged
+        state.set_mode_by_rule(OperationMode::Passthrough, "Paused: keyboard attached").unwrap();
+
+        let received: Vec<_> = changes.try_iter().collect();
+        assert_eq!(
+            received,
+            vec![
+                StateChange::Mode(OperationMode::Passthrough),
+                StateChange::ModeReason("Paused: keyboard attached".to_string()),
+            ]
+        );
+        assert!(state.is_paused());
+        assert_eq!(state.take_notifications().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_changes_are_recorded_with_their_cause() {
+        let state = SharedState::new();
+        state.set_mode_by_rule(OperationMode::Passthrough, "Paused: keyboard attached").unwrap();
+        state.toggle_mode_because(Cause::Hotkey).unwrap();
+        // Not a change
+        state.set_mode(OperationMode::Active).unwrap();
+        state.set_mode_because(OperationMode::Passthrough, Cause::Command).unwrap();
+
+        let recorded: Vec<_> = state
+            .history()
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.change, entry.cause))
+            .collect();
+        assert_eq!(
+            recorded,
+            vec![
+                (
+                    Change::Mode(OperationMode::Passthrough),
+                    Cause::Rule("Paused: keyboard attached".to_string())
+                ),
+                (Change::Mode(OperationMode::Active), Cause::Hotkey),
+                (Change::Mode(OperationMode::Passthrough), Cause::Command),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dropped_subscriber_is_forgotten() {
+        let state = SharedState::new();
+        drop(state.subscribe().unwrap());
+        let changes = state.subscribe().unwrap();
+
+        state.set_mode(OperationMode::Passthrough).unwrap();
+        assert_eq!(changes.try_recv(), Ok(StateChange::Mode(OperationMode::Passthrough)));
+        assert_eq!(state.inner.lock().unwrap().subscribers.len(), 1);
+    }
+
+    #[test]
+    fn test_stats_are_recorded_only_when_enabled() {
+        use crate::interceptor::{KeyAction, KeyEvent};
+        use crate::mapper::VirtualKey;
+
+        let state = SharedState::new();
+        let decided = Event::ActionDecided {
+            event: KeyEvent::press(VirtualKey::Semicolon, false),
+            action: KeyAction::Replace('ç'),
+        };
+        state.publish(decided.clone()).unwrap();
+        assert!(state.get_stats().unwrap().remaps.is_empty());
+
+        let mut config = Config::default();
+        config.statistics.enabled = true;
+        state.set_config(config).unwrap();
+        state.publish(decided).unwrap();
+        assert_eq!(state.get_stats().unwrap().remaps.values().sum::<u64>(), 1);
+    }
+
+    #[test]
+    fn test_palette_picks_are_recorded_only_when_enabled() {
+        let state = SharedState::new();
+        state.record_palette_pick("—").unwrap();
+        assert!(state.get_stats().unwrap().palette.is_empty());
+
+        let mut config = Config::default();
+        config.statistics.enabled = true;
+        state.set_config(config).unwrap();
+        state.record_palette_pick("—").unwrap();
+        state.record_palette_pick("—").unwrap();
+        assert_eq!(state.get_stats().unwrap().palette["—"], 2);
+    }
+
+    #[test]
+    fn test_mode_changes_are_published_as_events() {
+        let state = SharedState::new();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        let subscription =
+            state.subscribe_events(move |event| sink.lock().unwrap().push(event.clone())).unwrap();
+
+        state.set_mode(OperationMode::Passthrough).unwrap();
+        state.set_mode(OperationMode::Passthrough).unwrap();
+        state.toggle_mode().unwrap();
+        state.set_mode_by_rule(OperationMode::Passthrough, "ABNT2 keyboard").unwrap();
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [
+                Event::ModeChanged(OperationMode::Passthrough),
+                Event::ModeChanged(OperationMode::Active),
+                Event::ModeChanged(OperationMode::Passthrough),
+            ]
+        );
+
+        subscription.unsubscribe();
+        state.set_mode(OperationMode::Active).unwrap();
+        assert_eq!(seen.lock().unwrap().len(), 3);
+    }
+
     #[test]
     fn test_exit_flag() {
         let state = SharedState::new();
@@ -133,5 +703,6 @@ mod tests {
 
         state1.set_mode(OperationMode::Passthrough).unwrap();
         assert_eq!(state2.get_mode().unwrap(), OperationMode::Passthrough);
+        assert!(state2.is_paused());
     }
 }
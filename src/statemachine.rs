@@ -0,0 +1,310 @@
+//! Mapper state machine export
+//!
+//! `ghostkeys dump-statemachine [dot|mermaid]` prints the mapper's states and
+//! transitions as a Graphviz DOT graph or a Mermaid state diagram. Everything
+//! is read from a live `Mapper`, so custom layouts and compose sequences show
+//! up exactly as configured, and the diagram can't drift from the code.
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use crate::layout::us_char;
+use crate::mapper::{AccentType, Mapper, VirtualKey};
+
+/// Output format of the diagram
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    /// Graphviz DOT (`dot -Tsvg`)
+    #[default]
+    Dot,
+    /// Mermaid `stateDiagram-v2`, renderable in Markdown
+    Mermaid,
+}
+
+impl Format {
+    /// Parse a format name as given on the command line
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "dot" | "graphviz" => Some(Format::Dot),
+            "mermaid" => Some(Format::Mermaid),
+            _ => None,
+        }
+    }
+}
+
+/// A mapper state
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Node {
+    Idle,
+    Accent(AccentType),
+    Composing(String),
+}
+
+impl Node {
+    /// Identifier safe in both formats
+    fn id(&self, compose_prefixes: &[String]) -> String {
+        match self {
+            Node::Idle => "idle".to_string(),
+            Node::Accent(accent) => format!("accent_{:?}", accent).to_lowercase(),
+            Node::Composing(prefix) => {
+                let index = compose_prefixes.iter().position(|p| p == prefix).unwrap_or(0);
+                format!("compose_{}", index)
+            }
+        }
+    }
+
+    /// Human-readable state name
+    fn name(&self) -> String {
+        match self {
+            Node::Idle => "Idle".to_string(),
+            Node::Accent(accent) => format!("PendingAccent {}", accent.to_char()),
+            Node::Composing(prefix) if prefix.is_empty() => "Composing".to_string(),
+            Node::Composing(prefix) => format!("Composing {}", prefix),
+        }
+    }
+}
+
+/// Transitions between two states, with every trigger that takes it
+#[derive(Debug)]
+struct Edge {
+    from: Node,
+    to: Node,
+    labels: Vec<String>,
+}
+
+/// The state machine of a mapper
+#[derive(Debug, Default)]
+struct Graph {
+    nodes: BTreeSet<Node>,
+    edges: Vec<Edge>,
+}
+
+impl Graph {
+    fn add(&mut self, from: Node, to: Node, label: String) {
+        self.nodes.insert(from.clone());
+        self.nodes.insert(to.clone());
+        match self.edges.iter_mut().find(|e| e.from == from && e.to == to) {
+            Some(edge) if !edge.labels.contains(&label) => edge.labels.push(label),
+            Some(_) => {}
+            None => self.edges.push(Edge { from, to, labels: vec![label] }),
+        }
+    }
+}
+
+/// Name of a key press as typed on a US keyboard (e.g., `{` for Shift+[)
+fn key_label(key: VirtualKey, shift: bool) -> String {
+    match us_char(key, shift) {
+        Some(' ') => "Space".to_string(),
+        Some(c) => c.to_string(),
+        None if shift => format!("Shift+{:?}", key),
+        None => format!("{:?}", key),
+    }
+}
+
+/// Build the graph of everything `mapper` does in its current direction
+fn build(mapper: &Mapper) -> Graph {
+    let mut graph = Graph::default();
+    graph.nodes.insert(Node::Idle);
+
+    for ((key, shift), output) in mapper.mappings() {
+        graph.add(Node::Idle, Node::Idle, format!("{} → {}", key_label(key, shift), output));
+    }
+
+    let dead_keys: Vec<_> = mapper.dead_keys().collect();
+    for &((key, shift), accent) in &dead_keys {
+        graph.add(Node::Idle, Node::Accent(accent), key_label(key, shift));
+    }
+
+    let accents: BTreeSet<AccentType> = dead_keys.iter().map(|&(_, accent)| accent).collect();
+    let composing = !mapper.compose().is_empty();
+    for &accent in &accents {
+        let from = Node::Accent(accent);
+        let spacing = accent.to_char();
+        for ((combined_accent, base), combined) in mapper.combinations() {
+            if combined_accent == accent {
+                graph.add(from.clone(), Node::Idle, format!("{} → {}", base, combined));
+            }
+        }
+        graph.add(from.clone(), Node::Idle, format!("Space → {}", spacing));
+        graph.add(from.clone(), Node::Idle, format!("other key → {} + key", spacing));
+        graph.add(from.clone(), Node::Idle, "Esc → (nothing)".to_string());
+        for &((key, shift), next) in &dead_keys {
+            let label = format!("{} → {}", key_label(key, shift), spacing);
+            graph.add(from.clone(), Node::Accent(next), label);
+        }
+        if composing {
+            graph.add(from, Node::Composing(String::new()), format!("Compose → {}", spacing));
+        }
+    }
+
+    if composing {
+        graph.add(Node::Idle, Node::Composing(String::new()), "Compose".to_string());
+        for (sequence, output) in mapper.compose().sequences() {
+            let chars: Vec<char> = sequence.chars().collect();
+            for (i, &c) in chars.iter().enumerate() {
+                let prefix: String = chars[..i].iter().collect();
+                let next: String = chars[..=i].iter().collect();
+                // A shorter sequence fires first and hides this one
+                if i + 1 < chars.len() && mapper.compose().sequences().any(|(s, _)| s == next) {
+                    break;
+                }
+                if i + 1 == chars.len() {
+                    graph.add(Node::Composing(prefix), Node::Idle, format!("{} → {}", c, output));
+                } else {
+                    graph.add(Node::Composing(prefix), Node::Composing(next), c.to_string());
+                }
+            }
+        }
+        let prefixes: Vec<Node> = graph
+            .nodes
+            .iter()
+            .filter(|node| matches!(node, Node::Composing(_)))
+            .cloned()
+            .collect();
+        for node in prefixes {
+            graph.add(node.clone(), Node::Idle, "Compose → (cancel)".to_string());
+            graph.add(node, Node::Idle, "no match → typed literally".to_string());
+        }
+    }
+
+    graph
+}
+
+/// Escape text for a quoted DOT string
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Escape text for a Mermaid label, where ASCII punctuation can end the label
+fn mermaid_escape(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            if c.is_ascii_punctuation() {
+                format!("#{};", c as u32)
+            } else {
+                c.to_string()
+            }
+        })
+        .collect()
+}
+
+/// Render the state machine of `mapper` in the given format
+pub fn export(mapper: &Mapper, format: Format) -> String {
+    let graph = build(mapper);
+    let prefixes: Vec<String> = graph
+        .nodes
+        .iter()
+        .filter_map(|node| match node {
+            Node::Composing(prefix) => Some(prefix.clone()),
+            _ => None,
+        })
+        .collect();
+
+    let mut out = String::new();
+    match format {
+        Format::Dot => {
+            out.push_str("digraph ghostkeys {\n    rankdir=LR;\n    node [shape=ellipse];\n");
+            for node in &graph.nodes {
+                let _ = writeln!(
+                    out,
+                    "    {} [label=\"{}\"];",
+                    node.id(&prefixes),
+                    dot_escape(&node.name())
+                );
+            }
+            for edge in &graph.edges {
+                let labels: Vec<String> = edge.labels.iter().map(|l| dot_escape(l)).collect();
+                let _ = writeln!(
+                    out,
+                    "    {} -> {} [label=\"{}\"];",
+                    edge.from.id(&prefixes),
+                    edge.to.id(&prefixes),
+                    labels.join("\\n")
+                );
+            }
+            out.push_str("}\n");
+        }
+        Format::Mermaid => {
+            out.push_str("stateDiagram-v2\n    [*] --> idle\n");
+            for node in &graph.nodes {
+                let _ = writeln!(
+                    out,
+                    "    state \"{}\" as {}",
+                    mermaid_escape(&node.name()),
+                    node.id(&prefixes)
+                );
+            }
+            for edge in &graph.edges {
+                let labels: Vec<String> = edge.labels.iter().map(|l| mermaid_escape(l)).collect();
+                let _ = writeln!(
+                    out,
+                    "    {} --> {} : {}",
+                    edge.from.id(&prefixes),
+                    edge.to.id(&prefixes),
+                    labels.join("<br/>")
+                );
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::{ComposeTable, Direction};
+
+    #[test]
+    fn test_dot_lists_states_and_transitions() {
+        let dot = export(&Mapper::new(), Format::Dot);
+        assert!(dot.starts_with("digraph ghostkeys {"));
+        assert!(dot.contains("    idle [label=\"Idle\"];"));
+        assert!(dot.contains("    accent_tilde [label=\"PendingAccent ~\"];"));
+        assert!(dot.contains("    idle -> accent_tilde [label=\"'\"];"));
+        assert!(dot.contains("; → ç"));
+        assert!(dot.contains("a → ã"));
+        // No compose table, no compose states
+        assert!(!dot.contains("compose_"));
+    }
+
+    #[test]
+    fn test_compose_sequences_become_states() {
+        let mut table = ComposeTable::new();
+        table.insert("oc", "©");
+        table.insert("--", "–");
+        table.insert("---", "—");
+        let mut mapper = Mapper::new();
+        mapper.set_compose_table(table);
+
+        let dot = export(&mapper, Format::Dot);
+        assert!(dot.contains("[label=\"Composing o\"];"));
+        assert!(dot.contains("c → ©"));
+        assert!(dot.contains("- → –"));
+        // "---" is unreachable behind "--"
+        assert!(!dot.contains("—"));
+    }
+
+    #[test]
+    fn test_reverse_mode_has_no_accent_states() {
+        let mapper = Mapper::with_direction(Direction::Abnt2ToUs);
+        let dot = export(&mapper, Format::Dot);
+        assert!(!dot.contains("accent_"));
+        assert!(dot.contains("; → ;"));
+    }
+
+    #[test]
+    fn test_mermaid_escapes_punctuation() {
+        let mermaid = export(&Mapper::new(), Format::Mermaid);
+        assert!(mermaid.starts_with("stateDiagram-v2\n    [*] --> idle\n"));
+        assert!(mermaid.contains("    state \"PendingAccent #126;\" as accent_tilde"));
+        assert!(mermaid.contains("    idle --> accent_tilde : #39;"));
+        assert!(mermaid.contains("#59; → ç"));
+    }
+
+    #[test]
+    fn test_format_from_name() {
+        assert_eq!(Format::from_name("DOT"), Some(Format::Dot));
+        assert_eq!(Format::from_name("mermaid"), Some(Format::Mermaid));
+        assert_eq!(Format::from_name("svg"), None);
+    }
+}
@@ -0,0 +1,294 @@
+//! Local typing statistics
+//!
+//! With `enabled = true` under `[statistics]`, GhostKeys counts how often it
+//! remaps each US key position, which shows the ABNT2 features a user
+//! actually relies on (ç, the tilde key, moved brackets...) before they
+//! tweak their layout. Only counts per position are kept, never text, and
+//! they stay in `stats.toml` next to the config file until the user exports
+//! them with `ghostkeys stats export` as JSON or an SVG heatmap. Characters
+//! picked from the palette are counted there too, to list them first.
+//! `ghostkeys train` keeps the speed and accuracy of each drill session here
+//! as well, since the user asked for them by starting one.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{io_error, line_number};
+use crate::error::{GhostKeysError, Result};
+use crate::interceptor::{KeyAction, KeyEvent};
+use crate::layout::{us_char, KeyPosition};
+use crate::paths;
+
+/// US keyboard rows drawn in the heatmap, with each row's indent in key widths
+const ROWS: &[(f32, &str)] = &[
+    (0.0, "`1234567890-="),
+    (0.5, "qwertyuiop[]\\"),
+    (0.75, "asdfghjkl;'"),
+    (1.25, "zxcvbnm,./"),
+];
+
+/// Size of a key in the heatmap, in pixels
+const KEY_SIZE: f32 = 40.0;
+
+/// Drill sessions kept; older ones are dropped
+const DRILL_HISTORY: usize = 100;
+
+/// Remap counts, saved between runs
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Stats {
+    /// Key presses the mapper changed, per US key position
+    pub remaps: BTreeMap<KeyPosition, u64>,
+    /// Characters picked from the palette, to list the usual ones first
+    pub palette: BTreeMap<String, u64>,
+    /// `ghostkeys train` sessions, oldest first
+    pub drills: Vec<DrillResult>,
+}
+
+/// How a `ghostkeys train` session went
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DrillResult {
+    /// When it ended, in seconds since the Unix epoch
+    pub at: u64,
+    /// Sentences typed
+    pub sentences: u32,
+    /// Words (five characters) per minute
+    pub wpm: u32,
+    /// Characters typed right the first time, in percent
+    pub accuracy: u32,
+}
+
+impl Stats {
+    /// Count `event` if the mapper did anything other than let it through
+    pub fn record(&mut self, event: &KeyEvent, action: &KeyAction) {
+        if *action == KeyAction::Pass {
+            return;
+        }
+        // Through the typed character, so Char('a') and Char('A') count as one key
+        let position = us_char(event.key, event.modifiers.shift)
+            .and_then(|c| KeyPosition::parse(c.encode_utf8(&mut [0; 4])));
+        if let Some(position) = position {
+            *self.remaps.entry(position).or_default() += 1;
+        }
+    }
+
+    /// Count a character picked from the palette
+    pub fn record_pick(&mut self, text: &str) {
+        *self.palette.entry(text.to_string()).or_default() += 1;
+    }
+
+    /// Keep a drill session's result, dropping the oldest past `DRILL_HISTORY`
+    pub fn record_drill(&mut self, result: DrillResult) {
+        self.drills.push(result);
+        if self.drills.len() > DRILL_HISTORY {
+            self.drills.remove(0);
+        }
+    }
+
+    /// Remaps of a key, with and without Shift
+    pub fn key_total(&self, base: char) -> u64 {
+        self.remaps
+            .iter()
+            .filter(|(position, _)| us_char(position.key, false) == Some(base))
+            .map(|(_, count)| count)
+            .sum()
+    }
+
+    /// Parse saved statistics, naming the file and line in errors
+    pub fn parse(text: &str, path: Option<&Path>) -> Result<Self> {
+        toml::from_str(text).map_err(|e| GhostKeysError::ConfigError {
+            path: path.map(Path::to_path_buf),
+            line: e.span().map(|span| line_number(text, span.start)),
+            message: e.message().to_string(),
+        })
+    }
+
+    /// Load the saved statistics; none yet is not an error
+    pub fn load() -> Result<Self> {
+        let Some(path) = paths::stats_file() else {
+            return Ok(Self::default());
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text, Some(&path)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(io_error(&path, e)),
+        }
+    }
+
+    /// Save the statistics next to the config file
+    pub fn save(&self) -> Result<()> {
+        let path = paths::stats_file().ok_or_else(|| GhostKeysError::ConfigError {
+            path: None,
+            line: None,
+            message: "could not determine the config directory".to_string(),
+        })?;
+        let text = toml::to_string_pretty(self).map_err(|e| GhostKeysError::ConfigError {
+            path: Some(path.clone()),
+            line: None,
+            message: e.to_string(),
+        })?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| io_error(dir, e))?;
+        }
+        std::fs::write(&path, text).map_err(|e| io_error(&path, e))
+    }
+
+    /// Counts as JSON: `{"remaps": {"'": 12, "\"": 3}, "total": 15}`
+    pub fn to_json(&self) -> String {
+        let entries: Vec<String> = self
+            .remaps
+            .iter()
+            .map(|(position, count)| {
+                format!("\"{}\": {}", json_escape(&position.to_string()), count)
+            })
+            .collect();
+        let total: u64 = self.remaps.values().sum();
+        format!("{{\"remaps\": {{{}}}, \"total\": {}}}\n", entries.join(", "), total)
+    }
+
+    /// A US keyboard with each key shaded by how often it was remapped
+    pub fn to_svg(&self) -> String {
+        let max = ROWS
+            .iter()
+            .flat_map(|(_, keys)| keys.chars())
+            .map(|key| self.key_total(key))
+            .max()
+            .unwrap_or(0);
+        let width = 13.5 * KEY_SIZE;
+        let height = ROWS.len() as f32 * KEY_SIZE;
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+             font-family=\"sans-serif\" text-anchor=\"middle\">\n",
+            width, height
+        );
+        for (row, (indent, keys)) in ROWS.iter().enumerate() {
+            for (column, key) in keys.chars().enumerate() {
+                let count = self.key_total(key);
+                let x = (indent + column as f32) * KEY_SIZE;
+                let y = row as f32 * KEY_SIZE;
+                let center = x + KEY_SIZE / 2.0;
+                svg.push_str(&format!(
+                    "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"4\" fill=\"{}\" \
+                     stroke=\"#999\"><title>{}: {}</title></rect>\n",
+                    x + 1.0,
+                    y + 1.0,
+                    KEY_SIZE - 2.0,
+                    KEY_SIZE - 2.0,
+                    heat_color(count, max),
+                    xml_escape(key),
+                    count
+                ));
+                svg.push_str(&format!(
+                    "  <text x=\"{}\" y=\"{}\" font-size=\"14\">{}</text>\n",
+                    center,
+                    y + 18.0,
+                    xml_escape(key)
+                ));
+                if count > 0 {
+                    svg.push_str(&format!(
+                        "  <text x=\"{}\" y=\"{}\" font-size=\"9\">{}</text>\n",
+                        center,
+                        y + 32.0,
+                        count
+                    ));
+                }
+            }
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+/// Light grey for unused keys, shading to red for the most remapped one
+fn heat_color(count: u64, max: u64) -> String {
+    if count == 0 || max == 0 {
+        return "#f2f2f2".to_string();
+    }
+    let heat = count as f32 / max as f32;
+    let channel = |from: f32, to: f32| (from + (to - from) * heat).round() as u8;
+    let (red, green, blue) = (channel(254.0, 215.0), channel(224.0, 48.0), channel(144.0, 39.0));
+    format!("#{:02x}{:02x}{:02x}", red, green, blue)
+}
+
+fn json_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn xml_escape(c: char) -> String {
+    match c {
+        '&' => "&amp;".to_string(),
+        '<' => "&lt;".to_string(),
+        '>' => "&gt;".to_string(),
+        _ => c.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::VirtualKey;
+
+    fn counted(presses: &[(VirtualKey, bool, KeyAction)]) -> Stats {
+        let mut stats = Stats::default();
+        for (key, shift, action) in presses {
+            stats.record(&KeyEvent::press(*key, *shift), action);
+        }
+        stats
+    }
+
+    #[test]
+    fn test_record_counts_remapped_positions() {
+        let stats = counted(&[
+            (VirtualKey::Semicolon, false, KeyAction::Replace('ç')),
+            (VirtualKey::Semicolon, false, KeyAction::Replace('ç')),
+            (VirtualKey::Apostrophe, true, KeyAction::Suppress),
+            (VirtualKey::Char('A'), false, KeyAction::Pass),
+            (VirtualKey::Enter, false, KeyAction::ReplaceThenPass('~')),
+        ]);
+        assert_eq!(stats.remaps.len(), 2);
+        assert_eq!(stats.remaps[&KeyPosition::parse(";").unwrap()], 2);
+        assert_eq!(stats.remaps[&KeyPosition::parse("\"").unwrap()], 1);
+        assert_eq!(stats.key_total('\''), 1);
+    }
+
+    #[test]
+    fn test_json() {
+        let stats = counted(&[
+            (VirtualKey::Semicolon, false, KeyAction::Replace('ç')),
+            (VirtualKey::Apostrophe, true, KeyAction::Suppress),
+        ]);
+        assert_eq!(stats.to_json(), "{\"remaps\": {\";\": 1, \"\\\"\": 1}, \"total\": 2}\n");
+        assert_eq!(Stats::default().to_json(), "{\"remaps\": {}, \"total\": 0}\n");
+    }
+
+    #[test]
+    fn test_svg_shades_remapped_keys() {
+        let stats = counted(&[
+            (VirtualKey::Semicolon, false, KeyAction::Replace('ç')),
+            (VirtualKey::Semicolon, false, KeyAction::Replace('ç')),
+            (VirtualKey::LeftBracket, false, KeyAction::Suppress),
+        ]);
+        let svg = stats.to_svg();
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.contains("fill=\"#d73027\" stroke=\"#999\"><title>;: 2</title>"));
+        assert!(svg.contains("<title>[: 1</title>"));
+        assert!(svg.contains("fill=\"#f2f2f2\" stroke=\"#999\"><title>q: 0</title>"));
+        assert_eq!(svg.matches("<rect").count(), 13 + 13 + 11 + 10);
+    }
+
+    #[test]
+    fn test_drills_are_saved_and_capped() {
+        let mut stats = Stats::default();
+        for at in 0..DRILL_HISTORY as u64 + 2 {
+            stats.record_drill(DrillResult { at, sentences: 5, wpm: 30, accuracy: 95 });
+        }
+        assert_eq!(stats.drills.len(), DRILL_HISTORY);
+        assert_eq!(stats.drills[0].at, 2);
+
+        let text = toml::to_string_pretty(&stats).unwrap();
+        assert_eq!(Stats::parse(&text, None).unwrap(), stats);
+    }
+}
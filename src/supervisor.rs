@@ -0,0 +1,127 @@
+//! Supervisor mode
+//!
+//! `ghostkeys --supervise` starts a small parent process that runs the real
+//! GhostKeys as a child and starts it again if it exits abnormally, so a rare
+//! crash doesn't leave the user without ABNT2 characters until they notice.
+//! Restarts back off exponentially while the child keeps crashing quickly,
+//! and the child is told it's supervised so it doesn't relaunch itself too.
+
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Command-line flag that enables supervisor mode
+pub const SUPERVISE_FLAG: &str = "--supervise";
+
+/// Environment variable set in the supervised child
+pub const SUPERVISED_ENV: &str = "GHOSTKEYS_SUPERVISED";
+
+/// Delay before the first restart
+const INITIAL_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest delay between restarts
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// A child that ran this long was healthy, so the delay starts over
+const STABLE_UPTIME: Duration = Duration::from_secs(300);
+
+/// Exponential restart delay
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backoff {
+    next: Duration,
+}
+
+impl Backoff {
+    /// Create a backoff starting at the initial delay
+    pub fn new() -> Self {
+        Self { next: INITIAL_DELAY }
+    }
+
+    /// Delay before restarting a child that crashed after running for `uptime`
+    pub fn delay(&mut self, uptime: Duration) -> Duration {
+        if uptime >= STABLE_UPTIME {
+            self.next = INITIAL_DELAY;
+        }
+        let delay = self.next;
+        self.next = (delay * 2).min(MAX_DELAY);
+        delay
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether this process is the child of a supervisor
+pub fn is_supervised() -> bool {
+    std::env::var_os(SUPERVISED_ENV).is_some()
+}
+
+/// Arguments to start the child with: ours, minus the supervise flag
+pub fn child_args(args: &[String]) -> Vec<String> {
+    args.iter().filter(|arg| *arg != SUPERVISE_FLAG).cloned().collect()
+}
+
+/// Run GhostKeys as a supervised child until it exits normally
+///
+/// `args` excludes the program name. Returns the exit code for this process.
+pub fn run(args: &[String]) -> i32 {
+    let exe = match std::env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => {
+            eprintln!("Supervisor can't find the GhostKeys executable: {}", e);
+            return 1;
+        }
+    };
+    let args = child_args(args);
+    let mut backoff = Backoff::new();
+
+    loop {
+        let started = Instant::now();
+        let status = Command::new(&exe)
+            .args(&args)
+            .env(SUPERVISED_ENV, "1")
+            .status();
+        let uptime = started.elapsed();
+
+        match status {
+            Ok(status) if status.success() => return 0,
+            Ok(status) => eprintln!("GhostKeys exited abnormally ({})", status),
+            Err(e) => eprintln!("Failed to start GhostKeys: {}", e),
+        }
+
+        let delay = backoff.delay(uptime);
+        eprintln!("Restarting GhostKeys in {}s", delay.as_secs());
+        thread::sleep(delay);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_maximum() {
+        let mut backoff = Backoff::new();
+        let quick = Duration::from_secs(2);
+        let delays: Vec<u64> = (0..8).map(|_| backoff.delay(quick).as_secs()).collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 16, 32, 60, 60]);
+    }
+
+    #[test]
+    fn test_backoff_resets_after_a_stable_run() {
+        let mut backoff = Backoff::new();
+        backoff.delay(Duration::ZERO);
+        backoff.delay(Duration::ZERO);
+        assert_eq!(backoff.delay(STABLE_UPTIME), INITIAL_DELAY);
+        assert_eq!(backoff.delay(Duration::ZERO), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_child_args_drop_the_flag() {
+        let args = vec!["--supervise".to_string(), "--layout".to_string(), "br.klc".to_string()];
+        assert_eq!(child_args(&args), vec!["--layout", "br.klc"]);
+    }
+}
@@ -0,0 +1,251 @@
+//! `ghostkeys try`: a terminal sandbox for the mapper
+//!
+//! Keys typed in the terminal go through a `Mapper` instead of a system-wide
+//! hook, and the screen shows what was pressed next to what GhostKeys would
+//! type. Useful for checking a custom layout or practicing ABNT2 typing
+//! without touching any other application.
+//!
+//! The terminal delivers characters rather than key positions, so each one
+//! is traced back to the US key that types it, as `Mapper::map_text` does.
+
+use std::io::{self, Stdout, Write};
+use std::time::Duration;
+
+use crossterm::cursor::MoveTo;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::Print;
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+
+use crate::i18n::{tr, Text};
+use crate::interceptor::{KeyAction, KeyEvent};
+use crate::layout::us_key;
+use crate::mapper::{Mapper, MapperState, VirtualKey};
+
+/// How often a pending accent is checked for a timeout while no key is pressed
+const TICK: Duration = Duration::from_millis(100);
+
+/// Keys pressed and what the mapper typed for them, line by line
+pub struct Trainer {
+    mapper: Mapper,
+    /// Keys as pressed, one entry per line typed
+    keys: Vec<String>,
+    /// Mapped text, one entry per line typed
+    output: Vec<String>,
+}
+
+impl Trainer {
+    /// Start with empty lines
+    pub fn new(mapper: Mapper) -> Self {
+        Self { mapper, keys: vec![String::new()], output: vec![String::new()] }
+    }
+
+    /// Press the US key that types `c` (`\n` is Enter, `\t` is Tab)
+    pub fn type_char(&mut self, c: char) {
+        match c {
+            '\n' => self.keys.push(String::new()),
+            '\t' => self.push_key('⇥'),
+            _ => self.push_key(c),
+        }
+        match us_key(c) {
+            Some((key, shift)) => {
+                let action = self.mapper.process_key(KeyEvent::press(key, shift));
+                self.apply(action, Some(c));
+            }
+            // Like an unknown key, typed as is
+            None => self.push_output(c),
+        }
+    }
+
+    /// Press Backspace, which erases the last character when the mapper lets
+    /// it through (with an accent pending, the accent is typed instead)
+    pub fn backspace(&mut self) {
+        self.push_key('⌫');
+        let action = self.mapper.process_key(KeyEvent::press(VirtualKey::Other, false));
+        let passes = passes_original(&action);
+        self.apply(action, None);
+        if passes {
+            self.erase(1);
+        }
+    }
+
+    /// Press Esc, which discards a pending accent
+    pub fn escape(&mut self) {
+        self.push_key('⎋');
+        let action = self.mapper.process_key(KeyEvent::press(VirtualKey::Escape, false));
+        self.apply(action, None);
+    }
+
+    /// Type a pending accent whose time ran out
+    pub fn check_timeout(&mut self) {
+        if let Some(action) = self.mapper.check_timeout() {
+            self.apply(action, None);
+        }
+    }
+
+    /// What the mapper typed on the current line
+    pub fn line(&self) -> &str {
+        self.output.last().map(String::as_str).unwrap_or_default()
+    }
+
+    /// What the mapper is holding back, if anything
+    pub fn pending(&self) -> Option<String> {
+        match self.mapper.state() {
+            MapperState::Idle => None,
+            MapperState::PendingAccent(accent) => Some(accent.to_char().to_string()),
+            MapperState::Composing(sequence) => Some(sequence.iter().collect()),
+        }
+    }
+
+    /// The last `rows` lines as `keys │ output`, with the keys padded to `width`
+    pub fn render(&self, rows: usize, width: usize) -> Vec<String> {
+        let lines = self.keys.len().max(self.output.len());
+        (lines.saturating_sub(rows)..lines)
+            .map(|i| {
+                let keys = self.keys.get(i).map(String::as_str).unwrap_or_default();
+                let output = self.output.get(i).map(String::as_str).unwrap_or_default();
+                format!("{:<width$} │ {}", keys, output.replace('\t', "⇥"), width = width)
+            })
+            .collect()
+    }
+
+    /// Put what `action` types on screen; `typed` is the key's own character
+    fn apply(&mut self, action: KeyAction, typed: Option<char>) {
+        if let KeyAction::Rewrite { backspaces, .. } = action {
+            self.erase(backspaces);
+        }
+        for c in action.output(typed) {
+            self.push_output(c);
+        }
+    }
+
+    fn push_key(&mut self, c: char) {
+        if let Some(line) = self.keys.last_mut() {
+            line.push(c);
+        }
+    }
+
+    fn push_output(&mut self, c: char) {
+        if c == '\n' {
+            self.output.push(String::new());
+        } else if let Some(line) = self.output.last_mut() {
+            line.push(c);
+        }
+    }
+
+    /// Erase up to `count` characters from the current line
+    fn erase(&mut self, count: usize) {
+        if let Some(line) = self.output.last_mut() {
+            for _ in 0..count {
+                line.pop();
+            }
+        }
+    }
+}
+
+/// Whether the original keystroke reaches the application after `action`
+fn passes_original(action: &KeyAction) -> bool {
+    matches!(
+        action,
+        KeyAction::Pass | KeyAction::ReplaceThenPass(_) | KeyAction::ReplaceMultipleThenPass(_)
+    )
+}
+
+/// Run the trainer in the terminal until Ctrl+C or Ctrl+D
+pub fn run(mapper: Mapper) -> io::Result<()> {
+    let mut trainer = Trainer::new(mapper);
+    let mut stdout = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(stdout, EnterAlternateScreen)?;
+    let result = event_loop(&mut trainer, &mut stdout);
+    // Give the terminal back even if drawing failed
+    let _ = execute!(stdout, LeaveAlternateScreen);
+    terminal::disable_raw_mode()?;
+    result
+}
+
+fn event_loop(trainer: &mut Trainer, stdout: &mut Stdout) -> io::Result<()> {
+    loop {
+        draw(trainer, stdout)?;
+        if !event::poll(TICK)? {
+            trainer.check_timeout();
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        // Windows reports releases too
+        if key.kind == KeyEventKind::Release {
+            continue;
+        }
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('c') | KeyCode::Char('d') if ctrl => return Ok(()),
+            KeyCode::Char(c) if !ctrl && !key.modifiers.contains(KeyModifiers::ALT) => {
+                trainer.type_char(c)
+            }
+            KeyCode::Enter => trainer.type_char('\n'),
+            KeyCode::Tab => trainer.type_char('\t'),
+            KeyCode::Backspace => trainer.backspace(),
+            KeyCode::Esc => trainer.escape(),
+            _ => {}
+        }
+    }
+}
+
+/// Header, then as many lines as fit, the keys column taking half the width
+fn draw(trainer: &Trainer, stdout: &mut Stdout) -> io::Result<()> {
+    let (columns, rows) = terminal::size()?;
+    queue!(stdout, Clear(ClearType::All), MoveTo(0, 0), Print(tr(Text::TryHeader)))?;
+    let width = (columns as usize / 2).saturating_sub(2);
+    let lines = trainer.render(rows.saturating_sub(3) as usize, width);
+    for (row, line) in lines.iter().enumerate() {
+        queue!(stdout, MoveTo(0, row as u16 + 2), Print(line))?;
+    }
+    if let Some(pending) = trainer.pending() {
+        queue!(stdout, MoveTo(0, rows.saturating_sub(1)), Print(format!("… {}", pending)))?;
+    }
+    stdout.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_text(trainer: &mut Trainer, text: &str) {
+        for c in text.chars() {
+            trainer.type_char(c);
+        }
+    }
+
+    #[test]
+    fn test_keys_and_output_side_by_side() {
+        let mut trainer = Trainer::new(Mapper::new());
+        type_text(&mut trainer, "n'ao;\nok");
+        assert_eq!(trainer.render(10, 6), ["n'ao;  │ nãoç", "ok     │ ok"]);
+        assert_eq!(trainer.render(1, 2), ["ok │ ok"]);
+    }
+
+    #[test]
+    fn test_pending_accent() {
+        let mut trainer = Trainer::new(Mapper::new());
+        trainer.type_char('\'');
+        assert_eq!(trainer.pending().as_deref(), Some("~"));
+        trainer.escape();
+        assert_eq!(trainer.pending(), None);
+        assert_eq!(trainer.render(1, 3), ["'⎋  │ "]);
+    }
+
+    #[test]
+    fn test_backspace_erases_output() {
+        let mut trainer = Trainer::new(Mapper::new());
+        type_text(&mut trainer, "ab");
+        trainer.backspace();
+        assert_eq!(trainer.render(1, 3), ["ab⌫ │ a"]);
+
+        // Like any key that doesn't combine, it types a pending accent
+        trainer.type_char('\'');
+        trainer.backspace();
+        assert_eq!(trainer.render(1, 5), ["ab⌫'⌫ │ a~"]);
+    }
+}
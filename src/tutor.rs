@@ -0,0 +1,348 @@
+//! `ghostkeys train`: accent drills in the terminal
+//!
+//! Shows Portuguese sentences full of accents one at a time and has the user
+//! type them as on an ABNT2 keyboard. Keys go through a `Trainer`, so the
+//! mapper types exactly what the system-wide hook would, and every character
+//! that doesn't match the sentence counts as a mistake even if it's then
+//! erased. At the end, speed and accuracy are kept in `stats.toml` so each
+//! session is shown next to the previous one.
+
+use std::io::{self, Stdout, Write};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crossterm::cursor::MoveTo;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::Print;
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+
+use crate::i18n::{tr, tr_args, Text};
+use crate::mapper::Mapper;
+use crate::stats::DrillResult;
+use crate::trainer::Trainer;
+
+/// Sentences drilled, each exercising dead keys, ç or both
+pub const SENTENCES: &[&str] = &[
+    "A lição de português é às três.",
+    "Não há razão para ficar sem ação.",
+    "O pôr do sol na praça é lindo.",
+    "Você já comeu pão com manteiga hoje.",
+    "A avó e o avô moram no sítio.",
+    "Ela está atrás da porta, não está.",
+    "O açúcar acabou na padaria.",
+    "Três corações batem juntos à noite.",
+    "É difícil escolher uma única canção.",
+    "A exceção confirma a regra, então.",
+    "Pêssego, maçã e limão estão à venda.",
+    "O ônibus saiu às sete e vinte.",
+];
+
+/// Sentences in a session
+pub const SESSION_LENGTH: usize = 5;
+
+/// How often a pending accent is checked for a timeout while no key is pressed
+const TICK: Duration = Duration::from_millis(100);
+
+/// A drill session in progress
+pub struct Drill {
+    trainer: Trainer,
+    sentences: Vec<&'static str>,
+    /// Index of the sentence being typed
+    current: usize,
+    /// Characters typed, mistakes included
+    typed: usize,
+    /// Characters that didn't match the sentence when typed
+    mistakes: usize,
+    /// When the first key was pressed
+    started: Option<Instant>,
+}
+
+impl Drill {
+    /// A session of `SESSION_LENGTH` sentences, starting after those of the
+    /// `sessions` before it so each session drills different ones
+    pub fn new(mapper: Mapper, sessions: usize) -> Self {
+        let first = sessions * SESSION_LENGTH;
+        let sentences = (first..first + SESSION_LENGTH)
+            .map(|i| SENTENCES[i % SENTENCES.len()])
+            .collect();
+        Self {
+            trainer: Trainer::new(mapper),
+            sentences,
+            current: 0,
+            typed: 0,
+            mistakes: 0,
+            started: None,
+        }
+    }
+
+    /// Sentence to type, or `None` once all were typed
+    pub fn sentence(&self) -> Option<&'static str> {
+        self.sentences.get(self.current).copied()
+    }
+
+    /// Sentence number, from 1, and how many there are
+    pub fn progress(&self) -> (usize, usize) {
+        (
+            (self.current + 1).min(self.sentences.len()),
+            self.sentences.len(),
+        )
+    }
+
+    /// What was typed of the current sentence
+    pub fn line(&self) -> &str {
+        self.trainer.line()
+    }
+
+    /// Press the US key that types `c`, moving to the next sentence once the
+    /// current one is typed right
+    pub fn type_char(&mut self, c: char) {
+        let Some(sentence) = self.sentence() else {
+            return;
+        };
+        self.started.get_or_insert_with(Instant::now);
+        let before = self.trainer.line().chars().count();
+        self.trainer.type_char(c);
+        self.count(sentence, before);
+        if self.trainer.line() == sentence {
+            self.trainer.type_char('\n');
+            self.current += 1;
+        }
+    }
+
+    /// Press Backspace
+    pub fn backspace(&mut self) {
+        self.trainer.backspace();
+    }
+
+    /// Press Esc, which discards a pending accent
+    pub fn escape(&mut self) {
+        self.trainer.escape();
+    }
+
+    /// Type a pending accent whose time ran out
+    pub fn check_timeout(&mut self) {
+        if let Some(sentence) = self.sentence() {
+            let before = self.trainer.line().chars().count();
+            self.trainer.check_timeout();
+            self.count(sentence, before);
+        }
+    }
+
+    /// What the mapper is holding back, if anything
+    pub fn pending(&self) -> Option<String> {
+        self.trainer.pending()
+    }
+
+    /// Whether every sentence was typed
+    pub fn is_done(&self) -> bool {
+        self.sentence().is_none()
+    }
+
+    /// Speed and accuracy of the sentences typed so far, `elapsed` after the
+    /// first key
+    pub fn result(&self, elapsed: Duration) -> DrillResult {
+        let chars: usize = self.sentences[..self.current]
+            .iter()
+            .map(|s| s.chars().count())
+            .sum();
+        // A word is five characters, as typing tests count them
+        let minutes = elapsed.as_secs_f64() / 60.0;
+        let wpm = if minutes > 0.0 {
+            (chars as f64 / 5.0 / minutes).round() as u32
+        } else {
+            0
+        };
+        let accuracy = match self.typed {
+            0 => 100,
+            typed => ((typed - self.mistakes.min(typed)) * 100 / typed) as u32,
+        };
+        let at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |t| t.as_secs());
+        DrillResult {
+            at,
+            sentences: self.current as u32,
+            wpm,
+            accuracy,
+        }
+    }
+
+    /// Time since the first key, if one was pressed
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.started.map(|started| started.elapsed())
+    }
+
+    /// Count the characters added to the line since it was `before` long
+    fn count(&mut self, sentence: &str, before: usize) {
+        let expected: Vec<char> = sentence.chars().collect();
+        for (i, c) in self.trainer.line().chars().enumerate().skip(before) {
+            self.typed += 1;
+            if expected.get(i) != Some(&c) {
+                self.mistakes += 1;
+            }
+        }
+    }
+}
+
+/// Run a session until every sentence is typed; returns its result, or
+/// `None` if it was stopped with Ctrl+C or Ctrl+D before the end
+///
+/// `previous` is the last session's result, shown for comparison.
+pub fn run(
+    mapper: Mapper,
+    sessions: usize,
+    previous: Option<&DrillResult>,
+) -> io::Result<Option<DrillResult>> {
+    let mut drill = Drill::new(mapper, sessions);
+    let mut stdout = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(stdout, EnterAlternateScreen)?;
+    let result = event_loop(&mut drill, previous, &mut stdout);
+    // Give the terminal back even if drawing failed
+    let _ = execute!(stdout, LeaveAlternateScreen);
+    terminal::disable_raw_mode()?;
+    result?;
+    Ok(drill
+        .is_done()
+        .then(|| drill.result(drill.elapsed().unwrap_or_default())))
+}
+
+fn event_loop(
+    drill: &mut Drill,
+    previous: Option<&DrillResult>,
+    stdout: &mut Stdout,
+) -> io::Result<()> {
+    while !drill.is_done() {
+        draw(drill, previous, stdout)?;
+        if !event::poll(TICK)? {
+            drill.check_timeout();
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        // Windows reports releases too
+        if key.kind == KeyEventKind::Release {
+            continue;
+        }
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('c') | KeyCode::Char('d') if ctrl => return Ok(()),
+            KeyCode::Char(c) if !ctrl && !key.modifiers.contains(KeyModifiers::ALT) => {
+                drill.type_char(c)
+            }
+            KeyCode::Backspace => drill.backspace(),
+            KeyCode::Esc => drill.escape(),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Header, the previous session, then the sentence over what was typed
+fn draw(drill: &Drill, previous: Option<&DrillResult>, stdout: &mut Stdout) -> io::Result<()> {
+    let (current, total) = drill.progress();
+    queue!(
+        stdout,
+        Clear(ClearType::All),
+        MoveTo(0, 0),
+        Print(tr(Text::TrainHeader))
+    )?;
+    if let Some(previous) = previous {
+        let last = tr_args(Text::TrainPrevious, &[&previous.wpm, &previous.accuracy]);
+        queue!(stdout, MoveTo(0, 1), Print(last))?;
+    }
+    queue!(
+        stdout,
+        MoveTo(0, 3),
+        Print(tr_args(Text::TrainProgress, &[&current, &total])),
+        MoveTo(2, 5),
+        Print(drill.sentence().unwrap_or_default()),
+        MoveTo(2, 6),
+        Print(drill.line()),
+    )?;
+    if let Some(pending) = drill.pending() {
+        queue!(stdout, Print(format!("… {}", pending)))?;
+    }
+    stdout.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_text(drill: &mut Drill, text: &str) {
+        for c in text.chars() {
+            drill.type_char(c);
+        }
+    }
+
+    #[test]
+    fn test_sessions_rotate_through_sentences() {
+        let first = Drill::new(Mapper::new(), 0);
+        let second = Drill::new(Mapper::new(), 1);
+        assert_eq!(first.sentence(), Some(SENTENCES[0]));
+        assert_eq!(second.sentence(), Some(SENTENCES[SESSION_LENGTH]));
+        assert_eq!(first.progress(), (1, SESSION_LENGTH));
+    }
+
+    #[test]
+    fn test_typing_a_sentence_moves_to_the_next() {
+        let mut drill = Drill::new(Mapper::new(), 0);
+        // "A lição de português é às três." on a US keyboard
+        type_text(&mut drill, "A li;'ao de portugu\"es [e {as tr\"es.");
+        assert_eq!(drill.sentence(), Some(SENTENCES[1]));
+        assert_eq!(drill.line(), "");
+
+        let result = drill.result(Duration::from_secs(60));
+        assert_eq!(result.sentences, 1);
+        assert_eq!(result.accuracy, 100);
+        // 31 characters in a minute
+        assert_eq!(result.wpm, 6);
+    }
+
+    #[test]
+    fn test_erased_mistakes_still_count() {
+        let mut drill = Drill::new(Mapper::new(), 0);
+        type_text(&mut drill, "Ax");
+        drill.backspace();
+        drill.backspace();
+        assert_eq!(drill.line(), "");
+        type_text(&mut drill, "A");
+        assert_eq!(drill.result(Duration::from_secs(1)).accuracy, 66);
+    }
+
+    #[test]
+    fn test_finishing_the_session() {
+        let mut drill = Drill::new(Mapper::new(), 0);
+        let mut mapper = Mapper::new();
+        for sentence in &drill.sentences.clone() {
+            // Back to US keys: what a US keyboard types for each character
+            let mut keys = String::new();
+            for c in sentence.chars() {
+                keys.push_str(&us_keys(&mut mapper, c));
+            }
+            type_text(&mut drill, &keys);
+        }
+        assert!(drill.is_done());
+        assert_eq!(drill.progress(), (SESSION_LENGTH, SESSION_LENGTH));
+    }
+
+    /// US keys typing `c` through the ABNT2 mapper, found by trying them
+    fn us_keys(mapper: &mut Mapper, c: char) -> String {
+        let keys: Vec<String> = (' '..='~').map(String::from).collect();
+        let single = keys
+            .iter()
+            .find(|key| mapper.map_text(key) == c.to_string());
+        if let Some(key) = single {
+            return key.clone();
+        }
+        keys.iter()
+            .flat_map(|accent| {
+                keys.iter()
+                    .map(move |letter| format!("{}{}", accent, letter))
+            })
+            .find(|pair| mapper.map_text(pair) == c.to_string())
+            .unwrap_or_else(|| panic!("no keys type {:?}", c))
+    }
+}
@@ -0,0 +1,178 @@
+//! Safeguard against staying paused by a rule that stopped applying
+//!
+//! `[auto_pause]` rules resume GhostKeys when their condition clears, but
+//! only if whatever watches the condition sees it clear: a missed device
+//! notification or a hook thread that died leaves GhostKeys paused with
+//! nothing on screen saying why. With `resume_after_minutes` set, a
+//! background thread re-evaluates the rules every `CHECK_INTERVAL` while a
+//! rule has GhostKeys paused, resumes once they have stopped matching for
+//! that long, and warns once if they can't be evaluated at all.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::history::Cause;
+use crate::i18n::{tr, tr_args, Text};
+use crate::notify::Severity;
+use crate::state::{OperationMode, SharedState};
+
+/// How often the rules are re-evaluated
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// What re-evaluating the `[auto_pause]` rules found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    /// A rule still matches
+    Holds,
+    /// No rule matches anymore
+    Cleared,
+    /// The rules can't be evaluated (the hook isn't running, or the
+    /// platform has no rules)
+    Unknown,
+}
+
+/// What to do after a check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// Nothing, for now
+    Wait,
+    /// Resume: the rules have stopped matching for long enough
+    Resume,
+    /// Tell the user the pause can't be checked (once per pause)
+    Warn,
+}
+
+/// Remembers, between checks, since when the rules stopped matching
+#[derive(Debug, Default)]
+pub struct Watchdog {
+    cleared_since: Option<Instant>,
+    warned: bool,
+}
+
+impl Watchdog {
+    /// Create a watchdog that hasn't seen a pause yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decide what to do at `now`
+    ///
+    /// `condition` is only evaluated while a rule has GhostKeys paused.
+    pub fn check(
+        &mut self,
+        paused_by_rule: bool,
+        condition: impl FnOnce() -> Condition,
+        after: Duration,
+        now: Instant,
+    ) -> Verdict {
+        if !paused_by_rule {
+            *self = Self::new();
+            return Verdict::Wait;
+        }
+
+        match condition() {
+            Condition::Holds => {
+                self.cleared_since = None;
+                Verdict::Wait
+            }
+            Condition::Cleared => {
+                let since = *self.cleared_since.get_or_insert(now);
+                if now.duration_since(since) >= after {
+                    self.cleared_since = None;
+                    Verdict::Resume
+                } else {
+                    Verdict::Wait
+                }
+            }
+            Condition::Unknown if !self.warned => {
+                self.warned = true;
+                Verdict::Warn
+            }
+            Condition::Unknown => Verdict::Wait,
+        }
+    }
+}
+
+/// Check the rules on a background thread until `ghostkeys ctl quit`
+///
+/// `probe` re-evaluates the rules of the config it is given. The setting is
+/// read on every check, so a reloaded config turns the safeguard on or off.
+pub fn spawn(state: SharedState, probe: fn(&Config) -> Condition) {
+    thread::spawn(move || {
+        let mut watchdog = Watchdog::new();
+        while !state.should_exit() {
+            thread::sleep(CHECK_INTERVAL);
+            let Ok(config) = state.get_config() else {
+                return;
+            };
+            let minutes = config.auto_pause.resume_after_minutes;
+            let paused_by_rule = minutes > 0
+                && state.is_paused()
+                && matches!(state.last_mode_cause(), Ok(Some(Cause::Rule(_))));
+            let after = Duration::from_secs(u64::from(minutes) * 60);
+
+            match watchdog.check(paused_by_rule, || probe(&config), after, Instant::now()) {
+                Verdict::Resume => {
+                    let _ = state.set_mode_because(OperationMode::Active, Cause::Watchdog);
+                    let message = tr_args(Text::ResumedByWatchdog, &[&minutes]);
+                    let _ = state.notify(Severity::Warning, message);
+                }
+                Verdict::Warn => {
+                    let _ = state.notify(Severity::Warning, tr(Text::RuleUncheckable));
+                }
+                Verdict::Wait => {}
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AFTER: Duration = Duration::from_secs(600);
+
+    #[test]
+    fn test_resumes_once_cleared_for_long_enough() {
+        let mut watchdog = Watchdog::new();
+        let start = Instant::now();
+        let cleared = || Condition::Cleared;
+        assert_eq!(watchdog.check(true, || Condition::Holds, AFTER, start), Verdict::Wait);
+        assert_eq!(watchdog.check(true, cleared, AFTER, start), Verdict::Wait);
+        assert_eq!(watchdog.check(true, cleared, AFTER, start + AFTER / 2), Verdict::Wait);
+        assert_eq!(watchdog.check(true, cleared, AFTER, start + AFTER), Verdict::Resume);
+    }
+
+    #[test]
+    fn test_matching_again_restarts_the_wait() {
+        let mut watchdog = Watchdog::new();
+        let start = Instant::now();
+        let cleared = || Condition::Cleared;
+        watchdog.check(true, cleared, AFTER, start);
+        watchdog.check(true, || Condition::Holds, AFTER, start + AFTER / 2);
+        assert_eq!(watchdog.check(true, cleared, AFTER, start + AFTER), Verdict::Wait);
+        assert_eq!(watchdog.check(true, cleared, AFTER, start + AFTER * 2), Verdict::Resume);
+    }
+
+    #[test]
+    fn test_other_pauses_are_left_alone() {
+        let mut watchdog = Watchdog::new();
+        let start = Instant::now();
+        let probe = || -> Condition { panic!("rules evaluated without a rule pause") };
+        assert_eq!(watchdog.check(false, probe, AFTER, start + AFTER), Verdict::Wait);
+    }
+
+    #[test]
+    fn test_warns_once_per_pause_when_rules_cant_be_checked() {
+        let mut watchdog = Watchdog::new();
+        let now = Instant::now();
+        let unknown = || Condition::Unknown;
+        assert_eq!(watchdog.check(true, unknown, AFTER, now), Verdict::Warn);
+        assert_eq!(watchdog.check(true, unknown, AFTER, now), Verdict::Wait);
+
+        // A new pause warns again
+        watchdog.check(false, unknown, AFTER, now);
+        assert_eq!(watchdog.check(true, unknown, AFTER, now), Verdict::Warn);
+    }
+}
@@ -0,0 +1,106 @@
+//! Golden-corpus tests with real Portuguese text
+//!
+//! Each file in `tests/corpus/` pairs what a US keyboard types (`keys:`) with
+//! the text GhostKeys must produce from it (`text:`). Add a pair there to
+//! cover a regression; no code changes needed.
+
+use std::fs;
+use std::path::Path;
+
+use ghostkeys::layout::Layout;
+use ghostkeys::mapper::Mapper;
+
+/// One keys/text pair and where it was read from
+struct Entry {
+    location: String,
+    keys: String,
+    text: String,
+}
+
+/// Read every corpus file, in name order
+fn load_corpus() -> Vec<Entry> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("corpus");
+    let mut files: Vec<_> = fs::read_dir(&dir)
+        .expect("tests/corpus is missing")
+        .map(|entry| entry.expect("unreadable corpus entry").path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "txt"))
+        .collect();
+    files.sort();
+
+    let mut entries = Vec::new();
+    for path in files {
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let contents = fs::read_to_string(&path).expect("unreadable corpus file");
+        let mut keys: Option<(usize, &str)> = None;
+        for (index, line) in contents.lines().enumerate() {
+            let line_number = index + 1;
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(rest) = line.strip_prefix("keys: ") {
+                assert!(keys.is_none(), "{}:{}: keys: without text:", name, line_number);
+                keys = Some((line_number, rest));
+            } else if let Some(text) = line.strip_prefix("text: ") {
+                let Some((keys_line, keys)) = keys.take() else {
+                    panic!("{}:{}: text: without keys:", name, line_number);
+                };
+                entries.push(Entry {
+                    location: format!("{}:{}", name, keys_line),
+                    keys: keys.to_string(),
+                    text: text.to_string(),
+                });
+            } else {
+                panic!("{}:{}: expected keys: or text:", name, line_number);
+            }
+        }
+        assert!(keys.is_none(), "{}: ends with keys: without text:", name);
+    }
+    entries
+}
+
+#[test]
+fn test_corpus_is_reproduced_exactly() {
+    let entries = load_corpus();
+    assert!(!entries.is_empty());
+
+    let failures: Vec<String> = entries
+        .iter()
+        .filter_map(|entry| {
+            let typed = Mapper::new().map_text(&entry.keys);
+            (typed != entry.text).then(|| {
+                format!("{}: expected {:?}, got {:?}", entry.location, entry.text, typed)
+            })
+        })
+        .collect();
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn test_corpus_typed_in_one_go() {
+    // Nothing from one sentence may leak into the next
+    let entries = load_corpus();
+    let keys: Vec<&str> = entries.iter().map(|entry| entry.keys.as_str()).collect();
+    let text: Vec<&str> = entries.iter().map(|entry| entry.text.as_str()).collect();
+    assert_eq!(Mapper::new().map_text(&keys.join("\n")), text.join("\n"));
+}
+
+#[test]
+fn test_corpus_covers_the_layout() {
+    // Every position remap and every accent on the letters Portuguese (and
+    // Spanish ñ) uses, in both cases
+    let layout = Layout::abnt2();
+    let corpus: String = load_corpus().into_iter().map(|entry| entry.text).collect();
+    let combined = layout
+        .combinations
+        .iter()
+        .filter(|((_, base), _)| "aeiounAEIOUN".contains(*base))
+        .map(|(_, combined)| combined.clone());
+    let positions = layout.positions.values().map(|c| c.to_string());
+
+    let mut missing: Vec<String> = combined
+        .chain(positions)
+        .filter(|expected| !corpus.contains(expected.as_str()))
+        .collect();
+    missing.sort();
+    assert!(missing.is_empty(), "not in the corpus: {}", missing.join(" "));
+}
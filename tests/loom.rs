@@ -0,0 +1,159 @@
+//! Model checks of the state handed between the UI and hook threads
+//!
+//! Run with `RUSTFLAGS="--cfg ghostkeys_loom" cargo test --release --test loom`;
+//! loom explores every interleaving (and every value a relaxed load may
+//! return) of each model.
+
+#![cfg(ghostkeys_loom)]
+
+use loom::cell::UnsafeCell;
+use loom::sync::Arc;
+use loom::thread;
+
+use ghostkeys::flags::{ExitSignal, PauseFlag};
+use ghostkeys::handoff::Handoff;
+
+/// Stands in for the pipeline: the hook writes to it on every key press
+struct Mapper {
+    id: usize,
+    presses: UnsafeCell<usize>,
+}
+
+impl Mapper {
+    fn new(id: usize) -> Self {
+        Self { id, presses: UnsafeCell::new(0) }
+    }
+
+    /// A key press: loom reports a data race if the UI can touch the mapper
+    /// at the same time
+    fn press(&mut self) -> usize {
+        self.presses.with_mut(|presses| unsafe { *presses += 1 });
+        self.id
+    }
+}
+
+#[test]
+fn test_pause_seen_by_the_hook_never_goes_back() {
+    loom::model(|| {
+        let paused = Arc::new(PauseFlag::new());
+        let ui = {
+            let paused = Arc::clone(&paused);
+            thread::spawn(move || {
+                paused.swap(true);
+            })
+        };
+
+        // Key presses on the hook thread
+        let first = paused.is_set();
+        let second = paused.is_set();
+        assert!(!first || second);
+
+        ui.join().unwrap();
+        assert!(paused.is_set());
+    });
+}
+
+#[test]
+fn test_concurrent_toggles_are_not_lost() {
+    loom::model(|| {
+        let paused = Arc::new(PauseFlag::new());
+        let tray = {
+            let paused = Arc::clone(&paused);
+            thread::spawn(move || paused.toggle())
+        };
+
+        // The pause hotkey on the hook thread
+        let hotkey = paused.toggle();
+        let tray = tray.join().unwrap();
+
+        assert_ne!(hotkey, tray);
+        assert!(!paused.is_set());
+    });
+}
+
+#[test]
+fn test_exit_signal_publishes_earlier_writes() {
+    loom::model(|| {
+        let exit = Arc::new(ExitSignal::new());
+        let saved = Arc::new(UnsafeCell::new(0));
+        let quit = {
+            let (exit, saved) = (Arc::clone(&exit), Arc::clone(&saved));
+            thread::spawn(move || {
+                // Written without synchronization: loom reports a data race
+                // unless raising the signal publishes it
+                saved.with_mut(|saved| unsafe { *saved = 42 });
+                exit.raise();
+            })
+        };
+
+        if exit.is_raised() {
+            assert_eq!(saved.with(|saved| unsafe { *saved }), 42);
+        }
+        quit.join().unwrap();
+    });
+}
+
+#[test]
+fn test_replaced_mapper_is_never_in_use() {
+    loom::model(|| {
+        let pipeline = Arc::new(Handoff::new(Mapper::new(1)));
+        let ui = {
+            let pipeline = Arc::clone(&pipeline);
+            thread::spawn(move || {
+                // The UI frees the old mapper once it is handed back
+                let old = pipeline.replace(Mapper::new(2)).unwrap();
+                old.presses.with(|presses| unsafe { *presses })
+            })
+        };
+
+        let first = pipeline.with(Mapper::press).unwrap();
+        let second = pipeline.with(Mapper::press).unwrap();
+        assert!(first <= second);
+
+        let old_presses = ui.join().unwrap();
+        let new_presses = pipeline.with(|mapper| mapper.presses.with(|p| unsafe { *p })).unwrap();
+        assert_eq!(old_presses + new_presses, 2);
+    });
+}
+
+#[test]
+fn test_pause_before_a_mapper_swap_is_not_missed() {
+    loom::model(|| {
+        let paused = Arc::new(PauseFlag::new());
+        let pipeline = Arc::new(Handoff::new(Mapper::new(1)));
+        let ui = {
+            let (paused, pipeline) = (Arc::clone(&paused), Arc::clone(&pipeline));
+            thread::spawn(move || {
+                paused.swap(true);
+                pipeline.replace(Mapper::new(2)).unwrap();
+            })
+        };
+
+        // A key press that got the new mapper, then the next one's check
+        if pipeline.with(Mapper::press).unwrap() == 2 {
+            assert!(paused.is_set());
+        }
+        ui.join().unwrap();
+    });
+}
+
+#[test]
+fn test_exit_after_a_mapper_swap_sees_the_new_mapper() {
+    loom::model(|| {
+        let exit = Arc::new(ExitSignal::new());
+        let pipeline = Arc::new(Handoff::new(Mapper::new(1)));
+        let ui = {
+            let (exit, pipeline) = (Arc::clone(&exit), Arc::clone(&pipeline));
+            thread::spawn(move || {
+                pipeline.replace(Mapper::new(2)).unwrap();
+                exit.raise();
+            })
+        };
+
+        // The hook's last key press before shutting down
+        if exit.is_raised() {
+            assert_eq!(pipeline.with(Mapper::press).unwrap(), 2);
+        }
+        ui.join().unwrap();
+    });
+}
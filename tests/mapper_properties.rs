@@ -6,7 +6,8 @@
 use proptest::prelude::*;
 
 // Import from the main crate
-use ghostkeys::mapper::{AccentType, KeyAction, Mapper, MapperState, VirtualKey};
+use ghostkeys::layout::{us_char, Layout};
+use ghostkeys::mapper::{AccentType, KeyAction, KeyEvent, Mapper, MapperState, VirtualKey};
 
 /// Generator for position-mapped keys (;, ], \, /)
 /// These keys have direct character mappings (not dead keys)
@@ -28,6 +29,43 @@ fn dead_key_strategy() -> impl Strategy<Value = VirtualKey> {
     ]
 }
 
+/// Generator for every printable key that isn't a letter or Space
+fn non_letter_key_strategy() -> impl Strategy<Value = VirtualKey> {
+    prop_oneof![
+        proptest::char::range('0', '9').prop_map(VirtualKey::Digit),
+        Just(VirtualKey::Backquote),
+        Just(VirtualKey::Minus),
+        Just(VirtualKey::Equals),
+        Just(VirtualKey::Comma),
+        Just(VirtualKey::Period),
+        Just(VirtualKey::Semicolon),
+        Just(VirtualKey::Apostrophe),
+        Just(VirtualKey::LeftBracket),
+        Just(VirtualKey::RightBracket),
+        Just(VirtualKey::Backslash),
+        Just(VirtualKey::Slash),
+    ]
+}
+
+/// Generator for the key presses of someone typing: letters, digits,
+/// punctuation (dead keys included), Space, Enter and Tab, with or without
+/// Shift
+fn typing_key_strategy() -> impl Strategy<Value = (VirtualKey, bool)> {
+    let key = prop_oneof![
+        proptest::char::range('A', 'Z').prop_map(VirtualKey::Char),
+        non_letter_key_strategy(),
+        Just(VirtualKey::Space),
+        Just(VirtualKey::Enter),
+        Just(VirtualKey::Tab),
+    ];
+    (key, any::<bool>())
+}
+
+/// Generator for text without any character the ABNT2 mapping changes
+fn unmapped_text_strategy() -> impl Strategy<Value = String> {
+    proptest::string::string_regex("[a-zA-Z0-9 \\t\\n!@#$%&*()_+=,.<>`~-]{0,40}").unwrap()
+}
+
 /// Generator for combinable characters (those that can combine with accents)
 fn combinable_char_strategy() -> impl Strategy<Value = char> {
     prop_oneof![
@@ -43,6 +81,10 @@ fn combinable_char_strategy() -> impl Strategy<Value = char> {
         Just('U'),
         Just('n'),
         Just('N'),
+        Just('y'),
+        Just('Y'),
+        Just('w'),
+        Just('W'),
     ]
 }
 
@@ -65,9 +107,7 @@ fn non_combinable_char_strategy() -> impl Strategy<Value = char> {
         Just('s'),
         Just('t'),
         Just('v'),
-        Just('w'),
         Just('x'),
-        Just('y'),
         Just('z'),
     ]
 }
@@ -83,7 +123,7 @@ proptest! {
         shift in any::<bool>()
     ) {
         let mut mapper = Mapper::new();
-        let action = mapper.process_key(key, shift);
+        let action = mapper.process_key(KeyEvent::press(key, shift));
 
         // All position-mapped keys should return Replace action
         match action {
@@ -120,7 +160,7 @@ proptest! {
         shift in any::<bool>()
     ) {
         let mut mapper = Mapper::new();
-        let action = mapper.process_key(key, shift);
+        let action = mapper.process_key(KeyEvent::press(key, shift));
 
         // Dead key triggers should return Suppress action
         prop_assert_eq!(action, KeyAction::Suppress);
@@ -154,7 +194,7 @@ proptest! {
         let mut mapper = Mapper::new();
 
         // Press dead key
-        mapper.process_key(dead_key, shift_dead);
+        mapper.process_key(KeyEvent::press(dead_key, shift_dead));
 
         // Determine expected accent based on corrected mapping:
         // ' (unshifted) -> Tilde, ' (shifted) -> Circumflex
@@ -167,8 +207,10 @@ proptest! {
             _ => return Ok(()), // Skip invalid combinations
         };
 
-        // Press follow-up character
-        let action = mapper.process_key(VirtualKey::Char(follow_char), false);
+        // Press follow-up character: the letter's key, with Shift for a
+        // capital, as the keyboard sends it
+        let key = VirtualKey::Char(follow_char.to_ascii_uppercase());
+        let action = mapper.process_key(KeyEvent::press(key, follow_char.is_ascii_uppercase()));
 
         // Check if this is a valid combination
         let expected = get_expected_combination(accent, follow_char);
@@ -202,7 +244,7 @@ proptest! {
         let mut mapper = Mapper::new();
 
         // Press dead key
-        mapper.process_key(dead_key, shift_dead);
+        mapper.process_key(KeyEvent::press(dead_key, shift_dead));
 
         // Get the accent character based on corrected mapping:
         // ' (unshifted) -> Tilde, ' (shifted) -> Circumflex
@@ -216,7 +258,7 @@ proptest! {
         };
 
         // Press non-combinable character
-        let action = mapper.process_key(VirtualKey::Char(follow_char), false);
+        let action = mapper.process_key(KeyEvent::press(VirtualKey::Char(follow_char), false));
 
         // Should return ReplaceMultiple with accent + character
         match action {
@@ -232,20 +274,101 @@ proptest! {
         prop_assert_eq!(mapper.state(), &MapperState::Idle);
     }
 
+    // **Feature: ghostkeys, Property 4b: Digit and Punctuation Fallback**
+    // No keystroke after a dead key is lost: the output is the accent
+    // followed by what the key types on its own
+    #[test]
+    fn prop_non_letter_fallback_keeps_key(
+        dead_key in dead_key_strategy(),
+        shift_dead in any::<bool>(),
+        key in non_letter_key_strategy(),
+        shift in any::<bool>(),
+    ) {
+        let mut mapper = Mapper::new();
+        mapper.process_key(KeyEvent::press(dead_key, shift_dead));
+        let accent = match mapper.state() {
+            MapperState::PendingAccent(accent) => *accent,
+            _ => return Err(TestCaseError::fail("Expected PendingAccent state")),
+        };
+
+        let typed = us_char(key, shift);
+        let mut alone = Mapper::new();
+        let mut expected = vec![accent.to_char()];
+        expected.extend(alone.process_key(KeyEvent::press(key, shift)).output(typed));
+
+        let action = mapper.process_key(KeyEvent::press(key, shift));
+        prop_assert_eq!(action.output(typed), expected);
+        // A dead key arms its own accent, like it does from Idle
+        prop_assert_eq!(mapper.state(), alone.state());
+    }
+
+    // **Feature: ghostkeys, Property 4c: Keystroke Conservation**
+    // Over any sequence of keys, every key that isn't a dead key shows up in
+    // the output exactly once (alone or carrying the accent before it), and
+    // every accent exactly once (on a letter or typed on its own)
+    #[test]
+    fn prop_no_keystroke_lost_or_duplicated(
+        keys in proptest::collection::vec(typing_key_strategy(), 0..40),
+    ) {
+        let mut mapper = Mapper::new();
+        prop_assert_eq!(mapper.map_keys(keys.clone()), abnt2_typing(&keys));
+        prop_assert_eq!(mapper.state(), &MapperState::Idle);
+    }
+
+    // **Feature: ghostkeys, Property 4d: Unmapped Text Is Unchanged**
+    #[test]
+    fn prop_unmapped_text_is_unchanged(text in unmapped_text_strategy()) {
+        let mut mapper = Mapper::new();
+        prop_assert_eq!(mapper.map_text(&text), text);
+    }
+
     // **Feature: ghostkeys, Property 7: Passthrough Mode Transparency**
     // **Validates: Requirements 7.3**
     #[test]
-    fn prop_passthrough_unhandled_keys(key_code in 0u8..128u8) {
+    fn prop_passthrough_unhandled_keys(_key_code in 0u8..128u8) {
         let mut mapper = Mapper::new();
 
         // Use Other for keys we don't handle
-        let action = mapper.process_key(VirtualKey::Other, false);
+        let action = mapper.process_key(KeyEvent::press(VirtualKey::Other, false));
 
         // Unhandled keys should pass through
         prop_assert_eq!(action, KeyAction::Pass);
     }
 }
 
+/// What typing the keys on an ABNT2 keyboard puts on screen, one key at a
+/// time: a dead key holds its accent until the next key, which either takes
+/// it or is typed after it
+fn abnt2_typing(keys: &[(VirtualKey, bool)]) -> String {
+    let layout = Layout::abnt2();
+    let mut text = String::new();
+    let mut pending: Option<AccentType> = None;
+    for &(key, shift) in keys {
+        if let Some(&accent) = layout.dead_keys.get(&(key, shift)) {
+            text.extend(pending.replace(accent).map(AccentType::to_char));
+            continue;
+        }
+        let alone = match key {
+            VirtualKey::Enter => Some('\n'),
+            VirtualKey::Tab => Some('\t'),
+            _ => layout.positions.get(&(key, shift)).copied().or(us_char(key, shift)),
+        };
+        match (pending.take(), alone) {
+            (None, _) => text.extend(alone),
+            (Some(accent), Some(' ')) => text.push(accent.to_char()),
+            (Some(accent), Some(c)) if layout.combinations.contains_key(&(accent, c)) => {
+                text.push_str(&layout.combinations[&(accent, c)]);
+            }
+            (Some(accent), _) => {
+                text.push(accent.to_char());
+                text.extend(alone);
+            }
+        }
+    }
+    text.extend(pending.map(AccentType::to_char));
+    text
+}
+
 /// Helper function to get expected combination result
 fn get_expected_combination(accent: AccentType, c: char) -> Option<char> {
     match (accent, c) {
@@ -256,6 +379,8 @@ fn get_expected_combination(accent: AccentType, c: char) -> Option<char> {
         (AccentType::Tilde, 'O') => Some('Õ'),
         (AccentType::Tilde, 'n') => Some('ñ'),
         (AccentType::Tilde, 'N') => Some('Ñ'),
+        (AccentType::Tilde, 'y') => Some('ỹ'),
+        (AccentType::Tilde, 'Y') => Some('Ỹ'),
         // Acute
         (AccentType::Acute, 'a') => Some('á'),
         (AccentType::Acute, 'A') => Some('Á'),
@@ -267,9 +392,17 @@ fn get_expected_combination(accent: AccentType, c: char) -> Option<char> {
         (AccentType::Acute, 'O') => Some('Ó'),
         (AccentType::Acute, 'u') => Some('ú'),
         (AccentType::Acute, 'U') => Some('Ú'),
+        (AccentType::Acute, 'y') => Some('ý'),
+        (AccentType::Acute, 'Y') => Some('Ý'),
+        (AccentType::Acute, 'w') => Some('ẃ'),
+        (AccentType::Acute, 'W') => Some('Ẃ'),
         // Grave
         (AccentType::Grave, 'a') => Some('à'),
         (AccentType::Grave, 'A') => Some('À'),
+        (AccentType::Grave, 'y') => Some('ỳ'),
+        (AccentType::Grave, 'Y') => Some('Ỳ'),
+        (AccentType::Grave, 'w') => Some('ẁ'),
+        (AccentType::Grave, 'W') => Some('Ẁ'),
         // Circumflex
         (AccentType::Circumflex, 'a') => Some('â'),
         (AccentType::Circumflex, 'A') => Some('Â'),
@@ -277,6 +410,10 @@ fn get_expected_combination(accent: AccentType, c: char) -> Option<char> {
         (AccentType::Circumflex, 'E') => Some('Ê'),
         (AccentType::Circumflex, 'o') => Some('ô'),
         (AccentType::Circumflex, 'O') => Some('Ô'),
+        (AccentType::Circumflex, 'y') => Some('ŷ'),
+        (AccentType::Circumflex, 'Y') => Some('Ŷ'),
+        (AccentType::Circumflex, 'w') => Some('ŵ'),
+        (AccentType::Circumflex, 'W') => Some('Ŵ'),
         _ => None,
     }
 }
@@ -294,7 +431,7 @@ mod timeout_tests {
         let mut mapper = Mapper::new();
 
         // Press dead key
-        mapper.process_key(VirtualKey::Apostrophe, false);
+        mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false));
         assert!(matches!(mapper.state(), MapperState::PendingAccent(_)));
 
         // Wait for timeout (500ms + buffer)
@@ -312,7 +449,7 @@ mod timeout_tests {
         let mut mapper = Mapper::new();
 
         // Press dead key
-        mapper.process_key(VirtualKey::Apostrophe, false);
+        mapper.process_key(KeyEvent::press(VirtualKey::Apostrophe, false));
 
         // Wait less than timeout
         sleep(Duration::from_millis(100));
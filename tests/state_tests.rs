@@ -75,6 +75,54 @@ mod thread_safety_tests {
         assert!(state.should_exit());
     }
 
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_tray_toggle_reaches_interceptor() {
+        use ghostkeys::interceptor::KeyboardInterceptor;
+        use ghostkeys::platform::linux::LinuxInterceptor;
+
+        let state = SharedState::new();
+        let mut interceptor = LinuxInterceptor::without_preflight();
+        assert!(interceptor.set_mode(OperationMode::Passthrough).is_err());
+
+        // The hook reads the flag of the state it was started with
+        interceptor.start(state.clone()).unwrap();
+        let hook_state = state.clone();
+
+        interceptor.set_mode(OperationMode::Passthrough).unwrap();
+        assert!(hook_state.is_paused());
+        assert_eq!(state.get_mode().unwrap(), OperationMode::Passthrough);
+
+        interceptor.set_mode(OperationMode::Active).unwrap();
+        assert!(!hook_state.is_paused());
+
+        interceptor.stop().unwrap();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_layout_switch_without_restart() {
+        use ghostkeys::interceptor::KeyboardInterceptor;
+        use ghostkeys::layout::Layout;
+        use ghostkeys::mapper::Mapper;
+        use ghostkeys::platform::linux::LinuxInterceptor;
+
+        let state = SharedState::new();
+        let mut interceptor = LinuxInterceptor::without_preflight();
+        assert!(interceptor.set_mapper(Mapper::new()).is_err());
+        assert!(interceptor.update_layout(None).is_err());
+
+        interceptor.start(state.clone()).unwrap();
+        let mut custom = Layout::abnt2();
+        custom.name = "Custom".to_string();
+        interceptor.update_layout(Some(custom)).unwrap();
+        assert_eq!(state.get_layout().unwrap().unwrap().name, "Custom");
+        assert!(interceptor.is_running());
+
+        interceptor.set_mapper(Mapper::new()).unwrap();
+        interceptor.stop().unwrap();
+    }
+
     #[test]
     fn test_concurrent_toggles() {
         let state = Arc::new(SharedState::new());
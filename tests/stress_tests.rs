@@ -0,0 +1,318 @@
+//! Concurrency stress tests for the interceptor and the shared state
+//!
+//! A mock interceptor feeds synthetic key events through the mapper the way
+//! the Windows hook does: the mode is checked on every key, and mappers handed
+//! over with `set_mapper` are picked up without ever waiting for a lock. Other
+//! threads meanwhile toggle the mode, swap layouts, reload the config and flip
+//! the direction. A short run is part of `cargo test`; the long one only runs
+//! when asked for:
+//!
+//! ```text
+//! cargo test --test stress_tests -- --ignored
+//! ```
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ghostkeys::interceptor::build_mapper;
+use ghostkeys::latency::Sample;
+use ghostkeys::layout::us_key;
+use ghostkeys::{
+    Direction, GhostKeysError, KeyAction, KeyEvent, KeyboardInterceptor, Layout, Mapper,
+    MapperState, Modifiers, OperationMode, Result, SharedState, VirtualKey,
+};
+
+/// How long past the planned run time a stress run may take before it
+/// counts as deadlocked
+const DEADLOCK_MARGIN: Duration = Duration::from_secs(30);
+
+/// Keys typed over and over: accents, remapped positions and plain letters
+const TYPED: &str = "n'ao a;[ucar, \"e {a 'x ]\\ /?\n";
+
+/// A key press as the mock receives it
+type Event = (VirtualKey, Modifiers);
+
+/// What the event thread saw
+#[derive(Debug, Default)]
+struct Stats {
+    /// Key presses handled
+    events: usize,
+    /// Key presses that weren't passed through
+    remapped: usize,
+    /// Space presses after which an accent was still pending
+    stuck_accents: usize,
+    /// Mapper state after the last event
+    final_state: Option<MapperState>,
+}
+
+/// A backend without a keyboard: key events come from a channel
+struct MockInterceptor {
+    state: Option<SharedState>,
+    running: Arc<AtomicBool>,
+    /// Mapper handed over by `set_mapper`, taken by the event thread
+    pending: Arc<Mutex<Option<Mapper>>>,
+    events: Option<SyncSender<Event>>,
+    worker: Option<thread::JoinHandle<Stats>>,
+}
+
+impl MockInterceptor {
+    fn new() -> Self {
+        Self {
+            state: None,
+            running: Arc::new(AtomicBool::new(false)),
+            pending: Arc::new(Mutex::new(None)),
+            events: None,
+            worker: None,
+        }
+    }
+
+    /// Where to send key presses while running
+    fn keyboard(&self) -> Option<SyncSender<Event>> {
+        self.events.clone()
+    }
+
+    /// Wait for the event thread to drain its queue after `stop`
+    fn join(&mut self) -> Stats {
+        self.worker
+            .take()
+            .map(|worker| worker.join().expect("the event thread panicked"))
+            .unwrap_or_default()
+    }
+
+    /// Handle key presses until every sender is gone
+    fn run(
+        state: SharedState,
+        mut mapper: Mapper,
+        pending: Arc<Mutex<Option<Mapper>>>,
+        events: Receiver<Event>,
+    ) -> Stats {
+        let mut stats = Stats::default();
+        for (key, modifiers) in events {
+            stats.events += 1;
+            // Never wait for a handover in progress
+            if let Some(new_mapper) = pending.try_lock().ok().and_then(|mut p| p.take()) {
+                mapper = new_mapper;
+            }
+            if state.is_paused() {
+                mapper.reset();
+                continue;
+            }
+            if let Ok(direction) = state.get_direction() {
+                mapper.set_direction(direction);
+            }
+            if mapper.process_key(KeyEvent::down(key, modifiers)) != KeyAction::Pass {
+                stats.remapped += 1;
+            }
+            // Space always settles a pending accent
+            if key == VirtualKey::Space && *mapper.state() != MapperState::Idle {
+                stats.stuck_accents += 1;
+            }
+        }
+        stats.final_state = Some(mapper.state().clone());
+        stats
+    }
+
+    fn state(&self) -> Result<&SharedState> {
+        self.state
+            .as_ref()
+            .ok_or_else(|| GhostKeysError::HookInstallError("Interceptor not running".to_string()))
+    }
+}
+
+impl KeyboardInterceptor for MockInterceptor {
+    fn start(&mut self, state: SharedState) -> Result<()> {
+        if self.running.load(Ordering::SeqCst) {
+            return Err(GhostKeysError::HookInstallError(
+                "Interceptor already running".to_string(),
+            ));
+        }
+        let mapper = build_mapper(&state)?;
+        let (sender, receiver) = mpsc::sync_channel(256);
+        let pending = Arc::clone(&self.pending);
+        let worker_state = state.clone();
+        self.worker = Some(thread::spawn(move || {
+            Self::run(worker_state, mapper, pending, receiver)
+        }));
+        self.events = Some(sender);
+        self.state = Some(state);
+        self.running.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.running.store(false, Ordering::SeqCst);
+        self.events = None;
+        Ok(())
+    }
+
+    fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    fn set_mode(&mut self, mode: OperationMode) -> Result<()> {
+        self.state()?.set_mode(mode)
+    }
+
+    fn set_mapper(&mut self, mapper: Mapper) -> Result<()> {
+        if !self.is_running() {
+            return Err(GhostKeysError::HookInstallError("Interceptor not running".to_string()));
+        }
+        *self.pending.lock().map_err(|_| GhostKeysError::StateLockPoisoned)? = Some(mapper);
+        Ok(())
+    }
+
+    fn update_layout(&mut self, layout: Option<Layout>) -> Result<()> {
+        let state = self.state()?;
+        state.set_layout(layout)?;
+        let mapper = build_mapper(state)?;
+        self.set_mapper(mapper)
+    }
+
+    fn self_test(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn measure_latency(&self, _samples: usize) -> Result<Vec<Sample>> {
+        Ok(Vec::new())
+    }
+}
+
+/// The key presses that type `text` on a US keyboard
+fn key_presses(text: &str) -> Vec<Event> {
+    text.chars()
+        .map(|c| {
+            let (key, shift) = us_key(c).expect("no US key");
+            (key, Modifiers { shift, ..Modifiers::default() })
+        })
+        .collect()
+}
+
+/// Run `round` over and over on its own thread until `done` is set
+fn hammer(
+    done: &Arc<AtomicBool>,
+    mut round: impl FnMut(usize) + Send + 'static,
+) -> thread::JoinHandle<usize> {
+    let done = Arc::clone(done);
+    thread::spawn(move || {
+        let mut rounds = 0;
+        while !done.load(Ordering::SeqCst) {
+            round(rounds);
+            rounds += 1;
+            thread::yield_now();
+        }
+        rounds
+    })
+}
+
+/// Type and reconfigure from every direction at once for `duration`
+fn stress(duration: Duration) -> Stats {
+    let state = SharedState::new();
+    let interceptor = Arc::new(Mutex::new(MockInterceptor::new()));
+    interceptor.lock().unwrap().start(state.clone()).unwrap();
+    let keyboard = interceptor.lock().unwrap().keyboard().unwrap();
+    let changes = state.subscribe().unwrap();
+    let done = Arc::new(AtomicBool::new(false));
+
+    let mut hammers = Vec::new();
+    for _ in 0..2 {
+        let keyboard = keyboard.clone();
+        let presses = key_presses(TYPED);
+        hammers.push(hammer(&done, move |_| {
+            for &press in &presses {
+                keyboard.send(press).expect("the event thread stopped");
+            }
+        }));
+    }
+    let toggles = state.clone();
+    hammers.push(hammer(&done, move |_| {
+        toggles.toggle_mode().expect("toggling failed");
+    }));
+    let layouts = Arc::clone(&interceptor);
+    hammers.push(hammer(&done, move |round| {
+        let layout = (round % 2 == 0).then(Layout::us);
+        layouts.lock().unwrap().update_layout(layout).expect("layout swap failed");
+    }));
+    let reloads = (state.clone(), Arc::clone(&interceptor));
+    hammers.push(hammer(&done, move |_| {
+        let (state, interceptor) = &reloads;
+        let mut config = state.get_config().expect("config unreadable");
+        config.features.chain_accents = !config.features.chain_accents;
+        state.set_config(config).expect("config reload failed");
+        let mapper = build_mapper(state).expect("mapper rebuild failed");
+        interceptor.lock().unwrap().set_mapper(mapper).expect("mapper handover failed");
+    }));
+    let directions = state.clone();
+    hammers.push(hammer(&done, move |round| {
+        let direction = if round % 2 == 0 { Direction::Abnt2ToUs } else { Direction::UsToAbnt2 };
+        directions.set_direction(direction).expect("direction change failed");
+    }));
+    // The tray drains its subscription all along
+    hammers.push(hammer(&done, move |_| {
+        while changes.try_recv().is_ok() {}
+    }));
+
+    thread::sleep(duration);
+    done.store(true, Ordering::SeqCst);
+    for hammer in hammers {
+        assert!(hammer.join().expect("a stress thread panicked") > 0);
+    }
+
+    // Type a last accent and Space with remapping on: nothing may be left over
+    state.set_mode(OperationMode::Active).unwrap();
+    state.set_direction(Direction::UsToAbnt2).unwrap();
+    for press in key_presses("' ") {
+        keyboard.send(press).unwrap();
+    }
+    drop(keyboard);
+
+    let mut interceptor = interceptor.lock().expect("the interceptor lock is poisoned");
+    interceptor.stop().unwrap();
+    let stats = interceptor.join();
+
+    // Every lock in the state must still be usable
+    state.get_mode().expect("state lock poisoned");
+    state.get_direction().expect("state lock poisoned");
+    state.get_layout().expect("state lock poisoned");
+    state.get_config().expect("state lock poisoned");
+    stats
+}
+
+/// Run a stress test, failing if it doesn't finish in time
+fn stress_without_deadlock(duration: Duration) -> Stats {
+    let (finished, result) = mpsc::channel();
+    let started = Instant::now();
+    let run = thread::spawn(move || finished.send(stress(duration)).unwrap());
+    match result.recv_timeout(duration + DEADLOCK_MARGIN) {
+        Ok(stats) => stats,
+        Err(mpsc::RecvTimeoutError::Disconnected) => {
+            // The run panicked; show its message instead of ours
+            std::panic::resume_unwind(run.join().unwrap_err())
+        }
+        Err(mpsc::RecvTimeoutError::Timeout) => {
+            panic!("deadlocked: still running after {:?}", started.elapsed())
+        }
+    }
+}
+
+fn assert_healthy(stats: &Stats) {
+    assert!(stats.events > TYPED.len(), "only {} events got through", stats.events);
+    assert!(stats.remapped > 0, "nothing was remapped");
+    assert_eq!(stats.stuck_accents, 0, "accents stayed pending after Space");
+    assert_eq!(stats.final_state, Some(MapperState::Idle));
+}
+
+#[test]
+fn test_stress_short() {
+    let stats = stress_without_deadlock(Duration::from_millis(500));
+    assert_healthy(&stats);
+}
+
+#[test]
+#[ignore = "runs for a minute"]
+fn test_stress_long() {
+    let stats = stress_without_deadlock(Duration::from_secs(60));
+    assert_healthy(&stats);
+}
@@ -0,0 +1,308 @@
+//! End-to-end tests of the Windows keyboard hook
+//!
+//! Installs the real hook, types into an edit control with SendInput and
+//! reads back what arrived, so injection order and the handling of injected
+//! input are tested the way applications see them. They take over the
+//! keyboard for a moment and need an interactive desktop with a US layout
+//! selected, so they only run when asked for:
+//!
+//! ```text
+//! cargo test --test windows_integration -- --ignored
+//! ```
+
+#![cfg(windows)]
+
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use windows::core::{w, PCWSTR};
+use windows::Win32::Foundation::{BOOL, HWND, LPARAM, WPARAM};
+use windows::Win32::System::Threading::{AttachThreadInput, GetCurrentThreadId};
+use windows::Win32::UI::Input::KeyboardAndMouse::{
+    SendInput, SetFocus, INPUT, INPUT_0, INPUT_KEYBOARD, KEYBDINPUT, KEYBD_EVENT_FLAGS,
+    KEYEVENTF_KEYUP, KEYEVENTF_SCANCODE, KEYEVENTF_UNICODE, VIRTUAL_KEY,
+};
+use windows::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, DestroyWindow, DispatchMessageW, GetForegroundWindow, GetMessageW,
+    GetWindowThreadProcessId, PostThreadMessageW, SendMessageW, SetForegroundWindow,
+    TranslateMessage, MSG, WINDOW_STYLE, WM_GETTEXT, WM_QUIT, WM_SETTEXT, WS_EX_TOOLWINDOW,
+    WS_EX_TOPMOST, WS_POPUP, WS_VISIBLE,
+};
+
+use ghostkeys::layout::{scan_code_to_virtual_key, us_key};
+use ghostkeys::platform::windows::{run_self_test, WindowsInterceptor};
+use ghostkeys::{KeyboardInterceptor, OperationMode, SharedState, VirtualKey};
+
+/// Multi-line edit control style (not exported as a WINDOW_STYLE)
+const ES_MULTILINE: u32 = 0x0004;
+
+/// Scan code of the left Shift key
+const SCAN_LEFT_SHIFT: u16 = 0x2A;
+
+/// How long to wait for typed text to show up
+const SETTLE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The hook is global: one test at a time
+static HOOK: Mutex<()> = Mutex::new(());
+
+/// A focused edit control on a thread pumping its messages, with the hook
+/// installed
+struct Harness {
+    state: SharedState,
+    edit: isize,
+    thread_id: u32,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Harness {
+    fn start() -> Self {
+        let state = SharedState::new();
+        let hook_state = state.clone();
+        let (ready, started) = mpsc::channel();
+
+        let thread = thread::spawn(move || {
+            let mut interceptor = WindowsInterceptor::new();
+            interceptor.start(hook_state).expect("can't install the hook");
+            let edit = unsafe { create_focused_edit() };
+            ready.send((edit.0 as isize, unsafe { GetCurrentThreadId() })).unwrap();
+
+            let mut msg = MSG::default();
+            unsafe {
+                while GetMessageW(&mut msg, None, 0, 0).as_bool() {
+                    let _ = TranslateMessage(&msg);
+                    DispatchMessageW(&msg);
+                }
+                let _ = DestroyWindow(edit);
+            }
+            interceptor.stop().expect("can't remove the hook");
+        });
+
+        let (edit, thread_id) = started.recv().expect("the hook thread died");
+        Self { state, edit, thread_id, thread: Some(thread) }
+    }
+
+    fn edit(&self) -> HWND {
+        HWND(self.edit as *mut std::ffi::c_void)
+    }
+
+    /// Press keys as a US keyboard would type `keys`, then wait for `expected`
+    fn type_keys(&self, keys: &str, expected: &str) -> String {
+        let mut inputs = Vec::new();
+        for c in keys.chars() {
+            let (key, shift) = us_key(c).unwrap_or_else(|| panic!("{:?} has no US key", c));
+            let scan = scan_code(key);
+            if shift {
+                inputs.push(scan_input(SCAN_LEFT_SHIFT, false));
+            }
+            inputs.push(scan_input(scan, false));
+            inputs.push(scan_input(scan, true));
+            if shift {
+                inputs.push(scan_input(SCAN_LEFT_SHIFT, true));
+            }
+        }
+        // One event at a time, as a typist would, so the modifier state the
+        // hook reads is up to date
+        for input in &inputs {
+            send(std::slice::from_ref(input));
+            thread::sleep(Duration::from_millis(5));
+        }
+        self.wait_for(expected)
+    }
+
+    /// Text in the edit control once it equals `expected` or stops changing
+    fn wait_for(&self, expected: &str) -> String {
+        let deadline = Instant::now() + SETTLE_TIMEOUT;
+        loop {
+            let text = self.text();
+            if text == expected || Instant::now() >= deadline {
+                return text;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    /// Contents of the edit control, with line breaks as `\n`
+    fn text(&self) -> String {
+        let mut buffer = [0u16; 1024];
+        let len = unsafe {
+            SendMessageW(
+                self.edit(),
+                WM_GETTEXT,
+                WPARAM(buffer.len()),
+                LPARAM(buffer.as_mut_ptr() as isize),
+            )
+        };
+        String::from_utf16_lossy(&buffer[..len.0 as usize]).replace("\r\n", "\n")
+    }
+
+    fn clear(&self) {
+        let empty = w!("");
+        unsafe {
+            SendMessageW(self.edit(), WM_SETTEXT, WPARAM(0), LPARAM(empty.as_ptr() as isize));
+        }
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = PostThreadMessageW(self.thread_id, WM_QUIT, WPARAM(0), LPARAM(0));
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Create a small edit control and give it the keyboard focus
+///
+/// A hidden window can't have the focus, and Windows only delivers keyboard
+/// input to the focused window, so the control is a small topmost tool window.
+/// Attaching to the foreground thread's input lets a background test process
+/// take the foreground.
+unsafe fn create_focused_edit() -> HWND {
+    let edit = CreateWindowExW(
+        WS_EX_TOPMOST | WS_EX_TOOLWINDOW,
+        w!("EDIT"),
+        PCWSTR::null(),
+        WS_POPUP | WS_VISIBLE | WINDOW_STYLE(ES_MULTILINE),
+        0,
+        0,
+        320,
+        120,
+        None,
+        None,
+        None,
+        None,
+    )
+    .expect("can't create the edit control");
+
+    let this_thread = GetCurrentThreadId();
+    let foreground_thread = GetWindowThreadProcessId(GetForegroundWindow(), None);
+    let attached = foreground_thread != this_thread
+        && AttachThreadInput(this_thread, foreground_thread, BOOL::from(true)).as_bool();
+    let _ = SetForegroundWindow(edit);
+    let _ = SetFocus(edit);
+    if attached {
+        let _ = AttachThreadInput(this_thread, foreground_thread, BOOL::from(false));
+    }
+    assert_eq!(GetForegroundWindow(), edit, "the edit control didn't get the focus");
+    edit
+}
+
+/// Scan code of a key position
+fn scan_code(key: VirtualKey) -> u16 {
+    (1..=0x58)
+        .find(|&code| scan_code_to_virtual_key(code) == Some(key))
+        .map(u16::from)
+        .unwrap_or_else(|| panic!("no scan code for {:?}", key))
+}
+
+/// Press or release a key by scan code, as a physical keyboard reports it
+fn scan_input(scan: u16, up: bool) -> INPUT {
+    let mut flags = KEYEVENTF_SCANCODE;
+    if up {
+        flags |= KEYEVENTF_KEYUP;
+    }
+    keyboard_input(VIRTUAL_KEY(0), scan, flags)
+}
+
+fn keyboard_input(vk: VIRTUAL_KEY, scan: u16, flags: KEYBD_EVENT_FLAGS) -> INPUT {
+    INPUT {
+        r#type: INPUT_KEYBOARD,
+        Anonymous: INPUT_0 {
+            ki: KEYBDINPUT { wVk: vk, wScan: scan, dwFlags: flags, time: 0, dwExtraInfo: 0 },
+        },
+    }
+}
+
+fn send(inputs: &[INPUT]) {
+    let sent = unsafe { SendInput(inputs, std::mem::size_of::<INPUT>() as i32) };
+    assert_eq!(sent as usize, inputs.len(), "SendInput was blocked");
+}
+
+fn lock() -> std::sync::MutexGuard<'static, ()> {
+    HOOK.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[test]
+#[ignore = "installs a global keyboard hook and needs an interactive desktop"]
+fn test_positions_and_dead_keys() {
+    let _hook = lock();
+    let harness = Harness::start();
+    let expected = "não açúcar, é você; [a] {b}";
+    assert_eq!(harness.type_keys("n'ao a;[ucar, [e voc\"e/ ]a\\ }b|", expected), expected);
+}
+
+#[test]
+#[ignore = "installs a global keyboard hook and needs an interactive desktop"]
+fn test_replace_multiple_keeps_order() {
+    let _hook = lock();
+    let harness = Harness::start();
+    // Accent then key, never the other way round
+    let expected = "~x~5^k´1`;";
+    assert_eq!(harness.type_keys("'x'5\"k[1{/", expected), expected);
+}
+
+#[test]
+#[ignore = "installs a global keyboard hook and needs an interactive desktop"]
+fn test_enter_after_dead_key_types_accent_first() {
+    let _hook = lock();
+    let harness = Harness::start();
+    let expected = "a~\nb";
+    assert_eq!(harness.type_keys("a'\nb", expected), expected);
+}
+
+#[test]
+#[ignore = "installs a global keyboard hook and needs an interactive desktop"]
+fn test_unicode_input_is_not_remapped() {
+    let _hook = lock();
+    let harness = Harness::start();
+
+    // Keys injected by other programs are remapped like physical ones (every
+    // test here types that way), but Unicode input is text, not key positions.
+    // Its UTF-16 units arrive where scan codes go: '\'' is 0x27, the ; key,
+    // '(' the ' dead key, '+' the \ key and '5' the / key
+    let text = "'(+5";
+    let units: Vec<INPUT> = text
+        .encode_utf16()
+        .flat_map(|unit| {
+            [
+                keyboard_input(VIRTUAL_KEY(0), unit, KEYEVENTF_UNICODE),
+                keyboard_input(VIRTUAL_KEY(0), unit, KEYEVENTF_UNICODE | KEYEVENTF_KEYUP),
+            ]
+        })
+        .collect();
+    send(&units);
+    assert_eq!(harness.wait_for(text), text);
+}
+
+#[test]
+#[ignore = "installs a global keyboard hook and needs an interactive desktop"]
+fn test_stop_from_another_thread_removes_the_hook() {
+    let _hook = lock();
+    let mut interceptor = WindowsInterceptor::new();
+    interceptor.start(SharedState::new()).expect("can't install the hook");
+    run_self_test().expect("the hook isn't receiving keys");
+
+    // Started here, stopped there, and nothing on this thread pumps messages
+    thread::spawn(move || interceptor.stop().expect("can't remove the hook"))
+        .join()
+        .unwrap();
+    assert!(run_self_test().is_err(), "the hook is still installed");
+}
+
+#[test]
+#[ignore = "installs a global keyboard hook and needs an interactive desktop"]
+fn test_paused_passes_keys_through() {
+    let _hook = lock();
+    let harness = Harness::start();
+    harness.state.set_mode(OperationMode::Passthrough).unwrap();
+    assert_eq!(harness.type_keys(";'a[", ";'a["), ";'a[");
+
+    harness.clear();
+    harness.state.set_mode(OperationMode::Active).unwrap();
+    assert_eq!(harness.type_keys(";'a", "çã"), "çã");
+}